@@ -40,8 +40,9 @@ impl EventEmitter for CommandBot {
                     formatted_body: None,
                     relates_to: None,
                 });
-                // we clone here to hold the lock for as little time as possible.
-                let room_id = room.read().await.room_id.clone();
+                // the context of the room is taken when the event is emitted,
+                // so we don't need to lock the room to get its id.
+                let room_id = room.context().room_id.clone();
 
                 println!("sending");
 
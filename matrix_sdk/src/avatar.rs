@@ -0,0 +1,170 @@
+// Copyright 2020 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::api::r0::media::get_content_thumbnail::Method;
+use crate::js_int::UInt;
+use crate::{Client, Result, Room, RoomMember};
+
+/// The size an avatar should be fetched in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AvatarSize {
+    /// A 32x32 thumbnail, e.g. for member lists.
+    Small,
+    /// A 96x96 thumbnail, e.g. for room lists.
+    Medium,
+    /// A 256x256 thumbnail, e.g. for profile views.
+    Large,
+    /// The original image that was uploaded.
+    Original,
+}
+
+impl AvatarSize {
+    /// The width and height of the thumbnail, `None` for the original image.
+    pub fn dimensions(self) -> Option<u32> {
+        match self {
+            AvatarSize::Small => Some(32),
+            AvatarSize::Medium => Some(96),
+            AvatarSize::Large => Some(256),
+            AvatarSize::Original => None,
+        }
+    }
+}
+
+/// Fetch the avatar of a room or room member.
+///
+/// Avatars are downloaded through the media cache of the `Client`, so they
+/// are only downloaded once if the cache is enabled.
+///
+/// # Examples
+/// ```no_run
+/// # use std::convert::TryFrom;
+/// # use matrix_sdk::{Client, identifiers::RoomId};
+/// # use url::Url;
+/// # use futures::executor::block_on;
+/// use matrix_sdk::{AvatarExt, AvatarSize};
+/// # block_on(async {
+/// # let homeserver = Url::parse("http://localhost:8080").unwrap();
+/// # let client = Client::new(homeserver, None).unwrap();
+/// # let room_id = RoomId::try_from("!test:localhost").unwrap();
+///
+/// if let Some(room) = client.get_joined_room(&room_id).await {
+///     let avatar = room.read().await.avatar(&client, AvatarSize::Medium).await;
+/// }
+/// # });
+/// ```
+#[async_trait::async_trait]
+pub trait AvatarExt {
+    /// Get the avatar in the given size.
+    ///
+    /// Returns `None` if no avatar is set.
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - The client that should be used to download the avatar.
+    ///
+    /// * `size` - The size the avatar should be fetched in.
+    async fn avatar(&self, client: &Client, size: AvatarSize) -> Result<Option<Vec<u8>>>;
+}
+
+#[async_trait::async_trait]
+impl AvatarExt for Room {
+    async fn avatar(&self, client: &Client, size: AvatarSize) -> Result<Option<Vec<u8>>> {
+        match self.avatar_url() {
+            Some(url) => fetch_avatar(client, url, size).await.map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl AvatarExt for RoomMember {
+    async fn avatar(&self, client: &Client, size: AvatarSize) -> Result<Option<Vec<u8>>> {
+        match &self.avatar_url {
+            Some(url) => fetch_avatar(client, url, size).await.map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Download the avatar with the given `mxc://` URI in the given size.
+async fn fetch_avatar(client: &Client, url: &str, size: AvatarSize) -> Result<Vec<u8>> {
+    match size.dimensions() {
+        Some(dimension) => {
+            let dimension = UInt::from(dimension);
+            client
+                .get_thumbnail(url, dimension, dimension, Some(Method::Crop))
+                .await
+        }
+        None => client.get_media_content(url).await,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::identifiers::{RoomId, UserId};
+    use crate::{ClientConfig, Session};
+
+    use mockito::{mock, Matcher};
+    use std::convert::TryFrom;
+    use std::str::FromStr;
+    use url::Url;
+
+    #[tokio::test]
+    async fn room_avatar() {
+        let homeserver = Url::from_str(&mockito::server_url()).unwrap();
+        let user_id = UserId::try_from("@example:localhost").unwrap();
+        let room_id = RoomId::try_from("!testroom:example.org").unwrap();
+
+        let session = Session {
+            access_token: "1234".to_owned(),
+            user_id: user_id.clone(),
+            device_id: "DEVICEID".to_owned(),
+        };
+
+        let _m = mock(
+            "GET",
+            Matcher::Regex(r"^/_matrix/media/r0/thumbnail/example.com/roomAvatar".to_string()),
+        )
+        .with_status(200)
+        .with_header("content-type", "image/png")
+        .with_body("avatar")
+        .expect(1)
+        .create();
+
+        let config = ClientConfig::new().media_cache_size(1024);
+        let client = Client::new_with_config(homeserver, Some(session), config).unwrap();
+
+        let mut room = Room::new(&room_id, &user_id);
+        assert_eq!(room.avatar(&client, AvatarSize::Small).await.unwrap(), None);
+
+        room.receive_raw_state_event(&serde_json::json!({
+            "content": { "url": "mxc://example.com/roomAvatar" },
+            "event_id": "$h29iv0s8:example.com",
+            "origin_server_ts": 1_520_372_800_469u64,
+            "sender": "@example:localhost",
+            "state_key": "",
+            "type": "m.room.avatar"
+        }));
+
+        for _ in 0..2 {
+            assert_eq!(
+                room.avatar(&client, AvatarSize::Small).await.unwrap(),
+                Some(b"avatar".to_vec())
+            );
+        }
+
+        _m.assert();
+    }
+}
@@ -17,42 +17,130 @@
 use std::collections::BTreeMap;
 use std::collections::HashMap;
 use std::convert::{TryFrom, TryInto};
+use std::io::Read;
 use std::result::Result as StdResult;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use matrix_sdk_common::instant::{Duration, Instant};
-use matrix_sdk_common::locks::RwLock;
+use matrix_sdk_common::locks::{Mutex, RwLock};
 use matrix_sdk_common::uuid::Uuid;
 
+use futures_channel::mpsc::{unbounded, UnboundedSender};
+use futures_core::stream::Stream;
 use futures_timer::Delay as sleep;
 use std::future::Future;
-#[cfg(feature = "encryption")]
-use tracing::{debug, warn};
-use tracing::{info, instrument, trace};
+use tracing::{debug, info, instrument, trace, warn};
 
 use http::Method as HttpMethod;
 use http::Response as HttpResponse;
-use reqwest::header::{HeaderValue, InvalidHeaderValue, AUTHORIZATION};
+use reqwest::header::{HeaderValue, InvalidHeaderValue, AUTHORIZATION, CONTENT_TYPE};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value as JsonValue;
 use url::Url;
 
+use crate::events::collections::all::RoomEvent;
+use crate::events::presence::{PresenceEventContent, PresenceState};
+use crate::events::room::member::MemberEvent;
 use crate::events::room::message::MessageEventContent;
+use crate::events::EventJson;
 use crate::events::EventType;
-use crate::identifiers::{EventId, RoomId, RoomIdOrAliasId, UserId};
+use crate::identifiers::{DeviceId, EventId, RoomAliasId, RoomId, RoomIdOrAliasId, UserId};
+use crate::js_int::UInt;
 use crate::Endpoint;
-
-#[cfg(feature = "encryption")]
-use crate::identifiers::DeviceId;
+use crate::{EndpointError, FromHttpResponseError, ServerError};
 
 use crate::api;
+use crate::completion::{rank_suggestions, PublicRooms};
+#[cfg(feature = "messages")]
+use crate::event_stream::{EventSender, EventStreamEmitter};
+use crate::history::{BatchSendRequest, BatchSendResponse, HistoricalEvent};
+use crate::media;
+use crate::RetentionPolicy;
 use crate::VERSION;
-use crate::{Error, EventEmitter, Result};
+use crate::{
+    AuthStage, AvatarSize, ContentTransformer, DeviceInfo, EmitterHandle, Error, EventEmitter,
+    MessagesRequestBuilder, Quirks, Result, RoomBuilder, RoomSuggestion, ServerVersion, SyncEvent,
+    SyncObserver, SyncRoom, SyncTelemetry, UiaaHandler,
+};
 use matrix_sdk_base::BaseClient;
+use matrix_sdk_base::BaseClientConfig;
+#[cfg(feature = "local-search")]
+use matrix_sdk_base::LocalSearchResult;
+use matrix_sdk_base::MediaCache;
 use matrix_sdk_base::Room;
+use matrix_sdk_base::RoomStateSnapshot;
 use matrix_sdk_base::Session;
+use matrix_sdk_base::SpamChecker;
 use matrix_sdk_base::StateStore;
+#[cfg(feature = "encryption")]
+use matrix_sdk_base::{
+    encrypt_key_export, AttachmentDecryptor, AttachmentEncryptor, EncryptionInfo, GroupSessionInfo,
+    VerificationPolicy, DEFAULT_EXPORT_ROUNDS,
+};
+use matrix_sdk_base::{
+    PermissionError, PowerAction, PowerLevelChanges, PushActions, Pusher, QueuedMessage,
+    SearchOrder, SearchResults, SendState, SpaceHierarchy, SpaceTreeNode, UrlPreview,
+};
 
 const DEFAULT_SYNC_TIMEOUT: Duration = Duration::from_secs(30);
 
+/// The number of user-interactive authentication stages the `UiaaHandler` may
+/// complete for a single request before giving up.
+const UIAA_MAX_STAGES: usize = 10;
+
+/// The number of times a queued message is retried after a transient error.
+const SEND_QUEUE_MAX_RETRIES: u32 = 5;
+
+/// The time to wait before the first retry of a queued message, the time is
+/// doubled for every further retry.
+const SEND_QUEUE_BACKOFF: Duration = Duration::from_secs(1);
+
+/// The time to wait between two redactions of `Client::redact_events()`,
+/// the time is doubled every time the server rate limits us.
+const REDACTION_INTERVAL: Duration = Duration::from_millis(200);
+
+/// The number of times a redaction is retried after the server rate limited
+/// us.
+const REDACTION_MAX_RETRIES: u32 = 5;
+
+/// The number of events before and after every search result that are
+/// returned as its context.
+const SEARCH_CONTEXT_SIZE: u32 = 3;
+
+/// The width and height of the thumbnail that is generated for room avatars.
+const ROOM_AVATAR_THUMBNAIL_SIZE: u32 = 96;
+
+/// The time the homeserver shows us as typing for if no other timeout is
+/// configured.
+const DEFAULT_TYPING_NOTICE_TIMEOUT: Duration = Duration::from_secs(4);
+
+/// How often `room_send_and_wait()` checks if the sent event came down the
+/// sync stream.
+const ECHO_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// The number of rooms that are requested from the room directory when
+/// suggesting rooms.
+const DIRECTORY_SEARCH_LIMIT: u32 = 20;
+
+/// The account data event that holds the rooms our user viewed recently.
+const BREADCRUMBS_EVENT_TYPE: &str = "im.vector.setting.breadcrumbs";
+
+/// The number of recently viewed rooms that are remembered.
+const MAX_BREADCRUMBS: usize = 20;
+
+/// The time to wait between two events or batches of `import_history()`, the
+/// time is doubled every time the server rate limits us.
+const HISTORY_IMPORT_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The number of times an imported event is retried after the server rate
+/// limited us.
+const HISTORY_IMPORT_MAX_RETRIES: u32 = 5;
+
+/// The number of events that are sent in a single `batch_send` request.
+const HISTORY_BATCH_SIZE: usize = 100;
+
 /// An async/await enabled Matrix client.
 ///
 /// All of the state is held in an `Arc` so the `Client` can be cloned freely.
@@ -64,6 +152,56 @@ pub struct Client {
     http_client: reqwest::Client,
     /// User session data.
     pub(crate) base_client: BaseClient,
+    /// The handler that completes user-interactive authentication stages.
+    uiaa_handler: Arc<RwLock<Option<Arc<dyn UiaaHandler>>>>,
+    /// The observer that receives the telemetry of every sync call.
+    sync_observer: Arc<RwLock<Option<Box<dyn SyncObserver>>>>,
+    /// The hook that changes the content of outgoing messages.
+    content_transformer: Arc<RwLock<Option<Box<dyn ContentTransformer>>>>,
+    /// The senders of the streams returned by `presence_stream()`.
+    presence_senders: Arc<Mutex<Vec<UnboundedSender<(UserId, PresenceState)>>>>,
+    /// The senders of the streams returned by `room_events()` and
+    /// `event_stream()`.
+    event_senders: Arc<Mutex<Vec<EventSender>>>,
+    /// Was the emitter that feeds the event streams added to the base client.
+    event_stream_registered: Arc<AtomicBool>,
+    /// Should previews be generated for the URLs in incoming messages.
+    url_previews: bool,
+    /// The number of bytes of member avatars that may be prefetched after a
+    /// sync, `None` if avatars aren't prefetched.
+    avatar_prefetch_budget: Option<usize>,
+    /// The rooms that were active in the previous sync responses and whose
+    /// member avatars weren't prefetched yet.
+    pending_avatar_rooms: Arc<Mutex<Vec<RoomId>>>,
+    /// Should the suggested rooms of a space be joined together with it.
+    auto_join_suggested_rooms: bool,
+    /// Should the replacement of an upgraded room be joined.
+    auto_join_upgraded_rooms: bool,
+    /// The time the homeserver shows us as typing for after a typing notice.
+    typing_notice_timeout: Duration,
+    /// The time the last typing notice was sent at per room, `None` if we
+    /// told the room that we stopped typing.
+    typing_notices: Arc<Mutex<HashMap<RoomId, Option<Instant>>>>,
+    /// The URLs found in incoming messages whose previews weren't fetched
+    /// yet, together with the message they were found in.
+    pending_url_previews: Arc<Mutex<Vec<(RoomId, EventId, String)>>>,
+    /// The senders of the streams returned by `send_queue_updates()`.
+    send_queue_senders: Arc<Mutex<Vec<UnboundedSender<(String, SendState)>>>>,
+    /// The transaction ids of the events `room_send_and_wait()` waits for,
+    /// together with the event once it came down the sync stream.
+    sent_event_waiters: Arc<Mutex<HashMap<String, Option<EventJson<RoomEvent>>>>>,
+    /// Locks that make sure that only one group session share request is in
+    /// flight per room.
+    #[cfg(feature = "encryption")]
+    group_session_locks: Arc<Mutex<HashMap<RoomId, Arc<Mutex<()>>>>>,
+    /// Should the workarounds for homeserver deviations be applied.
+    quirks_enabled: bool,
+    /// The workarounds for the homeserver, `None` if they weren't detected
+    /// yet.
+    quirks: Arc<RwLock<Option<Quirks>>>,
+    /// Does the homeserver support the MSC2716 `batch_send` endpoint, `None`
+    /// if the supported versions weren't fetched yet.
+    batch_send_supported: Arc<RwLock<Option<bool>>>,
 }
 
 impl std::fmt::Debug for Client {
@@ -102,7 +240,13 @@ pub struct ClientConfig {
     proxy: Option<reqwest::Proxy>,
     user_agent: Option<HeaderValue>,
     disable_ssl_verification: bool,
-    state_store: Option<Box<dyn StateStore>>,
+    url_previews: bool,
+    avatar_prefetch_budget: Option<usize>,
+    auto_join_suggested_rooms: bool,
+    auto_join_upgraded_rooms: bool,
+    typing_notice_timeout: Option<Duration>,
+    disable_quirks: bool,
+    base_config: BaseClientConfig,
 }
 
 impl std::fmt::Debug for ClientConfig {
@@ -114,6 +258,13 @@ impl std::fmt::Debug for ClientConfig {
 
         res.field("user_agent", &self.user_agent)
             .field("disable_ssl_verification", &self.disable_ssl_verification)
+            .field("url_previews", &self.url_previews)
+            .field("avatar_prefetch_budget", &self.avatar_prefetch_budget)
+            .field("auto_join_suggested_rooms", &self.auto_join_suggested_rooms)
+            .field("auto_join_upgraded_rooms", &self.auto_join_upgraded_rooms)
+            .field("typing_notice_timeout", &self.typing_notice_timeout)
+            .field("disable_quirks", &self.disable_quirks)
+            .field("base_config", &self.base_config)
             .finish()
     }
 }
@@ -163,17 +314,223 @@ impl ClientConfig {
     ///
     /// The state store should be opened before being set.
     pub fn state_store(mut self, store: Box<dyn StateStore>) -> Self {
-        self.state_store = Some(store);
+        self.base_config = self.base_config.state_store(store);
+        self
+    }
+
+    /// Set the `RetentionPolicy` that should be applied to the messages of
+    /// every room.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use std::time::Duration;
+    /// use matrix_sdk::{ClientConfig, RetentionPolicy};
+    ///
+    /// let policy = RetentionPolicy::new()
+    ///     .max_events(5)
+    ///     .max_age(Duration::from_secs(60 * 60 * 24));
+    /// let client_config = ClientConfig::new().retention_policy(policy);
+    /// ```
+    #[cfg(feature = "messages")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "messages")))]
+    pub fn retention_policy(mut self, policy: RetentionPolicy) -> Self {
+        self.base_config = self.base_config.retention_policy(policy);
+        self
+    }
+
+    /// Set the number of member events a room may receive in a single sync
+    /// response before they are delivered to the `EventEmitter` as a single
+    /// `MembersSummary`.
+    ///
+    /// # Arguments
+    ///
+    /// * `threshold` - The maximal number of member events that are emitted
+    /// one by one.
+    pub fn member_batch_threshold(mut self, threshold: usize) -> Self {
+        self.base_config = self.base_config.member_batch_threshold(threshold);
+        self
+    }
+
+    /// Enable caching of downloaded media and thumbnails.
+    ///
+    /// Cached media is served by `get_media_content()` and `get_thumbnail()`
+    /// without contacting the server. Once the cache grows larger than the
+    /// given size the least recently used media is evicted. The cache is
+    /// persisted in the state store if one is configured.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_size` - The maximal number of bytes the cached media may take
+    /// up.
+    pub fn media_cache_size(mut self, max_size: usize) -> Self {
+        self.base_config = self.base_config.media_cache_size(max_size);
+        self
+    }
+
+    /// Generate previews for the URLs found in incoming messages.
+    ///
+    /// The previews are fetched from the homeserver after the messages were
+    /// received, `Room::url_previews()` returns them and the `EventEmitter`
+    /// receives an `on_room_url_preview()` call once a preview is ready.
+    /// `Client::sync_forever()` fetches them automatically, users of
+    /// `Client::sync()` need to call `Client::fetch_url_previews()`.
+    pub fn url_previews(mut self) -> Self {
+        self.url_previews = true;
+        self
+    }
+
+    /// Prefetch the avatar thumbnails of the members of active rooms.
+    ///
+    /// After a sync the small avatar thumbnails of the members of the rooms
+    /// that received new timeline events are downloaded into the media
+    /// cache, so member lists can be rendered without waiting for the
+    /// avatars. `Client::sync_forever()` prefetches them automatically while
+    /// it's idle, users of `Client::sync()` need to call
+    /// `Client::prefetch_member_avatars()`.
+    ///
+    /// Nothing is prefetched unless the media cache is enabled with
+    /// `media_cache_size()`.
+    ///
+    /// # Arguments
+    ///
+    /// * `budget` - The maximal number of bytes that are downloaded per
+    /// prefetch.
+    pub fn avatar_prefetch(mut self, budget: usize) -> Self {
+        self.avatar_prefetch_budget = Some(budget);
+        self
+    }
+
+    /// Join the rooms that the admins of a space suggest when joining the
+    /// space with `Client::join_space()`.
+    pub fn auto_join_suggested_rooms(mut self) -> Self {
+        self.auto_join_suggested_rooms = true;
+        self
+    }
+
+    /// Join the room that replaces a joined room once the room is upgraded.
+    ///
+    /// The replacement room is joined as soon as the `m.room.tombstone` event
+    /// of the old room comes down the sync stream, so bots follow room
+    /// upgrades without any further work.
+    pub fn auto_join_upgraded_rooms(mut self) -> Self {
+        self.auto_join_upgraded_rooms = true;
+        self
+    }
+
+    /// Set the time the homeserver shows us as typing for after a call to
+    /// `Client::typing_notice()`, defaults to 4 seconds.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - The time after which the typing notice expires.
+    pub fn typing_notice_timeout(mut self, timeout: Duration) -> Self {
+        self.typing_notice_timeout = Some(timeout);
+        self
+    }
+
+    /// Count the event types that the client receives but can't handle.
+    ///
+    /// This helps to discover custom events that should get a handler, the
+    /// counts are available through `Client::unhandled_event_types()`.
+    pub fn track_unhandled_events(mut self) -> Self {
+        self.base_config = self.base_config.track_unhandled_events();
+        self
+    }
+
+    /// Skip the `m.typing` ephemeral events the server sends.
+    ///
+    /// Bots rarely care about typing notifications, skipping them saves the
+    /// work of deserializing and dispatching them.
+    pub fn ignore_typing_events(mut self) -> Self {
+        self.base_config = self.base_config.ignore_typing_events();
+        self
+    }
+
+    /// Skip the `m.receipt` ephemeral events the server sends.
+    pub fn ignore_receipt_events(mut self) -> Self {
+        self.base_config = self.base_config.ignore_receipt_events();
+        self
+    }
+
+    /// Set the `VerificationPolicy` that decides which key verifications are
+    /// accepted and completed without user interaction.
+    ///
+    /// Headless clients, e.g. bots, can use this to get verified by the
+    /// devices of their operator without comparing emojis.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use matrix_sdk::{ClientConfig, VerificationPolicy};
+    ///
+    /// let client_config = ClientConfig::new().verification_policy(VerificationPolicy::OwnDevices);
+    /// ```
+    #[cfg(feature = "encryption")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "encryption")))]
+    pub fn verification_policy(mut self, policy: VerificationPolicy) -> Self {
+        self.base_config = self.base_config.verification_policy(policy);
+        self
+    }
+
+    /// Deserialize and decrypt sync responses on a bounded pool of worker
+    /// jobs instead of the task that calls `Client::sync()`.
+    ///
+    /// Large sync responses, e.g. the initial sync of a busy account, would
+    /// otherwise block the runtime thread for a long time.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_jobs` - The number of jobs that may run at the same time.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn sync_worker_pool(mut self, max_jobs: usize) -> Self {
+        self.base_config = self.base_config.sync_worker_pool(max_jobs);
+        self
+    }
+
+    /// Don't wait for all the rooms to be loaded from the state store before
+    /// the first sync.
+    ///
+    /// Rooms are loaded from the store once they are needed, spawn
+    /// `Client::load_stored_rooms()` to load the rest of them in the
+    /// background.
+    pub fn lazy_room_loading(mut self) -> Self {
+        self.base_config = self.base_config.lazy_room_loading();
+        self
+    }
+
+    /// Don't apply the workarounds for known deviations of homeservers from
+    /// the spec, see `Quirks`.
+    ///
+    /// Responses that don't follow the spec will fail to deserialize.
+    pub fn disable_quirks(mut self) -> Self {
+        self.disable_quirks = true;
         self
     }
 }
 
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Clone)]
 /// Settings for a sync call.
 pub struct SyncSettings {
     pub(crate) timeout: Option<Duration>,
     pub(crate) token: Option<String>,
     pub(crate) full_state: bool,
+    pub(crate) skip_left_rooms: bool,
+    pub(crate) lazy_load_members: bool,
+    pub(crate) filter: Option<sync_events::Filter>,
+}
+
+impl Default for SyncSettings {
+    fn default() -> Self {
+        Self {
+            timeout: None,
+            token: None,
+            full_state: false,
+            skip_left_rooms: false,
+            lazy_load_members: true,
+            filter: None,
+        }
+    }
 }
 
 impl SyncSettings {
@@ -214,24 +571,80 @@ impl SyncSettings {
         self.full_state = full_state;
         self
     }
+
+    /// Should the rooms the user left be skipped.
+    ///
+    /// Left rooms make up most of the initial sync of old accounts, the
+    /// server is asked to leave them out of the response and the client
+    /// ignores the left rooms of servers that still return them.
+    ///
+    /// # Arguments
+    /// * `skip_left_rooms` - A boolean deciding if left rooms should be
+    ///     skipped or not.
+    pub fn skip_left_rooms(mut self, skip_left_rooms: bool) -> Self {
+        self.skip_left_rooms = skip_left_rooms;
+        self
+    }
+
+    /// Should the server lazily load the members of rooms, enabled by
+    /// default.
+    ///
+    /// Only the members that sent the events of the sync response are
+    /// returned, which keeps the initial sync of big rooms small. The full
+    /// member list of a room can be fetched with
+    /// `Client::get_room_members()`.
+    ///
+    /// # Arguments
+    /// * `lazy_load_members` - A boolean deciding if members should be lazily
+    ///     loaded or not.
+    pub fn lazy_load_members(mut self, lazy_load_members: bool) -> Self {
+        self.lazy_load_members = lazy_load_members;
+        self
+    }
+
+    /// Set the filter the sync should use, either an inline filter
+    /// definition or the id of a filter that was uploaded with
+    /// `Client::get_or_upload_filter()`.
+    ///
+    /// The filter replaces the one that is built from the `skip_left_rooms`
+    /// and `lazy_load_members` settings.
+    ///
+    /// # Arguments
+    ///
+    /// * `filter` - The filter that should be used for the sync call.
+    pub fn filter(mut self, filter: sync_events::Filter) -> Self {
+        self.filter = Some(filter);
+        self
+    }
 }
 
+use api::r0::account::{change_password, register};
+use api::r0::alias::get_alias;
+use api::r0::context::get_context;
+use api::r0::device::{delete_devices, get_devices, update_device};
+use api::r0::filter::{create_filter, FilterDefinition, RoomEventFilter};
 #[cfg(feature = "encryption")]
 use api::r0::keys::{claim_keys, get_keys, upload_keys, KeyAlgorithm};
+use api::r0::media::{create_content, get_content, get_content_thumbnail};
 use api::r0::membership::{
     ban_user, forget_room,
     invite_user::{self, InvitationRecipient},
-    join_room_by_id, join_room_by_id_or_alias, kick_user, leave_room, Invite3pid,
+    join_room_by_id, join_room_by_id_or_alias, kick_user, leave_room, unban_user, Invite3pid,
 };
 use api::r0::message::create_message_event;
-use api::r0::message::get_message_events;
+use api::r0::message::get_message_events::{self, Direction};
+use api::r0::presence::{get_presence, set_presence};
+use api::r0::read_marker::set_read_marker;
 use api::r0::receipt::create_receipt;
-use api::r0::room::create_room;
+use api::r0::redact::redact_event;
+use api::r0::room::create_room::{self, RoomPreset};
 use api::r0::session::login;
+use api::r0::state::create_state_event_for_key;
 use api::r0::sync::sync_events;
 #[cfg(feature = "encryption")]
 use api::r0::to_device::send_event_to_device;
 use api::r0::typing::create_typing_event;
+use api::r0::uiaa::{AuthData, UiaaResponse};
 
 impl Client {
     /// Creates a new client for making HTTP requests to the given homeserver.
@@ -246,6 +659,27 @@ impl Client {
         Client::new_with_config(homeserver_url, session, config)
     }
 
+    /// Creates a new client for an appservice that authenticates with the
+    /// `as_token` of its registration instead of logging in.
+    ///
+    /// The client doesn't have a device, so end-to-end encryption isn't
+    /// available. Use `Session::appservice()` with `new_with_config()` to
+    /// configure the client further.
+    ///
+    /// # Arguments
+    ///
+    /// * `homeserver_url` - The homeserver that the client should connect to.
+    /// * `as_token` - The `as_token` of the appservice registration.
+    /// * `user_id` - The user the appservice acts as, usually its sender user.
+    pub fn new_appservice<U: TryInto<Url>>(
+        homeserver_url: U,
+        as_token: &str,
+        user_id: &UserId,
+    ) -> Result<Self> {
+        let session = Session::appservice(as_token, user_id.clone());
+        Client::new(homeserver_url, Some(session))
+    }
+
     /// Create a new client with the given configuration.
     ///
     /// # Arguments
@@ -294,16 +728,35 @@ impl Client {
 
         let http_client = http_client.build()?;
 
-        let base_client = if let Some(store) = config.state_store {
-            BaseClient::new_with_state_store(session, store)?
-        } else {
-            BaseClient::new(session)?
-        };
+        let base_client = BaseClient::new_with_config(session, config.base_config)?;
 
         Ok(Self {
             homeserver,
             http_client,
             base_client,
+            uiaa_handler: Arc::new(RwLock::new(None)),
+            sync_observer: Arc::new(RwLock::new(None)),
+            content_transformer: Arc::new(RwLock::new(None)),
+            presence_senders: Arc::new(Mutex::new(Vec::new())),
+            event_senders: Arc::new(Mutex::new(Vec::new())),
+            event_stream_registered: Arc::new(AtomicBool::new(false)),
+            url_previews: config.url_previews,
+            avatar_prefetch_budget: config.avatar_prefetch_budget,
+            pending_avatar_rooms: Arc::new(Mutex::new(Vec::new())),
+            auto_join_suggested_rooms: config.auto_join_suggested_rooms,
+            auto_join_upgraded_rooms: config.auto_join_upgraded_rooms,
+            typing_notice_timeout: config
+                .typing_notice_timeout
+                .unwrap_or(DEFAULT_TYPING_NOTICE_TIMEOUT),
+            typing_notices: Arc::new(Mutex::new(HashMap::new())),
+            pending_url_previews: Arc::new(Mutex::new(Vec::new())),
+            send_queue_senders: Arc::new(Mutex::new(Vec::new())),
+            sent_event_waiters: Arc::new(Mutex::new(HashMap::new())),
+            #[cfg(feature = "encryption")]
+            group_session_locks: Arc::new(Mutex::new(HashMap::new())),
+            quirks_enabled: !config.disable_quirks,
+            quirks: Arc::new(RwLock::new(None)),
+            batch_send_supported: Arc::new(RwLock::new(None)),
         })
     }
 
@@ -312,6 +765,17 @@ impl Client {
         self.base_client.logged_in().await
     }
 
+    /// The user id of the logged in user, `None` if the client isn't logged
+    /// in.
+    pub async fn user_id(&self) -> Option<UserId> {
+        self.base_client
+            .session()
+            .read()
+            .await
+            .as_ref()
+            .map(|s| s.user_id.clone())
+    }
+
     /// The Homeserver of the client.
     pub fn homeserver(&self) -> &Url {
         &self.homeserver
@@ -320,1054 +784,8284 @@ impl Client {
     /// Add `EventEmitter` to `Client`.
     ///
     /// The methods of `EventEmitter` are called when the respective `RoomEvents` occur.
-    pub async fn add_event_emitter(&mut self, emitter: Box<dyn EventEmitter>) {
-        self.base_client.add_event_emitter(emitter).await;
-    }
-
-    /// Returns the joined rooms this client knows about.
-    ///
-    /// A `HashMap` of room id to `matrix::models::Room`
-    pub fn joined_rooms(&self) -> Arc<RwLock<HashMap<RoomId, Arc<RwLock<Room>>>>> {
-        self.base_client.joined_rooms()
-    }
-
-    /// Returns the invited rooms this client knows about.
+    /// Any number of emitters can be added, they are called in the order they
+    /// were added in.
     ///
-    /// A `HashMap` of room id to `matrix::models::Room`
-    pub fn invited_rooms(&self) -> Arc<RwLock<HashMap<RoomId, Arc<RwLock<Room>>>>> {
-        self.base_client.invited_rooms()
+    /// Returns a handle that can be used to remove the emitter again.
+    pub async fn add_event_emitter(&mut self, emitter: Box<dyn EventEmitter>) -> EmitterHandle {
+        self.base_client.add_event_emitter(emitter).await
     }
 
-    /// Returns the left rooms this client knows about.
+    /// Remove an `EventEmitter` that was added with `add_event_emitter()`, e.g.
+    /// when a plugin of a bot is unloaded.
     ///
-    /// A `HashMap` of room id to `matrix::models::Room`
-    pub fn left_rooms(&self) -> Arc<RwLock<HashMap<RoomId, Arc<RwLock<Room>>>>> {
-        self.base_client.left_rooms()
-    }
-
-    /// Get a joined room with the given room id.
+    /// Returns true if the emitter was removed, false if it was already
+    /// removed.
     ///
     /// # Arguments
     ///
-    /// `room_id` - The unique id of the room that should be fetched.
-    pub async fn get_joined_room(&self, room_id: &RoomId) -> Option<Arc<RwLock<Room>>> {
-        self.base_client.get_joined_room(room_id).await
+    /// * `handle` - The handle `add_event_emitter()` returned for the
+    /// emitter.
+    pub async fn remove_event_emitter(&self, handle: EmitterHandle) -> bool {
+        self.base_client.remove_event_emitter(handle).await
     }
 
-    /// Get an invited room with the given room id.
-    ///
-    /// # Arguments
+    /// Register a handler that is called with every timeline or state event
+    /// of the given type.
     ///
-    /// `room_id` - The unique id of the room that should be fetched.
-    pub async fn get_invited_room(&self, room_id: &RoomId) -> Option<Arc<RwLock<Room>>> {
-        self.base_client.get_invited_room(room_id).await
-    }
-
-    /// Get a left room with the given room id.
+    /// The type of the event is taken from the argument of the handler, any
+    /// type implementing `SyncEvent` can be handled. Unlike the
+    /// `EventEmitter`, any number of handlers can be registered.
     ///
     /// # Arguments
     ///
-    /// `room_id` - The unique id of the room that should be fetched.
-    pub async fn get_left_room(&self, room_id: &RoomId) -> Option<Arc<RwLock<Room>>> {
-        self.base_client.get_left_room(room_id).await
-    }
-
-    /// This allows `Client` to manually sync state with the provided `StateStore`.
-    ///
-    /// Returns true when a successful `StateStore` sync has completed.
+    /// * `handler` - The handler that is called with the event and the room
+    /// the event belongs to.
     ///
     /// # Examples
-    ///
     /// ```no_run
-    /// use matrix_sdk::{Client, ClientConfig, JsonStore, RoomBuilder};
-    /// # use matrix_sdk::api::r0::room::Visibility;
+    /// # use matrix_sdk::{Client, SyncRoom};
+    /// # use matrix_sdk::events::room::message::MessageEvent;
     /// # use url::Url;
-    ///
     /// # let homeserver = Url::parse("http://example.com").unwrap();
-    /// let store = JsonStore::open("path/to/store").unwrap();
-    /// let config = ClientConfig::new().state_store(Box::new(store));
-    /// let mut client = Client::new(homeserver, None).unwrap();
+    /// # let client = Client::new(homeserver, None).unwrap();
     /// # use futures::executor::block_on;
     /// # block_on(async {
-    /// let _ = client.login("name", "password", None, None).await.unwrap();
-    /// // returns true when a state store sync is successful
-    /// assert!(client.sync_with_state_store().await.unwrap());
-    /// // now state is restored without a request to the server
-    /// let mut names = vec![];
-    /// for r in client.joined_rooms().read().await.values() {
-    ///     names.push(r.read().await.display_name());
-    /// }
-    /// assert_eq!(vec!["room".to_string(), "names".to_string()], names)
+    /// let sender = client.clone();
+    ///
+    /// client
+    ///     .register_event_handler(move |event: MessageEvent, room: SyncRoom| {
+    ///         let client = sender.clone();
+    ///         async move {
+    ///             println!("{} sent a message to {}", event.sender, room.context().room_id);
+    ///             let _ = client.sync_token().await;
+    ///         }
+    ///     })
+    ///     .await;
     /// # });
     /// ```
-    pub async fn sync_with_state_store(&self) -> Result<bool> {
-        Ok(self.base_client.sync_with_state_store().await?)
+    pub async fn register_event_handler<Ev, H, Fut>(&self, handler: H)
+    where
+        Ev: SyncEvent,
+        H: Fn(Ev, SyncRoom) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.base_client.register_event_handler(handler).await;
     }
 
-    /// This allows `Client` to manually store `Room` state with the provided
-    /// `StateStore`.
+    /// Set the `SpamChecker` that classifies incoming messages.
     ///
-    /// Returns Ok when a successful `Room` store occurs.
-    pub async fn store_room_state(&self, room_id: &RoomId) -> Result<()> {
-        self.base_client
-            .store_room_state(room_id)
-            .await
-            .map_err(Into::into)
+    /// Messages the checker drops don't update the room state and aren't
+    /// delivered to the `EventEmitter`, the verdict for every other message
+    /// is delivered to `EventEmitter::on_room_message_verdict()`.
+    ///
+    /// # Arguments
+    ///
+    /// * `checker` - The check that classifies the messages.
+    pub async fn set_spam_checker(&self, checker: Box<dyn SpamChecker>) {
+        self.base_client.set_spam_checker(checker).await;
     }
 
-    /// Login to the server.
+    /// Set the handler that completes user-interactive authentication.
+    ///
+    /// Requests that require user-interactive authentication, e.g.
+    /// registering, deleting devices or changing the password, will ask the
+    /// handler for the data of the next stage and retry the request until it
+    /// either succeeds or the handler gives up.
     ///
     /// # Arguments
     ///
-    /// * `user` - The user that should be logged in to the homeserver.
+    /// * `handler` - The handler that supplies the stage data.
+    pub async fn set_uiaa_handler(&self, handler: Box<dyn UiaaHandler>) {
+        *self.uiaa_handler.write().await = Some(Arc::from(handler));
+    }
+
+    /// Remove the user-interactive authentication handler.
     ///
-    /// * `password` - The password of the user.
+    /// Requests requiring further authentication will return an error
+    /// containing the `UiaaInfo` again, see `Error::uiaa_response()`.
+    pub async fn remove_uiaa_handler(&self) {
+        self.uiaa_handler.write().await.take();
+    }
+
+    /// Set the observer that receives the `SyncTelemetry` of every sync
+    /// call.
     ///
-    /// * `device_id` - A unique id that will be associated with this session. If
-    ///     not given the homeserver will create one. Can be an existing
-    ///     device_id from a previous login call. Note that this should be done
-    ///     only if the client also holds the encryption keys for this device.
-    #[instrument(skip(password))]
-    pub async fn login<S: Into<String> + std::fmt::Debug>(
-        &self,
-        user: S,
-        password: S,
-        device_id: Option<S>,
-        initial_device_display_name: Option<S>,
-    ) -> Result<login::Response> {
-        info!("Logging in to {} as {:?}", self.homeserver, user);
+    /// # Arguments
+    ///
+    /// * `observer` - The observer that should receive the telemetry.
+    pub async fn set_sync_observer(&self, observer: Box<dyn SyncObserver>) {
+        *self.sync_observer.write().await = Some(observer);
+    }
 
-        let request = login::Request {
-            user: login::UserInfo::MatrixId(user.into()),
-            login_info: login::LoginInfo::Password {
-                password: password.into(),
-            },
-            device_id: device_id.map(|d| d.into()),
-            initial_device_display_name: initial_device_display_name.map(|d| d.into()),
-        };
-
-        let response = self.send(request).await?;
-        self.base_client.receive_login_response(&response).await?;
-
-        Ok(response)
+    /// Remove the observer that receives the sync telemetry.
+    pub async fn remove_sync_observer(&self) {
+        self.sync_observer.write().await.take();
     }
 
-    /// Join a room by `RoomId`.
+    /// Set the hook that changes the content of every outgoing message, e.g.
+    /// to linkify URLs or to append a signature to the messages of a bot.
     ///
-    /// Returns a `join_room_by_id::Response` consisting of the
-    /// joined rooms `RoomId`.
+    /// See `ContentTransformer` for the messages the hook is applied to.
     ///
     /// # Arguments
     ///
-    /// * `room_id` - The `RoomId` of the room to be joined.
-    pub async fn join_room_by_id(&self, room_id: &RoomId) -> Result<join_room_by_id::Response> {
-        let request = join_room_by_id::Request {
-            room_id: room_id.clone(),
-            third_party_signed: None,
-        };
-        self.send(request).await
+    /// * `transformer` - The hook that should transform the messages.
+    pub async fn set_content_transformer(&self, transformer: Box<dyn ContentTransformer>) {
+        *self.content_transformer.write().await = Some(transformer);
     }
 
-    /// Join a room by `RoomId`.
-    ///
-    /// Returns a `join_room_by_id_or_alias::Response` consisting of the
-    /// joined rooms `RoomId`.
-    ///
-    /// # Arguments
-    ///
-    /// * `alias` - The `RoomId` or `RoomAliasId` of the room to be joined.
-    /// An alias looks like this `#name:example.com`
-    pub async fn join_room_by_id_or_alias(
+    /// Remove the hook that changes the content of outgoing messages.
+    pub async fn remove_content_transformer(&self) {
+        self.content_transformer.write().await.take();
+    }
+
+    /// Apply the content transformer to the content of an outgoing message.
+    async fn transform_content(
         &self,
-        alias: &RoomIdOrAliasId,
-        server_names: &[String],
-    ) -> Result<join_room_by_id_or_alias::Response> {
-        let request = join_room_by_id_or_alias::Request {
-            room_id_or_alias: alias.clone(),
-            server_name: server_names.to_owned(),
-            third_party_signed: None,
-        };
-        self.send(request).await
+        room_id: &RoomId,
+        mut content: MessageEventContent,
+    ) -> MessageEventContent {
+        if let Some(transformer) = self.content_transformer.read().await.as_ref() {
+            transformer.transform(room_id, &mut content).await;
+        }
+
+        content
     }
 
-    /// Forget a room by `RoomId`.
-    ///
-    /// Returns a `forget_room::Response`, an empty response.
-    ///
-    /// # Arguments
+    /// Returns the joined rooms this client knows about.
     ///
-    /// * `room_id` - The `RoomId` of the room to be forget.
-    pub async fn forget_room_by_id(&self, room_id: &RoomId) -> Result<forget_room::Response> {
-        let request = forget_room::Request {
-            room_id: room_id.clone(),
-        };
-        self.send(request).await
+    /// A `HashMap` of room id to `matrix::models::Room`
+    pub fn joined_rooms(&self) -> Arc<RwLock<HashMap<RoomId, Arc<RwLock<Room>>>>> {
+        self.base_client.joined_rooms()
     }
 
-    /// Ban a user from a room by `RoomId` and `UserId`.
+    /// Attach data of an application to a room, the data is stored in the
+    /// `StateStore` together with the room.
     ///
-    /// Returns a `ban_user::Response`, an empty response.
+    /// This lets e.g. a bridge keep the mapping to the room of the remote
+    /// network next to the room. Returns false if the room isn't known.
     ///
     /// # Arguments
     ///
-    /// * `room_id` - The `RoomId` of the room to ban the user from.
+    /// * `room_id` - The id of the room.
     ///
-    /// * `user_id` - The user to ban by `UserId`.
+    /// * `namespace` - The namespace of the application, e.g.
+    /// `org.example.bridge`.
     ///
-    /// * `reason` - The reason for banning this user.
-    pub async fn ban_user(
+    /// * `data` - The data that should be attached, it replaces the data that
+    /// was previously attached under the namespace.
+    pub async fn set_room_app_data<T: Serialize>(
         &self,
         room_id: &RoomId,
-        user_id: &UserId,
-        reason: Option<String>,
-    ) -> Result<ban_user::Response> {
-        let request = ban_user::Request {
-            reason,
-            room_id: room_id.clone(),
-            user_id: user_id.clone(),
-        };
-        self.send(request).await
+        namespace: &str,
+        data: &T,
+    ) -> Result<bool> {
+        Ok(self
+            .base_client
+            .set_room_app_data(room_id, namespace, data)
+            .await?)
     }
 
-    /// Kick a user out of the specified room.
-    ///
-    /// Returns a `kick_user::Response`, an empty response.
+    /// Get the data an application attached to a room.
     ///
     /// # Arguments
     ///
-    /// * `room_id` - The `RoomId` of the room the user should be kicked out of.
-    ///
-    /// * `user_id` - The `UserId` of the user that should be kicked out of the room.
+    /// * `room_id` - The id of the room.
     ///
-    /// * `reason` - Optional reason why the room member is being kicked out.
-    pub async fn kick_user(
+    /// * `namespace` - The namespace of the application.
+    pub async fn room_app_data<T: DeserializeOwned>(
         &self,
         room_id: &RoomId,
-        user_id: &UserId,
-        reason: Option<String>,
-    ) -> Result<kick_user::Response> {
-        let request = kick_user::Request {
-            reason,
-            room_id: room_id.clone(),
-            user_id: user_id.clone(),
-        };
-        self.send(request).await
+        namespace: &str,
+    ) -> Result<Option<T>> {
+        Ok(self.base_client.room_app_data(room_id, namespace).await?)
     }
 
-    /// Leave the specified room.
+    /// Persist a small application defined value, e.g. the last command a bot
+    /// processed or a feature flag, in the state store of the client.
     ///
-    /// Returns a `leave_room::Response`, an empty response.
+    /// Nothing is persisted if the client doesn't have a state store.
     ///
     /// # Arguments
     ///
-    /// * `room_id` - The `RoomId` of the room to leave.
+    /// * `key` - The key the value is stored under, applications should
+    /// prefix it with their own namespace.
     ///
-    pub async fn leave_room(&self, room_id: &RoomId) -> Result<leave_room::Response> {
-        let request = leave_room::Request {
-            room_id: room_id.clone(),
-        };
-        self.send(request).await
+    /// * `value` - The value that should be stored.
+    pub async fn set_custom_value(&self, key: &str, value: &[u8]) -> Result<()> {
+        Ok(self.base_client.set_custom_value(key, value).await?)
     }
 
-    /// Invite the specified user by `UserId` to the given room.
+    /// Load a value that was stored with `set_custom_value()`.
     ///
-    /// Returns a `invite_user::Response`, an empty response.
+    /// # Arguments
+    ///
+    /// * `key` - The key the value was stored under.
+    pub async fn get_custom_value(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        Ok(self.base_client.get_custom_value(key).await?)
+    }
+
+    /// Remove a value that was stored with `set_custom_value()`.
     ///
     /// # Arguments
     ///
-    /// * `room_id` - The `RoomId` of the room to invite the specified user to.
+    /// * `key` - The key the value was stored under.
+    pub async fn remove_custom_value(&self, key: &str) -> Result<()> {
+        Ok(self.base_client.remove_custom_value(key).await?)
+    }
+
+    /// Get the joined rooms the homeserver sends its server notices to, e.g.
+    /// the notice that the usage limit of the homeserver was reached.
     ///
-    /// * `user_id` - The `UserId` of the user to invite to the room.
-    pub async fn invite_user_by_id(
-        &self,
-        room_id: &RoomId,
-        user_id: &UserId,
-    ) -> Result<invite_user::Response> {
-        let request = invite_user::Request {
-            room_id: room_id.clone(),
-            recipient: InvitationRecipient::UserId {
-                user_id: user_id.clone(),
-            },
-        };
-        self.send(request).await
+    /// The latest notice of a room is available with
+    /// `Room::server_notice()`.
+    pub async fn server_notices(&self) -> Vec<Arc<RwLock<Room>>> {
+        self.base_client.server_notice_rooms().await
     }
 
-    /// Invite the specified user by third party id to the given room.
+    /// Group the joined rooms under the joined spaces they are part of, so
+    /// the room list can be rendered as a tree of spaces.
     ///
-    /// Returns a `invite_user::Response`, an empty response.
+    /// Rooms that aren't part of any joined space are at the top level of the
+    /// returned tree.
+    pub async fn joined_space_tree(&self) -> Vec<SpaceTreeNode> {
+        self.base_client.joined_space_tree().await
+    }
+
+    /// Returns the invited rooms this client knows about.
     ///
-    /// # Arguments
+    /// A `HashMap` of room id to `matrix::models::Room`
+    pub fn invited_rooms(&self) -> Arc<RwLock<HashMap<RoomId, Arc<RwLock<Room>>>>> {
+        self.base_client.invited_rooms()
+    }
+
+    /// Returns the left rooms this client knows about.
     ///
-    /// * `room_id` - The `RoomId` of the room to invite the specified user to.
+    /// A `HashMap` of room id to `matrix::models::Room`
     ///
-    /// * `invite_id` - A third party id of a user to invite to the room.
-    pub async fn invite_user_by_3pid(
-        &self,
-        room_id: &RoomId,
-        invite_id: &Invite3pid,
-    ) -> Result<invite_user::Response> {
-        let request = invite_user::Request {
-            room_id: room_id.clone(),
-            recipient: InvitationRecipient::ThirdPartyId(invite_id.clone()),
-        };
-        self.send(request).await
+    /// Left rooms that are restored from the state store are loaded lazily,
+    /// call `load_left_rooms()` first if all of them are required.
+    pub fn left_rooms(&self) -> Arc<RwLock<HashMap<RoomId, Arc<RwLock<Room>>>>> {
+        self.base_client.left_rooms()
     }
 
-    /// Create a room using the `RoomBuilder` and send the request.
+    /// Load all the left rooms from the state store that weren't loaded yet.
+    pub async fn load_left_rooms(&self) -> Result<()> {
+        Ok(self.base_client.load_left_rooms().await?)
+    }
+
+    /// Get a joined room with the given room id.
+    ///
+    /// # Arguments
     ///
-    /// Sends a request to `/_matrix/client/r0/createRoom`, returns a `create_room::Response`,
-    /// this is an empty response.
+    /// `room_id` - The unique id of the room that should be fetched.
+    pub async fn get_joined_room(&self, room_id: &RoomId) -> Option<Arc<RwLock<Room>>> {
+        self.base_client.get_joined_room(room_id).await
+    }
+
+    /// Get an invited room with the given room id.
     ///
     /// # Arguments
     ///
-    /// * `room` - The easiest way to create this request is using the `RoomBuilder`.
+    /// `room_id` - The unique id of the room that should be fetched.
+    pub async fn get_invited_room(&self, room_id: &RoomId) -> Option<Arc<RwLock<Room>>> {
+        self.base_client.get_invited_room(room_id).await
+    }
+
+    /// Get a left room with the given room id.
+    ///
+    /// # Arguments
+    ///
+    /// `room_id` - The unique id of the room that should be fetched.
+    pub async fn get_left_room(&self, room_id: &RoomId) -> Option<Arc<RwLock<Room>>> {
+        self.base_client.get_left_room(room_id).await
+    }
+
+    /// This allows `Client` to manually sync state with the provided `StateStore`.
+    ///
+    /// Returns true when a successful `StateStore` sync has completed.
     ///
     /// # Examples
+    ///
     /// ```no_run
-    /// use matrix_sdk::{Client, RoomBuilder};
+    /// use matrix_sdk::{Client, ClientConfig, JsonStore, RoomBuilder};
     /// # use matrix_sdk::api::r0::room::Visibility;
     /// # use url::Url;
     ///
     /// # let homeserver = Url::parse("http://example.com").unwrap();
-    /// let mut builder = RoomBuilder::default();
-    /// builder.creation_content(false)
-    ///     .initial_state(vec![])
-    ///     .visibility(Visibility::Public)
-    ///     .name("name")
-    ///     .room_version("v1.0");
-    ///
-    /// let mut cli = Client::new(homeserver, None).unwrap();
+    /// let store = JsonStore::open("path/to/store").unwrap();
+    /// let config = ClientConfig::new().state_store(Box::new(store));
+    /// let mut client = Client::new(homeserver, None).unwrap();
     /// # use futures::executor::block_on;
     /// # block_on(async {
-    /// assert!(cli.create_room(builder).await.is_ok());
+    /// let _ = client.login("name", "password", None, None).await.unwrap();
+    /// // returns true when a state store sync is successful
+    /// assert!(client.sync_with_state_store().await.unwrap());
+    /// // now state is restored without a request to the server
+    /// let mut names = vec![];
+    /// for r in client.joined_rooms().read().await.values() {
+    ///     names.push(r.read().await.display_name());
+    /// }
+    /// assert_eq!(vec!["room".to_string(), "names".to_string()], names)
     /// # });
     /// ```
-    pub async fn create_room<R: Into<create_room::Request>>(
-        &self,
-        room: R,
-    ) -> Result<create_room::Response> {
-        let request = room.into();
-        self.send(request).await
+    pub async fn sync_with_state_store(&self) -> Result<bool> {
+        Ok(self.base_client.sync_with_state_store().await?)
     }
 
-    /// Get messages starting at a specific sync point using the
-    /// `MessagesRequestBuilder`s `from` field as a starting point.
-    ///
-    /// Sends a request to `/_matrix/client/r0/rooms/{room_id}/messages` and
-    /// returns a `get_message_events::IncomingResponse` that contains chunks
-    /// of `RoomEvents`.
+    /// Get the event types that were received during this session but
+    /// couldn't be handled, with the number of times each one was received.
     ///
-    /// # Arguments
+    /// Returns `None` if tracking was not enabled using
+    /// `ClientConfig::track_unhandled_events()`.
+    pub async fn unhandled_event_types(&self) -> Option<HashMap<String, u64>> {
+        self.base_client.unhandled_event_types().await
+    }
+
+    /// Load the rooms that are still only in the state store because lazy
+    /// room loading is enabled.
     ///
-    /// * `request` - The easiest way to create a `Request` is using the
-    /// `MessagesRequestBuilder`.
+    /// This is meant to run in the background while the client syncs, rooms
+    /// that are touched by a sync response are loaded before the response is
+    /// applied to them.
     ///
     /// # Examples
+    ///
     /// ```no_run
-    /// # use std::convert::TryFrom;
-    /// use matrix_sdk::{Client, MessagesRequestBuilder};
-    /// # use matrix_sdk::identifiers::RoomId;
-    /// # use matrix_sdk::api::r0::filter::RoomEventFilter;
-    /// # use matrix_sdk::api::r0::message::get_message_events::Direction;
+    /// use matrix_sdk::{Client, ClientConfig, JsonStore, SyncSettings};
     /// # use url::Url;
-    /// # use matrix_sdk::js_int::UInt;
-    ///
     /// # let homeserver = Url::parse("http://example.com").unwrap();
-    /// let mut builder = MessagesRequestBuilder::new();
-    /// builder.room_id(RoomId::try_from("!roomid:example.com").unwrap())
-    ///     .from("t47429-4392820_219380_26003_2265".to_string())
-    ///     .to("t4357353_219380_26003_2265".to_string())
-    ///     .direction(Direction::Backward)
-    ///     .limit(UInt::new(10).unwrap());
+    /// # let mut rt = tokio::runtime::Runtime::new().unwrap();
+    /// # rt.block_on(async {
+    /// let store = JsonStore::open("path/to/store").unwrap();
+    /// let config = ClientConfig::new()
+    ///     .state_store(Box::new(store))
+    ///     .lazy_room_loading();
+    /// let client = Client::new_with_config(homeserver, None, config).unwrap();
+    /// client.login("name", "password", None, None).await.unwrap();
     ///
-    /// let mut cli = Client::new(homeserver, None).unwrap();
-    /// # use futures::executor::block_on;
-    /// # block_on(async {
-    /// assert!(cli.room_messages(builder).await.is_ok());
+    /// client.sync(SyncSettings::new()).await.unwrap();
+    ///
+    /// let background_client = client.clone();
+    /// tokio::spawn(async move { background_client.load_stored_rooms().await });
     /// # });
     /// ```
-    pub async fn room_messages<R: Into<get_message_events::Request>>(
-        &self,
-        request: R,
-    ) -> Result<get_message_events::Response> {
-        let req = request.into();
-        self.send(req).await
+    pub async fn load_stored_rooms(&self) -> Result<()> {
+        Ok(self.base_client.load_stored_rooms().await?)
+    }
+
+    /// This allows `Client` to manually store `Room` state with the provided
+    /// `StateStore`.
+    ///
+    /// Returns Ok when a successful `Room` store occurs.
+    pub async fn store_room_state(&self, room_id: &RoomId) -> Result<()> {
+        self.base_client
+            .store_room_state(room_id)
+            .await
+            .map_err(Into::into)
     }
 
-    /// Send a request to notify the room of a user typing.
+    /// Remove all the messages of a room that were sent before the given point
+    /// in time.
     ///
-    /// Returns a `create_typing_event::Response`, an empty response.
+    /// Returns true if any message was removed.
     ///
     /// # Arguments
     ///
-    /// * `room_id` - The `RoomId` the user is typing in.
+    /// * `room_id` - The unique id of the room that should be pruned.
     ///
-    /// * `user_id` - The `UserId` of the user that is typing.
+    /// * `before` - Messages older than this will be removed.
+    #[cfg(feature = "messages")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "messages")))]
+    pub async fn prune_room_history(&self, room_id: &RoomId, before: SystemTime) -> Result<bool> {
+        Ok(self.base_client.prune_room_history(room_id, before).await?)
+    }
+
+    /// Login to the server.
     ///
-    /// * `typing` - Whether the user is typing, if false `timeout` is not needed.
+    /// # Arguments
     ///
-    /// * `timeout` - Length of time in milliseconds to mark user is typing.
-    pub async fn typing_notice(
+    /// * `user` - The user that should be logged in to the homeserver.
+    ///
+    /// * `password` - The password of the user.
+    ///
+    /// * `device_id` - A unique id that will be associated with this session. If
+    ///     not given the homeserver will create one. Can be an existing
+    ///     device_id from a previous login call. Note that this should be done
+    ///     only if the client also holds the encryption keys for this device.
+    #[instrument(skip(password))]
+    pub async fn login<S: Into<String> + std::fmt::Debug>(
         &self,
-        room_id: &RoomId,
-        user_id: &UserId,
-        typing: bool,
-        timeout: Option<Duration>,
-    ) -> Result<create_typing_event::Response> {
-        let request = create_typing_event::Request {
-            room_id: room_id.clone(),
-            user_id: user_id.clone(),
-            timeout,
-            typing,
+        user: S,
+        password: S,
+        device_id: Option<S>,
+        initial_device_display_name: Option<S>,
+    ) -> Result<login::Response> {
+        info!("Logging in to {} as {:?}", self.homeserver, user);
+
+        let request = login::Request {
+            user: login::UserInfo::MatrixId(user.into()),
+            login_info: login::LoginInfo::Password {
+                password: password.into(),
+            },
+            device_id: device_id.map(|d| d.into()),
+            initial_device_display_name: initial_device_display_name.map(|d| d.into()),
         };
-        self.send(request).await
+
+        let response = self.send(request).await?;
+        self.base_client.receive_login_response(&response).await?;
+
+        Ok(response)
     }
 
-    /// Send a request to notify the room of a user typing.
+    /// Register a new account on the homeserver.
+    ///
+    /// Registration requires user-interactive authentication on most
+    /// homeservers. If a `UiaaHandler` is set the stages are completed using
+    /// it, otherwise if no `auth_data` is given the server will respond with
+    /// the authentication stages that need to be completed, see
+    /// `Error::uiaa_response()`.
     ///
-    /// Returns a `create_receipt::Response`, an empty response.
+    /// The client doesn't get logged in as the new user, use `login()` for
+    /// that.
     ///
     /// # Arguments
     ///
-    /// * `room_id` - The `RoomId` the user is typing in.
+    /// * `username` - The localpart of the user id of the new account. If not
+    ///     given the homeserver will pick one.
     ///
-    /// * `event_id` - The `UserId` of the user that is typing.
-    pub async fn read_receipt(
+    /// * `password` - The password of the new account.
+    ///
+    /// * `device_id` - A unique id that will be associated with the device
+    ///     of the registration. If not given the homeserver will create one.
+    ///
+    /// * `initial_device_display_name` - The display name of that device.
+    ///
+    /// * `auth_data` - The authentication data for the current stage of the
+    /// user-interactive authentication.
+    #[instrument(skip(password, auth_data))]
+    pub async fn register<S: Into<String> + std::fmt::Debug>(
         &self,
-        room_id: &RoomId,
-        event_id: &EventId,
-    ) -> Result<create_receipt::Response> {
-        let request = create_receipt::Request {
-            room_id: room_id.clone(),
-            event_id: event_id.clone(),
-            receipt_type: create_receipt::ReceiptType::Read,
-        };
-        self.send(request).await
+        username: Option<S>,
+        password: S,
+        device_id: Option<S>,
+        initial_device_display_name: Option<S>,
+        auth_data: Option<AuthData>,
+    ) -> Result<register::Response> {
+        info!("Registering {:?} on {}", username, self.homeserver);
+
+        let username = username.map(|u| u.into());
+        let password = password.into();
+        let device_id = device_id.map(|d| d.into());
+        let initial_device_display_name = initial_device_display_name.map(|d| d.into());
+
+        self.send_uiaa_with_handler(
+            |auth| register::Request {
+                password: Some(password.clone()),
+                username: username.clone(),
+                device_id: device_id.clone(),
+                initial_device_display_name: initial_device_display_name.clone(),
+                auth,
+                kind: None,
+                inhibit_login: false,
+            },
+            auth_data,
+        )
+        .await
     }
 
-    /// Synchronize the client's state with the latest state on the server.
+    /// Join a room by `RoomId`.
     ///
-    /// If a `StateStore` is provided and this is the initial sync state will
-    /// be loaded from the state store.
+    /// Returns a `join_room_by_id::Response` consisting of the
+    /// joined rooms `RoomId`. The room is added to the joined rooms of the
+    /// client right away.
+    ///
+    /// If the room is known to have a restricted join rule, the servers of
+    /// the rooms that allow joining are sent along as `via` servers so the
+    /// homeserver can check our membership in them.
     ///
     /// # Arguments
     ///
-    /// * `sync_settings` - Settings for the sync call.
-    #[instrument]
-    #[allow(clippy::useless_let_if_seq)]
-    pub async fn sync(&self, mut sync_settings: SyncSettings) -> Result<sync_events::Response> {
-        {
-            // if the client has been synced from the state store don't sync again
-            if !self.base_client.is_state_store_synced() {
-                // this will bail out returning false if the store has not been set up
-                if let Ok(synced) = self.sync_with_state_store().await {
-                    if synced {
-                        // once synced, update the sync token to the last known state from `StateStore`.
-                        sync_settings.token = self.sync_token().await;
-                    }
+    /// * `room_id` - The `RoomId` of the room to be joined.
+    pub async fn join_room_by_id(&self, room_id: &RoomId) -> Result<join_room_by_id::Response> {
+        let via = self.restricted_join_servers(room_id).await;
+
+        if via.is_empty() {
+            let request = join_room_by_id::Request {
+                room_id: room_id.clone(),
+                third_party_signed: None,
+            };
+            let response = self.send(request).await?;
+            self.base_client
+                .receive_joined_room(&response.room_id)
+                .await?;
+
+            Ok(response)
+        } else {
+            let alias = RoomIdOrAliasId::from(room_id.clone());
+            let response = self.join_room_by_id_or_alias(&alias, &via).await?;
+
+            Ok(join_room_by_id::Response {
+                room_id: response.room_id,
+            })
+        }
+    }
+
+    /// The servers that should be used to join the given room if it has a
+    /// restricted join rule, empty otherwise.
+    async fn restricted_join_servers(&self, room_id: &RoomId) -> Vec<String> {
+        let room = if let Some(room) = self.get_invited_room(room_id).await {
+            room
+        } else if let Some(room) = self.get_left_room(room_id).await {
+            room
+        } else {
+            return Vec::new();
+        };
+
+        let room = room.read().await;
+
+        let allowed_rooms = match room.join_rule() {
+            Some(rule) => rule.allowed_rooms(),
+            None => return Vec::new(),
+        };
+
+        let mut servers: Vec<String> = Vec::new();
+
+        for id in allowed_rooms.into_iter().chain(std::iter::once(room_id)) {
+            let id = id.to_string();
+
+            if let Some(server) = id.splitn(2, ':').nth(1) {
+                if !servers.iter().any(|s| s == server) {
+                    servers.push(server.to_owned());
                 }
             }
         }
 
-        let request = sync_events::Request {
-            filter: None,
-            since: sync_settings.token,
-            full_state: sync_settings.full_state,
-            set_presence: sync_events::SetPresence::Online,
-            timeout: sync_settings.timeout,
-        };
+        // Only the room's own server is known, a plain join works just as well.
+        if servers.len() <= 1 {
+            servers.clear();
+        }
 
-        let mut response = self.send(request).await?;
+        servers
+    }
 
+    /// Join a room by `RoomId`.
+    ///
+    /// Returns a `join_room_by_id_or_alias::Response` consisting of the
+    /// joined rooms `RoomId`. The room is added to the joined rooms of the
+    /// client right away.
+    ///
+    /// # Arguments
+    ///
+    /// * `alias` - The `RoomId` or `RoomAliasId` of the room to be joined.
+    /// An alias looks like this `#name:example.com`
+    pub async fn join_room_by_id_or_alias(
+        &self,
+        alias: &RoomIdOrAliasId,
+        server_names: &[String],
+    ) -> Result<join_room_by_id_or_alias::Response> {
+        let request = join_room_by_id_or_alias::Request {
+            room_id_or_alias: alias.clone(),
+            server_name: server_names.to_owned(),
+            third_party_signed: None,
+        };
+        let response = self.send(request).await?;
         self.base_client
-            .receive_sync_response(&mut response)
+            .receive_joined_room(&response.room_id)
             .await?;
 
         Ok(response)
     }
 
-    /// Repeatedly call sync to synchronize the client state with the server.
-    ///
-    /// # Arguments
-    ///
-    /// * `sync_settings` - Settings for the sync call. Note that those settings
-    ///     will be only used for the first sync call.
-    ///
-    /// * `callback` - A callback that will be called every time a successful
-    ///     response has been fetched from the server.
-    ///
-    /// # Examples
+    /// Resolve a room alias to the id of the room it points to.
     ///
-    /// The following example demonstrates how to sync forever while sending all
-    /// the interesting events through a mpsc channel to another thread e.g. a
-    /// UI thread.
+    /// Resolved aliases are cached, so the homeserver is only asked once per
+    /// alias. The canonical aliases of the joined rooms are resolved without
+    /// asking the homeserver at all.
     ///
-    /// ```compile_fail,E0658
-    /// # use matrix_sdk::events::{
-    /// #     collections::all::RoomEvent,
-    /// #     room::message::{MessageEvent, MessageEventContent, TextMessageEventContent},
-    /// #     EventResult,
-    /// # };
-    /// # use matrix_sdk::Room;
-    /// # use std::sync::{Arc, RwLock};
-    /// # use matrix_sdk::{Client, SyncSettings};
-    /// # use url::Url;
-    /// # use futures::executor::block_on;
-    /// # block_on(async {
-    /// # let homeserver = Url::parse("http://localhost:8080").unwrap();
-    /// # let mut client = Client::new(homeserver, None).unwrap();
+    /// # Arguments
     ///
-    /// use async_std::sync::channel;
+    /// * `alias` - The alias of the room, e.g. `#room:example.com`.
+    pub async fn resolve_room_alias(&self, alias: &RoomAliasId) -> Result<RoomId> {
+        if let Some(room_id) = self.base_client.cached_room_id(alias).await {
+            return Ok(room_id);
+        }
+
+        let request = get_alias::Request {
+            room_alias: alias.clone(),
+        };
+        let response = self.send(request).await?;
+        self.base_client
+            .cache_room_alias(alias, &response.room_id)
+            .await?;
+
+        Ok(response.room_id)
+    }
+
+    /// Suggest rooms for a partially typed room alias or room name.
     ///
-    /// let (tx, rx) = channel(100);
+    /// The aliases and names of the joined rooms are matched against the
+    /// prefix, optionally the public room directory of the homeserver is
+    /// searched as well. The suggestions are ordered from the best to the
+    /// worst match, joined rooms come before directory rooms that match
+    /// equally well.
     ///
-    /// let sync_channel = &tx;
-    /// let sync_settings = SyncSettings::new()
-    ///     .timeout(30_000)
-    ///     .unwrap();
+    /// # Arguments
     ///
-    /// client
-    ///     .sync_forever(sync_settings, async move |response| {
-    ///         let channel = sync_channel;
+    /// * `prefix` - The text that should be completed, e.g. `#mat` or `rust`.
     ///
-    ///         for (room_id, room) in response.rooms.join {
-    ///             for event in room.timeline.events {
-    ///                 if let EventResult::Ok(e) = event {
-    ///                     channel.send(e).await;
-    ///                 }
-    ///             }
-    ///         }
-    ///     })
-    ///     .await;
-    /// })
-    /// ```
-    #[instrument(skip(callback))]
-    pub async fn sync_forever<C>(
+    /// * `search_directory` - Should the room directory be searched as well.
+    pub async fn complete_room(
         &self,
-        sync_settings: SyncSettings,
-        callback: impl Fn(sync_events::Response) -> C + Send,
-    ) where
-        C: Future<Output = ()>,
-    {
-        let mut sync_settings = sync_settings;
-        let mut last_sync_time: Option<Instant> = None;
-
-        loop {
-            let response = self.sync(sync_settings.clone()).await;
-
-            let response = if let Ok(r) = response {
-                r
-            } else {
-                #[cfg(not(target_arch = "wasm32"))]
-                sleep::new(Duration::from_secs(1)).await;
-
-                continue;
-            };
+        prefix: &str,
+        search_directory: bool,
+    ) -> Result<Vec<RoomSuggestion>> {
+        let mut suggestions = Vec::new();
+
+        for room in self.joined_rooms().read().await.values() {
+            let room = room.read().await;
+
+            let mut aliases: Vec<RoomAliasId> = room
+                .room_name
+                .canonical_alias()
+                .cloned()
+                .into_iter()
+                .collect();
+            aliases.extend(room.room_name.aliases().iter().cloned());
+
+            if let Some(suggestion) =
+                RoomSuggestion::new(&room.room_id, &aliases, room.display_name(), true, prefix)
+            {
+                suggestions.push(suggestion);
+            }
+        }
 
-            // TODO send out to-device messages here
+        let term = prefix.trim().trim_start_matches('#');
 
-            #[cfg(feature = "encryption")]
-            {
-                if self.base_client.should_upload_keys().await {
-                    let response = self.keys_upload().await;
+        if search_directory && !term.is_empty() {
+            let public_rooms = self.search_public_rooms(term).await?;
+            suggestions.extend(
+                public_rooms
+                    .chunk
+                    .into_iter()
+                    .filter_map(|room| room.suggestion(prefix)),
+            );
+        }
 
-                    if let Err(e) = response {
-                        warn!("Error while uploading E2EE keys {:?}", e);
-                    }
-                }
+        Ok(rank_suggestions(suggestions))
+    }
 
-                if self.base_client.should_query_keys().await {
-                    let response = self.keys_query().await;
+    /// Search the public room directory of the homeserver.
+    async fn search_public_rooms(&self, term: &str) -> Result<PublicRooms> {
+        let mut request_url = self.homeserver.clone();
+        request_url.set_path("/_matrix/client/r0/publicRooms");
 
-                    if let Err(e) = response {
-                        warn!("Error while querying device keys {:?}", e);
-                    }
-                }
-            }
+        let body = serde_json::json!({
+            "limit": DIRECTORY_SEARCH_LIMIT,
+            "filter": { "generic_search_term": term },
+        });
 
-            callback(response).await;
+        self.send_raw(HttpMethod::POST, request_url, Some(&body))
+            .await
+    }
 
-            let now = Instant::now();
+    /// Upgrade a room to a new room version.
+    ///
+    /// The homeserver creates a new room with the given version, copies the
+    /// important state over and closes the old room with a `m.room.tombstone`
+    /// event that points to the new room, see `Room::successor()`.
+    ///
+    /// Returns the id of the new room.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The id of the room that should be upgraded.
+    ///
+    /// * `new_version` - The room version of the new room, e.g. `"6"`.
+    pub async fn upgrade_room(&self, room_id: &RoomId, new_version: &str) -> Result<RoomId> {
+        let mut request_url = self.homeserver.clone();
+        request_url.set_path(&format!("/_matrix/client/r0/rooms/{}/upgrade", room_id));
 
-            // If the last sync happened less than a second ago, sleep for a
-            // while to not hammer out requests if the server doesn't respect
-            // the sync timeout.
-            #[cfg(not(target_arch = "wasm32"))]
-            {
-                if let Some(t) = last_sync_time {
-                    if now - t <= Duration::from_secs(1) {
-                        sleep::new(Duration::from_secs(1)).await;
-                    }
-                }
-            }
+        let body = serde_json::json!({ "new_version": new_version });
 
-            last_sync_time = Some(now);
+        let response: JsonValue = self
+            .send_raw(HttpMethod::POST, request_url, Some(&body))
+            .await?;
 
-            sync_settings = SyncSettings::new().timeout(DEFAULT_SYNC_TIMEOUT).token(
-                self.sync_token()
-                    .await
-                    .expect("No sync token found after initial sync"),
-            );
-        }
+        Ok(serde_json::from_value(
+            response["replacement_room"].clone(),
+        )?)
     }
 
-    async fn send<Request: Endpoint<ResponseError = crate::api::Error> + std::fmt::Debug>(
-        &self,
-        request: Request,
-    ) -> Result<Request::Response> {
-        let request: http::Request<Vec<u8>> = request.try_into()?;
-        let url = request.uri();
-        let path_and_query = url.path_and_query().unwrap();
-        let mut url = self.homeserver.clone();
+    /// Set the canonical alias of a room and add alternative aliases to it.
+    ///
+    /// The current `m.room.canonical_alias` event is fetched from the
+    /// homeserver first, so the alternative aliases that are already set, and
+    /// any other field of the event, are kept. The canonical alias is never
+    /// listed as an alternative alias as well.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The id of the room.
+    ///
+    /// * `alias` - The new canonical alias of the room.
+    ///
+    /// * `alt_aliases` - Alternative aliases that should be added to the
+    /// room.
+    pub async fn set_canonical_alias(
+        &self,
+        room_id: &RoomId,
+        alias: &RoomAliasId,
+        alt_aliases: &[RoomAliasId],
+    ) -> Result<()> {
+        let current = self
+            .get_state_event_content(room_id, "m.room.canonical_alias")
+            .await?;
+        let content = canonical_alias_content(current, alias, alt_aliases);
 
-        url.set_path(path_and_query.path());
-        url.set_query(path_and_query.query());
+        let request = create_state_event_for_key::Request {
+            room_id: room_id.clone(),
+            event_type: EventType::RoomCanonicalAlias,
+            state_key: "".to_owned(),
+            data: serde_json::value::to_raw_value(&content)?,
+        };
 
-        trace!("Doing request {:?}", url);
+        let _response: create_state_event_for_key::Response = self.send(request).await?;
 
-        let request_builder = match Request::METADATA.method {
-            HttpMethod::GET => self.http_client.get(url),
-            HttpMethod::POST => {
-                let body = request.body().clone();
-                self.http_client
-                    .post(url)
-                    .body(body)
-                    .header(reqwest::header::CONTENT_TYPE, "application/json")
-            }
-            HttpMethod::PUT => {
-                let body = request.body().clone();
-                self.http_client
-                    .put(url)
-                    .body(body)
-                    .header(reqwest::header::CONTENT_TYPE, "application/json")
-            }
-            HttpMethod::DELETE => unimplemented!(),
-            _ => panic!("Unsuported method"),
-        };
+        Ok(())
+    }
 
-        let request_builder = if Request::METADATA.requires_authentication {
-            let session = self.base_client.session().read().await;
+    /// Change the power levels of a room.
+    ///
+    /// The current `m.room.power_levels` state event is fetched from the
+    /// homeserver first and the changes are applied on top of it, so the
+    /// levels that aren't changed are kept.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The id of the room.
+    ///
+    /// * `changes` - The changes to the power levels.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// # use std::convert::TryFrom;
+    /// # use matrix_sdk::{Client, PowerLevelChanges};
+    /// # use matrix_sdk::identifiers::{RoomId, UserId};
+    /// # use matrix_sdk::js_int::Int;
+    /// # use futures::executor::block_on;
+    /// # use url::Url;
+    /// # let homeserver = Url::parse("http://localhost:8080").unwrap();
+    /// # let client = Client::new(homeserver, None).unwrap();
+    /// # let room_id = RoomId::try_from("!test:localhost").unwrap();
+    /// # block_on(async {
+    /// let moderator = UserId::try_from("@moderator:localhost").unwrap();
+    /// let changes = PowerLevelChanges::new().user(moderator, Int::from(50));
+    ///
+    /// client.update_power_levels(&room_id, &changes).await.unwrap();
+    /// # });
+    /// ```
+    pub async fn update_power_levels(
+        &self,
+        room_id: &RoomId,
+        changes: &PowerLevelChanges,
+    ) -> Result<()> {
+        if changes.is_empty() {
+            return Ok(());
+        }
 
-            if let Some(session) = session.as_ref() {
-                let header_value = format!("Bearer {}", &session.access_token);
-                request_builder.header(AUTHORIZATION, header_value)
-            } else {
-                return Err(Error::AuthenticationRequired);
-            }
-        } else {
-            request_builder
+        let current = self
+            .get_state_event_content(room_id, "m.room.power_levels")
+            .await?;
+
+        let request = create_state_event_for_key::Request {
+            room_id: room_id.clone(),
+            event_type: EventType::RoomPowerLevels,
+            state_key: "".to_owned(),
+            data: serde_json::value::to_raw_value(&changes.apply(current))?,
         };
-        let mut response = request_builder.send().await?;
 
-        trace!("Got response: {:?}", response);
+        let _response: create_state_event_for_key::Response = self.send(request).await?;
 
-        let status = response.status();
-        let mut http_builder = HttpResponse::builder().status(status);
-        let headers = http_builder.headers_mut().unwrap();
+        Ok(())
+    }
 
-        for (k, v) in response.headers_mut().drain() {
-            if let Some(key) = k {
-                headers.insert(key, v);
-            }
-        }
-        let body = response.bytes().await?.as_ref().to_owned();
-        let http_response = http_builder.body(body).unwrap();
+    /// Fetch the content of a state event with an empty state key from the
+    /// homeserver.
+    ///
+    /// Returns an empty JSON object if the room has no such state event.
+    async fn get_state_event_content(
+        &self,
+        room_id: &RoomId,
+        event_type: &str,
+    ) -> Result<JsonValue> {
+        let mut request_url = self.homeserver.clone();
+        request_url.set_path(&format!(
+            "/_matrix/client/r0/rooms/{}/state/{}",
+            room_id, event_type
+        ));
+
+        let response = self
+            .send_raw_request(HttpMethod::GET, request_url, None)
+            .await?;
 
-        Ok(<Request::Response>::try_from(http_response)?)
+        if response.status() == http::StatusCode::NOT_FOUND {
+            Ok(JsonValue::Object(Default::default()))
+        } else {
+            raw_response(response)
+        }
     }
 
-    /// Send a room message to the homeserver.
-    ///
-    /// Returns the parsed response from the server.
+    /// Upload a filter to the homeserver.
     ///
-    /// If the encryption feature is enabled this method will transparently
-    /// encrypt the room message if the given room is encrypted.
+    /// Returns the id the homeserver assigned to the filter, it can be used
+    /// in place of the filter definition in later sync calls.
     ///
     /// # Arguments
     ///
-    /// * `room_id` -  The id of the room that should receive the message.
+    /// * `definition` - The filter that should be uploaded.
+    pub async fn upload_filter(&self, definition: FilterDefinition) -> Result<String> {
+        let user_id = match self.base_client.session().read().await.as_ref() {
+            Some(session) => session.user_id.clone(),
+            None => return Err(Error::AuthenticationRequired),
+        };
+
+        let request = create_filter::Request {
+            filter: definition,
+            user_id,
+        };
+
+        Ok(self.send(request).await?.filter_id)
+    }
+
+    /// Get the id of the filter that was stored under the given name, the
+    /// filter is uploaded if it wasn't uploaded before.
     ///
-    /// * `content` - The content of the message event.
+    /// The filter id is persisted in the state store, so the filter is
+    /// reused across restarts instead of being uploaded again. Use a
+    /// different name if the definition of the filter changes.
     ///
-    /// * `txn_id` - A unique `Uuid` that can be attached to a `MessageEvent` held
-    /// in it's unsigned field as `transaction_id`. If not given one is created for the
-    /// message.
+    /// # Arguments
     ///
-    /// # Example
+    /// * `filter_name` - The name the filter id is stored under.
+    ///
+    /// * `definition` - The filter that should be uploaded if no filter id
+    /// is stored under the name.
+    ///
+    /// # Examples
     /// ```no_run
-    /// # use matrix_sdk::Room;
-    /// # use std::sync::{Arc, RwLock};
     /// # use matrix_sdk::{Client, SyncSettings};
+    /// # use matrix_sdk::api::r0::filter::FilterDefinition;
+    /// # use matrix_sdk::api::r0::sync::sync_events::Filter;
     /// # use url::Url;
+    /// # let homeserver = Url::parse("http://example.com").unwrap();
+    /// # let client = Client::new(homeserver, None).unwrap();
+    /// # let definition: FilterDefinition = serde_json::from_str("{}").unwrap();
     /// # use futures::executor::block_on;
-    /// # use ruma_identifiers::RoomId;
-    /// # use std::convert::TryFrom;
-    /// use matrix_sdk::events::room::message::{MessageEventContent, TextMessageEventContent};
     /// # block_on(async {
-    /// # let homeserver = Url::parse("http://localhost:8080").unwrap();
-    /// # let mut client = Client::new(homeserver, None).unwrap();
-    /// # let room_id = RoomId::try_from("!test:localhost").unwrap();
-    /// use matrix_sdk_common::uuid::Uuid;
+    /// let filter_id = client.get_or_upload_filter("sync", definition).await.unwrap();
+    /// let sync_settings = SyncSettings::new().filter(Filter::FilterId(filter_id));
     ///
-    /// let content = MessageEventContent::Text(TextMessageEventContent {
-    ///     body: "Hello world".to_owned(),
-    ///     format: None,
-    ///     formatted_body: None,
-    ///     relates_to: None,
-    /// });
-    /// let txn_id = Uuid::new_v4();
-    /// client.room_send(&room_id, content, Some(txn_id)).await.unwrap();
-    /// })
+    /// client.sync(sync_settings).await.unwrap();
+    /// # });
     /// ```
-    pub async fn room_send(
+    pub async fn get_or_upload_filter(
         &self,
-        room_id: &RoomId,
-        content: MessageEventContent,
-        txn_id: Option<Uuid>,
-    ) -> Result<create_message_event::Response> {
-        #[allow(unused_mut)]
-        let mut event_type = EventType::RoomMessage;
-        #[allow(unused_mut)]
-        let mut raw_content = serde_json::value::to_raw_value(&content)?;
-
-        #[cfg(feature = "encryption")]
-        {
-            let encrypted = {
-                let room = self.base_client.get_joined_room(room_id).await;
+        filter_name: &str,
+        definition: FilterDefinition,
+    ) -> Result<String> {
+        if let Some(filter_id) = self.base_client.get_filter(filter_name).await? {
+            return Ok(filter_id);
+        }
 
-                match room {
-                    Some(r) => r.read().await.is_encrypted(),
-                    None => false,
-                }
-            };
+        let filter_id = self.upload_filter(definition).await?;
+        self.base_client
+            .receive_filter_upload(filter_name, &filter_id)
+            .await?;
 
-            if encrypted {
-                let missing_sessions = {
-                    let room = self.base_client.get_joined_room(room_id).await;
-                    let room = room.as_ref().unwrap().read().await;
-                    let users = room.members.keys();
-                    self.base_client.get_missing_sessions(users).await?
-                };
+        Ok(filter_id)
+    }
 
-                if !missing_sessions.is_empty() {
-                    self.claim_one_time_keys(missing_sessions).await?;
-                }
+    /// Fetch the full member list of a room from the `/members` endpoint and
+    /// merge it into the members of the room.
+    ///
+    /// Syncs lazily load the members of rooms by default, so the room only
+    /// knows the members that recently sent events until this is called.
+    ///
+    /// Returns the member events the server returned.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The id of the room.
+    pub async fn get_room_members(&self, room_id: &RoomId) -> Result<Vec<MemberEvent>> {
+        let mut request_url = self.homeserver.clone();
+        request_url.set_path(&format!("/_matrix/client/r0/rooms/{}/members", room_id));
+
+        let response: JsonValue = self.send_raw(HttpMethod::GET, request_url, None).await?;
+
+        // Skip the member events that don't deserialize instead of failing
+        // to load the whole member list.
+        let events: Vec<MemberEvent> = response["chunk"]
+            .as_array()
+            .map(|chunk| {
+                chunk
+                    .iter()
+                    .filter_map(|e| serde_json::from_value(e.clone()).ok())
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        self.base_client.receive_members(room_id, &events).await?;
+
+        Ok(events)
+    }
 
-                if self.base_client.should_share_group_session(room_id).await {
-                    // TODO we need to make sure that only one such request is
-                    // in flight per room at a time.
-                    self.share_group_session(room_id).await?;
-                }
+    /// Make sure the member list of a room is complete, fetching it from the
+    /// `/members` endpoint if it wasn't fetched before.
+    ///
+    /// Call this before relying on the full member list, e.g. for power level
+    /// checks or mention completion, since syncs lazily load the members of
+    /// rooms by default. The member list is only fetched once per room.
+    ///
+    /// Returns true if the member list was fetched, false if it was already
+    /// complete or if we aren't joined to the room.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The id of the room.
+    pub async fn ensure_members(&self, room_id: &RoomId) -> Result<bool> {
+        let synced = match self.get_joined_room(room_id).await {
+            Some(room) => room.read().await.are_members_synced(),
+            None => return Ok(false),
+        };
 
-                raw_content = serde_json::value::to_raw_value(
-                    &self.base_client.encrypt(room_id, content).await?,
-                )?;
-                event_type = EventType::RoomEncrypted;
-            }
+        if synced {
+            return Ok(false);
         }
 
-        let request = create_message_event::Request {
-            room_id: room_id.clone(),
-            event_type,
-            txn_id: txn_id.unwrap_or_else(Uuid::new_v4).to_string(),
-            data: raw_content,
-        };
+        self.get_room_members(room_id).await?;
 
-        let response = self.send(request).await?;
-        Ok(response)
+        Ok(true)
     }
 
-    /// Claim one-time keys creating new Olm sessions.
+    /// Get the content of a global account data event of our own user.
     ///
-    /// # Arguments
+    /// The account data that came down the sync stream is used if there is
+    /// any, otherwise it is fetched from the homeserver.
     ///
-    /// * `users` - The list of user/device pairs that we should claim keys for.
+    /// Returns `None` if the user has no account data of the given type.
     ///
-    /// # Panics
+    /// # Arguments
     ///
-    /// Panics if the client isn't logged in, or if no encryption keys need to
-    /// be uploaded.
-    #[cfg(feature = "encryption")]
-    #[cfg_attr(docsrs, doc(cfg(feature = "encryption")))]
-    #[instrument]
-    async fn claim_one_time_keys(
+    /// * `event_type` - The type of the account data event, e.g.
+    /// `io.element.recent_emoji`.
+    pub async fn get_account_data<T: DeserializeOwned>(
         &self,
-        one_time_keys: BTreeMap<UserId, BTreeMap<DeviceId, KeyAlgorithm>>,
-    ) -> Result<claim_keys::Response> {
-        let request = claim_keys::Request {
-            timeout: None,
-            one_time_keys,
+        event_type: &str,
+    ) -> Result<Option<T>> {
+        let content = match self.base_client.account_data(event_type).await {
+            Some(content) => Some(content),
+            None => self.fetch_account_data(None, event_type).await?,
         };
 
-        let response = self.send(request).await?;
+        Ok(content.map(serde_json::from_value).transpose()?)
+    }
+
+    /// Get the content of a room account data event of our own user.
+    ///
+    /// The account data that came down the sync stream is used if there is
+    /// any, otherwise it is fetched from the homeserver.
+    ///
+    /// Returns `None` if the user has no account data of the given type in
+    /// the room.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The id of the room the account data belongs to.
+    ///
+    /// * `event_type` - The type of the account data event.
+    pub async fn get_room_account_data<T: DeserializeOwned>(
+        &self,
+        room_id: &RoomId,
+        event_type: &str,
+    ) -> Result<Option<T>> {
+        if let Some(room) = self.get_joined_room(room_id).await {
+            if let Some(content) = room.read().await.account_data(event_type)? {
+                return Ok(Some(content));
+            }
+        }
+
+        let content = self.fetch_account_data(Some(room_id), event_type).await?;
+
+        Ok(content.map(serde_json::from_value).transpose()?)
+    }
+
+    /// Replace the content of a global account data event of our own user.
+    ///
+    /// Account data is private to the user and is synced to all of their
+    /// devices, e.g. to store the settings of a client.
+    ///
+    /// # Arguments
+    ///
+    /// * `event_type` - The type of the account data event.
+    ///
+    /// * `content` - The new content of the account data event.
+    pub async fn set_account_data<C: Serialize>(
+        &self,
+        event_type: &str,
+        content: &C,
+    ) -> Result<()> {
+        let content = serde_json::to_value(content)?;
+        self.put_account_data(None, event_type, &content).await?;
         self.base_client
-            .receive_keys_claim_response(&response)
+            .receive_global_account_data(event_type, content)
             .await?;
-        Ok(response)
+
+        Ok(())
     }
 
-    /// Share a group session for a room.
+    /// Get the rooms our user viewed recently, the most recently viewed room
+    /// first.
+    ///
+    /// The rooms are stored in the `im.vector.setting.breadcrumbs` account
+    /// data, so the list is shared with other clients, see
+    /// `track_room_view()`.
+    pub async fn breadcrumbs(&self) -> Result<Vec<RoomId>> {
+        let content = self.breadcrumbs_content().await?;
+
+        Ok(breadcrumb_rooms(&content))
+    }
+
+    /// Remember that our user viewed a room.
+    ///
+    /// The room is moved to the front of the recently viewed rooms, see
+    /// `breadcrumbs()`. The account data is only updated if the room wasn't
+    /// the most recently viewed room already.
     ///
     /// # Arguments
     ///
-    /// * `room_id` - The ID of the room for which we want to share a group
-    /// session.
+    /// * `room_id` - The id of the room that was viewed.
+    pub async fn track_room_view(&self, room_id: &RoomId) -> Result<()> {
+        // Keep the fields other clients might have added to the content.
+        let mut content = self.breadcrumbs_content().await?;
+        let mut rooms = breadcrumb_rooms(&content);
+
+        if rooms.first() == Some(room_id) {
+            return Ok(());
+        }
+
+        rooms.retain(|r| r != room_id);
+        rooms.insert(0, room_id.clone());
+        rooms.truncate(MAX_BREADCRUMBS);
+
+        content["recent_rooms"] = serde_json::to_value(&rooms)?;
+
+        self.set_account_data(BREADCRUMBS_EVENT_TYPE, &content)
+            .await
+    }
+
+    /// Get the content of the breadcrumbs account data, an empty object if
+    /// there is none yet.
+    async fn breadcrumbs_content(&self) -> Result<JsonValue> {
+        let content: Option<JsonValue> = self.get_account_data(BREADCRUMBS_EVENT_TYPE).await?;
+
+        Ok(content
+            .filter(JsonValue::is_object)
+            .unwrap_or_else(|| serde_json::json!({})))
+    }
+
+    /// Replace the content of a room account data event of our own user.
     ///
-    /// # Panics
+    /// # Arguments
     ///
-    /// Panics if the client isn't logged in.
-    #[cfg(feature = "encryption")]
-    #[cfg_attr(docsrs, doc(cfg(feature = "encryption")))]
-    #[instrument]
-    async fn share_group_session(&self, room_id: &RoomId) -> Result<()> {
-        let mut requests = self
-            .base_client
-            .share_group_session(room_id)
+    /// * `room_id` - The id of the room the account data belongs to.
+    ///
+    /// * `event_type` - The type of the account data event.
+    ///
+    /// * `content` - The new content of the account data event.
+    pub async fn set_room_account_data<C: Serialize>(
+        &self,
+        room_id: &RoomId,
+        event_type: &str,
+        content: &C,
+    ) -> Result<()> {
+        let content = serde_json::to_value(content)?;
+        self.put_account_data(Some(room_id), event_type, &content)
+            .await?;
+        self.base_client
+            .receive_room_account_data(room_id, event_type, content)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Build the URL of an account data event of our own user, the account
+    /// data of a room if a room id is given.
+    async fn account_data_url(&self, room_id: Option<&RoomId>, event_type: &str) -> Result<Url> {
+        let user_id = self.user_id().await.ok_or(Error::AuthenticationRequired)?;
+
+        let mut request_url = self.homeserver.clone();
+        request_url.set_path(&match room_id {
+            Some(room_id) => format!(
+                "/_matrix/client/r0/user/{}/rooms/{}/account_data/{}",
+                user_id, room_id, event_type
+            ),
+            None => format!(
+                "/_matrix/client/r0/user/{}/account_data/{}",
+                user_id, event_type
+            ),
+        });
+
+        Ok(request_url)
+    }
+
+    /// Fetch the content of an account data event of our own user from the
+    /// homeserver.
+    ///
+    /// Returns `None` if the user has no such account data.
+    async fn fetch_account_data(
+        &self,
+        room_id: Option<&RoomId>,
+        event_type: &str,
+    ) -> Result<Option<JsonValue>> {
+        let request_url = self.account_data_url(room_id, event_type).await?;
+
+        let response = self
+            .send_raw_request(HttpMethod::GET, request_url, None)
+            .await?;
+
+        if response.status() == http::StatusCode::NOT_FOUND {
+            Ok(None)
+        } else {
+            Ok(Some(raw_response(response)?))
+        }
+    }
+
+    /// Replace the content of an account data event of our own user on the
+    /// homeserver.
+    async fn put_account_data(
+        &self,
+        room_id: Option<&RoomId>,
+        event_type: &str,
+        content: &JsonValue,
+    ) -> Result<()> {
+        let request_url = self.account_data_url(room_id, event_type).await?;
+
+        let _response: JsonValue = self
+            .send_raw(HttpMethod::PUT, request_url, Some(content))
+            .await?;
+
+        Ok(())
+    }
+
+    /// Send a state event with an arbitrary content to a room, e.g. the state
+    /// of a widget or of a bridge.
+    ///
+    /// Returns the id of the state event.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The id of the room.
+    ///
+    /// * `event_type` - The type of the state event.
+    ///
+    /// * `state_key` - The state key of the event, usually an empty string.
+    ///
+    /// * `content` - The content of the state event.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// # use std::convert::TryFrom;
+    /// # use matrix_sdk::Client;
+    /// # use matrix_sdk::identifiers::RoomId;
+    /// # use futures::executor::block_on;
+    /// # use url::Url;
+    /// # let homeserver = Url::parse("http://localhost:8080").unwrap();
+    /// # let client = Client::new(homeserver, None).unwrap();
+    /// # let room_id = RoomId::try_from("!test:localhost").unwrap();
+    /// # block_on(async {
+    /// let content = serde_json::json!({ "protocol": "irc" });
+    ///
+    /// client
+    ///     .send_state_event(&room_id, "m.bridge", "irc", &content)
+    ///     .await
+    ///     .unwrap();
+    /// # });
+    /// ```
+    pub async fn send_state_event(
+        &self,
+        room_id: &RoomId,
+        event_type: &str,
+        state_key: &str,
+        content: &JsonValue,
+    ) -> Result<EventId> {
+        self.send_state_event_at(room_id, event_type, state_key, content, None)
+            .await
+    }
+
+    /// Send a state event to a room with the given origin timestamp.
+    ///
+    /// This uses the `ts` query parameter of the application service API so
+    /// bridges can preserve the original timestamp of events they relay,
+    /// homeservers ignore it for clients that aren't application services.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The id of the room the state event should be sent to.
+    ///
+    /// * `event_type` - The type of the state event.
+    ///
+    /// * `state_key` - The state key of the event.
+    ///
+    /// * `content` - The content of the state event.
+    ///
+    /// * `timestamp` - The origin timestamp the event should have.
+    pub async fn send_state_event_with_timestamp(
+        &self,
+        room_id: &RoomId,
+        event_type: &str,
+        state_key: &str,
+        content: &JsonValue,
+        timestamp: SystemTime,
+    ) -> Result<EventId> {
+        self.send_state_event_at(room_id, event_type, state_key, content, Some(timestamp))
+            .await
+    }
+
+    async fn send_state_event_at(
+        &self,
+        room_id: &RoomId,
+        event_type: &str,
+        state_key: &str,
+        content: &JsonValue,
+        timestamp: Option<SystemTime>,
+    ) -> Result<EventId> {
+        self.check_permission(
+            room_id,
+            &PowerAction::SendState(EventType::from(event_type)),
+        )
+        .await?;
+
+        let request = create_state_event_for_key::Request {
+            room_id: room_id.clone(),
+            event_type: EventType::from(event_type),
+            state_key: state_key.to_owned(),
+            data: serde_json::value::to_raw_value(content)?,
+        };
+
+        let response: create_state_event_for_key::Response =
+            self.send_with_timestamp(request, timestamp).await?;
+
+        Ok(response.event_id)
+    }
+
+    /// Get a page of the room hierarchy of a space.
+    ///
+    /// The hierarchy endpoint isn't supported by the typed requests yet, so
+    /// the request is sent by hand.
+    ///
+    /// # Arguments
+    ///
+    /// * `space_id` - The id of the space.
+    ///
+    /// * `from` - The `next_batch` token of the previous page, `None` for the
+    /// first page.
+    ///
+    /// * `max_depth` - How deep into nested spaces the hierarchy should go,
+    /// `None` lets the homeserver decide.
+    ///
+    /// * `suggested_only` - Only return the rooms that are suggested by the
+    /// admins of the space.
+    pub async fn get_space_hierarchy(
+        &self,
+        space_id: &RoomId,
+        from: Option<&str>,
+        max_depth: Option<u32>,
+        suggested_only: bool,
+    ) -> Result<SpaceHierarchy> {
+        let mut request_url = self.homeserver.clone();
+        request_url.set_path(&format!("/_matrix/client/v1/rooms/{}/hierarchy", space_id));
+
+        {
+            let mut query = request_url.query_pairs_mut();
+            if let Some(from) = from {
+                query.append_pair("from", from);
+            }
+            if let Some(max_depth) = max_depth {
+                query.append_pair("max_depth", &max_depth.to_string());
+            }
+            if suggested_only {
+                query.append_pair("suggested_only", "true");
+            }
+        }
+
+        self.send_raw(HttpMethod::GET, request_url, None).await
+    }
+
+    /// Join a space.
+    ///
+    /// If `ClientConfig::auto_join_suggested_rooms()` is enabled the
+    /// suggested rooms of the space are joined as well, see
+    /// `Client::join_suggested_rooms()`.
+    ///
+    /// Returns the ids of all the rooms that were joined, the space comes
+    /// first.
+    ///
+    /// # Arguments
+    ///
+    /// * `space_id` - The id of the space.
+    ///
+    /// * `progress` - Called with the number of handled suggested rooms and
+    /// the total number of suggested rooms after every room that was joined.
+    pub async fn join_space(
+        &self,
+        space_id: &RoomId,
+        progress: impl FnMut(usize, usize),
+    ) -> Result<Vec<RoomId>> {
+        let response = self.join_room_by_id(space_id).await?;
+        let mut joined = vec![response.room_id];
+
+        if self.auto_join_suggested_rooms {
+            joined.extend(self.join_suggested_rooms(space_id, progress).await?);
+        }
+
+        Ok(joined)
+    }
+
+    /// Join the rooms that the admins of a space suggest to join.
+    ///
+    /// Only the direct children of the space are considered, rooms that are
+    /// already joined are skipped. A room that can't be joined is logged and
+    /// skipped as well, so one broken room doesn't prevent joining the others.
+    ///
+    /// Returns the ids of the rooms that were joined.
+    ///
+    /// # Arguments
+    ///
+    /// * `space_id` - The id of the space.
+    ///
+    /// * `progress` - Called with the number of handled rooms and the total
+    /// number of suggested rooms after every room.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use std::convert::TryFrom;
+    /// # use futures::executor::block_on;
+    /// # use matrix_sdk::{Client, identifiers::RoomId};
+    /// # use url::Url;
+    /// # block_on(async {
+    /// # let homeserver = Url::parse("http://localhost:8080").unwrap();
+    /// # let client = Client::new(homeserver, None).unwrap();
+    /// let space_id = RoomId::try_from("!space:example.org").unwrap();
+    ///
+    /// client
+    ///     .join_suggested_rooms(&space_id, |done, total| {
+    ///         println!("Joined {} of {} rooms", done, total)
+    ///     })
+    ///     .await
+    ///     .unwrap();
+    /// # })
+    /// ```
+    pub async fn join_suggested_rooms(
+        &self,
+        space_id: &RoomId,
+        mut progress: impl FnMut(usize, usize),
+    ) -> Result<Vec<RoomId>> {
+        let mut children = Vec::new();
+        let mut from = None;
+
+        loop {
+            let page = self
+                .get_space_hierarchy(space_id, from.as_deref(), Some(1), true)
+                .await?;
+
+            for room in page.rooms.iter().filter(|r| &r.room_id == space_id) {
+                children.extend(room.suggested_children().cloned());
+            }
+
+            from = page.next_batch;
+            if from.is_none() {
+                break;
+            }
+        }
+
+        let mut to_join = Vec::new();
+        for child in children {
+            if self.get_joined_room(&child.state_key).await.is_none() {
+                to_join.push(child);
+            }
+        }
+
+        let total = to_join.len();
+        let mut joined = Vec::new();
+
+        for (done, child) in to_join.into_iter().enumerate() {
+            let room_id = RoomIdOrAliasId::from(child.state_key.clone());
+
+            match self
+                .join_room_by_id_or_alias(&room_id, &child.content.via)
+                .await
+            {
+                Ok(response) => joined.push(response.room_id),
+                Err(e) => warn!(
+                    "Couldn't join the suggested room {} of {}: {}",
+                    child.state_key, space_id, e
+                ),
+            }
+
+            progress(done + 1, total);
+        }
+
+        Ok(joined)
+    }
+
+    /// Forget a room by `RoomId`.
+    ///
+    /// Returns a `forget_room::Response`, an empty response. The room is
+    /// removed from the client and its state store.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The `RoomId` of the room to be forget.
+    pub async fn forget_room_by_id(&self, room_id: &RoomId) -> Result<forget_room::Response> {
+        let request = forget_room::Request {
+            room_id: room_id.clone(),
+        };
+        let response = self.send(request).await?;
+        self.base_client.receive_forgotten_room(room_id).await?;
+
+        Ok(response)
+    }
+
+    /// Ban a user from a room by `RoomId` and `UserId`.
+    ///
+    /// Returns a `ban_user::Response`, an empty response.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The `RoomId` of the room to ban the user from.
+    ///
+    /// * `user_id` - The user to ban by `UserId`.
+    ///
+    /// * `reason` - The reason for banning this user.
+    pub async fn ban_user(
+        &self,
+        room_id: &RoomId,
+        user_id: &UserId,
+        reason: Option<String>,
+    ) -> Result<ban_user::Response> {
+        let request = ban_user::Request {
+            reason,
+            room_id: room_id.clone(),
+            user_id: user_id.clone(),
+        };
+        self.send(request).await
+    }
+
+    /// Kick a user out of the specified room.
+    ///
+    /// Returns a `kick_user::Response`, an empty response.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The `RoomId` of the room the user should be kicked out of.
+    ///
+    /// * `user_id` - The `UserId` of the user that should be kicked out of the room.
+    ///
+    /// * `reason` - Optional reason why the room member is being kicked out.
+    pub async fn kick_user(
+        &self,
+        room_id: &RoomId,
+        user_id: &UserId,
+        reason: Option<String>,
+    ) -> Result<kick_user::Response> {
+        let request = kick_user::Request {
+            reason,
+            room_id: room_id.clone(),
+            user_id: user_id.clone(),
+        };
+        self.send(request).await
+    }
+
+    /// Lift the ban of a user in the specified room.
+    ///
+    /// Returns a `unban_user::Response`, an empty response.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The `RoomId` of the room the user should be unbanned from.
+    ///
+    /// * `user_id` - The `UserId` of the user whose ban should be lifted.
+    pub async fn unban_user(
+        &self,
+        room_id: &RoomId,
+        user_id: &UserId,
+    ) -> Result<unban_user::Response> {
+        let request = unban_user::Request {
+            room_id: room_id.clone(),
+            user_id: user_id.clone(),
+        };
+        self.send(request).await
+    }
+
+    /// Leave the specified room.
+    ///
+    /// Returns a `leave_room::Response`, an empty response. The room is moved
+    /// into the left rooms of the client right away.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The `RoomId` of the room to leave.
+    ///
+    pub async fn leave_room(&self, room_id: &RoomId) -> Result<leave_room::Response> {
+        let request = leave_room::Request {
+            room_id: room_id.clone(),
+        };
+        let response = self.send(request).await?;
+        self.base_client.receive_left_room(room_id).await?;
+
+        Ok(response)
+    }
+
+    /// Accept the invite to the given room.
+    ///
+    /// The room is joined and moved from the invited rooms into the joined
+    /// rooms of the client right away.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The `RoomId` of the room we are invited to.
+    pub async fn accept_invite(&self, room_id: &RoomId) -> Result<join_room_by_id::Response> {
+        self.join_room_by_id(room_id).await
+    }
+
+    /// Reject the invite to the given room.
+    ///
+    /// The room is left and moved from the invited rooms into the left rooms
+    /// of the client right away.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The `RoomId` of the room we are invited to.
+    pub async fn reject_invite(&self, room_id: &RoomId) -> Result<leave_room::Response> {
+        self.leave_room(room_id).await
+    }
+
+    /// Invite the specified user by `UserId` to the given room.
+    ///
+    /// Returns a `invite_user::Response`, an empty response.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The `RoomId` of the room to invite the specified user to.
+    ///
+    /// * `user_id` - The `UserId` of the user to invite to the room.
+    pub async fn invite_user_by_id(
+        &self,
+        room_id: &RoomId,
+        user_id: &UserId,
+    ) -> Result<invite_user::Response> {
+        let request = invite_user::Request {
+            room_id: room_id.clone(),
+            recipient: InvitationRecipient::UserId {
+                user_id: user_id.clone(),
+            },
+        };
+        self.send(request).await
+    }
+
+    /// Invite the specified user by third party id to the given room.
+    ///
+    /// Returns a `invite_user::Response`, an empty response.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The `RoomId` of the room to invite the specified user to.
+    ///
+    /// * `invite_id` - A third party id of a user to invite to the room.
+    pub async fn invite_user_by_3pid(
+        &self,
+        room_id: &RoomId,
+        invite_id: &Invite3pid,
+    ) -> Result<invite_user::Response> {
+        let request = invite_user::Request {
+            room_id: room_id.clone(),
+            recipient: InvitationRecipient::ThirdPartyId(invite_id.clone()),
+        };
+        self.send(request).await
+    }
+
+    /// Create a room using the `RoomBuilder` and send the request.
+    ///
+    /// Sends a request to `/_matrix/client/r0/createRoom`, returns a `create_room::Response`.
+    /// Once the server responds the new room is added to the joined rooms of
+    /// the client, without waiting for the next sync.
+    ///
+    /// # Arguments
+    ///
+    /// * `room` - The easiest way to create this request is using the `RoomBuilder`.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use matrix_sdk::{Client, RoomBuilder};
+    /// # use matrix_sdk::api::r0::room::Visibility;
+    /// # use url::Url;
+    ///
+    /// # let homeserver = Url::parse("http://example.com").unwrap();
+    /// let mut builder = RoomBuilder::default();
+    /// builder.creation_content(false)
+    ///     .initial_state(vec![])
+    ///     .visibility(Visibility::Public)
+    ///     .name("name")
+    ///     .room_version("v1.0")
+    ///     .encrypted();
+    ///
+    /// let mut cli = Client::new(homeserver, None).unwrap();
+    /// # use futures::executor::block_on;
+    /// # block_on(async {
+    /// assert!(cli.create_room(builder).await.is_ok());
+    /// # });
+    /// ```
+    pub async fn create_room<R: Into<create_room::Request>>(
+        &self,
+        room: R,
+    ) -> Result<create_room::Response> {
+        let (response, _) = self.create_and_register_room(room.into()).await?;
+        Ok(response)
+    }
+
+    /// Create a room using the `RoomBuilder` and return the joined room.
+    ///
+    /// Works like `create_room` but returns the newly created room instead of
+    /// the `create_room::Response`.
+    ///
+    /// # Arguments
+    ///
+    /// * `room` - The easiest way to create this request is using the `RoomBuilder`.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use matrix_sdk::{Client, RoomBuilder};
+    /// # use url::Url;
+    ///
+    /// # let homeserver = Url::parse("http://example.com").unwrap();
+    /// let mut builder = RoomBuilder::default();
+    /// builder.name("name").encrypted();
+    ///
+    /// let mut cli = Client::new(homeserver, None).unwrap();
+    /// # use futures::executor::block_on;
+    /// # block_on(async {
+    /// let room = cli.create_room_and_join(builder).await.unwrap();
+    /// println!("Created room {}", room.read().await.room_id);
+    /// # });
+    /// ```
+    pub async fn create_room_and_join<R: Into<create_room::Request>>(
+        &self,
+        room: R,
+    ) -> Result<Arc<RwLock<Room>>> {
+        let (_, room) = self.create_and_register_room(room.into()).await?;
+        Ok(room)
+    }
+
+    /// Send the `createRoom` request and add the new room to the joined
+    /// rooms of the client.
+    async fn create_and_register_room(
+        &self,
+        request: create_room::Request,
+    ) -> Result<(create_room::Response, Arc<RwLock<Room>>)> {
+        let encrypted = request.initial_state.iter().any(|event| {
+            serde_json::to_value(event)
+                .ok()
+                .and_then(|e| e.get("type").cloned())
+                == Some(JsonValue::from("m.room.encryption"))
+        });
+
+        let response = self.send(request).await?;
+        let room = self
+            .base_client
+            .receive_created_room(&response.room_id, encrypted)
+            .await?;
+
+        Ok((response, room))
+    }
+
+    /// Get the joined direct message room we share with the given user.
+    ///
+    /// Direct message rooms are taken from the `m.direct` account data of our
+    /// own user, the first one that we are joined to is returned.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - The user the direct messages are exchanged with.
+    pub async fn get_dm_room(&self, user_id: &UserId) -> Option<Arc<RwLock<Room>>> {
+        for room_id in self.base_client.direct_room_ids(user_id).await {
+            if let Some(room) = self.get_joined_room(&room_id).await {
+                return Some(room);
+            }
+        }
+
+        None
+    }
+
+    /// Get or create a direct message room with the given user.
+    ///
+    /// An existing direct message room is reused, otherwise a room is created
+    /// with the `is_direct` flag set, the user is invited and the room is
+    /// added to the `m.direct` account data of our own user.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - The user the direct messages are exchanged with.
+    pub async fn create_dm(&self, user_id: &UserId) -> Result<Arc<RwLock<Room>>> {
+        if let Some(room) = self.get_dm_room(user_id).await {
+            return Ok(room);
+        }
+
+        let mut builder = RoomBuilder::new();
+        builder
+            .invite(vec![user_id.clone()])
+            .is_direct(true)
+            .preset(RoomPreset::TrustedPrivateChat);
+
+        let room = self.create_room_and_join(builder).await?;
+        let room_id = room.read().await.room_id.clone();
+
+        self.mark_room_as_direct(user_id, &room_id).await?;
+
+        Ok(room)
+    }
+
+    /// Create an encrypted direct message room with the given user.
+    ///
+    /// The user is invited to the room and the room is added to the
+    /// `m.direct` account data of our own user, so other clients show it as
+    /// a direct message room as well.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - The user the direct messages are exchanged with.
+    pub async fn create_encrypted_dm(&self, user_id: &UserId) -> Result<Arc<RwLock<Room>>> {
+        let mut builder = RoomBuilder::new();
+        builder
+            .invite(vec![user_id.clone()])
+            .is_direct(true)
+            .preset(RoomPreset::TrustedPrivateChat)
+            .encrypted();
+
+        let room = self.create_room_and_join(builder).await?;
+        let room_id = room.read().await.room_id.clone();
+
+        self.mark_room_as_direct(user_id, &room_id).await?;
+
+        Ok(room)
+    }
+
+    /// Create a private group chat that can only be joined by invitation.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the group.
+    ///
+    /// * `invitees` - The users that should be invited to the group.
+    ///
+    /// * `encrypted` - Should end-to-end encryption be enabled for the group.
+    pub async fn create_private_group(
+        &self,
+        name: &str,
+        invitees: Vec<UserId>,
+        encrypted: bool,
+    ) -> Result<Arc<RwLock<Room>>> {
+        let mut builder = RoomBuilder::new();
+        builder
+            .name(name)
+            .invite(invitees)
+            .preset(RoomPreset::PrivateChat);
+
+        if encrypted {
+            builder.encrypted();
+        }
+
+        self.create_room_and_join(builder).await
+    }
+
+    /// Add a room to the direct message rooms of a user in the `m.direct`
+    /// account data of our own user.
+    async fn mark_room_as_direct(&self, user_id: &UserId, room_id: &RoomId) -> Result<()> {
+        let mut direct: JsonValue = self.get_account_data("m.direct").await?.unwrap_or_default();
+
+        if !direct.is_object() {
+            direct = JsonValue::Object(Default::default());
+        }
+
+        let rooms = &mut direct[user_id.to_string()];
+
+        if !rooms.is_array() {
+            *rooms = JsonValue::Array(Vec::new());
+        }
+
+        let room_id = JsonValue::from(room_id.to_string());
+
+        if let Some(rooms) = rooms.as_array_mut() {
+            if !rooms.contains(&room_id) {
+                rooms.push(room_id);
+            }
+        }
+
+        self.set_account_data("m.direct", &direct).await
+    }
+
+    /// Get messages starting at a specific sync point using the
+    /// `MessagesRequestBuilder`s `from` field as a starting point.
+    ///
+    /// Sends a request to `/_matrix/client/r0/rooms/{room_id}/messages` and
+    /// returns a `get_message_events::IncomingResponse` that contains chunks
+    /// of `RoomEvents`.
+    ///
+    /// Encrypted events are decrypted and all the events are handed to
+    /// `EventEmitter::on_timeline_event()` as `EventOrigin::Backfill` events.
+    /// Paging backwards moves the pagination token of the room, see
+    /// `Room::prev_batch()`.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - The easiest way to create a `Request` is using the
+    /// `MessagesRequestBuilder`.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// # use std::convert::TryFrom;
+    /// use matrix_sdk::{Client, MessagesRequestBuilder};
+    /// # use matrix_sdk::identifiers::RoomId;
+    /// # use matrix_sdk::api::r0::filter::RoomEventFilter;
+    /// # use matrix_sdk::api::r0::message::get_message_events::Direction;
+    /// # use url::Url;
+    /// # use matrix_sdk::js_int::UInt;
+    ///
+    /// # let homeserver = Url::parse("http://example.com").unwrap();
+    /// let mut builder = MessagesRequestBuilder::new();
+    /// builder.room_id(RoomId::try_from("!roomid:example.com").unwrap())
+    ///     .from("t47429-4392820_219380_26003_2265".to_string())
+    ///     .to("t4357353_219380_26003_2265".to_string())
+    ///     .direction(Direction::Backward)
+    ///     .limit(UInt::new(10).unwrap());
+    ///
+    /// let mut cli = Client::new(homeserver, None).unwrap();
+    /// # use futures::executor::block_on;
+    /// # block_on(async {
+    /// assert!(cli.room_messages(builder).await.is_ok());
+    /// # });
+    /// ```
+    pub async fn room_messages<R: Into<get_message_events::Request>>(
+        &self,
+        request: R,
+    ) -> Result<get_message_events::Response> {
+        let req = request.into();
+        let room_id = req.room_id.clone();
+        let backwards = matches!(req.dir, Direction::Backward);
+
+        let mut response = self.send(req).await?;
+        self.base_client
+            .receive_messages(&room_id, &mut response, backwards)
+            .await?;
+
+        Ok(response)
+    }
+
+    /// Fetch the events of a room that came before the ones we know about.
+    ///
+    /// The pagination token of the room is taken from the sync responses and
+    /// moved backwards with every page, calling this repeatedly pages through
+    /// the whole history of the room. The events are decrypted and handed to
+    /// `EventEmitter::on_timeline_event()` as `EventOrigin::Backfill` events.
+    ///
+    /// Returns `None` if the room isn't known or no pagination token is known
+    /// for the room, e.g. because the start of the room was reached.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The id of the room.
+    ///
+    /// * `limit` - The maximum number of events to fetch.
+    ///
+    /// * `filter` - A filter that the fetched events should match.
+    pub async fn backfill_room(
+        &self,
+        room_id: &RoomId,
+        limit: UInt,
+        filter: Option<RoomEventFilter>,
+    ) -> Result<Option<get_message_events::Response>> {
+        let room = match self.get_joined_room(room_id).await {
+            Some(room) => room,
+            None => match self.get_left_room(room_id).await {
+                Some(room) => room,
+                None => return Ok(None),
+            },
+        };
+
+        let from = match room.read().await.prev_batch() {
+            Some(token) => token.to_owned(),
+            None => return Ok(None),
+        };
+
+        let mut builder = MessagesRequestBuilder::new();
+        builder
+            .room_id(room_id.clone())
+            .from(from)
+            .direction(Direction::Backward)
+            .limit(limit);
+
+        if let Some(filter) = filter {
+            builder.filter(filter);
+        }
+
+        self.room_messages(builder).await.map(Some)
+    }
+
+    /// Search the events of the joined rooms on the server.
+    ///
+    /// Every result comes with the events right before and after it as its
+    /// context. Encrypted events among the results are returned decrypted if
+    /// the room keys are known, note that the server can only search the
+    /// events of unencrypted rooms.
+    ///
+    /// # Arguments
+    ///
+    /// * `term` - The words to search for.
+    ///
+    /// * `rooms` - The rooms that should be searched, all joined rooms if
+    /// `None`.
+    ///
+    /// * `order` - The order in which the results should be returned.
+    ///
+    /// * `limit` - The maximum number of results to return.
+    ///
+    /// * `next_batch` - The `next_batch` token of the previous page of
+    /// results, `None` to get the first page.
+    pub async fn search(
+        &self,
+        term: &str,
+        rooms: Option<&[RoomId]>,
+        order: SearchOrder,
+        limit: Option<UInt>,
+        next_batch: Option<&str>,
+    ) -> Result<SearchResults> {
+        let mut request_url = self.homeserver.clone();
+        request_url.set_path("/_matrix/client/r0/search");
+
+        if let Some(next_batch) = next_batch {
+            request_url
+                .query_pairs_mut()
+                .append_pair("next_batch", next_batch);
+        }
+
+        let mut filter = serde_json::json!({});
+        if let Some(rooms) = rooms {
+            let rooms: Vec<String> = rooms.iter().map(|r| r.to_string()).collect();
+            filter["rooms"] = rooms.into();
+        }
+        if let Some(limit) = limit {
+            filter["limit"] = u64::from(limit).into();
+        }
+
+        let body = serde_json::json!({
+            "search_categories": {
+                "room_events": {
+                    "search_term": term,
+                    "order_by": order,
+                    "filter": filter,
+                    "event_context": {
+                        "before_limit": SEARCH_CONTEXT_SIZE,
+                        "after_limit": SEARCH_CONTEXT_SIZE,
+                    },
+                }
+            }
+        });
+
+        let mut response: JsonValue = self
+            .send_raw(HttpMethod::POST, request_url, Some(&body))
+            .await?;
+        let mut results: SearchResults =
+            serde_json::from_value(response["search_categories"]["room_events"].take())?;
+
+        self.base_client.receive_search_results(&mut results).await;
+
+        Ok(results)
+    }
+
+    /// Search the messages the client received so far.
+    ///
+    /// Unlike `search()` this works for encrypted rooms as well, the
+    /// decrypted messages are indexed as they arrive. Only the messages that
+    /// were received since the client was created are searched.
+    ///
+    /// Returns the matching messages together with the messages around them,
+    /// the best match first.
+    ///
+    /// # Arguments
+    ///
+    /// * `term` - The words to search for.
+    ///
+    /// * `room_id` - Only search the messages of this room if set.
+    #[cfg(feature = "local-search")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "local-search")))]
+    pub async fn search_local(
+        &self,
+        term: &str,
+        room_id: Option<&RoomId>,
+    ) -> Vec<LocalSearchResult> {
+        self.base_client.search_local(term, room_id).await
+    }
+
+    /// Get an event together with the events that came right before and after
+    /// it.
+    ///
+    /// This lets clients jump to a specific event, e.g. from a notification
+    /// or a search result, and render the timeline around it. Encrypted
+    /// events are returned decrypted if the room keys are known.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The id of the room the event belongs to.
+    ///
+    /// * `event_id` - The id of the event.
+    ///
+    /// * `limit` - The maximum number of events to return before and after
+    /// the event combined.
+    pub async fn get_event_context(
+        &self,
+        room_id: &RoomId,
+        event_id: &EventId,
+        limit: UInt,
+    ) -> Result<get_context::Response> {
+        let request = get_context::Request {
+            room_id: room_id.clone(),
+            event_id: event_id.clone(),
+            limit,
+        };
+
+        let mut response = self.send(request).await?;
+        self.base_client
+            .receive_context(room_id, &mut response)
+            .await;
+
+        Ok(response)
+    }
+
+    /// Upload some media to the server.
+    ///
+    /// The returned response contains the `mxc://` URI of the uploaded
+    /// content.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - A `Reader` that will be used to read the data that should
+    /// be uploaded.
+    ///
+    /// * `content_type` - The content type of the data, e.g. `image/png`. If
+    /// `None` is given the content type is detected from the data, falling
+    /// back to `application/octet-stream`.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// # use std::fs::File;
+    /// # use matrix_sdk::Client;
+    /// # use url::Url;
+    /// # use futures::executor::block_on;
+    /// # block_on(async {
+    /// # let homeserver = Url::parse("http://localhost:8080").unwrap();
+    /// # let client = Client::new(homeserver, None).unwrap();
+    /// let mut image = File::open("/home/example/my-cat.jpg").unwrap();
+    ///
+    /// let response = client.upload(&mut image, Some("image/jpeg")).await.unwrap();
+    ///
+    /// println!("Cat URI: {}", response.content_uri);
+    /// # });
+    /// ```
+    pub async fn upload<R: Read>(
+        &self,
+        reader: &mut R,
+        content_type: Option<&str>,
+    ) -> Result<create_content::Response> {
+        self.upload_with_progress(reader, content_type, |_| {})
+            .await
+    }
+
+    /// Upload some media to the server, reporting the progress while reading
+    /// the data.
+    ///
+    /// See `upload()` for more info.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - A `Reader` that will be used to read the data that should
+    /// be uploaded.
+    ///
+    /// * `content_type` - The content type of the data, detected from the data
+    /// if `None` is given.
+    ///
+    /// * `progress` - A callback that is called with the number of bytes that
+    /// were read so far.
+    pub async fn upload_with_progress<R: Read>(
+        &self,
+        reader: &mut R,
+        content_type: Option<&str>,
+        progress: impl FnMut(usize),
+    ) -> Result<create_content::Response> {
+        let file = media::read_with_progress(reader, progress)?;
+
+        let content_type = content_type
+            .unwrap_or_else(|| media::detect_content_type(&file))
+            .to_owned();
+
+        let request = create_content::Request { content_type, file };
+
+        self.send(request).await
+    }
+
+    /// Download some media from the media repository.
+    ///
+    /// If the media cache is enabled the media is served from it if possible,
+    /// otherwise the downloaded media is added to it.
+    ///
+    /// # Arguments
+    ///
+    /// * `mxc_uri` - The `mxc://` URI of the media.
+    pub async fn get_media_content(&self, mxc_uri: &str) -> Result<Vec<u8>> {
+        let (server_name, media_id) = media::parse_mxc_uri(mxc_uri)
+            .ok_or_else(|| Error::InvalidMxcUri(mxc_uri.to_owned()))?;
+
+        if let Some(data) = self.base_client.get_cached_media(mxc_uri).await? {
+            return Ok(data);
+        }
+
+        let request = get_content::Request {
+            media_id: media_id.to_owned(),
+            server_name: server_name.to_owned(),
+            allow_remote: None,
+        };
+
+        let file = self.send(request).await?.file;
+        self.base_client.cache_media(mxc_uri, &file).await?;
+
+        Ok(file)
+    }
+
+    /// Download a thumbnail of some media from the media repository.
+    ///
+    /// The server generates the thumbnail, so this doesn't work for
+    /// encrypted media, see `get_attachment_thumbnail()` for those.
+    ///
+    /// # Arguments
+    ///
+    /// * `mxc_uri` - The `mxc://` URI of the media.
+    ///
+    /// * `width` - The desired width of the thumbnail.
+    ///
+    /// * `height` - The desired height of the thumbnail.
+    ///
+    /// * `method` - How the media should be resized to fit the size, the
+    /// server picks one if not given.
+    pub async fn get_thumbnail(
+        &self,
+        mxc_uri: &str,
+        width: UInt,
+        height: UInt,
+        method: Option<get_content_thumbnail::Method>,
+    ) -> Result<Vec<u8>> {
+        let (server_name, media_id) = media::parse_mxc_uri(mxc_uri)
+            .ok_or_else(|| Error::InvalidMxcUri(mxc_uri.to_owned()))?;
+
+        let key = MediaCache::thumbnail_key(
+            mxc_uri,
+            width.into(),
+            height.into(),
+            method.as_ref().map(|m| match m {
+                get_content_thumbnail::Method::Crop => "crop",
+                get_content_thumbnail::Method::Scale => "scale",
+            }),
+        );
+
+        if let Some(data) = self.base_client.get_cached_media(&key).await? {
+            return Ok(data);
+        }
+
+        let request = get_content_thumbnail::Request {
+            media_id: media_id.to_owned(),
+            server_name: server_name.to_owned(),
+            method,
+            width,
+            height,
+            allow_remote: None,
+        };
+
+        let file = self.send(request).await?.file;
+        self.base_client.cache_media(&key, &file).await?;
+
+        Ok(file)
+    }
+
+    /// Download the attachment of a message, e.g. the image of an `m.image`
+    /// message.
+    ///
+    /// If the attachment is encrypted it is transparently decrypted using the
+    /// file info of the message.
+    ///
+    /// Returns `None` if the message doesn't carry an attachment.
+    ///
+    /// # Arguments
+    ///
+    /// * `content` - The content of the message.
+    pub async fn get_attachment(&self, content: &MessageEventContent) -> Result<Option<Vec<u8>>> {
+        let content = serde_json::to_value(content)?;
+
+        self.get_file(content.get("url"), content.get("file")).await
+    }
+
+    /// Download the thumbnail of the attachment of a message, e.g. the
+    /// thumbnail the sender attached to an `m.image` message.
+    ///
+    /// Encrypted thumbnails are transparently decrypted.
+    ///
+    /// Returns `None` if the message doesn't carry a thumbnail.
+    ///
+    /// # Arguments
+    ///
+    /// * `content` - The content of the message.
+    pub async fn get_attachment_thumbnail(
+        &self,
+        content: &MessageEventContent,
+    ) -> Result<Option<Vec<u8>>> {
+        let content = serde_json::to_value(content)?;
+        let info = content.get("info");
+
+        self.get_file(
+            info.and_then(|i| i.get("thumbnail_url")),
+            info.and_then(|i| i.get("thumbnail_file")),
+        )
+        .await
+    }
+
+    /// Download a file given either as a plain `mxc://` URI or as an
+    /// `EncryptedFile` JSON object.
+    #[allow(unused_variables)]
+    async fn get_file(
+        &self,
+        url: Option<&JsonValue>,
+        file: Option<&JsonValue>,
+    ) -> Result<Option<Vec<u8>>> {
+        #[cfg(feature = "encryption")]
+        {
+            if let Some(file) = file {
+                let url = file
+                    .get("url")
+                    .and_then(JsonValue::as_str)
+                    .ok_or_else(|| Error::InvalidMxcUri(file.to_string()))?;
+                let info: EncryptionInfo = serde_json::from_value(file.clone())?;
+
+                let ciphertext = self.get_media_content(url).await?;
+                let mut cursor = ciphertext.as_slice();
+                let mut decryptor = AttachmentDecryptor::new(&mut cursor, info)?;
+
+                let mut plaintext = Vec::new();
+                decryptor.read_to_end(&mut plaintext)?;
+
+                return Ok(Some(plaintext));
+            }
+        }
+
+        match url.and_then(JsonValue::as_str) {
+            Some(url) => Ok(Some(self.get_media_content(url).await?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Decrypt the raw JSON of an encrypted room event, e.g. one that was
+    /// delivered inside of a push notification.
+    ///
+    /// No request is sent to the server, only the keys that are already
+    /// known to the crypto store are used.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The id of the room the event was sent in.
+    ///
+    /// * `event` - The JSON of the `m.room.encrypted` event.
+    #[cfg(feature = "encryption")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "encryption")))]
+    pub async fn decrypt_raw_event(
+        &self,
+        room_id: &RoomId,
+        event: &str,
+    ) -> Result<EventJson<RoomEvent>> {
+        Ok(self.base_client.decrypt_raw_event(room_id, event).await?)
+    }
+
+    /// Get the inbound group sessions the crypto store holds for a room.
+    ///
+    /// Every session contains its id, the key of its sender and the index of
+    /// the first message it can decrypt, useful to show encryption debug
+    /// information or to pick the keys of a single room for an export.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The id of the room the sessions belong to.
+    #[cfg(feature = "encryption")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "encryption")))]
+    pub async fn inbound_group_sessions(&self, room_id: &RoomId) -> Result<Vec<GroupSessionInfo>> {
+        Ok(self.base_client.inbound_group_sessions(room_id).await?)
+    }
+
+    /// Export the room keys of a single room, encrypted with a passphrase.
+    ///
+    /// The export uses the standard key export format, so the keys can be
+    /// imported by another client of the user, e.g. to read the history of
+    /// the room there. Deriving the encryption key from the passphrase is
+    /// deliberately slow.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The id of the room whose keys should be exported.
+    ///
+    /// * `passphrase` - The passphrase the export is encrypted with.
+    #[cfg(feature = "encryption")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "encryption")))]
+    pub async fn export_room_keys_for_room(
+        &self,
+        room_id: &RoomId,
+        passphrase: &str,
+    ) -> Result<String> {
+        let keys = self.base_client.export_room_keys(room_id).await?;
+
+        Ok(encrypt_key_export(
+            &keys,
+            passphrase,
+            DEFAULT_EXPORT_ROUNDS,
+        )?)
+    }
+
+    /// Notify the room that our user is typing or stopped typing.
+    ///
+    /// This can be called on every key press, the calls are debounced: while
+    /// we are shown as typing, a typing notice is only sent again once the
+    /// previous one is about to expire, and stopping to type is only sent
+    /// once. The timeout of the typing notices can be set with
+    /// `ClientConfig::typing_notice_timeout()`.
+    ///
+    /// Returns a `create_typing_event::Response`, an empty response.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The `RoomId` the user is typing in.
+    ///
+    /// * `typing` - Whether the user is typing.
+    pub async fn typing_notice(
+        &self,
+        room_id: &RoomId,
+        typing: bool,
+    ) -> Result<create_typing_event::Response> {
+        let user_id = self.user_id().await.ok_or(Error::AuthenticationRequired)?;
+        let timeout = self.typing_notice_timeout;
+
+        let previous = {
+            let mut notices = self.typing_notices.lock().await;
+            let previous = notices.get(room_id).copied();
+
+            // Re-send a typing notice once a quarter of its timeout is left.
+            let debounced = match previous {
+                Some(Some(sent_at)) if typing => sent_at.elapsed() < timeout - timeout / 4,
+                Some(None) => !typing,
+                _ => false,
+            };
+
+            if debounced {
+                return Ok(create_typing_event::Response);
+            }
+
+            notices.insert(
+                room_id.clone(),
+                if typing { Some(Instant::now()) } else { None },
+            );
+            previous
+        };
+
+        let request = create_typing_event::Request {
+            room_id: room_id.clone(),
+            user_id,
+            timeout: if typing { Some(timeout) } else { None },
+            typing,
+        };
+
+        match self.send(request).await {
+            Ok(response) => Ok(response),
+            Err(e) => {
+                let mut notices = self.typing_notices.lock().await;
+                match previous {
+                    Some(previous) => notices.insert(room_id.clone(), previous),
+                    None => notices.remove(room_id),
+                };
+                Err(e)
+            }
+        }
+    }
+
+    /// Send a read receipt for the given event to the room.
+    ///
+    /// Returns a `create_receipt::Response`, an empty response. The read
+    /// receipt of the room is updated right away.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The `RoomId` of the room the event belongs to.
+    ///
+    /// * `event_id` - The `EventId` of the event that was read.
+    pub async fn read_receipt(
+        &self,
+        room_id: &RoomId,
+        event_id: &EventId,
+    ) -> Result<create_receipt::Response> {
+        let request = create_receipt::Request {
+            room_id: room_id.clone(),
+            event_id: event_id.clone(),
+            receipt_type: create_receipt::ReceiptType::Read,
+        };
+        let response = self.send(request).await?;
+
+        if let Some(room) = self.get_joined_room(room_id).await {
+            if room.write().await.set_read_receipt(event_id) {
+                self.base_client.store_room_state(room_id).await?;
+            }
+        }
+
+        Ok(response)
+    }
+
+    /// Set the fully read marker of a room and optionally send a read receipt
+    /// at the same time.
+    ///
+    /// Returns a `set_read_marker::Response`, an empty response. The markers
+    /// of the room are updated right away.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The `RoomId` of the room the markers belong to.
+    ///
+    /// * `fully_read` - The `EventId` of the event the user has fully read up
+    /// to.
+    ///
+    /// * `read` - The `EventId` of the event a read receipt should be sent for.
+    pub async fn read_marker(
+        &self,
+        room_id: &RoomId,
+        fully_read: &EventId,
+        read: Option<&EventId>,
+    ) -> Result<set_read_marker::Response> {
+        let request = set_read_marker::Request {
+            room_id: room_id.clone(),
+            fully_read: fully_read.clone(),
+            read_receipt: read.cloned(),
+        };
+        let response = self.send(request).await?;
+
+        if let Some(room) = self.get_joined_room(room_id).await {
+            let mut room = room.write().await;
+            let mut changed = room.set_fully_read(fully_read);
+
+            if let Some(read) = read {
+                changed |= room.set_read_receipt(read);
+            }
+
+            drop(room);
+
+            if changed {
+                self.base_client.store_room_state(room_id).await?;
+            }
+        }
+
+        Ok(response)
+    }
+
+    /// Get the list of devices of the logged in user.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use matrix_sdk::Client;
+    /// # use url::Url;
+    /// # use futures::executor::block_on;
+    /// # block_on(async {
+    /// # let homeserver = Url::parse("http://localhost:8080").unwrap();
+    /// # let client = Client::new(homeserver, None).unwrap();
+    /// for device in client.devices().await.unwrap() {
+    ///     println!(
+    ///         "Device: {} {}",
+    ///         device.device_id,
+    ///         device.display_name.as_deref().unwrap_or("")
+    ///     );
+    /// }
+    /// # });
+    /// ```
+    pub async fn devices(&self) -> Result<Vec<DeviceInfo>> {
+        self.own_device_id().await?;
+
+        let request = get_devices::Request {};
+        let response = self.send(request).await?;
+
+        Ok(response.devices.into_iter().map(DeviceInfo::from).collect())
+    }
+
+    /// Get the id of the device of our session.
+    ///
+    /// Fails with `Error::DeviceRequired` if the session isn't tied to a
+    /// device, e.g. for appservices. The device APIs refuse to work in that
+    /// case since they would treat every device as one of the other devices.
+    async fn own_device_id(&self) -> Result<DeviceId> {
+        match self.base_client.session().read().await.as_ref() {
+            Some(session) if session.has_device() => Ok(session.device_id.clone()),
+            Some(_) => Err(Error::DeviceRequired),
+            None => Err(Error::AuthenticationRequired),
+        }
+    }
+
+    /// Change the display name of one of our devices.
+    ///
+    /// # Arguments
+    ///
+    /// * `device_id` - The unique id of the device that should be renamed.
+    ///
+    /// * `display_name` - The new display name of the device.
+    pub async fn rename_device(&self, device_id: &DeviceId, display_name: &str) -> Result<()> {
+        self.own_device_id().await?;
+
+        let request = update_device::Request {
+            device_id: device_id.clone(),
+            display_name: Some(display_name.to_owned()),
+        };
+
+        self.send(request).await?;
+        Ok(())
+    }
+
+    /// Delete the given devices from the server.
+    ///
+    /// This endpoint requires user-interactive authentication. If a
+    /// `UiaaHandler` is set the stages are completed using it, otherwise if
+    /// no `auth_data` is given the server will respond with the
+    /// authentication stages that need to be completed, see
+    /// `Error::uiaa_response()`.
+    ///
+    /// # Arguments
+    ///
+    /// * `devices` - The list of devices that should be deleted.
+    ///
+    /// * `auth_data` - The authentication data for the current stage of the
+    /// user-interactive authentication.
+    pub async fn delete_devices(
+        &self,
+        devices: &[DeviceId],
+        auth_data: Option<AuthData>,
+    ) -> Result<delete_devices::Response> {
+        self.own_device_id().await?;
+
+        self.send_uiaa_with_handler(
+            |auth| delete_devices::Request {
+                devices: devices.to_vec(),
+                auth,
+            },
+            auth_data,
+        )
+        .await
+    }
+
+    /// Delete the given devices from the server, confirming the deletion with
+    /// the password of the logged in user.
+    ///
+    /// This performs the user-interactive authentication round-trip that
+    /// deleting devices requires.
+    ///
+    /// # Arguments
+    ///
+    /// * `devices` - The list of devices that should be deleted.
+    ///
+    /// * `password` - The password of the logged in user.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use matrix_sdk::Client;
+    /// # use url::Url;
+    /// # use futures::executor::block_on;
+    /// # block_on(async {
+    /// # let homeserver = Url::parse("http://localhost:8080").unwrap();
+    /// # let client = Client::new(homeserver, None).unwrap();
+    /// let devices = &["QBUAZIFURK".to_owned()];
+    /// client
+    ///     .delete_devices_with_password(devices, "wordpass")
+    ///     .await
+    ///     .unwrap();
+    /// # });
+    /// ```
+    pub async fn delete_devices_with_password(
+        &self,
+        devices: &[DeviceId],
+        password: &str,
+    ) -> Result<delete_devices::Response> {
+        let session = match self.delete_devices(devices, None).await {
+            Ok(response) => return Ok(response),
+            Err(e) => match e.uiaa_response() {
+                Some(info) => info.session.clone(),
+                None => return Err(e),
+            },
+        };
+
+        let stage = AuthStage::Password {
+            password: password.to_owned(),
+        };
+        let auth_data = stage.into_auth_data(session, self.user_id().await.as_ref());
+
+        self.delete_devices(devices, Some(auth_data)).await
+    }
+
+    /// Sign out all the devices of the logged in user except the current one.
+    ///
+    /// The other devices are deleted, which requires user-interactive
+    /// authentication, see `delete_devices()`. Our own device list is queried
+    /// again afterwards so the deleted devices aren't used for encryption
+    /// anymore.
+    ///
+    /// Returns the ids of the devices that were signed out.
+    ///
+    /// # Arguments
+    ///
+    /// * `auth_data` - The authentication data for the current stage of the
+    /// user-interactive authentication.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use matrix_sdk::Client;
+    /// # use url::Url;
+    /// # use futures::executor::block_on;
+    /// # block_on(async {
+    /// # let homeserver = Url::parse("http://localhost:8080").unwrap();
+    /// # let client = Client::new(homeserver, None).unwrap();
+    /// let signed_out = client.sign_out_other_devices(None).await.unwrap();
+    /// println!("Signed out {} devices", signed_out.len());
+    /// # });
+    /// ```
+    pub async fn sign_out_other_devices(
+        &self,
+        auth_data: Option<AuthData>,
+    ) -> Result<Vec<DeviceId>> {
+        let own_device_id = self.own_device_id().await?;
+
+        let devices: Vec<DeviceId> = self
+            .devices()
+            .await?
+            .into_iter()
+            .map(|d| d.device_id)
+            .filter(|d| d != &own_device_id)
+            .collect();
+
+        if devices.is_empty() {
+            return Ok(devices);
+        }
+
+        self.delete_devices(&devices, auth_data).await?;
+
+        #[cfg(feature = "encryption")]
+        {
+            if let Some(user_id) = self.user_id().await {
+                self.base_client.mark_user_as_changed(&user_id).await;
+            }
+
+            if self.base_client.should_query_keys().await {
+                if let Err(e) = self.keys_query().await {
+                    warn!("Error while querying device keys {:?}", e);
+                }
+            }
+        }
+
+        Ok(devices)
+    }
+
+    /// Register a pusher, so the homeserver sends push notifications for our
+    /// user to a push gateway.
+    ///
+    /// The pusher endpoints aren't supported by the typed requests yet, so
+    /// the request is sent by hand.
+    ///
+    /// # Arguments
+    ///
+    /// * `pusher` - The pusher that should be registered, a pusher with the
+    /// same app id and pushkey is replaced.
+    ///
+    /// * `append` - Keep the pushers of other users that use the same pushkey,
+    /// by default they are removed.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// # use matrix_sdk::{Client, Pusher};
+    /// # use url::Url;
+    /// # use futures::executor::block_on;
+    /// # block_on(async {
+    /// # let homeserver = Url::parse("http://localhost:8080").unwrap();
+    /// # let client = Client::new(homeserver, None).unwrap();
+    /// let pusher = Pusher::http(
+    ///     "org.example.app",
+    ///     "<device token>",
+    ///     "https://push.example.org/_matrix/push/v1/notify",
+    /// )
+    /// .app_display_name("Example")
+    /// .device_display_name("Phone");
+    ///
+    /// client.set_pusher(&pusher, false).await.unwrap();
+    /// # });
+    /// ```
+    pub async fn set_pusher(&self, pusher: &Pusher, append: bool) -> Result<()> {
+        let mut request_url = self.homeserver.clone();
+        request_url.set_path("/_matrix/client/r0/pushers/set");
+
+        let mut body = serde_json::to_value(pusher)?;
+        body["append"] = append.into();
+
+        let _response: JsonValue = self
+            .send_raw(HttpMethod::POST, request_url, Some(&body))
+            .await?;
+
+        Ok(())
+    }
+
+    /// Get the pushers that are registered for our user.
+    pub async fn get_pushers(&self) -> Result<Vec<Pusher>> {
+        let mut request_url = self.homeserver.clone();
+        request_url.set_path("/_matrix/client/r0/pushers");
+
+        let mut response: JsonValue = self.send_raw(HttpMethod::GET, request_url, None).await?;
+
+        match response.get_mut("pushers") {
+            Some(pushers) => Ok(serde_json::from_value(pushers.take())?),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Get the keywords that notify our user.
+    ///
+    /// These are the patterns of the content push rules our user added, the
+    /// list is empty until the push rules were received in a sync.
+    pub async fn keywords(&self) -> Vec<String> {
+        self.base_client.keywords().await
+    }
+
+    /// Add a keyword that notifies our user and highlights the messages
+    /// containing it.
+    ///
+    /// This creates a content push rule for the keyword, the local push rules
+    /// are updated right away so the change applies to the events of the
+    /// next sync.
+    ///
+    /// # Arguments
+    ///
+    /// * `keyword` - The keyword, it may contain the `*` and `?` wildcards.
+    pub async fn add_keyword(&self, keyword: &str) -> Result<()> {
+        let actions = PushActions::keyword();
+        let body = serde_json::json!({
+            "pattern": keyword,
+            "actions": actions.to_json(),
+        });
+
+        let request_url = self.keyword_rule_url(keyword);
+
+        let _response: JsonValue = self
+            .send_raw(HttpMethod::PUT, request_url, Some(&body))
+            .await?;
+
+        self.base_client
+            .receive_keyword_rule(keyword, Some(&actions))
+            .await?;
+
+        Ok(())
+    }
+
+    /// Remove a keyword that was added with `add_keyword()`.
+    ///
+    /// Removing a keyword that doesn't exist isn't an error.
+    ///
+    /// # Arguments
+    ///
+    /// * `keyword` - The keyword that should be removed.
+    pub async fn remove_keyword(&self, keyword: &str) -> Result<()> {
+        let request_url = self.keyword_rule_url(keyword);
+
+        let response = self
+            .send_raw_request(HttpMethod::DELETE, request_url, None)
+            .await?;
+
+        if response.status() != http::StatusCode::NOT_FOUND {
+            let _response: JsonValue = raw_response(response)?;
+        }
+
+        self.base_client.receive_keyword_rule(keyword, None).await?;
+
+        Ok(())
+    }
+
+    /// Get the URL of the content push rule of a keyword.
+    ///
+    /// The push rule endpoints aren't supported by the typed requests yet, so
+    /// the requests are sent by hand.
+    fn keyword_rule_url(&self, keyword: &str) -> Url {
+        let mut request_url = self.homeserver.clone();
+        request_url.set_path("/_matrix/client/r0/pushrules/global/content");
+        // The keyword may contain characters that need to be escaped.
+        if let Ok(mut segments) = request_url.path_segments_mut() {
+            segments.push(keyword);
+        }
+
+        request_url
+    }
+
+    /// Get the software and version the homeserver runs.
+    ///
+    /// The `/versions` endpoint of the client API only lists the supported
+    /// spec versions, the server software is read from the
+    /// `/_matrix/federation/v1/version` endpoint instead.
+    ///
+    /// Returns `None` if the server doesn't announce its software.
+    pub async fn server_version(&self) -> Result<Option<ServerVersion>> {
+        let mut request_url = self.homeserver.clone();
+        request_url.set_path("/_matrix/federation/v1/version");
+
+        let response = self
+            .http_client
+            .get(request_url)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        let response: JsonValue = serde_json::from_slice(&response.bytes().await?)?;
+
+        Ok(ServerVersion::from_json(&response))
+    }
+
+    /// Detect the homeserver software and pick the `Quirks` that should be
+    /// applied to its responses.
+    ///
+    /// This is done automatically before the first sync, if the server
+    /// software can't be detected all quirks are enabled. Returns no quirks
+    /// if they were disabled with `ClientConfig::disable_quirks()`.
+    pub async fn detect_quirks(&self) -> Quirks {
+        if !self.quirks_enabled {
+            return Quirks::none();
+        }
+
+        let server = match self.server_version().await {
+            Ok(server) => server,
+            Err(e) => {
+                debug!("Couldn't detect the homeserver software: {:?}", e);
+                None
+            }
+        };
+
+        if let Some(server) = &server {
+            debug!("Detected homeserver {} {}", server.name, server.version);
+        }
+
+        let quirks = Quirks::for_server(server.as_ref());
+        *self.quirks.write().await = Some(quirks);
+
+        quirks
+    }
+
+    /// Get the `Quirks` that are applied to the responses of the homeserver.
+    pub async fn quirks(&self) -> Quirks {
+        if self.quirks_enabled {
+            self.quirks.read().await.unwrap_or_default()
+        } else {
+            Quirks::none()
+        }
+    }
+
+    /// Change the password of the logged in user.
+    ///
+    /// This endpoint requires user-interactive authentication, see
+    /// `delete_devices()` for how the authentication is performed.
+    ///
+    /// # Arguments
+    ///
+    /// * `new_password` - The new password of the user.
+    ///
+    /// * `auth_data` - The authentication data for the current stage of the
+    /// user-interactive authentication.
+    pub async fn change_password(
+        &self,
+        new_password: &str,
+        auth_data: Option<AuthData>,
+    ) -> Result<change_password::Response> {
+        self.send_uiaa_with_handler(
+            |auth| change_password::Request {
+                new_password: new_password.to_owned(),
+                auth,
+            },
+            auth_data,
+        )
+        .await
+    }
+
+    /// Erase our account, e.g. to comply with a request to be forgotten.
+    ///
+    /// All the rooms we are in or are invited to are left, every known room
+    /// is forgotten, the account is deactivated with the `erase` flag set and
+    /// the local state of the client, including the state store, is wiped.
+    /// This can't be undone.
+    ///
+    /// The deactivation requires user-interactive authentication, see
+    /// `delete_devices()` for how the authentication is performed. If neither
+    /// `auth_data` nor a `UiaaHandler` is given this fails with
+    /// `Error::UiaaRequired` before any room is left.
+    ///
+    /// The operation isn't atomic, the rooms are left before the account is
+    /// deactivated. If leaving a room or the authentication fails, the rooms
+    /// that were handled so far stay left and forgotten while the account is
+    /// still active. Calling this again continues with the remaining rooms.
+    ///
+    /// # Arguments
+    ///
+    /// * `auth_data` - The authentication data for the current stage of the
+    /// user-interactive authentication.
+    ///
+    /// * `progress` - Called with the number of handled rooms and the total
+    /// number of rooms after every room that was left and forgotten.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use futures::executor::block_on;
+    /// # use matrix_sdk::Client;
+    /// # use url::Url;
+    /// # block_on(async {
+    /// # let homeserver = Url::parse("http://localhost:8080").unwrap();
+    /// # let client = Client::new(homeserver, None).unwrap();
+    /// client
+    ///     .erase_account(None, |done, total| println!("Left {} of {} rooms", done, total))
+    ///     .await
+    ///     .unwrap();
+    /// # })
+    /// ```
+    pub async fn erase_account(
+        &self,
+        auth_data: Option<AuthData>,
+        mut progress: impl FnMut(usize, usize),
+    ) -> Result<()> {
+        if auth_data.is_none() && self.uiaa_handler.read().await.is_none() {
+            return Err(Error::UiaaRequired);
+        }
+
+        self.base_client.load_stored_rooms().await?;
+        self.base_client.load_left_rooms().await?;
+
+        // Rooms we are in or are invited to need to be left before they can
+        // be forgotten.
+        let mut rooms: Vec<(RoomId, bool)> = Vec::new();
+        for room_id in self.joined_rooms().read().await.keys() {
+            rooms.push((room_id.clone(), true));
+        }
+        for room_id in self.invited_rooms().read().await.keys() {
+            rooms.push((room_id.clone(), true));
+        }
+        for room_id in self.left_rooms().read().await.keys() {
+            rooms.push((room_id.clone(), false));
+        }
+
+        let total = rooms.len();
+
+        for (done, (room_id, leave)) in rooms.iter().enumerate() {
+            if *leave {
+                self.leave_room(room_id).await?;
+            }
+            self.forget_room_by_id(room_id).await?;
+
+            progress(done + 1, total);
+        }
+
+        self.retry_with_uiaa_handler(|auth_data| self.deactivate_and_erase(auth_data), auth_data)
+            .await?;
+
+        self.base_client.clear_local_state().await?;
+
+        Ok(())
+    }
+
+    /// Deactivate our account and ask the server to erase our data.
+    ///
+    /// The `erase` flag isn't supported by the typed request, so the request
+    /// is sent by hand. A request that needs further authentication fails with
+    /// an `Error::UiaaError` that contains the `UiaaInfo` of the server.
+    async fn deactivate_and_erase(&self, auth_data: Option<AuthData>) -> Result<()> {
+        let mut request_url = self.homeserver.clone();
+        request_url.set_path("/_matrix/client/r0/account/deactivate");
+
+        let mut body = serde_json::json!({ "erase": true });
+        if let Some(auth_data) = auth_data {
+            body["auth"] = serde_json::to_value(auth_data)?;
+        }
+
+        let response = self
+            .send_raw_request(HttpMethod::POST, request_url, Some(&body))
+            .await?;
+
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(server_error::<UiaaResponse>(response).into())
+        }
+    }
+
+    /// Synchronize the client's state with the latest state on the server.
+    ///
+    /// If a `StateStore` is provided and this is the initial sync state will
+    /// be loaded from the state store.
+    ///
+    /// # Arguments
+    ///
+    /// * `sync_settings` - Settings for the sync call.
+    #[instrument]
+    #[allow(clippy::useless_let_if_seq)]
+    pub async fn sync(&self, mut sync_settings: SyncSettings) -> Result<sync_events::Response> {
+        {
+            // if the client has been synced from the state store don't sync again
+            if !self.base_client.is_state_store_synced() {
+                // this will bail out returning false if the store has not been set up
+                if let Ok(synced) = self.sync_with_state_store().await {
+                    if synced {
+                        // once synced, update the sync token to the last known state from `StateStore`.
+                        sync_settings.token = self.sync_token().await;
+                    }
+                }
+            }
+        }
+
+        if self.quirks_enabled && self.quirks.read().await.is_none() {
+            self.detect_quirks().await;
+        }
+
+        let mut room_filter = serde_json::json!({});
+
+        if sync_settings.skip_left_rooms {
+            room_filter["include_leave"] = false.into();
+        }
+
+        if sync_settings.lazy_load_members {
+            let lazy_load = serde_json::json!({ "lazy_load_members": true });
+            room_filter["state"] = lazy_load.clone();
+            room_filter["timeline"] = lazy_load;
+        }
+
+        let filter = if let Some(filter) = sync_settings.filter.clone() {
+            Some(filter)
+        } else if room_filter.as_object().map_or(false, |f| !f.is_empty()) {
+            let definition = serde_json::from_value(serde_json::json!({ "room": room_filter }))?;
+            Some(sync_events::Filter::FilterDefinition(definition))
+        } else {
+            None
+        };
+
+        let request = sync_events::Request {
+            filter,
+            since: sync_settings.token,
+            full_state: sync_settings.full_state,
+            set_presence: sync_events::SetPresence::Online,
+            timeout: sync_settings.timeout,
+        };
+
+        let start = Instant::now();
+        let http_response = self.send_request(request).await?;
+        let response_size = http_response.body().len();
+        let request_time = start.elapsed();
+
+        let mut response = self.deserialize_sync_response(http_response).await?;
+
+        if sync_settings.skip_left_rooms {
+            response.rooms.leave.clear();
+        }
+
+        self.base_client
+            .receive_sync_response(&mut response)
+            .await?;
+
+        if let Some(observer) = self.sync_observer.read().await.as_ref() {
+            let telemetry = SyncTelemetry {
+                response_size,
+                request_time,
+                processing_time: start.elapsed() - request_time,
+                timeout: sync_settings.timeout,
+                joined_rooms: response.rooms.join.len(),
+                invited_rooms: response.rooms.invite.len(),
+                left_rooms: response.rooms.leave.len(),
+            };
+
+            observer.on_sync(&telemetry).await;
+        }
+
+        self.send_presence_updates(&response).await;
+        self.resolve_sent_event_waiters(&response).await;
+
+        if self.url_previews {
+            self.collect_url_previews(&response).await;
+        }
+
+        if self.avatar_prefetch_budget.is_some() {
+            self.collect_active_rooms(&response).await;
+        }
+
+        if self.auto_join_upgraded_rooms {
+            self.follow_room_upgrades(&response).await;
+        }
+
+        Ok(response)
+    }
+
+    /// Join the replacement rooms of the rooms whose `m.room.tombstone` event
+    /// is part of a sync response.
+    async fn follow_room_upgrades(&self, response: &sync_events::Response) {
+        for room in response.rooms.join.values() {
+            let events = room
+                .state
+                .events
+                .iter()
+                .map(|e| e.json())
+                .chain(room.timeline.events.iter().map(|e| e.json()));
+
+            for event in events {
+                let event = match serde_json::from_str::<JsonValue>(event.get()) {
+                    Ok(e) if e["type"] == "m.room.tombstone" => e,
+                    _ => continue,
+                };
+
+                let replacement = match serde_json::from_value::<RoomId>(
+                    event["content"]["replacement_room"].clone(),
+                ) {
+                    Ok(r) => r,
+                    Err(_) => continue,
+                };
+
+                if self.get_joined_room(&replacement).await.is_some() {
+                    continue;
+                }
+
+                if let Err(e) = self.join_room_by_id(&replacement).await {
+                    warn!("Couldn't join the upgraded room {}: {:?}", replacement, e);
+                }
+            }
+        }
+    }
+
+    /// Remember the URLs in the messages of a sync response so their previews
+    /// can be fetched.
+    async fn collect_url_previews(&self, response: &sync_events::Response) {
+        let mut pending = self.pending_url_previews.lock().await;
+
+        for (room_id, room) in &response.rooms.join {
+            for event in &room.timeline.events {
+                let message = match event.deserialize() {
+                    Ok(RoomEvent::RoomMessage(m)) => m,
+                    _ => continue,
+                };
+
+                let body = match &message.content {
+                    MessageEventContent::Text(c) => &c.body,
+                    MessageEventContent::Notice(c) => &c.body,
+                    MessageEventContent::Emote(c) => &c.body,
+                    _ => continue,
+                };
+
+                for url in media::extract_urls(body) {
+                    pending.push((room_id.clone(), message.event_id.clone(), url));
+                }
+            }
+        }
+    }
+
+    /// Fetch the previews of the URLs that were found in the messages of the
+    /// previous sync responses.
+    ///
+    /// Every preview is attached to the message it was found in and the
+    /// `EventEmitter` receives an `on_room_url_preview()` call for it. URLs
+    /// whose previews can't be fetched are skipped.
+    ///
+    /// Only does something if `ClientConfig::url_previews()` is enabled.
+    pub async fn fetch_url_previews(&self) {
+        let pending: Vec<_> = self.pending_url_previews.lock().await.drain(..).collect();
+
+        for (room_id, event_id, url) in pending {
+            match self.get_url_preview(&url).await {
+                Ok(preview) => {
+                    self.base_client
+                        .receive_url_preview(&room_id, &event_id, preview)
+                        .await;
+                }
+                Err(e) => warn!("Couldn't fetch the preview of {}: {}", url, e),
+            }
+        }
+    }
+
+    /// Remember the rooms that received timeline events in a sync response
+    /// so the avatars of their members can be prefetched.
+    async fn collect_active_rooms(&self, response: &sync_events::Response) {
+        let mut pending = self.pending_avatar_rooms.lock().await;
+
+        for (room_id, room) in &response.rooms.join {
+            if !room.timeline.events.is_empty() && !pending.contains(room_id) {
+                pending.push(room_id.clone());
+            }
+        }
+    }
+
+    /// Download the small avatar thumbnails of the members of the rooms that
+    /// were active in the previous sync responses into the media cache.
+    ///
+    /// Avatars that are already cached are skipped. Once the configured
+    /// budget is used up the remaining avatars are left for the next call.
+    /// Returns the number of bytes that were downloaded.
+    ///
+    /// Only does something if `ClientConfig::avatar_prefetch()` and the
+    /// media cache are enabled.
+    pub async fn prefetch_member_avatars(&self) -> usize {
+        let budget = match self.avatar_prefetch_budget {
+            Some(b) if self.base_client.has_media_cache() => b,
+            _ => return 0,
+        };
+
+        let size = AvatarSize::Small
+            .dimensions()
+            .map(UInt::from)
+            .expect("Small avatars are thumbnails");
+        let mut fetched = 0;
+
+        loop {
+            let room_id = match self.pending_avatar_rooms.lock().await.first() {
+                Some(r) => r.clone(),
+                None => break,
+            };
+
+            let urls: Vec<String> = match self.get_joined_room(&room_id).await {
+                Some(room) => room
+                    .read()
+                    .await
+                    .members
+                    .values()
+                    .filter_map(|m| m.avatar_url.clone())
+                    .collect(),
+                None => Vec::new(),
+            };
+
+            for url in urls {
+                let key = MediaCache::thumbnail_key(&url, size.into(), size.into(), Some("crop"));
+
+                if self.base_client.is_media_cached(&key).await {
+                    continue;
+                }
+
+                if fetched >= budget {
+                    return fetched;
+                }
+
+                match self
+                    .get_thumbnail(&url, size, size, Some(get_content_thumbnail::Method::Crop))
+                    .await
+                {
+                    Ok(thumbnail) => fetched += thumbnail.len(),
+                    Err(e) => warn!("Couldn't prefetch the avatar {}: {}", url, e),
+                }
+            }
+
+            self.pending_avatar_rooms
+                .lock()
+                .await
+                .retain(|r| r != &room_id);
+        }
+
+        fetched
+    }
+
+    /// Get the preview of a URL from the homeserver.
+    ///
+    /// The homeserver fetches the page and generates the preview from its
+    /// OpenGraph metadata.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - The URL that should be previewed.
+    pub async fn get_url_preview(&self, url: &str) -> Result<UrlPreview> {
+        let mut request_url = self.homeserver.clone();
+        request_url.set_path("/_matrix/media/r0/preview_url");
+        request_url.query_pairs_mut().append_pair("url", url);
+
+        let data: JsonValue = self.send_raw(HttpMethod::GET, request_url, None).await?;
+
+        Ok(UrlPreview::from_open_graph(url, &data))
+    }
+
+    /// Set the presence of our own user.
+    ///
+    /// Returns a `set_presence::Response`, an empty response. The presence is
+    /// also stored as the last known presence of our user.
+    ///
+    /// # Arguments
+    ///
+    /// * `presence` - The new presence state.
+    ///
+    /// * `status_msg` - An optional status message, e.g. "Out for lunch".
+    pub async fn set_presence(
+        &self,
+        presence: PresenceState,
+        status_msg: Option<&str>,
+    ) -> Result<set_presence::Response> {
+        let user_id = self.user_id().await.ok_or(Error::AuthenticationRequired)?;
+
+        let request = set_presence::Request {
+            user_id: user_id.clone(),
+            status_msg: status_msg.map(str::to_owned),
+            presence,
+        };
+        let response = self.send(request).await?;
+
+        let content = PresenceEventContent {
+            avatar_url: None,
+            currently_active: Some(presence == PresenceState::Online),
+            displayname: None,
+            last_active_ago: None,
+            presence,
+            status_msg: status_msg.map(str::to_owned),
+        };
+        self.base_client
+            .receive_user_presence(&user_id, content)
+            .await;
+
+        Ok(response)
+    }
+
+    /// Fetch the presence of a user from the homeserver.
+    ///
+    /// The fetched presence replaces the last known presence of the user,
+    /// which can be queried without a request using `Client::presence()`.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - The id of the user.
+    pub async fn get_presence(&self, user_id: &UserId) -> Result<PresenceEventContent> {
+        let request = get_presence::Request {
+            user_id: user_id.clone(),
+        };
+        let response = self.send(request).await?;
+
+        // The display name and avatar aren't part of the response, keep the
+        // ones we already know about.
+        let known = self.base_client.presence(user_id).await;
+
+        let content = PresenceEventContent {
+            avatar_url: known.as_ref().and_then(|p| p.avatar_url.clone()),
+            currently_active: response.currently_active,
+            displayname: known.and_then(|p| p.displayname),
+            last_active_ago: response
+                .last_active_ago
+                .and_then(|d| u64::try_from(d.as_millis()).ok())
+                .and_then(|ms| UInt::try_from(ms).ok()),
+            presence: response.presence,
+            status_msg: response.status_msg,
+        };
+        self.base_client
+            .receive_user_presence(user_id, content.clone())
+            .await;
+
+        Ok(content)
+    }
+
+    /// Get the last known presence of a user.
+    ///
+    /// The presence is updated by every sync and by `Client::get_presence()`,
+    /// no matter which rooms we share with the user. Returns `None` if we
+    /// don't know about the presence of the user.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - The id of the user.
+    pub async fn presence(&self, user_id: &UserId) -> Option<PresenceEventContent> {
+        self.base_client.presence(user_id).await
+    }
+
+    /// Get a stream of the presence updates of all the users the client
+    /// knows about, including our own user.
+    ///
+    /// Every presence event of a sync response is turned into a
+    /// `(UserId, PresenceState)` update, no matter which rooms the user shares
+    /// with us. The stream ends once the `Client` is dropped.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use futures::{executor::block_on, StreamExt};
+    /// # use matrix_sdk::Client;
+    /// # use url::Url;
+    /// # block_on(async {
+    /// # let homeserver = Url::parse("http://localhost:8080").unwrap();
+    /// # let client = Client::new(homeserver, None).unwrap();
+    /// let mut presence = client.presence_stream().await;
+    ///
+    /// while let Some((user_id, state)) = presence.next().await {
+    ///     println!("{} is now {:?}", user_id, state);
+    /// }
+    /// # })
+    /// ```
+    pub async fn presence_stream(&self) -> impl Stream<Item = (UserId, PresenceState)> + Unpin {
+        let (sender, receiver) = unbounded();
+        self.presence_senders.lock().await.push(sender);
+        receiver
+    }
+
+    /// Get a stream of the live timeline events of a room.
+    ///
+    /// This is an alternative to implementing `EventEmitter::on_timeline_event()`,
+    /// the stream is fed from the same place. Events of the room history that
+    /// are fetched with the `/messages` endpoint aren't put into the stream.
+    /// The stream ends once the `Client` is dropped.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The id of the room whose events should be streamed.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use std::convert::TryFrom;
+    /// # use futures::{executor::block_on, StreamExt};
+    /// # use matrix_sdk::{Client, identifiers::RoomId};
+    /// # use url::Url;
+    /// # block_on(async {
+    /// # let homeserver = Url::parse("http://localhost:8080").unwrap();
+    /// # let client = Client::new(homeserver, None).unwrap();
+    /// # let room_id = RoomId::try_from("!test:localhost").unwrap();
+    /// let mut events = client.room_events(&room_id).await;
+    ///
+    /// while let Some(event) = events.next().await {
+    ///     println!("New event {:?}", event);
+    /// }
+    /// # })
+    /// ```
+    pub async fn room_events(&self, room_id: &RoomId) -> impl Stream<Item = RoomEvent> + Unpin {
+        let (sender, receiver) = unbounded();
+        self.add_event_sender(EventSender::Room(room_id.clone(), sender))
+            .await;
+        receiver
+    }
+
+    /// Get a stream of the live timeline events of all rooms, together with
+    /// the id of the room the event belongs to.
+    ///
+    /// See `room_events()` for a stream of a single room.
+    pub async fn event_stream(&self) -> impl Stream<Item = (RoomId, RoomEvent)> + Unpin {
+        let (sender, receiver) = unbounded();
+        self.add_event_sender(EventSender::All(sender)).await;
+        receiver
+    }
+
+    async fn add_event_sender(&self, sender: EventSender) {
+        self.event_senders.lock().await.push(sender);
+
+        if !self.event_stream_registered.swap(true, Ordering::SeqCst) {
+            let emitter = EventStreamEmitter(Arc::clone(&self.event_senders));
+            self.base_client.add_event_emitter(Box::new(emitter)).await;
+        }
+    }
+
+    /// Send the presence events of a sync response to the presence streams,
+    /// forgetting the streams that were dropped.
+    async fn send_presence_updates(&self, response: &sync_events::Response) {
+        let mut senders = self.presence_senders.lock().await;
+
+        if senders.is_empty() {
+            return;
+        }
+
+        for event in &response.presence.events {
+            if let Ok(e) = event.deserialize() {
+                let update = (e.sender, e.content.presence);
+                senders.retain(|s| s.unbounded_send(update.clone()).is_ok());
+            }
+        }
+    }
+
+    /// Hand the events we sent that came down the sync stream to the
+    /// `room_send_and_wait()` calls that wait for them.
+    async fn resolve_sent_event_waiters(&self, response: &sync_events::Response) {
+        let mut waiters = self.sent_event_waiters.lock().await;
+
+        if waiters.is_empty() {
+            return;
+        }
+
+        for room in response.rooms.join.values() {
+            for event in &room.timeline.events {
+                let txn_id = serde_json::from_str::<JsonValue>(event.json().get())
+                    .ok()
+                    .and_then(|e| {
+                        e["unsigned"]["transaction_id"]
+                            .as_str()
+                            .map(ToOwned::to_owned)
+                    });
+
+                if let Some(waiter) = txn_id.and_then(|t| waiters.get_mut(&t)) {
+                    *waiter = Some(event.clone());
+                }
+            }
+        }
+    }
+
+    /// Repeatedly call sync to synchronize the client state with the server.
+    ///
+    /// # Arguments
+    ///
+    /// * `sync_settings` - Settings for the sync call. Note that those settings
+    ///     will be only used for the first sync call.
+    ///
+    /// * `callback` - A callback that will be called every time a successful
+    ///     response has been fetched from the server.
+    ///
+    /// # Examples
+    ///
+    /// The following example demonstrates how to sync forever while sending all
+    /// the interesting events through a mpsc channel to another thread e.g. a
+    /// UI thread.
+    ///
+    /// ```compile_fail,E0658
+    /// # use matrix_sdk::events::{
+    /// #     collections::all::RoomEvent,
+    /// #     room::message::{MessageEvent, MessageEventContent, TextMessageEventContent},
+    /// #     EventResult,
+    /// # };
+    /// # use matrix_sdk::Room;
+    /// # use std::sync::{Arc, RwLock};
+    /// # use matrix_sdk::{Client, SyncSettings};
+    /// # use url::Url;
+    /// # use futures::executor::block_on;
+    /// # block_on(async {
+    /// # let homeserver = Url::parse("http://localhost:8080").unwrap();
+    /// # let mut client = Client::new(homeserver, None).unwrap();
+    ///
+    /// use async_std::sync::channel;
+    ///
+    /// let (tx, rx) = channel(100);
+    ///
+    /// let sync_channel = &tx;
+    /// let sync_settings = SyncSettings::new()
+    ///     .timeout(30_000)
+    ///     .unwrap();
+    ///
+    /// client
+    ///     .sync_forever(sync_settings, async move |response| {
+    ///         let channel = sync_channel;
+    ///
+    ///         for (room_id, room) in response.rooms.join {
+    ///             for event in room.timeline.events {
+    ///                 if let EventResult::Ok(e) = event {
+    ///                     channel.send(e).await;
+    ///                 }
+    ///             }
+    ///         }
+    ///     })
+    ///     .await;
+    /// })
+    /// ```
+    #[instrument(skip(callback))]
+    pub async fn sync_forever<C>(
+        &self,
+        sync_settings: SyncSettings,
+        callback: impl Fn(sync_events::Response) -> C + Send,
+    ) where
+        C: Future<Output = ()>,
+    {
+        let mut sync_settings = sync_settings;
+        let mut last_sync_time: Option<Instant> = None;
+        #[cfg(feature = "encryption")]
+        let mut resumed_room_key_shares = false;
+
+        loop {
+            let response = self.sync(sync_settings.clone()).await;
+
+            let response = if let Ok(r) = response {
+                r
+            } else {
+                #[cfg(not(target_arch = "wasm32"))]
+                sleep::new(Duration::from_secs(1)).await;
+
+                continue;
+            };
+
+            #[cfg(feature = "encryption")]
+            {
+                if !resumed_room_key_shares {
+                    match self.resume_room_key_shares().await {
+                        Ok(()) => resumed_room_key_shares = true,
+                        Err(e) => warn!("Error while resuming room key shares {:?}", e),
+                    }
+                }
+
+                for request in self.base_client.outgoing_to_device_requests().await {
+                    let response: Result<send_event_to_device::Response> = self.send(request).await;
+
+                    if let Err(e) = response {
+                        warn!("Error while sending to-device messages {:?}", e);
+                    }
+                }
+
+                if self.base_client.should_upload_keys().await {
+                    let response = self.keys_upload().await;
+
+                    if let Err(e) = response {
+                        warn!("Error while uploading E2EE keys {:?}", e);
+                    }
+                }
+
+                if self.base_client.should_query_keys().await {
+                    let response = self.keys_query().await;
+
+                    if let Err(e) = response {
+                        warn!("Error while querying device keys {:?}", e);
+                    }
+                }
+            }
+
+            callback(response).await;
+
+            if self.url_previews {
+                self.fetch_url_previews().await;
+            }
+
+            if self.avatar_prefetch_budget.is_some() {
+                self.prefetch_member_avatars().await;
+            }
+
+            let now = Instant::now();
+
+            // If the last sync happened less than a second ago, sleep for a
+            // while to not hammer out requests if the server doesn't respect
+            // the sync timeout.
+            #[cfg(not(target_arch = "wasm32"))]
+            {
+                if let Some(t) = last_sync_time {
+                    if now - t <= Duration::from_secs(1) {
+                        sleep::new(Duration::from_secs(1)).await;
+                    }
+                }
+            }
+
+            last_sync_time = Some(now);
+
+            sync_settings = sync_settings
+                .timeout(DEFAULT_SYNC_TIMEOUT)
+                .full_state(false)
+                .token(
+                    self.sync_token()
+                        .await
+                        .expect("No sync token found after initial sync"),
+                );
+        }
+    }
+
+    /// Send a sync request, the response is deserialized on the worker pool
+    /// if one is configured.
+    async fn deserialize_sync_response(
+        &self,
+        response: HttpResponse<Vec<u8>>,
+    ) -> Result<sync_events::Response> {
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            if let Some(pool) = self.base_client.worker_pool() {
+                return Ok(pool
+                    .run_blocking(move || sync_events::Response::try_from(response))
+                    .await?);
+            }
+        }
+
+        Ok(sync_events::Response::try_from(response)?)
+    }
+
+    async fn apply_quirks(&self, path: &str, status: u16, body: Vec<u8>) -> Vec<u8> {
+        if !self.quirks_enabled {
+            return body;
+        }
+
+        let quirks = self.quirks.read().await.unwrap_or_default();
+        quirks.apply(path, status, body)
+    }
+
+    async fn send<Request: Endpoint<ResponseError = crate::api::Error> + std::fmt::Debug>(
+        &self,
+        request: Request,
+    ) -> Result<Request::Response> {
+        self.send_with_timestamp(request, None).await
+    }
+
+    /// Send a request with the `ts` query parameter of the application service
+    /// API set to the given origin timestamp.
+    async fn send_with_timestamp<
+        Request: Endpoint<ResponseError = crate::api::Error> + std::fmt::Debug,
+    >(
+        &self,
+        request: Request,
+        timestamp: Option<SystemTime>,
+    ) -> Result<Request::Response> {
+        let response = self.send_request_with_timestamp(request, timestamp).await?;
+
+        Ok(<Request::Response>::try_from(response)?)
+    }
+
+    /// Send a request to an endpoint that may require user-interactive
+    /// authentication.
+    ///
+    /// If the server requires further authentication stages the returned
+    /// error will contain the `UiaaInfo`, see `Error::uiaa_response()`.
+    async fn send_uiaa<Request: Endpoint<ResponseError = UiaaResponse> + std::fmt::Debug>(
+        &self,
+        request: Request,
+    ) -> Result<Request::Response> {
+        let response = self.send_request(request).await?;
+
+        Ok(<Request::Response>::try_from(response)?)
+    }
+
+    /// Send a request to an endpoint that requires user-interactive
+    /// authentication, completing the stages using the `UiaaHandler`.
+    ///
+    /// The request is rebuilt with the new authentication data for every
+    /// stage, if no handler is set, the handler gives up or it didn't finish
+    /// after `UIAA_MAX_STAGES` stages the error containing the `UiaaInfo` is
+    /// returned.
+    ///
+    /// # Arguments
+    ///
+    /// * `build_request` - Creates the request for the given authentication
+    /// data.
+    ///
+    /// * `auth_data` - The authentication data for the first request.
+    async fn send_uiaa_with_handler<Request, F>(
+        &self,
+        build_request: F,
+        auth_data: Option<AuthData>,
+    ) -> Result<Request::Response>
+    where
+        Request: Endpoint<ResponseError = UiaaResponse> + std::fmt::Debug,
+        F: Fn(Option<AuthData>) -> Request,
+    {
+        self.retry_with_uiaa_handler(
+            |auth_data| self.send_uiaa(build_request(auth_data)),
+            auth_data,
+        )
+        .await
+    }
+
+    /// Run a request that may require user-interactive authentication until
+    /// it succeeds, completing the stages using the `UiaaHandler`.
+    ///
+    /// This is the stage loop of `send_uiaa_with_handler()` for requests that
+    /// can't be expressed as an `Endpoint`, `send` needs to fail with an error
+    /// that contains the `UiaaInfo` if a further stage is required.
+    ///
+    /// # Arguments
+    ///
+    /// * `send` - Sends the request with the given authentication data.
+    ///
+    /// * `auth_data` - The authentication data for the first request.
+    async fn retry_with_uiaa_handler<T, F, Fut>(
+        &self,
+        send: F,
+        mut auth_data: Option<AuthData>,
+    ) -> Result<T>
+    where
+        F: Fn(Option<AuthData>) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let mut stages = 0;
+
+        loop {
+            let error = match send(auth_data.take()).await {
+                Ok(response) => return Ok(response),
+                Err(e) => e,
+            };
+
+            let info = match error.uiaa_response() {
+                Some(info) => info,
+                None => return Err(error),
+            };
+
+            if stages == UIAA_MAX_STAGES {
+                warn!(
+                    "Giving up on user-interactive authentication after {} stages",
+                    stages
+                );
+                return Err(error);
+            }
+            stages += 1;
+
+            // Don't hold the lock while the handler runs, it may want to
+            // replace or remove itself.
+            let handler = self.uiaa_handler.read().await.clone();
+
+            let stage = match handler {
+                Some(handler) => handler.next_stage(info).await,
+                None => None,
+            };
+
+            match stage {
+                Some(stage) => {
+                    trace!("Retrying request with the {} stage", stage.kind());
+                    let session = info.session.clone();
+                    auth_data = Some(stage.into_auth_data(session, self.user_id().await.as_ref()));
+                }
+                None => return Err(error),
+            }
+        }
+    }
+
+    async fn send_request<Request: Endpoint + std::fmt::Debug>(
+        &self,
+        request: Request,
+    ) -> Result<HttpResponse<Vec<u8>>> {
+        self.send_request_with_timestamp(request, None).await
+    }
+
+    async fn send_request_with_timestamp<Request: Endpoint + std::fmt::Debug>(
+        &self,
+        request: Request,
+        timestamp: Option<SystemTime>,
+    ) -> Result<HttpResponse<Vec<u8>>> {
+        let request: http::Request<Vec<u8>> = request.try_into()?;
+        let url = request.uri();
+        let path_and_query = url.path_and_query().unwrap();
+        let mut url = self.homeserver.clone();
+
+        url.set_path(path_and_query.path());
+        url.set_query(path_and_query.query());
+
+        if let Some(timestamp) = timestamp {
+            let millis = timestamp
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis();
+            url.query_pairs_mut().append_pair("ts", &millis.to_string());
+        }
+
+        let body = if Request::METADATA.method == HttpMethod::GET {
+            None
+        } else {
+            let content_type = request
+                .headers()
+                .get(CONTENT_TYPE)
+                .cloned()
+                .unwrap_or_else(|| HeaderValue::from_static("application/json"));
+
+            Some((request.body().clone(), content_type))
+        };
+
+        self.send_http_request(
+            Request::METADATA.method,
+            url,
+            body,
+            Request::METADATA.requires_authentication,
+        )
+        .await
+    }
+
+    /// Send a request to an endpoint that isn't supported by the typed
+    /// requests.
+    ///
+    /// The request takes the same path as the typed requests, the access
+    /// token is attached and the quirks of the homeserver are applied to the
+    /// response. Use `raw_response()` to get the JSON body of the response,
+    /// or the Matrix error the server returned.
+    ///
+    /// # Arguments
+    ///
+    /// * `method` - The HTTP method of the request.
+    ///
+    /// * `url` - The URL of the endpoint, including the query string.
+    ///
+    /// * `body` - The JSON body of the request, if it has one.
+    async fn send_raw_request(
+        &self,
+        method: HttpMethod,
+        url: Url,
+        body: Option<&JsonValue>,
+    ) -> Result<HttpResponse<Vec<u8>>> {
+        let body = match body {
+            Some(body) => Some((
+                serde_json::to_vec(body)?,
+                HeaderValue::from_static("application/json"),
+            )),
+            None => None,
+        };
+
+        self.send_http_request(method, url, body, true).await
+    }
+
+    /// Send a request to an endpoint that isn't supported by the typed
+    /// requests and deserialize the JSON body of the response.
+    ///
+    /// This is a shorthand for `send_raw_request()` followed by
+    /// `raw_response()`.
+    async fn send_raw<T: DeserializeOwned>(
+        &self,
+        method: HttpMethod,
+        url: Url,
+        body: Option<&JsonValue>,
+    ) -> Result<T> {
+        raw_response(self.send_raw_request(method, url, body).await?)
+    }
+
+    /// Send a HTTP request to the homeserver, the access token is attached if
+    /// the endpoint requires authentication.
+    async fn send_http_request(
+        &self,
+        method: HttpMethod,
+        url: Url,
+        body: Option<(Vec<u8>, HeaderValue)>,
+        requires_authentication: bool,
+    ) -> Result<HttpResponse<Vec<u8>>> {
+        trace!("Doing request {:?}", url);
+
+        let path = url.path().to_owned();
+
+        let request_builder = match method {
+            HttpMethod::GET => self.http_client.get(url),
+            HttpMethod::POST => self.http_client.post(url),
+            HttpMethod::PUT => self.http_client.put(url),
+            HttpMethod::DELETE => self.http_client.delete(url),
+            _ => panic!("Unsuported method"),
+        };
+
+        let request_builder = match body {
+            Some((body, content_type)) => request_builder
+                .body(body)
+                .header(CONTENT_TYPE, content_type),
+            None => request_builder,
+        };
+
+        let request_builder = if requires_authentication {
+            let session = self.base_client.session().read().await;
+
+            if let Some(session) = session.as_ref() {
+                let header_value = format!("Bearer {}", &session.access_token);
+                request_builder.header(AUTHORIZATION, header_value)
+            } else {
+                return Err(Error::AuthenticationRequired);
+            }
+        } else {
+            request_builder
+        };
+        let mut response = request_builder.send().await?;
+
+        trace!("Got response: {:?}", response);
+
+        let status = response.status();
+        let mut http_builder = HttpResponse::builder().status(status);
+        let headers = http_builder.headers_mut().unwrap();
+
+        for (k, v) in response.headers_mut().drain() {
+            if let Some(key) = k {
+                headers.insert(key, v);
+            }
+        }
+        let body = response.bytes().await?.as_ref().to_owned();
+        let body = self.apply_quirks(&path, status.as_u16(), body).await;
+
+        Ok(http_builder.body(body).unwrap())
+    }
+
+    /// Send a room message to the homeserver.
+    ///
+    /// Returns the parsed response from the server.
+    ///
+    /// If the encryption feature is enabled this method will transparently
+    /// encrypt the room message if the given room is encrypted.
+    ///
+    /// A local echo of the message is added to the `Room` right away, see
+    /// `Room::pending_events()`. It is removed once the message comes down
+    /// the sync stream or if sending it fails.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` -  The id of the room that should receive the message.
+    ///
+    /// * `content` - The content of the message event.
+    ///
+    /// * `txn_id` - A unique `Uuid` that can be attached to a `MessageEvent` held
+    /// in it's unsigned field as `transaction_id`. If not given one is created for the
+    /// message.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use matrix_sdk::Room;
+    /// # use std::sync::{Arc, RwLock};
+    /// # use matrix_sdk::{Client, SyncSettings};
+    /// # use url::Url;
+    /// # use futures::executor::block_on;
+    /// # use ruma_identifiers::RoomId;
+    /// # use std::convert::TryFrom;
+    /// use matrix_sdk::events::room::message::{MessageEventContent, TextMessageEventContent};
+    /// # block_on(async {
+    /// # let homeserver = Url::parse("http://localhost:8080").unwrap();
+    /// # let mut client = Client::new(homeserver, None).unwrap();
+    /// # let room_id = RoomId::try_from("!test:localhost").unwrap();
+    /// use matrix_sdk_common::uuid::Uuid;
+    ///
+    /// let content = MessageEventContent::Text(TextMessageEventContent {
+    ///     body: "Hello world".to_owned(),
+    ///     format: None,
+    ///     formatted_body: None,
+    ///     relates_to: None,
+    /// });
+    /// let txn_id = Uuid::new_v4();
+    /// client.room_send(&room_id, content, Some(txn_id)).await.unwrap();
+    /// })
+    /// ```
+    pub async fn room_send(
+        &self,
+        room_id: &RoomId,
+        content: MessageEventContent,
+        txn_id: Option<Uuid>,
+    ) -> Result<create_message_event::Response> {
+        let txn_id = txn_id.unwrap_or_else(Uuid::new_v4).to_string();
+        let content = self.transform_content(room_id, content).await;
+
+        self.base_client
+            .add_local_echo(room_id, &txn_id, content.clone(), SendState::Sending)
+            .await;
+
+        match self
+            .room_send_with_txn_id(room_id, content, txn_id.clone())
+            .await
+        {
+            Ok(response) => {
+                if let Some(event_id) = &response.event_id {
+                    self.base_client
+                        .update_local_echo(room_id, &txn_id, SendState::Sent(event_id.clone()))
+                        .await;
+                }
+                Ok(response)
+            }
+            Err(e) => {
+                self.base_client.remove_local_echo(room_id, &txn_id).await;
+                Err(e)
+            }
+        }
+    }
+
+    /// Send a room message and wait until it comes back down the sync stream.
+    ///
+    /// Returns the event as the server sent it to us, with the event id and
+    /// the unsigned data the server assigned, decrypted if the room is
+    /// encrypted. This is useful for bots that want to react to or pin the
+    /// message they just sent.
+    ///
+    /// The client needs to be synced while waiting, e.g. by calling
+    /// `sync_forever()` in another task, otherwise this method fails with
+    /// `Error::EchoTimeout` once the timeout is reached.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The id of the room that should receive the message.
+    ///
+    /// * `content` - The content of the message event.
+    ///
+    /// * `txn_id` - A unique `Uuid` that can be attached to a `MessageEvent`
+    /// held in its unsigned field as `transaction_id`. If not given one is
+    /// created for the message.
+    ///
+    /// * `timeout` - How long to wait for the message to come down the sync
+    /// stream after it was sent.
+    pub async fn room_send_and_wait(
+        &self,
+        room_id: &RoomId,
+        content: MessageEventContent,
+        txn_id: Option<Uuid>,
+        timeout: Duration,
+    ) -> Result<EventJson<RoomEvent>> {
+        let txn_id = txn_id.unwrap_or_else(Uuid::new_v4);
+        let key = txn_id.to_string();
+
+        // Wait for the transaction id before sending, the event might come
+        // down the sync stream before the send request returns.
+        self.sent_event_waiters
+            .lock()
+            .await
+            .insert(key.clone(), None);
+
+        let response = self.room_send(room_id, content, Some(txn_id)).await;
+        let start = Instant::now();
+
+        let event = loop {
+            let mut waiters = self.sent_event_waiters.lock().await;
+
+            if let Some(Some(_)) = waiters.get(&key) {
+                break waiters.remove(&key).flatten();
+            }
+
+            if response.is_err() || start.elapsed() >= timeout {
+                waiters.remove(&key);
+                break None;
+            }
+
+            drop(waiters);
+            sleep::new(ECHO_POLL_INTERVAL).await;
+        };
+
+        response?;
+        event.ok_or(Error::EchoTimeout)
+    }
+
+    /// Send a room message using the given transaction id, encrypting it
+    /// first if the room is encrypted.
+    async fn room_send_with_txn_id(
+        &self,
+        room_id: &RoomId,
+        content: MessageEventContent,
+        txn_id: String,
+    ) -> Result<create_message_event::Response> {
+        let content = serde_json::to_value(&content)?;
+
+        self.room_send_raw(room_id, EventType::RoomMessage, content, txn_id, None)
+            .await
+    }
+
+    /// Send a room message with the given origin timestamp.
+    ///
+    /// This uses the `ts` query parameter of the application service API so
+    /// bridges can preserve the original timestamp of messages when they
+    /// relay history, homeservers ignore it for clients that aren't
+    /// application services. Unlike `room_send()` no local echo is added for
+    /// the message.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` -  The id of the room that should receive the message.
+    ///
+    /// * `content` - The content of the message event.
+    ///
+    /// * `txn_id` - A unique `Uuid` for the transaction, if not given one is
+    /// created for the message.
+    ///
+    /// * `timestamp` - The origin timestamp the message should have.
+    pub async fn room_send_with_timestamp(
+        &self,
+        room_id: &RoomId,
+        content: MessageEventContent,
+        txn_id: Option<Uuid>,
+        timestamp: SystemTime,
+    ) -> Result<create_message_event::Response> {
+        let content = self.transform_content(room_id, content).await;
+        let content = serde_json::to_value(&content)?;
+        let txn_id = txn_id.unwrap_or_else(Uuid::new_v4).to_string();
+
+        self.room_send_raw(
+            room_id,
+            EventType::RoomMessage,
+            content,
+            txn_id,
+            Some(timestamp),
+        )
+        .await
+    }
+
+    /// Check the local state of the room to find out if our own user is
+    /// allowed to perform the action.
+    ///
+    /// Rooms we don't know about are left for the server to decide.
+    async fn check_permission(&self, room_id: &RoomId, action: &PowerAction) -> Result<()> {
+        if let Some(room) = self.get_joined_room(room_id).await {
+            room.read().await.check_permission(action)?;
+        } else if self.get_invited_room(room_id).await.is_some()
+            || self.get_left_room(room_id).await.is_some()
+        {
+            return Err(PermissionError::NotJoined.into());
+        }
+
+        Ok(())
+    }
+
+    /// Send the raw JSON content of a room event to a room, encrypting it
+    /// first if the room is encrypted.
+    ///
+    /// The `timestamp` is sent as the `ts` query parameter of the application
+    /// service API if given.
+    async fn room_send_raw(
+        &self,
+        room_id: &RoomId,
+        event_type: EventType,
+        content: JsonValue,
+        txn_id: String,
+        timestamp: Option<SystemTime>,
+    ) -> Result<create_message_event::Response> {
+        self.check_permission(room_id, &PowerAction::SendMessage(event_type.clone()))
+            .await?;
+
+        #[allow(unused_mut)]
+        let mut event_type = event_type;
+        #[allow(unused_mut)]
+        let mut raw_content = serde_json::value::to_raw_value(&content)?;
+
+        #[cfg(feature = "encryption")]
+        {
+            if self.is_room_encrypted(room_id).await {
+                self.prepare_group_session(room_id).await?;
+
+                raw_content = serde_json::value::to_raw_value(
+                    &self
+                        .base_client
+                        .encrypt_raw(room_id, event_type, content)
+                        .await?,
+                )?;
+                event_type = EventType::RoomEncrypted;
+            }
+        }
+
+        let request = create_message_event::Request {
+            room_id: room_id.clone(),
+            event_type,
+            txn_id,
+            data: raw_content,
+        };
+
+        let response = self.send_with_timestamp(request, timestamp).await?;
+        Ok(response)
+    }
+
+    /// Edit a message that was sent earlier.
+    ///
+    /// The edit is sent as a new `m.room.message` event that replaces the
+    /// content of the original message. Clients that don't support edits
+    /// show the body of the new content prefixed with `*` as a fallback.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` -  The id of the room the message was sent to.
+    ///
+    /// * `event_id` - The id of the message that should be edited.
+    ///
+    /// * `new_content` - The new content of the message.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use std::convert::TryFrom;
+    /// # use matrix_sdk::{Client, identifiers::{EventId, RoomId}};
+    /// # use matrix_sdk::events::room::message::{MessageEventContent, TextMessageEventContent};
+    /// # use futures::executor::block_on;
+    /// # use url::Url;
+    /// # block_on(async {
+    /// # let homeserver = Url::parse("http://localhost:8080").unwrap();
+    /// # let client = Client::new(homeserver, None).unwrap();
+    /// # let room_id = RoomId::try_from("!test:localhost").unwrap();
+    /// # let event_id = EventId::try_from("$xxxxxx:localhost").unwrap();
+    /// let content = MessageEventContent::Text(TextMessageEventContent {
+    ///     body: "Hello world, fixed".to_owned(),
+    ///     format: None,
+    ///     formatted_body: None,
+    ///     relates_to: None,
+    /// });
+    ///
+    /// client.edit_message(&room_id, &event_id, content).await.unwrap();
+    /// # })
+    /// ```
+    pub async fn edit_message(
+        &self,
+        room_id: &RoomId,
+        event_id: &EventId,
+        new_content: MessageEventContent,
+    ) -> Result<create_message_event::Response> {
+        let new_content = self.transform_content(room_id, new_content).await;
+        let new_content = serde_json::to_value(&new_content)?;
+        let mut content = new_content.clone();
+
+        for key in &["body", "formatted_body"] {
+            if let Some(text) = new_content.get(*key).and_then(JsonValue::as_str) {
+                content[*key] = JsonValue::String(format!("* {}", text));
+            }
+        }
+
+        content["m.new_content"] = new_content;
+        content["m.relates_to"] = serde_json::json!({
+            "rel_type": "m.replace",
+            "event_id": event_id,
+        });
+
+        let txn_id = Uuid::new_v4().to_string();
+        self.room_send_raw(room_id, EventType::RoomMessage, content, txn_id, None)
+            .await
+    }
+
+    /// React to an event of a room.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` -  The id of the room the event belongs to.
+    ///
+    /// * `event_id` - The id of the event that should be reacted to.
+    ///
+    /// * `key` - The key of the reaction, usually an emoji.
+    pub async fn react(
+        &self,
+        room_id: &RoomId,
+        event_id: &EventId,
+        key: &str,
+    ) -> Result<create_message_event::Response> {
+        let content = serde_json::json!({
+            "m.relates_to": {
+                "rel_type": "m.annotation",
+                "event_id": event_id,
+                "key": key,
+            }
+        });
+
+        let txn_id = Uuid::new_v4().to_string();
+        self.room_send_raw(
+            room_id,
+            EventType::Custom("m.reaction".to_owned()),
+            content,
+            txn_id,
+            None,
+        )
+        .await
+    }
+
+    /// Remove a reaction of our own user by redacting it.
+    ///
+    /// The reaction is looked up in the reactions the room received, returns
+    /// `None` if our own user didn't react to the event with the given key.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` -  The id of the room the event belongs to.
+    ///
+    /// * `event_id` - The id of the event that was reacted to.
+    ///
+    /// * `key` - The key of the reaction that should be removed.
+    pub async fn redact_reaction(
+        &self,
+        room_id: &RoomId,
+        event_id: &EventId,
+        key: &str,
+    ) -> Result<Option<redact_event::Response>> {
+        let room = match self.get_joined_room(room_id).await {
+            Some(r) => r,
+            None => return Ok(None),
+        };
+
+        let reaction_id = {
+            let room = room.read().await;
+            room.reaction_events(event_id)
+                .iter()
+                .find(|r| r.key == key && r.sender == room.own_user_id)
+                .map(|r| r.event_id.clone())
+        };
+
+        let reaction_id = match reaction_id {
+            Some(id) => id,
+            None => return Ok(None),
+        };
+
+        Ok(Some(self.redact(room_id, &reaction_id, None).await?))
+    }
+
+    /// Redact an event, removing its content from the room.
+    ///
+    /// The redaction is applied to the stored state of the room once the
+    /// `m.room.redaction` event comes down the sync stream.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The id of the room the event belongs to.
+    ///
+    /// * `event_id` - The id of the event that should be redacted.
+    ///
+    /// * `reason` - The reason why the event is redacted.
+    pub async fn redact(
+        &self,
+        room_id: &RoomId,
+        event_id: &EventId,
+        reason: Option<String>,
+    ) -> Result<redact_event::Response> {
+        let request = redact_event::Request {
+            room_id: room_id.clone(),
+            event_id: event_id.clone(),
+            txn_id: Uuid::new_v4().to_string(),
+            reason,
+        };
+
+        self.send(request).await
+    }
+
+    /// Redact many events of a room, e.g. to clean up after a spam wave.
+    ///
+    /// The redactions are sent one after the other with a pause in between,
+    /// if the server rate limits us the redaction is retried later on and the
+    /// pause is made longer. A failed redaction doesn't stop the others from
+    /// being sent.
+    ///
+    /// Returns the result of every redaction together with the id of the
+    /// event it belongs to, in the order of the given events.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The id of the room the events belong to.
+    ///
+    /// * `event_ids` - The ids of the events that should be redacted.
+    ///
+    /// * `reason` - The reason why the events are redacted.
+    pub async fn redact_events(
+        &self,
+        room_id: &RoomId,
+        event_ids: &[EventId],
+        reason: Option<String>,
+    ) -> Vec<(EventId, Result<redact_event::Response>)> {
+        let mut results = Vec::with_capacity(event_ids.len());
+        let mut interval = REDACTION_INTERVAL;
+
+        for (i, event_id) in event_ids.iter().enumerate() {
+            if i > 0 {
+                sleep::new(interval).await;
+            }
+
+            let mut retries = 0;
+
+            let result = loop {
+                match self.redact(room_id, event_id, reason.clone()).await {
+                    Err(e) if e.is_rate_limited() && retries < REDACTION_MAX_RETRIES => {
+                        warn!("Redacting {} was rate limited, retrying", event_id);
+                        interval *= 2;
+                        retries += 1;
+                        sleep::new(interval).await;
+                    }
+                    result => break result,
+                }
+            };
+
+            results.push((event_id.clone(), result));
+        }
+
+        results
+    }
+
+    /// Import a sequence of historical events into a room, e.g. when a bridge
+    /// backfills the history of a bridged chat.
+    ///
+    /// The events are sorted by their timestamp and keep it as their origin
+    /// timestamp, see `room_send_with_timestamp()`. This only works for
+    /// application services, see `Client::new_appservice()`.
+    ///
+    /// If `prev_event_id` is given, the events are all message events and the
+    /// homeserver supports the MSC2716 `batch_send` endpoint, the events are
+    /// inserted after that event in batches. Otherwise they are sent one by
+    /// one, the import is throttled and slowed down further every time the
+    /// server rate limits us.
+    ///
+    /// Returns the ids of the imported events in the order they were sent,
+    /// `None` if the server didn't return the id of an event. The import
+    /// stops at the first event that can't be sent.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The id of the room the events should be imported into.
+    ///
+    /// * `events` - The events that should be imported.
+    ///
+    /// * `prev_event_id` - The event the history should be inserted after if
+    /// batch sending is supported.
+    ///
+    /// * `progress` - Called with the number of imported events and the total
+    /// number of events after every event or batch that was sent.
+    pub async fn import_history(
+        &self,
+        room_id: &RoomId,
+        mut events: Vec<HistoricalEvent>,
+        prev_event_id: Option<&EventId>,
+        mut progress: impl FnMut(usize, usize),
+    ) -> Result<Vec<Option<EventId>>> {
+        events.sort_by_key(|e| e.timestamp);
+
+        let total = events.len();
+        let mut event_ids = Vec::with_capacity(total);
+
+        if let Some(prev_event_id) = prev_event_id {
+            if !events.iter().any(HistoricalEvent::is_state) && self.supports_batch_send().await {
+                // Every batch is inserted before the batch that was sent
+                // previously, so the batches are sent from the newest to the
+                // oldest one.
+                let mut batches = Vec::new();
+                let mut batch_id = None;
+
+                for (i, chunk) in events.rchunks(HISTORY_BATCH_SIZE).enumerate() {
+                    if i > 0 {
+                        sleep::new(HISTORY_IMPORT_INTERVAL).await;
+                    }
+
+                    let response = self
+                        .batch_send(room_id, prev_event_id, batch_id.as_deref(), chunk)
+                        .await?;
+
+                    batch_id = Some(response.next_batch_id);
+                    batches.push(response.event_ids);
+                    progress(batches.iter().map(Vec::len).sum(), total);
+                }
+
+                event_ids.extend(batches.into_iter().rev().flatten().map(Some));
+                return Ok(event_ids);
+            }
+        }
+
+        let mut interval = HISTORY_IMPORT_INTERVAL;
+
+        for (i, event) in events.iter().enumerate() {
+            if i > 0 {
+                sleep::new(interval).await;
+            }
+
+            let mut retries = 0;
+
+            let event_id = loop {
+                match self.send_historical_event(room_id, event).await {
+                    Err(e) if e.is_rate_limited() && retries < HISTORY_IMPORT_MAX_RETRIES => {
+                        warn!(
+                            "Importing an event into {} was rate limited, retrying",
+                            room_id
+                        );
+                        interval *= 2;
+                        retries += 1;
+                        sleep::new(interval).await;
+                    }
+                    result => break result?,
+                }
+            };
+
+            event_ids.push(event_id);
+            progress(i + 1, total);
+        }
+
+        Ok(event_ids)
+    }
+
+    /// Send a single historical event with its origin timestamp.
+    async fn send_historical_event(
+        &self,
+        room_id: &RoomId,
+        event: &HistoricalEvent,
+    ) -> Result<Option<EventId>> {
+        match &event.state_key {
+            Some(state_key) => self
+                .send_state_event_at(
+                    room_id,
+                    &event.event_type,
+                    state_key,
+                    &event.content,
+                    Some(event.timestamp),
+                )
+                .await
+                .map(Some),
+            None => {
+                let txn_id = Uuid::new_v4().to_string();
+                let response = self
+                    .room_send_raw(
+                        room_id,
+                        EventType::from(event.event_type.as_str()),
+                        event.content.clone(),
+                        txn_id,
+                        Some(event.timestamp),
+                    )
+                    .await?;
+
+                Ok(response.event_id)
+            }
+        }
+    }
+
+    /// Does the homeserver support the MSC2716 `batch_send` endpoint.
+    ///
+    /// The supported versions are only fetched once, a failed request is
+    /// retried the next time.
+    async fn supports_batch_send(&self) -> bool {
+        if let Some(supported) = *self.batch_send_supported.read().await {
+            return supported;
+        }
+
+        let mut request_url = self.homeserver.clone();
+        request_url.set_path("/_matrix/client/versions");
+
+        let versions: JsonValue = match self
+            .send_http_request(HttpMethod::GET, request_url, None, false)
+            .await
+            .and_then(raw_response)
+        {
+            Ok(versions) => versions,
+            Err(_) => return false,
+        };
+
+        let supported = versions["unstable_features"]["org.matrix.msc2716"]
+            .as_bool()
+            .unwrap_or(false);
+        *self.batch_send_supported.write().await = Some(supported);
+
+        supported
+    }
+
+    /// Insert a batch of historical message events after the given event
+    /// using the MSC2716 `batch_send` endpoint.
+    ///
+    /// The endpoint isn't supported by the typed requests, so the request is
+    /// sent by hand.
+    async fn batch_send(
+        &self,
+        room_id: &RoomId,
+        prev_event_id: &EventId,
+        batch_id: Option<&str>,
+        events: &[HistoricalEvent],
+    ) -> Result<BatchSendResponse> {
+        let sender = self.user_id().await.ok_or(Error::AuthenticationRequired)?;
+
+        let mut request_url = self.homeserver.clone();
+        request_url.set_path(&format!(
+            "/_matrix/client/unstable/org.matrix.msc2716/rooms/{}/batch_send",
+            room_id
+        ));
+
+        {
+            let mut query = request_url.query_pairs_mut();
+            query.append_pair("prev_event_id", &prev_event_id.to_string());
+            if let Some(batch_id) = batch_id {
+                query.append_pair("batch_id", batch_id);
+            }
+        }
+
+        let body = serde_json::to_value(BatchSendRequest::new(&sender, events))?;
+
+        self.send_raw(HttpMethod::POST, request_url, Some(&body))
+            .await
+    }
+
+    /// Send a message to a room, encrypting it first if the room is
+    /// encrypted.
+    ///
+    /// This is a shorthand for `room_send()` that creates a new transaction
+    /// id for the message.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` -  The id of the room that should receive the message.
+    ///
+    /// * `content` - The content of the message event.
+    pub async fn send_message(
+        &self,
+        room_id: &RoomId,
+        content: MessageEventContent,
+    ) -> Result<create_message_event::Response> {
+        self.room_send(room_id, content, None).await
+    }
+
+    /// Put a message into the send queue.
+    ///
+    /// Returns the transaction id of the message. The message is sent once
+    /// `send_queued_messages()` is called, the messages of a room are sent in
+    /// the order they were queued in. If a `StateStore` is set the queue is
+    /// persisted and survives restarts.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` -  The id of the room that should receive the message.
+    ///
+    /// * `content` - The content of the message event.
+    pub async fn queue_message(
+        &self,
+        room_id: &RoomId,
+        content: MessageEventContent,
+    ) -> Result<String> {
+        let txn_id = Uuid::new_v4().to_string();
+        let content = self.transform_content(room_id, content).await;
+        self.base_client
+            .queue_message(room_id, &txn_id, content)
+            .await?;
+        self.send_queue_update(&txn_id, SendState::Queued).await;
+
+        Ok(txn_id)
+    }
+
+    /// Get the messages of the send queue that weren't sent yet.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - Only return the messages for this room if given.
+    pub async fn queued_messages(&self, room_id: Option<&RoomId>) -> Vec<QueuedMessage> {
+        self.base_client.queued_messages(room_id).await
+    }
+
+    /// Get a stream of the state changes of the messages in the send queue.
+    ///
+    /// Every time a message is queued, starts being sent, was sent or failed
+    /// to be sent the transaction id of the message and its new state are
+    /// put into the stream.
+    pub async fn send_queue_updates(&self) -> impl Stream<Item = (String, SendState)> + Unpin {
+        let (sender, receiver) = unbounded();
+        self.send_queue_senders.lock().await.push(sender);
+        receiver
+    }
+
+    /// Send the messages of the send queue.
+    ///
+    /// Transient failures, e.g. network errors or rate limiting, are retried
+    /// with an exponential backoff. If the message still can't be sent it
+    /// stays in the queue and the error is returned, call this method again
+    /// once the server is reachable again.
+    ///
+    /// Messages that the server refuses are marked as failed and hold back
+    /// the later messages for the same room until they are retried using
+    /// `retry_queued_message()` or removed using `cancel_queued_message()`.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use matrix_sdk::{Client, SyncSettings};
+    /// # use url::Url;
+    /// # let homeserver = Url::parse("http://localhost:8080").unwrap();
+    /// # let client = Client::new(homeserver, None).unwrap();
+    /// let queue_client = client.clone();
+    ///
+    /// tokio::spawn(async move {
+    ///     queue_client.send_queued_messages().await
+    /// });
+    /// ```
+    pub async fn send_queued_messages(&self) -> Result<()> {
+        while let Some(message) = self.base_client.next_queued_message().await? {
+            let QueuedMessage {
+                room_id,
+                txn_id,
+                content,
+                ..
+            } = message;
+
+            self.send_queue_update(&txn_id, SendState::Sending).await;
+
+            let mut retries = 0;
+            let mut backoff = SEND_QUEUE_BACKOFF;
+
+            let state = loop {
+                match self
+                    .room_send_with_txn_id(&room_id, content.clone(), txn_id.clone())
+                    .await
+                {
+                    Ok(response) => match response.event_id {
+                        Some(event_id) => break SendState::Sent(event_id),
+                        None => {
+                            break SendState::Failed(
+                                "the server didn't return an event id".to_owned(),
+                            )
+                        }
+                    },
+                    Err(e) if e.is_transient() && retries < SEND_QUEUE_MAX_RETRIES => {
+                        warn!("Sending queued message {} failed, retrying: {}", txn_id, e);
+                        sleep::new(backoff).await;
+                        retries += 1;
+                        backoff *= 2;
+                    }
+                    Err(e) if e.is_transient() => {
+                        self.base_client
+                            .set_queued_message_state(&txn_id, SendState::Queued)
+                            .await?;
+                        self.send_queue_update(&txn_id, SendState::Queued).await;
+                        return Err(e);
+                    }
+                    Err(e) => break SendState::Failed(e.to_string()),
+                }
+            };
+
+            self.base_client
+                .set_queued_message_state(&txn_id, state.clone())
+                .await?;
+            self.send_queue_update(&txn_id, state).await;
+        }
+
+        Ok(())
+    }
+
+    /// Send a message that failed to be sent again.
+    ///
+    /// The message is put back into the queue at its old position, it's sent
+    /// with the next call to `send_queued_messages()`.
+    ///
+    /// # Arguments
+    ///
+    /// * `txn_id` - The transaction id of the failed message.
+    pub async fn retry_queued_message(&self, txn_id: &str) -> Result<()> {
+        self.base_client
+            .set_queued_message_state(txn_id, SendState::Queued)
+            .await?;
+        self.send_queue_update(txn_id, SendState::Queued).await;
+
+        Ok(())
+    }
+
+    /// Remove a message from the send queue without sending it.
+    ///
+    /// Returns the removed message, `None` if no such message is queued or if
+    /// the message is being sent right now.
+    ///
+    /// # Arguments
+    ///
+    /// * `txn_id` - The transaction id of the message.
+    pub async fn cancel_queued_message(&self, txn_id: &str) -> Result<Option<QueuedMessage>> {
+        self.base_client.remove_queued_message(txn_id).await
+    }
+
+    /// Send a state change of a queued message to the send queue streams,
+    /// forgetting the streams that were dropped.
+    async fn send_queue_update(&self, txn_id: &str, state: SendState) {
+        self.send_queue_senders
+            .lock()
+            .await
+            .retain(|s| s.unbounded_send((txn_id.to_owned(), state.clone())).is_ok());
+    }
+
+    /// Make sure that every member of the room has received the outbound
+    /// group session of the room so a message can be encrypted.
+    ///
+    /// The full member list of the room is fetched first since syncs lazily
+    /// load the members by default, and the devices of the new members are
+    /// queried. Missing Olm sessions are established next. Concurrent calls
+    /// for the same room wait for each other so the group session is only
+    /// shared once.
+    #[cfg(feature = "encryption")]
+    async fn prepare_group_session(&self, room_id: &RoomId) -> Result<()> {
+        let lock = Arc::clone(
+            self.group_session_locks
+                .lock()
+                .await
+                .entry(room_id.clone())
+                .or_insert_with(|| Arc::new(Mutex::new(()))),
+        );
+        let _guard = lock.lock().await;
+
+        self.resume_room_key_shares().await?;
+
+        if self.ensure_members(room_id).await? && self.base_client.should_query_keys().await {
+            self.keys_query().await?;
+        }
+
+        let missing_sessions = match self.base_client.get_joined_room(room_id).await {
+            Some(room) => {
+                let room = room.read().await;
+                let users = room.members.keys();
+                self.base_client.get_missing_sessions(users).await?
+            }
+            None => BTreeMap::new(),
+        };
+
+        if !missing_sessions.is_empty() {
+            self.claim_one_time_keys(missing_sessions).await?;
+        }
+
+        if self.base_client.should_share_group_session(room_id).await {
+            self.share_group_session(room_id).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Send an attachment to a room.
+    ///
+    /// The attachment is uploaded to the media repository and a message
+    /// pointing to it is sent to the room. The message type is picked using
+    /// the content type, `m.image`, `m.video` and `m.audio` are used for the
+    /// respective media, `m.file` for everything else.
+    ///
+    /// If the encryption feature is enabled and the room is encrypted the
+    /// attachment is encrypted before it is uploaded and the info that is
+    /// needed to decrypt it is attached to the, also encrypted, message.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The id of the room that should receive the attachment.
+    ///
+    /// * `body` - A textual representation of the attachment, usually the
+    /// file name.
+    ///
+    /// * `content_type` - The content type of the attachment, e.g.
+    /// `image/jpeg`.
+    ///
+    /// * `reader` - A `Reader` that will be used to read the attachment.
+    ///
+    /// * `txn_id` - A unique `Uuid` that can be attached to the message, one
+    /// is created if not given.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use std::fs::File;
+    /// # use std::convert::TryFrom;
+    /// # use matrix_sdk::{Client, identifiers::RoomId};
+    /// # use url::Url;
+    /// # use futures::executor::block_on;
+    /// # block_on(async {
+    /// # let homeserver = Url::parse("http://localhost:8080").unwrap();
+    /// # let client = Client::new(homeserver, None).unwrap();
+    /// # let room_id = RoomId::try_from("!test:localhost").unwrap();
+    /// let mut image = File::open("/home/example/my-cat.jpg").unwrap();
+    ///
+    /// client
+    ///     .send_attachment(&room_id, "my-cat.jpg", "image/jpeg", &mut image, None)
+    ///     .await
+    ///     .unwrap();
+    /// # });
+    /// ```
+    pub async fn send_attachment<R: Read>(
+        &self,
+        room_id: &RoomId,
+        body: &str,
+        content_type: &str,
+        reader: &mut R,
+        txn_id: Option<Uuid>,
+    ) -> Result<create_message_event::Response> {
+        let msgtype = match content_type.split('/').next() {
+            Some("image") => "m.image",
+            Some("video") => "m.video",
+            Some("audio") => "m.audio",
+            _ => "m.file",
+        };
+
+        let mut content = serde_json::json!({
+            "msgtype": msgtype,
+            "body": body,
+        });
+        let mut size = 0;
+
+        #[cfg(feature = "encryption")]
+        {
+            if self.is_room_encrypted(room_id).await {
+                let mut encryptor = AttachmentEncryptor::new(reader);
+                let response = self
+                    .upload_with_progress(&mut encryptor, Some("application/octet-stream"), |p| {
+                        size = p
+                    })
+                    .await?;
+
+                let mut file = serde_json::to_value(encryptor.finish())?;
+                file["url"] = response.content_uri.into();
+                content["file"] = file;
+            }
+        }
+
+        if content.get("file").is_none() {
+            let response = self
+                .upload_with_progress(reader, Some(content_type), |p| size = p)
+                .await?;
+            content["url"] = response.content_uri.into();
+        }
+
+        content["info"] = serde_json::json!({
+            "mimetype": content_type,
+            "size": size,
+        });
+
+        let content: MessageEventContent = serde_json::from_value(content)?;
+
+        self.room_send(room_id, content, txn_id).await
+    }
+
+    /// Set the avatar of a room.
+    ///
+    /// The image is uploaded to the media repository and a thumbnail of it is
+    /// generated by the homeserver, both are referenced in the new
+    /// `m.room.avatar` state event of the room.
+    ///
+    /// Returns the `mxc://` URI of the uploaded image.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The id of the room whose avatar should be set.
+    ///
+    /// * `image` - The data of the image.
+    ///
+    /// * `content_type` - The content type of the image, e.g. `image/png`.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use std::convert::TryFrom;
+    /// # use matrix_sdk::{Client, identifiers::RoomId};
+    /// # use url::Url;
+    /// # use futures::executor::block_on;
+    /// # block_on(async {
+    /// # let homeserver = Url::parse("http://localhost:8080").unwrap();
+    /// # let client = Client::new(homeserver, None).unwrap();
+    /// # let room_id = RoomId::try_from("!test:localhost").unwrap();
+    /// let image = std::fs::read("/home/example/room-avatar.png").unwrap();
+    ///
+    /// let uri = client
+    ///     .set_room_avatar(&room_id, &image, "image/png")
+    ///     .await
+    ///     .unwrap();
+    ///
+    /// println!("Avatar URI: {}", uri);
+    /// # });
+    /// ```
+    pub async fn set_room_avatar(
+        &self,
+        room_id: &RoomId,
+        image: &[u8],
+        content_type: &str,
+    ) -> Result<String> {
+        let mut reader = image;
+        let url = self
+            .upload(&mut reader, Some(content_type))
+            .await?
+            .content_uri;
+        self.base_client.cache_media(&url, image).await?;
+
+        let mut info = serde_json::json!({
+            "mimetype": content_type,
+            "size": image.len(),
+        });
+
+        let size = UInt::from(ROOM_AVATAR_THUMBNAIL_SIZE);
+        match self
+            .get_thumbnail(&url, size, size, Some(get_content_thumbnail::Method::Scale))
+            .await
+        {
+            Ok(thumbnail) => {
+                let thumbnail_type = media::detect_content_type(&thumbnail);
+                let thumbnail_url = self
+                    .upload(&mut thumbnail.as_slice(), Some(thumbnail_type))
+                    .await?
+                    .content_uri;
+
+                info["thumbnail_url"] = thumbnail_url.into();
+                info["thumbnail_info"] = serde_json::json!({
+                    "mimetype": thumbnail_type,
+                    "size": thumbnail.len(),
+                });
+            }
+            Err(e) => warn!("Couldn't generate a thumbnail for the room avatar {}", e),
+        }
+
+        let content = serde_json::json!({
+            "url": url,
+            "info": info,
+        });
+
+        let request = create_state_event_for_key::Request {
+            room_id: room_id.clone(),
+            event_type: EventType::RoomAvatar,
+            state_key: "".to_owned(),
+            data: serde_json::value::to_raw_value(&content)?,
+        };
+
+        let _response: create_state_event_for_key::Response = self.send(request).await?;
+
+        Ok(url)
+    }
+
+    /// Is the given room a joined room that is encrypted.
+    #[cfg(feature = "encryption")]
+    async fn is_room_encrypted(&self, room_id: &RoomId) -> bool {
+        match self.base_client.get_joined_room(room_id).await {
+            Some(r) => r.read().await.is_encrypted(),
+            None => false,
+        }
+    }
+
+    /// Claim one-time keys creating new Olm sessions.
+    ///
+    /// # Arguments
+    ///
+    /// * `users` - The list of user/device pairs that we should claim keys for.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the client isn't logged in, or if no encryption keys need to
+    /// be uploaded.
+    #[cfg(feature = "encryption")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "encryption")))]
+    #[instrument]
+    async fn claim_one_time_keys(
+        &self,
+        one_time_keys: BTreeMap<UserId, BTreeMap<DeviceId, KeyAlgorithm>>,
+    ) -> Result<claim_keys::Response> {
+        let request = claim_keys::Request {
+            timeout: None,
+            one_time_keys,
+        };
+
+        let response = self.send(request).await?;
+        self.base_client
+            .receive_keys_claim_response(&response)
+            .await?;
+        Ok(response)
+    }
+
+    /// Share a group session for a room.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The ID of the room for which we want to share a group
+    /// session.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the client isn't logged in.
+    #[cfg(feature = "encryption")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "encryption")))]
+    #[instrument]
+    async fn share_group_session(&self, room_id: &RoomId) -> Result<()> {
+        let mut requests = self
+            .base_client
+            .share_group_session(room_id)
+            .await
+            .expect("Keys don't need to be uploaded");
+
+        for request in requests.drain(..) {
+            let txn_id = request.txn_id.clone();
+            let _response: send_event_to_device::Response = self.send(request).await?;
+            self.base_client
+                .mark_room_key_request_as_sent(&txn_id)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Send the to-device requests of room key shares that were interrupted,
+    /// e.g. by a network error or because the client was stopped.
+    ///
+    /// Until they are sent some devices of the room don't have the room key,
+    /// so this needs to succeed before a message is encrypted.
+    #[cfg(feature = "encryption")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "encryption")))]
+    async fn resume_room_key_shares(&self) -> Result<()> {
+        for request in self.base_client.pending_room_key_requests().await? {
+            let txn_id = request.txn_id.clone();
+            let _response: send_event_to_device::Response = self.send(request).await?;
+            self.base_client
+                .mark_room_key_request_as_sent(&txn_id)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Upload the E2E encryption keys.
+    ///
+    /// This uploads the long lived device keys as well as the required amount
+    /// of one-time keys.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the client isn't logged in, or if no encryption keys need to
+    /// be uploaded.
+    #[cfg(feature = "encryption")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "encryption")))]
+    #[instrument]
+    async fn keys_upload(&self) -> Result<upload_keys::Response> {
+        let (device_keys, one_time_keys) = self
+            .base_client
+            .keys_for_upload()
+            .await
+            .expect("Keys don't need to be uploaded");
+
+        debug!(
+            "Uploading encryption keys device keys: {}, one-time-keys: {}",
+            device_keys.is_some(),
+            one_time_keys.as_ref().map_or(0, |k| k.len())
+        );
+
+        let request = upload_keys::Request {
+            device_keys,
+            one_time_keys,
+        };
+
+        let response = self.send(request).await?;
+        self.base_client
+            .receive_keys_upload_response(&response)
+            .await?;
+        Ok(response)
+    }
+
+    /// Get the current, if any, sync token of the client.
+    /// This will be None if the client didn't sync at least once.
+    pub async fn sync_token(&self) -> Option<String> {
+        self.base_client.sync_token().await
+    }
+
+    /// Take a snapshot of the state of a joined, invited or left room that
+    /// carries the current sync token.
+    ///
+    /// Pass the snapshot to `Room::state_changes_since()` later on to find
+    /// out how the state of the room changed since that sync. Returns `None`
+    /// if the room isn't known.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The unique id of the room.
+    pub async fn room_state_snapshot(&self, room_id: &RoomId) -> Option<RoomStateSnapshot> {
+        self.base_client.room_state_snapshot(room_id).await
+    }
+
+    /// Query the server for users device keys.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no key query needs to be done.
+    #[cfg(feature = "encryption")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "encryption")))]
+    #[instrument]
+    async fn keys_query(&self) -> Result<get_keys::Response> {
+        let mut users_for_query = self
+            .base_client
+            .users_for_key_query()
+            .await
+            .expect("Keys don't need to be uploaded");
+
+        debug!(
+            "Querying device keys device for users: {:?}",
+            users_for_query
+        );
+
+        let mut device_keys: BTreeMap<UserId, Vec<DeviceId>> = BTreeMap::new();
+
+        for user in users_for_query.drain() {
+            device_keys.insert(user, Vec::new());
+        }
+
+        let request = get_keys::Request {
+            timeout: None,
+            device_keys,
+            token: None,
+        };
+
+        let response = self.send(request).await?;
+        self.base_client
+            .receive_keys_query_response(&response)
+            .await?;
+
+        Ok(response)
+    }
+}
+
+/// Deserialize the JSON body of a response to a request that was sent with
+/// `Client::send_raw_request()`.
+///
+/// A response with an error status fails with the Matrix error the server
+/// returned, the same way it does for the typed requests.
+fn raw_response<T: DeserializeOwned>(response: HttpResponse<Vec<u8>>) -> Result<T> {
+    if response.status().is_success() {
+        Ok(serde_json::from_slice(response.body())?)
+    } else {
+        Err(server_error::<api::Error>(response).into())
+    }
+}
+
+/// Turn a response with an error status into the error of an endpoint.
+fn server_error<E: EndpointError>(response: HttpResponse<Vec<u8>>) -> FromHttpResponseError<E> {
+    FromHttpResponseError::Http(match E::try_from_response(response) {
+        Ok(error) => ServerError::Known(error),
+        Err(error) => ServerError::Unknown(error),
+    })
+}
+
+/// Build the content of a `m.room.canonical_alias` event that sets the given
+/// canonical alias and adds the alternative aliases to the current content.
+fn canonical_alias_content(
+    mut current: JsonValue,
+    alias: &RoomAliasId,
+    alt_aliases: &[RoomAliasId],
+) -> JsonValue {
+    if !current.is_object() {
+        current = JsonValue::Object(Default::default());
+    }
+
+    let alias = alias.to_string();
+    let mut aliases: Vec<String> = current
+        .get("alt_aliases")
+        .and_then(|a| a.as_array())
+        .map(|a| {
+            a.iter()
+                .filter_map(|a| a.as_str())
+                .map(str::to_owned)
+                .collect()
+        })
+        .unwrap_or_default();
+
+    for alt_alias in alt_aliases.iter().map(|a| a.to_string()) {
+        if !aliases.contains(&alt_alias) {
+            aliases.push(alt_alias);
+        }
+    }
+
+    aliases.retain(|a| a != &alias);
+
+    current["alias"] = alias.into();
+    current["alt_aliases"] = aliases.into();
+
+    current
+}
+
+/// Get the valid room ids of the `recent_rooms` of the breadcrumbs account
+/// data.
+fn breadcrumb_rooms(content: &JsonValue) -> Vec<RoomId> {
+    content
+        .get("recent_rooms")
+        .and_then(|r| r.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|r| r.as_str())
+        .filter_map(|r| RoomId::try_from(r).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        ban_user, create_receipt, create_typing_event, forget_room, invite_user, kick_user,
+        leave_room, unban_user, Invite3pid, MessageEventContent, RoomIdOrAliasId,
+    };
+    use super::{Client, ClientConfig, HistoricalEvent, Session, SyncSettings, Url};
+    use crate::events::collections::all::RoomEvent;
+    use crate::events::room::member::MembershipState;
+    use crate::events::room::message::TextMessageEventContent;
+    use crate::identifiers::{EventId, RoomAliasId, RoomId, UserId};
+
+    use matrix_sdk_base::{JsonStore, Pusher};
+    use matrix_sdk_test::{EventBuilder, EventsFile};
+
+    use mockito::{mock, Matcher};
+    use serde_json::Value as JsonValue;
+    use std::convert::TryFrom;
+    use std::str::FromStr;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[tokio::test]
+    async fn account_data() {
+        let homeserver = Url::from_str(&mockito::server_url()).unwrap();
+
+        let session = Session {
+            access_token: "1234".to_owned(),
+            user_id: UserId::try_from("@example:example.com").unwrap(),
+            device_id: "DEVICEID".to_owned(),
+        };
+
+        let _m = mock(
+            "GET",
+            Matcher::Regex(r"^/_matrix/client/r0/sync\?.*$".to_string()),
+        )
+        .with_status(200)
+        .with_body_from_file("../test_data/sync.json")
+        .create();
+
+        let client = Client::new(homeserver, Some(session)).unwrap();
+
+        let sync_settings = SyncSettings::new().timeout(Duration::from_millis(3000));
+
+        let _response = client.sync(sync_settings).await.unwrap();
+
+        // let bc = &client.base_client;
+        // let ignored_users = bc.ignored_users.read().await;
+        // assert_eq!(1, ignored_users.len())
+    }
+
+    #[tokio::test]
+    async fn account_data_api() {
+        let homeserver = Url::from_str(&mockito::server_url()).unwrap();
+        let room_id = RoomId::try_from("!SVkFJHzfwvuaIEawgC:localhost").unwrap();
+
+        let session = Session {
+            access_token: "1234".to_owned(),
+            user_id: UserId::try_from("@example:localhost").unwrap(),
+            device_id: "DEVICEID".to_owned(),
+        };
+
+        let _sync = mock(
+            "GET",
+            Matcher::Regex(r"^/_matrix/client/r0/sync\?.*$".to_string()),
+        )
+        .with_status(200)
+        .with_body_from_file("../test_data/sync.json")
+        .create();
+
+        let _missing = mock(
+            "GET",
+            "/_matrix/client/r0/user/@example:localhost/account_data/org.example.missing",
+        )
+        .with_status(404)
+        .with_body(r#"{ "errcode": "M_NOT_FOUND", "error": "Account data not found" }"#)
+        .create();
+
+        let set_global = mock(
+            "PUT",
+            "/_matrix/client/r0/user/@example:localhost/account_data/org.example.settings",
+        )
+        .match_body(Matcher::Json(serde_json::json!({ "theme": "dark" })))
+        .with_status(200)
+        .with_body("{}")
+        .create();
+
+        let set_room = mock(
+            "PUT",
+            Matcher::Regex(
+                r"^/_matrix/client/r0/user/.*/rooms/.*/account_data/org.example.pin$".to_string(),
+            ),
+        )
+        .with_status(200)
+        .with_body("{}")
+        .create();
+
+        let client = Client::new(homeserver, Some(session)).unwrap();
+        client.sync(SyncSettings::default()).await.unwrap();
+
+        assert!(client
+            .get_account_data::<JsonValue>("org.example.missing")
+            .await
+            .unwrap()
+            .is_none());
+
+        client
+            .set_account_data(
+                "org.example.settings",
+                &serde_json::json!({ "theme": "dark" }),
+            )
+            .await
+            .unwrap();
+        set_global.assert();
+        // The content that was set is known without asking the homeserver.
+        assert_eq!(
+            client
+                .get_account_data::<JsonValue>("org.example.settings")
+                .await
+                .unwrap(),
+            Some(serde_json::json!({ "theme": "dark" }))
+        );
+
+        // The room account data of the sync response is stored as well.
+        assert_eq!(
+            client
+                .get_room_account_data::<JsonValue>(&room_id, "m.fully_read")
+                .await
+                .unwrap(),
+            Some(serde_json::json!({ "event_id": "$someplace:example.org" }))
+        );
+
+        client
+            .set_room_account_data(&room_id, "org.example.pin", &true)
+            .await
+            .unwrap();
+        set_room.assert();
+        assert_eq!(
+            client
+                .get_room_account_data::<bool>(&room_id, "org.example.pin")
+                .await
+                .unwrap(),
+            Some(true)
+        );
+    }
+
+    #[tokio::test]
+    async fn room_creation() {
+        let session = Session {
+            access_token: "12345".to_owned(),
+            user_id: UserId::try_from("@example:localhost").unwrap(),
+            device_id: "DEVICEID".to_owned(),
+        };
+        let homeserver = url::Url::parse(&mockito::server_url()).unwrap();
+        let client = Client::new(homeserver, Some(session)).unwrap();
+
+        let mut response = EventBuilder::default()
+            .add_room_event(EventsFile::Member, RoomEvent::RoomMember)
+            .add_room_event(EventsFile::PowerLevels, RoomEvent::RoomPowerLevels)
+            .build_sync_response();
+
+        client
+            .base_client
+            .receive_sync_response(&mut response)
+            .await
+            .unwrap();
+        let room_id = RoomId::try_from("!SVkFJHzfwvuaIEawgC:localhost").unwrap();
+
+        assert_eq!(
+            client.homeserver(),
+            &Url::parse(&mockito::server_url()).unwrap()
+        );
+
+        let room = client.get_joined_room(&room_id).await;
+        assert!(room.is_some());
+    }
+
+    #[tokio::test]
+    async fn login_error() {
+        let homeserver = Url::from_str(&mockito::server_url()).unwrap();
+
+        let _m = mock("POST", "/_matrix/client/r0/login")
+            .with_status(403)
+            .with_body_from_file("../test_data/login_response_error.json")
+            .create();
+
+        let client = Client::new(homeserver, None).unwrap();
+
+        if let Err(err) = client.login("example", "wordpass", None, None).await {
+            if let crate::Error::RumaResponse(crate::FromHttpResponseError::Http(
+                crate::ServerError::Known(crate::api::Error {
+                    kind,
+                    message,
+                    status_code,
+                }),
+            )) = err
+            {
+                if let crate::api::error::ErrorKind::Forbidden = kind {
+                } else {
+                    panic!(
+                        "found the wrong `ErrorKind` {:?}, expected `Forbidden",
+                        kind
+                    );
+                }
+                assert_eq!(message, "Invalid password".to_string());
+                assert_eq!(status_code, http::StatusCode::from_u16(403).unwrap());
+            } else {
+                panic!(
+                    "found the wrong `Error` type {:?}, expected `Error::RumaResponse",
+                    err
+                );
+            }
+        } else {
+            panic!("this request should return an `Err` variant")
+        }
+    }
+
+    #[tokio::test]
+    async fn join_room_by_id() {
+        let homeserver = Url::from_str(&mockito::server_url()).unwrap();
+
+        let session = Session {
+            access_token: "1234".to_owned(),
+            user_id: UserId::try_from("@example:localhost").unwrap(),
+            device_id: "DEVICEID".to_owned(),
+        };
+
+        let _m = mock(
+            "POST",
+            Matcher::Regex(r"^/_matrix/client/r0/rooms/.*/join".to_string()),
+        )
+        .with_status(200)
+        .with_body_from_file("../test_data/room_id.json")
+        .create();
+
+        let client = Client::new(homeserver, Some(session)).unwrap();
+        let room_id = RoomId::try_from("!testroom:example.org").unwrap();
+
+        assert_eq!(
+            // this is the `join_by_room_id::Response` but since no PartialEq we check the RoomId field
+            client.join_room_by_id(&room_id).await.unwrap().room_id,
+            room_id
+        );
+        assert!(client.get_joined_room(&room_id).await.is_some());
+    }
+
+    #[tokio::test]
+    async fn leave_and_forget_joined_room() {
+        let homeserver = Url::from_str(&mockito::server_url()).unwrap();
+
+        let session = Session {
+            access_token: "1234".to_owned(),
+            user_id: UserId::try_from("@example:localhost").unwrap(),
+            device_id: "DEVICEID".to_owned(),
+        };
+
+        let _m = mock(
+            "GET",
+            Matcher::Regex(r"^/_matrix/client/r0/sync\?.*$".to_string()),
+        )
+        .with_status(200)
+        .with_body_from_file("../test_data/sync.json")
+        .create();
+
+        let _leave = mock(
+            "POST",
+            Matcher::Regex(r"^/_matrix/client/r0/rooms/.*/leave".to_string()),
+        )
+        .with_status(200)
+        .with_body("{}")
+        .create();
+
+        let _forget = mock(
+            "POST",
+            Matcher::Regex(r"^/_matrix/client/r0/rooms/.*/forget".to_string()),
+        )
+        .with_status(200)
+        .with_body("{}")
+        .create();
+
+        let client = Client::new(homeserver, Some(session)).unwrap();
+        let room_id = RoomId::try_from("!SVkFJHzfwvuaIEawgC:localhost").unwrap();
+
+        let sync_settings = SyncSettings::new().timeout(Duration::from_millis(3000));
+        let _response = client.sync(sync_settings).await.unwrap();
+
+        let name = client
+            .get_joined_room(&room_id)
+            .await
+            .unwrap()
+            .read()
+            .await
+            .display_name();
+
+        client.leave_room(&room_id).await.unwrap();
+
+        assert!(client.get_joined_room(&room_id).await.is_none());
+        // The state of the room is kept when it moves into the left rooms.
+        let room = client.get_left_room(&room_id).await.unwrap();
+        assert_eq!(room.read().await.display_name(), name);
+
+        client.forget_room_by_id(&room_id).await.unwrap();
+        assert!(client.get_left_room(&room_id).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn join_restricted_room_by_id() {
+        use matrix_sdk_base::RoomStateType;
+        use serde_json::value::RawValue;
+
+        let homeserver = Url::from_str(&mockito::server_url()).unwrap();
+
+        let session = Session {
+            access_token: "1234".to_owned(),
+            user_id: UserId::try_from("@example:localhost").unwrap(),
+            device_id: "DEVICEID".to_owned(),
+        };
+
+        let _m = mock(
+            "POST",
+            Matcher::Regex(r"^/_matrix/client/r0/join/".to_string()),
+        )
+        .match_query(Matcher::AllOf(vec![
+            Matcher::UrlEncoded("server_name".into(), "space.org".into()),
+            Matcher::UrlEncoded("server_name".into(), "example.org".into()),
+        ]))
+        .with_status(200)
+        .with_body_from_file("../test_data/room_id.json")
+        .create();
+
+        let client = Client::new(homeserver, Some(session)).unwrap();
+        let room_id = RoomId::try_from("!testroom:example.org").unwrap();
+
+        let join_rules = serde_json::json!({
+            "content": {
+                "join_rule": "restricted",
+                "allow": [{ "type": "m.room_membership", "room_id": "!space:space.org" }]
+            },
+            "sender": "@example:localhost",
+            "state_key": "",
+            "type": "m.room.join_rules"
+        });
+        let join_rules = RawValue::from_string(join_rules.to_string()).unwrap();
+
+        assert!(
+            client
+                .base_client
+                .receive_raw_state_event(&room_id, RoomStateType::Invited, &join_rules)
+                .await
+        );
+
+        assert_eq!(
+            client.join_room_by_id(&room_id).await.unwrap().room_id,
+            room_id
+        );
+    }
+
+    #[tokio::test]
+    async fn join_room_by_id_or_alias() {
+        let homeserver = Url::from_str(&mockito::server_url()).unwrap();
+
+        let session = Session {
+            access_token: "1234".to_owned(),
+            user_id: UserId::try_from("@example:localhost").unwrap(),
+            device_id: "DEVICEID".to_owned(),
+        };
+
+        let _m = mock(
+            "POST",
+            Matcher::Regex(r"^/_matrix/client/r0/join/".to_string()),
+        )
+        .with_status(200)
+        .with_body_from_file("../test_data/room_id.json")
+        .create();
+
+        let client = Client::new(homeserver, Some(session)).unwrap();
+        let room_id = RoomIdOrAliasId::try_from("!testroom:example.org").unwrap();
+
+        assert_eq!(
+            // this is the `join_by_room_id::Response` but since no PartialEq we check the RoomId field
+            client
+                .join_room_by_id_or_alias(&room_id, &["server.com".to_string()])
+                .await
+                .unwrap()
+                .room_id,
+            RoomId::try_from("!testroom:example.org").unwrap()
+        );
+    }
+
+    #[tokio::test]
+    #[allow(irrefutable_let_patterns)]
+    async fn invite_user_by_id() {
+        let homeserver = Url::from_str(&mockito::server_url()).unwrap();
+        let user = UserId::try_from("@example:localhost").unwrap();
+        let room_id = RoomId::try_from("!testroom:example.org").unwrap();
+
+        let session = Session {
+            access_token: "1234".to_owned(),
+            user_id: user.clone(),
+            device_id: "DEVICEID".to_owned(),
+        };
+
+        let _m = mock(
+            "POST",
+            Matcher::Regex(r"^/_matrix/client/r0/rooms/.*/invite".to_string()),
+        )
+        .with_status(200)
+        .with_body_from_file("../test_data/logout_response.json")
+        .create();
+
+        let client = Client::new(homeserver, Some(session)).unwrap();
+
+        if let invite_user::Response = client.invite_user_by_id(&room_id, &user).await.unwrap() {}
+    }
+
+    #[tokio::test]
+    #[allow(irrefutable_let_patterns)]
+    async fn invite_user_by_3pid() {
+        let homeserver = Url::from_str(&mockito::server_url()).unwrap();
+        let user = UserId::try_from("@example:localhost").unwrap();
+        let room_id = RoomId::try_from("!testroom:example.org").unwrap();
+
+        let session = Session {
+            access_token: "1234".to_owned(),
+            user_id: user.clone(),
+            device_id: "DEVICEID".to_owned(),
+        };
+
+        let _m = mock(
+            "POST",
+            Matcher::Regex(r"^/_matrix/client/r0/rooms/.*/invite".to_string()),
+        )
+        .with_status(200)
+        .with_body_from_file("../test_data/logout_response.json")
+        .create();
+
+        let client = Client::new(homeserver, Some(session)).unwrap();
+
+        if let invite_user::Response = client
+            .invite_user_by_3pid(
+                &room_id,
+                &Invite3pid {
+                    id_server: "example.org".to_string(),
+                    id_access_token: "IdToken".to_string(),
+                    medium: crate::api::r0::thirdparty::Medium::Email,
+                    address: "address".to_string(),
+                },
+            )
+            .await
+            .unwrap()
+        {}
+    }
+
+    #[tokio::test]
+    #[allow(irrefutable_let_patterns)]
+    async fn leave_room() {
+        let homeserver = Url::from_str(&mockito::server_url()).unwrap();
+
+        let session = Session {
+            access_token: "1234".to_owned(),
+            user_id: UserId::try_from("@example:localhost").unwrap(),
+            device_id: "DEVICEID".to_owned(),
+        };
+
+        let _m = mock(
+            "POST",
+            Matcher::Regex(r"^/_matrix/client/r0/rooms/.*/leave".to_string()),
+        )
+        .with_status(200)
+        // this is an empty JSON object
+        .with_body_from_file("../test_data/logout_response.json")
+        .create();
+
+        let client = Client::new(homeserver, Some(session)).unwrap();
+        let room_id = RoomId::try_from("!testroom:example.org").unwrap();
+
+        let response = client.leave_room(&room_id).await.unwrap();
+        if let leave_room::Response = response {
+        } else {
+            panic!(
+                "expected `ruma_client_api::leave_room::Response` found {:?}",
+                response
+            )
+        }
+    }
+
+    #[tokio::test]
+    #[allow(irrefutable_let_patterns)]
+    async fn ban_user() {
+        let homeserver = Url::from_str(&mockito::server_url()).unwrap();
+        let user = UserId::try_from("@example:localhost").unwrap();
+        let room_id = RoomId::try_from("!testroom:example.org").unwrap();
+
+        let session = Session {
+            access_token: "1234".to_owned(),
+            user_id: user.clone(),
+            device_id: "DEVICEID".to_owned(),
+        };
+
+        let _m = mock(
+            "POST",
+            Matcher::Regex(r"^/_matrix/client/r0/rooms/.*/ban".to_string()),
+        )
+        .with_status(200)
+        // this is an empty JSON object
+        .with_body_from_file("../test_data/logout_response.json")
+        .create();
+
+        let client = Client::new(homeserver, Some(session)).unwrap();
+
+        let response = client.ban_user(&room_id, &user, None).await.unwrap();
+        if let ban_user::Response = response {
+        } else {
+            panic!(
+                "expected `ruma_client_api::ban_user::Response` found {:?}",
+                response
+            )
+        }
+    }
+
+    #[tokio::test]
+    #[allow(irrefutable_let_patterns)]
+    async fn kick_user() {
+        let homeserver = Url::from_str(&mockito::server_url()).unwrap();
+        let user = UserId::try_from("@example:localhost").unwrap();
+        let room_id = RoomId::try_from("!testroom:example.org").unwrap();
+
+        let session = Session {
+            access_token: "1234".to_owned(),
+            user_id: user.clone(),
+            device_id: "DEVICEID".to_owned(),
+        };
+
+        let _m = mock(
+            "POST",
+            Matcher::Regex(r"^/_matrix/client/r0/rooms/.*/kick".to_string()),
+        )
+        .with_status(200)
+        // this is an empty JSON object
+        .with_body_from_file("../test_data/logout_response.json")
+        .create();
+
+        let client = Client::new(homeserver, Some(session)).unwrap();
+
+        let response = client.kick_user(&room_id, &user, None).await.unwrap();
+        if let kick_user::Response = response {
+        } else {
+            panic!(
+                "expected `ruma_client_api::kick_user::Response` found {:?}",
+                response
+            )
+        }
+    }
+
+    #[tokio::test]
+    #[allow(irrefutable_let_patterns)]
+    async fn unban_user() {
+        let homeserver = Url::from_str(&mockito::server_url()).unwrap();
+        let user = UserId::try_from("@example:localhost").unwrap();
+        let room_id = RoomId::try_from("!testroom:example.org").unwrap();
+
+        let session = Session {
+            access_token: "1234".to_owned(),
+            user_id: user.clone(),
+            device_id: "DEVICEID".to_owned(),
+        };
+
+        let _m = mock(
+            "POST",
+            Matcher::Regex(r"^/_matrix/client/r0/rooms/.*/unban".to_string()),
+        )
+        .with_status(200)
+        // this is an empty JSON object
+        .with_body_from_file("../test_data/logout_response.json")
+        .create();
+
+        let client = Client::new(homeserver, Some(session)).unwrap();
+
+        let response = client.unban_user(&room_id, &user).await.unwrap();
+        if let unban_user::Response = response {
+        } else {
+            panic!(
+                "expected `ruma_client_api::unban_user::Response` found {:?}",
+                response
+            )
+        }
+    }
+
+    #[tokio::test]
+    async fn resolve_room_alias() {
+        let homeserver = Url::from_str(&mockito::server_url()).unwrap();
+        let alias = RoomAliasId::try_from("#alias:example.org").unwrap();
+
+        let session = Session {
+            access_token: "1234".to_owned(),
+            user_id: UserId::try_from("@example:localhost").unwrap(),
+            device_id: "DEVICEID".to_owned(),
+        };
+
+        let _m = mock(
+            "GET",
+            Matcher::Regex(r"^/_matrix/client/r0/directory/room/.*".to_string()),
+        )
+        .with_status(200)
+        .with_body(
+            serde_json::json!({
+                "room_id": "!testroom:example.org",
+                "servers": ["example.org"]
+            })
+            .to_string(),
+        )
+        .expect(1)
+        .create();
+
+        let client = Client::new(homeserver, Some(session)).unwrap();
+        let room_id = RoomId::try_from("!testroom:example.org").unwrap();
+
+        // The second call is answered from the cache.
+        assert_eq!(client.resolve_room_alias(&alias).await.unwrap(), room_id);
+        assert_eq!(client.resolve_room_alias(&alias).await.unwrap(), room_id);
+
+        _m.assert();
+    }
+
+    #[tokio::test]
+    async fn backfill_room() {
+        use crate::events::collections::all::RoomEvent;
+        use crate::js_int::UInt;
+        use crate::locks::Mutex;
+        use crate::{EventEmitter, EventOrigin, SyncRoom};
+        use std::sync::Arc;
+
+        struct OriginCollector(Arc<Mutex<Vec<EventOrigin>>>);
+
+        #[async_trait::async_trait]
+        impl EventEmitter for OriginCollector {
+            async fn on_timeline_event(&self, _: SyncRoom, _: &RoomEvent, origin: EventOrigin) {
+                self.0.lock().await.push(origin);
+            }
+        }
+
+        let homeserver = Url::from_str(&mockito::server_url()).unwrap();
+        let room_id = RoomId::try_from("!SVkFJHzfwvuaIEawgC:localhost").unwrap();
+
+        let session = Session {
+            access_token: "1234".to_owned(),
+            user_id: UserId::try_from("@example:localhost").unwrap(),
+            device_id: "DEVICEID".to_owned(),
+        };
+
+        let _sync = mock(
+            "GET",
+            Matcher::Regex(r"^/_matrix/client/r0/sync\?.*$".to_string()),
+        )
+        .with_status(200)
+        .with_body_from_file("../test_data/sync.json")
+        .create();
+
+        let _messages = mock(
+            "GET",
+            Matcher::Regex(r"^/_matrix/client/r0/rooms/.*/messages".to_string()),
+        )
+        .match_query(Matcher::AllOf(vec![
+            Matcher::UrlEncoded("from".into(), "t392-516_47314_0_7_1_1_1_11444_1".into()),
+            Matcher::UrlEncoded("dir".into(), "b".into()),
+        ]))
+        .with_status(200)
+        .with_body_from_file("../test_data/room_messages.json")
+        .expect(1)
+        .create();
+
+        let client = Client::new(homeserver, Some(session)).unwrap();
+        let origins = Arc::new(Mutex::new(Vec::new()));
+        client
+            .add_event_emitter(Box::new(OriginCollector(origins.clone())))
+            .await;
+
+        client.sync(SyncSettings::new()).await.unwrap();
+        assert!(!origins.lock().await.contains(&EventOrigin::Backfill));
+        origins.lock().await.clear();
+
+        let response = client
+            .backfill_room(&room_id, UInt::from(10u32), None)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(response.chunk.len(), 3);
+        assert_eq!(*origins.lock().await, vec![EventOrigin::Backfill; 3]);
+
+        let room = client.get_joined_room(&room_id).await.unwrap();
+        assert_eq!(
+            room.read().await.prev_batch(),
+            Some("t47409-4357353_219380_26003_2265")
+        );
+
+        _messages.assert();
+    }
+
+    #[tokio::test]
+    async fn search() {
+        use crate::js_int::UInt;
+        use crate::SearchOrder;
+
+        let homeserver = Url::from_str(&mockito::server_url()).unwrap();
+        let room_id = RoomId::try_from("!SVkFJHzfwvuaIEawgC:localhost").unwrap();
+
+        let session = Session {
+            access_token: "1234".to_owned(),
+            user_id: UserId::try_from("@example:localhost").unwrap(),
+            device_id: "DEVICEID".to_owned(),
+        };
+
+        let _m = mock("POST", "/_matrix/client/r0/search")
+            .match_query(Matcher::UrlEncoded("next_batch".into(), "page2".into()))
+            .match_body(Matcher::PartialJson(serde_json::json!({
+                "search_categories": {
+                    "room_events": {
+                        "search_term": "hello",
+                        "order_by": "recent",
+                        "filter": { "rooms": [room_id.to_string()], "limit": 5 }
+                    }
+                }
+            })))
+            .with_status(200)
+            .with_body(
+                serde_json::json!({
+                    "search_categories": {
+                        "room_events": {
+                            "count": 1,
+                            "highlights": ["hello"],
+                            "results": [{
+                                "rank": 1.0,
+                                "result": {
+                                    "content": { "body": "hello", "msgtype": "m.text" },
+                                    "event_id": "$match:localhost",
+                                    "origin_server_ts": 1_520_372_800_469u64,
+                                    "room_id": room_id.to_string(),
+                                    "sender": "@example:localhost",
+                                    "type": "m.room.message"
+                                }
+                            }]
+                        }
+                    }
+                })
+                .to_string(),
+            )
+            .expect(1)
+            .create();
+
+        let client = Client::new(homeserver, Some(session)).unwrap();
+
+        let results = client
+            .search(
+                "hello",
+                Some(&[room_id.clone()]),
+                SearchOrder::Recent,
+                Some(UInt::from(5u32)),
+                Some("page2"),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(results.results.len(), 1);
+        assert_eq!(results.results[0].room_id(), Some(room_id));
+        assert!(results.next_batch.is_none());
+
+        _m.assert();
+    }
+
+    #[tokio::test]
+    async fn redact_events() {
+        let homeserver = Url::from_str(&mockito::server_url()).unwrap();
+        let room_id = RoomId::try_from("!spam:localhost").unwrap();
+
+        let session = Session {
+            access_token: "1234".to_owned(),
+            user_id: UserId::try_from("@example:localhost").unwrap(),
+            device_id: "DEVICEID".to_owned(),
+        };
+
+        let _first = mock(
+            "PUT",
+            Matcher::Regex(r"^/_matrix/client/r0/rooms/.*spam.*/redact/.*first.*".to_string()),
+        )
+        .with_status(200)
+        .with_body(r#"{ "event_id": "$redaction:localhost" }"#)
+        .expect(1)
+        .create();
+
+        let _second = mock(
+            "PUT",
+            Matcher::Regex(r"^/_matrix/client/r0/rooms/.*spam.*/redact/.*second.*".to_string()),
+        )
+        .with_status(403)
+        .with_body(r#"{ "errcode": "M_FORBIDDEN", "error": "Not allowed" }"#)
+        .expect(1)
+        .create();
+
+        let client = Client::new(homeserver, Some(session)).unwrap();
+        let event_ids = vec![
+            EventId::try_from("$first:localhost").unwrap(),
+            EventId::try_from("$second:localhost").unwrap(),
+        ];
+
+        let results = client
+            .redact_events(&room_id, &event_ids, Some("Spam".to_owned()))
+            .await;
+
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].0, event_ids[0]);
+        assert_eq!(
+            results[0].1.as_ref().unwrap().event_id,
+            EventId::try_from("$redaction:localhost").unwrap()
+        );
+        assert_eq!(results[1].0, event_ids[1]);
+        assert!(results[1].1.is_err());
+
+        _first.assert();
+        _second.assert();
+    }
+
+    #[tokio::test]
+    async fn room_upgrade() {
+        let homeserver = Url::from_str(&mockito::server_url()).unwrap();
+        let room_id = RoomId::try_from("!SVkFJHzfwvuaIEawgC:localhost").unwrap();
+        let new_room_id = RoomId::try_from("!upgraded:localhost").unwrap();
+
+        let session = Session {
+            access_token: "1234".to_owned(),
+            user_id: UserId::try_from("@example:localhost").unwrap(),
+            device_id: "DEVICEID".to_owned(),
+        };
+
+        let _upgrade = mock(
+            "POST",
+            Matcher::Regex(r"^/_matrix/client/r0/rooms/.*/upgrade".to_string()),
+        )
+        .match_body(Matcher::Json(serde_json::json!({ "new_version": "6" })))
+        .with_status(200)
+        .with_body(r#"{ "replacement_room": "!upgraded:localhost" }"#)
+        .create();
+
+        let mut sync: serde_json::Value =
+            serde_json::from_str(include_str!("../../test_data/sync.json")).unwrap();
+        sync["rooms"]["join"][&room_id.to_string()]["timeline"]["events"] = serde_json::json!([{
+            "content": {
+                "body": "This room has been replaced",
+                "replacement_room": "!upgraded:localhost"
+            },
+            "event_id": "$tombstone:localhost",
+            "origin_server_ts": 1_590_000_000_000u64,
+            "sender": "@example:localhost",
+            "state_key": "",
+            "type": "m.room.tombstone"
+        }]);
+
+        let _sync = mock(
+            "GET",
+            Matcher::Regex(r"^/_matrix/client/r0/sync\?.*$".to_string()),
+        )
+        .with_status(200)
+        .with_body(sync.to_string())
+        .create();
+
+        let join = mock(
+            "POST",
+            Matcher::Regex(r"^/_matrix/client/r0/rooms/.*upgraded.*/join".to_string()),
+        )
+        .with_status(200)
+        .with_body(r#"{ "room_id": "!upgraded:localhost" }"#)
+        .expect(1)
+        .create();
+
+        let config = ClientConfig::new().auto_join_upgraded_rooms();
+        let client = Client::new_with_config(homeserver, Some(session), config).unwrap();
+
+        assert_eq!(
+            client.upgrade_room(&room_id, "6").await.unwrap(),
+            new_room_id
+        );
+
+        client.sync(SyncSettings::default()).await.unwrap();
+
+        let room = client.get_joined_room(&room_id).await.unwrap();
+        assert_eq!(room.read().await.successor(), Some(&new_room_id));
+        assert!(client.get_joined_room(&new_room_id).await.is_some());
+        join.assert();
+    }
+
+    #[tokio::test]
+    async fn send_state_event() {
+        let homeserver = Url::from_str(&mockito::server_url()).unwrap();
+        let room_id = RoomId::try_from("!SVkFJHzfwvuaIEawgC:localhost").unwrap();
+
+        let session = Session {
+            access_token: "1234".to_owned(),
+            user_id: UserId::try_from("@example:localhost").unwrap(),
+            device_id: "DEVICEID".to_owned(),
+        };
+
+        let content = serde_json::json!({ "url": "https://example.org" });
+
+        let _m = mock(
+            "PUT",
+            Matcher::Regex(
+                r"^/_matrix/client/r0/rooms/.*/state/im.vector.modular.widgets/widget".to_string(),
+            ),
+        )
+        .match_body(Matcher::Json(content.clone()))
+        .with_status(200)
+        .with_body(r#"{ "event_id": "$widget:localhost" }"#)
+        .create();
+
+        let client = Client::new(homeserver, Some(session)).unwrap();
+
+        let event_id = client
+            .send_state_event(&room_id, "im.vector.modular.widgets", "widget", &content)
+            .await
+            .unwrap();
+
+        assert_eq!(event_id, EventId::try_from("$widget:localhost").unwrap());
+    }
+
+    #[tokio::test]
+    async fn send_with_timestamp() {
+        let homeserver = Url::from_str(&mockito::server_url()).unwrap();
+        let room_id = RoomId::try_from("!SVkFJHzfwvuaIEawgC:localhost").unwrap();
+        let user_id = UserId::try_from("@_bridge:localhost").unwrap();
+        let timestamp = UNIX_EPOCH + Duration::from_millis(1_500_000_000_000);
+
+        let message = mock(
+            "PUT",
+            Matcher::Regex(r"^/_matrix/client/r0/rooms/.*/send/m.room.message/".to_string()),
+        )
+        .match_query(Matcher::UrlEncoded("ts".into(), "1500000000000".into()))
+        .with_status(200)
+        .with_body(r#"{ "event_id": "$message:localhost" }"#)
+        .create();
+
+        let state = mock(
+            "PUT",
+            Matcher::Regex(r"^/_matrix/client/r0/rooms/.*/state/m.room.topic".to_string()),
+        )
+        .match_query(Matcher::UrlEncoded("ts".into(), "1500000000000".into()))
+        .with_status(200)
+        .with_body(r#"{ "event_id": "$topic:localhost" }"#)
+        .create();
+
+        let client = Client::new_appservice(homeserver, "as_token", &user_id).unwrap();
+
+        let content = MessageEventContent::Text(TextMessageEventContent {
+            body: "Hello from the past".to_owned(),
+            format: None,
+            formatted_body: None,
+            relates_to: None,
+        });
+
+        let response = client
+            .room_send_with_timestamp(&room_id, content, None, timestamp)
+            .await
+            .unwrap();
+        assert_eq!(
+            response.event_id,
+            Some(EventId::try_from("$message:localhost").unwrap())
+        );
+
+        let topic = serde_json::json!({ "topic": "Bridged" });
+        let event_id = client
+            .send_state_event_with_timestamp(&room_id, "m.room.topic", "", &topic, timestamp)
+            .await
+            .unwrap();
+        assert_eq!(event_id, EventId::try_from("$topic:localhost").unwrap());
+
+        message.assert();
+        state.assert();
+    }
+
+    #[tokio::test]
+    async fn import_history() {
+        let homeserver = Url::from_str(&mockito::server_url()).unwrap();
+        let room_id = RoomId::try_from("!import:localhost").unwrap();
+        let user_id = UserId::try_from("@_bridge:localhost").unwrap();
+        let first = UNIX_EPOCH + Duration::from_millis(1_500_000_000_000);
+        let second = first + Duration::from_secs(60);
+
+        let _versions = mock("GET", "/_matrix/client/versions")
+            .with_status(200)
+            .with_body(r#"{ "versions": ["r0.6.0"], "unstable_features": {} }"#)
+            .create();
+
+        let topic = mock(
+            "PUT",
+            Matcher::Regex(r"^/_matrix/client/r0/rooms/.*/state/m.room.topic".to_string()),
+        )
+        .match_query(Matcher::UrlEncoded("ts".into(), "1500000000000".into()))
+        .with_status(200)
+        .with_body(r#"{ "event_id": "$topic:localhost" }"#)
+        .create();
+
+        let message = mock(
+            "PUT",
+            Matcher::Regex(r"^/_matrix/client/r0/rooms/.*/send/m.room.message/".to_string()),
+        )
+        .match_query(Matcher::UrlEncoded("ts".into(), "1500000060000".into()))
+        .with_status(200)
+        .with_body(r#"{ "event_id": "$message:localhost" }"#)
+        .create();
+
+        let client = Client::new_appservice(homeserver, "as_token", &user_id).unwrap();
+
+        let events = vec![
+            HistoricalEvent::new(
+                "m.room.message",
+                serde_json::json!({ "msgtype": "m.text", "body": "Hello" }),
+                second,
+            ),
+            HistoricalEvent::state(
+                "m.room.topic",
+                "",
+                serde_json::json!({ "topic": "Bridged" }),
+                first,
+            ),
+        ];
+
+        let mut reported = Vec::new();
+        let event_ids = client
+            .import_history(&room_id, events, None, |done, total| {
+                reported.push((done, total))
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(
+            event_ids,
+            vec![
+                Some(EventId::try_from("$topic:localhost").unwrap()),
+                Some(EventId::try_from("$message:localhost").unwrap()),
+            ]
+        );
+        assert_eq!(reported, vec![(1, 2), (2, 2)]);
+
+        topic.assert();
+        message.assert();
+    }
+
+    #[tokio::test]
+    async fn import_history_batch_send() {
+        let homeserver = Url::from_str(&mockito::server_url()).unwrap();
+        let room_id = RoomId::try_from("!batch:localhost").unwrap();
+        let user_id = UserId::try_from("@_bridge:localhost").unwrap();
+        let prev_event_id = EventId::try_from("$create:localhost").unwrap();
+        let timestamp = UNIX_EPOCH + Duration::from_millis(1_500_000_000_000);
+
+        let versions = mock("GET", "/_matrix/client/versions")
+            .with_status(200)
+            .with_body(
+                r#"{
+                    "versions": ["r0.6.0"],
+                    "unstable_features": { "org.matrix.msc2716": true }
+                }"#,
+            )
+            .expect(1)
+            .create();
+
+        let batch = mock(
+            "POST",
+            Matcher::Regex(
+                r"^/_matrix/client/unstable/org.matrix.msc2716/rooms/.*/batch_send".to_string(),
+            ),
+        )
+        .match_query(Matcher::UrlEncoded(
+            "prev_event_id".into(),
+            "$create:localhost".into(),
+        ))
+        .match_body(Matcher::PartialJson(serde_json::json!({
+            "events": [{
+                "type": "m.room.message",
+                "sender": "@_bridge:localhost",
+                "origin_server_ts": 1_500_000_000_000u64,
+            }]
+        })))
+        .with_status(200)
+        .with_body(r#"{ "event_ids": ["$imported:localhost"], "next_batch_id": "batch2" }"#)
+        .expect(2)
+        .create();
+
+        let client = Client::new_appservice(homeserver, "as_token", &user_id).unwrap();
+
+        let events = vec![HistoricalEvent::new(
+            "m.room.message",
+            serde_json::json!({ "msgtype": "m.text", "body": "Hello" }),
+            timestamp,
+        )];
+
+        let event_ids = client
+            .import_history(&room_id, events.clone(), Some(&prev_event_id), |_, _| {})
+            .await
+            .unwrap();
+
+        assert_eq!(
+            event_ids,
+            vec![Some(EventId::try_from("$imported:localhost").unwrap())]
+        );
+
+        // The supported versions are only fetched once.
+        client
+            .import_history(&room_id, events, Some(&prev_event_id), |_, _| {})
+            .await
+            .unwrap();
+
+        versions.assert();
+        batch.assert();
+    }
+
+    #[tokio::test]
+    async fn canonical_alias() {
+        let homeserver = Url::from_str(&mockito::server_url()).unwrap();
+        let room_id = RoomId::try_from("!alias:localhost").unwrap();
+
+        let session = Session {
+            access_token: "1234".to_owned(),
+            user_id: UserId::try_from("@example:localhost").unwrap(),
+            device_id: "DEVICEID".to_owned(),
+        };
+
+        let _get = mock(
+            "GET",
+            Matcher::Regex(
+                r"^/_matrix/client/r0/rooms/.*alias.*/state/m.room.canonical_alias$".to_string(),
+            ),
+        )
+        .with_status(200)
+        .with_body(
+            serde_json::json!({
+                "alias": "#old:localhost",
+                "alt_aliases": ["#alt:localhost", "#new:localhost"],
+                "org.example.custom": true
+            })
+            .to_string(),
+        )
+        .create();
+
+        let _put = mock(
+            "PUT",
+            Matcher::Regex(
+                r"^/_matrix/client/r0/rooms/.*alias.*/state/m.room.canonical_alias".to_string(),
+            ),
+        )
+        .match_body(Matcher::Json(serde_json::json!({
+            "alias": "#new:localhost",
+            "alt_aliases": ["#alt:localhost", "#other:localhost"],
+            "org.example.custom": true
+        })))
+        .with_status(200)
+        .with_body(r#"{ "event_id": "$alias:localhost" }"#)
+        .expect(1)
+        .create();
+
+        let client = Client::new(homeserver, Some(session)).unwrap();
+
+        client
+            .set_canonical_alias(
+                &room_id,
+                &RoomAliasId::try_from("#new:localhost").unwrap(),
+                &[
+                    RoomAliasId::try_from("#other:localhost").unwrap(),
+                    RoomAliasId::try_from("#alt:localhost").unwrap(),
+                ],
+            )
+            .await
+            .unwrap();
+
+        _put.assert();
+    }
+
+    #[tokio::test]
+    async fn update_power_levels() {
+        use crate::js_int::Int;
+        use crate::PowerLevelChanges;
+
+        let homeserver = Url::from_str(&mockito::server_url()).unwrap();
+        let room_id = RoomId::try_from("!power:localhost").unwrap();
+
+        let session = Session {
+            access_token: "1234".to_owned(),
+            user_id: UserId::try_from("@example:localhost").unwrap(),
+            device_id: "DEVICEID".to_owned(),
+        };
+
+        let _get = mock(
+            "GET",
+            Matcher::Regex(
+                r"^/_matrix/client/r0/rooms/.*power.*/state/m.room.power_levels$".to_string(),
+            ),
+        )
+        .with_status(200)
+        .with_body(
+            serde_json::json!({
+                "ban": 50,
+                "users": { "@example:localhost": 100 }
+            })
+            .to_string(),
+        )
+        .create();
+
+        let put = mock(
+            "PUT",
+            Matcher::Regex(
+                r"^/_matrix/client/r0/rooms/.*power.*/state/m.room.power_levels".to_string(),
+            ),
+        )
+        .match_body(Matcher::Json(serde_json::json!({
+            "ban": 50,
+            "users": { "@example:localhost": 100, "@moderator:localhost": 50 }
+        })))
+        .with_status(200)
+        .with_body(r#"{ "event_id": "$power:localhost" }"#)
+        .expect(1)
+        .create();
+
+        let client = Client::new(homeserver, Some(session)).unwrap();
+        let changes = PowerLevelChanges::new().user(
+            UserId::try_from("@moderator:localhost").unwrap(),
+            Int::from(50),
+        );
+
+        client
+            .update_power_levels(&room_id, &changes)
+            .await
+            .unwrap();
+
+        put.assert();
+    }
+
+    #[tokio::test]
+    async fn create_encrypted_dm() {
+        let homeserver = Url::from_str(&mockito::server_url()).unwrap();
+        let friend = UserId::try_from("@friend:localhost").unwrap();
+
+        let session = Session {
+            access_token: "1234".to_owned(),
+            user_id: UserId::try_from("@example:localhost").unwrap(),
+            device_id: "DEVICEID".to_owned(),
+        };
+
+        let create = mock("POST", "/_matrix/client/r0/createRoom")
+            .match_body(Matcher::PartialJson(serde_json::json!({
+                "invite": ["@friend:localhost"],
+                "is_direct": true,
+                "preset": "trusted_private_chat",
+                "initial_state": [{
+                    "type": "m.room.encryption",
+                    "state_key": "",
+                    "content": { "algorithm": "m.megolm.v1.aes-sha2" }
+                }]
+            })))
+            .with_status(200)
+            .with_body(r#"{ "room_id": "!dm:localhost" }"#)
+            .create();
+
+        let _get_direct = mock(
+            "GET",
+            "/_matrix/client/r0/user/@example:localhost/account_data/m.direct",
+        )
+        .with_status(200)
+        .with_body(r#"{ "@other:localhost": ["!other:localhost"] }"#)
+        .create();
+
+        let set_direct = mock(
+            "PUT",
+            "/_matrix/client/r0/user/@example:localhost/account_data/m.direct",
+        )
+        .match_body(Matcher::Json(serde_json::json!({
+            "@other:localhost": ["!other:localhost"],
+            "@friend:localhost": ["!dm:localhost"]
+        })))
+        .with_status(200)
+        .with_body("{}")
+        .create();
+
+        let client = Client::new(homeserver, Some(session)).unwrap();
+        let room = client.create_encrypted_dm(&friend).await.unwrap();
+
+        assert!(room.read().await.is_encrypted());
+        create.assert();
+        set_direct.assert();
+    }
+
+    #[tokio::test]
+    async fn complete_room() {
+        let homeserver = Url::from_str(&mockito::server_url()).unwrap();
+
+        let session = Session {
+            access_token: "1234".to_owned(),
+            user_id: UserId::try_from("@example:localhost").unwrap(),
+            device_id: "DEVICEID".to_owned(),
+        };
+
+        let _sync = mock(
+            "GET",
+            Matcher::Regex(r"^/_matrix/client/r0/sync\?.*$".to_string()),
+        )
+        .with_status(200)
+        .with_body_from_file("../test_data/sync.json")
+        .create();
+
+        let directory = mock("POST", "/_matrix/client/r0/publicRooms")
+            .match_body(Matcher::PartialJson(serde_json::json!({
+                "filter": { "generic_search_term": "tut" }
+            })))
+            .with_status(200)
+            .with_body(
+                serde_json::json!({
+                    "chunk": [
+                        {
+                            "room_id": "!SVkFJHzfwvuaIEawgC:localhost",
+                            "canonical_alias": "#tutorial:localhost",
+                            "num_joined_members": 2,
+                            "world_readable": true,
+                            "guest_can_join": false
+                        },
+                        {
+                            "room_id": "!public:localhost",
+                            "name": "Rust tutorials",
+                            "num_joined_members": 100,
+                            "world_readable": true,
+                            "guest_can_join": false
+                        }
+                    ]
+                })
+                .to_string(),
+            )
+            .create();
+
+        let client = Client::new(homeserver, Some(session)).unwrap();
+        client.sync(SyncSettings::default()).await.unwrap();
+
+        let suggestions = client.complete_room("#tut", false).await.unwrap();
+        assert_eq!(suggestions.len(), 1);
+        assert!(suggestions[0].joined);
+        assert_eq!(
+            suggestions[0].alias,
+            Some(RoomAliasId::try_from("#tutorial:localhost").unwrap())
+        );
+
+        let suggestions = client.complete_room("#tut", true).await.unwrap();
+        directory.assert();
+
+        // The joined room isn't suggested twice.
+        assert_eq!(suggestions.len(), 2);
+        assert!(suggestions[0].joined);
+        assert_eq!(
+            suggestions[1].room_id,
+            RoomId::try_from("!public:localhost").unwrap()
+        );
+        assert!(!suggestions[1].joined);
+    }
+
+    #[tokio::test]
+    async fn create_dm() {
+        let homeserver = Url::from_str(&mockito::server_url()).unwrap();
+        let friend = UserId::try_from("@friend:localhost").unwrap();
+        let room_id = RoomId::try_from("!dm:localhost").unwrap();
+
+        let session = Session {
+            access_token: "1234".to_owned(),
+            user_id: UserId::try_from("@example:localhost").unwrap(),
+            device_id: "DEVICEID".to_owned(),
+        };
+
+        let create = mock("POST", "/_matrix/client/r0/createRoom")
+            .match_body(Matcher::PartialJson(serde_json::json!({
+                "invite": ["@friend:localhost"],
+                "is_direct": true,
+            })))
+            .with_status(200)
+            .with_body(r#"{ "room_id": "!dm:localhost" }"#)
+            .expect(1)
+            .create();
+
+        let _get_direct = mock(
+            "GET",
+            "/_matrix/client/r0/user/@example:localhost/account_data/m.direct",
+        )
+        .with_status(404)
+        .with_body(r#"{ "errcode": "M_NOT_FOUND", "error": "Account data not found" }"#)
+        .create();
+
+        let set_direct = mock(
+            "PUT",
+            "/_matrix/client/r0/user/@example:localhost/account_data/m.direct",
+        )
+        .match_body(Matcher::Json(serde_json::json!({
+            "@friend:localhost": ["!dm:localhost"]
+        })))
+        .with_status(200)
+        .with_body("{}")
+        .create();
+
+        let client = Client::new(homeserver, Some(session)).unwrap();
+        assert!(client.get_dm_room(&friend).await.is_none());
+
+        let room = client.create_dm(&friend).await.unwrap();
+        assert!(room.read().await.is_direct());
+        assert_eq!(room.read().await.direct_target(), Some(&friend));
+
+        // The existing room is reused the second time around.
+        let room = client.create_dm(&friend).await.unwrap();
+        assert_eq!(room.read().await.room_id, room_id);
+        assert!(client.get_dm_room(&friend).await.is_some());
+
+        create.assert();
+        set_direct.assert();
+    }
+
+    #[tokio::test]
+    async fn typed_event_handlers() {
+        use crate::events::room::member::MemberEvent;
+        use crate::events::room::message::MessageEvent;
+        use crate::locks::Mutex;
+        use crate::SyncRoom;
+        use std::sync::Arc;
+
+        let homeserver = Url::from_str(&mockito::server_url()).unwrap();
+
+        let session = Session {
+            access_token: "1234".to_owned(),
+            user_id: UserId::try_from("@example:localhost").unwrap(),
+            device_id: "DEVICEID".to_owned(),
+        };
+
+        let _m = mock(
+            "GET",
+            Matcher::Regex(r"^/_matrix/client/r0/sync\?.*$".to_string()),
+        )
+        .with_status(200)
+        .with_body_from_file("../test_data/sync.json")
+        .create();
+
+        let client = Client::new(homeserver, Some(session)).unwrap();
+        let handled = Arc::new(Mutex::new(Vec::new()));
+
+        let h = handled.clone();
+        client
+            .register_event_handler(move |event: MessageEvent, _: SyncRoom| {
+                let handled = h.clone();
+                async move { handled.lock().await.push(event.event_id.to_string()) }
+            })
+            .await;
+
+        let h = handled.clone();
+        client
+            .register_event_handler(move |event: MemberEvent, room: SyncRoom| {
+                let handled = h.clone();
+                async move {
+                    assert_eq!(
+                        room.context().room_id,
+                        RoomId::try_from("!SVkFJHzfwvuaIEawgC:localhost").unwrap()
+                    );
+                    handled.lock().await.push(event.state_key)
+                }
+            })
+            .await;
+
+        let sync_settings = SyncSettings::new().timeout(Duration::from_millis(3000));
+        client.sync(sync_settings).await.unwrap();
+
+        let handled = handled.lock().await;
+        assert!(handled.iter().any(|e| e == "@example:localhost"));
+        assert!(handled.iter().any(|e| e.starts_with('$')));
+    }
+
+    #[tokio::test]
+    async fn sync_observer() {
+        use crate::locks::Mutex;
+        use crate::{SyncObserver, SyncTelemetry};
+        use std::sync::Arc;
+
+        struct Observer(Arc<Mutex<Vec<SyncTelemetry>>>);
+
+        #[async_trait::async_trait]
+        impl SyncObserver for Observer {
+            async fn on_sync(&self, telemetry: &SyncTelemetry) {
+                self.0.lock().await.push(telemetry.clone());
+            }
+        }
+
+        let homeserver = Url::from_str(&mockito::server_url()).unwrap();
+
+        let session = Session {
+            access_token: "1234".to_owned(),
+            user_id: UserId::try_from("@example:localhost").unwrap(),
+            device_id: "DEVICEID".to_owned(),
+        };
+
+        let _m = mock(
+            "GET",
+            Matcher::Regex(r"^/_matrix/client/r0/sync\?.*$".to_string()),
+        )
+        .with_status(200)
+        .with_body_from_file("../test_data/sync.json")
+        .create();
+
+        let client = Client::new(homeserver, Some(session)).unwrap();
+        let telemetry = Arc::new(Mutex::new(Vec::new()));
+        client
+            .set_sync_observer(Box::new(Observer(telemetry.clone())))
+            .await;
+
+        let sync_settings = SyncSettings::new().timeout(Duration::from_millis(3000));
+        client.sync(sync_settings).await.unwrap();
+
+        let telemetry = telemetry.lock().await;
+        assert_eq!(telemetry.len(), 1);
+        assert_eq!(
+            telemetry[0].response_size,
+            std::fs::metadata("../test_data/sync.json").unwrap().len() as usize
+        );
+        assert_eq!(telemetry[0].timeout, Some(Duration::from_millis(3000)));
+        assert_eq!(telemetry[0].joined_rooms, 1);
+    }
+
+    #[tokio::test]
+    async fn content_transformer() {
+        use crate::ContentTransformer;
+
+        struct Signature;
+
+        #[async_trait::async_trait]
+        impl ContentTransformer for Signature {
+            async fn transform(&self, _: &RoomId, content: &mut MessageEventContent) {
+                if let MessageEventContent::Text(text) = content {
+                    text.body.push_str(" -- bot");
+                }
+            }
+        }
+
+        let homeserver = Url::from_str(&mockito::server_url()).unwrap();
+        let room_id = RoomId::try_from("!transform:localhost").unwrap();
+        let event_id = EventId::try_from("$original:localhost").unwrap();
+
+        let session = Session {
+            access_token: "1234".to_owned(),
+            user_id: UserId::try_from("@example:localhost").unwrap(),
+            device_id: "DEVICEID".to_owned(),
+        };
+
+        let message = mock(
+            "PUT",
+            Matcher::Regex(r"^/_matrix/client/r0/rooms/.*/send/m.room.message/".to_string()),
+        )
+        .match_body(Matcher::PartialJson(serde_json::json!({
+            "body": "Hello -- bot",
+        })))
+        .with_status(200)
+        .with_body(r#"{ "event_id": "$message:localhost" }"#)
+        .create();
+
+        let edit = mock(
+            "PUT",
+            Matcher::Regex(r"^/_matrix/client/r0/rooms/.*/send/m.room.message/".to_string()),
+        )
+        .match_body(Matcher::PartialJson(serde_json::json!({
+            "body": "* Fixed -- bot",
+            "m.new_content": { "body": "Fixed -- bot" },
+        })))
+        .with_status(200)
+        .with_body(r#"{ "event_id": "$edit:localhost" }"#)
+        .create();
+
+        let client = Client::new(homeserver, Some(session)).unwrap();
+        client.set_content_transformer(Box::new(Signature)).await;
+
+        let text = |body: &str| {
+            MessageEventContent::Text(TextMessageEventContent {
+                body: body.to_owned(),
+                format: None,
+                formatted_body: None,
+                relates_to: None,
+            })
+        };
+
+        client.send_message(&room_id, text("Hello")).await.unwrap();
+        client
+            .edit_message(&room_id, &event_id, text("Fixed"))
+            .await
+            .unwrap();
+
+        message.assert();
+        edit.assert();
+    }
+
+    #[tokio::test]
+    async fn get_or_upload_filter() {
+        let homeserver = Url::from_str(&mockito::server_url()).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+
+        let session = Session {
+            access_token: "1234".to_owned(),
+            user_id: UserId::try_from("@example:localhost").unwrap(),
+            device_id: "DEVICEID".to_owned(),
+        };
+
+        let upload = mock(
+            "POST",
+            Matcher::Regex(r"^/_matrix/client/r0/user/.*/filter".to_string()),
+        )
+        .with_status(200)
+        .with_body(r#"{ "filter_id": "sync_filter" }"#)
+        .expect(1)
+        .create();
+
+        let definition = || serde_json::from_str(r#"{ "room": { "rooms": [] } }"#).unwrap();
+
+        let config =
+            ClientConfig::default().state_store(Box::new(JsonStore::open(dir.path()).unwrap()));
+        let client =
+            Client::new_with_config(homeserver.clone(), Some(session.clone()), config).unwrap();
+
+        let filter_id = client
+            .get_or_upload_filter("sync", definition())
+            .await
+            .unwrap();
+        assert_eq!(filter_id, "sync_filter");
+
+        // A restarted client reuses the uploaded filter.
+        let config =
+            ClientConfig::default().state_store(Box::new(JsonStore::open(dir.path()).unwrap()));
+        let client = Client::new_with_config(homeserver, Some(session), config).unwrap();
+
+        let filter_id = client
+            .get_or_upload_filter("sync", definition())
+            .await
+            .unwrap();
+        assert_eq!(filter_id, "sync_filter");
+
+        upload.assert();
+    }
+
+    #[tokio::test]
+    async fn get_room_members() {
+        let homeserver = Url::from_str(&mockito::server_url()).unwrap();
+        let room_id = RoomId::try_from("!SVkFJHzfwvuaIEawgC:localhost").unwrap();
+        let bob = UserId::try_from("@bob:localhost").unwrap();
+
+        let session = Session {
+            access_token: "1234".to_owned(),
+            user_id: UserId::try_from("@example:localhost").unwrap(),
+            device_id: "DEVICEID".to_owned(),
+        };
+
+        let _sync = mock(
+            "GET",
+            Matcher::Regex(r"^/_matrix/client/r0/sync\?.*lazy_load_members.*$".to_string()),
+        )
+        .with_status(200)
+        .with_body_from_file("../test_data/sync.json")
+        .create();
+
+        let _members = mock(
+            "GET",
+            Matcher::Regex(r"^/_matrix/client/r0/rooms/.*/members$".to_string()),
+        )
+        .with_status(200)
+        .with_body(
+            serde_json::json!({
+                "chunk": [{
+                    "content": { "displayname": "Bob", "membership": "join" },
+                    "event_id": "$bob:localhost",
+                    "origin_server_ts": 1432735824653u64,
+                    "room_id": "!SVkFJHzfwvuaIEawgC:localhost",
+                    "sender": "@bob:localhost",
+                    "state_key": "@bob:localhost",
+                    "type": "m.room.member",
+                    "unsigned": { "age": 1234 }
+                }]
+            })
+            .to_string(),
+        )
+        .create();
+
+        let client = Client::new(homeserver, Some(session)).unwrap();
+        client.sync(SyncSettings::default()).await.unwrap();
+
+        let room = client.get_joined_room(&room_id).await.unwrap();
+        assert!(!room.read().await.members.contains_key(&bob));
+
+        assert!(!room.read().await.are_members_synced());
+        assert!(client.ensure_members(&room_id).await.unwrap());
+        assert!(!client.ensure_members(&room_id).await.unwrap());
+
+        let events = client.get_room_members(&room_id).await.unwrap();
+        assert_eq!(events.len(), 1);
+
+        let room = room.read().await;
+        assert!(room.are_members_synced());
+        assert_eq!(
+            room.members.get(&bob).unwrap().display_name.as_deref(),
+            Some("Bob")
+        );
+    }
+
+    #[tokio::test]
+    async fn event_context() {
+        use crate::js_int::UInt;
+
+        let homeserver = Url::from_str(&mockito::server_url()).unwrap();
+        let room_id = RoomId::try_from("!SVkFJHzfwvuaIEawgC:localhost").unwrap();
+        let event_id = EventId::try_from("$event:localhost").unwrap();
+
+        let session = Session {
+            access_token: "1234".to_owned(),
+            user_id: UserId::try_from("@example:localhost").unwrap(),
+            device_id: "DEVICEID".to_owned(),
+        };
+
+        let message = |id: &str, body: &str| {
+            serde_json::json!({
+                "content": { "body": body, "msgtype": "m.text" },
+                "event_id": id,
+                "origin_server_ts": 1_520_372_800_469u64,
+                "sender": "@example:localhost",
+                "type": "m.room.message"
+            })
+        };
+
+        let _m = mock(
+            "GET",
+            Matcher::Regex(r"^/_matrix/client/r0/rooms/.*/context/.*".to_string()),
+        )
+        .match_query(Matcher::UrlEncoded("limit".into(), "2".into()))
+        .with_status(200)
+        .with_body(
+            serde_json::json!({
+                "start": "t1",
+                "end": "t2",
+                "events_before": [message("$before:localhost", "Before")],
+                "event": message("$event:localhost", "Event"),
+                "events_after": [message("$after:localhost", "After")],
+                "state": []
+            })
+            .to_string(),
+        )
+        .expect(1)
+        .create();
+
+        let client = Client::new(homeserver, Some(session)).unwrap();
+
+        let response = client
+            .get_event_context(&room_id, &event_id, UInt::from(2u32))
+            .await
+            .unwrap();
+
+        let event = response.event.unwrap().deserialize().unwrap();
+        if let RoomEvent::RoomMessage(event) = event {
+            assert_eq!(event.event_id, event_id);
+        } else {
+            panic!("expected a message event, got {:?}", event);
+        }
+        assert_eq!(response.events_before.len(), 1);
+        assert_eq!(response.events_after.len(), 1);
+
+        _m.assert();
+    }
+
+    #[tokio::test]
+    async fn pushers() {
+        let homeserver = Url::from_str(&mockito::server_url()).unwrap();
+
+        let session = Session {
+            access_token: "1234".to_owned(),
+            user_id: UserId::try_from("@example:localhost").unwrap(),
+            device_id: "DEVICEID".to_owned(),
+        };
+
+        let pusher = Pusher::http("org.example.app", "token", "https://push.example.org")
+            .device_display_name("Phone");
+
+        let _set = mock("POST", "/_matrix/client/r0/pushers/set")
+            .match_body(Matcher::PartialJson(serde_json::json!({
+                "app_id": "org.example.app",
+                "pushkey": "token",
+                "device_display_name": "Phone",
+                "data": { "url": "https://push.example.org" },
+                "append": false
+            })))
+            .with_status(200)
+            .with_body("{}")
+            .expect(1)
+            .create();
+
+        let _get = mock("GET", "/_matrix/client/r0/pushers")
+            .with_status(200)
+            .with_body(
+                serde_json::json!({ "pushers": [serde_json::to_value(&pusher).unwrap()] })
+                    .to_string(),
+            )
+            .create();
+
+        let client = Client::new(homeserver, Some(session)).unwrap();
+
+        client.set_pusher(&pusher, false).await.unwrap();
+        assert_eq!(client.get_pushers().await.unwrap(), vec![pusher]);
+
+        _set.assert();
+    }
+
+    #[tokio::test]
+    async fn raw_request_error() {
+        use crate::api::error::ErrorKind;
+        use crate::{FromHttpResponseError, ServerError};
+
+        let homeserver = Url::from_str(&mockito::server_url()).unwrap();
+
+        let session = Session {
+            access_token: "1234".to_owned(),
+            user_id: UserId::try_from("@example:localhost").unwrap(),
+            device_id: "DEVICEID".to_owned(),
+        };
+
+        let _preview = mock("GET", "/_matrix/media/r0/preview_url")
+            .match_query(Matcher::Any)
+            .match_header("authorization", "Bearer 1234")
+            .with_status(403)
+            .with_body(r#"{ "errcode": "M_FORBIDDEN", "error": "Not allowed" }"#)
+            .create();
+
+        let client = Client::new(homeserver.clone(), Some(session)).unwrap();
+
+        // Requests that aren't supported by the typed requests fail with the
+        // same errors as the typed ones.
+        match client.get_url_preview("https://example.org").await {
+            Err(crate::Error::RumaResponse(FromHttpResponseError::Http(ServerError::Known(
+                error,
+            )))) => {
+                assert!(matches!(error.kind, ErrorKind::Forbidden));
+                assert_eq!(error.message, "Not allowed");
+            }
+            other => panic!("expected a known server error, got {:?}", other),
+        }
+
+        let client = Client::new(homeserver, None).unwrap();
+
+        assert!(matches!(
+            client.get_url_preview("https://example.org").await,
+            Err(crate::Error::AuthenticationRequired)
+        ));
+    }
+
+    #[tokio::test]
+    async fn breadcrumbs() {
+        let homeserver = Url::from_str(&mockito::server_url()).unwrap();
+        let first = RoomId::try_from("!first:localhost").unwrap();
+        let second = RoomId::try_from("!second:localhost").unwrap();
+
+        let session = Session {
+            access_token: "1234".to_owned(),
+            user_id: UserId::try_from("@example:localhost").unwrap(),
+            device_id: "DEVICEID".to_owned(),
+        };
+
+        let _get = mock(
+            "GET",
+            "/_matrix/client/r0/user/@example:localhost/account_data/im.vector.setting.breadcrumbs",
+        )
+        .with_status(200)
+        .with_body(r#"{ "recent_rooms": ["!first:localhost", "invalid"], "other": true }"#)
+        .create();
+
+        let set = mock(
+            "PUT",
+            "/_matrix/client/r0/user/@example:localhost/account_data/im.vector.setting.breadcrumbs",
+        )
+        .match_body(Matcher::Json(serde_json::json!({
+            "recent_rooms": ["!second:localhost", "!first:localhost"],
+            "other": true,
+        })))
+        .with_status(200)
+        .with_body("{}")
+        .expect(1)
+        .create();
+
+        let client = Client::new(homeserver, Some(session)).unwrap();
+
+        assert_eq!(client.breadcrumbs().await.unwrap(), vec![first.clone()]);
+
+        // The most recently viewed room doesn't need to be stored again.
+        client.track_room_view(&first).await.unwrap();
+
+        client.track_room_view(&second).await.unwrap();
+        assert_eq!(client.breadcrumbs().await.unwrap(), vec![second, first]);
+
+        set.assert();
+    }
+
+    #[tokio::test]
+    async fn keywords() {
+        use crate::events::EventJson;
+
+        async fn highlights(
+            client: &Client,
+            room_id: &RoomId,
+            event: &EventJson<RoomEvent>,
+        ) -> bool {
+            client
+                .base_client
+                .push_actions_for_event(room_id, event)
+                .await
+                .unwrap()
+                .highlight
+        }
+
+        let homeserver = Url::from_str(&mockito::server_url()).unwrap();
+        let room_id = RoomId::try_from("!SVkFJHzfwvuaIEawgC:localhost").unwrap();
+
+        let session = Session {
+            access_token: "1234".to_owned(),
+            user_id: UserId::try_from("@example:localhost").unwrap(),
+            device_id: "DEVICEID".to_owned(),
+        };
+
+        let _sync = mock(
+            "GET",
+            Matcher::Regex(r"^/_matrix/client/r0/sync\?.*$".to_string()),
+        )
+        .with_status(200)
+        .with_body_from_file("../test_data/sync.json")
+        .create();
+
+        let add = mock(
+            "PUT",
+            "/_matrix/client/r0/pushrules/global/content/rust%20sdk",
+        )
+        .match_body(Matcher::PartialJson(serde_json::json!({
+            "pattern": "rust sdk",
+            "actions": [
+                "notify",
+                { "set_tweak": "sound", "value": "default" },
+                { "set_tweak": "highlight" }
+            ],
+        })))
+        .with_status(200)
+        .with_body("{}")
+        .create();
+
+        let remove = mock(
+            "DELETE",
+            "/_matrix/client/r0/pushrules/global/content/rust%20sdk",
+        )
+        .with_status(200)
+        .with_body("{}")
+        .create();
+
+        let client = Client::new(homeserver, Some(session)).unwrap();
+        client.sync(SyncSettings::new()).await.unwrap();
+        client
+            .base_client
+            .receive_global_account_data(
+                "m.push_rules",
+                serde_json::json!({
+                    "global": {
+                        "override": [],
+                        "content": [],
+                        "room": [],
+                        "sender": [],
+                        "underride": []
+                    }
+                }),
+            )
+            .await
+            .unwrap();
+
+        let event = serde_json::from_value::<EventJson<RoomEvent>>(serde_json::json!({
+            "content": { "body": "The Rust SDK is great", "msgtype": "m.text" },
+            "event_id": "$message:localhost",
+            "origin_server_ts": 1_589_000_000_000u64,
+            "sender": "@alice:localhost",
+            "type": "m.room.message"
+        }))
+        .unwrap();
+
+        assert!(!highlights(&client, &room_id, &event).await);
+
+        client.add_keyword("rust sdk").await.unwrap();
+        assert_eq!(client.keywords().await, vec!["rust sdk"]);
+        assert!(highlights(&client, &room_id, &event).await);
+
+        client.remove_keyword("rust sdk").await.unwrap();
+        assert!(client.keywords().await.is_empty());
+        assert!(!highlights(&client, &room_id, &event).await);
+
+        add.assert();
+        remove.assert();
+    }
+
+    #[tokio::test]
+    async fn quirks() {
+        use crate::api::error::ErrorKind;
+        use crate::{FromHttpResponseError, ServerError};
+
+        let homeserver = Url::from_str(&mockito::server_url()).unwrap();
+        let room_id = RoomId::try_from("!quirks:localhost").unwrap();
+
+        let session = Session {
+            access_token: "1234".to_owned(),
+            user_id: UserId::try_from("@example:localhost").unwrap(),
+            device_id: "DEVICEID".to_owned(),
+        };
+
+        let _version = mock("GET", "/_matrix/federation/v1/version")
+            .with_status(200)
+            .with_body(
+                serde_json::json!({ "server": { "name": "Synapse", "version": "1.15.0" } })
+                    .to_string(),
+            )
+            .create();
+
+        let _leave = mock(
+            "POST",
+            Matcher::Regex(r"^/_matrix/client/r0/rooms/.*quirks.*/leave".to_string()),
+        )
+        .with_status(502)
+        .with_body("<html><body>Bad Gateway</body></html>")
+        .create();
+
+        let client = Client::new(homeserver.clone(), Some(session.clone())).unwrap();
+
+        match client.leave_room(&room_id).await {
+            Err(crate::Error::RumaResponse(FromHttpResponseError::Http(ServerError::Known(
+                error,
+            )))) => {
+                assert!(matches!(error.kind, ErrorKind::Unknown));
+                assert_eq!(error.message, "<html><body>Bad Gateway</body></html>");
+            }
+            other => panic!("expected a known server error, got {:?}", other),
+        }
+
+        let quirks = client.detect_quirks().await;
+        assert!(!quirks.sync_defaults);
+        assert!(quirks.error_bodies);
+        assert_eq!(client.quirks().await, quirks);
+
+        let config = ClientConfig::new().disable_quirks();
+        let client = Client::new_with_config(homeserver, Some(session), config).unwrap();
+
+        assert!(!client.detect_quirks().await.error_bodies);
+        assert!(matches!(
+            client.leave_room(&room_id).await,
+            Err(crate::Error::RumaResponse(FromHttpResponseError::Http(
+                ServerError::Unknown(_)
+            )))
+        ));
+    }
+
+    #[tokio::test]
+    async fn join_space() {
+        let homeserver = Url::from_str(&mockito::server_url()).unwrap();
+        let space_id = RoomId::try_from("!space:example.org").unwrap();
+        let suggested_id = RoomId::try_from("!suggested:example.org").unwrap();
+
+        let session = Session {
+            access_token: "1234".to_owned(),
+            user_id: UserId::try_from("@example:localhost").unwrap(),
+            device_id: "DEVICEID".to_owned(),
+        };
+
+        let _join_space = mock(
+            "POST",
+            Matcher::Regex(r"^/_matrix/client/r0/rooms/.*/join".to_string()),
+        )
+        .with_status(200)
+        .with_body(serde_json::json!({ "room_id": "!space:example.org" }).to_string())
+        .create();
+
+        let _hierarchy = mock(
+            "GET",
+            Matcher::Regex(r"^/_matrix/client/v1/rooms/.*/hierarchy".to_string()),
+        )
+        .match_query(Matcher::AllOf(vec![
+            Matcher::UrlEncoded("max_depth".into(), "1".into()),
+            Matcher::UrlEncoded("suggested_only".into(), "true".into()),
+        ]))
+        .with_status(200)
+        .with_body(
+            serde_json::json!({
+                "rooms": [{
+                    "room_id": "!space:example.org",
+                    "num_joined_members": 2,
+                    "room_type": "m.space",
+                    "children_state": [
+                        {
+                            "type": "m.space.child",
+                            "state_key": "!suggested:example.org",
+                            "sender": "@admin:example.org",
+                            "origin_server_ts": 1_629_413_349_153u64,
+                            "content": { "via": ["example.org"], "suggested": true }
+                        },
+                        {
+                            "type": "m.space.child",
+                            "state_key": "!other:example.org",
+                            "sender": "@admin:example.org",
+                            "origin_server_ts": 1_629_413_349_153u64,
+                            "content": { "via": ["example.org"] }
+                        }
+                    ]
+                }]
+            })
+            .to_string(),
+        )
+        .expect(1)
+        .create();
+
+        let _join_child = mock(
+            "POST",
+            Matcher::Regex(r"^/_matrix/client/r0/join/.*".to_string()),
+        )
+        .match_query(Matcher::UrlEncoded(
+            "server_name".into(),
+            "example.org".into(),
+        ))
+        .with_status(200)
+        .with_body(serde_json::json!({ "room_id": "!suggested:example.org" }).to_string())
+        .expect(1)
+        .create();
+
+        let config = ClientConfig::new().auto_join_suggested_rooms();
+        let client = Client::new_with_config(homeserver, Some(session), config).unwrap();
+
+        let mut updates = Vec::new();
+        let joined = client
+            .join_space(&space_id, |done, total| updates.push((done, total)))
+            .await
+            .unwrap();
+
+        assert_eq!(joined, vec![space_id, suggested_id.clone()]);
+        assert_eq!(updates, vec![(1, 1)]);
+        assert!(client.get_joined_room(&suggested_id).await.is_some());
+
+        _hierarchy.assert();
+        _join_child.assert();
+    }
+
+    #[tokio::test]
+    #[allow(irrefutable_let_patterns)]
+    async fn forget_room() {
+        let homeserver = Url::from_str(&mockito::server_url()).unwrap();
+        let user = UserId::try_from("@example:localhost").unwrap();
+        let room_id = RoomId::try_from("!testroom:example.org").unwrap();
+
+        let session = Session {
+            access_token: "1234".to_owned(),
+            user_id: user.clone(),
+            device_id: "DEVICEID".to_owned(),
+        };
+
+        let _m = mock(
+            "POST",
+            Matcher::Regex(r"^/_matrix/client/r0/rooms/.*/forget".to_string()),
+        )
+        .with_status(200)
+        // this is an empty JSON object
+        .with_body_from_file("../test_data/logout_response.json")
+        .create();
+
+        let client = Client::new(homeserver, Some(session)).unwrap();
+
+        let response = client.forget_room_by_id(&room_id).await.unwrap();
+        if let forget_room::Response = response {
+        } else {
+            panic!(
+                "expected `ruma_client_api::forget_room::Response` found {:?}",
+                response
+            )
+        }
+    }
+
+    #[tokio::test]
+    async fn erase_account() {
+        let homeserver = Url::from_str(&mockito::server_url()).unwrap();
+        let room_id = RoomId::try_from("!testroom:example.org").unwrap();
+
+        let session = Session {
+            access_token: "1234".to_owned(),
+            user_id: UserId::try_from("@example:localhost").unwrap(),
+            device_id: "DEVICEID".to_owned(),
+        };
+
+        let _leave = mock(
+            "POST",
+            Matcher::Regex(r"^/_matrix/client/r0/rooms/.*/leave".to_string()),
+        )
+        .with_status(200)
+        .with_body_from_file("../test_data/logout_response.json")
+        .expect(1)
+        .create();
+
+        let _forget = mock(
+            "POST",
+            Matcher::Regex(r"^/_matrix/client/r0/rooms/.*/forget".to_string()),
+        )
+        .with_status(200)
+        .with_body_from_file("../test_data/logout_response.json")
+        .expect(1)
+        .create();
+
+        let _deactivate = mock("POST", "/_matrix/client/r0/account/deactivate")
+            .match_body(Matcher::PartialJson(serde_json::json!({ "erase": true })))
+            .with_status(200)
+            .with_body(r#"{ "id_server_unbind_result": "no-support" }"#)
+            .expect(1)
+            .create();
+
+        let client = Client::new(homeserver, Some(session)).unwrap();
+        client
+            .base_client
+            .receive_joined_room(&room_id)
+            .await
+            .unwrap();
+
+        // Nothing is left if there is no way to authenticate the
+        // deactivation.
+        assert!(matches!(
+            client.erase_account(None, |_, _| {}).await,
+            Err(crate::Error::UiaaRequired)
+        ));
+        assert!(!client.joined_rooms().read().await.is_empty());
+
+        let auth_data = crate::AuthStage::Password {
+            password: "wordpass".to_owned(),
+        }
+        .into_auth_data(None, None);
+
+        let mut updates = Vec::new();
+        client
+            .erase_account(Some(auth_data), |done, total| updates.push((done, total)))
+            .await
+            .unwrap();
+
+        assert_eq!(updates, vec![(1, 1)]);
+        assert!(client.joined_rooms().read().await.is_empty());
+        assert!(client.left_rooms().read().await.is_empty());
+        assert!(!client.logged_in().await);
+
+        _leave.assert();
+        _forget.assert();
+        _deactivate.assert();
+    }
+
+    #[tokio::test]
+    #[allow(irrefutable_let_patterns)]
+    async fn read_receipt() {
+        let homeserver = Url::from_str(&mockito::server_url()).unwrap();
+        let user_id = UserId::try_from("@example:localhost").unwrap();
+        let room_id = RoomId::try_from("!testroom:example.org").unwrap();
+        let event_id = EventId::new("example.org").unwrap();
+
+        let session = Session {
+            access_token: "1234".to_owned(),
+            user_id,
+            device_id: "DEVICEID".to_owned(),
+        };
+
+        let _m = mock(
+            "POST",
+            Matcher::Regex(r"^/_matrix/client/r0/rooms/.*/receipt".to_string()),
+        )
+        .with_status(200)
+        // this is an empty JSON object
+        .with_body_from_file("../test_data/logout_response.json")
+        .create();
+
+        let client = Client::new(homeserver, Some(session)).unwrap();
+
+        let response = client.read_receipt(&room_id, &event_id).await.unwrap();
+        if let create_receipt::Response = response {
+        } else {
+            panic!(
+                "expected `ruma_client_api::create_receipt::Response` found {:?}",
+                response
+            )
+        }
+    }
+
+    #[tokio::test]
+    async fn read_marker() {
+        let homeserver = Url::from_str(&mockito::server_url()).unwrap();
+        let room_id = RoomId::try_from("!testroom:example.org").unwrap();
+        let fully_read = EventId::try_from("$fully_read:example.org").unwrap();
+        let read = EventId::try_from("$read:example.org").unwrap();
+
+        let session = Session {
+            access_token: "1234".to_owned(),
+            user_id: UserId::try_from("@example:localhost").unwrap(),
+            device_id: "DEVICEID".to_owned(),
+        };
+
+        let _m = mock(
+            "POST",
+            Matcher::Regex(r"^/_matrix/client/r0/rooms/.*/read_markers".to_string()),
+        )
+        .match_body(Matcher::Json(serde_json::json!({
+            "m.fully_read": "$fully_read:example.org",
+            "m.read": "$read:example.org",
+        })))
+        .with_status(200)
+        // this is an empty JSON object
+        .with_body_from_file("../test_data/logout_response.json")
+        .create();
+
+        let client = Client::new(homeserver, Some(session)).unwrap();
+        client
+            .base_client
+            .receive_joined_room(&room_id)
+            .await
+            .unwrap();
+
+        client
+            .read_marker(&room_id, &fully_read, Some(&read))
+            .await
+            .unwrap();
+
+        let room = client.get_joined_room(&room_id).await.unwrap();
+        let room = room.read().await;
+        assert_eq!(room.fully_read(), Some(&fully_read));
+        assert_eq!(room.read_receipt(), Some(&read));
+    }
+
+    #[tokio::test]
+    #[allow(irrefutable_let_patterns)]
+    async fn typing_notice() {
+        let homeserver = Url::from_str(&mockito::server_url()).unwrap();
+        let user = UserId::try_from("@example:localhost").unwrap();
+        let room_id = RoomId::try_from("!testroom:example.org").unwrap();
+
+        let session = Session {
+            access_token: "1234".to_owned(),
+            user_id: user,
+            device_id: "DEVICEID".to_owned(),
+        };
+
+        let _m = mock(
+            "PUT",
+            Matcher::Regex(r"^/_matrix/client/r0/rooms/.*/typing".to_string()),
+        )
+        .with_status(200)
+        // this is an empty JSON object
+        .with_body_from_file("../test_data/logout_response.json")
+        .create();
+
+        let client = Client::new(homeserver, Some(session)).unwrap();
+
+        let response = client.typing_notice(&room_id, true).await.unwrap();
+        if let create_typing_event::Response = response {
+        } else {
+            panic!(
+                "expected `ruma_client_api::create_typing_event::Response` found {:?}",
+                response
+            )
+        }
+    }
+
+    #[tokio::test]
+    async fn typing_notice_debounce() {
+        let homeserver = Url::from_str(&mockito::server_url()).unwrap();
+        let room_id = RoomId::try_from("!debounce:example.org").unwrap();
+
+        let session = Session {
+            access_token: "1234".to_owned(),
+            user_id: UserId::try_from("@example:localhost").unwrap(),
+            device_id: "DEVICEID".to_owned(),
+        };
+
+        let typing = mock(
+            "PUT",
+            Matcher::Regex(r"^/_matrix/client/r0/rooms/%21debounce.*/typing".to_string()),
+        )
+        .match_body(Matcher::PartialJson(serde_json::json!({ "typing": true })))
+        .with_status(200)
+        .with_body("{}")
+        .expect(2)
+        .create();
+
+        let stopped = mock(
+            "PUT",
+            Matcher::Regex(r"^/_matrix/client/r0/rooms/%21debounce.*/typing".to_string()),
+        )
+        .match_body(Matcher::PartialJson(serde_json::json!({ "typing": false })))
+        .with_status(200)
+        .with_body("{}")
+        .expect(1)
+        .create();
+
+        let config = ClientConfig::new().typing_notice_timeout(Duration::from_millis(400));
+        let client = Client::new_with_config(homeserver, Some(session), config).unwrap();
+
+        client.typing_notice(&room_id, true).await.unwrap();
+        client.typing_notice(&room_id, true).await.unwrap();
+
+        // Once the notice is about to expire it is sent again.
+        super::sleep::new(Duration::from_millis(350)).await;
+        client.typing_notice(&room_id, true).await.unwrap();
+
+        client.typing_notice(&room_id, false).await.unwrap();
+        client.typing_notice(&room_id, false).await.unwrap();
+
+        typing.assert();
+        stopped.assert();
+    }
+
+    #[tokio::test]
+    async fn devices() {
+        let homeserver = Url::from_str(&mockito::server_url()).unwrap();
+
+        let session = Session {
+            access_token: "1234".to_owned(),
+            user_id: UserId::try_from("@example:localhost").unwrap(),
+            device_id: "DEVICEID".to_owned(),
+        };
+
+        let _m = mock("GET", "/_matrix/client/r0/devices")
+            .with_status(200)
+            .with_body_from_file("../test_data/devices.json")
+            .create();
+
+        let client = Client::new(homeserver, Some(session)).unwrap();
+        let devices = client.devices().await.unwrap();
+
+        assert_eq!(devices.len(), 1);
+        assert_eq!(devices[0].device_id, "QBUAZIFURK");
+        assert_eq!(devices[0].display_name.as_deref(), Some("android"));
+        assert_eq!(devices[0].last_seen_ip.as_deref(), Some("1.2.3.4"));
+    }
+
+    #[tokio::test]
+    async fn delete_devices() {
+        let homeserver = Url::from_str(&mockito::server_url()).unwrap();
+
+        let session = Session {
+            access_token: "1234".to_owned(),
+            user_id: UserId::try_from("@example:localhost").unwrap(),
+            device_id: "DEVICEID".to_owned(),
+        };
+
+        let _m = mock("POST", "/_matrix/client/r0/delete_devices")
+            .with_status(401)
+            .with_body_from_file("../test_data/delete_devices.json")
+            .create();
+
+        let client = Client::new(homeserver, Some(session)).unwrap();
+        let devices = &["QBUAZIFURK".to_owned()];
+
+        let error = client.delete_devices(devices, None).await.unwrap_err();
+        let info = error.uiaa_response().unwrap();
+
+        assert_eq!(info.session.as_deref(), Some("xxxxxxyz"));
+        assert_eq!(info.flows[0].stages, vec!["example.type.foo".to_owned()]);
+    }
+
+    #[tokio::test]
+    async fn delete_devices_with_password() {
+        let homeserver = Url::from_str(&mockito::server_url()).unwrap();
+
+        let session = Session {
+            access_token: "1234".to_owned(),
+            user_id: UserId::try_from("@example:localhost").unwrap(),
+            device_id: "DEVICEID".to_owned(),
+        };
+
+        let _m = mock("POST", "/_matrix/client/r0/delete_devices")
+            .match_body(Matcher::Json(
+                serde_json::json!({ "devices": ["QBUAZIFURK"] }),
+            ))
+            .with_status(401)
+            .with_body_from_file("../test_data/delete_devices.json")
+            .create();
+
+        let _m = mock("POST", "/_matrix/client/r0/delete_devices")
+            .match_body(Matcher::PartialJson(serde_json::json!({
+                "auth": {
+                    "type": "m.login.password",
+                    "session": "xxxxxxyz",
+                    "user": "@example:localhost",
+                    "password": "wordpass"
+                }
+            })))
+            .with_status(200)
+            .with_body_from_file("../test_data/logout_response.json")
+            .create();
+
+        let client = Client::new(homeserver, Some(session)).unwrap();
+        let devices = &["QBUAZIFURK".to_owned()];
+
+        client
+            .delete_devices_with_password(devices, "wordpass")
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn sign_out_other_devices() {
+        let homeserver = Url::from_str(&mockito::server_url()).unwrap();
+
+        let session = Session {
+            access_token: "1234".to_owned(),
+            user_id: UserId::try_from("@example:localhost").unwrap(),
+            device_id: "DEVICEID".to_owned(),
+        };
+
+        let _devices = mock("GET", "/_matrix/client/r0/devices")
+            .with_status(200)
+            .with_body_from_file("../test_data/devices.json")
+            .create();
+
+        let delete = mock("POST", "/_matrix/client/r0/delete_devices")
+            .match_body(Matcher::Json(
+                serde_json::json!({ "devices": ["QBUAZIFURK"] }),
+            ))
+            .with_status(200)
+            .with_body_from_file("../test_data/logout_response.json")
+            .expect(1)
+            .create();
+
+        let client = Client::new(homeserver, Some(session)).unwrap();
+        let signed_out = client.sign_out_other_devices(None).await.unwrap();
+
+        assert_eq!(signed_out, vec!["QBUAZIFURK".to_owned()]);
+        delete.assert();
+    }
+
+    #[tokio::test]
+    async fn change_password_with_uiaa_handler() {
+        use crate::api::r0::uiaa::UiaaInfo;
+        use crate::{AuthStage, UiaaHandler};
+
+        struct PasswordHandler;
+
+        #[async_trait::async_trait]
+        impl UiaaHandler for PasswordHandler {
+            async fn next_stage(&self, info: &UiaaInfo) -> Option<AuthStage> {
+                assert_eq!(info.session.as_deref(), Some("xxxxxxyz"));
+                Some(AuthStage::Password {
+                    password: "wordpass".to_owned(),
+                })
+            }
+        }
+
+        let homeserver = Url::from_str(&mockito::server_url()).unwrap();
+
+        let session = Session {
+            access_token: "1234".to_owned(),
+            user_id: UserId::try_from("@example:localhost").unwrap(),
+            device_id: "DEVICEID".to_owned(),
+        };
+
+        let _m = mock("POST", "/_matrix/client/r0/account/password")
+            .match_body(Matcher::Json(
+                serde_json::json!({ "new_password": "new_wordpass" }),
+            ))
+            .with_status(401)
+            .with_body_from_file("../test_data/delete_devices.json")
+            .create();
+
+        let _m = mock("POST", "/_matrix/client/r0/account/password")
+            .match_body(Matcher::PartialJson(serde_json::json!({
+                "auth": {
+                    "type": "m.login.password",
+                    "session": "xxxxxxyz",
+                    "user": "@example:localhost",
+                    "password": "wordpass"
+                }
+            })))
+            .with_status(200)
+            .with_body_from_file("../test_data/logout_response.json")
+            .create();
+
+        let client = Client::new(homeserver, Some(session)).unwrap();
+
+        assert!(client
+            .change_password("new_wordpass", None)
             .await
-            .expect("Keys don't need to be uploaded");
+            .unwrap_err()
+            .uiaa_response()
+            .is_some());
 
-        for request in requests.drain(..) {
-            let _response: send_event_to_device::Response = self.send(request).await?;
+        client.set_uiaa_handler(Box::new(PasswordHandler)).await;
+        client.change_password("new_wordpass", None).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn register_with_uiaa_handler() {
+        use crate::api::r0::uiaa::UiaaInfo;
+        use crate::{AuthStage, UiaaHandler};
+
+        struct DummyHandler;
+
+        #[async_trait::async_trait]
+        impl UiaaHandler for DummyHandler {
+            async fn next_stage(&self, info: &UiaaInfo) -> Option<AuthStage> {
+                assert_eq!(crate::remaining_stages(info), vec!["m.login.dummy"]);
+                Some(AuthStage::Dummy)
+            }
         }
 
-        Ok(())
-    }
+        let homeserver = Url::from_str(&mockito::server_url()).unwrap();
 
-    /// Upload the E2E encryption keys.
-    ///
-    /// This uploads the long lived device keys as well as the required amount
-    /// of one-time keys.
-    ///
-    /// # Panics
-    ///
-    /// Panics if the client isn't logged in, or if no encryption keys need to
-    /// be uploaded.
-    #[cfg(feature = "encryption")]
-    #[cfg_attr(docsrs, doc(cfg(feature = "encryption")))]
-    #[instrument]
-    async fn keys_upload(&self) -> Result<upload_keys::Response> {
-        let (device_keys, one_time_keys) = self
-            .base_client
-            .keys_for_upload()
+        // The mocks are matched in the order they were created.
+        let _m = mock("POST", "/_matrix/client/r0/register")
+            .match_body(Matcher::PartialJson(serde_json::json!({
+                "auth": { "type": "m.login.dummy", "session": "xxxxxxyz" }
+            })))
+            .with_status(200)
+            .with_body(
+                serde_json::json!({
+                    "user_id": "@example:localhost",
+                    "access_token": "abc123",
+                    "device_id": "GHTYAJCE"
+                })
+                .to_string(),
+            )
+            .create();
+
+        let _m = mock("POST", "/_matrix/client/r0/register")
+            .match_body(Matcher::PartialJson(serde_json::json!({
+                "username": "example",
+                "password": "wordpass"
+            })))
+            .with_status(401)
+            .with_body(
+                serde_json::json!({
+                    "flows": [{ "stages": ["m.login.dummy"] }],
+                    "params": {},
+                    "completed": [],
+                    "session": "xxxxxxyz"
+                })
+                .to_string(),
+            )
+            .create();
+
+        let client = Client::new(homeserver, None).unwrap();
+        client.set_uiaa_handler(Box::new(DummyHandler)).await;
+
+        let response = client
+            .register(Some("example"), "wordpass", None, None, None)
             .await
-            .expect("Keys don't need to be uploaded");
+            .unwrap();
 
-        debug!(
-            "Uploading encryption keys device keys: {}, one-time-keys: {}",
-            device_keys.is_some(),
-            one_time_keys.as_ref().map_or(0, |k| k.len())
+        assert_eq!(
+            response.user_id,
+            UserId::try_from("@example:localhost").unwrap()
         );
+        assert!(client.user_id().await.is_none());
+    }
 
-        let request = upload_keys::Request {
-            device_keys,
-            one_time_keys,
+    #[tokio::test]
+    async fn uiaa_handler_stage_limit() {
+        use crate::api::r0::uiaa::UiaaInfo;
+        use crate::{AuthStage, UiaaHandler};
+
+        struct WrongPasswordHandler;
+
+        #[async_trait::async_trait]
+        impl UiaaHandler for WrongPasswordHandler {
+            async fn next_stage(&self, _: &UiaaInfo) -> Option<AuthStage> {
+                Some(AuthStage::Password {
+                    password: "wrongpass".to_owned(),
+                })
+            }
+        }
+
+        let homeserver = Url::from_str(&mockito::server_url()).unwrap();
+
+        let session = Session {
+            access_token: "1234".to_owned(),
+            user_id: UserId::try_from("@example:localhost").unwrap(),
+            device_id: "DEVICEID".to_owned(),
         };
 
-        let response = self.send(request).await?;
-        self.base_client
-            .receive_keys_upload_response(&response)
-            .await?;
-        Ok(response)
-    }
+        let m = mock("POST", "/_matrix/client/r0/account/password")
+            .with_status(401)
+            .with_body_from_file("../test_data/delete_devices.json")
+            .expect(UIAA_MAX_STAGES + 1)
+            .create();
 
-    /// Get the current, if any, sync token of the client.
-    /// This will be None if the client didn't sync at least once.
-    pub async fn sync_token(&self) -> Option<String> {
-        self.base_client.sync_token().await
+        let client = Client::new(homeserver, Some(session)).unwrap();
+        client
+            .set_uiaa_handler(Box::new(WrongPasswordHandler))
+            .await;
+
+        assert!(client
+            .change_password("new_wordpass", None)
+            .await
+            .unwrap_err()
+            .uiaa_response()
+            .is_some());
+        m.assert();
     }
 
-    /// Query the server for users device keys.
-    ///
-    /// # Panics
-    ///
-    /// Panics if no key query needs to be done.
-    #[cfg(feature = "encryption")]
-    #[cfg_attr(docsrs, doc(cfg(feature = "encryption")))]
-    #[instrument]
-    async fn keys_query(&self) -> Result<get_keys::Response> {
-        let mut users_for_query = self
-            .base_client
-            .users_for_key_query()
+    #[tokio::test]
+    async fn upload() {
+        let homeserver = Url::from_str(&mockito::server_url()).unwrap();
+
+        let session = Session {
+            access_token: "1234".to_owned(),
+            user_id: UserId::try_from("@example:localhost").unwrap(),
+            device_id: "DEVICEID".to_owned(),
+        };
+
+        let _m = mock("POST", "/_matrix/media/r0/upload")
+            .match_header("content-type", "image/png")
+            .with_status(200)
+            .with_body_from_file("../test_data/upload_response.json")
+            .create();
+
+        let client = Client::new(homeserver, Some(session)).unwrap();
+
+        let mut image: &[u8] = b"\x89PNG\r\n\x1a\nimage";
+        let mut read = 0;
+
+        let response = client
+            .upload_with_progress(&mut image, None, |p| read = p)
             .await
-            .expect("Keys don't need to be uploaded");
+            .unwrap();
 
-        debug!(
-            "Querying device keys device for users: {:?}",
-            users_for_query
+        assert_eq!(read, 13);
+        assert_eq!(
+            response.content_uri,
+            "mxc://example.com/AQwafuaFswefuhsfAFAgsw"
         );
+    }
 
-        let mut device_keys: BTreeMap<UserId, Vec<DeviceId>> = BTreeMap::new();
-
-        for user in users_for_query.drain() {
-            device_keys.insert(user, Vec::new());
-        }
+    #[tokio::test]
+    async fn send_attachment() {
+        let homeserver = Url::from_str(&mockito::server_url()).unwrap();
+        let room_id = RoomId::try_from("!testroom:example.org").unwrap();
 
-        let request = get_keys::Request {
-            timeout: None,
-            device_keys,
-            token: None,
+        let session = Session {
+            access_token: "1234".to_owned(),
+            user_id: UserId::try_from("@example:localhost").unwrap(),
+            device_id: "DEVICEID".to_owned(),
         };
 
-        let response = self.send(request).await?;
-        self.base_client
-            .receive_keys_query_response(&response)
-            .await?;
+        let _m = mock("POST", "/_matrix/media/r0/upload")
+            .match_header("content-type", "image/jpeg")
+            .with_status(200)
+            .with_body_from_file("../test_data/upload_response.json")
+            .create();
 
-        Ok(response)
-    }
-}
+        let _m = mock(
+            "PUT",
+            Matcher::Regex(r"^/_matrix/client/r0/rooms/.*/send/m.room.message/".to_string()),
+        )
+        .match_body(Matcher::PartialJson(serde_json::json!({
+            "msgtype": "m.image",
+            "body": "my-cat.jpg",
+            "url": "mxc://example.com/AQwafuaFswefuhsfAFAgsw",
+            "info": { "mimetype": "image/jpeg", "size": 5 }
+        })))
+        .with_status(200)
+        .with_body_from_file("../test_data/event_id.json")
+        .create();
 
-#[cfg(test)]
-mod test {
-    use super::{
-        ban_user, create_receipt, create_typing_event, forget_room, invite_user, kick_user,
-        leave_room, Invite3pid, MessageEventContent, RoomIdOrAliasId,
-    };
-    use super::{Client, ClientConfig, Session, SyncSettings, Url};
-    use crate::events::collections::all::RoomEvent;
-    use crate::events::room::member::MembershipState;
-    use crate::events::room::message::TextMessageEventContent;
-    use crate::identifiers::{EventId, RoomId, UserId};
+        let client = Client::new(homeserver, Some(session)).unwrap();
+        let mut image: &[u8] = b"image";
 
-    use matrix_sdk_base::JsonStore;
-    use matrix_sdk_test::{EventBuilder, EventsFile};
+        let response = client
+            .send_attachment(&room_id, "my-cat.jpg", "image/jpeg", &mut image, None)
+            .await
+            .unwrap();
 
-    use mockito::{mock, Matcher};
-    use std::convert::TryFrom;
-    use std::str::FromStr;
-    use std::time::Duration;
+        assert_eq!(
+            EventId::try_from("$h29iv0s8:example.com").ok(),
+            response.event_id
+        );
+    }
 
     #[tokio::test]
-    async fn account_data() {
+    async fn set_room_avatar() {
         let homeserver = Url::from_str(&mockito::server_url()).unwrap();
+        let room_id = RoomId::try_from("!testroom:example.org").unwrap();
 
         let session = Session {
             access_token: "1234".to_owned(),
-            user_id: UserId::try_from("@example:example.com").unwrap(),
+            user_id: UserId::try_from("@example:localhost").unwrap(),
             device_id: "DEVICEID".to_owned(),
         };
 
+        let _m = mock("POST", "/_matrix/media/r0/upload")
+            .with_status(200)
+            .with_body_from_file("../test_data/upload_response.json")
+            .expect(2)
+            .create();
+
         let _m = mock(
             "GET",
-            Matcher::Regex(r"^/_matrix/client/r0/sync\?.*$".to_string()),
+            Matcher::Regex(
+                r"^/_matrix/media/r0/thumbnail/example.com/AQwafuaFswefuhsfAFAgsw".to_string(),
+            ),
         )
         .with_status(200)
-        .with_body_from_file("../test_data/sync.json")
+        .with_header("content-type", "image/png")
+        .with_body(&b"\x89PNG\r\n\x1a\nthumbnail"[..])
         .create();
 
-        let client = Client::new(homeserver, Some(session)).unwrap();
+        let state = mock(
+            "PUT",
+            Matcher::Regex(r"^/_matrix/client/r0/rooms/.*/state/m.room.avatar".to_string()),
+        )
+        .match_body(Matcher::PartialJson(serde_json::json!({
+            "url": "mxc://example.com/AQwafuaFswefuhsfAFAgsw",
+            "info": {
+                "mimetype": "image/png",
+                "thumbnail_url": "mxc://example.com/AQwafuaFswefuhsfAFAgsw",
+            },
+        })))
+        .with_status(200)
+        .with_body_from_file("../test_data/event_id.json")
+        .create();
 
-        let sync_settings = SyncSettings::new().timeout(Duration::from_millis(3000));
+        let client = Client::new(homeserver, Some(session)).unwrap();
 
-        let _response = client.sync(sync_settings).await.unwrap();
+        let uri = client
+            .set_room_avatar(&room_id, b"\x89PNG\r\n\x1a\nimage", "image/png")
+            .await
+            .unwrap();
 
-        // let bc = &client.base_client;
-        // let ignored_users = bc.ignored_users.read().await;
-        // assert_eq!(1, ignored_users.len())
+        assert_eq!(uri, "mxc://example.com/AQwafuaFswefuhsfAFAgsw");
+        state.assert();
     }
 
     #[tokio::test]
-    async fn room_creation() {
+    async fn get_attachment() {
+        let homeserver = Url::from_str(&mockito::server_url()).unwrap();
+
         let session = Session {
-            access_token: "12345".to_owned(),
+            access_token: "1234".to_owned(),
             user_id: UserId::try_from("@example:localhost").unwrap(),
             device_id: "DEVICEID".to_owned(),
         };
-        let homeserver = url::Url::parse(&mockito::server_url()).unwrap();
-        let client = Client::new(homeserver, Some(session)).unwrap();
 
-        let mut response = EventBuilder::default()
-            .add_room_event(EventsFile::Member, RoomEvent::RoomMember)
-            .add_room_event(EventsFile::PowerLevels, RoomEvent::RoomPowerLevels)
-            .build_sync_response();
+        let _m = mock(
+            "GET",
+            "/_matrix/media/r0/download/example.com/AQwafuaFswefuhsfAFAgsw",
+        )
+        .with_status(200)
+        .with_header("content-type", "image/jpeg")
+        .with_body("image")
+        .create();
 
-        client
-            .base_client
-            .receive_sync_response(&mut response)
-            .await
-            .unwrap();
-        let room_id = RoomId::try_from("!SVkFJHzfwvuaIEawgC:localhost").unwrap();
+        let client = Client::new(homeserver, Some(session)).unwrap();
+
+        let content: MessageEventContent = serde_json::from_value(serde_json::json!({
+            "msgtype": "m.image",
+            "body": "my-cat.jpg",
+            "url": "mxc://example.com/AQwafuaFswefuhsfAFAgsw"
+        }))
+        .unwrap();
 
         assert_eq!(
-            client.homeserver(),
-            &Url::parse(&mockito::server_url()).unwrap()
+            client.get_attachment(&content).await.unwrap(),
+            Some(b"image".to_vec())
         );
-
-        let room = client.get_joined_room(&room_id).await;
-        assert!(room.is_some());
+        assert_eq!(
+            client.get_attachment_thumbnail(&content).await.unwrap(),
+            None
+        );
+        assert!(client.get_media_content("example.com/foo").await.is_err());
     }
 
     #[tokio::test]
-    async fn login_error() {
+    async fn media_cache() {
         let homeserver = Url::from_str(&mockito::server_url()).unwrap();
 
-        let _m = mock("POST", "/_matrix/client/r0/login")
-            .with_status(403)
-            .with_body_from_file("../test_data/login_response_error.json")
-            .create();
-
-        let client = Client::new(homeserver, None).unwrap();
-
-        if let Err(err) = client.login("example", "wordpass", None, None).await {
-            if let crate::Error::RumaResponse(crate::FromHttpResponseError::Http(
-                crate::ServerError::Known(crate::api::Error {
-                    kind,
-                    message,
-                    status_code,
-                }),
-            )) = err
-            {
-                if let crate::api::error::ErrorKind::Forbidden = kind {
-                } else {
-                    panic!(
-                        "found the wrong `ErrorKind` {:?}, expected `Forbidden",
-                        kind
-                    );
-                }
-                assert_eq!(message, "Invalid password".to_string());
-                assert_eq!(status_code, http::StatusCode::from_u16(403).unwrap());
-            } else {
-                panic!(
-                    "found the wrong `Error` type {:?}, expected `Error::RumaResponse",
-                    err
-                );
-            }
-        } else {
-            panic!("this request should return an `Err` variant")
-        }
+        let session = Session {
+            access_token: "1234".to_owned(),
+            user_id: UserId::try_from("@example:localhost").unwrap(),
+            device_id: "DEVICEID".to_owned(),
+        };
+
+        let _m = mock(
+            "GET",
+            "/_matrix/media/r0/download/example.com/cachedMediaId",
+        )
+        .with_status(200)
+        .with_header("content-type", "image/jpeg")
+        .with_body("image")
+        .expect(1)
+        .create();
+
+        let config = ClientConfig::new().media_cache_size(1024);
+        let client = Client::new_with_config(homeserver, Some(session), config).unwrap();
+
+        let uri = "mxc://example.com/cachedMediaId";
+
+        assert_eq!(client.get_media_content(uri).await.unwrap(), b"image");
+        assert_eq!(client.get_media_content(uri).await.unwrap(), b"image");
+
+        _m.assert();
     }
 
     #[tokio::test]
-    async fn join_room_by_id() {
+    async fn room_message_send() {
+        use matrix_sdk_common::uuid::Uuid;
+
         let homeserver = Url::from_str(&mockito::server_url()).unwrap();
+        let user = UserId::try_from("@example:localhost").unwrap();
+        let room_id = RoomId::try_from("!testroom:example.org").unwrap();
 
         let session = Session {
             access_token: "1234".to_owned(),
-            user_id: UserId::try_from("@example:localhost").unwrap(),
+            user_id: user.clone(),
             device_id: "DEVICEID".to_owned(),
         };
 
         let _m = mock(
-            "POST",
-            Matcher::Regex(r"^/_matrix/client/r0/rooms/.*/join".to_string()),
+            "PUT",
+            Matcher::Regex(r"^/_matrix/client/r0/rooms/.*/send/".to_string()),
         )
         .with_status(200)
-        .with_body_from_file("../test_data/room_id.json")
+        .with_body_from_file("../test_data/event_id.json")
         .create();
 
         let client = Client::new(homeserver, Some(session)).unwrap();
-        let room_id = RoomId::try_from("!testroom:example.org").unwrap();
+
+        let content = MessageEventContent::Text(TextMessageEventContent {
+            body: "Hello world".to_owned(),
+            format: None,
+            formatted_body: None,
+            relates_to: None,
+        });
+        let txn_id = Uuid::new_v4();
+        let response = client
+            .room_send(&room_id, content, Some(txn_id))
+            .await
+            .unwrap();
 
         assert_eq!(
-            // this is the `join_by_room_id::Response` but since no PartialEq we check the RoomId field
-            client.join_room_by_id(&room_id).await.unwrap().room_id,
-            room_id
-        );
+            EventId::try_from("$h29iv0s8:example.com").ok(),
+            response.event_id
+        )
     }
 
     #[tokio::test]
-    async fn join_room_by_id_or_alias() {
+    async fn room_send_and_wait() {
+        use matrix_sdk_common::uuid::Uuid;
+
         let homeserver = Url::from_str(&mockito::server_url()).unwrap();
+        let room_id = RoomId::try_from("!SVkFJHzfwvuaIEawgC:localhost").unwrap();
+        let txn_id = Uuid::new_v4();
 
         let session = Session {
             access_token: "1234".to_owned(),
@@ -1375,34 +9069,62 @@ mod test {
             device_id: "DEVICEID".to_owned(),
         };
 
-        let _m = mock(
-            "POST",
-            Matcher::Regex(r"^/_matrix/client/r0/join/".to_string()),
+        let mut sync: serde_json::Value =
+            serde_json::from_str(include_str!("../../test_data/sync.json")).unwrap();
+        sync["rooms"]["join"][&room_id.to_string()]["timeline"]["events"][0]["unsigned"]
+            ["transaction_id"] = serde_json::json!(txn_id.to_string());
+
+        let _send = mock(
+            "PUT",
+            Matcher::Regex(r"^/_matrix/client/r0/rooms/.*/send/".to_string()),
         )
         .with_status(200)
-        .with_body_from_file("../test_data/room_id.json")
+        .with_body(r#"{ "event_id": "$152037280074GZeOm:localhost" }"#)
+        .create();
+
+        let _sync = mock(
+            "GET",
+            Matcher::Regex(r"^/_matrix/client/r0/sync\?.*$".to_string()),
+        )
+        .with_status(200)
+        .with_body(sync.to_string())
         .create();
 
         let client = Client::new(homeserver, Some(session)).unwrap();
-        let room_id = RoomIdOrAliasId::try_from("!testroom:example.org").unwrap();
+        let content = MessageEventContent::Text(TextMessageEventContent {
+            body: "baba".to_owned(),
+            format: None,
+            formatted_body: None,
+            relates_to: None,
+        });
 
-        assert_eq!(
-            // this is the `join_by_room_id::Response` but since no PartialEq we check the RoomId field
+        // Nobody syncs, the echo never arrives.
+        assert!(matches!(
             client
-                .join_room_by_id_or_alias(&room_id, &["server.com".to_string()])
-                .await
-                .unwrap()
-                .room_id,
-            RoomId::try_from("!testroom:example.org").unwrap()
+                .room_send_and_wait(&room_id, content.clone(), None, Duration::from_millis(100))
+                .await,
+            Err(crate::Error::EchoTimeout)
+        ));
+
+        let (event, _) = futures::join!(
+            client.room_send_and_wait(&room_id, content, Some(txn_id), Duration::from_secs(5)),
+            async {
+                super::sleep::new(Duration::from_millis(100)).await;
+                client.sync(SyncSettings::default()).await.unwrap();
+            }
         );
+
+        let event: serde_json::Value = serde_json::from_str(event.unwrap().json().get()).unwrap();
+        assert_eq!(event["event_id"], "$152037280074GZeOm:localhost");
+        assert_eq!(event["content"]["body"], "baba");
     }
 
     #[tokio::test]
-    #[allow(irrefutable_let_patterns)]
-    async fn invite_user_by_id() {
+    async fn edit_message() {
         let homeserver = Url::from_str(&mockito::server_url()).unwrap();
         let user = UserId::try_from("@example:localhost").unwrap();
         let room_id = RoomId::try_from("!testroom:example.org").unwrap();
+        let event_id = EventId::try_from("$original:example.org").unwrap();
 
         let session = Session {
             access_token: "1234".to_owned(),
@@ -1411,60 +9133,93 @@ mod test {
         };
 
         let _m = mock(
-            "POST",
-            Matcher::Regex(r"^/_matrix/client/r0/rooms/.*/invite".to_string()),
+            "PUT",
+            Matcher::Regex(r"^/_matrix/client/r0/rooms/.*/send/m.room.message/".to_string()),
         )
+        .match_body(Matcher::PartialJson(serde_json::json!({
+            "msgtype": "m.text",
+            "body": "* Hello world",
+            "m.new_content": {
+                "msgtype": "m.text",
+                "body": "Hello world",
+            },
+            "m.relates_to": {
+                "rel_type": "m.replace",
+                "event_id": "$original:example.org",
+            },
+        })))
         .with_status(200)
-        .with_body_from_file("../test_data/logout_response.json")
+        .with_body_from_file("../test_data/event_id.json")
         .create();
 
         let client = Client::new(homeserver, Some(session)).unwrap();
 
-        if let invite_user::Response = client.invite_user_by_id(&room_id, &user).await.unwrap() {}
+        let content = MessageEventContent::Text(TextMessageEventContent {
+            body: "Hello world".to_owned(),
+            format: None,
+            formatted_body: None,
+            relates_to: None,
+        });
+        let response = client
+            .edit_message(&room_id, &event_id, content)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            EventId::try_from("$h29iv0s8:example.com").ok(),
+            response.event_id
+        )
     }
 
     #[tokio::test]
-    #[allow(irrefutable_let_patterns)]
-    async fn invite_user_by_3pid() {
+    async fn react() {
         let homeserver = Url::from_str(&mockito::server_url()).unwrap();
-        let user = UserId::try_from("@example:localhost").unwrap();
         let room_id = RoomId::try_from("!testroom:example.org").unwrap();
+        let event_id = EventId::try_from("$reacted:example.org").unwrap();
 
         let session = Session {
             access_token: "1234".to_owned(),
-            user_id: user.clone(),
+            user_id: UserId::try_from("@example:localhost").unwrap(),
             device_id: "DEVICEID".to_owned(),
         };
 
         let _m = mock(
-            "POST",
-            Matcher::Regex(r"^/_matrix/client/r0/rooms/.*/invite".to_string()),
+            "PUT",
+            Matcher::Regex(r"^/_matrix/client/r0/rooms/.*/send/m.reaction/".to_string()),
         )
+        .match_body(Matcher::Json(serde_json::json!({
+            "m.relates_to": {
+                "rel_type": "m.annotation",
+                "event_id": "$reacted:example.org",
+                "key": "👍",
+            },
+        })))
         .with_status(200)
-        .with_body_from_file("../test_data/logout_response.json")
+        .with_body_from_file("../test_data/event_id.json")
         .create();
 
         let client = Client::new(homeserver, Some(session)).unwrap();
 
-        if let invite_user::Response = client
-            .invite_user_by_3pid(
-                &room_id,
-                &Invite3pid {
-                    id_server: "example.org".to_string(),
-                    id_access_token: "IdToken".to_string(),
-                    medium: crate::api::r0::thirdparty::Medium::Email,
-                    address: "address".to_string(),
-                },
-            )
+        let response = client.react(&room_id, &event_id, "👍").await.unwrap();
+
+        assert_eq!(
+            EventId::try_from("$h29iv0s8:example.com").ok(),
+            response.event_id
+        );
+
+        // We don't know about any reaction of our own user.
+        assert!(client
+            .redact_reaction(&room_id, &event_id, "👍")
             .await
             .unwrap()
-        {}
+            .is_none());
     }
 
     #[tokio::test]
-    #[allow(irrefutable_let_patterns)]
-    async fn leave_room() {
+    async fn redact() {
         let homeserver = Url::from_str(&mockito::server_url()).unwrap();
+        let room_id = RoomId::try_from("!testroom:example.org").unwrap();
+        let event_id = EventId::try_from("$redacted:example.org").unwrap();
 
         let session = Session {
             access_token: "1234".to_owned(),
@@ -1473,251 +9228,348 @@ mod test {
         };
 
         let _m = mock(
-            "POST",
-            Matcher::Regex(r"^/_matrix/client/r0/rooms/.*/leave".to_string()),
+            "PUT",
+            Matcher::Regex(r"^/_matrix/client/r0/rooms/.*/redact/.*".to_string()),
         )
+        .match_body(Matcher::Json(serde_json::json!({ "reason": "Spam" })))
         .with_status(200)
-        // this is an empty JSON object
-        .with_body_from_file("../test_data/logout_response.json")
+        .with_body_from_file("../test_data/event_id.json")
+        .expect(1)
         .create();
 
         let client = Client::new(homeserver, Some(session)).unwrap();
-        let room_id = RoomId::try_from("!testroom:example.org").unwrap();
 
-        let response = client.leave_room(&room_id).await.unwrap();
-        if let leave_room::Response = response {
-        } else {
-            panic!(
-                "expected `ruma_client_api::leave_room::Response` found {:?}",
-                response
-            )
-        }
+        client
+            .redact(&room_id, &event_id, Some("Spam".to_owned()))
+            .await
+            .unwrap();
+
+        _m.assert();
     }
 
     #[tokio::test]
-    #[allow(irrefutable_let_patterns)]
-    async fn ban_user() {
+    async fn send_queue() {
+        use crate::SendState;
+        use futures::StreamExt;
+
         let homeserver = Url::from_str(&mockito::server_url()).unwrap();
-        let user = UserId::try_from("@example:localhost").unwrap();
         let room_id = RoomId::try_from("!testroom:example.org").unwrap();
 
         let session = Session {
             access_token: "1234".to_owned(),
-            user_id: user.clone(),
+            user_id: UserId::try_from("@example:localhost").unwrap(),
             device_id: "DEVICEID".to_owned(),
         };
 
+        let client = Client::new(homeserver, Some(session)).unwrap();
+        let mut updates = client.send_queue_updates().await;
+
+        let content = MessageEventContent::Text(TextMessageEventContent {
+            body: "Hello world".to_owned(),
+            format: None,
+            formatted_body: None,
+            relates_to: None,
+        });
+        let txn_id = client.queue_message(&room_id, content).await.unwrap();
+
         let _m = mock(
-            "POST",
-            Matcher::Regex(r"^/_matrix/client/r0/rooms/.*/ban".to_string()),
+            "PUT",
+            Matcher::Regex(format!(
+                r"^/_matrix/client/r0/rooms/.*/send/m.room.message/{}",
+                txn_id
+            )),
         )
         .with_status(200)
-        // this is an empty JSON object
-        .with_body_from_file("../test_data/logout_response.json")
+        .with_body_from_file("../test_data/event_id.json")
         .create();
 
-        let client = Client::new(homeserver, Some(session)).unwrap();
+        client.send_queued_messages().await.unwrap();
 
-        let response = client.ban_user(&room_id, &user, None).await.unwrap();
-        if let ban_user::Response = response {
-        } else {
-            panic!(
-                "expected `ruma_client_api::ban_user::Response` found {:?}",
-                response
-            )
-        }
+        assert!(client.queued_messages(None).await.is_empty());
+
+        let event_id = EventId::try_from("$h29iv0s8:example.com").unwrap();
+        assert_eq!(
+            updates.next().await,
+            Some((txn_id.clone(), SendState::Queued))
+        );
+        assert_eq!(
+            updates.next().await,
+            Some((txn_id.clone(), SendState::Sending))
+        );
+        assert_eq!(
+            updates.next().await,
+            Some((txn_id, SendState::Sent(event_id)))
+        );
     }
 
     #[tokio::test]
-    #[allow(irrefutable_let_patterns)]
-    async fn kick_user() {
+    async fn url_previews() {
         let homeserver = Url::from_str(&mockito::server_url()).unwrap();
-        let user = UserId::try_from("@example:localhost").unwrap();
-        let room_id = RoomId::try_from("!testroom:example.org").unwrap();
+        let room_id = RoomId::try_from("!SVkFJHzfwvuaIEawgC:localhost").unwrap();
+        let event_id = EventId::try_from("$152037280074GZeOm:localhost").unwrap();
 
         let session = Session {
             access_token: "1234".to_owned(),
-            user_id: user.clone(),
+            user_id: UserId::try_from("@example:localhost").unwrap(),
             device_id: "DEVICEID".to_owned(),
         };
 
+        let mut sync: serde_json::Value =
+            serde_json::from_str(include_str!("../../test_data/sync.json")).unwrap();
+        sync["rooms"]["join"][&room_id.to_string()]["timeline"]["events"][0]["content"]["body"] =
+            serde_json::json!("Have a look at https://matrix.org.");
+
         let _m = mock(
-            "POST",
-            Matcher::Regex(r"^/_matrix/client/r0/rooms/.*/kick".to_string()),
+            "GET",
+            Matcher::Regex(r"^/_matrix/client/r0/sync\?.*$".to_string()),
         )
         .with_status(200)
-        // this is an empty JSON object
-        .with_body_from_file("../test_data/logout_response.json")
+        .with_body(sync.to_string())
         .create();
 
-        let client = Client::new(homeserver, Some(session)).unwrap();
+        let _preview = mock("GET", "/_matrix/media/r0/preview_url")
+            .match_query(Matcher::UrlEncoded(
+                "url".into(),
+                "https://matrix.org".into(),
+            ))
+            .with_status(200)
+            .with_body(r#"{ "og:title": "Matrix.org", "og:image": "mxc://example.com/image" }"#)
+            .create();
 
-        let response = client.kick_user(&room_id, &user, None).await.unwrap();
-        if let kick_user::Response = response {
-        } else {
-            panic!(
-                "expected `ruma_client_api::kick_user::Response` found {:?}",
-                response
-            )
-        }
+        let config = ClientConfig::new().url_previews();
+        let client = Client::new_with_config(homeserver, Some(session), config).unwrap();
+
+        let sync_settings = SyncSettings::new().timeout(Duration::from_millis(3000));
+        let _response = client.sync(sync_settings).await.unwrap();
+        client.fetch_url_previews().await;
+
+        let room = client.get_joined_room(&room_id).await.unwrap();
+        let room = room.read().await;
+        let previews = room.url_previews(&event_id);
+
+        assert_eq!(previews.len(), 1);
+        assert_eq!(previews[0].url, "https://matrix.org");
+        assert_eq!(previews[0].title.as_deref(), Some("Matrix.org"));
     }
 
     #[tokio::test]
-    #[allow(irrefutable_let_patterns)]
-    async fn forget_room() {
+    async fn avatar_prefetch() {
+        use super::get_content_thumbnail;
+        use crate::js_int::UInt;
+
         let homeserver = Url::from_str(&mockito::server_url()).unwrap();
-        let user = UserId::try_from("@example:localhost").unwrap();
-        let room_id = RoomId::try_from("!testroom:example.org").unwrap();
+        let room_id = RoomId::try_from("!SVkFJHzfwvuaIEawgC:localhost").unwrap();
 
         let session = Session {
             access_token: "1234".to_owned(),
-            user_id: user.clone(),
+            user_id: UserId::try_from("@example:localhost").unwrap(),
             device_id: "DEVICEID".to_owned(),
         };
 
+        let mut sync: serde_json::Value =
+            serde_json::from_str(include_str!("../../test_data/sync.json")).unwrap();
+        let state = sync["rooms"]["join"][&room_id.to_string()]["state"]["events"]
+            .as_array_mut()
+            .unwrap();
+
+        for event in state {
+            if event["state_key"] == "@example2:localhost" {
+                event["content"]["avatar_url"] =
+                    serde_json::json!("mxc://example.com/memberAvatar");
+            }
+        }
+
         let _m = mock(
-            "POST",
-            Matcher::Regex(r"^/_matrix/client/r0/rooms/.*/forget".to_string()),
+            "GET",
+            Matcher::Regex(r"^/_matrix/client/r0/sync\?.*$".to_string()),
         )
         .with_status(200)
-        // this is an empty JSON object
-        .with_body_from_file("../test_data/logout_response.json")
+        .with_body(sync.to_string())
         .create();
 
-        let client = Client::new(homeserver, Some(session)).unwrap();
+        let thumbnail = mock(
+            "GET",
+            Matcher::Regex(r"^/_matrix/media/r0/thumbnail/example.com/memberAvatar".to_string()),
+        )
+        .with_status(200)
+        .with_header("content-type", "image/png")
+        .with_body("avatar")
+        .expect(1)
+        .create();
+
+        let config = ClientConfig::new()
+            .media_cache_size(1024)
+            .avatar_prefetch(1024);
+        let client = Client::new_with_config(homeserver, Some(session), config).unwrap();
+
+        let sync_settings = SyncSettings::new().timeout(Duration::from_millis(3000));
+        let _response = client.sync(sync_settings).await.unwrap();
+
+        assert_eq!(client.prefetch_member_avatars().await, 6);
+        assert_eq!(client.prefetch_member_avatars().await, 0);
+
+        let size = UInt::from(32u32);
+        assert_eq!(
+            client
+                .get_thumbnail(
+                    "mxc://example.com/memberAvatar",
+                    size,
+                    size,
+                    Some(get_content_thumbnail::Method::Crop)
+                )
+                .await
+                .unwrap(),
+            b"avatar"
+        );
 
-        let response = client.forget_room_by_id(&room_id).await.unwrap();
-        if let forget_room::Response = response {
-        } else {
-            panic!(
-                "expected `ruma_client_api::forget_room::Response` found {:?}",
-                response
-            )
-        }
+        thumbnail.assert();
     }
 
     #[tokio::test]
-    #[allow(irrefutable_let_patterns)]
-    async fn read_receipt() {
+    async fn user_presence() {
         let homeserver = Url::from_str(&mockito::server_url()).unwrap();
-        let user_id = UserId::try_from("@example:localhost").unwrap();
-        let room_id = RoomId::try_from("!testroom:example.org").unwrap();
-        let event_id = EventId::new("example.org").unwrap();
 
         let session = Session {
             access_token: "1234".to_owned(),
-            user_id,
+            user_id: UserId::try_from("@example:localhost").unwrap(),
             device_id: "DEVICEID".to_owned(),
         };
 
         let _m = mock(
-            "POST",
-            Matcher::Regex(r"^/_matrix/client/r0/rooms/.*/receipt".to_string()),
+            "GET",
+            Matcher::Regex(r"^/_matrix/client/r0/sync\?.*$".to_string()),
         )
         .with_status(200)
-        // this is an empty JSON object
-        .with_body_from_file("../test_data/logout_response.json")
+        .with_body_from_file("../test_data/sync.json")
         .create();
 
         let client = Client::new(homeserver, Some(session)).unwrap();
 
-        let response = client.read_receipt(&room_id, &event_id).await.unwrap();
-        if let create_receipt::Response = response {
-        } else {
-            panic!(
-                "expected `ruma_client_api::create_receipt::Response` found {:?}",
-                response
-            )
+        let sync_settings = SyncSettings::new().timeout(Duration::from_millis(3000));
+
+        let _response = client.sync(sync_settings).await.unwrap();
+
+        let rooms_lock = &client.base_client.joined_rooms();
+        let rooms = rooms_lock.read().await;
+        let room = &rooms
+            .get(&RoomId::try_from("!SVkFJHzfwvuaIEawgC:localhost").unwrap())
+            .unwrap()
+            .read()
+            .await;
+
+        assert_eq!(2, room.members.len());
+        for member in room.members.values() {
+            assert_eq!(MembershipState::Join, member.membership);
         }
+
+        assert!(room.power_levels.is_some())
     }
 
     #[tokio::test]
-    #[allow(irrefutable_let_patterns)]
-    async fn typing_notice() {
+    async fn set_and_get_presence() {
+        use crate::events::presence::PresenceState;
+        use crate::js_int::UInt;
+
         let homeserver = Url::from_str(&mockito::server_url()).unwrap();
-        let user = UserId::try_from("@example:localhost").unwrap();
-        let room_id = RoomId::try_from("!testroom:example.org").unwrap();
+        let user_id = UserId::try_from("@example:localhost").unwrap();
+        let alice = UserId::try_from("@alice:localhost").unwrap();
 
         let session = Session {
             access_token: "1234".to_owned(),
-            user_id: user.clone(),
+            user_id: user_id.clone(),
             device_id: "DEVICEID".to_owned(),
         };
 
-        let _m = mock(
+        let _set = mock(
             "PUT",
-            Matcher::Regex(r"^/_matrix/client/r0/rooms/.*/typing".to_string()),
+            Matcher::Regex(r"^/_matrix/client/r0/presence/.*example.*/status".to_string()),
         )
+        .match_body(Matcher::PartialJson(serde_json::json!({
+            "presence": "unavailable",
+            "status_msg": "Out for lunch"
+        })))
         .with_status(200)
-        // this is an empty JSON object
-        .with_body_from_file("../test_data/logout_response.json")
+        .with_body("{}")
+        .create();
+
+        let _get = mock(
+            "GET",
+            Matcher::Regex(r"^/_matrix/client/r0/presence/.*alice.*/status".to_string()),
+        )
+        .with_status(200)
+        .with_body(
+            serde_json::json!({
+                "presence": "online",
+                "last_active_ago": 420845,
+                "currently_active": true
+            })
+            .to_string(),
+        )
         .create();
 
         let client = Client::new(homeserver, Some(session)).unwrap();
+        assert!(client.presence(&alice).await.is_none());
 
-        let response = client
-            .typing_notice(
-                &room_id,
-                &user,
-                true,
-                Some(std::time::Duration::from_secs(1)),
-            )
+        client
+            .set_presence(PresenceState::Unavailable, Some("Out for lunch"))
             .await
             .unwrap();
-        if let create_typing_event::Response = response {
-        } else {
-            panic!(
-                "expected `ruma_client_api::create_typing_event::Response` found {:?}",
-                response
-            )
-        }
+        let presence = client.presence(&user_id).await.unwrap();
+        assert_eq!(presence.presence, PresenceState::Unavailable);
+        assert_eq!(presence.status_msg.as_deref(), Some("Out for lunch"));
+
+        let presence = client.get_presence(&alice).await.unwrap();
+        assert_eq!(presence.presence, PresenceState::Online);
+        assert_eq!(presence.currently_active, Some(true));
+        assert_eq!(presence.last_active_ago, Some(UInt::from(420_845u32)));
+        assert_eq!(
+            client.presence(&alice).await.unwrap().presence,
+            PresenceState::Online
+        );
     }
 
     #[tokio::test]
-    async fn room_message_send() {
-        use matrix_sdk_common::uuid::Uuid;
+    async fn presence_stream() {
+        use crate::events::presence::PresenceState;
+        use futures::StreamExt;
 
         let homeserver = Url::from_str(&mockito::server_url()).unwrap();
-        let user = UserId::try_from("@example:localhost").unwrap();
-        let room_id = RoomId::try_from("!testroom:example.org").unwrap();
+        let user_id = UserId::try_from("@example:localhost").unwrap();
 
         let session = Session {
             access_token: "1234".to_owned(),
-            user_id: user.clone(),
+            user_id: user_id.clone(),
             device_id: "DEVICEID".to_owned(),
         };
 
         let _m = mock(
-            "PUT",
-            Matcher::Regex(r"^/_matrix/client/r0/rooms/.*/send/".to_string()),
+            "GET",
+            Matcher::Regex(r"^/_matrix/client/r0/sync\?.*$".to_string()),
         )
         .with_status(200)
-        .with_body_from_file("../test_data/event_id.json")
+        .with_body_from_file("../test_data/sync.json")
         .create();
 
         let client = Client::new(homeserver, Some(session)).unwrap();
+        let mut presence = client.presence_stream().await;
 
-        let content = MessageEventContent::Text(TextMessageEventContent {
-            body: "Hello world".to_owned(),
-            format: None,
-            formatted_body: None,
-            relates_to: None,
-        });
-        let txn_id = Uuid::new_v4();
-        let response = client
-            .room_send(&room_id, content, Some(txn_id))
-            .await
-            .unwrap();
+        let sync_settings = SyncSettings::new().timeout(Duration::from_millis(3000));
+        let _response = client.sync(sync_settings).await.unwrap();
 
         assert_eq!(
-            EventId::try_from("$h29iv0s8:example.com").ok(),
-            response.event_id
-        )
+            presence.next().await,
+            Some((user_id, PresenceState::Online))
+        );
     }
 
     #[tokio::test]
-    async fn user_presence() {
+    async fn room_event_streams() {
+        use crate::events::collections::all::RoomEvent;
+        use futures::{FutureExt, StreamExt};
+
         let homeserver = Url::from_str(&mockito::server_url()).unwrap();
+        let room_id = RoomId::try_from("!SVkFJHzfwvuaIEawgC:localhost").unwrap();
+        let other_room_id = RoomId::try_from("!other:localhost").unwrap();
 
         let session = Session {
             access_token: "1234".to_owned(),
@@ -1734,25 +9586,22 @@ mod test {
         .create();
 
         let client = Client::new(homeserver, Some(session)).unwrap();
+        let mut room_events = client.room_events(&room_id).await;
+        let mut other_room_events = client.room_events(&other_room_id).await;
+        let mut all_events = client.event_stream().await;
 
         let sync_settings = SyncSettings::new().timeout(Duration::from_millis(3000));
-
         let _response = client.sync(sync_settings).await.unwrap();
 
-        let rooms_lock = &client.base_client.joined_rooms();
-        let rooms = rooms_lock.read().await;
-        let room = &rooms
-            .get(&RoomId::try_from("!SVkFJHzfwvuaIEawgC:localhost").unwrap())
-            .unwrap()
-            .read()
-            .await;
-
-        assert_eq!(2, room.members.len());
-        for member in room.members.values() {
-            assert_eq!(MembershipState::Join, member.membership);
-        }
+        assert!(matches!(
+            room_events.next().await,
+            Some(RoomEvent::RoomMessage(_))
+        ));
+        assert!(other_room_events.next().now_or_never().is_none());
 
-        assert!(room.power_levels.is_some())
+        let (id, event) = all_events.next().await.unwrap();
+        assert_eq!(id, room_id);
+        assert!(matches!(event, RoomEvent::RoomMessage(_)));
     }
 
     #[tokio::test]
@@ -1783,7 +9632,7 @@ mod test {
             room_names.push(room.read().await.display_name())
         }
 
-        assert_eq!(vec!["example, example2"], room_names);
+        assert_eq!(vec!["alice and bob"], room_names);
     }
 
     #[tokio::test]
@@ -1819,6 +9668,63 @@ mod test {
             .is_some());
     }
 
+    #[tokio::test]
+    async fn accept_and_reject_invite() {
+        let session = Session {
+            access_token: "12345".to_owned(),
+            user_id: UserId::try_from("@bob:example.com").unwrap(),
+            device_id: "DEVICEID".to_owned(),
+        };
+
+        let homeserver = url::Url::parse(&mockito::server_url()).unwrap();
+        let client = Client::new(homeserver, Some(session)).unwrap();
+        let room_id = RoomId::try_from("!696r7674:example.com").unwrap();
+
+        let _m = mock(
+            "GET",
+            Matcher::Regex(r"^/_matrix/client/r0/sync\?.*$".to_string()),
+        )
+        .with_status(200)
+        .with_body_from_file("../test_data/invite_sync.json")
+        .create();
+
+        let _response = client.sync(SyncSettings::default()).await.unwrap();
+
+        {
+            let room = client.get_invited_room(&room_id).await.unwrap();
+            let room = room.read().await;
+            assert_eq!(
+                room.inviter(),
+                Some(&UserId::try_from("@alice:example.com").unwrap())
+            );
+            assert_eq!(room.display_name(), "My Room Name");
+        }
+
+        let _m = mock(
+            "POST",
+            Matcher::Regex(r"^/_matrix/client/r0/rooms/.*/leave".to_string()),
+        )
+        .with_status(200)
+        .with_body("{}")
+        .create();
+
+        client.reject_invite(&room_id).await.unwrap();
+        assert!(client.get_invited_room(&room_id).await.is_none());
+        assert!(client.get_left_room(&room_id).await.is_some());
+
+        let _m = mock(
+            "POST",
+            Matcher::Regex(r"^/_matrix/client/r0/rooms/.*/join".to_string()),
+        )
+        .with_status(200)
+        .with_body(serde_json::json!({ "room_id": room_id }).to_string())
+        .create();
+
+        client.accept_invite(&room_id).await.unwrap();
+        assert!(client.get_left_room(&room_id).await.is_none());
+        assert!(client.get_joined_room(&room_id).await.is_some());
+    }
+
     #[tokio::test]
     async fn left_rooms() {
         use std::convert::TryFrom;
@@ -1852,6 +9758,40 @@ mod test {
             .is_some())
     }
 
+    #[tokio::test]
+    async fn skip_left_rooms() {
+        use std::convert::TryFrom;
+
+        let session = Session {
+            access_token: "12345".to_owned(),
+            user_id: UserId::try_from("@example:localhost").unwrap(),
+            device_id: "DEVICEID".to_owned(),
+        };
+
+        let homeserver = url::Url::parse(&mockito::server_url()).unwrap();
+        let client = Client::new(homeserver, Some(session)).unwrap();
+
+        // The server ignores the filter and returns the left room anyway.
+        let _m = mock(
+            "GET",
+            Matcher::Regex(r"^/_matrix/client/r0/sync\?.*filter=.*include_leave.*$".to_string()),
+        )
+        .with_status(200)
+        .with_body_from_file("../test_data/leave_sync.json")
+        .create();
+
+        client
+            .sync(SyncSettings::default().skip_left_rooms(true))
+            .await
+            .unwrap();
+
+        assert!(client.left_rooms().read().await.is_empty());
+        assert!(client
+            .get_left_room(&RoomId::try_from("!SVkFJHzfwvuaIEawgC:localhost").unwrap())
+            .await
+            .is_none())
+    }
+
     #[tokio::test]
     async fn test_client_sync_store() {
         let homeserver = url::Url::from_str(&mockito::server_url()).unwrap();
@@ -1955,6 +9895,106 @@ mod test {
         assert!(client.sync_token().await.is_some());
     }
 
+    #[tokio::test]
+    async fn appservice_sync() {
+        let homeserver = Url::from_str(&mockito::server_url()).unwrap();
+        let user_id = UserId::try_from("@bot:localhost").unwrap();
+
+        let _m = mock(
+            "GET",
+            Matcher::Regex(r"^/_matrix/client/r0/sync\?.*$".to_string()),
+        )
+        .match_header("authorization", "Bearer as_token")
+        .with_status(200)
+        .with_body_from_file("../test_data/sync.json")
+        .create();
+
+        let client = Client::new_appservice(homeserver, "as_token", &user_id).unwrap();
+        assert!(!client
+            .base_client
+            .session()
+            .read()
+            .await
+            .as_ref()
+            .unwrap()
+            .has_device());
+
+        let sync_settings = SyncSettings::new().timeout(Duration::from_millis(3000));
+        let response = client.sync(sync_settings).await.unwrap();
+
+        assert_ne!(response.next_batch, "");
+        assert_eq!(client.user_id().await, Some(user_id));
+    }
+
+    #[tokio::test]
+    async fn appservice_devices() {
+        let homeserver = Url::from_str(&mockito::server_url()).unwrap();
+        let user_id = UserId::try_from("@bot:localhost").unwrap();
+
+        let devices = mock("GET", "/_matrix/client/r0/devices")
+            .with_status(200)
+            .with_body_from_file("../test_data/devices.json")
+            .expect(0)
+            .create();
+        let delete = mock("POST", "/_matrix/client/r0/delete_devices")
+            .with_status(200)
+            .with_body("{}")
+            .expect(0)
+            .create();
+
+        let client = Client::new_appservice(homeserver, "as_token", &user_id).unwrap();
+
+        // Without a device of our own every device would be one of the other
+        // devices, nothing may be signed out.
+        assert!(matches!(
+            client.sign_out_other_devices(None).await,
+            Err(crate::Error::DeviceRequired)
+        ));
+        assert!(matches!(
+            client.devices().await,
+            Err(crate::Error::DeviceRequired)
+        ));
+        assert!(matches!(
+            client
+                .delete_devices(&["QBUAZIFURK".to_owned()], None)
+                .await,
+            Err(crate::Error::DeviceRequired)
+        ));
+
+        devices.assert();
+        delete.assert();
+    }
+
+    #[tokio::test]
+    async fn sync_with_worker_pool() {
+        let homeserver = Url::from_str(&mockito::server_url()).unwrap();
+        let room_id = RoomId::try_from("!SVkFJHzfwvuaIEawgC:localhost").unwrap();
+
+        let session = Session {
+            access_token: "1234".to_owned(),
+            user_id: UserId::try_from("@example:localhost").unwrap(),
+            device_id: "DEVICEID".to_owned(),
+        };
+
+        let _m = mock(
+            "GET",
+            Matcher::Regex(r"^/_matrix/client/r0/sync\?.*$".to_string()),
+        )
+        .with_status(200)
+        .with_body_from_file("../test_data/sync.json")
+        .create();
+
+        let config = ClientConfig::new().sync_worker_pool(2);
+        let client = Client::new_with_config(homeserver, Some(session), config).unwrap();
+        assert!(client.base_client.worker_pool().is_some());
+
+        let sync_settings = SyncSettings::new().timeout(Duration::from_millis(3000));
+        let response = client.sync(sync_settings).await.unwrap();
+
+        assert_ne!(response.next_batch, "");
+        assert!(client.get_joined_room(&room_id).await.is_some());
+    }
+
     #[tokio::test]
     async fn room_names() {
         let homeserver = Url::from_str(&mockito::server_url()).unwrap();
@@ -1983,12 +10023,15 @@ mod test {
         for r in client.joined_rooms().read().await.values() {
             names.push(r.read().await.display_name());
         }
-        assert_eq!(vec!["tutorial"], names);
+        assert_eq!(vec!["#tutorial:localhost"], names);
         let room = client
             .get_joined_room(&RoomId::try_from("!SVkFJHzfwvuaIEawgC:localhost").unwrap())
             .await
             .unwrap();
 
-        assert_eq!("tutorial".to_string(), room.read().await.display_name());
+        assert_eq!(
+            "#tutorial:localhost".to_string(),
+            room.read().await.display_name()
+        );
     }
 }
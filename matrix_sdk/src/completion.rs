@@ -0,0 +1,240 @@
+// Copyright 2020 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashSet;
+
+use serde::Deserialize;
+
+use crate::identifiers::{RoomAliasId, RoomId};
+
+/// How well a room matches the prefix that is completed, better matches are
+/// ordered first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MatchKind {
+    /// An alias of the room is the completed text.
+    ExactAlias,
+    /// An alias of the room starts with the completed text.
+    AliasPrefix,
+    /// The name of the room starts with the completed text.
+    NamePrefix,
+    /// A word in the name of the room starts with the completed text.
+    WordPrefix,
+}
+
+/// A room suggested by `Client::complete_room()`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RoomSuggestion {
+    /// The unique id of the room.
+    pub room_id: RoomId,
+    /// The alias that matched, or the canonical alias of the room if its
+    /// name matched.
+    pub alias: Option<RoomAliasId>,
+    /// The display name of the room.
+    pub name: String,
+    /// Is our own user joined to the room, false for rooms that were found
+    /// in the room directory.
+    pub joined: bool,
+    /// How well the room matches the completed text.
+    pub match_kind: MatchKind,
+}
+
+impl RoomSuggestion {
+    /// Match a room against the text that is completed.
+    ///
+    /// Returns `None` if neither an alias nor the name of the room matches.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The unique id of the room.
+    ///
+    /// * `aliases` - The aliases of the room, the canonical alias first.
+    ///
+    /// * `name` - The display name of the room.
+    ///
+    /// * `joined` - Is our own user joined to the room.
+    ///
+    /// * `prefix` - The text that is completed.
+    pub(crate) fn new(
+        room_id: &RoomId,
+        aliases: &[RoomAliasId],
+        name: String,
+        joined: bool,
+        prefix: &str,
+    ) -> Option<Self> {
+        let prefix = prefix.trim().to_lowercase();
+
+        if prefix.is_empty() {
+            return None;
+        }
+
+        let alias_match = aliases.iter().find_map(|alias| {
+            let full = alias.to_string().to_lowercase();
+            // The prefix doesn't need to include the sigil of the alias.
+            let alias_text = if prefix.starts_with('#') {
+                full.as_str()
+            } else {
+                &full[1..]
+            };
+
+            if alias_text == prefix {
+                Some((MatchKind::ExactAlias, alias))
+            } else if alias_text.starts_with(&prefix) {
+                Some((MatchKind::AliasPrefix, alias))
+            } else {
+                None
+            }
+        });
+
+        let (match_kind, alias) = match alias_match {
+            Some((kind, alias)) => (kind, Some(alias.clone())),
+            None => {
+                let lowercase_name = name.to_lowercase();
+                let term = prefix.trim_start_matches('#');
+
+                let kind = if lowercase_name.starts_with(term) {
+                    MatchKind::NamePrefix
+                } else if lowercase_name
+                    .split(|c: char| !c.is_alphanumeric())
+                    .any(|word| !word.is_empty() && word.starts_with(term))
+                {
+                    MatchKind::WordPrefix
+                } else {
+                    return None;
+                };
+
+                (kind, aliases.first().cloned())
+            }
+        };
+
+        Some(Self {
+            room_id: room_id.clone(),
+            alias,
+            name,
+            joined,
+            match_kind,
+        })
+    }
+}
+
+/// Order the suggestions from the best to the worst match, preferring joined
+/// rooms, and remove the rooms that were suggested more than once.
+pub(crate) fn rank_suggestions(mut suggestions: Vec<RoomSuggestion>) -> Vec<RoomSuggestion> {
+    suggestions.sort_by(|a, b| {
+        a.match_kind
+            .cmp(&b.match_kind)
+            .then(b.joined.cmp(&a.joined))
+            .then_with(|| a.name.to_lowercase().cmp(&b.name.to_lowercase()))
+    });
+
+    let mut seen = HashSet::new();
+    suggestions.retain(|s| seen.insert(s.room_id.clone()));
+
+    suggestions
+}
+
+/// The part of a `/publicRooms` response that is needed for suggestions.
+#[derive(Deserialize)]
+pub(crate) struct PublicRooms {
+    #[serde(default)]
+    pub chunk: Vec<PublicRoom>,
+}
+
+/// A room of the room directory.
+#[derive(Deserialize)]
+pub(crate) struct PublicRoom {
+    pub room_id: RoomId,
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub canonical_alias: Option<RoomAliasId>,
+    #[serde(default)]
+    pub aliases: Vec<RoomAliasId>,
+}
+
+impl PublicRoom {
+    /// Match the room against the text that is completed.
+    pub fn suggestion(self, prefix: &str) -> Option<RoomSuggestion> {
+        let mut aliases: Vec<RoomAliasId> = self.canonical_alias.into_iter().collect();
+
+        for alias in self.aliases {
+            if !aliases.contains(&alias) {
+                aliases.push(alias);
+            }
+        }
+
+        let name = self
+            .name
+            .filter(|n| !n.trim().is_empty())
+            .or_else(|| aliases.first().map(|a| a.to_string()))
+            .unwrap_or_else(|| self.room_id.to_string());
+
+        RoomSuggestion::new(&self.room_id, &aliases, name, false, prefix)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::convert::TryFrom;
+
+    fn suggestion(room: &str, alias: &str, name: &str, joined: bool) -> Option<RoomSuggestion> {
+        RoomSuggestion::new(
+            &RoomId::try_from(room).unwrap(),
+            &[RoomAliasId::try_from(alias).unwrap()],
+            name.to_owned(),
+            joined,
+            "rust",
+        )
+    }
+
+    #[test]
+    fn room_suggestions() {
+        let prefixed = suggestion("!a:localhost", "#rust:localhost", "Rust", false).unwrap();
+        assert_eq!(prefixed.match_kind, MatchKind::AliasPrefix);
+
+        let word = suggestion("!b:localhost", "#sdk:localhost", "Matrix Rust SDK", true).unwrap();
+        assert_eq!(word.match_kind, MatchKind::WordPrefix);
+        assert_eq!(
+            word.alias,
+            Some(RoomAliasId::try_from("#sdk:localhost").unwrap())
+        );
+
+        let name = suggestion("!c:localhost", "#r:localhost", "rustaceans", false).unwrap();
+        assert_eq!(name.match_kind, MatchKind::NamePrefix);
+
+        let joined = suggestion("!d:localhost", "#rust-dev:localhost", "Dev", true).unwrap();
+
+        assert!(suggestion("!e:localhost", "#go:localhost", "Trusty", true).is_none());
+
+        let ranked = rank_suggestions(vec![
+            word.clone(),
+            name.clone(),
+            prefixed.clone(),
+            joined.clone(),
+            prefixed.clone(),
+        ]);
+        assert_eq!(ranked, vec![joined, prefixed, name, word]);
+
+        let exact = RoomSuggestion::new(
+            &RoomId::try_from("!a:localhost").unwrap(),
+            &[RoomAliasId::try_from("#rust:localhost").unwrap()],
+            "Rust".to_owned(),
+            false,
+            "#Rust:localhost",
+        )
+        .unwrap();
+        assert_eq!(exact.match_kind, MatchKind::ExactAlias);
+    }
+}
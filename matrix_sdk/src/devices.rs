@@ -0,0 +1,45 @@
+// Copyright 2020 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::time::SystemTime;
+
+use crate::api::r0::device::Device;
+use crate::identifiers::DeviceId;
+
+/// A device of the logged in user as seen by the homeserver.
+///
+/// This is the information the `/devices` endpoint returns, it has nothing
+/// to do with the encryption state of the device.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DeviceInfo {
+    /// The unique id of the device.
+    pub device_id: DeviceId,
+    /// The display name of the device, set by the user.
+    pub display_name: Option<String>,
+    /// The IP address the device was last seen at.
+    pub last_seen_ip: Option<String>,
+    /// The point in time the device was last seen at.
+    pub last_seen_ts: Option<SystemTime>,
+}
+
+impl From<Device> for DeviceInfo {
+    fn from(device: Device) -> Self {
+        Self {
+            device_id: device.device_id,
+            display_name: device.display_name,
+            last_seen_ip: device.last_seen_ip,
+            last_seen_ts: device.last_seen_ts,
+        }
+    }
+}
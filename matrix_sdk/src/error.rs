@@ -16,13 +16,19 @@
 
 use reqwest::Error as ReqwestError;
 use serde_json::Error as JsonError;
+use std::io::Error as IoError;
 use thiserror::Error;
 
+#[cfg(feature = "encryption")]
+use matrix_sdk_base::DecryptorError;
 use matrix_sdk_base::Error as MatrixError;
+use matrix_sdk_base::PermissionError;
 
+use crate::api::r0::uiaa::{UiaaInfo, UiaaResponse as UiaaError};
 use crate::api::Error as RumaClientError;
 use crate::FromHttpResponseError as RumaResponseError;
 use crate::IntoHttpError as RumaIntoHttpError;
+use crate::ServerError;
 
 /// Result type of the rust-sdk.
 pub type Result<T> = std::result::Result<T, Error>;
@@ -42,6 +48,10 @@ pub enum Error {
     #[error(transparent)]
     SerdeJson(#[from] JsonError),
 
+    /// An IO error happened, e.g. while reading the data of an upload.
+    #[error(transparent)]
+    Io(#[from] IoError),
+
     /// An error converting between ruma_client_api types and Hyper types.
     #[error("can't parse the JSON response as a Matrix response")]
     RumaResponse(RumaResponseError<RumaClientError>),
@@ -50,11 +60,95 @@ pub enum Error {
     #[error("can't convert between ruma_client_api and hyper types.")]
     IntoHttp(RumaIntoHttpError),
 
+    /// An error converting between ruma_client_api types and Hyper types for
+    /// an endpoint that uses user-interactive authentication.
+    #[error("can't parse the JSON response as a Matrix uiaa response")]
+    UiaaError(RumaResponseError<UiaaError>),
+
+    /// A request needs user-interactive authentication but neither
+    /// authentication data nor a `UiaaHandler` was given.
+    #[error(
+        "the request requires user-interactive authentication but no way to authenticate was given"
+    )]
+    UiaaRequired,
+
+    /// The request needs a device but the session isn't tied to one, e.g.
+    /// because it belongs to an appservice.
+    #[error("the session doesn't belong to a device")]
+    DeviceRequired,
+
+    /// A sent event didn't come down the sync stream before the timeout.
+    #[error("the sent event didn't come down the sync stream in time")]
+    EchoTimeout,
+
+    /// The given URI isn't a valid `mxc://` URI.
+    #[error("the media URI {0} isn't a valid mxc URI")]
+    InvalidMxcUri(String),
+
+    /// Our own user isn't allowed to perform the action in the room.
+    #[error(transparent)]
+    Permission(#[from] PermissionError),
+
+    /// The encryption info of an encrypted attachment is invalid.
+    #[cfg(feature = "encryption")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "encryption")))]
+    #[error(transparent)]
+    AttachmentDecryption(#[from] DecryptorError),
+
     /// An error occured in the Matrix client library.
     #[error(transparent)]
     MatrixError(#[from] MatrixError),
 }
 
+impl Error {
+    /// Try to destructure the error into an user-interactive authentication
+    /// info.
+    ///
+    /// Returns `Some` if the server asked for further authentication stages to
+    /// be completed before the request can succeed, `None` otherwise.
+    pub fn uiaa_response(&self) -> Option<&UiaaInfo> {
+        if let Error::UiaaError(RumaResponseError::Http(ServerError::Known(
+            UiaaError::AuthResponse(info),
+        ))) = self
+        {
+            Some(info)
+        } else {
+            None
+        }
+    }
+
+    /// Is the error likely to go away if the request is retried later on.
+    ///
+    /// This is the case for errors at the HTTP layer, e.g. if the server
+    /// couldn't be reached, server side errors and rate limiting.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Error::Reqwest(_) => true,
+            Error::RumaResponse(RumaResponseError::Http(ServerError::Known(e))) => {
+                e.status_code.is_server_error() || e.status_code.as_u16() == 429
+            }
+            Error::RumaResponse(RumaResponseError::Http(ServerError::Unknown(_))) => true,
+            _ => false,
+        }
+    }
+
+    /// Did the server refuse the request because we sent too many requests.
+    pub fn is_rate_limited(&self) -> bool {
+        match self {
+            Error::RumaResponse(RumaResponseError::Http(ServerError::Known(e))) => {
+                e.status_code.as_u16() == 429
+            }
+            _ => false,
+        }
+    }
+}
+
+impl From<RumaResponseError<UiaaError>> for Error {
+    fn from(error: RumaResponseError<UiaaError>) -> Self {
+        Self::UiaaError(error)
+    }
+}
+
 impl From<RumaResponseError<RumaClientError>> for Error {
     fn from(error: RumaResponseError<RumaClientError>) -> Self {
         Self::RumaResponse(error)
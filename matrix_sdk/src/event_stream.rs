@@ -0,0 +1,71 @@
+// Copyright 2020 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Streams of the timeline events the client receives.
+
+use std::sync::Arc;
+
+use futures_channel::mpsc::UnboundedSender;
+
+use matrix_sdk_base::{EventEmitter, EventOrigin, SyncRoom};
+use matrix_sdk_common::events::collections::all::RoomEvent;
+use matrix_sdk_common::identifiers::RoomId;
+use matrix_sdk_common::locks::Mutex;
+
+/// The sending half of a stream returned by `Client::room_events()` or
+/// `Client::event_stream()`.
+#[derive(Debug)]
+pub(crate) enum EventSender {
+    /// A stream of the events of a single room.
+    Room(RoomId, UnboundedSender<RoomEvent>),
+    /// A stream of the events of all rooms.
+    All(UnboundedSender<(RoomId, RoomEvent)>),
+}
+
+impl EventSender {
+    /// Send the event if the stream is interested in it.
+    ///
+    /// Returns false if the stream was dropped.
+    fn send(&self, room_id: &RoomId, event: &RoomEvent) -> bool {
+        match self {
+            EventSender::Room(id, sender) if id == room_id => {
+                sender.unbounded_send(event.clone()).is_ok()
+            }
+            EventSender::Room(_, sender) => !sender.is_closed(),
+            EventSender::All(sender) => sender
+                .unbounded_send((room_id.clone(), event.clone()))
+                .is_ok(),
+        }
+    }
+}
+
+/// An `EventEmitter` that feeds the live timeline events to the event
+/// streams, forgetting the streams that were dropped.
+#[derive(Debug)]
+pub(crate) struct EventStreamEmitter(pub(crate) Arc<Mutex<Vec<EventSender>>>);
+
+#[async_trait::async_trait]
+impl EventEmitter for EventStreamEmitter {
+    async fn on_timeline_event(&self, room: SyncRoom, event: &RoomEvent, origin: EventOrigin) {
+        if origin == EventOrigin::Backfill {
+            return;
+        }
+
+        let room_id = &room.context().room_id;
+        self.0
+            .lock()
+            .await
+            .retain(|sender| sender.send(room_id, event));
+    }
+}
@@ -0,0 +1,188 @@
+// Copyright 2020 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+
+use crate::events::room::message::MessageEventContent;
+use crate::identifiers::{EventId, UserId};
+
+/// An event that is imported into a room by `Client::import_history()`.
+#[derive(Clone, Debug)]
+pub struct HistoricalEvent {
+    /// The type of the event.
+    pub event_type: String,
+    /// The state key of the event, `None` for message events.
+    pub state_key: Option<String>,
+    /// The content of the event.
+    pub content: JsonValue,
+    /// The origin timestamp the event should have, usually the time the
+    /// event was sent on the bridged network.
+    pub timestamp: SystemTime,
+}
+
+impl HistoricalEvent {
+    /// Create a new historical message event.
+    ///
+    /// # Arguments
+    ///
+    /// * `event_type` - The type of the event.
+    ///
+    /// * `content` - The content of the event.
+    ///
+    /// * `timestamp` - The origin timestamp the event should have.
+    pub fn new(event_type: impl Into<String>, content: JsonValue, timestamp: SystemTime) -> Self {
+        Self {
+            event_type: event_type.into(),
+            state_key: None,
+            content,
+            timestamp,
+        }
+    }
+
+    /// Create a new historical `m.room.message` event.
+    ///
+    /// # Arguments
+    ///
+    /// * `content` - The content of the message.
+    ///
+    /// * `timestamp` - The origin timestamp the message should have.
+    pub fn message(content: &MessageEventContent, timestamp: SystemTime) -> Self {
+        let content = serde_json::to_value(content).unwrap_or_default();
+        Self::new("m.room.message", content, timestamp)
+    }
+
+    /// Create a new historical state event.
+    ///
+    /// # Arguments
+    ///
+    /// * `event_type` - The type of the event.
+    ///
+    /// * `state_key` - The state key of the event.
+    ///
+    /// * `content` - The content of the event.
+    ///
+    /// * `timestamp` - The origin timestamp the event should have.
+    pub fn state(
+        event_type: impl Into<String>,
+        state_key: impl Into<String>,
+        content: JsonValue,
+        timestamp: SystemTime,
+    ) -> Self {
+        Self {
+            state_key: Some(state_key.into()),
+            ..Self::new(event_type, content, timestamp)
+        }
+    }
+
+    /// Is this a state event.
+    pub fn is_state(&self) -> bool {
+        self.state_key.is_some()
+    }
+
+    /// The origin timestamp as milliseconds since the unix epoch.
+    pub(crate) fn timestamp_millis(&self) -> u64 {
+        self.timestamp
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64
+    }
+}
+
+/// The body of a MSC2716 `batch_send` request.
+#[derive(Serialize)]
+pub(crate) struct BatchSendRequest<'a> {
+    pub state_events_at_start: Vec<JsonValue>,
+    pub events: Vec<BatchEvent<'a>>,
+}
+
+/// A message event of a `batch_send` request.
+#[derive(Serialize)]
+pub(crate) struct BatchEvent<'a> {
+    #[serde(rename = "type")]
+    pub event_type: &'a str,
+    pub sender: &'a UserId,
+    pub origin_server_ts: u64,
+    pub content: &'a JsonValue,
+}
+
+impl<'a> BatchSendRequest<'a> {
+    /// Create the body of a `batch_send` request for the given message
+    /// events, all of them are sent by `sender`.
+    pub fn new(sender: &'a UserId, events: &'a [HistoricalEvent]) -> Self {
+        Self {
+            state_events_at_start: Vec::new(),
+            events: events
+                .iter()
+                .map(|e| BatchEvent {
+                    event_type: &e.event_type,
+                    sender,
+                    origin_server_ts: e.timestamp_millis(),
+                    content: &e.content,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// The part of a `batch_send` response that is needed to continue the
+/// import.
+#[derive(Deserialize)]
+pub(crate) struct BatchSendResponse {
+    #[serde(default)]
+    pub event_ids: Vec<EventId>,
+    pub next_batch_id: String,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::convert::TryFrom;
+    use std::time::Duration;
+
+    #[test]
+    fn batch_send_body() {
+        let sender = UserId::try_from("@_bridge:localhost").unwrap();
+        let timestamp = UNIX_EPOCH + Duration::from_millis(1_500_000_000_000);
+        let content = serde_json::json!({ "msgtype": "m.text", "body": "Hello" });
+
+        let events = vec![HistoricalEvent::new(
+            "m.room.message",
+            content.clone(),
+            timestamp,
+        )];
+        let topic = HistoricalEvent::state("m.room.topic", "", JsonValue::Null, timestamp);
+
+        assert!(!events[0].is_state());
+        assert!(topic.is_state());
+
+        let body = serde_json::to_value(BatchSendRequest::new(&sender, &events)).unwrap();
+
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "state_events_at_start": [],
+                "events": [{
+                    "type": "m.room.message",
+                    "sender": "@_bridge:localhost",
+                    "origin_server_ts": 1_500_000_000_000u64,
+                    "content": content,
+                }],
+            })
+        );
+    }
+}
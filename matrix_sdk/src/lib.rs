@@ -36,21 +36,61 @@
     unused_qualifications
 )]
 
+pub use matrix_sdk_base::{
+    AllowRule, BundledAnnotation, BundledReference, BundledRelations, BundledReplacement,
+    CustomStateEventContent, GuestAccess, HistoryVisibility, JoinRule, MemberExportFormat,
+    MessageEventContentExt, PendingEvent, PermissionError, PowerAction, PowerLevelChanges,
+    PushActions, Pusher, PusherData, QueuedMessage, Reaction, ReactionGroup, ReadReceipt,
+    RedactedBecause, RedactionContent, RelationChunk, RoomMember, RoomState, RoomStateSnapshot,
+    SearchContext, SearchOrder, SearchResult, SearchResults, SendState, ServerAcl, ServerNotice,
+    ServerNoticeKind, Space, SpaceChildContent, SpaceChildEvent, SpaceHierarchy,
+    SpaceHierarchyRoom, SpaceParentContent, SpaceTreeNode, StateChange, StateStore, StrippedMember,
+    Unsigned, UrlPreview, SERVER_NOTICE_TAG,
+};
+pub use matrix_sdk_base::{
+    EmittedRoom, EmitterHandle, EventEmitter, EventOrigin, MediaCache, MembersSummary, Room,
+    RoomContext, Session, SpamChecker, SpamVerdict, SyncEvent, SyncRoom,
+};
 #[cfg(not(target_arch = "wasm32"))]
-pub use matrix_sdk_base::JsonStore;
-pub use matrix_sdk_base::{EventEmitter, Room, Session, SyncRoom};
-pub use matrix_sdk_base::{RoomState, StateStore};
+pub use matrix_sdk_base::{JsonStore, WorkerPool};
+
+#[cfg(feature = "local-search")]
+pub use matrix_sdk_base::{IndexedMessage, LocalSearchResult, SearchIndex};
+#[cfg(feature = "messages")]
+pub use matrix_sdk_base::{RetentionPolicy, Timeline, TimelineEvent, TimelineItem};
 pub use matrix_sdk_common::*;
 pub use reqwest::header::InvalidHeaderValue;
 
 #[cfg(feature = "encryption")]
-pub use matrix_sdk_base::{Device, TrustState};
+pub use matrix_sdk_base::{
+    decrypt_key_export, AttachmentDecryptor, AttachmentEncryptor, DecryptorError, Device,
+    EncryptionInfo, ExportedRoomKey, GroupSessionInfo, JsonWebKey, KeyExportError, TrustState,
+    VerificationPolicy,
+};
 
+mod avatar;
 mod client;
+mod completion;
+mod devices;
 mod error;
+mod event_stream;
+mod history;
+mod media;
+mod quirks;
 mod request_builder;
+mod telemetry;
+mod transformer;
+mod uiaa;
+pub use avatar::{AvatarExt, AvatarSize};
 pub use client::{Client, ClientConfig, SyncSettings};
+pub use completion::{MatchKind, RoomSuggestion};
+pub use devices::DeviceInfo;
 pub use error::{Error, Result};
-pub use request_builder::{MessagesRequestBuilder, RoomBuilder};
+pub use history::HistoricalEvent;
+pub use quirks::{Quirks, ServerVersion};
+pub use request_builder::{MessagesRequestBuilder, ReplyBuilder, RoomBuilder};
+pub use telemetry::{SyncObserver, SyncTelemetry};
+pub use transformer::ContentTransformer;
+pub use uiaa::{remaining_stages, AuthStage, UiaaHandler};
 
 pub(crate) const VERSION: &str = env!("CARGO_PKG_VERSION");
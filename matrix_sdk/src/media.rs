@@ -0,0 +1,179 @@
+// Copyright 2020 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::io::Read;
+
+/// The content type that is used if none was given and none could be
+/// detected.
+const DEFAULT_CONTENT_TYPE: &str = "application/octet-stream";
+
+/// The size of the chunks the data is read in.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Well known file signatures and the content types they belong to.
+const SIGNATURES: &[(&[u8], &str)] = &[
+    (b"\x89PNG\r\n\x1a\n", "image/png"),
+    (b"\xff\xd8\xff", "image/jpeg"),
+    (b"GIF87a", "image/gif"),
+    (b"GIF89a", "image/gif"),
+    (b"%PDF-", "application/pdf"),
+    (b"OggS", "audio/ogg"),
+    (b"ID3", "audio/mpeg"),
+    (b"fLaC", "audio/flac"),
+    (b"PK\x03\x04", "application/zip"),
+    (b"\x1a\x45\xdf\xa3", "video/webm"),
+];
+
+/// Guess the content type of the given data by looking at its first bytes.
+///
+/// Returns `application/octet-stream` if the content type can't be detected.
+pub(crate) fn detect_content_type(data: &[u8]) -> &'static str {
+    if data.len() >= 12 && &data[0..4] == b"RIFF" && &data[8..12] == b"WEBP" {
+        return "image/webp";
+    }
+
+    if data.len() >= 8 && &data[4..8] == b"ftyp" {
+        return "video/mp4";
+    }
+
+    SIGNATURES
+        .iter()
+        .find(|(signature, _)| data.starts_with(signature))
+        .map(|(_, content_type)| *content_type)
+        .unwrap_or(DEFAULT_CONTENT_TYPE)
+}
+
+/// Split a `mxc://` URI into its server name and media id.
+///
+/// Returns `None` if the URI isn't a valid `mxc://` URI.
+pub(crate) fn parse_mxc_uri(uri: &str) -> Option<(&str, &str)> {
+    const SCHEME: &str = "mxc://";
+
+    if !uri.starts_with(SCHEME) {
+        return None;
+    }
+
+    let mut parts = uri[SCHEME.len()..].splitn(2, '/');
+
+    let server_name = parts.next().filter(|s| !s.is_empty())?;
+    let media_id = parts.next().filter(|s| !s.is_empty() && !s.contains('/'))?;
+
+    Some((server_name, media_id))
+}
+
+/// Find the `http` and `https` URLs in the body of a message.
+///
+/// URLs are separated from the surrounding text by whitespace, trailing
+/// punctuation is not considered to be part of the URL. Every URL is only
+/// returned once.
+pub(crate) fn extract_urls(body: &str) -> Vec<String> {
+    let mut urls: Vec<String> = Vec::new();
+
+    for word in body.split_whitespace() {
+        let word = word.trim_start_matches(|c| c == '(' || c == '<' || c == '"');
+
+        let scheme_len = if word.starts_with("https://") {
+            "https://".len()
+        } else if word.starts_with("http://") {
+            "http://".len()
+        } else {
+            continue;
+        };
+
+        let url = word.trim_end_matches(|c: char| ".,;:!?)>\"'".contains(c));
+
+        if url.len() > scheme_len && !urls.iter().any(|u| u == url) {
+            urls.push(url.to_owned());
+        }
+    }
+
+    urls
+}
+
+/// Read all the data out of the reader, reporting the number of bytes read
+/// so far after every chunk.
+pub(crate) fn read_with_progress<R: Read>(
+    reader: &mut R,
+    mut progress: impl FnMut(usize),
+) -> std::io::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    let mut chunk = vec![0; CHUNK_SIZE];
+
+    loop {
+        let read = match reader.read(&mut chunk) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+
+        data.extend_from_slice(&chunk[..read]);
+        progress(data.len());
+    }
+
+    Ok(data)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn content_type_detection() {
+        assert_eq!(detect_content_type(b"\x89PNG\r\n\x1a\n\0\0"), "image/png");
+        assert_eq!(detect_content_type(b"\xff\xd8\xff\xe0"), "image/jpeg");
+        assert_eq!(detect_content_type(b"RIFF\0\0\0\0WEBPVP8 "), "image/webp");
+        assert_eq!(detect_content_type(b"%PDF-1.4"), "application/pdf");
+        assert_eq!(detect_content_type(b"hello world"), DEFAULT_CONTENT_TYPE);
+        assert_eq!(detect_content_type(b""), DEFAULT_CONTENT_TYPE);
+    }
+
+    #[test]
+    fn mxc_uri_parsing() {
+        assert_eq!(
+            parse_mxc_uri("mxc://example.com/AQwafuaFswefuhsfAFAgsw"),
+            Some(("example.com", "AQwafuaFswefuhsfAFAgsw"))
+        );
+        assert_eq!(
+            parse_mxc_uri("https://example.com/AQwafuaFswefuhsfAFAgsw"),
+            None
+        );
+        assert_eq!(parse_mxc_uri("mxc://example.com"), None);
+        assert_eq!(parse_mxc_uri("mxc:///AQwafuaFswefuhsfAFAgsw"), None);
+    }
+
+    #[test]
+    fn url_extraction() {
+        assert_eq!(
+            extract_urls("Have a look at https://matrix.org, or (http://example.com/a?b=c)."),
+            vec!["https://matrix.org", "http://example.com/a?b=c"]
+        );
+        assert_eq!(
+            extract_urls("https://matrix.org https://matrix.org"),
+            vec!["https://matrix.org"]
+        );
+        assert!(extract_urls("no links, not even ftp://example.com or https://").is_empty());
+    }
+
+    #[test]
+    fn progress() {
+        let data = vec![1u8; CHUNK_SIZE * 2 + 10];
+        let mut reported = Vec::new();
+
+        let read = read_with_progress(&mut data.as_slice(), |p| reported.push(p)).unwrap();
+
+        assert_eq!(read, data);
+        assert_eq!(reported, [CHUNK_SIZE, CHUNK_SIZE * 2, CHUNK_SIZE * 2 + 10]);
+    }
+}
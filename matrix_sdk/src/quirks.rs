@@ -0,0 +1,257 @@
+// Copyright 2020 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use serde_json::{json, Value as JsonValue};
+use tracing::warn;
+
+/// The software and version a homeserver runs.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ServerVersion {
+    /// The name of the server implementation, e.g. `Synapse`.
+    pub name: String,
+    /// The version of the server implementation.
+    pub version: String,
+}
+
+impl ServerVersion {
+    /// Parse the server version out of the response of the
+    /// `/_matrix/federation/v1/version` endpoint.
+    ///
+    /// Returns `None` if the response doesn't contain a server name.
+    pub fn from_json(response: &JsonValue) -> Option<Self> {
+        let server = response.get("server")?;
+        let name = server.get("name")?.as_str()?.to_owned();
+        let version = server
+            .get("version")
+            .and_then(|v| v.as_str())
+            .unwrap_or_default()
+            .to_owned();
+
+        Some(Self { name, version })
+    }
+}
+
+/// Workarounds for known deviations of homeservers from the spec.
+///
+/// The quirks are applied to the raw response bodies before they get
+/// deserialized, this way a missing field or a non-spec error body doesn't
+/// make the whole request fail.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quirks {
+    /// Insert the sections a sync response must have if the server left them
+    /// out.
+    pub sync_defaults: bool,
+    /// Turn error responses that don't carry a Matrix error body, e.g. the
+    /// HTML error pages of a reverse proxy, into a `M_UNKNOWN` error.
+    pub error_bodies: bool,
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Self::for_server(None)
+    }
+}
+
+impl Quirks {
+    /// Quirks that don't touch any response.
+    pub fn none() -> Self {
+        Self {
+            sync_defaults: false,
+            error_bodies: false,
+        }
+    }
+
+    /// Get the quirks that should be applied for the given server.
+    ///
+    /// If the server software is unknown all quirks are enabled.
+    ///
+    /// # Arguments
+    ///
+    /// * `server` - The software and version the homeserver runs.
+    pub fn for_server(server: Option<&ServerVersion>) -> Self {
+        let sync_defaults = match server.map(|s| s.name.to_lowercase()) {
+            Some(name) if name == "synapse" => false,
+            _ => true,
+        };
+
+        Self {
+            sync_defaults,
+            error_bodies: true,
+        }
+    }
+
+    /// Apply the quirks to a response body.
+    ///
+    /// Returns the body unchanged if no quirk applies.
+    pub(crate) fn apply(&self, path: &str, status: u16, body: Vec<u8>) -> Vec<u8> {
+        if status >= 400 {
+            if self.error_bodies {
+                return normalize_error_body(path, status, body);
+            }
+        } else if self.sync_defaults && path.ends_with("/sync") {
+            return fill_sync_defaults(body);
+        }
+
+        body
+    }
+}
+
+fn normalize_error_body(path: &str, status: u16, body: Vec<u8>) -> Vec<u8> {
+    if let Ok(error) = serde_json::from_slice::<JsonValue>(&body) {
+        if error.get("errcode").and_then(|e| e.as_str()).is_some() {
+            return body;
+        }
+    }
+
+    warn!("Request to {} returned a non-spec error body", path);
+
+    let message = String::from_utf8_lossy(&body).trim().to_owned();
+    let message = if message.is_empty() {
+        format!("The server returned status {}", status)
+    } else {
+        message
+    };
+
+    serde_json::to_vec(&json!({ "errcode": "M_UNKNOWN", "error": message })).unwrap_or(body)
+}
+
+fn fill_sync_defaults(body: Vec<u8>) -> Vec<u8> {
+    let mut response = match serde_json::from_slice::<JsonValue>(&body) {
+        Ok(r) if r.is_object() => r,
+        _ => return body,
+    };
+
+    let events = || json!({ "events": [] });
+
+    insert_default(&mut response, "rooms", json!({}));
+    insert_default(&mut response, "presence", events());
+    insert_default(&mut response, "account_data", events());
+    insert_default(&mut response, "to_device", events());
+    insert_default(&mut response, "device_lists", json!({}));
+    insert_default(&mut response, "device_one_time_keys_count", json!({}));
+
+    let rooms = &mut response["rooms"];
+
+    for section in &["join", "invite", "leave"] {
+        insert_default(rooms, section, json!({}));
+    }
+
+    if let Some(joined) = rooms["join"].as_object_mut() {
+        for room in joined.values_mut() {
+            insert_default(room, "summary", json!({}));
+            insert_default(room, "unread_notifications", json!({}));
+            insert_default(room, "timeline", events());
+            insert_default(room, "state", events());
+            insert_default(room, "account_data", events());
+            insert_default(room, "ephemeral", events());
+            insert_default(&mut room["timeline"], "events", json!([]));
+        }
+    }
+
+    if let Some(invited) = rooms["invite"].as_object_mut() {
+        for room in invited.values_mut() {
+            insert_default(room, "invite_state", events());
+        }
+    }
+
+    if let Some(left) = rooms["leave"].as_object_mut() {
+        for room in left.values_mut() {
+            insert_default(room, "timeline", events());
+            insert_default(room, "state", events());
+            insert_default(room, "account_data", events());
+            insert_default(&mut room["timeline"], "events", json!([]));
+        }
+    }
+
+    serde_json::to_vec(&response).unwrap_or(body)
+}
+
+fn insert_default(object: &mut JsonValue, key: &str, default: JsonValue) {
+    if let Some(object) = object.as_object_mut() {
+        let value = object.entry(key).or_insert(JsonValue::Null);
+
+        if value.is_null() {
+            *value = default;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn quirks_for_server() {
+        let synapse = ServerVersion {
+            name: "Synapse".to_owned(),
+            version: "1.15.0".to_owned(),
+        };
+        let conduit = ServerVersion::from_json(&json!({
+            "server": { "name": "Conduit", "version": "0.1" }
+        }))
+        .unwrap();
+
+        assert!(!Quirks::for_server(Some(&synapse)).sync_defaults);
+        assert!(Quirks::for_server(Some(&conduit)).sync_defaults);
+        assert!(Quirks::for_server(None).sync_defaults);
+        assert_eq!(Quirks::default(), Quirks::for_server(None));
+    }
+
+    #[test]
+    fn error_body_normalization() {
+        let quirks = Quirks::default();
+
+        let body = quirks.apply("/_matrix/client/r0/login", 502, b"Bad Gateway".to_vec());
+        let error: JsonValue = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            error,
+            json!({ "errcode": "M_UNKNOWN", "error": "Bad Gateway" })
+        );
+
+        let body = br#"{"errcode":"M_FORBIDDEN","error":"Invalid password"}"#.to_vec();
+        assert_eq!(
+            quirks.apply("/_matrix/client/r0/login", 403, body.clone()),
+            body
+        );
+
+        assert_eq!(
+            Quirks::none().apply("/_matrix/client/r0/login", 502, b"Bad Gateway".to_vec()),
+            b"Bad Gateway".to_vec()
+        );
+    }
+
+    #[test]
+    fn sync_defaults() {
+        let body = serde_json::to_vec(&json!({
+            "next_batch": "s1",
+            "rooms": {
+                "join": {
+                    "!room:localhost": { "timeline": { "limited": false } }
+                }
+            }
+        }))
+        .unwrap();
+
+        let body = Quirks::default().apply("/_matrix/client/r0/sync", 200, body);
+        let response: JsonValue = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(response["presence"], json!({ "events": [] }));
+        assert_eq!(response["rooms"]["leave"], json!({}));
+
+        let room = &response["rooms"]["join"]["!room:localhost"];
+        assert_eq!(room["timeline"]["events"], json!([]));
+        assert_eq!(room["timeline"]["limited"], json!(false));
+        assert_eq!(room["ephemeral"], json!({ "events": [] }));
+    }
+}
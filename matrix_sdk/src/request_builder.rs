@@ -1,7 +1,10 @@
 use crate::api;
+use crate::events::room::message::{
+    InReplyTo, MessageEvent, MessageEventContent, RelatesTo, TextMessageEventContent,
+};
 use crate::events::room::power_levels::PowerLevelsEventContent;
 use crate::events::EventJson;
-use crate::identifiers::{RoomId, UserId};
+use crate::identifiers::{EventId, RoomId, UserId};
 use api::r0::filter::RoomEventFilter;
 use api::r0::membership::Invite3pid;
 use api::r0::message::get_message_events::{self, Direction};
@@ -69,6 +72,8 @@ pub struct RoomBuilder {
     /// list. A private visibility will hide the room from the published room list. Rooms
     /// default to private visibility if this key is not included.
     visibility: Option<Visibility>,
+    /// If set, an `m.room.encryption` event will be part of the initial state.
+    encrypted: bool,
 }
 
 impl RoomBuilder {
@@ -153,13 +158,39 @@ impl RoomBuilder {
         self.visibility = Some(vis);
         self
     }
+
+    /// Enable end-to-end encryption for the room.
+    ///
+    /// An `m.room.encryption` event using the Megolm algorithm will be added
+    /// to the initial state of the room.
+    pub fn encrypted(&mut self) -> &mut Self {
+        self.encrypted = true;
+        self
+    }
 }
 
 impl Into<create_room::Request> for RoomBuilder {
     fn into(self) -> create_room::Request {
+        let mut initial_state = self.initial_state;
+
+        if self.encrypted {
+            let encryption = serde_json::json!({
+                "type": "m.room.encryption",
+                "state_key": "",
+                "content": {
+                    "algorithm": "m.megolm.v1.aes-sha2"
+                }
+            });
+
+            initial_state.push(
+                serde_json::from_value(encryption)
+                    .expect("Can't create the m.room.encryption initial state event"),
+            );
+        }
+
         create_room::Request {
             creation_content: self.creation_content,
-            initial_state: self.initial_state,
+            initial_state,
             invite: self.invite,
             invite_3pid: self.invite_3pid,
             is_direct: self.is_direct,
@@ -288,6 +319,203 @@ impl Into<get_message_events::Request> for MessagesRequestBuilder {
     }
 }
 
+/// A builder for the content of a reply to a message.
+///
+/// The content contains the `m.relates_to.m.in_reply_to` relation as well as
+/// the quoted fallback of the original message for clients that don't
+/// support replies.
+///
+/// # Examples
+/// ```
+/// # use std::convert::TryFrom;
+/// # use matrix_sdk::{Client, ReplyBuilder};
+/// # use matrix_sdk::events::{room::message::MessageEvent, EventJson};
+/// # use matrix_sdk::identifiers::RoomId;
+/// # use url::Url;
+/// # let homeserver = Url::parse("http://example.com").unwrap();
+/// # let mut rt = tokio::runtime::Runtime::new().unwrap();
+/// # rt.block_on(async {
+/// # let room_id = RoomId::try_from("!test:localhost").unwrap();
+/// # let original = serde_json::from_value::<EventJson<MessageEvent>>(serde_json::json!({
+/// #     "content": { "body": "Hello", "msgtype": "m.text" },
+/// #     "event_id": "$original:localhost",
+/// #     "origin_server_ts": 1_520_372_800_469u64,
+/// #     "sender": "@alice:localhost",
+/// #     "type": "m.room.message"
+/// # })).unwrap().deserialize().unwrap();
+/// let cli = Client::new(homeserver, None).unwrap();
+///
+/// let mut builder = ReplyBuilder::new(&room_id, &original, "Hello Alice");
+/// builder.formatted_body("Hello <em>Alice</em>");
+///
+/// cli.send_message(&room_id, builder.into()).await.is_err();
+/// # })
+/// ```
+#[derive(Clone, Debug)]
+pub struct ReplyBuilder {
+    /// The room the original message was sent to.
+    room_id: RoomId,
+    /// The id of the original message.
+    event_id: EventId,
+    /// The sender of the original message.
+    sender: UserId,
+    /// The plain text of the original message, without its own fallback.
+    original_body: String,
+    /// The HTML of the original message, without its own fallback.
+    original_html: String,
+    /// The plain text of the reply.
+    body: String,
+    /// The HTML of the reply.
+    formatted_body: Option<String>,
+}
+
+impl ReplyBuilder {
+    /// Create a `ReplyBuilder` for a reply to the given message.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The id of the room the message was sent to.
+    ///
+    /// * `original` - The message that is replied to.
+    ///
+    /// * `body` - The plain text of the reply.
+    pub fn new<S: Into<String>>(room_id: &RoomId, original: &MessageEvent, body: S) -> Self {
+        let content = serde_json::to_value(&original.content).unwrap_or_default();
+        let text = |key: &str| content.get(key).and_then(|v| v.as_str());
+
+        let (original_body, original_html) = match text("msgtype") {
+            Some("m.text") | Some("m.notice") | Some("m.emote") => {
+                let mut body = strip_reply_fallback(text("body").unwrap_or_default());
+                let mut html = match (text("format"), text("formatted_body")) {
+                    (Some("org.matrix.custom.html"), Some(html)) => strip_html_reply_fallback(html),
+                    _ => escape_html(&body).replace('\n', "<br />"),
+                };
+
+                if text("msgtype") == Some("m.emote") {
+                    body = format!("* {} {}", original.sender, body);
+                    html = format!("* {} {}", original.sender, html);
+                }
+
+                (body, html)
+            }
+            Some("m.image") => ("sent an image.".to_owned(), "sent an image.".to_owned()),
+            Some("m.video") => ("sent a video.".to_owned(), "sent a video.".to_owned()),
+            Some("m.audio") => (
+                "sent an audio file.".to_owned(),
+                "sent an audio file.".to_owned(),
+            ),
+            Some("m.file") => ("sent a file.".to_owned(), "sent a file.".to_owned()),
+            Some("m.location") => ("sent a location.".to_owned(), "sent a location.".to_owned()),
+            _ => {
+                let body = text("body").unwrap_or_default().to_owned();
+                let html = escape_html(&body);
+                (body, html)
+            }
+        };
+
+        Self {
+            room_id: room_id.clone(),
+            event_id: original.event_id.clone(),
+            sender: original.sender.clone(),
+            original_body,
+            original_html,
+            body: body.into(),
+            formatted_body: None,
+        }
+    }
+
+    /// Set the HTML of the reply.
+    ///
+    /// If this isn't set the HTML is created from the plain text body.
+    pub fn formatted_body<S: Into<String>>(&mut self, formatted_body: S) -> &mut Self {
+        self.formatted_body = Some(formatted_body.into());
+        self
+    }
+}
+
+impl Into<MessageEventContent> for ReplyBuilder {
+    fn into(self) -> MessageEventContent {
+        let mut quoted = self.original_body.lines();
+        let mut body = format!(
+            "> <{}> {}\n",
+            self.sender,
+            quoted.next().unwrap_or_default()
+        );
+        for line in quoted {
+            body.push_str(&format!("> {}\n", line));
+        }
+        body.push('\n');
+        body.push_str(&self.body);
+
+        let reply_html = self
+            .formatted_body
+            .unwrap_or_else(|| escape_html(&self.body).replace('\n', "<br />"));
+
+        let formatted_body = format!(
+            "<mx-reply><blockquote>\
+             <a href=\"https://matrix.to/#/{room}/{event}\">In reply to</a> \
+             <a href=\"https://matrix.to/#/{sender}\">{sender}</a><br />{original}\
+             </blockquote></mx-reply>{reply}",
+            room = self.room_id,
+            event = self.event_id,
+            sender = self.sender,
+            original = self.original_html,
+            reply = reply_html,
+        );
+
+        MessageEventContent::Text(TextMessageEventContent {
+            body,
+            format: Some("org.matrix.custom.html".to_owned()),
+            formatted_body: Some(formatted_body),
+            relates_to: Some(RelatesTo {
+                in_reply_to: InReplyTo {
+                    event_id: self.event_id,
+                },
+            }),
+        })
+    }
+}
+
+/// Escape the characters of the given text that have a special meaning in
+/// HTML.
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+/// Remove the quoted fallback of a reply from its plain text body.
+fn strip_reply_fallback(body: &str) -> String {
+    if !body.starts_with("> ") {
+        return body.to_owned();
+    }
+
+    body.lines()
+        .skip_while(|l| l.starts_with('>'))
+        .skip_while(|l| l.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Remove the `<mx-reply>` fallback of a reply from its HTML body.
+fn strip_html_reply_fallback(html: &str) -> String {
+    match html.find("</mx-reply>") {
+        Some(end) => html[end + "</mx-reply>".len()..].to_owned(),
+        None => html.to_owned(),
+    }
+}
+
 #[cfg(test)]
 mod test {
     use std::collections::BTreeMap;
@@ -346,6 +574,41 @@ mod test {
         assert!(cli.create_room(builder).await.is_ok());
     }
 
+    #[tokio::test]
+    async fn create_encrypted_room() {
+        let homeserver = Url::parse(&mockito::server_url()).unwrap();
+
+        let _m = mock("POST", "/_matrix/client/r0/createRoom")
+            .match_body(Matcher::PartialJson(serde_json::json!({
+                "initial_state": [{
+                    "type": "m.room.encryption",
+                    "state_key": "",
+                    "content": { "algorithm": "m.megolm.v1.aes-sha2" }
+                }]
+            })))
+            .with_status(200)
+            .with_body_from_file("../test_data/room_id.json")
+            .create();
+
+        let session = Session {
+            access_token: "1234".to_owned(),
+            user_id: UserId::try_from("@example:localhost").unwrap(),
+            device_id: "DEVICEID".to_owned(),
+        };
+
+        let mut builder = RoomBuilder::new();
+        builder.name("secret room").encrypted();
+
+        let cli = Client::new(homeserver, Some(session)).unwrap();
+        let room = cli.create_room_and_join(builder).await.unwrap();
+        let room = room.read().await;
+
+        let room_id = RoomId::try_from("!testroom:example.org").unwrap();
+        assert_eq!(room.room_id, room_id);
+        assert!(room.is_encrypted());
+        assert!(cli.get_joined_room(&room_id).await.is_some());
+    }
+
     #[tokio::test]
     async fn get_message_events() {
         let homeserver = Url::parse(&mockito::server_url()).unwrap();
@@ -381,4 +644,46 @@ mod test {
         let cli = Client::new(homeserver, Some(session)).unwrap();
         assert!(cli.room_messages(builder).await.is_ok());
     }
+
+    #[test]
+    fn reply_builder() {
+        let room_id = RoomId::try_from("!test:localhost").unwrap();
+        let original = serde_json::from_value::<EventJson<MessageEvent>>(serde_json::json!({
+            "content": {
+                "body": "> <@bob:localhost> Hi\n\nHello <everyone>\nHow are you?",
+                "msgtype": "m.text"
+            },
+            "event_id": "$original:localhost",
+            "origin_server_ts": 1_520_372_800_469u64,
+            "sender": "@alice:localhost",
+            "type": "m.room.message"
+        }))
+        .unwrap()
+        .deserialize()
+        .unwrap();
+
+        let content: MessageEventContent = ReplyBuilder::new(&room_id, &original, "Fine").into();
+
+        let text = match content {
+            MessageEventContent::Text(text) => text,
+            _ => panic!("Reply isn't a text message"),
+        };
+
+        assert_eq!(
+            text.body,
+            "> <@alice:localhost> Hello <everyone>\n> How are you?\n\nFine"
+        );
+        assert_eq!(
+            text.formatted_body.unwrap(),
+            "<mx-reply><blockquote>\
+             <a href=\"https://matrix.to/#/!test:localhost/$original:localhost\">In reply to</a> \
+             <a href=\"https://matrix.to/#/@alice:localhost\">@alice:localhost</a><br />\
+             Hello &lt;everyone&gt;<br />How are you?\
+             </blockquote></mx-reply>Fine"
+        );
+        assert_eq!(
+            text.relates_to.unwrap().in_reply_to.event_id,
+            original.event_id
+        );
+    }
 }
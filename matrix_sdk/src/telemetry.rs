@@ -0,0 +1,67 @@
+// Copyright 2020 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Telemetry about sync calls.
+
+use matrix_sdk_common::instant::Duration;
+
+/// Measurements of a single sync call.
+///
+/// Comparing the time the request took with the time the response took to
+/// process tells if the homeserver is lagging behind or if the client is the
+/// bottleneck.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SyncTelemetry {
+    /// The size of the response body in bytes.
+    pub response_size: usize,
+    /// The time from sending the request until the full response was
+    /// received.
+    ///
+    /// The homeserver holds the request for up to `timeout` if there are no
+    /// new events.
+    pub request_time: Duration,
+    /// The time it took to deserialize and process the response.
+    pub processing_time: Duration,
+    /// The timeout the sync call was made with.
+    pub timeout: Option<Duration>,
+    /// The number of joined rooms the response contained.
+    pub joined_rooms: usize,
+    /// The number of invited rooms the response contained.
+    pub invited_rooms: usize,
+    /// The number of left rooms the response contained.
+    pub left_rooms: usize,
+}
+
+/// An observer that receives the `SyncTelemetry` of every sync call.
+///
+/// # Examples
+/// ```
+/// use matrix_sdk::{SyncObserver, SyncTelemetry};
+///
+/// struct SlowSyncLogger;
+///
+/// #[async_trait::async_trait]
+/// impl SyncObserver for SlowSyncLogger {
+///     async fn on_sync(&self, telemetry: &SyncTelemetry) {
+///         if telemetry.processing_time > telemetry.request_time {
+///             println!("Processing the sync took {:?}", telemetry.processing_time);
+///         }
+///     }
+/// }
+/// ```
+#[async_trait::async_trait]
+pub trait SyncObserver: Send + Sync {
+    /// Called after a sync response was received and processed.
+    async fn on_sync(&self, telemetry: &SyncTelemetry);
+}
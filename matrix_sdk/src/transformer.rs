@@ -0,0 +1,54 @@
+// Copyright 2020 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Transformation of the content of outgoing messages.
+
+use crate::events::room::message::MessageEventContent;
+use crate::identifiers::RoomId;
+
+/// A hook that can change the content of every message before it is sent.
+///
+/// The transformer is applied to the messages of `Client::room_send()` and
+/// all the methods built on top of it, to queued messages when they are
+/// queued and to the new content of edits. Raw events, e.g. reactions or
+/// imported history, aren't transformed.
+///
+/// # Examples
+/// ```
+/// use matrix_sdk::{
+///     events::room::message::MessageEventContent, identifiers::RoomId, ContentTransformer,
+/// };
+///
+/// struct Signature;
+///
+/// #[async_trait::async_trait]
+/// impl ContentTransformer for Signature {
+///     async fn transform(&self, _: &RoomId, content: &mut MessageEventContent) {
+///         if let MessageEventContent::Text(text) = content {
+///             text.body.push_str("\n-- sent by a bot");
+///         }
+///     }
+/// }
+/// ```
+#[async_trait::async_trait]
+pub trait ContentTransformer: Send + Sync {
+    /// Change the content of a message before it is sent.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The id of the room the message is sent to.
+    ///
+    /// * `content` - The content of the message.
+    async fn transform(&self, room_id: &RoomId, content: &mut MessageEventContent);
+}
@@ -0,0 +1,204 @@
+// Copyright 2020 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! User-interactive authentication support.
+
+use std::collections::BTreeMap;
+
+use serde_json::Value as JsonValue;
+
+use crate::api::r0::uiaa::{AuthData, UiaaInfo};
+use crate::identifiers::UserId;
+
+/// The data the client submits to complete a single stage of the
+/// user-interactive authentication.
+#[derive(Clone, Debug)]
+pub enum AuthStage {
+    /// Authenticate using the password of the logged in user, `m.login.password`.
+    Password {
+        /// The password of the logged in user.
+        password: String,
+    },
+    /// Authenticate using a token, `m.login.token`.
+    Token {
+        /// The login token.
+        token: String,
+        /// A client generated nonce.
+        txn_id: String,
+    },
+    /// A stage that requires no data, `m.login.dummy`.
+    Dummy,
+    /// Any other stage type.
+    Custom {
+        /// The type of the stage.
+        kind: String,
+        /// The parameters the stage requires.
+        parameters: BTreeMap<String, JsonValue>,
+    },
+}
+
+impl AuthStage {
+    /// The login type of this stage.
+    pub fn kind(&self) -> &str {
+        match self {
+            AuthStage::Password { .. } => "m.login.password",
+            AuthStage::Token { .. } => "m.login.token",
+            AuthStage::Dummy => "m.login.dummy",
+            AuthStage::Custom { kind, .. } => kind,
+        }
+    }
+
+    /// Convert the stage into `AuthData` that can be attached to a request.
+    ///
+    /// # Arguments
+    ///
+    /// * `session` - The session of the authentication as given by the server.
+    ///
+    /// * `user_id` - The user that is authenticating, used for password stages.
+    pub fn into_auth_data(self, session: Option<String>, user_id: Option<&UserId>) -> AuthData {
+        let kind = self.kind().to_owned();
+
+        let auth_parameters = match self {
+            AuthStage::Password { password } => {
+                let mut parameters = BTreeMap::new();
+                if let Some(user_id) = user_id {
+                    parameters.insert("user".to_owned(), user_id.to_string().into());
+                }
+                parameters.insert("password".to_owned(), password.into());
+                parameters
+            }
+            AuthStage::Token { token, txn_id } => {
+                let mut parameters = BTreeMap::new();
+                parameters.insert("token".to_owned(), token.into());
+                parameters.insert("txn_id".to_owned(), txn_id.into());
+                parameters
+            }
+            AuthStage::Dummy => BTreeMap::new(),
+            AuthStage::Custom { parameters, .. } => parameters,
+        };
+
+        AuthData {
+            kind,
+            session,
+            auth_parameters,
+        }
+    }
+}
+
+/// Returns the stages of the flow that is being completed that are not yet
+/// completed.
+///
+/// The flow is the first one the server offers that contains all the stages
+/// that were already completed, no stages are returned if none does.
+///
+/// # Arguments
+///
+/// * `info` - The user-interactive authentication info the server responded
+/// with.
+pub fn remaining_stages(info: &UiaaInfo) -> Vec<&str> {
+    info.flows
+        .iter()
+        .find(|flow| info.completed.iter().all(|s| flow.stages.contains(s)))
+        .map(|flow| {
+            flow.stages
+                .iter()
+                .filter(|s| !info.completed.contains(s))
+                .map(|s| s.as_str())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// A handler that supplies the data for the stages of user-interactive
+/// authentication.
+///
+/// If a `UiaaHandler` is set on the `Client`, requests that require
+/// user-interactive authentication will be retried automatically with the
+/// data the handler provides.
+///
+/// # Examples
+/// ```
+/// # use matrix_sdk::api::r0::uiaa::UiaaInfo;
+/// use matrix_sdk::{AuthStage, UiaaHandler};
+///
+/// struct PasswordHandler(String);
+///
+/// #[async_trait::async_trait]
+/// impl UiaaHandler for PasswordHandler {
+///     async fn next_stage(&self, info: &UiaaInfo) -> Option<AuthStage> {
+///         Some(AuthStage::Password {
+///             password: self.0.clone(),
+///         })
+///     }
+/// }
+/// ```
+#[async_trait::async_trait]
+pub trait UiaaHandler: Send + Sync {
+    /// Called when the server requires a further authentication stage to be
+    /// completed.
+    ///
+    /// Return the data for the next stage or `None` to abort the request, in
+    /// which case the error containing the `UiaaInfo` is returned to the
+    /// caller.
+    async fn next_stage(&self, info: &UiaaInfo) -> Option<AuthStage>;
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::convert::TryFrom;
+
+    #[test]
+    fn password_auth_data() {
+        let user_id = UserId::try_from("@example:localhost").unwrap();
+        let stage = AuthStage::Password {
+            password: "wordpass".to_owned(),
+        };
+
+        let auth_data = stage.into_auth_data(Some("xxxxxxyz".to_owned()), Some(&user_id));
+
+        assert_eq!(auth_data.kind, "m.login.password");
+        assert_eq!(auth_data.session.as_deref(), Some("xxxxxxyz"));
+        assert_eq!(
+            auth_data.auth_parameters.get("user"),
+            Some(&JsonValue::from("@example:localhost"))
+        );
+        assert_eq!(
+            auth_data.auth_parameters.get("password"),
+            Some(&JsonValue::from("wordpass"))
+        );
+    }
+
+    #[test]
+    fn remaining() {
+        let info: UiaaInfo =
+            serde_json::from_str(include_str!("../../test_data/delete_devices.json")).unwrap();
+
+        assert!(remaining_stages(&info).is_empty());
+
+        let info: UiaaInfo = serde_json::from_str(
+            r#"{
+                "flows": [
+                    { "stages": ["m.login.password"] },
+                    { "stages": ["m.login.email.identity", "m.login.recaptcha"] }
+                ],
+                "params": {},
+                "completed": ["m.login.email.identity"]
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(remaining_stages(&info), vec!["m.login.recaptcha"]);
+    }
+}
@@ -0,0 +1,129 @@
+// Copyright 2020 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Benchmarks of the sync processing of the `BaseClient` and of the state
+//! store backends.
+//!
+//! Run them with `cargo bench -p matrix-sdk-base`.
+
+use std::convert::TryFrom;
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use tempfile::tempdir;
+use tokio::runtime::Runtime;
+
+use matrix_sdk_base::identifiers::{RoomId, UserId};
+use matrix_sdk_base::{BaseClient, BaseClientConfig, JsonStore, Session, StateStore};
+use matrix_sdk_test::{large_sync_response, sync_response, SyncResponseFile, SyncResponseSize};
+
+/// The size of the sync response of a busy account.
+const LARGE_SYNC: SyncResponseSize = SyncResponseSize {
+    rooms: 50,
+    members: 100,
+    messages: 50,
+};
+
+fn session() -> Session {
+    Session {
+        access_token: "1234".to_owned(),
+        user_id: UserId::try_from("@user0:localhost").unwrap(),
+        device_id: "DEVICEID".to_owned(),
+    }
+}
+
+fn receive_sync_response(c: &mut Criterion) {
+    let mut runtime = Runtime::new().unwrap();
+    let mut group = c.benchmark_group("receive_sync_response");
+
+    group.bench_function("default", |b| {
+        b.iter_batched(
+            || {
+                (
+                    BaseClient::new(Some(session())).unwrap(),
+                    sync_response(SyncResponseFile::Default),
+                )
+            },
+            |(client, mut response)| {
+                runtime
+                    .block_on(client.receive_sync_response(&mut response))
+                    .unwrap()
+            },
+            BatchSize::SmallInput,
+        )
+    });
+
+    group.throughput(Throughput::Elements(LARGE_SYNC.rooms as u64));
+    group.sample_size(10);
+    group.bench_function("large", |b| {
+        b.iter_batched(
+            || {
+                (
+                    BaseClient::new(Some(session())).unwrap(),
+                    large_sync_response(LARGE_SYNC),
+                )
+            },
+            |(client, mut response)| {
+                runtime
+                    .block_on(client.receive_sync_response(&mut response))
+                    .unwrap()
+            },
+            BatchSize::LargeInput,
+        )
+    });
+
+    group.finish();
+}
+
+/// Benchmark how long it takes to write the state of all the rooms of a large
+/// sync response to the given store.
+///
+/// Custom `StateStore` implementations can be benchmarked the same way using
+/// the sync response fixtures of `matrix_sdk_test`.
+fn bench_state_store(c: &mut Criterion, name: &str, store: Box<dyn StateStore>) {
+    let mut runtime = Runtime::new().unwrap();
+
+    let config = BaseClientConfig::new().state_store(store);
+    let client = BaseClient::new_with_config(Some(session()), config).unwrap();
+
+    let mut response = large_sync_response(LARGE_SYNC);
+    runtime
+        .block_on(client.receive_sync_response(&mut response))
+        .unwrap();
+
+    let room_ids: Vec<RoomId> = response.rooms.join.keys().cloned().collect();
+
+    let mut group = c.benchmark_group("store_room_state");
+    group.throughput(Throughput::Elements(room_ids.len() as u64));
+    group.sample_size(10);
+    group.bench_function(name, |b| {
+        b.iter(|| {
+            runtime.block_on(async {
+                for room_id in &room_ids {
+                    client.store_room_state(room_id).await.unwrap();
+                }
+            })
+        })
+    });
+    group.finish();
+}
+
+fn store_room_state(c: &mut Criterion) {
+    let dir = tempdir().unwrap();
+    let store = JsonStore::open(dir.path()).unwrap();
+
+    bench_state_store(c, "json_store", Box::new(store));
+}
+
+criterion_group!(benches, receive_sync_response, store_room_state);
+criterion_main!(benches);
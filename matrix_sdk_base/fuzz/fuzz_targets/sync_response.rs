@@ -0,0 +1,89 @@
+// Copyright 2020 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Feed arbitrary sync responses into the `BaseClient`.
+//!
+//! Run it with a nightly compiler and `cargo-fuzz` from the `matrix_sdk_base`
+//! directory, the sync responses in `test_data` make a good seed corpus:
+//!
+//! ```text
+//! cargo +nightly fuzz run sync_response fuzz/corpus/sync_response ../test_data
+//! ```
+//!
+//! Besides panics the target checks that the client state survives the
+//! response: the sync token has to be updated and every joined room has to
+//! round trip through the serialization the state stores use.
+
+#![no_main]
+
+use std::convert::TryFrom;
+
+use libfuzzer_sys::fuzz_target;
+use tokio::runtime::{Builder, Runtime};
+
+use matrix_sdk_base::api::r0::sync::sync_events::Response as SyncResponse;
+use matrix_sdk_base::identifiers::UserId;
+use matrix_sdk_base::{BaseClient, Room, Session};
+
+fn runtime() -> Runtime {
+    Builder::new()
+        .basic_scheduler()
+        .build()
+        .expect("Can't create the runtime")
+}
+
+fn session() -> Session {
+    Session {
+        access_token: "1234".to_owned(),
+        user_id: UserId::try_from("@example:localhost").unwrap(),
+        device_id: "DEVICEID".to_owned(),
+    }
+}
+
+fuzz_target!(|data: &[u8]| {
+    let response = match http::Response::builder().body(data.to_vec()) {
+        Ok(r) => r,
+        Err(_) => return,
+    };
+
+    // Only responses that deserialize can reach the state machine.
+    let mut response = match SyncResponse::try_from(response) {
+        Ok(r) => r,
+        Err(_) => return,
+    };
+
+    runtime().block_on(async {
+        let client = BaseClient::new(Some(session())).unwrap();
+
+        if client.receive_sync_response(&mut response).await.is_err() {
+            return;
+        }
+
+        assert_eq!(client.sync_token().await, Some(response.next_batch.clone()));
+
+        for room in client.joined_rooms().read().await.values() {
+            let room = room.read().await;
+            let json = serde_json::to_string(&*room).expect("Can't serialize a room");
+            let restored: Room = serde_json::from_str(&json).expect("Can't deserialize a room");
+
+            assert_eq!(restored.room_id, room.room_id);
+            assert_eq!(restored.members.len(), room.members.len());
+        }
+
+        // Receiving the same rooms again, under a new sync token so the
+        // response isn't skipped, must not panic either.
+        response.next_batch.push_str("-again");
+        let _ = client.receive_sync_response(&mut response).await;
+    });
+});
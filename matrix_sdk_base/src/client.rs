@@ -13,37 +13,60 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::BTreeMap;
 use std::collections::HashMap;
 #[cfg(feature = "encryption")]
-use std::collections::{BTreeMap, HashSet};
+use std::collections::HashSet;
 use std::fmt;
+use std::future::Future;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::time::SystemTime;
 
 #[cfg(feature = "encryption")]
 use std::result::Result as StdResult;
 
 use crate::api::r0 as api;
+use crate::error::Error;
 use crate::error::Result;
 use crate::events::collections::all::{RoomEvent, StateEvent};
-use crate::events::presence::PresenceEvent;
+use crate::events::presence::{PresenceEvent, PresenceEventContent};
+use crate::events::room::member::{MemberEvent, MembershipState};
+use crate::events::room::message::{MessageEvent, MessageEventContent};
 // `NonRoomEvent` is what it is aliased as
+use crate::event_emitter::EventEmitters;
+use crate::event_handler::{event_handler, EventHandler};
 use crate::events::collections::only::Event as NonRoomEvent;
 use crate::events::ignored_user_list::IgnoredUserListEvent;
 use crate::events::push_rules::{PushRulesEvent, Ruleset};
 use crate::events::stripped::AnyStrippedStateEvent;
-use crate::events::EventJson;
-use crate::identifiers::{RoomId, UserId};
-use crate::models::Room;
+use crate::events::{EventJson, EventType};
+use crate::identifiers::{EventId, RoomAliasId, RoomId, UserId};
+use crate::media_cache::MediaCache;
+use crate::models::{
+    keywords, set_keyword_rule, PendingEvent, PushActions, QueuedMessage, Reaction, ReadReceipt,
+    Room, RoomStateSnapshot, SearchResults, SendState, SpaceTreeNode, UrlPreview,
+};
+#[cfg(feature = "messages")]
+use crate::models::{RetentionPolicy, TimelineEvent};
+#[cfg(feature = "local-search")]
+use crate::search_index::{LocalSearchResult, SearchIndex};
 use crate::session::Session;
-use crate::state::{AllRooms, ClientState, StateStore};
-use crate::EventEmitter;
+use crate::state::{ClientState, StateStore};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::worker::{WorkerJob, WorkerPool};
+use crate::{
+    EmitterHandle, EventEmitter, EventOrigin, MembersSummary, SpamChecker, SpamVerdict, SyncEvent,
+    SyncRoom,
+};
 
-#[cfg(feature = "encryption")]
-use matrix_sdk_common::locks::Mutex;
-use matrix_sdk_common::locks::RwLock;
+use matrix_sdk_common::locks::{Mutex, RwLock};
 use std::ops::Deref;
 
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::value::RawValue as RawJsonValue;
+use serde_json::Value as JsonValue;
+
 #[cfg(feature = "encryption")]
 use crate::api::r0::keys::{
     claim_keys::Response as KeysClaimResponse, get_keys::Response as KeysQueryResponse,
@@ -52,16 +75,51 @@ use crate::api::r0::keys::{
 #[cfg(feature = "encryption")]
 use crate::api::r0::to_device::send_event_to_device;
 #[cfg(feature = "encryption")]
-use crate::events::room::{encrypted::EncryptedEventContent, message::MessageEventContent};
+use crate::events::room::encrypted::{EncryptedEvent, EncryptedEventContent};
 #[cfg(feature = "encryption")]
 use crate::identifiers::DeviceId;
 #[cfg(feature = "encryption")]
-use matrix_sdk_crypto::{OlmMachine, OneTimeKeys};
+use matrix_sdk_crypto::{
+    ExportedRoomKey, GroupSessionInfo, OlmError, OlmMachine, OneTimeKeys, VerificationPolicy,
+};
 
 pub type Token = String;
 
+/// The number of rooms that are loaded from the state store at once.
+pub(crate) const ROOM_LOAD_BATCH_SIZE: usize = 100;
+
+/// The number of timeline events that are decrypted by a single task.
+#[cfg(all(feature = "encryption", not(target_arch = "wasm32")))]
+const DECRYPTION_CHUNK_SIZE: usize = 16;
+
+/// A task decrypting a chunk of the timeline events of a room.
+#[cfg(not(target_arch = "wasm32"))]
+type DecryptionJob = WorkerJob<Vec<Option<EventJson<RoomEvent>>>>;
+
+/// Helper to get the room id out of the JSON of an event.
+#[derive(Deserialize)]
+struct EventRoomId {
+    room_id: Option<RoomId>,
+}
+
+/// Helper to get the type out of the JSON of an event.
+#[derive(Deserialize)]
+struct EventTypeJson {
+    #[serde(rename = "type")]
+    event_type: String,
+}
+
+/// Helper to get the type and the content out of the JSON of an account data
+/// event.
+#[derive(Deserialize)]
+struct AccountDataJson {
+    #[serde(rename = "type")]
+    event_type: String,
+    content: JsonValue,
+}
+
 /// Signals to the `BaseClient` which `RoomState` to send to `EventEmitter`.
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum RoomStateType {
     /// Represents a joined room, the `joined_rooms` HashMap will be used.
     Joined,
@@ -76,7 +134,7 @@ pub enum RoomStateType {
 /// If the event came from the `join`, `invite` or `leave` rooms map from the server
 /// the variant that holds the corresponding room is used. `RoomState` is generic
 /// so it can be used to represent a `Room` or an `Arc<RwLock<Room>>`
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub enum RoomState<R> {
     /// A room from the `join` section of a sync response.
     Joined(R),
@@ -86,6 +144,174 @@ pub enum RoomState<R> {
     Invited(R),
 }
 
+/// Configuration for the creation of the `BaseClient`.
+#[derive(Default)]
+pub struct BaseClientConfig {
+    state_store: Option<Box<dyn StateStore>>,
+    member_batch_threshold: Option<usize>,
+    media_cache_size: Option<usize>,
+    lazy_room_loading: bool,
+    track_unhandled_events: bool,
+    ignore_typing_events: bool,
+    ignore_receipt_events: bool,
+    #[cfg(not(target_arch = "wasm32"))]
+    sync_worker_jobs: Option<usize>,
+    #[cfg(feature = "messages")]
+    retention_policy: RetentionPolicy,
+    #[cfg(feature = "encryption")]
+    verification_policy: VerificationPolicy,
+}
+
+impl fmt::Debug for BaseClientConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut res = f.debug_struct("BaseClientConfig");
+
+        #[cfg(feature = "encryption")]
+        let res = res.field("verification_policy", &self.verification_policy);
+
+        #[cfg(feature = "messages")]
+        let res = res.field("retention_policy", &self.retention_policy);
+
+        #[cfg(not(target_arch = "wasm32"))]
+        let res = res.field("sync_worker_jobs", &self.sync_worker_jobs);
+
+        res.field(
+            "state_store",
+            &self.state_store.as_ref().map(|_| "StateStore<...>"),
+        )
+        .field("member_batch_threshold", &self.member_batch_threshold)
+        .field("media_cache_size", &self.media_cache_size)
+        .field("lazy_room_loading", &self.lazy_room_loading)
+        .field("track_unhandled_events", &self.track_unhandled_events)
+        .field("ignore_typing_events", &self.ignore_typing_events)
+        .field("ignore_receipt_events", &self.ignore_receipt_events)
+        .finish()
+    }
+}
+
+impl BaseClientConfig {
+    /// Create a new default `BaseClientConfig`.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Set a custom implementation of a `StateStore`.
+    ///
+    /// The state store should be opened before being set.
+    pub fn state_store(mut self, store: Box<dyn StateStore>) -> Self {
+        self.state_store = Some(store);
+        self
+    }
+
+    /// Set the number of member events a room may receive in a single sync
+    /// response before they are delivered as a batch.
+    ///
+    /// If a room receives more member state events than the threshold the
+    /// `EventEmitter` will receive a single `on_room_members_loaded()` call
+    /// with a `MembersSummary` instead of an `on_state_member()` call for
+    /// every event. The room state is updated either way.
+    ///
+    /// # Arguments
+    ///
+    /// * `threshold` - The maximal number of member events that are emitted
+    /// one by one.
+    pub fn member_batch_threshold(mut self, threshold: usize) -> Self {
+        self.member_batch_threshold = Some(threshold);
+        self
+    }
+
+    /// Enable the `MediaCache` for downloaded media and thumbnails.
+    ///
+    /// The least recently used media is evicted once the cache grows larger
+    /// than the given size. If a `StateStore` is set the cached media is
+    /// persisted in it and survives restarts.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_size` - The maximal number of bytes the cached media may take
+    /// up.
+    pub fn media_cache_size(mut self, max_size: usize) -> Self {
+        self.media_cache_size = Some(max_size);
+        self
+    }
+
+    /// Don't load the rooms from the `StateStore` before the first sync.
+    ///
+    /// Only the sync token and the client wide state are restored, so the
+    /// first sync can be sent right away. A room is loaded from the store as
+    /// soon as a sync response or one of the room getters needs it, the
+    /// remaining rooms can be loaded in the background using
+    /// `BaseClient::load_stored_rooms()`.
+    pub fn lazy_room_loading(mut self) -> Self {
+        self.lazy_room_loading = true;
+        self
+    }
+
+    /// Keep track of the event types that the client received in sync
+    /// responses but couldn't handle.
+    ///
+    /// Events with a type that is unknown to the client, or that fail to
+    /// deserialize, are counted per event type. The counts can be fetched
+    /// using `BaseClient::unhandled_event_types()`.
+    pub fn track_unhandled_events(mut self) -> Self {
+        self.track_unhandled_events = true;
+        self
+    }
+
+    /// Skip the `m.typing` ephemeral events of sync responses.
+    ///
+    /// The events aren't deserialized and the `EventEmitter` won't receive
+    /// any `on_account_data_typing()` calls.
+    pub fn ignore_typing_events(mut self) -> Self {
+        self.ignore_typing_events = true;
+        self
+    }
+
+    /// Skip the `m.receipt` ephemeral events of sync responses.
+    ///
+    /// The events aren't deserialized and the `EventEmitter` won't receive
+    /// any `on_account_data_receipt()` calls.
+    pub fn ignore_receipt_events(mut self) -> Self {
+        self.ignore_receipt_events = true;
+        self
+    }
+
+    /// Offload the CPU-heavy parts of sync processing to a `WorkerPool`.
+    ///
+    /// Sync responses are deserialized on the blocking thread pool of the
+    /// runtime. The decryption of the timelines of all the joined rooms is
+    /// started before the rooms are handled, every chunk of a timeline is
+    /// decrypted as a separate task, so the rooms are decrypted in parallel
+    /// and a large sync doesn't starve the other tasks of the application.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_jobs` - The number of jobs that may run at the same time.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn sync_worker_pool(mut self, max_jobs: usize) -> Self {
+        self.sync_worker_jobs = Some(max_jobs);
+        self
+    }
+
+    /// Set the `RetentionPolicy` that should be applied to the messages of
+    /// every room.
+    #[cfg(feature = "messages")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "messages")))]
+    pub fn retention_policy(mut self, policy: RetentionPolicy) -> Self {
+        self.retention_policy = policy;
+        self
+    }
+
+    /// Set the `VerificationPolicy` that decides which key verifications are
+    /// accepted and completed without user interaction.
+    #[cfg(feature = "encryption")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "encryption")))]
+    pub fn verification_policy(mut self, policy: VerificationPolicy) -> Self {
+        self.verification_policy = policy;
+        self
+    }
+}
+
 /// A no IO Client implementation.
 ///
 /// This Client is a state machine that receives responses and events and
@@ -103,13 +329,25 @@ pub struct BaseClient {
     invited_rooms: Arc<RwLock<HashMap<RoomId, Arc<RwLock<Room>>>>>,
     /// A map of the rooms our user has left.
     left_rooms: Arc<RwLock<HashMap<RoomId, Arc<RwLock<Room>>>>>,
+    /// The rooms that are in the state store but weren't loaded yet.
+    unloaded_rooms: Arc<RwLock<HashMap<RoomId, RoomStateType>>>,
+    /// Lock that is held while rooms are loaded from the state store, so a
+    /// room that is being loaded can't be created by a sync at the same time.
+    room_load_lock: Arc<Mutex<()>>,
     /// A list of ignored users.
     pub(crate) ignored_users: Arc<RwLock<Vec<UserId>>>,
     /// The push ruleset for the logged in user.
     pub(crate) push_ruleset: Arc<RwLock<Option<Ruleset>>>,
+    /// The JSON of the push ruleset, the push rules are evaluated on it and it
+    /// is only serialized again if the ruleset changes.
+    push_rules: Arc<RwLock<Option<JsonValue>>>,
     /// Any implementor of EventEmitter will act as the callbacks for various
     /// events.
-    event_emitter: Arc<RwLock<Option<Box<dyn EventEmitter>>>>,
+    event_emitters: Arc<RwLock<EventEmitters>>,
+    /// The typed event handlers, keyed by the type of the event they handle.
+    event_handlers: Arc<RwLock<HashMap<&'static str, Vec<EventHandler>>>>,
+    /// The check that classifies incoming messages before they are handled.
+    spam_checker: Arc<RwLock<Option<Box<dyn SpamChecker>>>>,
     /// Any implementor of `StateStore` will be called to save `Room` and
     /// some `BaseClient` state after receiving a sync response.
     ///
@@ -117,9 +355,50 @@ pub struct BaseClient {
     state_store: Arc<RwLock<Option<Box<dyn StateStore>>>>,
     /// Does the `Client` need to sync with the state store.
     needs_state_store_sync: Arc<AtomicBool>,
+    /// The number of member events above which they are emitted as a batch.
+    member_batch_threshold: Option<usize>,
+    /// The cache of downloaded media, if enabled.
+    media_cache: Option<Arc<Mutex<MediaCache>>>,
+    /// Should the rooms be loaded from the state store on demand.
+    lazy_room_loading: bool,
+    /// The number of received events per event type that couldn't be
+    /// handled, if tracking them is enabled.
+    unhandled_events: Option<Arc<RwLock<HashMap<String, u64>>>>,
+    /// The messages that were queued to be sent but weren't sent yet, in
+    /// the order they were queued in.
+    send_queue: Arc<Mutex<Vec<QueuedMessage>>>,
+    /// The room ids that room aliases were resolved to.
+    room_aliases: Arc<RwLock<HashMap<RoomAliasId, RoomId>>>,
+    /// The direct message rooms of our own user, keyed by the user the
+    /// messages are exchanged with, taken from the `m.direct` account data.
+    pub(crate) direct_rooms: Arc<RwLock<BTreeMap<UserId, Vec<RoomId>>>>,
+    /// The content of the global account data events of our own user, keyed
+    /// by the event type.
+    pub(crate) account_data: Arc<RwLock<BTreeMap<String, JsonValue>>>,
+    /// The last known presence of every user we received a presence update
+    /// for, no matter which rooms we share with them.
+    presence: Arc<RwLock<HashMap<UserId, PresenceEventContent>>>,
+    /// Should `m.typing` ephemeral events be skipped.
+    ignore_typing_events: bool,
+    /// Should `m.receipt` ephemeral events be skipped.
+    ignore_receipt_events: bool,
+    /// The pool CPU-heavy sync processing is offloaded to, if enabled.
+    #[cfg(not(target_arch = "wasm32"))]
+    worker_pool: Option<WorkerPool>,
+    /// The retention policy that is applied to the messages of every room.
+    #[cfg(feature = "messages")]
+    retention_policy: RetentionPolicy,
+    /// The full-text index of the decrypted messages.
+    #[cfg(feature = "local-search")]
+    search_index: Arc<RwLock<SearchIndex>>,
 
+    /// The Olm machine, it locks its own state internally. The write lock is
+    /// only needed to set up the machine on login or to remove it.
     #[cfg(feature = "encryption")]
-    olm: Arc<Mutex<Option<OlmMachine>>>,
+    olm: Arc<RwLock<Option<OlmMachine>>>,
+    /// The verification policy every new Olm machine is set up with.
+    #[cfg(feature = "encryption")]
+    verification_policy: VerificationPolicy,
 }
 
 impl fmt::Debug for BaseClient {
@@ -130,7 +409,7 @@ impl fmt::Debug for BaseClient {
             .field("joined_rooms", &self.joined_rooms)
             .field("ignored_users", &self.ignored_users)
             .field("push_ruleset", &self.push_ruleset)
-            .field("event_emitter", &"EventEmitter<...>")
+            .field("event_emitters", &"EventEmitter<...>")
             .finish()
     }
 }
@@ -143,7 +422,7 @@ impl BaseClient {
     /// * `session` - An optional session if the user already has one from a
     /// previous login call.
     pub fn new(session: Option<Session>) -> Result<Self> {
-        BaseClient::new_helper(session, None)
+        BaseClient::new_with_config(session, BaseClientConfig::default())
     }
 
     /// Create a new client.
@@ -159,14 +438,26 @@ impl BaseClient {
         session: Option<Session>,
         store: Box<dyn StateStore>,
     ) -> Result<Self> {
-        BaseClient::new_helper(session, Some(store))
+        BaseClient::new_with_config(session, BaseClientConfig::new().state_store(store))
     }
 
-    fn new_helper(session: Option<Session>, store: Option<Box<dyn StateStore>>) -> Result<Self> {
+    /// Create a new client with the given configuration.
+    ///
+    /// # Arguments
+    ///
+    /// * `session` - An optional session if the user already has one from a
+    /// previous login call.
+    ///
+    /// * `config` - Configuration for the client.
+    pub fn new_with_config(session: Option<Session>, config: BaseClientConfig) -> Result<Self> {
         #[cfg(feature = "encryption")]
         let olm = match &session {
-            Some(s) => Some(OlmMachine::new(&s.user_id, &s.device_id)),
-            None => None,
+            Some(s) if s.has_device() => {
+                let mut olm = OlmMachine::new(&s.user_id, &s.device_id);
+                olm.set_verification_policy(config.verification_policy.clone());
+                Some(olm)
+            }
+            _ => None,
         };
 
         Ok(BaseClient {
@@ -175,13 +466,43 @@ impl BaseClient {
             joined_rooms: Arc::new(RwLock::new(HashMap::new())),
             invited_rooms: Arc::new(RwLock::new(HashMap::new())),
             left_rooms: Arc::new(RwLock::new(HashMap::new())),
+            unloaded_rooms: Arc::new(RwLock::new(HashMap::new())),
+            room_load_lock: Arc::new(Mutex::new(())),
             ignored_users: Arc::new(RwLock::new(Vec::new())),
             push_ruleset: Arc::new(RwLock::new(None)),
-            event_emitter: Arc::new(RwLock::new(None)),
-            state_store: Arc::new(RwLock::new(store)),
+            push_rules: Arc::new(RwLock::new(None)),
+            event_emitters: Arc::new(RwLock::new(EventEmitters::default())),
+            event_handlers: Arc::new(RwLock::new(HashMap::new())),
+            spam_checker: Arc::new(RwLock::new(None)),
+            state_store: Arc::new(RwLock::new(config.state_store)),
             needs_state_store_sync: Arc::new(AtomicBool::from(true)),
+            member_batch_threshold: config.member_batch_threshold,
+            media_cache: config
+                .media_cache_size
+                .map(|size| Arc::new(Mutex::new(MediaCache::new(size)))),
+            lazy_room_loading: config.lazy_room_loading,
+            unhandled_events: if config.track_unhandled_events {
+                Some(Arc::new(RwLock::new(HashMap::new())))
+            } else {
+                None
+            },
+            send_queue: Arc::new(Mutex::new(Vec::new())),
+            room_aliases: Arc::new(RwLock::new(HashMap::new())),
+            direct_rooms: Arc::new(RwLock::new(BTreeMap::new())),
+            account_data: Arc::new(RwLock::new(BTreeMap::new())),
+            presence: Arc::new(RwLock::new(HashMap::new())),
+            ignore_typing_events: config.ignore_typing_events,
+            ignore_receipt_events: config.ignore_receipt_events,
+            #[cfg(not(target_arch = "wasm32"))]
+            worker_pool: config.sync_worker_jobs.map(WorkerPool::new),
+            #[cfg(feature = "messages")]
+            retention_policy: config.retention_policy,
+            #[cfg(feature = "local-search")]
+            search_index: Arc::new(RwLock::new(SearchIndex::new())),
+            #[cfg(feature = "encryption")]
+            olm: Arc::new(RwLock::new(olm)),
             #[cfg(feature = "encryption")]
-            olm: Arc::new(Mutex::new(olm)),
+            verification_policy: config.verification_policy,
         })
     }
 
@@ -191,6 +512,13 @@ impl BaseClient {
         &self.session
     }
 
+    /// The pool CPU-heavy sync processing is offloaded to, if one was
+    /// configured using `BaseClientConfig::sync_worker_pool()`.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn worker_pool(&self) -> Option<&WorkerPool> {
+        self.worker_pool.as_ref()
+    }
+
     /// Is the client logged in.
     pub async fn logged_in(&self) -> bool {
         // TODO turn this into a atomic bool so this method doesn't need to be
@@ -201,8 +529,74 @@ impl BaseClient {
     /// Add `EventEmitter` to `Client`.
     ///
     /// The methods of `EventEmitter` are called when the respective `RoomEvents` occur.
-    pub async fn add_event_emitter(&self, emitter: Box<dyn EventEmitter>) {
-        *self.event_emitter.write().await = Some(emitter);
+    /// Any number of emitters can be added, they are called in the order they
+    /// were added in.
+    ///
+    /// Returns a handle that can be used to remove the emitter again.
+    pub async fn add_event_emitter(&self, emitter: Box<dyn EventEmitter>) -> EmitterHandle {
+        self.event_emitters.write().await.add(emitter)
+    }
+
+    /// Remove an `EventEmitter` that was added with `add_event_emitter()`.
+    ///
+    /// Returns true if the emitter was removed, false if it was already
+    /// removed.
+    ///
+    /// # Arguments
+    ///
+    /// * `handle` - The handle `add_event_emitter()` returned for the
+    /// emitter.
+    pub async fn remove_event_emitter(&self, handle: EmitterHandle) -> bool {
+        self.event_emitters.write().await.remove(handle)
+    }
+
+    /// Register a handler that is called with every timeline or state event
+    /// of the given type.
+    ///
+    /// The type of the event is taken from the argument of the handler, any
+    /// type implementing `SyncEvent` can be handled. Any number of handlers
+    /// can be registered for an event type, they are called in the order they
+    /// were registered in.
+    ///
+    /// # Arguments
+    ///
+    /// * `handler` - The handler that is called with the event and the room
+    /// the event belongs to.
+    pub async fn register_event_handler<Ev, H, Fut>(&self, handler: H)
+    where
+        Ev: SyncEvent,
+        H: Fn(Ev, SyncRoom) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.event_handlers
+            .write()
+            .await
+            .entry(Ev::EVENT_TYPE)
+            .or_default()
+            .push(event_handler(handler));
+    }
+
+    /// Set the `SpamChecker` that classifies the messages of joined rooms
+    /// before they update the room state and reach the `EventEmitter`.
+    pub async fn set_spam_checker(&self, checker: Box<dyn SpamChecker>) {
+        *self.spam_checker.write().await = Some(checker);
+    }
+
+    /// Ask the `SpamChecker` to classify a timeline event.
+    ///
+    /// Returns `None` if no checker is set or if the event isn't a message.
+    async fn check_spam(
+        &self,
+        room_id: &RoomId,
+        event: &EventJson<RoomEvent>,
+    ) -> Option<SpamVerdict> {
+        let checker = self.spam_checker.read().await;
+        let checker = checker.as_ref()?;
+
+        match event.deserialize() {
+            Ok(RoomEvent::RoomMessage(m)) => Some(checker.check_message(room_id, &m).await),
+            _ => None,
+        }
     }
 
     /// Returns true if the state store has been loaded into the client.
@@ -213,6 +607,9 @@ impl BaseClient {
     /// When a client is provided the state store will load state from the `StateStore`.
     ///
     /// Returns `true` when a state store sync has successfully completed.
+    ///
+    /// If lazy room loading is enabled in the `BaseClientConfig`, only the
+    /// ids of the stored rooms are loaded, see `load_stored_rooms()`.
     pub async fn sync_with_state_store(&self) -> Result<bool> {
         let store = self.state_store.read().await;
         if let Some(store) = store.as_ref() {
@@ -222,33 +619,83 @@ impl BaseClient {
                         sync_token,
                         ignored_users,
                         push_ruleset,
+                        direct_rooms,
+                        account_data,
                     } = client_state;
                     *self.sync_token.write().await = sync_token;
                     *self.ignored_users.write().await = ignored_users;
-                    *self.push_ruleset.write().await = push_ruleset;
+                    self.set_push_ruleset(push_ruleset).await;
+                    *self.direct_rooms.write().await = direct_rooms;
+                    *self.account_data.write().await = account_data;
                 } else {
                     // return false and continues with a sync request then save the state and create
                     // and populate the files during the sync
                     return Ok(false);
                 }
 
-                let AllRooms {
-                    mut joined,
-                    mut invited,
-                    mut left,
-                } = store.load_all_rooms().await?;
-                *self.joined_rooms.write().await = joined
-                    .drain()
-                    .map(|(k, room)| (k, Arc::new(RwLock::new(room))))
-                    .collect();
-                *self.invited_rooms.write().await = invited
-                    .drain()
-                    .map(|(k, room)| (k, Arc::new(RwLock::new(room))))
-                    .collect();
-                *self.left_rooms.write().await = left
-                    .drain()
-                    .map(|(k, room)| (k, Arc::new(RwLock::new(room))))
-                    .collect();
+                let mut unloaded = HashMap::new();
+
+                if self.lazy_room_loading {
+                    for room_state in &[RoomStateType::Joined, RoomStateType::Invited] {
+                        for room_id in store.load_room_ids(*room_state).await? {
+                            unloaded.insert(room_id, *room_state);
+                        }
+                    }
+                } else {
+                    *self.joined_rooms.write().await =
+                        Self::load_room_map(&**store, RoomStateType::Joined).await?;
+                    *self.invited_rooms.write().await =
+                        Self::load_room_map(&**store, RoomStateType::Invited).await?;
+                }
+
+                // Left rooms are rarely needed, only remember which ones exist
+                // and load them once they are accessed.
+                self.left_rooms.write().await.clear();
+                for room_id in store.load_room_ids(RoomStateType::Left).await? {
+                    unloaded.insert(room_id, RoomStateType::Left);
+                }
+
+                *self.unloaded_rooms.write().await = unloaded;
+                *self.room_aliases.write().await = store.load_room_aliases().await?;
+
+                if let Some(cache) = &self.media_cache {
+                    let mut cache = cache.lock().await;
+
+                    for (key, size) in store.load_media_keys().await? {
+                        let evicted = cache.insert_stored(&key, size);
+                        Self::remove_evicted_media(&**store, &evicted).await?;
+                    }
+                }
+
+                let mut queue = self.send_queue.lock().await;
+                let mut stored = store.load_queued_messages().await?;
+
+                // Messages that were being sent when the client stopped are
+                // sent again, the transaction id prevents duplicates.
+                for message in &mut stored {
+                    if message.state == SendState::Sending {
+                        message.state = SendState::Queued;
+                    }
+                }
+
+                stored.extend(
+                    queue
+                        .drain(..)
+                        .filter(|m| !stored.iter().any(|s| s.txn_id == m.txn_id)),
+                );
+                *queue = stored;
+                let restored = queue.clone();
+                drop(queue);
+
+                for message in restored {
+                    self.add_local_echo(
+                        &message.room_id,
+                        &message.txn_id,
+                        message.content,
+                        message.state,
+                    )
+                    .await;
+                }
 
                 self.needs_state_store_sync.store(false, Ordering::Relaxed);
             }
@@ -256,137 +703,1355 @@ impl BaseClient {
         Ok(!self.needs_state_store_sync.load(Ordering::Relaxed))
     }
 
-    /// When a client is provided the state store will load state from the `StateStore`.
+    /// Load all the rooms with the given state from the store with a single
+    /// bulk load.
+    async fn load_room_map(
+        store: &dyn StateStore,
+        room_state: RoomStateType,
+    ) -> Result<HashMap<RoomId, Arc<RwLock<Room>>>> {
+        Ok(store
+            .load_rooms_with_state(room_state)
+            .await?
+            .into_iter()
+            .map(|room| (room.room_id.clone(), Arc::new(RwLock::new(room))))
+            .collect())
+    }
+
+    /// Load a room from the state store if it wasn't loaded yet.
     ///
-    /// Returns `true` when a state store sync has successfully completed.
-    pub async fn store_room_state(&self, room_id: &RoomId) -> Result<()> {
+    /// The room is only marked as loaded once the store returned it, if
+    /// loading fails it is tried again the next time the room is accessed.
+    async fn load_unloaded_room(&self, room_id: &RoomId) -> Result<()> {
+        let _guard = self.room_load_lock.lock().await;
+
+        let room_state = match self.unloaded_rooms.read().await.get(room_id) {
+            Some(s) => *s,
+            None => return Ok(()),
+        };
+
+        self.load_rooms(room_state, &[room_id.clone()]).await?;
+        self.unloaded_rooms.write().await.remove(room_id);
+
+        Ok(())
+    }
+
+    /// Load the given rooms from the state store and add them to the room map
+    /// of the given state, rooms that are already in the map are kept.
+    async fn load_rooms(&self, room_state: RoomStateType, room_ids: &[RoomId]) -> Result<()> {
         if let Some(store) = self.state_store.read().await.as_ref() {
-            if let Some(room) = self.get_joined_room(room_id).await {
-                let room = room.read().await;
-                store
-                    .store_room_state(RoomState::Joined(room.deref()))
-                    .await?;
-            }
-            if let Some(room) = self.get_invited_room(room_id).await {
-                let room = room.read().await;
-                store
-                    .store_room_state(RoomState::Invited(room.deref()))
-                    .await?;
-            }
-            if let Some(room) = self.get_left_room(room_id).await {
-                let room = room.read().await;
-                store
-                    .store_room_state(RoomState::Left(room.deref()))
-                    .await?;
+            let rooms = store.load_rooms(room_state, room_ids).await?;
+
+            let mut map = match room_state {
+                RoomStateType::Joined => self.joined_rooms.write().await,
+                RoomStateType::Invited => self.invited_rooms.write().await,
+                RoomStateType::Left => self.left_rooms.write().await,
+            };
+
+            for room in rooms {
+                map.entry(room.room_id.clone())
+                    .or_insert_with(|| Arc::new(RwLock::new(room)));
             }
         }
+
         Ok(())
     }
 
-    /// Receive a login response and update the session of the client.
-    ///
-    /// # Arguments
+    /// Load all the rooms with the given state that are still only in the
+    /// state store.
     ///
-    /// * `response` - A successful login response that contains our access token
-    /// and device id.
-    pub async fn receive_login_response(
-        &self,
-        response: &api::session::login::Response,
-    ) -> Result<()> {
-        let session = Session {
-            access_token: response.access_token.clone(),
-            device_id: response.device_id.clone(),
-            user_id: response.user_id.clone(),
+    /// If the store can load single rooms, see
+    /// `StateStore::loads_rooms_partially()`, the rooms are loaded in batches
+    /// of `ROOM_LOAD_BATCH_SIZE` rooms so only a single batch is read at a
+    /// time. Otherwise all the rooms of the state are loaded at once. Rooms
+    /// that were loaded on demand in the meantime are kept.
+    async fn load_unloaded_rooms(&self, room_state: RoomStateType) -> Result<()> {
+        let store = self.state_store.read().await;
+        let store = match store.as_ref() {
+            Some(store) => store,
+            None => return Ok(()),
         };
-        *self.session.write().await = Some(session);
 
-        #[cfg(feature = "encryption")]
-        {
-            let mut olm = self.olm.lock().await;
-            *olm = Some(OlmMachine::new(&response.user_id, &response.device_id));
+        if !store.loads_rooms_partially() {
+            let _guard = self.room_load_lock.lock().await;
+            let rooms = store.load_rooms_with_state(room_state).await?;
+            self.insert_unloaded_rooms(room_state, rooms).await;
+
+            self.unloaded_rooms
+                .write()
+                .await
+                .retain(|_, s| *s != room_state);
+
+            return Ok(());
         }
 
-        Ok(())
-    }
+        loop {
+            let _guard = self.room_load_lock.lock().await;
 
-    pub(crate) async fn get_or_create_joined_room(&self, room_id: &RoomId) -> Arc<RwLock<Room>> {
-        // If this used to be an invited or left room remove them from our other
-        // hashmaps.
-        self.invited_rooms.write().await.remove(room_id);
-        self.left_rooms.write().await.remove(room_id);
+            let batch: Vec<RoomId> = self
+                .unloaded_rooms
+                .read()
+                .await
+                .iter()
+                .filter(|(_, s)| **s == room_state)
+                .map(|(room_id, _)| room_id.clone())
+                .take(ROOM_LOAD_BATCH_SIZE)
+                .collect();
 
-        let mut rooms = self.joined_rooms.write().await;
-        #[allow(clippy::or_fun_call)]
-        rooms
-            .entry(room_id.clone())
-            .or_insert(Arc::new(RwLock::new(Room::new(
-                room_id,
-                &self
-                    .session
-                    .read()
-                    .await
-                    .as_ref()
-                    .expect("Receiving events while not being logged in")
-                    .user_id,
-            ))))
-            .clone()
+            if batch.is_empty() {
+                return Ok(());
+            }
+
+            let rooms = store.load_rooms(room_state, &batch).await?;
+            self.insert_unloaded_rooms(room_state, rooms).await;
+
+            // Rooms that vanished from the store are forgotten as well,
+            // otherwise they would be part of every batch.
+            let mut unloaded = self.unloaded_rooms.write().await;
+            for room_id in &batch {
+                unloaded.remove(room_id);
+            }
+        }
     }
 
-    /// Get a joined room with the given room id.
-    ///
-    /// # Arguments
+    /// Add rooms that were loaded from the state store to the room map of
+    /// the given state if they still weren't loaded.
     ///
-    /// `room_id` - The unique id of the room that should be fetched.
-    pub async fn get_joined_room(&self, room_id: &RoomId) -> Option<Arc<RwLock<Room>>> {
-        self.joined_rooms.read().await.get(room_id).cloned()
+    /// The caller needs to hold the `room_load_lock`.
+    async fn insert_unloaded_rooms(&self, room_state: RoomStateType, rooms: Vec<Room>) {
+        let mut unloaded = self.unloaded_rooms.write().await;
+
+        let mut map = match room_state {
+            RoomStateType::Joined => self.joined_rooms.write().await,
+            RoomStateType::Invited => self.invited_rooms.write().await,
+            RoomStateType::Left => self.left_rooms.write().await,
+        };
+
+        for room in rooms {
+            if unloaded.remove(&room.room_id).is_some() {
+                map.entry(room.room_id.clone())
+                    .or_insert_with(|| Arc::new(RwLock::new(room)));
+            }
+        }
     }
 
-    /// Returns the joined rooms this client knows about.
+    /// Load the joined and invited rooms that weren't loaded yet because lazy
+    /// room loading is enabled.
     ///
-    /// A `HashMap` of room id to `matrix::models::Room`
-    pub fn joined_rooms(&self) -> Arc<RwLock<HashMap<RoomId, Arc<RwLock<Room>>>>> {
-        self.joined_rooms.clone()
+    /// The rooms are loaded in batches, a room that a sync response touches in
+    /// the meantime is loaded before the response is applied to it. This is
+    /// meant to run in the background while the client is already syncing.
+    pub async fn load_stored_rooms(&self) -> Result<()> {
+        self.load_unloaded_rooms(RoomStateType::Joined).await?;
+        self.load_unloaded_rooms(RoomStateType::Invited).await
     }
 
-    pub(crate) async fn get_or_create_invited_room(&self, room_id: &RoomId) -> Arc<RwLock<Room>> {
-        // Remove the left rooms only here, since a join -> invite action per
-        // spec can't happen.
-        self.left_rooms.write().await.remove(room_id);
-
-        let mut rooms = self.invited_rooms.write().await;
-        #[allow(clippy::or_fun_call)]
-        rooms
-            .entry(room_id.clone())
-            .or_insert(Arc::new(RwLock::new(Room::new(
-                room_id,
-                &self
-                    .session
-                    .read()
-                    .await
-                    .as_ref()
-                    .expect("Receiving events while not being logged in")
-                    .user_id,
-            ))))
-            .clone()
+    /// Load all the left rooms from the state store that weren't loaded yet.
+    ///
+    /// Left rooms are loaded lazily when the client syncs with the state
+    /// store, `get_left_room()` loads a single room on demand. Call this
+    /// before iterating over `left_rooms()` if all of them are required.
+    pub async fn load_left_rooms(&self) -> Result<()> {
+        self.load_unloaded_rooms(RoomStateType::Left).await
     }
 
-    /// Get an invited room with the given room id.
+    /// Get media from the `MediaCache`.
+    ///
+    /// Media that isn't held in memory is loaded from the `StateStore`.
+    /// Returns `None` if the media cache isn't enabled or the media isn't
+    /// cached.
     ///
     /// # Arguments
     ///
-    /// `room_id` - The unique id of the room that should be fetched.
-    pub async fn get_invited_room(&self, room_id: &RoomId) -> Option<Arc<RwLock<Room>>> {
-        self.invited_rooms.read().await.get(room_id).cloned()
-    }
+    /// * `key` - The `mxc://` URI of the media or the
+    /// `MediaCache::thumbnail_key()` of a thumbnail.
+    pub async fn get_cached_media(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        let cache = match &self.media_cache {
+            Some(c) => c,
+            None => return Ok(None),
+        };
+        let mut cache = cache.lock().await;
 
-    /// Returns the invited rooms this client knows about.
-    ///
-    /// A `HashMap` of room id to `matrix::models::Room`
-    pub fn invited_rooms(&self) -> Arc<RwLock<HashMap<RoomId, Arc<RwLock<Room>>>>> {
-        self.invited_rooms.clone()
-    }
+        if let Some(data) = cache.get(key) {
+            return Ok(Some(data));
+        }
+
+        let store = self.state_store.read().await;
+        let store = match store.as_ref() {
+            Some(s) => s,
+            None => return Ok(None),
+        };
+
+        let data = store.load_media(key).await?;
+
+        if let Some(data) = &data {
+            let evicted = cache.insert(key, data.clone());
+            Self::remove_evicted_media(&**store, &evicted).await?;
+        }
+
+        Ok(data)
+    }
+
+    /// Is the media cache enabled.
+    pub fn has_media_cache(&self) -> bool {
+        self.media_cache.is_some()
+    }
+
+    /// Is the media with the given key cached, either in memory or in the
+    /// `StateStore`.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The `mxc://` URI of the media or the
+    /// `MediaCache::thumbnail_key()` of a thumbnail.
+    pub async fn is_media_cached(&self, key: &str) -> bool {
+        match &self.media_cache {
+            Some(cache) => cache.lock().await.contains(key),
+            None => false,
+        }
+    }
+
+    /// Put downloaded media into the `MediaCache`.
+    ///
+    /// Does nothing if the media cache isn't enabled.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The `mxc://` URI of the media or the
+    /// `MediaCache::thumbnail_key()` of a thumbnail.
+    ///
+    /// * `data` - The downloaded media.
+    pub async fn cache_media(&self, key: &str, data: &[u8]) -> Result<()> {
+        let cache = match &self.media_cache {
+            Some(c) => c,
+            None => return Ok(()),
+        };
+        let mut cache = cache.lock().await;
+
+        let evicted = cache.insert(key, data.to_vec());
+
+        if let Some(store) = self.state_store.read().await.as_ref() {
+            if !evicted.iter().any(|k| k == key) {
+                store.store_media(key, data).await?;
+            }
+
+            Self::remove_evicted_media(&**store, &evicted).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn remove_evicted_media(store: &dyn StateStore, evicted: &[String]) -> Result<()> {
+        for key in evicted {
+            store.remove_media(key).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Put a message into the send queue.
+    ///
+    /// The queue is persisted in the `StateStore` if one is set.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The id of the room the message should be sent to.
+    ///
+    /// * `txn_id` - The transaction id the message should be sent with.
+    ///
+    /// * `content` - The content of the message.
+    pub async fn queue_message(
+        &self,
+        room_id: &RoomId,
+        txn_id: &str,
+        content: MessageEventContent,
+    ) -> Result<()> {
+        self.add_local_echo(room_id, txn_id, content.clone(), SendState::Queued)
+            .await;
+
+        let mut queue = self.send_queue.lock().await;
+        queue.push(QueuedMessage::new(
+            room_id.clone(),
+            txn_id.to_owned(),
+            content,
+        ));
+        self.store_send_queue(&queue).await
+    }
+
+    /// Get the messages of the send queue that weren't sent yet.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - Only return the messages for this room if given.
+    pub async fn queued_messages(&self, room_id: Option<&RoomId>) -> Vec<QueuedMessage> {
+        self.send_queue
+            .lock()
+            .await
+            .iter()
+            .filter(|m| room_id.map_or(true, |r| &m.room_id == r))
+            .cloned()
+            .collect()
+    }
+
+    /// Get the next message of the send queue that can be sent and mark it as
+    /// being sent.
+    ///
+    /// Messages of a room are sent in the order they were queued in, a room
+    /// whose oldest message failed or is being sent is skipped.
+    pub async fn next_queued_message(&self) -> Result<Option<QueuedMessage>> {
+        let mut queue = self.send_queue.lock().await;
+        let mut blocked_rooms = Vec::new();
+
+        let position = queue.iter().position(|m| {
+            if blocked_rooms.contains(&&m.room_id) {
+                return false;
+            }
+
+            if m.state == SendState::Queued {
+                true
+            } else {
+                blocked_rooms.push(&m.room_id);
+                false
+            }
+        });
+
+        let message = match position {
+            Some(p) => {
+                queue[p].state = SendState::Sending;
+                queue[p].clone()
+            }
+            None => return Ok(None),
+        };
+
+        self.store_send_queue(&queue).await?;
+        drop(queue);
+
+        self.update_local_echo(&message.room_id, &message.txn_id, SendState::Sending)
+            .await;
+
+        Ok(Some(message))
+    }
+
+    /// Update the state of a message in the send queue.
+    ///
+    /// Messages that were sent are removed from the queue.
+    ///
+    /// # Arguments
+    ///
+    /// * `txn_id` - The transaction id of the message.
+    ///
+    /// * `state` - The new state of the message.
+    pub async fn set_queued_message_state(&self, txn_id: &str, state: SendState) -> Result<()> {
+        let mut queue = self.send_queue.lock().await;
+
+        let room_id = match queue.iter_mut().find(|m| m.txn_id == txn_id) {
+            Some(message) => {
+                message.state = state.clone();
+                message.room_id.clone()
+            }
+            None => return Ok(()),
+        };
+
+        if let SendState::Sent(_) = state {
+            queue.retain(|m| m.txn_id != txn_id);
+        }
+
+        self.store_send_queue(&queue).await?;
+        drop(queue);
+
+        self.update_local_echo(&room_id, txn_id, state).await;
+
+        Ok(())
+    }
+
+    /// Remove a message from the send queue without sending it.
+    ///
+    /// Returns the message if it was queued and isn't being sent right now.
+    ///
+    /// # Arguments
+    ///
+    /// * `txn_id` - The transaction id of the message.
+    pub async fn remove_queued_message(&self, txn_id: &str) -> Result<Option<QueuedMessage>> {
+        let mut queue = self.send_queue.lock().await;
+
+        let position = queue
+            .iter()
+            .position(|m| m.txn_id == txn_id && m.state != SendState::Sending);
+
+        let message = match position {
+            Some(p) => queue.remove(p),
+            None => return Ok(None),
+        };
+
+        self.store_send_queue(&queue).await?;
+        drop(queue);
+
+        self.remove_local_echo(&message.room_id, txn_id).await;
+
+        Ok(Some(message))
+    }
+
+    /// Add the local echo of a message our own user sent to a joined room.
+    ///
+    /// The `EventEmitter` receives an `on_room_local_echo()` call for it.
+    /// Returns false if the room isn't known or if the message already has a
+    /// local echo.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The id of the room the message is sent to.
+    ///
+    /// * `txn_id` - The transaction id the message is sent with.
+    ///
+    /// * `content` - The content of the message.
+    ///
+    /// * `state` - How far the message got on its way to the server.
+    pub async fn add_local_echo(
+        &self,
+        room_id: &RoomId,
+        txn_id: &str,
+        content: MessageEventContent,
+        state: SendState,
+    ) -> bool {
+        let sender = match self.session.read().await.as_ref() {
+            Some(s) => s.user_id.clone(),
+            None => return false,
+        };
+
+        let room = match self.get_joined_room(room_id).await {
+            Some(r) => r,
+            None => return false,
+        };
+
+        let event = PendingEvent {
+            txn_id: txn_id.to_owned(),
+            sender,
+            content,
+            created: SystemTime::now(),
+            state,
+        };
+
+        if !room.write().await.add_pending_event(event.clone()) {
+            return false;
+        }
+
+        if let Some(ee) = self.event_emitters.read().await.active() {
+            ee.on_room_local_echo(
+                SyncRoom::with_context(RoomState::Joined(room)).await,
+                &event,
+            )
+            .await;
+        }
+
+        true
+    }
+
+    /// Attach the preview of a URL to the message of a joined room it was
+    /// found in.
+    ///
+    /// The `EventEmitter` receives an `on_room_url_preview()` call for it.
+    /// Returns false if the room isn't known or if the message already has
+    /// a preview for the URL.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The id of the room the message belongs to.
+    ///
+    /// * `event_id` - The id of the message the URL was found in.
+    ///
+    /// * `preview` - The preview of the URL.
+    pub async fn receive_url_preview(
+        &self,
+        room_id: &RoomId,
+        event_id: &EventId,
+        preview: UrlPreview,
+    ) -> bool {
+        let room = match self.get_joined_room(room_id).await {
+            Some(r) => r,
+            None => return false,
+        };
+
+        if !room
+            .write()
+            .await
+            .add_url_preview(event_id, preview.clone())
+        {
+            return false;
+        }
+
+        if let Some(ee) = self.event_emitters.read().await.active() {
+            let room = SyncRoom::with_context(RoomState::Joined(room)).await;
+            ee.on_room_url_preview(room, event_id, &preview).await;
+        }
+
+        true
+    }
+
+    /// Update the send state of the local echo of a message.
+    ///
+    /// Returns false if the message has no local echo.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The id of the room the message is sent to.
+    ///
+    /// * `txn_id` - The transaction id the message is sent with.
+    ///
+    /// * `state` - The new send state of the message.
+    pub async fn update_local_echo(
+        &self,
+        room_id: &RoomId,
+        txn_id: &str,
+        state: SendState,
+    ) -> bool {
+        match self.get_joined_room(room_id).await {
+            Some(room) => room.write().await.update_pending_event(txn_id, state),
+            None => false,
+        }
+    }
+
+    /// Remove the local echo of a message that won't be sent.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The id of the room the message was sent to.
+    ///
+    /// * `txn_id` - The transaction id of the message.
+    pub async fn remove_local_echo(&self, room_id: &RoomId, txn_id: &str) -> Option<PendingEvent> {
+        self.get_joined_room(room_id)
+            .await?
+            .write()
+            .await
+            .remove_pending_event(txn_id)
+    }
+
+    async fn store_send_queue(&self, queue: &[QueuedMessage]) -> Result<()> {
+        if let Some(store) = self.state_store.read().await.as_ref() {
+            store.store_queued_messages(queue).await?;
+        }
+
+        Ok(())
+    }
+
+    /// When a client is provided the state store will load state from the `StateStore`.
+    ///
+    /// Returns `true` when a state store sync has successfully completed.
+    pub async fn store_room_state(&self, room_id: &RoomId) -> Result<()> {
+        // Fetch the rooms first, fetching them might need to load them from
+        // the store.
+        let joined = self.get_joined_room(room_id).await;
+        let invited = self.get_invited_room(room_id).await;
+        let left = self.get_left_room(room_id).await;
+
+        if let Some(store) = self.state_store.read().await.as_ref() {
+            if let Some(room) = joined {
+                let room = room.read().await;
+                store
+                    .store_room_state(RoomState::Joined(room.deref()))
+                    .await?;
+            }
+            if let Some(room) = invited {
+                let room = room.read().await;
+                store
+                    .store_room_state(RoomState::Invited(room.deref()))
+                    .await?;
+            }
+            if let Some(room) = left {
+                let room = room.read().await;
+                store
+                    .store_room_state(RoomState::Left(room.deref()))
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Remove all the messages of a room that were sent before the given point
+    /// in time.
+    ///
+    /// Returns true if any message was removed, the updated room is saved in the
+    /// `StateStore` if one is set.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The unique id of the room that should be pruned.
+    ///
+    /// * `before` - Messages older than this will be removed.
+    #[cfg(feature = "messages")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "messages")))]
+    pub async fn prune_room_history(&self, room_id: &RoomId, before: SystemTime) -> Result<bool> {
+        let room = if let Some(room) = self.get_joined_room(room_id).await {
+            room
+        } else if let Some(room) = self.get_left_room(room_id).await {
+            room
+        } else {
+            return Ok(false);
+        };
+
+        let pruned = room.write().await.messages.prune_before(before);
+
+        if pruned {
+            self.store_room_state(room_id).await?;
+        }
+
+        Ok(pruned)
+    }
+
+    /// Receive a login response and update the session of the client.
+    ///
+    /// # Arguments
+    ///
+    /// * `response` - A successful login response that contains our access token
+    /// and device id.
+    pub async fn receive_login_response(
+        &self,
+        response: &api::session::login::Response,
+    ) -> Result<()> {
+        let session = Session {
+            access_token: response.access_token.clone(),
+            device_id: response.device_id.clone(),
+            user_id: response.user_id.clone(),
+        };
+        *self.session.write().await = Some(session);
+
+        #[cfg(feature = "encryption")]
+        {
+            let mut machine = OlmMachine::new(&response.user_id, &response.device_id);
+            machine.set_verification_policy(self.verification_policy.clone());
+            *self.olm.write().await = Some(machine);
+        }
+
+        Ok(())
+    }
+
+    pub(crate) async fn get_or_create_joined_room(&self, room_id: &RoomId) -> Arc<RwLock<Room>> {
+        // A failure to load the stored room isn't fatal, the room state will
+        // be rebuilt from the events.
+        let _ = self.load_unloaded_room(room_id).await;
+
+        // If this used to be an invited or left room remove them from our other
+        // hashmaps.
+        self.invited_rooms.write().await.remove(room_id);
+        self.left_rooms.write().await.remove(room_id);
+
+        let mut rooms = self.joined_rooms.write().await;
+        #[allow(clippy::or_fun_call)]
+        rooms
+            .entry(room_id.clone())
+            .or_insert(Arc::new(RwLock::new(Room::new(
+                room_id,
+                &self
+                    .session
+                    .read()
+                    .await
+                    .as_ref()
+                    .expect("Receiving events while not being logged in")
+                    .user_id,
+            ))))
+            .clone()
+    }
+
+    /// Receive the id of a room our user just joined.
+    ///
+    /// The room is moved into the joined rooms right away, keeping the state
+    /// that is already known from an invite or an earlier membership, instead
+    /// of waiting for the next sync response.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The unique id of the joined room.
+    pub async fn receive_joined_room(&self, room_id: &RoomId) -> Result<Arc<RwLock<Room>>> {
+        let room = self.move_room(room_id, RoomStateType::Joined).await?;
+        Ok(room.expect("Moving a room into the joined rooms always creates it"))
+    }
+
+    /// Receive the id of a room our user just left.
+    ///
+    /// If the room is known it is moved into the left rooms right away.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The unique id of the left room.
+    pub async fn receive_left_room(&self, room_id: &RoomId) -> Result<()> {
+        self.move_room(room_id, RoomStateType::Left).await?;
+        Ok(())
+    }
+
+    /// Receive the id of a room our user just forgot.
+    ///
+    /// The room is removed from the client and from the state store.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The unique id of the forgotten room.
+    pub async fn receive_forgotten_room(&self, room_id: &RoomId) -> Result<()> {
+        self.joined_rooms.write().await.remove(room_id);
+        self.invited_rooms.write().await.remove(room_id);
+        self.left_rooms.write().await.remove(room_id);
+        self.unloaded_rooms.write().await.remove(room_id);
+
+        if let Some(store) = self.state_store.read().await.as_ref() {
+            store.remove_room(room_id).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Get the room id the given room alias was resolved to before.
+    ///
+    /// Room aliases are resolved by `Client::resolve_room_alias()`, the
+    /// canonical aliases of the joined rooms are known as well.
+    ///
+    /// # Arguments
+    ///
+    /// * `alias` - The alias of the room.
+    pub async fn cached_room_id(&self, alias: &RoomAliasId) -> Option<RoomId> {
+        self.room_aliases.read().await.get(alias).cloned()
+    }
+
+    /// Remember the room id a room alias was resolved to.
+    ///
+    /// The mapping is persisted in the state store if there is one.
+    ///
+    /// # Arguments
+    ///
+    /// * `alias` - The alias of the room.
+    ///
+    /// * `room_id` - The id of the room the alias points to.
+    pub async fn cache_room_alias(&self, alias: &RoomAliasId, room_id: &RoomId) -> Result<()> {
+        let aliases = {
+            let mut aliases = self.room_aliases.write().await;
+
+            if aliases.get(alias) == Some(room_id) {
+                return Ok(());
+            }
+
+            aliases.insert(alias.clone(), room_id.clone());
+            aliases.clone()
+        };
+
+        if let Some(store) = self.state_store.read().await.as_ref() {
+            store.store_room_aliases(&aliases).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Add the canonical aliases of the given joined rooms to the room alias
+    /// cache.
+    async fn update_room_aliases(&self, room_ids: &[RoomId]) -> Result<()> {
+        let mut canonical_aliases = Vec::new();
+
+        for room_id in room_ids {
+            if let Some(room) = self.get_joined_room(room_id).await {
+                if let Some(alias) = room.read().await.room_name.canonical_alias() {
+                    canonical_aliases.push((alias.clone(), room_id.clone()));
+                }
+            }
+        }
+
+        let aliases = {
+            let mut aliases = self.room_aliases.write().await;
+            let mut changed = false;
+
+            for (alias, room_id) in canonical_aliases {
+                if aliases.get(&alias) != Some(&room_id) {
+                    aliases.insert(alias, room_id);
+                    changed = true;
+                }
+            }
+
+            if !changed {
+                return Ok(());
+            }
+
+            aliases.clone()
+        };
+
+        if let Some(store) = self.state_store.read().await.as_ref() {
+            store.store_room_aliases(&aliases).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Get the ids of the direct message rooms we share with the given user,
+    /// taken from the `m.direct` account data of our own user.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - The user the direct messages are exchanged with.
+    pub async fn direct_room_ids(&self, user_id: &UserId) -> Vec<RoomId> {
+        self.direct_rooms
+            .read()
+            .await
+            .get(user_id)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Receive the content of the `m.direct` account data of our own user.
+    ///
+    /// The known rooms are marked as direct message rooms right away, without
+    /// waiting for the account data to come down the next sync.
+    ///
+    /// Returns true if the direct message rooms changed, false otherwise.
+    ///
+    /// # Arguments
+    ///
+    /// * `direct` - The direct message rooms keyed by the user the messages
+    /// are exchanged with.
+    pub async fn receive_direct_rooms(
+        &self,
+        direct: BTreeMap<UserId, Vec<RoomId>>,
+    ) -> Result<bool> {
+        if *self.direct_rooms.read().await == direct {
+            return Ok(false);
+        }
+
+        *self.direct_rooms.write().await = direct;
+
+        let mut room_ids = Vec::new();
+        for rooms in &[&self.joined_rooms, &self.invited_rooms, &self.left_rooms] {
+            room_ids.extend(rooms.read().await.keys().cloned());
+        }
+        self.update_direct_targets(&room_ids).await?;
+
+        if let Some(store) = self.state_store.read().await.as_ref() {
+            let state = ClientState::from_base_client(&self).await;
+            store.store_client_state(state).await?;
+        }
+
+        Ok(true)
+    }
+
+    /// Mark the given rooms as direct message rooms if they are listed in
+    /// the `m.direct` account data, storing the rooms that changed.
+    async fn update_direct_targets(&self, room_ids: &[RoomId]) -> Result<()> {
+        let targets: Vec<(RoomId, Option<UserId>)> = {
+            let direct = self.direct_rooms.read().await;
+            room_ids
+                .iter()
+                .map(|room_id| {
+                    let target = direct
+                        .iter()
+                        .find(|(_, rooms)| rooms.contains(room_id))
+                        .map(|(user_id, _)| user_id.clone());
+                    (room_id.clone(), target)
+                })
+                .collect()
+        };
+
+        for (room_id, target) in targets {
+            let changed = match self.get_room(&room_id).await {
+                Some(room) => room.write().await.set_direct_target(target),
+                None => false,
+            };
+
+            if changed {
+                self.store_room_state(&room_id).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Get the content of a global account data event of our own user.
+    ///
+    /// Returns `None` if no account data of the given type was received.
+    ///
+    /// # Arguments
+    ///
+    /// * `event_type` - The type of the account data event.
+    pub async fn account_data(&self, event_type: &str) -> Option<JsonValue> {
+        self.account_data.read().await.get(event_type).cloned()
+    }
+
+    /// Receive the content of a global account data event of our own user,
+    /// e.g. after it was replaced using `Client::set_account_data()`.
+    ///
+    /// Returns true if the account data changed, false otherwise.
+    ///
+    /// # Arguments
+    ///
+    /// * `event_type` - The type of the account data event.
+    ///
+    /// * `content` - The new content of the account data event.
+    pub async fn receive_global_account_data(
+        &self,
+        event_type: &str,
+        content: JsonValue,
+    ) -> Result<bool> {
+        if self.account_data.read().await.get(event_type) == Some(&content) {
+            return Ok(false);
+        }
+
+        if event_type == "m.direct" {
+            if let Ok(direct) = serde_json::from_value(content.clone()) {
+                self.receive_direct_rooms(direct).await?;
+            }
+        } else if event_type == "m.push_rules" {
+            if let Ok(ruleset) = serde_json::from_value::<Ruleset>(content["global"].clone()) {
+                self.set_push_ruleset(Some(ruleset)).await;
+            }
+        }
+
+        self.account_data
+            .write()
+            .await
+            .insert(event_type.to_owned(), content);
+
+        if let Some(store) = self.state_store.read().await.as_ref() {
+            let state = ClientState::from_base_client(&self).await;
+            store.store_client_state(state).await?;
+        }
+
+        Ok(true)
+    }
+
+    /// Receive the content of a room account data event of our own user,
+    /// e.g. after it was replaced using `Client::set_room_account_data()`.
+    ///
+    /// Returns true if the account data of the room changed, false otherwise.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The id of the room the account data belongs to.
+    ///
+    /// * `event_type` - The type of the account data event.
+    ///
+    /// * `content` - The new content of the account data event.
+    pub async fn receive_room_account_data(
+        &self,
+        room_id: &RoomId,
+        event_type: &str,
+        content: JsonValue,
+    ) -> Result<bool> {
+        let changed = match self.get_room(room_id).await {
+            Some(room) => room.write().await.receive_account_data(event_type, content),
+            None => false,
+        };
+
+        if changed {
+            self.store_room_state(room_id).await?;
+        }
+
+        Ok(changed)
+    }
+
+    /// Receive the global account data of a sync response, the rooms of the
+    /// response are marked as direct message rooms if the `m.direct` account
+    /// data lists them.
+    async fn iter_account_data(&self, response: &api::sync::sync_events::Response) -> Result<()> {
+        for event in &response.account_data.events {
+            if let Ok(event) = serde_json::from_str::<AccountDataJson>(event.json().get()) {
+                self.receive_global_account_data(&event.event_type, event.content)
+                    .await?;
+            }
+        }
+
+        // The new rooms of the response need to be marked even if the account
+        // data didn't change.
+        let rooms = &response.rooms;
+        let room_ids: Vec<RoomId> = rooms
+            .join
+            .keys()
+            .chain(rooms.invite.keys())
+            .chain(rooms.leave.keys())
+            .cloned()
+            .collect();
+        self.update_direct_targets(&room_ids).await?;
+
+        Ok(())
+    }
+
+    /// Remove all the local state of the client, e.g. after the account was
+    /// deactivated.
+    ///
+    /// The rooms, the send queue, the sync token and the cached media are
+    /// dropped and the state store is cleared. The encryption keys are removed
+    /// from the crypto store as well. The session is removed too, so the
+    /// client is logged out afterwards.
+    pub async fn clear_local_state(&self) -> Result<()> {
+        self.joined_rooms.write().await.clear();
+        self.invited_rooms.write().await.clear();
+        self.left_rooms.write().await.clear();
+        self.unloaded_rooms.write().await.clear();
+        self.send_queue.lock().await.clear();
+        self.room_aliases.write().await.clear();
+        self.direct_rooms.write().await.clear();
+        self.account_data.write().await.clear();
+        self.presence.write().await.clear();
+        self.sync_token.write().await.take();
+        self.ignored_users.write().await.clear();
+        self.set_push_ruleset(None).await;
+
+        if let Some(cache) = &self.media_cache {
+            let mut cache = cache.lock().await;
+            *cache = MediaCache::new(cache.max_size());
+        }
+
+        if let Some(store) = self.state_store.read().await.as_ref() {
+            store.clear().await?;
+        }
+
+        #[cfg(feature = "encryption")]
+        {
+            if let Some(olm) = self.olm.write().await.take() {
+                olm.clear_store().await.map_err(OlmError::from)?;
+            }
+        }
+
+        self.session.write().await.take();
+
+        Ok(())
+    }
+
+    /// Move a room into the room map of the given state, keeping its state.
+    ///
+    /// A new room is only created if the room moves into the joined rooms,
+    /// returns `None` if the room isn't known otherwise.
+    async fn move_room(
+        &self,
+        room_id: &RoomId,
+        room_state: RoomStateType,
+    ) -> Result<Option<Arc<RwLock<Room>>>> {
+        self.load_unloaded_room(room_id).await?;
+
+        let (target, others) = match room_state {
+            RoomStateType::Joined => (&self.joined_rooms, [&self.invited_rooms, &self.left_rooms]),
+            RoomStateType::Invited => (&self.invited_rooms, [&self.joined_rooms, &self.left_rooms]),
+            RoomStateType::Left => (&self.left_rooms, [&self.joined_rooms, &self.invited_rooms]),
+        };
+
+        let mut previous = target.read().await.get(room_id).cloned();
+
+        for rooms in others.iter() {
+            if let Some(room) = rooms.write().await.remove(room_id) {
+                previous = previous.or(Some(room));
+            }
+        }
+
+        let room = match previous {
+            Some(room) => room,
+            None if room_state == RoomStateType::Joined => {
+                let own_user_id = self
+                    .session
+                    .read()
+                    .await
+                    .as_ref()
+                    .map(|s| s.user_id.clone())
+                    .ok_or(Error::AuthenticationRequired)?;
+
+                Arc::new(RwLock::new(Room::new(room_id, &own_user_id)))
+            }
+            None => return Ok(None),
+        };
+
+        target
+            .write()
+            .await
+            .insert(room_id.clone(), Arc::clone(&room));
+
+        if let Some(store) = self.state_store.read().await.as_ref() {
+            store.remove_room(room_id).await?;
+        }
+        self.store_room_state(room_id).await?;
+
+        Ok(Some(room))
+    }
+
+    /// Receive the id of a room our user just created.
+    ///
+    /// The room is added to the joined rooms right away, so it can be used
+    /// before the next sync response arrives.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The unique id of the created room.
+    ///
+    /// * `encrypted` - Was the room created with encryption enabled.
+    pub async fn receive_created_room(
+        &self,
+        room_id: &RoomId,
+        encrypted: bool,
+    ) -> Result<Arc<RwLock<Room>>> {
+        let room = self.get_or_create_joined_room(room_id).await;
+
+        {
+            let mut room = room.write().await;
+            room.creator = Some(room.own_user_id.clone());
+            room.encrypted |= encrypted;
+        }
+
+        self.store_room_state(room_id).await?;
+
+        Ok(room)
+    }
+
+    /// Get a joined room with the given room id.
+    ///
+    /// # Arguments
+    ///
+    /// `room_id` - The unique id of the room that should be fetched.
+    pub async fn get_joined_room(&self, room_id: &RoomId) -> Option<Arc<RwLock<Room>>> {
+        self.load_unloaded_room(room_id).await.ok()?;
+        self.joined_rooms.read().await.get(room_id).cloned()
+    }
+
+    /// Returns the joined rooms this client knows about.
+    ///
+    /// A `HashMap` of room id to `matrix::models::Room`
+    pub fn joined_rooms(&self) -> Arc<RwLock<HashMap<RoomId, Arc<RwLock<Room>>>>> {
+        self.joined_rooms.clone()
+    }
+
+    /// Attach data of an application to a room and store the room in the
+    /// `StateStore`.
+    ///
+    /// See `Room::set_app_data()`. Returns false if the room isn't known.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The id of the room.
+    ///
+    /// * `namespace` - The namespace of the application.
+    ///
+    /// * `data` - The data that should be attached.
+    pub async fn set_room_app_data<T: Serialize>(
+        &self,
+        room_id: &RoomId,
+        namespace: &str,
+        data: &T,
+    ) -> Result<bool> {
+        let room = match self.get_room(room_id).await {
+            Some(r) => r,
+            None => return Ok(false),
+        };
+
+        room.write().await.set_app_data(namespace, data)?;
+        self.store_room_state(room_id).await?;
+
+        Ok(true)
+    }
+
+    /// Get the data an application attached to a room.
+    ///
+    /// See `Room::app_data()`.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The id of the room.
+    ///
+    /// * `namespace` - The namespace of the application.
+    pub async fn room_app_data<T: DeserializeOwned>(
+        &self,
+        room_id: &RoomId,
+        namespace: &str,
+    ) -> Result<Option<T>> {
+        match self.get_room(room_id).await {
+            Some(room) => room.read().await.app_data(namespace),
+            None => Ok(None),
+        }
+    }
+
+    /// Get the id of a filter that was uploaded under the given name.
+    ///
+    /// # Arguments
+    ///
+    /// * `filter_name` - The name the filter was stored under.
+    pub async fn get_filter(&self, filter_name: &str) -> Result<Option<String>> {
+        match self.state_store.read().await.as_ref() {
+            Some(store) => store.load_filter(filter_name).await,
+            None => Ok(None),
+        }
+    }
+
+    /// Receive the id the homeserver assigned to an uploaded filter and
+    /// persist it in the state store, so the filter can be reused.
+    ///
+    /// # Arguments
+    ///
+    /// * `filter_name` - The name the filter should be stored under.
+    ///
+    /// * `filter_id` - The id of the uploaded filter.
+    pub async fn receive_filter_upload(&self, filter_name: &str, filter_id: &str) -> Result<()> {
+        if let Some(store) = self.state_store.read().await.as_ref() {
+            store.store_filter(filter_name, filter_id).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Persist a small application defined value in the state store.
+    ///
+    /// Nothing is persisted if the client doesn't have a state store.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key the value is stored under, applications should
+    /// prefix it with their own namespace.
+    ///
+    /// * `value` - The value that should be stored.
+    pub async fn set_custom_value(&self, key: &str, value: &[u8]) -> Result<()> {
+        if let Some(store) = self.state_store.read().await.as_ref() {
+            store.set_custom_value(key, value).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Load a value that was stored with `set_custom_value()`.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key the value was stored under.
+    pub async fn get_custom_value(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        match self.state_store.read().await.as_ref() {
+            Some(store) => store.get_custom_value(key).await,
+            None => Ok(None),
+        }
+    }
+
+    /// Remove a value that was stored with `set_custom_value()`.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key the value was stored under.
+    pub async fn remove_custom_value(&self, key: &str) -> Result<()> {
+        if let Some(store) = self.state_store.read().await.as_ref() {
+            store.remove_custom_value(key).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Merge the full member list of a room, e.g. fetched from the `/members`
+    /// endpoint, into the room.
+    ///
+    /// Returns true if the member list of the room changed or wasn't complete
+    /// before, false otherwise.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The id of the room.
+    ///
+    /// * `events` - The current `m.room.member` state events of the room.
+    pub async fn receive_members(&self, room_id: &RoomId, events: &[MemberEvent]) -> Result<bool> {
+        let room = match self.get_room(room_id).await {
+            Some(r) => r,
+            None => return Ok(false),
+        };
+
+        let changed = room.write().await.receive_members(events);
+
+        // Syncs lazily load the members, the devices of encrypted rooms are
+        // only tracked for the whole room once the full list is known.
+        #[cfg(feature = "encryption")]
+        {
+            let room = room.read().await;
+
+            if changed && room.is_encrypted() {
+                if let Some(o) = &*self.olm.read().await {
+                    o.update_tracked_users(room.members.keys()).await;
+                }
+            }
+        }
+
+        if changed {
+            self.store_room_state(room_id).await?;
+        }
+
+        Ok(changed)
+    }
+
+    /// Get the joined rooms the homeserver sends its server notices to.
+    pub async fn server_notice_rooms(&self) -> Vec<Arc<RwLock<Room>>> {
+        let mut rooms = Vec::new();
+
+        for room in self.joined_rooms.read().await.values() {
+            if room.read().await.is_server_notice_room() {
+                rooms.push(Arc::clone(room));
+            }
+        }
+
+        rooms
+    }
+
+    /// Get a joined, invited or left room with the given room id.
+    async fn get_room(&self, room_id: &RoomId) -> Option<Arc<RwLock<Room>>> {
+        if let Some(room) = self.get_joined_room(room_id).await {
+            Some(room)
+        } else if let Some(room) = self.get_invited_room(room_id).await {
+            Some(room)
+        } else {
+            self.get_left_room(room_id).await
+        }
+    }
+
+    /// Group the joined rooms under the joined spaces they are part of.
+    ///
+    /// A room is placed under a space if the space lists it in a
+    /// `m.space.child` state event or if the room lists the space in a
+    /// `m.space.parent` state event. Rooms that aren't part of any joined
+    /// space are at the top level of the returned tree.
+    pub async fn joined_space_tree(&self) -> Vec<SpaceTreeNode> {
+        let mut rooms = BTreeMap::new();
+
+        for (room_id, room) in self.joined_rooms.read().await.iter() {
+            rooms.insert(room_id.clone(), room.read().await.space.clone());
+        }
+
+        SpaceTreeNode::build(&rooms)
+    }
+
+    pub(crate) async fn get_or_create_invited_room(&self, room_id: &RoomId) -> Arc<RwLock<Room>> {
+        // A failure to load the stored room isn't fatal, the room state will
+        // be rebuilt from the events.
+        let _ = self.load_unloaded_room(room_id).await;
+
+        // Remove the left rooms only here, since a join -> invite action per
+        // spec can't happen.
+        self.left_rooms.write().await.remove(room_id);
+
+        let mut rooms = self.invited_rooms.write().await;
+        #[allow(clippy::or_fun_call)]
+        rooms
+            .entry(room_id.clone())
+            .or_insert(Arc::new(RwLock::new(Room::new(
+                room_id,
+                &self
+                    .session
+                    .read()
+                    .await
+                    .as_ref()
+                    .expect("Receiving events while not being logged in")
+                    .user_id,
+            ))))
+            .clone()
+    }
+
+    /// Get an invited room with the given room id.
+    ///
+    /// # Arguments
+    ///
+    /// `room_id` - The unique id of the room that should be fetched.
+    pub async fn get_invited_room(&self, room_id: &RoomId) -> Option<Arc<RwLock<Room>>> {
+        self.load_unloaded_room(room_id).await.ok()?;
+        self.invited_rooms.read().await.get(room_id).cloned()
+    }
+
+    /// Returns the invited rooms this client knows about.
+    ///
+    /// A `HashMap` of room id to `matrix::models::Room`
+    pub fn invited_rooms(&self) -> Arc<RwLock<HashMap<RoomId, Arc<RwLock<Room>>>>> {
+        self.invited_rooms.clone()
+    }
 
     pub(crate) async fn get_or_create_left_room(&self, room_id: &RoomId) -> Arc<RwLock<Room>> {
+        // A failure to load the stored room isn't fatal, the room state will
+        // be rebuilt from the events.
+        let _ = self.load_unloaded_room(room_id).await;
+
         // If this used to be an invited or joined room remove them from our other
         // hashmaps.
         self.invited_rooms.write().await.remove(room_id);
@@ -415,6 +2080,7 @@ impl BaseClient {
     ///
     /// `room_id` - The unique id of the room that should be fetched.
     pub async fn get_left_room(&self, room_id: &RoomId) -> Option<Arc<RwLock<Room>>> {
+        self.load_unloaded_room(room_id).await.ok()?;
         self.left_rooms.read().await.get(room_id).cloned()
     }
 
@@ -425,77 +2091,470 @@ impl BaseClient {
         self.left_rooms.clone()
     }
 
-    /// Handle a m.ignored_user_list event, updating the room state if necessary.
+    /// Handle a m.ignored_user_list event, updating the room state if necessary.
+    ///
+    /// Returns true if the room name changed, false otherwise.
+    pub(crate) async fn handle_ignored_users(&self, event: &IgnoredUserListEvent) -> bool {
+        // this avoids cloning every UserId for the eq check
+        if self.ignored_users.read().await.iter().collect::<Vec<_>>()
+            == event.content.ignored_users.iter().collect::<Vec<_>>()
+        {
+            false
+        } else {
+            *self.ignored_users.write().await = event.content.ignored_users.to_vec();
+            true
+        }
+    }
+
+    /// Handle a m.ignored_user_list event, updating the room state if necessary.
+    ///
+    /// Returns true if the room name changed, false otherwise.
+    pub(crate) async fn handle_push_rules(&self, event: &PushRulesEvent) -> bool {
+        // TODO this is basically a stub
+        // TODO ruma removed PartialEq for evens, so this doesn't work anymore.
+        // Returning always true for now should be ok here since those don't
+        // change often.
+        // if self.push_ruleset.as_ref() == Some(&event.content.global) {
+        //     false
+        // } else {
+        self.set_push_ruleset(Some(event.content.global.clone()))
+            .await;
+        true
+        // }
+    }
+
+    /// Receive a timeline event for a joined room and update the client state.
+    ///
+    /// Returns a tuple of the successfully decrypted event, or None on failure and
+    /// a bool, true when the `Room` state has been updated.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The unique id of the room the event belongs to.
+    ///
+    /// * `event` - The event that should be handled by the client.
+    pub async fn receive_joined_timeline_event(
+        &self,
+        room_id: &RoomId,
+        event: &mut EventJson<RoomEvent>,
+    ) -> (Option<EventJson<RoomEvent>>, bool) {
+        let decrypted_event = self
+            .decrypt_timeline_events(room_id, std::slice::from_ref(event))
+            .await
+            .pop()
+            .flatten();
+
+        let (decrypted_event, _, updated) = self
+            .handle_joined_timeline_event(room_id, event, decrypted_event)
+            .await;
+
+        (decrypted_event, updated)
+    }
+
+    /// Decrypt the encrypted events of a room timeline.
+    ///
+    /// The events are split into chunks and every chunk is decrypted by a
+    /// separate task, so the chunks are decrypted in parallel on a
+    /// multi-threaded runtime. If a `WorkerPool` is configured the tasks run
+    /// on the pool. Decryption only needs a read lock of the Olm machine, the
+    /// tasks don't block each other.
+    ///
+    /// Returns the decrypted events in the order of the given events, `None`
+    /// for events that aren't encrypted or that couldn't be decrypted.
+    async fn decrypt_timeline_events(
+        &self,
+        room_id: &RoomId,
+        events: &[EventJson<RoomEvent>],
+    ) -> Vec<Option<EventJson<RoomEvent>>> {
+        #[cfg(feature = "encryption")]
+        {
+            let encrypted = Self::encrypted_events(room_id, events);
+
+            if encrypted.iter().any(Option::is_some) {
+                #[cfg(not(target_arch = "wasm32"))]
+                return Self::join_decryption(self.start_decryption(encrypted).await).await;
+
+                #[cfg(target_arch = "wasm32")]
+                return Self::decrypt_events(&self.olm, &encrypted).await;
+            }
+        }
+
+        #[cfg(not(feature = "encryption"))]
+        let _ = room_id;
+
+        events.iter().map(|_| None).collect()
+    }
+
+    /// Start decrypting the timelines of all the joined rooms of a sync
+    /// response on the `WorkerPool`.
+    ///
+    /// Every chunk of the timeline of a room is decrypted as a separate job,
+    /// the jobs run in parallel while the rooms are handled one after the
+    /// other. Rooms without encrypted events don't get any jobs. Once all the
+    /// slots of the pool are taken, starting the next job waits until a
+    /// running one finished.
+    ///
+    /// Returns `None` if no pool is configured.
+    #[cfg(not(target_arch = "wasm32"))]
+    async fn start_timeline_decryption(
+        &self,
+        response: &api::sync::sync_events::Response,
+    ) -> Option<HashMap<RoomId, Vec<DecryptionJob>>> {
+        if self.worker_pool.is_none() {
+            return None;
+        }
+
+        #[cfg(feature = "encryption")]
+        let jobs = {
+            let mut jobs = HashMap::new();
+
+            for (room_id, joined_room) in &response.rooms.join {
+                let encrypted = Self::encrypted_events(room_id, &joined_room.timeline.events);
+
+                if encrypted.iter().any(Option::is_some) {
+                    jobs.insert(room_id.clone(), self.start_decryption(encrypted).await);
+                }
+            }
+
+            jobs
+        };
+
+        #[cfg(not(feature = "encryption"))]
+        let jobs = {
+            let _ = response;
+            HashMap::new()
+        };
+
+        Some(jobs)
+    }
+
+    /// Get the encrypted events of a room timeline, `None` for events that
+    /// aren't encrypted.
+    #[cfg(feature = "encryption")]
+    fn encrypted_events(
+        room_id: &RoomId,
+        events: &[EventJson<RoomEvent>],
+    ) -> Vec<Option<EncryptedEvent>> {
+        events
+            .iter()
+            .map(|event| match event.deserialize() {
+                Ok(RoomEvent::RoomEncrypted(mut e)) => {
+                    e.room_id = Some(room_id.to_owned());
+                    Some(e)
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Start a task for every `DECRYPTION_CHUNK_SIZE` events that decrypts
+    /// them.
+    ///
+    /// The tasks run on the `WorkerPool` if one is configured, otherwise they
+    /// are spawned on the runtime directly.
+    #[cfg(all(feature = "encryption", not(target_arch = "wasm32")))]
+    async fn start_decryption(&self, events: Vec<Option<EncryptedEvent>>) -> Vec<DecryptionJob> {
+        let mut events = events.into_iter().peekable();
+        let mut jobs = Vec::new();
+
+        while events.peek().is_some() {
+            let chunk: Vec<_> = events.by_ref().take(DECRYPTION_CHUNK_SIZE).collect();
+            let olm = self.olm.clone();
+            let job = async move { Self::decrypt_events(&olm, &chunk).await };
+
+            jobs.push(match &self.worker_pool {
+                Some(pool) => pool.start(job).await,
+                None => WorkerJob::spawn(job),
+            });
+        }
+
+        jobs
+    }
+
+    /// Wait for the decryption tasks of a timeline and put their results
+    /// back together in the order of the events.
+    #[cfg(not(target_arch = "wasm32"))]
+    async fn join_decryption(jobs: Vec<DecryptionJob>) -> Vec<Option<EventJson<RoomEvent>>> {
+        let mut decrypted = Vec::new();
+
+        for job in jobs {
+            decrypted.extend(job.join().await);
+        }
+
+        decrypted
+    }
+
+    /// Decrypt the given encrypted events one after the other while holding
+    /// a read lock on the Olm machine.
+    #[cfg(feature = "encryption")]
+    async fn decrypt_events(
+        olm: &RwLock<Option<OlmMachine>>,
+        events: &[Option<EncryptedEvent>],
+    ) -> Vec<Option<EventJson<RoomEvent>>> {
+        let olm = olm.read().await;
+        let mut decrypted = Vec::with_capacity(events.len());
+
+        for event in events {
+            decrypted.push(match (&*olm, event) {
+                (Some(o), Some(e)) => o.decrypt_room_event(e).await.ok(),
+                _ => None,
+            });
+        }
+
+        decrypted
+    }
+
+    /// Update the client state with a timeline event of a joined room whose
+    /// decryption, if it was encrypted, already happened.
+    ///
+    /// Returns the decrypted event, the parsed event that should be handed
+    /// out, the decrypted one if there is one, and a bool, true when the
+    /// `Room` state has been updated. The parsed event is reference counted
+    /// so the rest of the sync processing and the timeline of the room don't
+    /// need to parse or copy it again.
+    async fn handle_joined_timeline_event(
+        &self,
+        room_id: &RoomId,
+        event: &EventJson<RoomEvent>,
+        decrypted_event: Option<EventJson<RoomEvent>>,
+    ) -> (Option<EventJson<RoomEvent>>, Option<Arc<RoomEvent>>, bool) {
+        match event.deserialize() {
+            Ok(e) => {
+                let room_lock = self.get_or_create_joined_room(&room_id).await;
+                let mut room = room_lock.write().await;
+
+                let json = decrypted_event.as_ref().unwrap_or(event).json();
+                let e = Arc::new(e);
+                let parsed_event = match &decrypted_event {
+                    Some(decrypted) => decrypted.deserialize().ok().map(Arc::new),
+                    None => Some(e.clone()),
+                };
+
+                // The room keeps the event in its timeline, which also makes
+                // sure that the same event isn't applied twice.
+                #[cfg(feature = "messages")]
+                let mut updated = match parsed_event
+                    .clone()
+                    .and_then(|parsed| TimelineEvent::from_raw(json, parsed))
+                {
+                    Some(timeline_event) => room.receive_timeline_item(&e, timeline_event),
+                    None => room.receive_timeline_event(&e),
+                };
+                #[cfg(not(feature = "messages"))]
+                let mut updated = room.receive_timeline_event(&e);
+
+                // Edits and reactions are only visible in the raw JSON of the
+                // event, relations aren't part of the parsed content.
+                if json.get().contains("m.replace") {
+                    if let Ok(json) = serde_json::from_str::<JsonValue>(json.get()) {
+                        updated |= room.receive_edit(&json);
+                    }
+                }
+                if let Some(reaction) = Reaction::from_raw(json) {
+                    updated |= room.receive_reaction(reaction);
+                }
+
+                #[cfg(feature = "local-search")]
+                {
+                    if let Some(RoomEvent::RoomMessage(m)) = parsed_event.as_deref() {
+                        self.search_index.write().await.add_message(room_id, m);
+                    }
+                }
+
+                (decrypted_event, parsed_event, updated)
+            }
+            Err(_) => {
+                let updated = self
+                    .receive_raw_timeline_state_event(room_id, RoomStateType::Joined, event.json())
+                    .await;
+                (None, None, updated)
+            }
+        }
+    }
+
+    /// Receive a page of room history that was fetched using the `/messages`
+    /// endpoint.
+    ///
+    /// The encrypted events of the page are replaced with their decrypted
+    /// version and every event is handed to the `EventEmitter` as an
+    /// `EventOrigin::Backfill` event. The history doesn't change the state of
+    /// the room, only the pagination token of the room is moved backwards if
+    /// the page was fetched backwards.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The unique id of the room the events belong to.
+    ///
+    /// * `response` - The response of the `/messages` request.
+    ///
+    /// * `backwards` - Was the page fetched backwards, from newer to older
+    /// events.
+    pub async fn receive_messages(
+        &self,
+        room_id: &RoomId,
+        response: &mut api::message::get_message_events::Response,
+        backwards: bool,
+    ) -> Result<()> {
+        self.decrypt_events_in_place(room_id, &mut response.chunk)
+            .await;
+
+        let room = if let Some(room) = self.get_joined_room(room_id).await {
+            RoomState::Joined(room)
+        } else if let Some(room) = self.get_left_room(room_id).await {
+            RoomState::Left(room)
+        } else {
+            return Ok(());
+        };
+
+        let events: Vec<(&EventJson<RoomEvent>, Arc<RoomEvent>)> = response
+            .chunk
+            .iter()
+            .filter_map(|e| e.deserialize().ok().map(|parsed| (e, Arc::new(parsed))))
+            .collect();
+
+        if backwards {
+            let updated = match &room {
+                RoomState::Joined(r) | RoomState::Left(r) | RoomState::Invited(r) => {
+                    let mut room = r.write().await;
+
+                    // If the page belongs to a gap of the timeline, the next
+                    // page should be fetched from the gap that is left, if
+                    // any.
+                    #[cfg(feature = "messages")]
+                    let prev_batch = match &response.start {
+                        Some(start) if room.timeline().gaps().contains(&start.as_str()) => {
+                            let events = events
+                                .iter()
+                                .filter_map(|(raw, e)| {
+                                    TimelineEvent::from_raw(raw.json(), e.clone())
+                                })
+                                .collect();
+                            let timeline = room.timeline_mut();
+
+                            timeline.fill_gap(start, events, response.end.as_deref());
+                            timeline.latest_gap().map(str::to_owned)
+                        }
+                        _ => response.end.clone(),
+                    };
+                    #[cfg(not(feature = "messages"))]
+                    let prev_batch = response.end.clone();
+
+                    room.set_prev_batch(prev_batch.as_deref())
+                }
+            };
+
+            if updated {
+                self.store_room_state(room_id).await?;
+            }
+        }
+
+        #[cfg(feature = "local-search")]
+        {
+            let mut index = self.search_index.write().await;
+
+            for (_, e) in &events {
+                if let RoomEvent::RoomMessage(m) = &**e {
+                    index.add_message(room_id, m);
+                }
+            }
+        }
+
+        if let Some(ee) = self.event_emitters.read().await.active() {
+            let room = SyncRoom::with_context(room).await;
+
+            for (_, e) in &events {
+                ee.on_timeline_event(room.clone(), e, EventOrigin::Backfill)
+                    .await;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Receive the events around an event that were fetched using the
+    /// `/context` endpoint.
     ///
-    /// Returns true if the room name changed, false otherwise.
-    pub(crate) async fn handle_ignored_users(&self, event: &IgnoredUserListEvent) -> bool {
-        // this avoids cloning every UserId for the eq check
-        if self.ignored_users.read().await.iter().collect::<Vec<_>>()
-            == event.content.ignored_users.iter().collect::<Vec<_>>()
-        {
-            false
-        } else {
-            *self.ignored_users.write().await = event.content.ignored_users.to_vec();
-            true
+    /// The encrypted events of the response are replaced with their decrypted
+    /// version, the state of the room isn't changed.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The unique id of the room the events belong to.
+    ///
+    /// * `response` - The response of the `/context` request.
+    pub async fn receive_context(
+        &self,
+        room_id: &RoomId,
+        response: &mut api::context::get_context::Response,
+    ) {
+        self.decrypt_events_in_place(room_id, &mut response.events_before)
+            .await;
+        self.decrypt_events_in_place(room_id, &mut response.events_after)
+            .await;
+
+        if let Some(event) = response.event.as_mut() {
+            self.decrypt_events_in_place(room_id, std::slice::from_mut(event))
+                .await;
         }
     }
 
-    /// Handle a m.ignored_user_list event, updating the room state if necessary.
+    /// Search the messages that were received so far, including the ones of
+    /// encrypted rooms.
     ///
-    /// Returns true if the room name changed, false otherwise.
-    pub(crate) async fn handle_push_rules(&self, event: &PushRulesEvent) -> bool {
-        // TODO this is basically a stub
-        // TODO ruma removed PartialEq for evens, so this doesn't work anymore.
-        // Returning always true for now should be ok here since those don't
-        // change often.
-        // if self.push_ruleset.as_ref() == Some(&event.content.global) {
-        //     false
-        // } else {
-        *self.push_ruleset.write().await = Some(event.content.global.clone());
-        true
-        // }
+    /// See `SearchIndex::search()` for how the messages are matched and
+    /// ranked.
+    ///
+    /// # Arguments
+    ///
+    /// * `term` - The words to search for.
+    ///
+    /// * `room_id` - Only search the messages of this room if set.
+    #[cfg(feature = "local-search")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "local-search")))]
+    pub async fn search_local(
+        &self,
+        term: &str,
+        room_id: Option<&RoomId>,
+    ) -> Vec<LocalSearchResult> {
+        self.search_index.read().await.search(term, room_id)
     }
 
-    /// Receive a timeline event for a joined room and update the client state.
+    /// Receive the results of a server side search.
     ///
-    /// Returns a tuple of the successfully decrypted event, or None on failure and
-    /// a bool, true when the `Room` state has been updated.
+    /// The encrypted events of the results and their context are replaced
+    /// with their decrypted version.
     ///
     /// # Arguments
     ///
-    /// * `room_id` - The unique id of the room the event belongs to.
-    ///
-    /// * `event` - The event that should be handled by the client.
-    pub async fn receive_joined_timeline_event(
-        &self,
-        room_id: &RoomId,
-        event: &mut EventJson<RoomEvent>,
-    ) -> (Option<EventJson<RoomEvent>>, bool) {
-        match event.deserialize() {
-            #[allow(unused_mut)]
-            Ok(mut e) => {
-                #[cfg(feature = "encryption")]
-                let mut decrypted_event = None;
-                #[cfg(not(feature = "encryption"))]
-                let decrypted_event = None;
-
-                #[cfg(feature = "encryption")]
-                {
-                    if let RoomEvent::RoomEncrypted(ref mut e) = e {
-                        e.room_id = Some(room_id.to_owned());
-                        let mut olm = self.olm.lock().await;
+    /// * `results` - The results of the `/search` request.
+    pub async fn receive_search_results(&self, results: &mut SearchResults) {
+        for result in &mut results.results {
+            let room_id = match result.room_id() {
+                Some(id) => id,
+                None => continue,
+            };
 
-                        if let Some(o) = &mut *olm {
-                            decrypted_event = o.decrypt_room_event(&e).await.ok();
-                        }
-                    }
-                }
+            self.decrypt_events_in_place(&room_id, std::slice::from_mut(&mut result.result))
+                .await;
 
-                let room_lock = self.get_or_create_joined_room(&room_id).await;
-                let mut room = room_lock.write().await;
-                (decrypted_event, room.receive_timeline_event(&e))
+            if let Some(context) = result.context.as_mut() {
+                self.decrypt_events_in_place(&room_id, &mut context.events_before)
+                    .await;
+                self.decrypt_events_in_place(&room_id, &mut context.events_after)
+                    .await;
+            }
+        }
+    }
+
+    /// Replace the encrypted events of a room timeline with their decrypted
+    /// version.
+    async fn decrypt_events_in_place(&self, room_id: &RoomId, events: &mut [EventJson<RoomEvent>]) {
+        let decrypted_events = self.decrypt_timeline_events(room_id, events).await;
+
+        for (event, decrypted_event) in events.iter_mut().zip(decrypted_events) {
+            if let Some(e) = decrypted_event {
+                *event = e;
             }
-            _ => (None, false),
         }
     }
 
@@ -556,7 +2615,10 @@ impl BaseClient {
                 let mut room = room_lock.write().await;
                 room.receive_timeline_event(&e)
             }
-            _ => false,
+            Err(_) => {
+                self.receive_raw_timeline_state_event(room_id, RoomStateType::Left, event.json())
+                    .await
+            }
         }
     }
 
@@ -576,6 +2638,189 @@ impl BaseClient {
         room.receive_state_event(event)
     }
 
+    /// Receive a state event that couldn't be deserialized into one of the
+    /// known event types and update the client state.
+    ///
+    /// This handles state events that use a newer version of the spec than
+    /// the event types know about, e.g. restricted join rules.
+    ///
+    /// Returns true if the state of the room changed, false
+    /// otherwise.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The unique id of the room the event belongs to.
+    ///
+    /// * `room_state` - The state of the room the event belongs to.
+    ///
+    /// * `event` - The raw JSON of the event.
+    pub async fn receive_raw_state_event(
+        &self,
+        room_id: &RoomId,
+        room_state: RoomStateType,
+        event: &RawJsonValue,
+    ) -> bool {
+        let event: JsonValue = match serde_json::from_str(event.get()) {
+            Ok(e) => e,
+            Err(_) => return false,
+        };
+
+        let room_lock = match room_state {
+            RoomStateType::Joined => self.get_or_create_joined_room(room_id).await,
+            RoomStateType::Left => self.get_or_create_left_room(room_id).await,
+            RoomStateType::Invited => self.get_or_create_invited_room(room_id).await,
+        };
+
+        let mut room = room_lock.write().await;
+        room.receive_raw_state_event(&event)
+    }
+
+    /// Receive a timeline event that couldn't be deserialized and update the
+    /// client state if it is a state event.
+    ///
+    /// Unlike `receive_raw_state_event()` this only updates rooms we already
+    /// know about, events without a `state_key` are ignored.
+    ///
+    /// Returns true if the state of the room changed, false
+    /// otherwise.
+    async fn receive_raw_timeline_state_event(
+        &self,
+        room_id: &RoomId,
+        room_state: RoomStateType,
+        event: &RawJsonValue,
+    ) -> bool {
+        let event: JsonValue = match serde_json::from_str(event.get()) {
+            Ok(e) => e,
+            Err(_) => return false,
+        };
+
+        if !event.get("state_key").map_or(false, JsonValue::is_string) {
+            return false;
+        }
+
+        let room_lock = match room_state {
+            RoomStateType::Joined => self.get_joined_room(room_id).await,
+            RoomStateType::Left => self.get_left_room(room_id).await,
+            RoomStateType::Invited => self.get_invited_room(room_id).await,
+        };
+
+        match room_lock {
+            Some(room) => room.write().await.receive_raw_state_event(&event),
+            None => false,
+        }
+    }
+
+    /// Get the last known presence of a user.
+    ///
+    /// Returns `None` if we didn't receive a presence update for the user
+    /// yet.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - The id of the user.
+    pub async fn presence(&self, user_id: &UserId) -> Option<PresenceEventContent> {
+        self.presence.read().await.get(user_id).cloned()
+    }
+
+    /// Update the last known presence of a user.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - The id of the user.
+    ///
+    /// * `presence` - The new presence of the user.
+    pub async fn receive_user_presence(&self, user_id: &UserId, presence: PresenceEventContent) {
+        self.presence
+            .write()
+            .await
+            .insert(user_id.clone(), presence);
+    }
+
+    /// Get the actions the push rules of our user request for a timeline
+    /// event of a joined room.
+    ///
+    /// The rules are evaluated locally, see `PushActions::for_event()`. While
+    /// syncing, the `EventEmitter` receives an `on_notification()` call for
+    /// every event that should notify.
+    ///
+    /// Returns `None` if we didn't receive the push rules yet or if the room
+    /// isn't joined.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The id of the room the event belongs to.
+    ///
+    /// * `event` - The event that should be checked.
+    pub async fn push_actions_for_event(
+        &self,
+        room_id: &RoomId,
+        event: &EventJson<RoomEvent>,
+    ) -> Option<PushActions> {
+        let rules = self.push_rules.read().await;
+        let rules = rules.as_ref()?;
+        let room = self.get_joined_room(room_id).await?;
+        let event = serde_json::from_str::<JsonValue>(event.json().get()).ok()?;
+
+        let room = room.read().await;
+        Some(PushActions::for_event_with_rules(rules, &room, &event))
+    }
+
+    /// Replace the push ruleset and the JSON the push rules are evaluated on.
+    async fn set_push_ruleset(&self, ruleset: Option<Ruleset>) {
+        let mut push_ruleset = self.push_ruleset.write().await;
+
+        *self.push_rules.write().await = ruleset
+            .as_ref()
+            .and_then(|ruleset| serde_json::to_value(ruleset).ok());
+        *push_ruleset = ruleset;
+    }
+
+    /// Get the keywords that notify our user, the patterns of the content
+    /// push rules the user added.
+    ///
+    /// Returns an empty list if we didn't receive the push rules yet.
+    pub async fn keywords(&self) -> Vec<String> {
+        self.push_ruleset
+            .read()
+            .await
+            .as_ref()
+            .map(keywords)
+            .unwrap_or_default()
+    }
+
+    /// Add, replace or remove the push rule of a keyword in the local push
+    /// rules.
+    ///
+    /// This is used to update the rules right after they were changed on the
+    /// server, so `push_actions_for_event()` picks the change up before the
+    /// push rules come down the next sync.
+    ///
+    /// Returns false if we didn't receive the push rules yet, true otherwise.
+    ///
+    /// # Arguments
+    ///
+    /// * `keyword` - The keyword the rule matches.
+    ///
+    /// * `actions` - The actions of the rule, the rule is removed if `None`.
+    pub async fn receive_keyword_rule(
+        &self,
+        keyword: &str,
+        actions: Option<&PushActions>,
+    ) -> Result<bool> {
+        let ruleset = match self.push_ruleset.read().await.as_ref() {
+            Some(rules) => set_keyword_rule(rules, keyword, actions)?,
+            None => return Ok(false),
+        };
+        self.set_push_ruleset(Some(ruleset)).await;
+
+        if let Some(store) = self.state_store.read().await.as_ref() {
+            let state = ClientState::from_base_client(&self).await;
+            store.store_client_state(state).await?;
+        }
+
+        Ok(true)
+    }
+
     /// Receive a presence event from a sync response and updates the client state.
     ///
     /// Returns true if the state of the room changed, false
@@ -596,6 +2841,19 @@ impl BaseClient {
         }
     }
 
+    /// Receive a presence event that is shared between the joined rooms of a
+    /// sync response.
+    async fn receive_shared_presence_event(
+        &self,
+        room_id: &RoomId,
+        event: &Arc<PresenceEvent>,
+    ) -> bool {
+        match self.get_joined_room(room_id).await {
+            Some(room) => room.write().await.receive_shared_presence_event(event),
+            None => false,
+        }
+    }
+
     /// Receive an account data event from a sync response and updates the client state.
     ///
     /// Returns true if the state of the `Room` has changed, false otherwise.
@@ -610,6 +2868,14 @@ impl BaseClient {
             NonRoomEvent::IgnoredUserList(iu) => self.handle_ignored_users(iu).await,
             NonRoomEvent::Presence(p) => self.receive_presence_event(room_id, p).await,
             NonRoomEvent::PushRules(pr) => self.handle_push_rules(pr).await,
+            NonRoomEvent::FullyRead(fr) => match self.get_joined_room(room_id).await {
+                Some(room) => room.write().await.receive_fully_read(fr),
+                None => false,
+            },
+            NonRoomEvent::Tag(tags) => match self.get_joined_room(room_id).await {
+                Some(room) => room.write().await.receive_tags(tags),
+                None => false,
+            },
             _ => false,
         }
     }
@@ -628,6 +2894,14 @@ impl BaseClient {
             NonRoomEvent::IgnoredUserList(iu) => self.handle_ignored_users(iu).await,
             NonRoomEvent::Presence(p) => self.receive_presence_event(room_id, p).await,
             NonRoomEvent::PushRules(pr) => self.handle_push_rules(pr).await,
+            NonRoomEvent::Receipt(receipt) => match self.get_joined_room(room_id).await {
+                Some(room) => room.write().await.receive_receipt(receipt),
+                None => false,
+            },
+            NonRoomEvent::Typing(typing) => match self.get_joined_room(room_id).await {
+                Some(room) => room.write().await.receive_typing(typing),
+                None => false,
+            },
             _ => false,
         }
     }
@@ -638,6 +2912,28 @@ impl BaseClient {
         self.sync_token.read().await.clone()
     }
 
+    /// Take a snapshot of the state of a joined, invited or left room that
+    /// carries the current sync token.
+    ///
+    /// Returns `None` if the room isn't known.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The unique id of the room.
+    pub async fn room_state_snapshot(&self, room_id: &RoomId) -> Option<RoomStateSnapshot> {
+        let room = match self.get_joined_room(room_id).await {
+            Some(room) => room,
+            None => match self.get_invited_room(room_id).await {
+                Some(room) => room,
+                None => self.get_left_room(room_id).await?,
+            },
+        };
+
+        let mut snapshot = room.read().await.state_snapshot();
+        snapshot.set_sync_token(self.sync_token().await);
+        Some(snapshot)
+    }
+
     /// Receive a response from a sync call.
     ///
     /// # Arguments
@@ -660,9 +2956,9 @@ impl BaseClient {
 
         #[cfg(feature = "encryption")]
         {
-            let mut olm = self.olm.lock().await;
+            let olm = self.olm.read().await;
 
-            if let Some(o) = &mut *olm {
+            if let Some(o) = &*olm {
                 // Let the crypto machine handle the sync response, this
                 // decryptes to-device events, but leaves room events alone.
                 // This makes sure that we have the deryption keys for the room
@@ -671,25 +2967,239 @@ impl BaseClient {
             }
         }
 
-        // TODO do we want to move the rooms to the appropriate HashMaps when the corresponding
-        // event comes in e.g. move a joined room to a left room when leave event comes?
-
-        // when events change state, updated_* signals to StateStore to update database
-        self.iter_joined_rooms(response).await?;
-        self.iter_invited_rooms(&response).await?;
-        self.iter_left_rooms(response).await?;
-
-        let store = self.state_store.read().await;
+        // TODO do we want to move the rooms to the appropriate HashMaps when the corresponding
+        // event comes in e.g. move a joined room to a left room when leave event comes?
+
+        if let Some(unhandled) = &self.unhandled_events {
+            Self::count_unhandled_events(&mut *unhandled.write().await, response);
+        }
+
+        for event in &response.presence.events {
+            if let Ok(e) = event.deserialize() {
+                self.receive_user_presence(&e.sender, e.content).await;
+            }
+        }
+
+        // when events change state, updated_* signals to StateStore to update database
+        self.iter_joined_rooms(response).await?;
+        self.iter_invited_rooms(&response).await?;
+        self.iter_left_rooms(response).await?;
+        self.iter_account_data(response).await?;
+
+        let joined: Vec<RoomId> = response.rooms.join.keys().cloned().collect();
+        self.update_room_aliases(&joined).await?;
+
+        let store = self.state_store.read().await;
+
+        // Store now the new sync token an other client specific state. Since we
+        // know the sync token changed we can assume that this needs to be done
+        // always.
+        if let Some(store) = store.as_ref() {
+            let state = ClientState::from_base_client(&self).await;
+            store.store_client_state(state).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Receive the events of an appservice transaction.
+    ///
+    /// Appservices don't sync, the homeserver pushes the events of the rooms
+    /// the appservice is interested in as transactions instead. The events of
+    /// a transaction are routed to the room they belong to, a membership
+    /// change of our own user moves the room between the joined, invited and
+    /// left rooms. Events of rooms we have no membership info about are
+    /// treated as events of joined rooms.
+    ///
+    /// # Arguments
+    ///
+    /// * `events` - The events of the transaction, these will be replaced
+    /// with their decrypted version if they can be decrypted.
+    pub async fn receive_transaction(&self, events: &mut [EventJson<RoomEvent>]) -> Result<()> {
+        let mut updated_rooms: Vec<RoomId> = Vec::new();
+
+        for event in events.iter_mut() {
+            let room_id = match serde_json::from_str::<EventRoomId>(event.json().get()) {
+                Ok(EventRoomId {
+                    room_id: Some(room_id),
+                }) => room_id,
+                _ => continue,
+            };
+
+            let deserialized = event.deserialize();
+
+            let room_state = match &deserialized {
+                Ok(e) => self.transaction_room_state(&room_id, e).await,
+                Err(_) => self.known_room_state(&room_id).await,
+            };
+
+            let updated = match room_state {
+                RoomStateType::Joined => {
+                    let (decrypted_event, updated) =
+                        self.receive_joined_timeline_event(&room_id, event).await;
+
+                    if let Some(e) = decrypted_event {
+                        *event = e;
+                    }
+
+                    updated
+                }
+                RoomStateType::Left => self.receive_left_timeline_event(&room_id, event).await,
+                RoomStateType::Invited => match &deserialized {
+                    Ok(e) => {
+                        let room = self.get_or_create_invited_room(&room_id).await;
+                        let mut room = room.write().await;
+                        room.receive_timeline_event(e)
+                    }
+                    Err(_) => {
+                        self.receive_raw_state_event(&room_id, room_state, event.json())
+                            .await
+                    }
+                },
+            };
+
+            if let Ok(e) = event.deserialize() {
+                self.emit_timeline_event(&room_id, &e, event.json(), room_state)
+                    .await;
+            }
+
+            if updated && !updated_rooms.contains(&room_id) {
+                updated_rooms.push(room_id);
+            }
+        }
+
+        for room_id in updated_rooms {
+            self.store_room_state(&room_id).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Find out to which of our room maps a transaction event should be
+    /// routed.
+    ///
+    /// Membership events of our own user decide the state of the room, every
+    /// other event goes to the room we already know about.
+    async fn transaction_room_state(&self, room_id: &RoomId, event: &RoomEvent) -> RoomStateType {
+        if let RoomEvent::RoomMember(member) = event {
+            let own_user = self
+                .session
+                .read()
+                .await
+                .as_ref()
+                .map(|s| s.user_id.to_string());
+
+            if own_user.as_deref() == Some(member.state_key.as_str()) {
+                return match member.content.membership {
+                    MembershipState::Join => RoomStateType::Joined,
+                    MembershipState::Invite => RoomStateType::Invited,
+                    MembershipState::Leave | MembershipState::Ban => RoomStateType::Left,
+                    _ => self.known_room_state(room_id).await,
+                };
+            }
+        }
+
+        self.known_room_state(room_id).await
+    }
+
+    /// The state of the room we know about, rooms that we don't know are
+    /// considered to be joined.
+    async fn known_room_state(&self, room_id: &RoomId) -> RoomStateType {
+        if self.joined_rooms.read().await.contains_key(room_id) {
+            RoomStateType::Joined
+        } else if self.invited_rooms.read().await.contains_key(room_id) {
+            RoomStateType::Invited
+        } else if self.left_rooms.read().await.contains_key(room_id) {
+            RoomStateType::Left
+        } else if let Some(room_state) = self.unloaded_rooms.read().await.get(room_id) {
+            *room_state
+        } else {
+            RoomStateType::Joined
+        }
+    }
+
+    /// Get the event types that were received but couldn't be handled during
+    /// this session, together with the number of times they were received.
+    ///
+    /// Returns `None` if tracking unhandled events isn't enabled in the
+    /// `BaseClientConfig`.
+    pub async fn unhandled_event_types(&self) -> Option<HashMap<String, u64>> {
+        match &self.unhandled_events {
+            Some(unhandled) => Some(unhandled.read().await.clone()),
+            None => None,
+        }
+    }
+
+    /// Should the given ephemeral event be skipped without deserializing it.
+    fn is_ignored_ephemeral_event(&self, json: &RawJsonValue) -> bool {
+        if !self.ignore_typing_events && !self.ignore_receipt_events {
+            return false;
+        }
+
+        match serde_json::from_str::<EventTypeJson>(json.get()) {
+            Ok(e) => match EventType::from(e.event_type.as_str()) {
+                EventType::Typing => self.ignore_typing_events,
+                EventType::Receipt => self.ignore_receipt_events,
+                _ => false,
+            },
+            Err(_) => false,
+        }
+    }
+
+    /// Count the events of a sync response whose type is unknown or that
+    /// failed to deserialize.
+    fn count_unhandled_events(
+        counts: &mut HashMap<String, u64>,
+        response: &api::sync::sync_events::Response,
+    ) {
+        let mut count = |json: &RawJsonValue, deserialized: bool| {
+            let event_type = match serde_json::from_str::<EventTypeJson>(json.get()) {
+                Ok(e) => e.event_type,
+                Err(_) => return,
+            };
+
+            let known = !matches!(EventType::from(event_type.as_str()), EventType::Custom(_));
+
+            if !known || !deserialized {
+                *counts.entry(event_type).or_insert(0) += 1;
+            }
+        };
+
+        for joined_room in response.rooms.join.values() {
+            for e in &joined_room.state.events {
+                count(e.json(), e.deserialize().is_ok());
+            }
+            for e in &joined_room.timeline.events {
+                count(e.json(), e.deserialize().is_ok());
+            }
+            for e in &joined_room.ephemeral.events {
+                count(e.json(), e.deserialize().is_ok());
+            }
+            if let Some(account_data) = &joined_room.account_data {
+                for e in &account_data.events {
+                    count(e.json(), e.deserialize().is_ok());
+                }
+            }
+        }
+
+        for left_room in response.rooms.leave.values() {
+            for e in &left_room.state.events {
+                count(e.json(), e.deserialize().is_ok());
+            }
+            for e in &left_room.timeline.events {
+                count(e.json(), e.deserialize().is_ok());
+            }
+        }
 
-        // Store now the new sync token an other client specific state. Since we
-        // know the sync token changed we can assume that this needs to be done
-        // always.
-        if let Some(store) = store.as_ref() {
-            let state = ClientState::from_base_client(&self).await;
-            store.store_client_state(state).await?;
+        for invited_room in response.rooms.invite.values() {
+            for e in &invited_room.invite_state.events {
+                count(e.json(), e.deserialize().is_ok());
+            }
         }
 
-        Ok(())
+        for e in &response.presence.events {
+            count(e.json(), e.deserialize().is_ok());
+        }
     }
 
     async fn iter_joined_rooms(
@@ -697,13 +3207,40 @@ impl BaseClient {
         response: &mut api::sync::sync_events::Response,
     ) -> Result<bool> {
         let mut updated = false;
+
+        // The presence events are handed to every joined room, parse them
+        // only once and share them between the rooms.
+        let presence_events: Vec<Arc<PresenceEvent>> = response
+            .presence
+            .events
+            .iter()
+            .filter_map(|e| e.deserialize().ok())
+            .map(Arc::new)
+            .collect();
+
+        // With a worker pool the timelines of all the rooms are decrypted in
+        // parallel, the decryptions are started, as far as the pool has free
+        // slots, before the first room is handled.
+        #[cfg(not(target_arch = "wasm32"))]
+        let mut decryptions = self.start_timeline_decryption(response).await;
+
         for (room_id, joined_room) in &mut response.rooms.join {
             let matrix_room = {
                 for event in &joined_room.state.events {
-                    if let Ok(e) = event.deserialize() {
-                        if self.receive_joined_state_event(&room_id, &e).await {
-                            updated = true;
+                    let state_updated = match event.deserialize() {
+                        Ok(e) => self.receive_joined_state_event(&room_id, &e).await,
+                        Err(_) => {
+                            self.receive_raw_state_event(
+                                &room_id,
+                                RoomStateType::Joined,
+                                event.json(),
+                            )
+                            .await
                         }
+                    };
+
+                    if state_updated {
+                        updated = true;
                     }
                 }
 
@@ -712,13 +3249,11 @@ impl BaseClient {
 
             #[cfg(feature = "encryption")]
             {
-                let mut olm = self.olm.lock().await;
-
-                if let Some(o) = &mut *olm {
-                    let room = matrix_room.read().await;
+                let room = matrix_room.read().await;
 
-                    // If the room is encrypted, update the tracked users.
-                    if room.is_encrypted() {
+                // If the room is encrypted, update the tracked users.
+                if room.is_encrypted() {
+                    if let Some(o) = &*self.olm.read().await {
                         o.update_tracked_users(room.members.keys()).await;
                     }
                 }
@@ -736,38 +3271,141 @@ impl BaseClient {
                 .await
                 .set_unread_notice_count(&joined_room.unread_notifications);
 
-            // re looping is not ideal here
-            for event in &mut joined_room.state.events {
-                if let Ok(e) = event.deserialize() {
-                    self.emit_state_event(&room_id, &e, RoomStateType::Joined)
-                        .await;
+            // Remember where the history before this timeline starts, a
+            // limited timeline leaves a gap to the events we already know
+            // about, so the older token would skip events.
+            if let Some(prev_batch) = &joined_room.timeline.prev_batch {
+                let mut room = matrix_room.write().await;
+
+                if (room.prev_batch().is_none() || joined_room.timeline.limited == Some(true))
+                    && room.set_prev_batch(Some(prev_batch))
+                {
+                    updated = true;
+                }
+            }
+
+            // The timeline of the room keeps track of the events that a
+            // limited timeline skipped, they can be fetched later on.
+            #[cfg(feature = "messages")]
+            {
+                if let Some(prev_batch) = &joined_room.timeline.prev_batch {
+                    let mut room = matrix_room.write().await;
+
+                    if room.timeline().is_empty() || joined_room.timeline.limited == Some(true) {
+                        room.timeline_mut().push_gap(prev_batch);
+                    }
                 }
             }
 
-            for mut event in &mut joined_room.timeline.events {
-                let decrypted_event = {
-                    let (decrypt_ev, timeline_update) = self
-                        .receive_joined_timeline_event(room_id, &mut event)
+            // re looping is not ideal here
+            self.emit_state_events(&room_id, &joined_room.state.events, RoomStateType::Joined)
+                .await;
+
+            // Decrypt all the events of the timeline up front, so they don't
+            // wait on each other.
+            #[cfg(not(target_arch = "wasm32"))]
+            let decrypted_events = match decryptions.as_mut() {
+                Some(jobs) => match jobs.remove(room_id) {
+                    Some(jobs) => Self::join_decryption(jobs).await,
+                    None => joined_room.timeline.events.iter().map(|_| None).collect(),
+                },
+                None => {
+                    self.decrypt_timeline_events(room_id, &joined_room.timeline.events)
+                        .await
+                }
+            };
+            #[cfg(target_arch = "wasm32")]
+            let decrypted_events = self
+                .decrypt_timeline_events(room_id, &joined_room.timeline.events)
+                .await;
+
+            let mut dropped = Vec::new();
+
+            for (i, (event, decrypted_event)) in joined_room
+                .timeline
+                .events
+                .iter_mut()
+                .zip(decrypted_events)
+                .enumerate()
+            {
+                let verdict = self
+                    .check_spam(room_id, decrypted_event.as_ref().unwrap_or(event))
+                    .await;
+
+                if verdict == Some(SpamVerdict::Drop) {
+                    dropped.push(i);
+                    continue;
+                }
+
+                let (decrypted_event, parsed_event) = {
+                    let (decrypt_ev, parsed_ev, timeline_update) = self
+                        .handle_joined_timeline_event(room_id, event, decrypted_event)
                         .await;
                     if timeline_update {
                         updated = true;
                     };
-                    decrypt_ev
+                    (decrypt_ev, parsed_ev)
                 };
 
                 if let Some(e) = decrypted_event {
                     *event = e;
                 }
 
-                if let Ok(e) = event.deserialize() {
-                    self.emit_timeline_event(&room_id, &e, RoomStateType::Joined)
+                if let Some(e) = parsed_event {
+                    if let (Some(verdict), RoomEvent::RoomMessage(m)) = (&verdict, &*e) {
+                        self.emit_message_verdict(&room_id, m, verdict).await;
+                    }
+
+                    self.emit_timeline_event(&room_id, &e, event.json(), RoomStateType::Joined)
                         .await;
+
+                    if let Some(actions) = self.push_actions_for_event(&room_id, event).await {
+                        if actions.notify {
+                            self.emit_notification(&room_id, &e, &actions).await;
+                        }
+                    }
+                }
+
+                if let Some(reaction) = Reaction::from_raw(event.json()) {
+                    self.emit_reaction(&room_id, &reaction).await;
+                }
+            }
+
+            // Spam doesn't reach the users of the sync response either.
+            if !dropped.is_empty() {
+                let mut i = 0;
+                joined_room.timeline.events.retain(|_| {
+                    i += 1;
+                    !dropped.contains(&(i - 1))
+                });
+            }
+
+            #[cfg(feature = "messages")]
+            {
+                if matrix_room
+                    .write()
+                    .await
+                    .apply_retention_policy(&self.retention_policy)
+                {
+                    updated = true;
                 }
             }
 
             // look at AccountData to further cut down users by collecting ignored users
             if let Some(account_data) = &joined_room.account_data {
                 for account_data in &account_data.events {
+                    if let Ok(raw) =
+                        serde_json::from_str::<AccountDataJson>(account_data.json().get())
+                    {
+                        if matrix_room
+                            .write()
+                            .await
+                            .receive_account_data(&raw.event_type, raw.content)
+                        {
+                            updated = true;
+                        }
+                    }
+
                     {
                         if let Ok(e) = account_data.deserialize() {
                             if self.receive_account_data_event(&room_id, &e).await {
@@ -783,20 +3421,20 @@ impl BaseClient {
             // After the room has been created and state/timeline events accounted for we use the room_id of the newly created
             // room to add any presence events that relate to a user in the current room. This is not super
             // efficient but we need a room_id so we would loop through now or later.
-            for presence in &mut response.presence.events {
-                {
-                    if let Ok(e) = presence.deserialize() {
-                        if self.receive_presence_event(&room_id, &e).await {
-                            updated = true;
-                        }
-
-                        self.emit_presence_event(&room_id, &e, RoomStateType::Joined)
-                            .await;
-                    }
+            for e in &presence_events {
+                if self.receive_shared_presence_event(&room_id, e).await {
+                    updated = true;
                 }
+
+                self.emit_presence_event(&room_id, e, RoomStateType::Joined)
+                    .await;
             }
 
             for ephemeral in &mut joined_room.ephemeral.events {
+                if self.is_ignored_ephemeral_event(ephemeral.json()) {
+                    continue;
+                }
+
                 {
                     if let Ok(e) = ephemeral.deserialize() {
                         if self.receive_ephemeral_event(&room_id, &e).await {
@@ -805,6 +3443,12 @@ impl BaseClient {
 
                         self.emit_ephemeral_event(&room_id, &e, RoomStateType::Joined)
                             .await;
+
+                        if let NonRoomEvent::Receipt(receipt) = &e {
+                            for receipt in ReadReceipt::from_event(receipt) {
+                                self.emit_receipt(&room_id, &receipt).await;
+                            }
+                        }
                     }
                 }
             }
@@ -828,22 +3472,28 @@ impl BaseClient {
         for (room_id, left_room) in &mut response.rooms.leave {
             let matrix_room = {
                 for event in &left_room.state.events {
-                    if let Ok(e) = event.deserialize() {
-                        if self.receive_left_state_event(&room_id, &e).await {
-                            updated = true;
+                    let state_updated = match event.deserialize() {
+                        Ok(e) => self.receive_left_state_event(&room_id, &e).await,
+                        Err(_) => {
+                            self.receive_raw_state_event(
+                                &room_id,
+                                RoomStateType::Left,
+                                event.json(),
+                            )
+                            .await
                         }
+                    };
+
+                    if state_updated {
+                        updated = true;
                     }
                 }
 
                 self.get_or_create_left_room(&room_id).await.clone()
             };
 
-            for event in &mut left_room.state.events {
-                if let Ok(e) = event.deserialize() {
-                    self.emit_state_event(&room_id, &e, RoomStateType::Left)
-                        .await;
-                }
-            }
+            self.emit_state_events(&room_id, &left_room.state.events, RoomStateType::Left)
+                .await;
 
             for event in &mut left_room.timeline.events {
                 if self.receive_left_timeline_event(room_id, &event).await {
@@ -851,11 +3501,22 @@ impl BaseClient {
                 };
 
                 if let Ok(e) = event.deserialize() {
-                    self.emit_timeline_event(&room_id, &e, RoomStateType::Left)
+                    self.emit_timeline_event(&room_id, &e, event.json(), RoomStateType::Left)
                         .await;
                 }
             }
 
+            #[cfg(feature = "messages")]
+            {
+                if matrix_room
+                    .write()
+                    .await
+                    .apply_retention_policy(&self.retention_policy)
+                {
+                    updated = true;
+                }
+            }
+
             if updated {
                 if let Some(store) = self.state_store.read().await.as_ref() {
                     store
@@ -875,10 +3536,31 @@ impl BaseClient {
         for (room_id, invited_room) in &response.rooms.invite {
             let matrix_room = {
                 for event in &invited_room.invite_state.events {
-                    if let Ok(e) = event.deserialize() {
-                        if self.receive_invite_state_event(&room_id, &e).await {
-                            updated = true;
+                    let state_updated = match event.deserialize() {
+                        Ok(e) => {
+                            let state_updated = self.receive_invite_state_event(&room_id, &e).await;
+                            let timestamp_updated = match serde_json::from_str(event.json().get()) {
+                                Ok(raw) => {
+                                    let room_lock = self.get_or_create_invited_room(&room_id).await;
+                                    let mut room = room_lock.write().await;
+                                    room.receive_raw_invite_event(&raw)
+                                }
+                                Err(_) => false,
+                            };
+                            state_updated || timestamp_updated
+                        }
+                        Err(_) => {
+                            self.receive_raw_state_event(
+                                &room_id,
+                                RoomStateType::Invited,
+                                event.json(),
+                            )
+                            .await
                         }
+                    };
+
+                    if state_updated {
+                        updated = true;
                     }
                 }
 
@@ -907,7 +3589,7 @@ impl BaseClient {
     #[cfg(feature = "encryption")]
     #[cfg_attr(docsrs, doc(cfg(feature = "encryption")))]
     pub async fn should_upload_keys(&self) -> bool {
-        let olm = self.olm.lock().await;
+        let olm = self.olm.read().await;
 
         match &*olm {
             Some(o) => o.should_upload_keys().await,
@@ -926,22 +3608,76 @@ impl BaseClient {
     #[cfg(feature = "encryption")]
     #[cfg_attr(docsrs, doc(cfg(feature = "encryption")))]
     pub async fn should_share_group_session(&self, room_id: &RoomId) -> bool {
-        let olm = self.olm.lock().await;
+        let olm = self.olm.read().await;
 
         match &*olm {
-            Some(o) => o.should_share_group_session(room_id),
+            Some(o) => o.should_share_group_session(room_id).await,
             None => false,
         }
     }
 
+    /// Get the inbound group sessions we know for the given room, e.g. to
+    /// show why messages of the room can't be decrypted.
+    ///
+    /// Returns an empty list if the client isn't logged in.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The room id of the room the sessions belong to.
+    #[cfg(feature = "encryption")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "encryption")))]
+    pub async fn inbound_group_sessions(&self, room_id: &RoomId) -> Result<Vec<GroupSessionInfo>> {
+        let olm = self.olm.read().await;
+
+        match &*olm {
+            Some(o) => Ok(o.inbound_group_sessions(room_id).await?),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Export the inbound group sessions we know for the given room in the
+    /// key export format.
+    ///
+    /// Returns an empty list if the client isn't logged in.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The room id of the room the sessions belong to.
+    #[cfg(feature = "encryption")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "encryption")))]
+    pub async fn export_room_keys(&self, room_id: &RoomId) -> Result<Vec<ExportedRoomKey>> {
+        let olm = self.olm.read().await;
+
+        match &*olm {
+            Some(o) => Ok(o.export_room_keys(room_id).await?),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Mark the device list of a user as changed, so it's queried again with
+    /// the next key query.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - The user whose device list changed.
+    #[cfg(feature = "encryption")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "encryption")))]
+    pub async fn mark_user_as_changed(&self, user_id: &UserId) {
+        let olm = self.olm.read().await;
+
+        if let Some(o) = &*olm {
+            o.mark_user_as_changed(user_id).await;
+        }
+    }
+
     /// Should users be queried for their device keys.
     #[cfg(feature = "encryption")]
     #[cfg_attr(docsrs, doc(cfg(feature = "encryption")))]
     pub async fn should_query_keys(&self) -> bool {
-        let olm = self.olm.lock().await;
+        let olm = self.olm.read().await;
 
         match &*olm {
-            Some(o) => o.should_query_keys(),
+            Some(o) => o.should_query_keys().await,
             None => false,
         }
     }
@@ -955,9 +3691,9 @@ impl BaseClient {
         &self,
         users: impl Iterator<Item = &UserId>,
     ) -> Result<BTreeMap<UserId, BTreeMap<DeviceId, KeyAlgorithm>>> {
-        let mut olm = self.olm.lock().await;
+        let olm = self.olm.read().await;
 
-        match &mut *olm {
+        match &*olm {
             Some(o) => Ok(o.get_missing_sessions(users).await?),
             None => Ok(BTreeMap::new()),
         }
@@ -971,11 +3707,11 @@ impl BaseClient {
         room_id: &RoomId,
     ) -> Result<Vec<send_event_to_device::Request>> {
         let room = self.get_joined_room(room_id).await.expect("No room found");
-        let mut olm = self.olm.lock().await;
+        let olm = self.olm.read().await;
 
-        match &mut *olm {
+        match &*olm {
             Some(o) => {
-                let room = room.write().await;
+                let room = room.read().await;
                 let members = room.members.keys();
                 Ok(o.share_group_session(room_id, members).await?)
             }
@@ -983,6 +3719,57 @@ impl BaseClient {
         }
     }
 
+    /// Get the to-device requests that share a room key but weren't marked
+    /// as sent, e.g. because the client was stopped while sharing the key.
+    ///
+    /// Returns an empty list if the client isn't logged in.
+    #[cfg(feature = "encryption")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "encryption")))]
+    pub async fn pending_room_key_requests(&self) -> Result<Vec<send_event_to_device::Request>> {
+        let olm = self.olm.read().await;
+
+        match &*olm {
+            Some(o) => Ok(o
+                .pending_room_key_requests()
+                .await
+                .map_err(OlmError::from)?),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Mark a to-device request that was returned by `share_group_session()`
+    /// or `pending_room_key_requests()` as sent.
+    ///
+    /// # Arguments
+    ///
+    /// * `txn_id` - The transaction id of the request that was sent.
+    #[cfg(feature = "encryption")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "encryption")))]
+    pub async fn mark_room_key_request_as_sent(&self, txn_id: &str) -> Result<()> {
+        let olm = self.olm.read().await;
+
+        match &*olm {
+            Some(o) => Ok(o
+                .mark_room_key_request_as_sent(txn_id)
+                .await
+                .map_err(OlmError::from)?),
+            None => Ok(()),
+        }
+    }
+
+    /// Get the to-device messages the Olm machine wants to send out, e.g. the
+    /// messages of an automatically accepted key verification.
+    #[cfg(feature = "encryption")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "encryption")))]
+    pub async fn outgoing_to_device_requests(&self) -> Vec<send_event_to_device::Request> {
+        let olm = self.olm.read().await;
+
+        match &*olm {
+            Some(o) => o.outgoing_to_device_requests().await,
+            None => Vec::new(),
+        }
+    }
+
     /// Encrypt a message event content.
     #[cfg(feature = "encryption")]
     #[cfg_attr(docsrs, doc(cfg(feature = "encryption")))]
@@ -991,14 +3778,56 @@ impl BaseClient {
         room_id: &RoomId,
         content: MessageEventContent,
     ) -> Result<EncryptedEventContent> {
-        let mut olm = self.olm.lock().await;
+        let olm = self.olm.read().await;
 
-        match &mut *olm {
+        match &*olm {
             Some(o) => Ok(o.encrypt(room_id, content).await?),
             None => panic!("Olm machine wasn't started"),
         }
     }
 
+    /// Encrypt the raw JSON content of a room event.
+    #[cfg(feature = "encryption")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "encryption")))]
+    pub async fn encrypt_raw(
+        &self,
+        room_id: &RoomId,
+        event_type: EventType,
+        content: JsonValue,
+    ) -> Result<EncryptedEventContent> {
+        let olm = self.olm.read().await;
+
+        match &*olm {
+            Some(o) => Ok(o.encrypt_raw(room_id, event_type, content).await?),
+            None => panic!("Olm machine wasn't started"),
+        }
+    }
+
+    /// Decrypt the raw JSON of an encrypted room event without a sync, e.g.
+    /// one that was delivered inside of a push notification.
+    ///
+    /// Only the keys that are already known to the crypto store are used.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The id of the room the event was sent in.
+    ///
+    /// * `event` - The JSON of the `m.room.encrypted` event.
+    #[cfg(feature = "encryption")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "encryption")))]
+    pub async fn decrypt_raw_event(
+        &self,
+        room_id: &RoomId,
+        event: &str,
+    ) -> Result<EventJson<RoomEvent>> {
+        let olm = self.olm.read().await;
+
+        match &*olm {
+            Some(o) => Ok(o.decrypt_raw_room_event(room_id, event).await?),
+            None => Err(Error::AuthenticationRequired),
+        }
+    }
+
     /// Get a tuple of device and one-time keys that need to be uploaded.
     ///
     /// Returns an empty error if no keys need to be uploaded.
@@ -1007,7 +3836,7 @@ impl BaseClient {
     pub async fn keys_for_upload(
         &self,
     ) -> StdResult<(Option<DeviceKeys>, Option<OneTimeKeys>), ()> {
-        let olm = self.olm.lock().await;
+        let olm = self.olm.read().await;
 
         match &*olm {
             Some(o) => o.keys_for_upload().await,
@@ -1021,10 +3850,10 @@ impl BaseClient {
     #[cfg(feature = "encryption")]
     #[cfg_attr(docsrs, doc(cfg(feature = "encryption")))]
     pub async fn users_for_key_query(&self) -> StdResult<HashSet<UserId>, ()> {
-        let olm = self.olm.lock().await;
+        let olm = self.olm.read().await;
 
         match &*olm {
-            Some(o) => Ok(o.users_for_key_query()),
+            Some(o) => Ok(o.users_for_key_query().await),
             None => Err(()),
         }
     }
@@ -1041,9 +3870,9 @@ impl BaseClient {
     #[cfg(feature = "encryption")]
     #[cfg_attr(docsrs, doc(cfg(feature = "encryption")))]
     pub async fn receive_keys_upload_response(&self, response: &KeysUploadResponse) -> Result<()> {
-        let mut olm = self.olm.lock().await;
+        let olm = self.olm.read().await;
 
-        let o = olm.as_mut().expect("Client isn't logged in.");
+        let o = olm.as_ref().expect("Client isn't logged in.");
         o.receive_keys_upload_response(response).await?;
         Ok(())
     }
@@ -1060,41 +3889,149 @@ impl BaseClient {
     #[cfg(feature = "encryption")]
     #[cfg_attr(docsrs, doc(cfg(feature = "encryption")))]
     pub async fn receive_keys_claim_response(&self, response: &KeysClaimResponse) -> Result<()> {
-        let mut olm = self.olm.lock().await;
+        let olm = self.olm.read().await;
+
+        let o = olm.as_ref().expect("Client isn't logged in.");
+        o.receive_keys_claim_response(response).await?;
+        Ok(())
+    }
+
+    /// Receive a successful keys query response.
+    ///
+    /// # Arguments
+    ///
+    /// * `response` - The keys query response of the request that the client
+    /// performed.
+    ///
+    /// # Panics
+    /// Panics if the client hasn't been logged in.
+    #[cfg(feature = "encryption")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "encryption")))]
+    pub async fn receive_keys_query_response(&self, response: &KeysQueryResponse) -> Result<()> {
+        let olm = self.olm.read().await;
+
+        let o = olm.as_ref().expect("Client isn't logged in.");
+        o.receive_keys_query_response(response).await?;
+        // TODO notify our callers of new devices via some callback.
+        Ok(())
+    }
+
+    /// Emit a reaction to an event of a joined room.
+    pub(crate) async fn emit_reaction(&self, room_id: &RoomId, reaction: &Reaction) {
+        let room = match self.get_joined_room(room_id).await {
+            Some(r) => r,
+            None => return,
+        };
+
+        if let Some(ee) = self.event_emitters.read().await.active() {
+            let room = SyncRoom::with_context(RoomState::Joined(room)).await;
+            ee.on_room_reaction(room, reaction).await;
+        }
+    }
+
+    pub(crate) async fn emit_message_verdict(
+        &self,
+        room_id: &RoomId,
+        event: &MessageEvent,
+        verdict: &SpamVerdict,
+    ) {
+        let room = match self.get_joined_room(room_id).await {
+            Some(r) => r,
+            None => return,
+        };
+
+        if let Some(ee) = self.event_emitters.read().await.active() {
+            let room = SyncRoom::with_context(RoomState::Joined(room)).await;
+            ee.on_room_message_verdict(room, event, verdict).await;
+        }
+    }
+
+    pub(crate) async fn emit_notification(
+        &self,
+        room_id: &RoomId,
+        event: &RoomEvent,
+        actions: &PushActions,
+    ) {
+        let room = match self.get_joined_room(room_id).await {
+            Some(r) => r,
+            None => return,
+        };
+
+        if let Some(ee) = self.event_emitters.read().await.active() {
+            let room = SyncRoom::with_context(RoomState::Joined(room)).await;
+            ee.on_notification(room, event, actions).await;
+        }
+    }
+
+    pub(crate) async fn emit_receipt(&self, room_id: &RoomId, receipt: &ReadReceipt) {
+        let room = match self.get_joined_room(room_id).await {
+            Some(r) => r,
+            None => return,
+        };
+
+        if let Some(ee) = self.event_emitters.read().await.active() {
+            let room = SyncRoom::with_context(RoomState::Joined(room)).await;
+            ee.on_receipt(room, receipt).await;
+        }
+    }
+
+    /// Call the typed event handlers that were registered for the type of
+    /// the event.
+    pub(crate) async fn call_event_handlers<E: Serialize>(
+        &self,
+        room_id: &RoomId,
+        event: &E,
+        room_state: RoomStateType,
+    ) {
+        if self.event_handlers.read().await.is_empty() {
+            return;
+        }
+
+        let event = match serde_json::to_value(event) {
+            Ok(e) => e,
+            Err(_) => return,
+        };
+
+        let event_type = match event["type"].as_str() {
+            Some(t) => t,
+            None => return,
+        };
 
-        let o = olm.as_mut().expect("Client isn't logged in.");
-        o.receive_keys_claim_response(response).await?;
-        Ok(())
-    }
+        // Clone the handlers so a handler can register further handlers
+        // without deadlocking.
+        let handlers = match self.event_handlers.read().await.get(event_type) {
+            Some(h) => h.clone(),
+            None => return,
+        };
 
-    /// Receive a successful keys query response.
-    ///
-    /// # Arguments
-    ///
-    /// * `response` - The keys query response of the request that the client
-    /// performed.
-    ///
-    /// # Panics
-    /// Panics if the client hasn't been logged in.
-    #[cfg(feature = "encryption")]
-    #[cfg_attr(docsrs, doc(cfg(feature = "encryption")))]
-    pub async fn receive_keys_query_response(&self, response: &KeysQueryResponse) -> Result<()> {
-        let mut olm = self.olm.lock().await;
+        let room = match room_state {
+            RoomStateType::Invited => self.get_invited_room(room_id).await.map(RoomState::Invited),
+            RoomStateType::Joined => self.get_joined_room(room_id).await.map(RoomState::Joined),
+            RoomStateType::Left => self.get_left_room(room_id).await.map(RoomState::Left),
+        };
+        let room = match room {
+            Some(r) => SyncRoom::with_context(r).await,
+            None => return,
+        };
 
-        let o = olm.as_mut().expect("Client isn't logged in.");
-        o.receive_keys_query_response(response).await?;
-        // TODO notify our callers of new devices via some callback.
-        Ok(())
+        for handler in handlers {
+            if let Some(future) = handler(room.clone(), &event) {
+                future.await;
+            }
+        }
     }
 
     pub(crate) async fn emit_timeline_event(
         &self,
         room_id: &RoomId,
         event: &RoomEvent,
+        raw_event: &RawJsonValue,
         room_state: RoomStateType,
     ) {
-        let lock = self.event_emitter.read().await;
-        let event_emitter = if let Some(ee) = lock.as_ref() {
+        self.call_event_handlers(room_id, event, room_state).await;
+
+        let lock = self.event_emitters.read().await;
+        let event_emitter = if let Some(ee) = lock.active() {
             ee
         } else {
             return;
@@ -1123,6 +4060,14 @@ impl BaseClient {
                 }
             }
         };
+        let room = SyncRoom::with_context(room).await;
+
+        event_emitter
+            .on_timeline_event(room.clone(), event, EventOrigin::Live)
+            .await;
+        event_emitter
+            .on_raw_timeline_event(room.clone(), event, raw_event)
+            .await;
 
         match event {
             RoomEvent::RoomMember(mem) => event_emitter.on_room_member(room, &mem).await,
@@ -1134,6 +4079,16 @@ impl BaseClient {
             }
             RoomEvent::RoomAliases(aliases) => event_emitter.on_room_aliases(room, &aliases).await,
             RoomEvent::RoomAvatar(avatar) => event_emitter.on_room_avatar(room, &avatar).await,
+            RoomEvent::RoomTopic(topic) => event_emitter.on_room_topic(room, &topic).await,
+            RoomEvent::RoomGuestAccess(access) => {
+                event_emitter.on_room_guest_access(room, &access).await
+            }
+            RoomEvent::RoomHistoryVisibility(visibility) => {
+                event_emitter
+                    .on_room_history_visibility(room, &visibility)
+                    .await
+            }
+            RoomEvent::RoomServerAcl(acl) => event_emitter.on_room_server_acl(room, &acl).await,
             RoomEvent::RoomMessage(msg) => event_emitter.on_room_message(room, &msg).await,
             RoomEvent::RoomMessageFeedback(msg_feedback) => {
                 event_emitter
@@ -1147,18 +4102,110 @@ impl BaseClient {
                 event_emitter.on_room_power_levels(room, &power).await
             }
             RoomEvent::RoomTombstone(tomb) => event_emitter.on_room_tombstone(room, &tomb).await,
+            RoomEvent::CustomRoom(custom) => event_emitter.on_custom_event(room, &custom).await,
+            RoomEvent::CustomState(custom) => {
+                event_emitter.on_custom_state_event(room, &custom).await
+            }
             _ => {}
         }
     }
 
+    /// Emit the state events of a room, delivering the member events as a
+    /// single `MembersSummary` if there are more of them than the configured
+    /// `member_batch_threshold`.
+    pub(crate) async fn emit_state_events(
+        &self,
+        room_id: &RoomId,
+        events: &[EventJson<StateEvent>],
+        room_state: RoomStateType,
+    ) {
+        let events: Vec<(StateEvent, &RawJsonValue)> = events
+            .iter()
+            .filter_map(|e| e.deserialize().ok().map(|d| (d, e.json())))
+            .collect();
+
+        let batched = if let Some(threshold) = self.member_batch_threshold {
+            events
+                .iter()
+                .filter(|(e, _)| matches!(e, StateEvent::RoomMember(_)))
+                .count()
+                > threshold
+        } else {
+            false
+        };
+
+        let mut summary = MembersSummary::default();
+
+        for (event, raw_event) in &events {
+            self.call_event_handlers(room_id, event, room_state).await;
+
+            match event {
+                StateEvent::RoomMember(member) if batched => {
+                    summary.add(&member.content.membership)
+                }
+                _ => {
+                    self.emit_state_event(room_id, event, raw_event, room_state)
+                        .await
+                }
+            }
+        }
+
+        if batched {
+            self.emit_members_loaded(room_id, &summary, room_state)
+                .await;
+        }
+    }
+
+    pub(crate) async fn emit_members_loaded(
+        &self,
+        room_id: &RoomId,
+        summary: &MembersSummary,
+        room_state: RoomStateType,
+    ) {
+        let lock = self.event_emitters.read().await;
+        let event_emitter = if let Some(ee) = lock.active() {
+            ee
+        } else {
+            return;
+        };
+
+        let room = match room_state {
+            RoomStateType::Invited => {
+                if let Some(room) = self.get_invited_room(&room_id).await {
+                    RoomState::Invited(Arc::clone(&room))
+                } else {
+                    return;
+                }
+            }
+            RoomStateType::Joined => {
+                if let Some(room) = self.get_joined_room(&room_id).await {
+                    RoomState::Joined(Arc::clone(&room))
+                } else {
+                    return;
+                }
+            }
+            RoomStateType::Left => {
+                if let Some(room) = self.get_left_room(&room_id).await {
+                    RoomState::Left(Arc::clone(&room))
+                } else {
+                    return;
+                }
+            }
+        };
+        let room = SyncRoom::with_context(room).await;
+
+        event_emitter.on_room_members_loaded(room, summary).await;
+    }
+
     pub(crate) async fn emit_state_event(
         &self,
         room_id: &RoomId,
         event: &StateEvent,
+        raw_event: &RawJsonValue,
         room_state: RoomStateType,
     ) {
-        let lock = self.event_emitter.read().await;
-        let event_emitter = if let Some(ee) = lock.as_ref() {
+        let lock = self.event_emitters.read().await;
+        let event_emitter = if let Some(ee) = lock.active() {
             ee
         } else {
             return;
@@ -1187,6 +4234,11 @@ impl BaseClient {
                 }
             }
         };
+        let room = SyncRoom::with_context(room).await;
+
+        event_emitter
+            .on_raw_state_event(room.clone(), event, raw_event)
+            .await;
 
         match event {
             StateEvent::RoomMember(member) => event_emitter.on_state_member(room, &member).await,
@@ -1200,6 +4252,16 @@ impl BaseClient {
                 event_emitter.on_state_aliases(room, &aliases).await
             }
             StateEvent::RoomAvatar(avatar) => event_emitter.on_state_avatar(room, &avatar).await,
+            StateEvent::RoomTopic(topic) => event_emitter.on_state_topic(room, &topic).await,
+            StateEvent::RoomGuestAccess(access) => {
+                event_emitter.on_state_guest_access(room, &access).await
+            }
+            StateEvent::RoomHistoryVisibility(visibility) => {
+                event_emitter
+                    .on_state_history_visibility(room, &visibility)
+                    .await
+            }
+            StateEvent::RoomServerAcl(acl) => event_emitter.on_state_server_acl(room, &acl).await,
             StateEvent::RoomPowerLevels(power) => {
                 event_emitter.on_state_power_levels(room, &power).await
             }
@@ -1207,6 +4269,9 @@ impl BaseClient {
                 event_emitter.on_state_join_rules(room, &rules).await
             }
             StateEvent::RoomTombstone(tomb) => event_emitter.on_room_tombstone(room, &tomb).await,
+            StateEvent::CustomState(custom) => {
+                event_emitter.on_custom_state_event(room, &custom).await
+            }
             _ => {}
         }
     }
@@ -1217,8 +4282,8 @@ impl BaseClient {
         event: &AnyStrippedStateEvent,
         room_state: RoomStateType,
     ) {
-        let lock = self.event_emitter.read().await;
-        let event_emitter = if let Some(ee) = lock.as_ref() {
+        let lock = self.event_emitters.read().await;
+        let event_emitter = if let Some(ee) = lock.active() {
             ee
         } else {
             return;
@@ -1247,6 +4312,7 @@ impl BaseClient {
                 }
             }
         };
+        let room = SyncRoom::with_context(room).await;
 
         match event {
             AnyStrippedStateEvent::RoomMember(member) => {
@@ -1268,6 +4334,19 @@ impl BaseClient {
             AnyStrippedStateEvent::RoomAvatar(avatar) => {
                 event_emitter.on_stripped_state_avatar(room, &avatar).await
             }
+            AnyStrippedStateEvent::RoomTopic(topic) => {
+                event_emitter.on_stripped_state_topic(room, &topic).await
+            }
+            AnyStrippedStateEvent::RoomGuestAccess(access) => {
+                event_emitter
+                    .on_stripped_state_guest_access(room, &access)
+                    .await
+            }
+            AnyStrippedStateEvent::RoomHistoryVisibility(visibility) => {
+                event_emitter
+                    .on_stripped_state_history_visibility(room, &visibility)
+                    .await
+            }
             AnyStrippedStateEvent::RoomPowerLevels(power) => {
                 event_emitter
                     .on_stripped_state_power_levels(room, &power)
@@ -1288,8 +4367,8 @@ impl BaseClient {
         event: &NonRoomEvent,
         room_state: RoomStateType,
     ) {
-        let lock = self.event_emitter.read().await;
-        let event_emitter = if let Some(ee) = lock.as_ref() {
+        let lock = self.event_emitters.read().await;
+        let event_emitter = if let Some(ee) = lock.active() {
             ee
         } else {
             return;
@@ -1318,6 +4397,7 @@ impl BaseClient {
                 }
             }
         };
+        let room = SyncRoom::with_context(room).await;
 
         match event {
             NonRoomEvent::Presence(presence) => {
@@ -1344,8 +4424,8 @@ impl BaseClient {
         event: &NonRoomEvent,
         room_state: RoomStateType,
     ) {
-        let lock = self.event_emitter.read().await;
-        let event_emitter = if let Some(ee) = lock.as_ref() {
+        let lock = self.event_emitters.read().await;
+        let event_emitter = if let Some(ee) = lock.active() {
             ee
         } else {
             return;
@@ -1374,6 +4454,7 @@ impl BaseClient {
                 }
             }
         };
+        let room = SyncRoom::with_context(room).await;
 
         match event {
             NonRoomEvent::Presence(presence) => {
@@ -1390,6 +4471,12 @@ impl BaseClient {
                     .on_account_data_fully_read(room, &full_read)
                     .await
             }
+            NonRoomEvent::Typing(typing) => {
+                event_emitter.on_account_data_typing(room, &typing).await
+            }
+            NonRoomEvent::Receipt(receipt) => {
+                event_emitter.on_account_data_receipt(room, &receipt).await
+            }
             _ => {}
         }
     }
@@ -1423,8 +4510,698 @@ impl BaseClient {
                 }
             }
         };
-        if let Some(ee) = &self.event_emitter.read().await.as_ref() {
+        let room = SyncRoom::with_context(room).await;
+        if let Some(ee) = self.event_emitters.read().await.active() {
             ee.on_presence_event(room, &event).await;
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::identifiers::UserId;
+    #[cfg(not(target_arch = "wasm32"))]
+    use crate::JsonStore;
+    use crate::Session;
+    use matrix_sdk_test::async_test;
+    use serde_json::json;
+
+    #[cfg(target_arch = "wasm32")]
+    use wasm_bindgen_test::*;
+
+    use std::convert::TryFrom;
+
+    fn get_client() -> BaseClient {
+        let session = Session {
+            access_token: "1234".to_owned(),
+            user_id: UserId::try_from("@example:localhost").unwrap(),
+            device_id: "DEVICEID".to_owned(),
+        };
+        BaseClient::new(Some(session)).unwrap()
+    }
+
+    fn member_event(room_id: &str, membership: &str, event_id: &str) -> JsonValue {
+        json!({
+            "content": {
+                "membership": membership
+            },
+            "event_id": event_id,
+            "origin_server_ts": 1_589_000_000_000u64,
+            "room_id": room_id,
+            "sender": "@example:localhost",
+            "state_key": "@example:localhost",
+            "type": "m.room.member"
+        })
+    }
+
+    #[cfg(feature = "encryption")]
+    #[async_test]
+    async fn appservice_session() {
+        let user_id = UserId::try_from("@bot:localhost").unwrap();
+        let session = Session::appservice("as_token", user_id);
+        assert!(!session.has_device());
+
+        let client = BaseClient::new(Some(session)).unwrap();
+        assert!(client.logged_in().await);
+        assert!(!client.should_upload_keys().await);
+        assert!(get_client().should_upload_keys().await);
+    }
+
+    #[async_test]
+    async fn transaction_routing() {
+        let client = get_client();
+        let room_id = RoomId::try_from("!SVkFJHzfwvuaIEawgC:localhost").unwrap();
+        let other_room_id = RoomId::try_from("!other:localhost").unwrap();
+
+        let events = vec![
+            member_event(&room_id.to_string(), "join", "$1:localhost"),
+            json!({
+                "content": {
+                    "body": "Hello",
+                    "msgtype": "m.text"
+                },
+                "event_id": "$2:localhost",
+                "origin_server_ts": 1_589_000_000_001u64,
+                "room_id": other_room_id,
+                "sender": "@alice:localhost",
+                "type": "m.room.message"
+            }),
+            member_event(&other_room_id.to_string(), "leave", "$3:localhost"),
+        ];
+
+        let mut events: Vec<EventJson<RoomEvent>> = events
+            .into_iter()
+            .map(|e| serde_json::from_value(e).unwrap())
+            .collect();
+
+        client.receive_transaction(&mut events).await.unwrap();
+
+        let room = client.get_joined_room(&room_id).await.unwrap();
+        assert!(room
+            .read()
+            .await
+            .members
+            .contains_key(&UserId::try_from("@example:localhost").unwrap()));
+
+        assert!(client.get_joined_room(&other_room_id).await.is_none());
+        assert!(client.get_left_room(&other_room_id).await.is_some());
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[async_test]
+    async fn persisted_media_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let key = "mxc://localhost/AQwafuaFswefuhsfAFAgsw";
+
+        let session = Session {
+            access_token: "1234".to_owned(),
+            user_id: UserId::try_from("@example:localhost").unwrap(),
+            device_id: "DEVICEID".to_owned(),
+        };
+
+        let config = || {
+            BaseClientConfig::new()
+                .state_store(Box::new(JsonStore::open(dir.path()).unwrap()))
+                .media_cache_size(10)
+        };
+
+        let client = BaseClient::new_with_config(Some(session.clone()), config()).unwrap();
+        client.cache_media(key, b"media").await.unwrap();
+        assert_eq!(
+            client.get_cached_media(key).await.unwrap(),
+            Some(b"media".to_vec())
+        );
+
+        // A new client picks the media up from the state store.
+        let client = BaseClient::new_with_config(Some(session), config()).unwrap();
+        assert_eq!(
+            client.get_cached_media(key).await.unwrap(),
+            Some(b"media".to_vec())
+        );
+
+        // Media that doesn't fit into the cache isn't cached.
+        client
+            .cache_media("mxc://localhost/huge", &[0; 11])
+            .await
+            .unwrap();
+        assert_eq!(
+            client
+                .get_cached_media("mxc://localhost/huge")
+                .await
+                .unwrap(),
+            None
+        );
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[async_test]
+    async fn batched_room_loading() {
+        use crate::state::{ClientState, StateStore};
+
+        let dir = tempfile::tempdir().unwrap();
+        let user_id = UserId::try_from("@example:localhost").unwrap();
+
+        let session = Session {
+            access_token: "1234".to_owned(),
+            user_id: user_id.clone(),
+            device_id: "DEVICEID".to_owned(),
+        };
+
+        let store = JsonStore::open(dir.path()).unwrap();
+        store.load_client_state(&session).await.unwrap();
+        store
+            .store_client_state(ClientState {
+                sync_token: Some("token".to_owned()),
+                ignored_users: Vec::new(),
+                push_ruleset: None,
+                direct_rooms: Default::default(),
+                account_data: Default::default(),
+            })
+            .await
+            .unwrap();
+
+        let room_count = ROOM_LOAD_BATCH_SIZE * 2 + 1;
+        for i in 0..room_count {
+            let room_id = RoomId::try_from(format!("!room{}:localhost", i).as_str()).unwrap();
+            let room = Room::new(&room_id, &user_id);
+            store
+                .store_room_state(RoomState::Joined(&room))
+                .await
+                .unwrap();
+        }
+
+        let config = BaseClientConfig::new()
+            .state_store(Box::new(store))
+            .lazy_room_loading();
+        let client = BaseClient::new_with_config(Some(session), config).unwrap();
+
+        assert!(client.sync_with_state_store().await.unwrap());
+        assert!(client.joined_rooms().read().await.is_empty());
+
+        // A room that is accessed before the background loading is loaded on
+        // its own and kept.
+        let room_id = RoomId::try_from("!room0:localhost").unwrap();
+
+        // A room that can't be read is retried once it can be read again.
+        let path = dir
+            .path()
+            .join("example/rooms/joined")
+            .join(format!("{}.json", room_id));
+        let json = std::fs::read_to_string(&path).unwrap();
+        std::fs::write(&path, "not json").unwrap();
+        assert!(client.get_joined_room(&room_id).await.is_none());
+        std::fs::write(&path, json).unwrap();
+
+        let room = client.get_joined_room(&room_id).await.unwrap();
+
+        client.load_stored_rooms().await.unwrap();
+
+        let rooms = client.joined_rooms();
+        let rooms = rooms.read().await;
+        assert_eq!(rooms.len(), room_count);
+        assert!(Arc::ptr_eq(&room, &rooms[&room_id]));
+        assert!(client.unloaded_rooms.read().await.is_empty());
+    }
+
+    #[async_test]
+    async fn unhandled_event_tracking() {
+        use crate::api::r0::sync::sync_events::Response as SyncResponse;
+
+        let session = Session {
+            access_token: "1234".to_owned(),
+            user_id: UserId::try_from("@example:localhost").unwrap(),
+            device_id: "DEVICEID".to_owned(),
+        };
+
+        let mut json: JsonValue =
+            serde_json::from_str(include_str!("../../test_data/sync.json")).unwrap();
+        let custom = json!({
+            "content": { "score": 42 },
+            "event_id": "$custom:localhost",
+            "origin_server_ts": 1_589_000_000_000u64,
+            "sender": "@example:localhost",
+            "type": "org.example.score"
+        });
+        json["rooms"]["join"]["!SVkFJHzfwvuaIEawgC:localhost"]["timeline"]["events"]
+            .as_array_mut()
+            .unwrap()
+            .extend(vec![custom.clone(), custom]);
+
+        let response = http::Response::builder()
+            .body(serde_json::to_vec(&json).unwrap())
+            .unwrap();
+        let mut response = SyncResponse::try_from(response).unwrap();
+
+        let client = BaseClient::new_with_config(
+            Some(session),
+            BaseClientConfig::new().track_unhandled_events(),
+        )
+        .unwrap();
+        client.receive_sync_response(&mut response).await.unwrap();
+
+        let unhandled = client.unhandled_event_types().await.unwrap();
+        assert_eq!(unhandled.get("org.example.score"), Some(&2));
+        assert_eq!(unhandled.get("m.room.message"), None);
+
+        assert!(get_client().unhandled_event_types().await.is_none());
+    }
+
+    #[async_test]
+    async fn global_presence() {
+        use crate::api::r0::sync::sync_events::Response as SyncResponse;
+        use crate::events::presence::PresenceState;
+
+        let user_id = UserId::try_from("@example:localhost").unwrap();
+
+        // The presence is kept even if we don't share a room with the user.
+        let mut json: JsonValue =
+            serde_json::from_str(include_str!("../../test_data/sync.json")).unwrap();
+        json["rooms"]["join"] = json!({});
+
+        let response = http::Response::builder()
+            .body(serde_json::to_vec(&json).unwrap())
+            .unwrap();
+        let mut response = SyncResponse::try_from(response).unwrap();
+
+        let client = get_client();
+        assert!(client.presence(&user_id).await.is_none());
+
+        client.receive_sync_response(&mut response).await.unwrap();
+
+        let presence = client.presence(&user_id).await.unwrap();
+        assert_eq!(presence.presence, PresenceState::Online);
+        assert_eq!(presence.status_msg.as_deref(), Some("Making cupcakes"));
+
+        client.clear_local_state().await.unwrap();
+        assert!(client.presence(&user_id).await.is_none());
+    }
+
+    #[async_test]
+    async fn direct_rooms() {
+        use crate::api::r0::sync::sync_events::Response as SyncResponse;
+
+        let room_id = RoomId::try_from("!SVkFJHzfwvuaIEawgC:localhost").unwrap();
+        let friend = UserId::try_from("@friend:localhost").unwrap();
+
+        let mut json: JsonValue =
+            serde_json::from_str(include_str!("../../test_data/sync.json")).unwrap();
+        json["account_data"] = json!({
+            "events": [{
+                "content": { "@friend:localhost": [room_id.to_string()] },
+                "type": "m.direct"
+            }]
+        });
+
+        let response = http::Response::builder()
+            .body(serde_json::to_vec(&json).unwrap())
+            .unwrap();
+        let mut response = SyncResponse::try_from(response).unwrap();
+
+        let client = get_client();
+        client.receive_sync_response(&mut response).await.unwrap();
+
+        assert_eq!(client.direct_room_ids(&friend).await, vec![room_id.clone()]);
+        let room = client.get_joined_room(&room_id).await.unwrap();
+        assert!(room.read().await.is_direct());
+        assert_eq!(room.read().await.direct_target(), Some(&friend));
+
+        // The room isn't a direct message room anymore once the account data
+        // doesn't list it.
+        assert!(client.receive_direct_rooms(BTreeMap::new()).await.unwrap());
+        assert!(!room.read().await.is_direct());
+        assert!(!client.receive_direct_rooms(BTreeMap::new()).await.unwrap());
+    }
+
+    #[async_test]
+    async fn send_queue_ordering() {
+        use crate::events::room::message::TextMessageEventContent;
+
+        let client = get_client();
+        let first_room = RoomId::try_from("!first:localhost").unwrap();
+        let second_room = RoomId::try_from("!second:localhost").unwrap();
+        let content = MessageEventContent::Text(TextMessageEventContent {
+            body: "Hello world".to_owned(),
+            format: None,
+            formatted_body: None,
+            relates_to: None,
+        });
+
+        for (room_id, txn_id) in &[(&first_room, "1"), (&first_room, "2"), (&second_room, "3")] {
+            client
+                .queue_message(room_id, txn_id, content.clone())
+                .await
+                .unwrap();
+        }
+
+        // The second message of the first room waits for the first one.
+        let next = client.next_queued_message().await.unwrap().unwrap();
+        assert_eq!(next.txn_id, "1");
+        let next = client.next_queued_message().await.unwrap().unwrap();
+        assert_eq!(next.txn_id, "3");
+        assert!(client.next_queued_message().await.unwrap().is_none());
+
+        client
+            .set_queued_message_state("1", SendState::Failed("forbidden".to_owned()))
+            .await
+            .unwrap();
+        assert!(client.next_queued_message().await.unwrap().is_none());
+
+        client
+            .set_queued_message_state("1", SendState::Queued)
+            .await
+            .unwrap();
+        let next = client.next_queued_message().await.unwrap().unwrap();
+        assert_eq!(next.txn_id, "1");
+
+        let event_id = EventId::try_from("$1:localhost").unwrap();
+        client
+            .set_queued_message_state("1", SendState::Sent(event_id))
+            .await
+            .unwrap();
+        let next = client.next_queued_message().await.unwrap().unwrap();
+        assert_eq!(next.txn_id, "2");
+
+        assert_eq!(client.queued_messages(Some(&first_room)).await.len(), 1);
+        assert!(client.remove_queued_message("2").await.unwrap().is_none());
+        assert!(client.remove_queued_message("3").await.unwrap().is_none());
+    }
+
+    #[async_test]
+    async fn local_echo_reconciliation() {
+        use crate::events::room::message::TextMessageEventContent;
+
+        let client = get_client();
+        let room_id = RoomId::try_from("!test:localhost").unwrap();
+        let room = client.get_or_create_joined_room(&room_id).await;
+
+        let content = MessageEventContent::Text(TextMessageEventContent {
+            body: "Hello world".to_owned(),
+            format: None,
+            formatted_body: None,
+            relates_to: None,
+        });
+
+        assert!(
+            client
+                .add_local_echo(&room_id, "1", content.clone(), SendState::Sending)
+                .await
+        );
+        assert!(
+            !client
+                .add_local_echo(&room_id, "1", content.clone(), SendState::Sending)
+                .await
+        );
+        assert!(
+            client
+                .add_local_echo(&room_id, "2", content, SendState::Sending)
+                .await
+        );
+
+        let event_id = EventId::try_from("$2:localhost").unwrap();
+        assert!(
+            client
+                .update_local_echo(&room_id, "2", SendState::Sent(event_id))
+                .await
+        );
+        assert_eq!(room.read().await.pending_events().len(), 2);
+
+        // The sync echo of the first message carries its transaction id.
+        let mut event: EventJson<RoomEvent> = serde_json::from_value(json!({
+            "content": { "body": "Hello world", "msgtype": "m.text" },
+            "event_id": "$1:localhost",
+            "origin_server_ts": 1_589_000_000_000u64,
+            "sender": "@example:localhost",
+            "type": "m.room.message",
+            "unsigned": { "transaction_id": "1" }
+        }))
+        .unwrap();
+        client
+            .receive_joined_timeline_event(&room_id, &mut event)
+            .await;
+
+        let pending = room.read().await.pending_events().to_vec();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].txn_id, "2");
+
+        // The second message is matched using the event id the server
+        // returned.
+        let mut event: EventJson<RoomEvent> = serde_json::from_value(json!({
+            "content": { "body": "Hello world", "msgtype": "m.text" },
+            "event_id": "$2:localhost",
+            "origin_server_ts": 1_589_000_000_001u64,
+            "sender": "@example:localhost",
+            "type": "m.room.message"
+        }))
+        .unwrap();
+        client
+            .receive_joined_timeline_event(&room_id, &mut event)
+            .await;
+
+        assert!(room.read().await.pending_events().is_empty());
+    }
+
+    #[cfg(feature = "messages")]
+    #[async_test]
+    async fn duplicate_timeline_events() {
+        let client = get_client();
+        let room_id = RoomId::try_from("!duplicate:localhost").unwrap();
+
+        let json = member_event(&room_id.to_string(), "join", "$1:localhost");
+        let mut event: EventJson<RoomEvent> = serde_json::from_value(json).unwrap();
+
+        let (_, updated) = client
+            .receive_joined_timeline_event(&room_id, &mut event)
+            .await;
+        assert!(updated);
+
+        let (_, updated) = client
+            .receive_joined_timeline_event(&room_id, &mut event)
+            .await;
+        assert!(!updated);
+
+        let room = client.get_joined_room(&room_id).await.unwrap();
+        assert_eq!(room.read().await.timeline().len(), 1);
+    }
+
+    #[cfg(feature = "messages")]
+    #[async_test]
+    async fn timeline_gaps() {
+        use crate::api::r0::message::get_message_events::Response as MessagesResponse;
+        use crate::events::room::message::TextMessageEventContent;
+        use matrix_sdk_test::{sync_response, SyncResponseFile};
+
+        let client = get_client();
+        let room_id = RoomId::try_from("!SVkFJHzfwvuaIEawgC:localhost").unwrap();
+
+        let mut response = sync_response(SyncResponseFile::Default);
+        client.receive_sync_response(&mut response).await.unwrap();
+
+        let room = client.get_joined_room(&room_id).await.unwrap();
+        assert_eq!(
+            room.read().await.timeline().gaps(),
+            vec!["t392-516_47314_0_7_1_1_1_11444_1"]
+        );
+        assert_eq!(room.read().await.timeline().len(), 1);
+
+        // The event came down the sync stream before the server answered the
+        // send request, the local echo is dropped right away.
+        let content = MessageEventContent::Text(TextMessageEventContent {
+            body: "Hello world".to_owned(),
+            format: None,
+            formatted_body: None,
+            relates_to: None,
+        });
+        let event_id = EventId::try_from("$152037280074GZeOm:localhost").unwrap();
+
+        client
+            .add_local_echo(&room_id, "1", content, SendState::Sending)
+            .await;
+        assert!(
+            client
+                .update_local_echo(&room_id, "1", SendState::Sent(event_id))
+                .await
+        );
+        assert!(room.read().await.pending_events().is_empty());
+
+        let messages = json!({
+            "start": "t392-516_47314_0_7_1_1_1_11444_1",
+            "end": "t1",
+            "chunk": [{
+                "content": { "body": "Older", "msgtype": "m.text" },
+                "event_id": "$older:localhost",
+                "origin_server_ts": 1_520_372_800_000u64,
+                "sender": "@example:localhost",
+                "type": "m.room.message"
+            }],
+            "state": []
+        });
+        let response = http::Response::builder()
+            .body(serde_json::to_vec(&messages).unwrap())
+            .unwrap();
+        let mut response = MessagesResponse::try_from(response).unwrap();
+
+        client
+            .receive_messages(&room_id, &mut response, true)
+            .await
+            .unwrap();
+
+        let room = room.read().await;
+        let events: Vec<String> = room
+            .timeline()
+            .events()
+            .map(|e| e.event_id.to_string())
+            .collect();
+
+        assert_eq!(
+            events,
+            vec!["$older:localhost", "$152037280074GZeOm:localhost"]
+        );
+        assert_eq!(room.timeline().gaps(), vec!["t1"]);
+        assert_eq!(room.prev_batch(), Some("t1"));
+    }
+
+    #[async_test]
+    async fn raw_timeline_state_event() {
+        let client = get_client();
+        let room_id = RoomId::try_from("!restricted:localhost").unwrap();
+
+        let mut json = json!({
+            "content": {
+                "join_rule": "restricted",
+                "allow": [{ "type": "m.room_membership", "room_id": "!space:localhost" }]
+            },
+            "event_id": "$h29iv0s8:example.com",
+            "origin_server_ts": 1_520_372_800_469u64,
+            "sender": "@example:localhost",
+            "type": "m.room.join_rules"
+        });
+
+        let mut event: EventJson<RoomEvent> = serde_json::from_value(json.clone()).unwrap();
+        let (_, updated) = client
+            .receive_joined_timeline_event(&room_id, &mut event)
+            .await;
+        assert!(!updated);
+        assert!(client.get_joined_room(&room_id).await.is_none());
+
+        client.get_or_create_joined_room(&room_id).await;
+
+        let (_, updated) = client
+            .receive_joined_timeline_event(&room_id, &mut event)
+            .await;
+        assert!(!updated);
+
+        json["state_key"] = json!("");
+        let mut event: EventJson<RoomEvent> = serde_json::from_value(json).unwrap();
+        let (_, updated) = client
+            .receive_joined_timeline_event(&room_id, &mut event)
+            .await;
+        assert!(updated);
+    }
+
+    #[async_test]
+    async fn room_state_snapshot() {
+        use matrix_sdk_test::{sync_response, SyncResponseFile};
+
+        let client = get_client();
+        let room_id = RoomId::try_from("!SVkFJHzfwvuaIEawgC:localhost").unwrap();
+        assert!(client.room_state_snapshot(&room_id).await.is_none());
+
+        let mut response = sync_response(SyncResponseFile::Default);
+        client.receive_sync_response(&mut response).await.unwrap();
+
+        let snapshot = client.room_state_snapshot(&room_id).await.unwrap();
+        assert_eq!(snapshot.sync_token(), client.sync_token().await.as_deref());
+        assert!(snapshot.sync_token().is_some());
+        assert!(!snapshot.is_empty());
+    }
+
+    #[cfg(feature = "local-search")]
+    #[async_test]
+    async fn local_search() {
+        use matrix_sdk_test::{sync_response, SyncResponseFile};
+
+        let client = get_client();
+        let room_id = RoomId::try_from("!SVkFJHzfwvuaIEawgC:localhost").unwrap();
+
+        let mut response = sync_response(SyncResponseFile::Default);
+        client.receive_sync_response(&mut response).await.unwrap();
+
+        let results = client.search_local("BABA", None).await;
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].room_id, room_id);
+        assert_eq!(results[0].message.body, "baba");
+
+        let other_room = RoomId::try_from("!other:localhost").unwrap();
+        assert!(client
+            .search_local("baba", Some(&other_room))
+            .await
+            .is_empty());
+    }
+
+    #[async_test]
+    async fn spam_check() {
+        use crate::events::room::message::MessageEvent;
+        use crate::{SpamChecker, SpamVerdict, SyncRoom};
+        use matrix_sdk_test::{sync_response, SyncResponseFile};
+
+        struct Checker(SpamVerdict);
+
+        #[async_trait::async_trait]
+        impl SpamChecker for Checker {
+            async fn check_message(&self, _: &RoomId, _: &MessageEvent) -> SpamVerdict {
+                self.0.clone()
+            }
+        }
+
+        struct VerdictEmitter(Arc<Mutex<Vec<SpamVerdict>>>);
+
+        #[async_trait::async_trait]
+        impl EventEmitter for VerdictEmitter {
+            async fn on_room_message_verdict(
+                &self,
+                _: SyncRoom,
+                _: &MessageEvent,
+                verdict: &SpamVerdict,
+            ) {
+                self.0.lock().await.push(verdict.clone());
+            }
+        }
+
+        let room_id = RoomId::try_from("!SVkFJHzfwvuaIEawgC:localhost").unwrap();
+        let flag = SpamVerdict::Flag {
+            reason: "suspicious".to_owned(),
+        };
+
+        let client = get_client();
+        let verdicts = Arc::new(Mutex::new(Vec::new()));
+        client
+            .add_event_emitter(Box::new(VerdictEmitter(Arc::clone(&verdicts))))
+            .await;
+        client
+            .set_spam_checker(Box::new(Checker(flag.clone())))
+            .await;
+
+        let mut response = sync_response(SyncResponseFile::Default);
+        client.receive_sync_response(&mut response).await.unwrap();
+
+        assert_eq!(*verdicts.lock().await, vec![flag]);
+        assert_eq!(response.rooms.join[&room_id].timeline.events.len(), 1);
+
+        let client = get_client();
+        let verdicts = Arc::new(Mutex::new(Vec::new()));
+        client
+            .add_event_emitter(Box::new(VerdictEmitter(Arc::clone(&verdicts))))
+            .await;
+        client
+            .set_spam_checker(Box::new(Checker(SpamVerdict::Drop)))
+            .await;
+
+        let mut response = sync_response(SyncResponseFile::Default);
+        client.receive_sync_response(&mut response).await.unwrap();
+
+        // Dropped messages reach neither the emitter nor the sync response.
+        assert!(verdicts.lock().await.is_empty());
+        assert!(response.rooms.join[&room_id].timeline.events.is_empty());
+    }
+}
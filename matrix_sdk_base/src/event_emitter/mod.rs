@@ -12,11 +12,16 @@
 // WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
 // See the License for the specific language governing permissions and
 // limitations under the License.
+use std::ops::Deref;
 use std::sync::Arc;
 
 use matrix_sdk_common::locks::RwLock;
+use serde_json::value::RawValue as RawJsonValue;
 
 use crate::events::{
+    collections::all::{RoomEvent, StateEvent},
+    custom_room::CustomRoomEvent,
+    custom_state::CustomStateEvent,
     fully_read::FullyReadEvent,
     ignored_user_list::IgnoredUserListEvent,
     presence::PresenceEvent,
@@ -26,24 +31,158 @@ use crate::events::{
         aliases::AliasesEvent,
         avatar::AvatarEvent,
         canonical_alias::CanonicalAliasEvent,
+        guest_access::GuestAccessEvent,
+        history_visibility::HistoryVisibilityEvent,
         join_rules::JoinRulesEvent,
-        member::MemberEvent,
+        member::{MemberEvent, MembershipState},
         message::{feedback::FeedbackEvent, MessageEvent},
         name::NameEvent,
         power_levels::PowerLevelsEvent,
         redaction::RedactionEvent,
+        server_acl::ServerAclEvent,
         tombstone::TombstoneEvent,
+        topic::TopicEvent,
     },
     stripped::{
-        StrippedRoomAliases, StrippedRoomAvatar, StrippedRoomCanonicalAlias, StrippedRoomJoinRules,
-        StrippedRoomMember, StrippedRoomName, StrippedRoomPowerLevels,
+        StrippedRoomAliases, StrippedRoomAvatar, StrippedRoomCanonicalAlias,
+        StrippedRoomGuestAccess, StrippedRoomHistoryVisibility, StrippedRoomJoinRules,
+        StrippedRoomMember, StrippedRoomName, StrippedRoomPowerLevels, StrippedRoomTopic,
     },
     typing::TypingEvent,
 };
-use crate::{Room, RoomState};
+use crate::identifiers::{EventId, RoomId};
+use crate::{
+    PendingEvent, PushActions, Reaction, ReadReceipt, Room, RoomState, RoomStateType, SpamVerdict,
+    UrlPreview,
+};
 
 /// Type alias for `RoomState` enum when passed to `EventEmitter` methods.
-pub type SyncRoom = RoomState<Arc<RwLock<Room>>>;
+pub type SyncRoom = RoomState<EmittedRoom>;
+
+/// A summary of a room, taken right before an event of the room is emitted.
+///
+/// This lets simple event handlers, e.g. a bot that logs the events, show
+/// the room in a human readable way without locking the room themselves.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RoomContext {
+    /// The unique id of the room.
+    pub room_id: RoomId,
+    /// The display name of the room.
+    pub display_name: String,
+    /// Is our own user joined to, invited to or has our user left the room.
+    pub membership: RoomStateType,
+    /// Is the room encrypted.
+    pub encrypted: bool,
+}
+
+/// A room that is handed to the `EventEmitter` together with its
+/// `RoomContext`.
+///
+/// It dereferences to the `Arc<RwLock<Room>>` of the room, so the room can be
+/// locked as usual.
+#[derive(Clone, Debug)]
+pub struct EmittedRoom {
+    room: Arc<RwLock<Room>>,
+    context: Arc<RoomContext>,
+}
+
+impl EmittedRoom {
+    /// The summary of the room at the time the event was emitted.
+    pub fn context(&self) -> &RoomContext {
+        &self.context
+    }
+}
+
+impl Deref for EmittedRoom {
+    type Target = Arc<RwLock<Room>>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.room
+    }
+}
+
+impl SyncRoom {
+    /// The summary of the room at the time the event was emitted.
+    pub fn context(&self) -> &RoomContext {
+        match self {
+            RoomState::Joined(r) | RoomState::Left(r) | RoomState::Invited(r) => r.context(),
+        }
+    }
+
+    /// Take the `RoomContext` of the given room so it can be passed to the
+    /// `EventEmitter`.
+    pub(crate) async fn with_context(room: RoomState<Arc<RwLock<Room>>>) -> Self {
+        let (room, membership) = match room {
+            RoomState::Joined(r) => (r, RoomStateType::Joined),
+            RoomState::Left(r) => (r, RoomStateType::Left),
+            RoomState::Invited(r) => (r, RoomStateType::Invited),
+        };
+
+        let context = {
+            let room = room.read().await;
+
+            RoomContext {
+                room_id: room.room_id.clone(),
+                display_name: room.display_name(),
+                membership,
+                encrypted: room.encrypted,
+            }
+        };
+
+        let room = EmittedRoom {
+            room,
+            context: Arc::new(context),
+        };
+
+        match membership {
+            RoomStateType::Joined => RoomState::Joined(room),
+            RoomStateType::Left => RoomState::Left(room),
+            RoomStateType::Invited => RoomState::Invited(room),
+        }
+    }
+}
+
+/// Where a timeline event that is handed to `EventEmitter::on_timeline_event()`
+/// came from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EventOrigin {
+    /// The event was received in a sync response.
+    Live,
+    /// The event is part of the room history that was fetched using the
+    /// `/messages` endpoint.
+    Backfill,
+}
+
+/// A summary of the member events of a room that were delivered as a batch.
+///
+/// See `BaseClientConfig::member_batch_threshold()`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MembersSummary {
+    /// The number of member events in the batch.
+    pub total: usize,
+    /// The number of members that are joined.
+    pub joined: usize,
+    /// The number of members that are invited.
+    pub invited: usize,
+    /// The number of members that left the room or were kicked.
+    pub left: usize,
+    /// The number of members that are banned.
+    pub banned: usize,
+}
+
+impl MembersSummary {
+    pub(crate) fn add(&mut self, membership: &MembershipState) {
+        self.total += 1;
+
+        match membership {
+            MembershipState::Join => self.joined += 1,
+            MembershipState::Invite => self.invited += 1,
+            MembershipState::Leave => self.left += 1,
+            MembershipState::Ban => self.banned += 1,
+            _ => {}
+        }
+    }
+}
 
 /// This trait allows any type implementing `EventEmitter` to specify event callbacks for each event.
 /// The `Client` calls each method when the corresponding event is received.
@@ -102,8 +241,19 @@ pub trait EventEmitter: Send + Sync {
     async fn on_room_aliases(&self, _: SyncRoom, _: &AliasesEvent) {}
     /// Fires when `Client` receives a `RoomEvent::RoomAvatar` event.
     async fn on_room_avatar(&self, _: SyncRoom, _: &AvatarEvent) {}
+    /// Fires when `Client` receives a `RoomEvent::RoomTopic` event.
+    async fn on_room_topic(&self, _: SyncRoom, _: &TopicEvent) {}
+    /// Fires when `Client` receives a `RoomEvent::RoomGuestAccess` event.
+    async fn on_room_guest_access(&self, _: SyncRoom, _: &GuestAccessEvent) {}
+    /// Fires when `Client` receives a `RoomEvent::RoomHistoryVisibility` event.
+    async fn on_room_history_visibility(&self, _: SyncRoom, _: &HistoryVisibilityEvent) {}
+    /// Fires when `Client` receives a `RoomEvent::RoomServerAcl` event.
+    async fn on_room_server_acl(&self, _: SyncRoom, _: &ServerAclEvent) {}
     /// Fires when `Client` receives a `RoomEvent::RoomMessage` event.
     async fn on_room_message(&self, _: SyncRoom, _: &MessageEvent) {}
+    /// Fires with the verdict of the `SpamChecker` for every message that
+    /// it didn't drop, right before the message is delivered.
+    async fn on_room_message_verdict(&self, _: SyncRoom, _: &MessageEvent, _: &SpamVerdict) {}
     /// Fires when `Client` receives a `RoomEvent::RoomMessageFeedback` event.
     async fn on_room_message_feedback(&self, _: SyncRoom, _: &FeedbackEvent) {}
     /// Fires when `Client` receives a `RoomEvent::RoomRedaction` event.
@@ -112,10 +262,31 @@ pub trait EventEmitter: Send + Sync {
     async fn on_room_power_levels(&self, _: SyncRoom, _: &PowerLevelsEvent) {}
     /// Fires when `Client` receives a `RoomEvent::Tombstone` event.
     async fn on_room_tombstone(&self, _: SyncRoom, _: &TombstoneEvent) {}
+    /// Fires when `Client` receives a timeline event of a type the SDK
+    /// doesn't know about, e.g. the `org.example.*` events of a bridge.
+    ///
+    /// The content of the event is left as raw JSON.
+    async fn on_custom_event(&self, _: SyncRoom, _: &CustomRoomEvent) {}
+    /// Fires when our own user sends a message, before the server echoes it
+    /// back.
+    ///
+    /// The local echo stays in `Room::pending_events()` until the real
+    /// event arrives.
+    async fn on_room_local_echo(&self, _: SyncRoom, _: &PendingEvent) {}
+    /// Fires when the preview of a URL that was found in the message with
+    /// the given event id is ready.
+    async fn on_room_url_preview(&self, _: SyncRoom, _: &EventId, _: &UrlPreview) {}
+    /// Fires when `Client` receives a `m.reaction` event.
+    ///
+    /// The reactions of an event are aggregated in `Room::reactions()`.
+    async fn on_room_reaction(&self, _: SyncRoom, _: &Reaction) {}
 
     // `RoomEvent`s from `IncomingState`
     /// Fires when `Client` receives a `StateEvent::RoomMember` event.
     async fn on_state_member(&self, _: SyncRoom, _: &MemberEvent) {}
+    /// Fires instead of `on_state_member` when a room receives more member
+    /// state events than the configured member batch threshold.
+    async fn on_room_members_loaded(&self, _: SyncRoom, _: &MembersSummary) {}
     /// Fires when `Client` receives a `StateEvent::RoomName` event.
     async fn on_state_name(&self, _: SyncRoom, _: &NameEvent) {}
     /// Fires when `Client` receives a `StateEvent::RoomCanonicalAlias` event.
@@ -124,10 +295,23 @@ pub trait EventEmitter: Send + Sync {
     async fn on_state_aliases(&self, _: SyncRoom, _: &AliasesEvent) {}
     /// Fires when `Client` receives a `StateEvent::RoomAvatar` event.
     async fn on_state_avatar(&self, _: SyncRoom, _: &AvatarEvent) {}
+    /// Fires when `Client` receives a `StateEvent::RoomTopic` event.
+    async fn on_state_topic(&self, _: SyncRoom, _: &TopicEvent) {}
+    /// Fires when `Client` receives a `StateEvent::RoomGuestAccess` event.
+    async fn on_state_guest_access(&self, _: SyncRoom, _: &GuestAccessEvent) {}
+    /// Fires when `Client` receives a `StateEvent::RoomHistoryVisibility` event.
+    async fn on_state_history_visibility(&self, _: SyncRoom, _: &HistoryVisibilityEvent) {}
+    /// Fires when `Client` receives a `StateEvent::RoomServerAcl` event.
+    async fn on_state_server_acl(&self, _: SyncRoom, _: &ServerAclEvent) {}
     /// Fires when `Client` receives a `StateEvent::RoomPowerLevels` event.
     async fn on_state_power_levels(&self, _: SyncRoom, _: &PowerLevelsEvent) {}
     /// Fires when `Client` receives a `StateEvent::RoomJoinRules` event.
     async fn on_state_join_rules(&self, _: SyncRoom, _: &JoinRulesEvent) {}
+    /// Fires when `Client` receives a state event of a type the SDK doesn't
+    /// know about, either in the state or in the timeline of a room.
+    ///
+    /// The content of the event is left as raw JSON.
+    async fn on_custom_state_event(&self, _: SyncRoom, _: &CustomStateEvent) {}
 
     // `AnyStrippedStateEvent`s
     /// Fires when `Client` receives a `AnyStrippedStateEvent::StrippedRoomMember` event.
@@ -141,6 +325,18 @@ pub trait EventEmitter: Send + Sync {
     async fn on_stripped_state_aliases(&self, _: SyncRoom, _: &StrippedRoomAliases) {}
     /// Fires when `Client` receives a `AnyStrippedStateEvent::StrippedRoomAvatar` event.
     async fn on_stripped_state_avatar(&self, _: SyncRoom, _: &StrippedRoomAvatar) {}
+    /// Fires when `Client` receives a `AnyStrippedStateEvent::StrippedRoomTopic` event.
+    async fn on_stripped_state_topic(&self, _: SyncRoom, _: &StrippedRoomTopic) {}
+    /// Fires when `Client` receives a `AnyStrippedStateEvent::StrippedRoomGuestAccess` event.
+    async fn on_stripped_state_guest_access(&self, _: SyncRoom, _: &StrippedRoomGuestAccess) {}
+    /// Fires when `Client` receives a `AnyStrippedStateEvent::StrippedRoomHistoryVisibility`
+    /// event.
+    async fn on_stripped_state_history_visibility(
+        &self,
+        _: SyncRoom,
+        _: &StrippedRoomHistoryVisibility,
+    ) {
+    }
     /// Fires when `Client` receives a `AnyStrippedStateEvent::StrippedRoomPowerLevels` event.
     async fn on_stripped_state_power_levels(&self, _: SyncRoom, _: &StrippedRoomPowerLevels) {}
     /// Fires when `Client` receives a `AnyStrippedStateEvent::StrippedRoomJoinRules` event.
@@ -161,12 +357,454 @@ pub trait EventEmitter: Send + Sync {
     ///
     /// This is always a read receipt.
     async fn on_account_data_receipt(&self, _: SyncRoom, _: &ReceiptEvent) {}
+    /// Fires for every read receipt of a `NonRoomEvent::Receipt` event.
+    ///
+    /// The read receipts are aggregated in `Room::read_receipts()`.
+    async fn on_receipt(&self, _: SyncRoom, _: &ReadReceipt) {}
+    /// Fires for every timeline event of a joined room that should notify
+    /// according to the push rules of our user.
+    ///
+    /// The push rules are evaluated locally, see `PushActions::for_event()`.
+    async fn on_notification(&self, _: SyncRoom, _: &RoomEvent, _: &PushActions) {}
+    /// Fires for every timeline event of a room, the `EventOrigin` tells
+    /// live events from sync responses apart from events of the room history.
+    ///
+    /// Encrypted events are handed out decrypted if they could be decrypted.
+    /// Backfilled events only fire this method, they aren't delivered to the
+    /// other methods.
+    async fn on_timeline_event(&self, _: SyncRoom, _: &RoomEvent, _: EventOrigin) {}
+    /// Fires for every live timeline event of a room together with the JSON
+    /// the event was deserialized from.
+    ///
+    /// The JSON contains the fields the typed event drops, e.g. unknown
+    /// `unsigned` data or keys with an unstable prefix. Encrypted events are
+    /// handed out decrypted if they could be decrypted.
+    async fn on_raw_timeline_event(&self, _: SyncRoom, _: &RoomEvent, _: &RawJsonValue) {}
+    /// Fires for every state event of a room together with the JSON the event
+    /// was deserialized from.
+    ///
+    /// Member events that are delivered as a `MembersSummary` don't fire this
+    /// method.
+    async fn on_raw_state_event(&self, _: SyncRoom, _: &StateEvent, _: &RawJsonValue) {}
 
     // `PresenceEvent` is a struct so there is only the one method
     /// Fires when `Client` receives a `NonRoomEvent::RoomAliases` event.
     async fn on_presence_event(&self, _: SyncRoom, _: &PresenceEvent) {}
 }
 
+/// A handle to an `EventEmitter` that was added to the client, it can be used
+/// to remove the emitter again.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct EmitterHandle(u64);
+
+/// The `EventEmitter`s of the client, events are handed to all of them in the
+/// order they were added in.
+#[derive(Default)]
+pub(crate) struct EventEmitters {
+    next_handle: u64,
+    emitters: Vec<(EmitterHandle, Box<dyn EventEmitter>)>,
+}
+
+impl EventEmitters {
+    /// Add an emitter, returns the handle that removes it again.
+    pub(crate) fn add(&mut self, emitter: Box<dyn EventEmitter>) -> EmitterHandle {
+        let handle = EmitterHandle(self.next_handle);
+        self.next_handle += 1;
+        self.emitters.push((handle, emitter));
+        handle
+    }
+
+    /// Remove the emitter with the given handle.
+    ///
+    /// Returns true if the emitter was removed, false if it was already
+    /// removed.
+    pub(crate) fn remove(&mut self, handle: EmitterHandle) -> bool {
+        let len = self.emitters.len();
+        self.emitters.retain(|(h, _)| *h != handle);
+        self.emitters.len() != len
+    }
+
+    /// Get the emitters, `None` if no emitter was added, so the work of
+    /// preparing an event can be skipped.
+    pub(crate) fn active(&self) -> Option<&Self> {
+        if self.emitters.is_empty() {
+            None
+        } else {
+            Some(self)
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl EventEmitter for EventEmitters {
+    async fn on_room_member(&self, room: SyncRoom, event: &MemberEvent) {
+        for (_, emitter) in &self.emitters {
+            emitter.on_room_member(room.clone(), event).await;
+        }
+    }
+
+    async fn on_room_name(&self, room: SyncRoom, event: &NameEvent) {
+        for (_, emitter) in &self.emitters {
+            emitter.on_room_name(room.clone(), event).await;
+        }
+    }
+
+    async fn on_room_canonical_alias(&self, room: SyncRoom, event: &CanonicalAliasEvent) {
+        for (_, emitter) in &self.emitters {
+            emitter.on_room_canonical_alias(room.clone(), event).await;
+        }
+    }
+
+    async fn on_room_aliases(&self, room: SyncRoom, event: &AliasesEvent) {
+        for (_, emitter) in &self.emitters {
+            emitter.on_room_aliases(room.clone(), event).await;
+        }
+    }
+
+    async fn on_room_avatar(&self, room: SyncRoom, event: &AvatarEvent) {
+        for (_, emitter) in &self.emitters {
+            emitter.on_room_avatar(room.clone(), event).await;
+        }
+    }
+
+    async fn on_room_topic(&self, room: SyncRoom, event: &TopicEvent) {
+        for (_, emitter) in &self.emitters {
+            emitter.on_room_topic(room.clone(), event).await;
+        }
+    }
+
+    async fn on_room_guest_access(&self, room: SyncRoom, event: &GuestAccessEvent) {
+        for (_, emitter) in &self.emitters {
+            emitter.on_room_guest_access(room.clone(), event).await;
+        }
+    }
+
+    async fn on_room_history_visibility(&self, room: SyncRoom, event: &HistoryVisibilityEvent) {
+        for (_, emitter) in &self.emitters {
+            emitter
+                .on_room_history_visibility(room.clone(), event)
+                .await;
+        }
+    }
+
+    async fn on_room_server_acl(&self, room: SyncRoom, event: &ServerAclEvent) {
+        for (_, emitter) in &self.emitters {
+            emitter.on_room_server_acl(room.clone(), event).await;
+        }
+    }
+
+    async fn on_room_message(&self, room: SyncRoom, event: &MessageEvent) {
+        for (_, emitter) in &self.emitters {
+            emitter.on_room_message(room.clone(), event).await;
+        }
+    }
+
+    async fn on_room_message_verdict(
+        &self,
+        room: SyncRoom,
+        event: &MessageEvent,
+        verdict: &SpamVerdict,
+    ) {
+        for (_, emitter) in &self.emitters {
+            emitter
+                .on_room_message_verdict(room.clone(), event, verdict)
+                .await;
+        }
+    }
+
+    async fn on_room_message_feedback(&self, room: SyncRoom, event: &FeedbackEvent) {
+        for (_, emitter) in &self.emitters {
+            emitter.on_room_message_feedback(room.clone(), event).await;
+        }
+    }
+
+    async fn on_room_redaction(&self, room: SyncRoom, event: &RedactionEvent) {
+        for (_, emitter) in &self.emitters {
+            emitter.on_room_redaction(room.clone(), event).await;
+        }
+    }
+
+    async fn on_room_power_levels(&self, room: SyncRoom, event: &PowerLevelsEvent) {
+        for (_, emitter) in &self.emitters {
+            emitter.on_room_power_levels(room.clone(), event).await;
+        }
+    }
+
+    async fn on_room_tombstone(&self, room: SyncRoom, event: &TombstoneEvent) {
+        for (_, emitter) in &self.emitters {
+            emitter.on_room_tombstone(room.clone(), event).await;
+        }
+    }
+
+    async fn on_custom_event(&self, room: SyncRoom, event: &CustomRoomEvent) {
+        for (_, emitter) in &self.emitters {
+            emitter.on_custom_event(room.clone(), event).await;
+        }
+    }
+
+    async fn on_room_local_echo(&self, room: SyncRoom, event: &PendingEvent) {
+        for (_, emitter) in &self.emitters {
+            emitter.on_room_local_echo(room.clone(), event).await;
+        }
+    }
+
+    async fn on_room_url_preview(&self, room: SyncRoom, event_id: &EventId, preview: &UrlPreview) {
+        for (_, emitter) in &self.emitters {
+            emitter
+                .on_room_url_preview(room.clone(), event_id, preview)
+                .await;
+        }
+    }
+
+    async fn on_room_reaction(&self, room: SyncRoom, reaction: &Reaction) {
+        for (_, emitter) in &self.emitters {
+            emitter.on_room_reaction(room.clone(), reaction).await;
+        }
+    }
+
+    async fn on_state_member(&self, room: SyncRoom, event: &MemberEvent) {
+        for (_, emitter) in &self.emitters {
+            emitter.on_state_member(room.clone(), event).await;
+        }
+    }
+
+    async fn on_room_members_loaded(&self, room: SyncRoom, summary: &MembersSummary) {
+        for (_, emitter) in &self.emitters {
+            emitter.on_room_members_loaded(room.clone(), summary).await;
+        }
+    }
+
+    async fn on_state_name(&self, room: SyncRoom, event: &NameEvent) {
+        for (_, emitter) in &self.emitters {
+            emitter.on_state_name(room.clone(), event).await;
+        }
+    }
+
+    async fn on_state_canonical_alias(&self, room: SyncRoom, event: &CanonicalAliasEvent) {
+        for (_, emitter) in &self.emitters {
+            emitter.on_state_canonical_alias(room.clone(), event).await;
+        }
+    }
+
+    async fn on_state_aliases(&self, room: SyncRoom, event: &AliasesEvent) {
+        for (_, emitter) in &self.emitters {
+            emitter.on_state_aliases(room.clone(), event).await;
+        }
+    }
+
+    async fn on_state_avatar(&self, room: SyncRoom, event: &AvatarEvent) {
+        for (_, emitter) in &self.emitters {
+            emitter.on_state_avatar(room.clone(), event).await;
+        }
+    }
+
+    async fn on_state_topic(&self, room: SyncRoom, event: &TopicEvent) {
+        for (_, emitter) in &self.emitters {
+            emitter.on_state_topic(room.clone(), event).await;
+        }
+    }
+
+    async fn on_state_guest_access(&self, room: SyncRoom, event: &GuestAccessEvent) {
+        for (_, emitter) in &self.emitters {
+            emitter.on_state_guest_access(room.clone(), event).await;
+        }
+    }
+
+    async fn on_state_history_visibility(&self, room: SyncRoom, event: &HistoryVisibilityEvent) {
+        for (_, emitter) in &self.emitters {
+            emitter
+                .on_state_history_visibility(room.clone(), event)
+                .await;
+        }
+    }
+
+    async fn on_state_server_acl(&self, room: SyncRoom, event: &ServerAclEvent) {
+        for (_, emitter) in &self.emitters {
+            emitter.on_state_server_acl(room.clone(), event).await;
+        }
+    }
+
+    async fn on_state_power_levels(&self, room: SyncRoom, event: &PowerLevelsEvent) {
+        for (_, emitter) in &self.emitters {
+            emitter.on_state_power_levels(room.clone(), event).await;
+        }
+    }
+
+    async fn on_state_join_rules(&self, room: SyncRoom, event: &JoinRulesEvent) {
+        for (_, emitter) in &self.emitters {
+            emitter.on_state_join_rules(room.clone(), event).await;
+        }
+    }
+
+    async fn on_custom_state_event(&self, room: SyncRoom, event: &CustomStateEvent) {
+        for (_, emitter) in &self.emitters {
+            emitter.on_custom_state_event(room.clone(), event).await;
+        }
+    }
+
+    async fn on_stripped_state_member(&self, room: SyncRoom, event: &StrippedRoomMember) {
+        for (_, emitter) in &self.emitters {
+            emitter.on_stripped_state_member(room.clone(), event).await;
+        }
+    }
+
+    async fn on_stripped_state_name(&self, room: SyncRoom, event: &StrippedRoomName) {
+        for (_, emitter) in &self.emitters {
+            emitter.on_stripped_state_name(room.clone(), event).await;
+        }
+    }
+
+    async fn on_stripped_state_canonical_alias(
+        &self,
+        room: SyncRoom,
+        event: &StrippedRoomCanonicalAlias,
+    ) {
+        for (_, emitter) in &self.emitters {
+            emitter
+                .on_stripped_state_canonical_alias(room.clone(), event)
+                .await;
+        }
+    }
+
+    async fn on_stripped_state_aliases(&self, room: SyncRoom, event: &StrippedRoomAliases) {
+        for (_, emitter) in &self.emitters {
+            emitter.on_stripped_state_aliases(room.clone(), event).await;
+        }
+    }
+
+    async fn on_stripped_state_avatar(&self, room: SyncRoom, event: &StrippedRoomAvatar) {
+        for (_, emitter) in &self.emitters {
+            emitter.on_stripped_state_avatar(room.clone(), event).await;
+        }
+    }
+
+    async fn on_stripped_state_topic(&self, room: SyncRoom, event: &StrippedRoomTopic) {
+        for (_, emitter) in &self.emitters {
+            emitter.on_stripped_state_topic(room.clone(), event).await;
+        }
+    }
+
+    async fn on_stripped_state_guest_access(
+        &self,
+        room: SyncRoom,
+        event: &StrippedRoomGuestAccess,
+    ) {
+        for (_, emitter) in &self.emitters {
+            emitter
+                .on_stripped_state_guest_access(room.clone(), event)
+                .await;
+        }
+    }
+
+    async fn on_stripped_state_history_visibility(
+        &self,
+        room: SyncRoom,
+        event: &StrippedRoomHistoryVisibility,
+    ) {
+        for (_, emitter) in &self.emitters {
+            emitter
+                .on_stripped_state_history_visibility(room.clone(), event)
+                .await;
+        }
+    }
+
+    async fn on_stripped_state_power_levels(
+        &self,
+        room: SyncRoom,
+        event: &StrippedRoomPowerLevels,
+    ) {
+        for (_, emitter) in &self.emitters {
+            emitter
+                .on_stripped_state_power_levels(room.clone(), event)
+                .await;
+        }
+    }
+
+    async fn on_stripped_state_join_rules(&self, room: SyncRoom, event: &StrippedRoomJoinRules) {
+        for (_, emitter) in &self.emitters {
+            emitter
+                .on_stripped_state_join_rules(room.clone(), event)
+                .await;
+        }
+    }
+
+    async fn on_account_presence(&self, room: SyncRoom, event: &PresenceEvent) {
+        for (_, emitter) in &self.emitters {
+            emitter.on_account_presence(room.clone(), event).await;
+        }
+    }
+
+    async fn on_account_ignored_users(&self, room: SyncRoom, event: &IgnoredUserListEvent) {
+        for (_, emitter) in &self.emitters {
+            emitter.on_account_ignored_users(room.clone(), event).await;
+        }
+    }
+
+    async fn on_account_push_rules(&self, room: SyncRoom, event: &PushRulesEvent) {
+        for (_, emitter) in &self.emitters {
+            emitter.on_account_push_rules(room.clone(), event).await;
+        }
+    }
+
+    async fn on_account_data_fully_read(&self, room: SyncRoom, event: &FullyReadEvent) {
+        for (_, emitter) in &self.emitters {
+            emitter
+                .on_account_data_fully_read(room.clone(), event)
+                .await;
+        }
+    }
+
+    async fn on_account_data_typing(&self, room: SyncRoom, event: &TypingEvent) {
+        for (_, emitter) in &self.emitters {
+            emitter.on_account_data_typing(room.clone(), event).await;
+        }
+    }
+
+    async fn on_account_data_receipt(&self, room: SyncRoom, event: &ReceiptEvent) {
+        for (_, emitter) in &self.emitters {
+            emitter.on_account_data_receipt(room.clone(), event).await;
+        }
+    }
+
+    async fn on_receipt(&self, room: SyncRoom, receipt: &ReadReceipt) {
+        for (_, emitter) in &self.emitters {
+            emitter.on_receipt(room.clone(), receipt).await;
+        }
+    }
+
+    async fn on_notification(&self, room: SyncRoom, event: &RoomEvent, actions: &PushActions) {
+        for (_, emitter) in &self.emitters {
+            emitter.on_notification(room.clone(), event, actions).await;
+        }
+    }
+
+    async fn on_timeline_event(&self, room: SyncRoom, event: &RoomEvent, origin: EventOrigin) {
+        for (_, emitter) in &self.emitters {
+            emitter.on_timeline_event(room.clone(), event, origin).await;
+        }
+    }
+
+    async fn on_raw_timeline_event(&self, room: SyncRoom, event: &RoomEvent, raw: &RawJsonValue) {
+        for (_, emitter) in &self.emitters {
+            emitter
+                .on_raw_timeline_event(room.clone(), event, raw)
+                .await;
+        }
+    }
+
+    async fn on_raw_state_event(&self, room: SyncRoom, event: &StateEvent, raw: &RawJsonValue) {
+        for (_, emitter) in &self.emitters {
+            emitter.on_raw_state_event(room.clone(), event, raw).await;
+        }
+    }
+
+    async fn on_presence_event(&self, room: SyncRoom, event: &PresenceEvent) {
+        for (_, emitter) in &self.emitters {
+            emitter.on_presence_event(room.clone(), event).await;
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -288,10 +926,16 @@ mod test {
         async fn on_presence_event(&self, _: SyncRoom, _: &PresenceEvent) {
             self.0.lock().await.push("presence event".to_string())
         }
+        async fn on_room_members_loaded(&self, _: SyncRoom, summary: &MembersSummary) {
+            self.0.lock().await.push(format!(
+                "members loaded {} {} {}",
+                summary.total, summary.joined, summary.left
+            ))
+        }
     }
 
-    use crate::identifiers::UserId;
-    use crate::{BaseClient, Session};
+    use crate::identifiers::{RoomId, UserId};
+    use crate::{BaseClient, BaseClientConfig, Session};
 
     use std::convert::TryFrom;
 
@@ -381,4 +1025,311 @@ mod test {
             ],
         )
     }
+
+    #[async_test]
+    async fn event_emitter_member_batch() {
+        let vec = Arc::new(Mutex::new(Vec::new()));
+        let test_vec = Arc::clone(&vec);
+        let emitter = Box::new(EvEmitterTest(vec));
+
+        let session = Session {
+            access_token: "1234".to_owned(),
+            user_id: UserId::try_from("@example:example.com").unwrap(),
+            device_id: "DEVICEID".to_owned(),
+        };
+        let config = BaseClientConfig::new().member_batch_threshold(2);
+        let client = BaseClient::new_with_config(Some(session), config).unwrap();
+        client.add_event_emitter(emitter).await;
+
+        let mut response = sync_response(SyncResponseFile::Default);
+        client.receive_sync_response(&mut response).await.unwrap();
+
+        let v = test_vec.lock().await;
+        assert_eq!(
+            v.as_slice(),
+            [
+                "state rules",
+                "state aliases",
+                "state power",
+                "state canonical",
+                "members loaded 3 2 1",
+                "message",
+                "account read",
+                "account ignore",
+                "presence event"
+            ],
+        );
+
+        let room_id = RoomId::try_from("!SVkFJHzfwvuaIEawgC:localhost").unwrap();
+        let room = client.get_joined_room(&room_id).await.unwrap();
+        assert_eq!(room.read().await.members.len(), 2);
+    }
+
+    #[derive(Clone)]
+    pub struct ReceiptCounter(Arc<Mutex<usize>>);
+
+    #[async_trait::async_trait]
+    impl EventEmitter for ReceiptCounter {
+        async fn on_account_data_receipt(&self, _: SyncRoom, _: &ReceiptEvent) {
+            *self.0.lock().await += 1;
+        }
+    }
+
+    #[derive(Clone)]
+    pub struct ReadReceiptCollector(Arc<Mutex<Vec<ReadReceipt>>>);
+
+    #[async_trait::async_trait]
+    impl EventEmitter for ReadReceiptCollector {
+        async fn on_receipt(&self, _: SyncRoom, receipt: &ReadReceipt) {
+            self.0.lock().await.push(receipt.clone());
+        }
+    }
+
+    #[async_test]
+    async fn event_emitter_read_receipts() {
+        let receipts = Arc::new(Mutex::new(Vec::new()));
+        let client = get_client();
+        client
+            .add_event_emitter(Box::new(ReadReceiptCollector(Arc::clone(&receipts))))
+            .await;
+
+        let mut response = sync_response(SyncResponseFile::Default);
+        client.receive_sync_response(&mut response).await.unwrap();
+
+        let receipts = receipts.lock().await;
+        assert_eq!(receipts.len(), 1);
+        assert_eq!(receipts[0].user_id.to_string(), "@example:localhost");
+
+        let room_id = RoomId::try_from("!SVkFJHzfwvuaIEawgC:localhost").unwrap();
+        let room = client.get_joined_room(&room_id).await.unwrap();
+        let room = room.read().await;
+        assert_eq!(
+            room.read_receipts(&receipts[0].event_id),
+            &[receipts[0].user_id.clone()]
+        );
+    }
+
+    #[async_test]
+    async fn event_emitter_ignored_receipts() {
+        let session = Session {
+            access_token: "1234".to_owned(),
+            user_id: UserId::try_from("@example:example.com").unwrap(),
+            device_id: "DEVICEID".to_owned(),
+        };
+
+        let count = Arc::new(Mutex::new(0));
+        let client = get_client();
+        client
+            .add_event_emitter(Box::new(ReceiptCounter(Arc::clone(&count))))
+            .await;
+
+        let mut response = sync_response(SyncResponseFile::Default);
+        client.receive_sync_response(&mut response).await.unwrap();
+        assert_eq!(*count.lock().await, 1);
+
+        let ignored_count = Arc::new(Mutex::new(0));
+        let config = BaseClientConfig::new()
+            .ignore_typing_events()
+            .ignore_receipt_events();
+        let client = BaseClient::new_with_config(Some(session), config).unwrap();
+        client
+            .add_event_emitter(Box::new(ReceiptCounter(Arc::clone(&ignored_count))))
+            .await;
+
+        let mut response = sync_response(SyncResponseFile::Default);
+        client.receive_sync_response(&mut response).await.unwrap();
+        assert_eq!(*ignored_count.lock().await, 0);
+    }
+
+    #[async_test]
+    async fn event_emitter_multiple_and_removal() {
+        let first = Arc::new(Mutex::new(0));
+        let second = Arc::new(Mutex::new(0));
+        let client = get_client();
+        let handle = client
+            .add_event_emitter(Box::new(ReceiptCounter(Arc::clone(&first))))
+            .await;
+        client
+            .add_event_emitter(Box::new(ReceiptCounter(Arc::clone(&second))))
+            .await;
+
+        let mut response = sync_response(SyncResponseFile::Default);
+        client.receive_sync_response(&mut response).await.unwrap();
+        assert_eq!(*first.lock().await, 1);
+        assert_eq!(*second.lock().await, 1);
+
+        assert!(client.remove_event_emitter(handle).await);
+        assert!(!client.remove_event_emitter(handle).await);
+
+        let mut response = sync_response(SyncResponseFile::Default);
+        response.next_batch = "s526_47314_0_7_1_1_1_11444_2".to_owned();
+        client.receive_sync_response(&mut response).await.unwrap();
+        assert_eq!(*first.lock().await, 1);
+        assert_eq!(*second.lock().await, 2);
+    }
+
+    #[derive(Clone)]
+    pub struct CustomEventCollector(Arc<Mutex<Vec<String>>>);
+
+    #[async_trait::async_trait]
+    impl EventEmitter for CustomEventCollector {
+        async fn on_custom_event(&self, _: SyncRoom, event: &CustomRoomEvent) {
+            self.0.lock().await.push(event.event_type.clone());
+        }
+        async fn on_custom_state_event(&self, _: SyncRoom, event: &CustomStateEvent) {
+            self.0.lock().await.push(event.event_type.clone());
+        }
+    }
+
+    #[async_test]
+    async fn event_emitter_custom_events() {
+        let room_id = RoomId::try_from("!SVkFJHzfwvuaIEawgC:localhost").unwrap();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let client = get_client();
+        client
+            .add_event_emitter(Box::new(CustomEventCollector(Arc::clone(&events))))
+            .await;
+
+        let mut response = sync_response(SyncResponseFile::Default);
+        let room = response.rooms.join.get_mut(&room_id).unwrap();
+        room.timeline.events.push(
+            serde_json::from_value(serde_json::json!({
+                "content": { "body": "ping" },
+                "event_id": "$custom:localhost",
+                "origin_server_ts": 1_520_372_800_469u64,
+                "sender": "@example:localhost",
+                "type": "org.example.ping"
+            }))
+            .unwrap(),
+        );
+        room.state.events.push(
+            serde_json::from_value(serde_json::json!({
+                "content": { "enabled": true },
+                "event_id": "$custom_state:localhost",
+                "origin_server_ts": 1_520_372_800_469u64,
+                "sender": "@example:localhost",
+                "state_key": "",
+                "type": "org.example.settings"
+            }))
+            .unwrap(),
+        );
+
+        client.receive_sync_response(&mut response).await.unwrap();
+
+        let events = events.lock().await;
+        assert!(events.contains(&"org.example.ping".to_owned()));
+        assert!(events.contains(&"org.example.settings".to_owned()));
+    }
+
+    #[derive(Clone)]
+    pub struct TopicCollector(Arc<Mutex<Vec<String>>>);
+
+    #[async_trait::async_trait]
+    impl EventEmitter for TopicCollector {
+        async fn on_state_topic(&self, _: SyncRoom, event: &TopicEvent) {
+            self.0.lock().await.push(event.content.topic.clone());
+        }
+    }
+
+    #[async_test]
+    async fn event_emitter_topic() {
+        let room_id = RoomId::try_from("!SVkFJHzfwvuaIEawgC:localhost").unwrap();
+        let topics = Arc::new(Mutex::new(Vec::new()));
+        let client = get_client();
+        client
+            .add_event_emitter(Box::new(TopicCollector(Arc::clone(&topics))))
+            .await;
+
+        let mut response = sync_response(SyncResponseFile::Default);
+        client.receive_sync_response(&mut response).await.unwrap();
+
+        assert_eq!(*topics.lock().await, vec!["\u{1f600}".to_owned()]);
+
+        let room = client.get_joined_room(&room_id).await.unwrap();
+        assert_eq!(room.read().await.topic(), Some("\u{1f600}"));
+    }
+
+    #[derive(Clone)]
+    pub struct RawEventCollector(Arc<Mutex<Vec<serde_json::Value>>>);
+
+    #[async_trait::async_trait]
+    impl EventEmitter for RawEventCollector {
+        async fn on_raw_timeline_event(&self, _: SyncRoom, _: &RoomEvent, raw: &RawJsonValue) {
+            self.0
+                .lock()
+                .await
+                .push(serde_json::from_str(raw.get()).unwrap());
+        }
+    }
+
+    #[async_test]
+    async fn event_emitter_raw_events() {
+        let room_id = RoomId::try_from("!SVkFJHzfwvuaIEawgC:localhost").unwrap();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let client = get_client();
+        client
+            .add_event_emitter(Box::new(RawEventCollector(Arc::clone(&events))))
+            .await;
+
+        let mut response = sync_response(SyncResponseFile::Default);
+        let room = response.rooms.join.get_mut(&room_id).unwrap();
+        room.timeline.events.push(
+            serde_json::from_value(serde_json::json!({
+                "content": { "body": "hello", "msgtype": "m.text", "org.example.flag": true },
+                "event_id": "$raw:localhost",
+                "origin_server_ts": 1_520_372_800_469u64,
+                "sender": "@example:localhost",
+                "type": "m.room.message",
+                "unsigned": { "org.example.unsigned": "kept" }
+            }))
+            .unwrap(),
+        );
+
+        client.receive_sync_response(&mut response).await.unwrap();
+
+        let events = events.lock().await;
+        let raw = events
+            .iter()
+            .find(|e| e["event_id"] == "$raw:localhost")
+            .unwrap();
+        assert_eq!(raw["content"]["org.example.flag"], true);
+        assert_eq!(raw["unsigned"]["org.example.unsigned"], "kept");
+    }
+
+    #[derive(Clone)]
+    pub struct ContextCollector(Arc<Mutex<Vec<RoomContext>>>);
+
+    #[async_trait::async_trait]
+    impl EventEmitter for ContextCollector {
+        async fn on_room_message(&self, room: SyncRoom, _: &MessageEvent) {
+            self.0.lock().await.push(room.context().clone());
+        }
+    }
+
+    #[async_test]
+    async fn event_emitter_room_context() {
+        let contexts = Arc::new(Mutex::new(Vec::new()));
+        let client = get_client();
+        client
+            .add_event_emitter(Box::new(ContextCollector(Arc::clone(&contexts))))
+            .await;
+
+        let mut response = sync_response(SyncResponseFile::Default);
+        client.receive_sync_response(&mut response).await.unwrap();
+
+        let room_id = RoomId::try_from("!SVkFJHzfwvuaIEawgC:localhost").unwrap();
+        let room = client.get_joined_room(&room_id).await.unwrap();
+        let room = room.read().await;
+
+        let contexts = contexts.lock().await;
+        assert_eq!(
+            contexts.as_slice(),
+            [RoomContext {
+                room_id: room_id.clone(),
+                display_name: room.display_name(),
+                membership: RoomStateType::Joined,
+                encrypted: room.encrypted,
+            }]
+        );
+    }
 }
@@ -0,0 +1,118 @@
+// Copyright 2020 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use serde_json::Value as JsonValue;
+
+use crate::event_emitter::SyncRoom;
+use crate::events::room::{
+    aliases::AliasesEvent, avatar::AvatarEvent, canonical_alias::CanonicalAliasEvent,
+    encryption::EncryptionEvent, join_rules::JoinRulesEvent, member::MemberEvent,
+    message::MessageEvent, name::NameEvent, power_levels::PowerLevelsEvent,
+    redaction::RedactionEvent, tombstone::TombstoneEvent, topic::TopicEvent,
+};
+use crate::events::EventJson;
+
+/// An event that typed event handlers can be registered for, see
+/// `BaseClient::register_event_handler()`.
+///
+/// The trait is implemented for the room events of the SDK, applications can
+/// implement it for their own event types.
+///
+/// # Examples
+/// ```
+/// use matrix_sdk_base::SyncEvent;
+/// use serde::Deserialize;
+/// use serde_json::Value as JsonValue;
+///
+/// #[derive(Deserialize)]
+/// struct PollEvent {
+///     sender: String,
+///     content: JsonValue,
+/// }
+///
+/// impl SyncEvent for PollEvent {
+///     const EVENT_TYPE: &'static str = "org.example.poll";
+///
+///     fn from_json(json: &JsonValue) -> Option<Self> {
+///         serde_json::from_value(json.clone()).ok()
+///     }
+/// }
+/// ```
+pub trait SyncEvent: Sized + Send + 'static {
+    /// The type of the event, e.g. `m.room.message`.
+    const EVENT_TYPE: &'static str;
+
+    /// Parse the event from its JSON form.
+    ///
+    /// Returns `None` if the JSON isn't a valid event of this type, the
+    /// handlers of the event aren't called in that case.
+    fn from_json(json: &JsonValue) -> Option<Self>;
+}
+
+macro_rules! impl_sync_event {
+    ($($event:ty => $event_type:literal),* $(,)?) => {
+        $(
+            impl SyncEvent for $event {
+                const EVENT_TYPE: &'static str = $event_type;
+
+                fn from_json(json: &JsonValue) -> Option<Self> {
+                    serde_json::from_value::<EventJson<Self>>(json.clone())
+                        .ok()?
+                        .deserialize()
+                        .ok()
+                }
+            }
+        )*
+    };
+}
+
+impl_sync_event! {
+    AliasesEvent => "m.room.aliases",
+    AvatarEvent => "m.room.avatar",
+    CanonicalAliasEvent => "m.room.canonical_alias",
+    EncryptionEvent => "m.room.encryption",
+    JoinRulesEvent => "m.room.join_rules",
+    MemberEvent => "m.room.member",
+    MessageEvent => "m.room.message",
+    NameEvent => "m.room.name",
+    PowerLevelsEvent => "m.room.power_levels",
+    RedactionEvent => "m.room.redaction",
+    TombstoneEvent => "m.room.tombstone",
+    TopicEvent => "m.room.topic",
+}
+
+/// The future a typed event handler returns.
+pub(crate) type EventHandlerFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// A typed event handler that takes the event in its JSON form, it returns
+/// `None` if the event couldn't be parsed.
+pub(crate) type EventHandler =
+    Arc<dyn Fn(SyncRoom, &JsonValue) -> Option<EventHandlerFuture> + Send + Sync>;
+
+/// Wrap a handler of a typed event into an `EventHandler`.
+pub(crate) fn event_handler<Ev, H, Fut>(handler: H) -> EventHandler
+where
+    Ev: SyncEvent,
+    H: Fn(Ev, SyncRoom) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    Arc::new(move |room, json| {
+        let event = Ev::from_json(json)?;
+        Some(Box::pin(handler(event, room)))
+    })
+}
@@ -41,15 +41,48 @@ pub use matrix_sdk_common::*;
 mod client;
 mod error;
 mod event_emitter;
+mod event_handler;
+mod media_cache;
 mod models;
+#[cfg(feature = "local-search")]
+#[cfg_attr(docsrs, doc(cfg(feature = "local-search")))]
+mod search_index;
 mod session;
+mod spam_check;
 mod state;
+#[cfg(not(target_arch = "wasm32"))]
+mod worker;
 
-pub use client::{BaseClient, RoomState, RoomStateType};
-pub use event_emitter::{EventEmitter, SyncRoom};
+pub use client::{BaseClient, BaseClientConfig, RoomState, RoomStateType};
+pub use event_emitter::{
+    EmittedRoom, EmitterHandle, EventEmitter, EventOrigin, MembersSummary, RoomContext, SyncRoom,
+};
+pub use event_handler::SyncEvent;
 #[cfg(feature = "encryption")]
-pub use matrix_sdk_crypto::{Device, TrustState};
-pub use models::Room;
+pub use matrix_sdk_crypto::{
+    decrypt_key_export, encrypt_key_export, AttachmentDecryptor, AttachmentEncryptor,
+    DecryptorError, Device, EncryptionInfo, ExportedRoomKey, GroupSessionInfo, JsonWebKey,
+    KeyExportError, TrustState, VerificationPolicy, DEFAULT_EXPORT_ROUNDS,
+};
+pub use media_cache::MediaCache;
+pub use models::{
+    AllowRule, BundledAnnotation, BundledReference, BundledRelations, BundledReplacement,
+    CustomStateEventContent, GuestAccess, HistoryVisibility, JoinRule, MemberExportFormat,
+    MessageEventContentExt, PendingEvent, PermissionError, PowerAction, PowerLevelChanges,
+    PushActions, Pusher, PusherData, QueuedMessage, Reaction, ReactionGroup, ReadReceipt,
+    RedactedBecause, RedactionContent, RelationChunk, Room, RoomMember, RoomStateSnapshot,
+    SearchContext, SearchOrder, SearchResult, SearchResults, SendState, ServerAcl, ServerNotice,
+    ServerNoticeKind, Space, SpaceChildContent, SpaceChildEvent, SpaceHierarchy,
+    SpaceHierarchyRoom, SpaceParentContent, SpaceTreeNode, StateChange, StrippedMember, Unsigned,
+    UrlPreview, SERVER_NOTICE_TAG,
+};
+#[cfg(feature = "messages")]
+pub use models::{RetentionPolicy, Timeline, TimelineEvent, TimelineItem};
+#[cfg(feature = "local-search")]
+pub use search_index::{IndexedMessage, LocalSearchResult, SearchIndex};
+pub use spam_check::{SpamChecker, SpamVerdict};
 #[cfg(not(target_arch = "wasm32"))]
 pub use state::JsonStore;
 pub use state::StateStore;
+#[cfg(not(target_arch = "wasm32"))]
+pub use worker::WorkerPool;
@@ -0,0 +1,217 @@
+// Copyright 2020 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::{HashMap, VecDeque};
+
+/// A cached media file.
+#[derive(Debug)]
+struct Entry {
+    /// The size of the media in bytes.
+    size: usize,
+    /// The media itself, `None` if the media only lives in the state store.
+    data: Option<Vec<u8>>,
+}
+
+/// An in-memory cache of downloaded media and thumbnails with a least
+/// recently used eviction policy.
+///
+/// Media is keyed by its `mxc://` URI, thumbnails use the key returned by
+/// `MediaCache::thumbnail_key()`. The cache only keeps track of the media,
+/// the `BaseClient` is responsible for mirroring the changes into the
+/// `StateStore`.
+#[derive(Debug)]
+pub struct MediaCache {
+    max_size: usize,
+    size: usize,
+    entries: HashMap<String, Entry>,
+    /// The keys of the entries, the least recently used one first.
+    order: VecDeque<String>,
+}
+
+impl MediaCache {
+    /// Create a new empty `MediaCache`.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_size` - The maximal number of bytes the cached media may take
+    /// up.
+    pub fn new(max_size: usize) -> Self {
+        Self {
+            max_size,
+            size: 0,
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    /// The key that is used to cache a thumbnail of some media.
+    ///
+    /// # Arguments
+    ///
+    /// * `mxc_uri` - The `mxc://` URI of the media.
+    ///
+    /// * `width` - The width of the thumbnail.
+    ///
+    /// * `height` - The height of the thumbnail.
+    ///
+    /// * `method` - The resizing method of the thumbnail if one was requested.
+    pub fn thumbnail_key(mxc_uri: &str, width: u64, height: u64, method: Option<&str>) -> String {
+        format!(
+            "{}#thumbnail-{}x{}-{}",
+            mxc_uri,
+            width,
+            height,
+            method.unwrap_or("default")
+        )
+    }
+
+    /// The maximal number of bytes the cached media may take up.
+    pub fn max_size(&self) -> usize {
+        self.max_size
+    }
+
+    /// The number of bytes the cached media currently takes up.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Is the given key known to the cache.
+    pub fn contains(&self, key: &str) -> bool {
+        self.entries.contains_key(key)
+    }
+
+    /// Get the media for the given key and mark it as recently used.
+    ///
+    /// Returns `None` if the key isn't cached or if the media only lives in
+    /// the state store.
+    pub fn get(&mut self, key: &str) -> Option<Vec<u8>> {
+        let data = self.entries.get(key)?.data.clone();
+        self.touch(key);
+        data
+    }
+
+    /// Insert some media into the cache.
+    ///
+    /// Returns the keys of the entries that were evicted to make room for
+    /// the new media. Media that is larger than the cache itself isn't
+    /// cached.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key of the media.
+    ///
+    /// * `data` - The media itself.
+    pub fn insert(&mut self, key: &str, data: Vec<u8>) -> Vec<String> {
+        let size = data.len();
+        self.insert_entry(key, size, Some(data))
+    }
+
+    /// Register media that was persisted in the state store without loading
+    /// it into memory.
+    ///
+    /// Returns the keys of the entries that were evicted.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key of the media.
+    ///
+    /// * `size` - The size of the media in bytes.
+    pub fn insert_stored(&mut self, key: &str, size: usize) -> Vec<String> {
+        self.insert_entry(key, size, None)
+    }
+
+    /// Remove the media with the given key from the cache.
+    pub fn remove(&mut self, key: &str) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.size -= entry.size;
+            self.order.retain(|k| k != key);
+        }
+    }
+
+    fn insert_entry(&mut self, key: &str, size: usize, data: Option<Vec<u8>>) -> Vec<String> {
+        self.remove(key);
+
+        if size > self.max_size {
+            return vec![key.to_owned()];
+        }
+
+        let mut evicted = Vec::new();
+
+        while self.size + size > self.max_size {
+            match self.order.pop_front() {
+                Some(k) => {
+                    if let Some(entry) = self.entries.remove(&k) {
+                        self.size -= entry.size;
+                    }
+                    evicted.push(k);
+                }
+                None => break,
+            }
+        }
+
+        self.size += size;
+        self.entries.insert(key.to_owned(), Entry { size, data });
+        self.order.push_back(key.to_owned());
+
+        evicted
+    }
+
+    fn touch(&mut self, key: &str) {
+        if let Some(position) = self.order.iter().position(|k| k == key) {
+            if let Some(k) = self.order.remove(position) {
+                self.order.push_back(k);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn lru_eviction() {
+        let mut cache = MediaCache::new(10);
+
+        assert!(cache.insert("mxc://localhost/a", vec![0; 4]).is_empty());
+        assert!(cache.insert("mxc://localhost/b", vec![1; 4]).is_empty());
+
+        // Using `a` makes `b` the least recently used entry.
+        assert_eq!(cache.get("mxc://localhost/a"), Some(vec![0; 4]));
+
+        let evicted = cache.insert("mxc://localhost/c", vec![2; 4]);
+        assert_eq!(evicted, vec!["mxc://localhost/b".to_owned()]);
+
+        assert!(cache.contains("mxc://localhost/a"));
+        assert!(!cache.contains("mxc://localhost/b"));
+        assert!(cache.contains("mxc://localhost/c"));
+        assert_eq!(cache.size(), 8);
+
+        let evicted = cache.insert("mxc://localhost/huge", vec![3; 11]);
+        assert_eq!(evicted, vec!["mxc://localhost/huge".to_owned()]);
+        assert_eq!(cache.size(), 8);
+    }
+
+    #[test]
+    fn stored_entries() {
+        let mut cache = MediaCache::new(10);
+
+        cache.insert_stored("mxc://localhost/a", 6);
+        assert!(cache.contains("mxc://localhost/a"));
+        assert_eq!(cache.get("mxc://localhost/a"), None);
+
+        let evicted = cache.insert("mxc://localhost/b", vec![0; 6]);
+        assert_eq!(evicted, vec!["mxc://localhost/a".to_owned()]);
+    }
+}
@@ -0,0 +1,40 @@
+// Copyright 2020 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use serde::de::DeserializeOwned;
+
+/// The content of a state event type that isn't known to the SDK, e.g. the
+/// state of a widget or of a bridge.
+///
+/// Implement this for the content of your own state events to read them
+/// with `Room::get_state_event()`.
+///
+/// # Examples
+/// ```
+/// use matrix_sdk_base::CustomStateEventContent;
+/// use serde::Deserialize;
+///
+/// #[derive(Deserialize)]
+/// struct BridgeInfo {
+///     protocol: String,
+/// }
+///
+/// impl CustomStateEventContent for BridgeInfo {
+///     const EVENT_TYPE: &'static str = "m.bridge";
+/// }
+/// ```
+pub trait CustomStateEventContent: DeserializeOwned {
+    /// The type of the state event, e.g. `im.vector.modular.widgets`.
+    const EVENT_TYPE: &'static str;
+}
@@ -1,32 +1,34 @@
 //! De-/serialization functions to and from json strings, allows the type to be used as a query string.
 
+use std::sync::Arc;
+
 use serde::de::{Deserialize, Deserializer, Error as _};
 
 use crate::events::collections::all::Event;
 use crate::events::presence::PresenceEvent;
 use crate::events::EventJson;
 
-pub fn deserialize_events<'de, D>(deserializer: D) -> Result<Vec<Event>, D::Error>
+pub fn deserialize_events<'de, D>(deserializer: D) -> Result<Vec<Arc<Event>>, D::Error>
 where
     D: Deserializer<'de>,
 {
     let mut events = vec![];
     let ev = Vec::<EventJson<Event>>::deserialize(deserializer)?;
     for event in ev {
-        events.push(event.deserialize().map_err(D::Error::custom)?);
+        events.push(Arc::new(event.deserialize().map_err(D::Error::custom)?));
     }
 
     Ok(events)
 }
 
-pub fn deserialize_presence<'de, D>(deserializer: D) -> Result<Vec<PresenceEvent>, D::Error>
+pub fn deserialize_presence<'de, D>(deserializer: D) -> Result<Vec<Arc<PresenceEvent>>, D::Error>
 where
     D: Deserializer<'de>,
 {
     let mut events = vec![];
     let ev = Vec::<EventJson<PresenceEvent>>::deserialize(deserializer)?;
     for event in ev {
-        events.push(event.deserialize().map_err(D::Error::custom)?);
+        events.push(Arc::new(event.deserialize().map_err(D::Error::custom)?));
     }
 
     Ok(events)
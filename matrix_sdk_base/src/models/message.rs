@@ -5,10 +5,13 @@
 
 use std::cmp::Ordering;
 use std::ops::Deref;
+use std::sync::Arc;
+use std::time::SystemTime;
 use std::vec::IntoIter;
 
-use crate::events::room::message::MessageEvent;
+use crate::events::room::message::{MessageEvent, MessageEventContent, TextMessageEventContent};
 use crate::events::EventJson;
+use crate::identifiers::EventId;
 
 use serde::{de, ser, Serialize};
 
@@ -18,8 +21,19 @@ pub struct MessageQueue {
     msgs: Vec<MessageWrapper>,
 }
 
+/// A message of the `MessageQueue`.
+///
+/// The event is reference counted, sharing it with other parts of the
+/// application doesn't copy the event.
 #[derive(Clone, Debug, Serialize)]
-pub struct MessageWrapper(MessageEvent);
+pub struct MessageWrapper(Arc<MessageEvent>);
+
+impl MessageWrapper {
+    /// Get a shared reference to the message event.
+    pub fn event(&self) -> Arc<MessageEvent> {
+        self.0.clone()
+    }
+}
 
 impl Deref for MessageWrapper {
     type Target = MessageEvent;
@@ -70,8 +84,13 @@ impl MessageQueue {
 
     /// Inserts a `MessageEvent` into `MessageQueue`, sorted by by `origin_server_ts`.
     ///
+    /// The event can be given as an `Arc<MessageEvent>` if it's already
+    /// shared, in that case it isn't copied.
+    ///
     /// Removes the oldest element in the queue if there are more than 10 elements.
-    pub fn push(&mut self, msg: MessageEvent) -> bool {
+    pub fn push(&mut self, msg: impl Into<Arc<MessageEvent>>) -> bool {
+        let msg = msg.into();
+
         // only push new messages into the queue
         if let Some(latest) = self.msgs.last() {
             if msg.origin_server_ts < latest.origin_server_ts && self.msgs.len() >= 10 {
@@ -94,7 +113,81 @@ impl MessageQueue {
         true
     }
 
-    pub fn iter(&self) -> impl Iterator<Item = &MessageWrapper> {
+    /// Remove all the messages that were sent before the given point in time.
+    ///
+    /// Returns true if any message was removed.
+    pub fn prune_before(&mut self, before: SystemTime) -> bool {
+        let len = self.msgs.len();
+        self.msgs.retain(|m| m.origin_server_ts >= before);
+        len != self.msgs.len()
+    }
+
+    /// Remove the oldest messages until at most `max` messages are left.
+    ///
+    /// Returns true if any message was removed.
+    pub fn truncate(&mut self, max: usize) -> bool {
+        if self.msgs.len() > max {
+            let excess = self.msgs.len() - max;
+            self.msgs.drain(..excess);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Get the message with the given event id.
+    pub fn get(&self, event_id: &EventId) -> Option<&MessageWrapper> {
+        self.msgs.iter().find(|m| &m.event_id == event_id)
+    }
+
+    /// Replace the content of the message with the given event id, e.g.
+    /// because the message was edited.
+    ///
+    /// Returns true if the message is part of the queue.
+    pub(crate) fn replace_content(
+        &mut self,
+        event_id: &EventId,
+        content: MessageEventContent,
+    ) -> bool {
+        match self.msgs.iter_mut().find(|m| &m.event_id == event_id) {
+            Some(message) => {
+                Arc::make_mut(&mut message.0).content = content;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Blank the content of the message with the given event id because the
+    /// message was redacted.
+    ///
+    /// A message always needs a `msgtype`, the content is replaced with an
+    /// empty text message.
+    ///
+    /// Returns true if the message is part of the queue.
+    pub(crate) fn redact(&mut self, event_id: &EventId) -> bool {
+        self.replace_content(
+            event_id,
+            MessageEventContent::Text(TextMessageEventContent {
+                body: String::new(),
+                format: None,
+                formatted_body: None,
+                relates_to: None,
+            }),
+        )
+    }
+
+    /// The number of messages in the queue.
+    pub fn len(&self) -> usize {
+        self.msgs.len()
+    }
+
+    /// Returns true if the queue holds no messages.
+    pub fn is_empty(&self) -> bool {
+        self.msgs.is_empty()
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &MessageWrapper> {
         self.msgs.iter()
     }
 }
@@ -122,7 +215,7 @@ pub(crate) mod ser_deser {
         let mut msgs = vec![];
         for json in messages {
             let msg = json.deserialize().map_err(D::Error::custom)?;
-            msgs.push(MessageWrapper(msg));
+            msgs.push(MessageWrapper(Arc::new(msg)));
         }
 
         Ok(MessageQueue { msgs })
@@ -213,7 +306,14 @@ mod test {
         //     "encrypted": false,
         //     "unread_highlight": null,
         //     "unread_notifications": null,
-        //     "tombstone": null
+        //     "tombstone": null,
+        //     "retention": null,
+        //     "join_rule": null,
+        //     "avatar_url": null,
+        //     "state_event_ids": {},
+        //     "read_receipt": null,
+        //     "fully_read": null,
+        //     "prev_batch": null
         //   }
         // }"#,
         //             serde_json::to_string_pretty(&joined_rooms).unwrap()
@@ -255,13 +355,55 @@ mod test {
     "encrypted": false,
     "unread_highlight": null,
     "unread_notifications": null,
-    "tombstone": null
+    "tombstone": null,
+    "retention": null,
+    "space": {
+      "children": {},
+      "parents": {}
+    },
+    "custom_state": {},
+    "app_data": {},
+    "account_data": {},
+    "join_rule": null,
+    "avatar_url": null,
+    "topic": null,
+    "guest_access": null,
+    "history_visibility": null,
+    "server_acl": null,
+    "inviter": null,
+    "invite_timestamp": null,
+    "stripped_members": {},
+    "direct_target": null,
+    "state_event_ids": {},
+    "read_receipt": null,
+    "fully_read": null,
+    "prev_batch": null,
+    "tags": {},
+    "server_notice": null,
+    "members_synced": false
   }
 }"#,
             serde_json::to_string_pretty(&joined_rooms).unwrap()
         );
     }
 
+    #[test]
+    fn prune() {
+        let json = std::fs::read_to_string("../test_data/events/message_text.json").unwrap();
+        let event = serde_json::from_str::<EventJson<RoomEvent>>(&json).unwrap();
+
+        let mut msgs = MessageQueue::new();
+        if let Ok(RoomEvent::RoomMessage(msg)) = event.deserialize() {
+            msgs.push(msg);
+        }
+        assert_eq!(msgs.len(), 1);
+
+        assert!(!msgs.truncate(1));
+        assert!(!msgs.prune_before(std::time::UNIX_EPOCH));
+        assert!(msgs.prune_before(SystemTime::now()));
+        assert!(msgs.is_empty());
+    }
+
     #[test]
     fn deserialize() {
         let id = RoomId::try_from("!roomid:example.com").unwrap();
@@ -319,7 +461,10 @@ mod test {
     "encrypted": false,
     "unread_highlight": null,
     "unread_notifications": null,
-    "tombstone": null
+    "tombstone": null,
+    "retention": null,
+    "join_rule": null,
+    "avatar_url": null
   }
 }"#;
         assert_eq!(
@@ -0,0 +1,127 @@
+// Copyright 2020 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use serde_json::json;
+
+use crate::events::room::message::MessageEventContent;
+
+/// The format of the HTML body of a message.
+const HTML_FORMAT: &str = "org.matrix.custom.html";
+
+/// Constructors for the message types that are easy to get wrong when the
+/// content is put together by hand.
+///
+/// Bots should send their output as notices. Clients don't notify their
+/// users about notices and bots don't respond to them, which prevents two
+/// bots from answering each other forever.
+///
+/// # Examples
+/// ```
+/// use matrix_sdk_base::{events::room::message::MessageEventContent, MessageEventContentExt};
+///
+/// let reply = MessageEventContent::notice("The build is green");
+/// let action = MessageEventContent::emote_html("waves", "<em>waves</em>");
+/// ```
+pub trait MessageEventContentExt: Sized {
+    /// Create the content of a plain text `m.notice` message.
+    ///
+    /// # Arguments
+    ///
+    /// * `body` - The text of the notice.
+    fn notice(body: &str) -> Self;
+
+    /// Create the content of a `m.notice` message with an HTML body.
+    ///
+    /// # Arguments
+    ///
+    /// * `body` - The plain text version of the notice, shown by clients
+    /// that don't render HTML.
+    ///
+    /// * `html` - The HTML version of the notice.
+    fn notice_html(body: &str, html: &str) -> Self;
+
+    /// Create the content of a plain text `m.emote` message, the equivalent
+    /// of a `/me` command.
+    ///
+    /// # Arguments
+    ///
+    /// * `body` - The action the sender performs, without the name of the
+    /// sender.
+    fn emote(body: &str) -> Self;
+
+    /// Create the content of a `m.emote` message with an HTML body.
+    ///
+    /// # Arguments
+    ///
+    /// * `body` - The plain text version of the action, shown by clients
+    /// that don't render HTML.
+    ///
+    /// * `html` - The HTML version of the action.
+    fn emote_html(body: &str, html: &str) -> Self;
+}
+
+impl MessageEventContentExt for MessageEventContent {
+    fn notice(body: &str) -> Self {
+        message_content("m.notice", body, None)
+    }
+
+    fn notice_html(body: &str, html: &str) -> Self {
+        message_content("m.notice", body, Some(html))
+    }
+
+    fn emote(body: &str) -> Self {
+        message_content("m.emote", body, None)
+    }
+
+    fn emote_html(body: &str, html: &str) -> Self {
+        message_content("m.emote", body, Some(html))
+    }
+}
+
+fn message_content(msgtype: &str, body: &str, html: Option<&str>) -> MessageEventContent {
+    let mut content = json!({ "msgtype": msgtype, "body": body });
+
+    if let Some(html) = html {
+        content["format"] = json!(HTML_FORMAT);
+        content["formatted_body"] = json!(html);
+    }
+
+    serde_json::from_value(content).expect("Notice and emote contents are always valid")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn message_content_constructors() {
+        let notice = serde_json::to_value(MessageEventContent::notice("hello")).unwrap();
+        assert_eq!(notice, json!({ "msgtype": "m.notice", "body": "hello" }));
+        assert!(matches!(
+            MessageEventContent::notice_html("hello", "<b>hello</b>"),
+            MessageEventContent::Notice(_)
+        ));
+
+        let emote =
+            serde_json::to_value(MessageEventContent::emote_html("waves", "<em>waves</em>"))
+                .unwrap();
+        assert_eq!(emote["msgtype"], "m.emote");
+        assert_eq!(emote["format"], HTML_FORMAT);
+        assert_eq!(emote["formatted_body"], "<em>waves</em>");
+        assert!(matches!(
+            MessageEventContent::emote("waves"),
+            MessageEventContent::Emote(c) if c.body == "waves"
+        ));
+    }
+}
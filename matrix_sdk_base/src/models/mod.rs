@@ -1,9 +1,57 @@
+mod custom_state;
 mod event_deser;
 #[cfg(feature = "messages")]
 #[cfg_attr(docsrs, doc(cfg(feature = "messages")))]
 mod message;
+mod message_content;
+mod pending_event;
+mod power_levels;
+mod push_rules;
+mod pusher;
+mod queued_message;
+mod reaction;
+mod receipt;
 mod room;
 mod room_member;
+mod search;
+mod server_acl;
+mod server_notice;
+mod space;
+mod state_diff;
+#[cfg(feature = "messages")]
+#[cfg_attr(docsrs, doc(cfg(feature = "messages")))]
+mod timeline;
+mod unsigned;
+mod url_preview;
 
-pub use room::{Room, RoomName};
-pub use room_member::RoomMember;
+pub use custom_state::CustomStateEventContent;
+pub use message_content::MessageEventContentExt;
+pub use pending_event::PendingEvent;
+pub use power_levels::{PermissionError, PowerAction, PowerLevelChanges};
+pub use push_rules::PushActions;
+pub(crate) use push_rules::{keywords, set_keyword_rule};
+pub use pusher::{Pusher, PusherData};
+pub use queued_message::{QueuedMessage, SendState};
+pub use reaction::{Reaction, ReactionGroup};
+pub use receipt::ReadReceipt;
+#[cfg(feature = "messages")]
+pub use room::RetentionPolicy;
+pub use room::{
+    AllowRule, GuestAccess, HistoryVisibility, JoinRule, Room, RoomName, StrippedMember,
+};
+pub use room_member::{MemberExportFormat, RoomMember};
+pub use search::{SearchContext, SearchOrder, SearchResult, SearchResults};
+pub use server_acl::ServerAcl;
+pub use server_notice::{ServerNotice, ServerNoticeKind, SERVER_NOTICE_TAG};
+pub use space::{
+    Space, SpaceChildContent, SpaceChildEvent, SpaceHierarchy, SpaceHierarchyRoom,
+    SpaceParentContent, SpaceTreeNode,
+};
+pub use state_diff::{RoomStateSnapshot, StateChange};
+#[cfg(feature = "messages")]
+pub use timeline::{Timeline, TimelineEvent, TimelineItem};
+pub use unsigned::{
+    BundledAnnotation, BundledReference, BundledRelations, BundledReplacement, RedactedBecause,
+    RedactionContent, RelationChunk, Unsigned,
+};
+pub use url_preview::UrlPreview;
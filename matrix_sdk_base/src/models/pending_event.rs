@@ -0,0 +1,46 @@
+// Copyright 2020 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::time::SystemTime;
+
+use crate::events::room::message::MessageEventContent;
+use crate::identifiers::UserId;
+
+use super::SendState;
+
+/// The local echo of a message that our own user sent but that didn't come
+/// down the sync stream yet.
+///
+/// Pending events are kept in the `Room` until the server echoes the real
+/// event back, they are matched up using the transaction id or, once the
+/// server responded to the send request, the event id.
+#[derive(Clone, Debug)]
+pub struct PendingEvent {
+    /// The transaction id the message is sent with.
+    pub txn_id: String,
+    /// The sender of the message, our own user.
+    pub sender: UserId,
+    /// The content of the message.
+    pub content: MessageEventContent,
+    /// The point in time the message was sent at.
+    pub created: SystemTime,
+    /// How far the message got on its way to the server.
+    pub state: SendState,
+}
+
+impl PartialEq for PendingEvent {
+    fn eq(&self, other: &PendingEvent) -> bool {
+        self.txn_id == other.txn_id && self.state == other.state
+    }
+}
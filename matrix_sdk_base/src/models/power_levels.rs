@@ -0,0 +1,256 @@
+// Copyright 2020 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde_json::{json, Value as JsonValue};
+use thiserror::Error;
+
+use crate::events::EventType;
+use crate::identifiers::UserId;
+use crate::js_int::Int;
+
+/// An action in a room that requires a certain power level.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PowerAction {
+    /// Ban or unban users.
+    Ban,
+    /// Kick users out of the room.
+    Kick,
+    /// Invite users into the room.
+    Invite,
+    /// Redact events sent by other users.
+    Redact,
+    /// Send a message event of the given type.
+    SendMessage(EventType),
+    /// Send a state event of the given type.
+    SendState(EventType),
+    /// Notify the whole room with an `@room` mention.
+    NotifyRoom,
+}
+
+impl fmt::Display for PowerAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PowerAction::Ban => write!(f, "ban users"),
+            PowerAction::Kick => write!(f, "kick users"),
+            PowerAction::Invite => write!(f, "invite users"),
+            PowerAction::Redact => write!(f, "redact the events of other users"),
+            PowerAction::SendMessage(event_type) => write!(f, "send {} events", event_type),
+            PowerAction::SendState(event_type) => write!(f, "send {} state events", event_type),
+            PowerAction::NotifyRoom => write!(f, "notify the whole room"),
+        }
+    }
+}
+
+/// The reason our own user isn't allowed to perform an action in a room,
+/// according to the local state of the room.
+#[derive(Clone, Debug, Error, PartialEq)]
+pub enum PermissionError {
+    /// Our own user isn't joined to the room.
+    #[error("you aren't joined to the room")]
+    NotJoined,
+
+    /// Our own power level is too low for the action.
+    #[error("you need power level {required} to {action} in this room, but you have {own}")]
+    InsufficientPowerLevel {
+        /// The action that was attempted.
+        action: PowerAction,
+        /// The power level the action requires.
+        required: Int,
+        /// The power level of our own user.
+        own: Int,
+    },
+}
+
+/// Changes to the `m.room.power_levels` state event of a room.
+///
+/// The changes are applied on top of the current content of the event, the
+/// levels that aren't changed are kept.
+///
+/// # Examples
+/// ```
+/// # use std::convert::TryFrom;
+/// use matrix_sdk_base::{events::EventType, identifiers::UserId, js_int::Int, PowerLevelChanges};
+///
+/// let changes = PowerLevelChanges::new()
+///     .user(UserId::try_from("@moderator:example.org").unwrap(), Int::from(50))
+///     .event(EventType::RoomName, Int::from(100))
+///     .invite(Int::from(50));
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PowerLevelChanges {
+    users: BTreeMap<UserId, Option<Int>>,
+    events: BTreeMap<String, Int>,
+    levels: BTreeMap<&'static str, Int>,
+}
+
+impl PowerLevelChanges {
+    /// Create an empty set of changes.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the power level of a user.
+    pub fn user(mut self, user_id: UserId, level: Int) -> Self {
+        self.users.insert(user_id, Some(level));
+        self
+    }
+
+    /// Remove the power level of a user, so the user gets the
+    /// `users_default` level.
+    pub fn reset_user(mut self, user_id: UserId) -> Self {
+        self.users.insert(user_id, None);
+        self
+    }
+
+    /// Set the level that is required to send events of the given type.
+    pub fn event(mut self, event_type: EventType, level: Int) -> Self {
+        self.events.insert(event_type.to_string(), level);
+        self
+    }
+
+    /// Set the level that is required to ban users.
+    pub fn ban(self, level: Int) -> Self {
+        self.level("ban", level)
+    }
+
+    /// Set the level that is required to kick users.
+    pub fn kick(self, level: Int) -> Self {
+        self.level("kick", level)
+    }
+
+    /// Set the level that is required to invite users.
+    pub fn invite(self, level: Int) -> Self {
+        self.level("invite", level)
+    }
+
+    /// Set the level that is required to redact events of other users.
+    pub fn redact(self, level: Int) -> Self {
+        self.level("redact", level)
+    }
+
+    /// Set the level that is required to send message events that have no
+    /// level of their own.
+    pub fn events_default(self, level: Int) -> Self {
+        self.level("events_default", level)
+    }
+
+    /// Set the level that is required to send state events that have no
+    /// level of their own.
+    pub fn state_default(self, level: Int) -> Self {
+        self.level("state_default", level)
+    }
+
+    /// Set the level of the users that have no level of their own.
+    pub fn users_default(self, level: Int) -> Self {
+        self.level("users_default", level)
+    }
+
+    fn level(mut self, key: &'static str, level: Int) -> Self {
+        self.levels.insert(key, level);
+        self
+    }
+
+    /// Are there no changes.
+    pub fn is_empty(&self) -> bool {
+        self.users.is_empty() && self.events.is_empty() && self.levels.is_empty()
+    }
+
+    /// Apply the changes to the content of a `m.room.power_levels` event.
+    ///
+    /// # Arguments
+    ///
+    /// * `content` - The current content of the event, any other value than
+    /// a JSON object is replaced.
+    pub fn apply(&self, mut content: JsonValue) -> JsonValue {
+        if !content.is_object() {
+            content = JsonValue::Object(Default::default());
+        }
+
+        for (key, level) in &self.levels {
+            content[*key] = json!(level);
+        }
+
+        if !self.events.is_empty() {
+            if !content["events"].is_object() {
+                content["events"] = json!({});
+            }
+
+            for (event_type, level) in &self.events {
+                content["events"][event_type] = json!(level);
+            }
+        }
+
+        if !self.users.is_empty() {
+            if !content["users"].is_object() {
+                content["users"] = json!({});
+            }
+
+            let users = content["users"]
+                .as_object_mut()
+                .expect("The users are always an object");
+
+            for (user_id, level) in &self.users {
+                match level {
+                    Some(level) => {
+                        users.insert(user_id.to_string(), json!(level));
+                    }
+                    None => {
+                        users.remove(&user_id.to_string());
+                    }
+                }
+            }
+        }
+
+        content
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::convert::TryFrom;
+
+    #[test]
+    fn apply_power_level_changes() {
+        let alice = UserId::try_from("@alice:example.org").unwrap();
+        let bob = UserId::try_from("@bob:example.org").unwrap();
+
+        let current = json!({
+            "ban": 50,
+            "events": { "m.room.name": 50 },
+            "users": { "@alice:example.org": 100, "@bob:example.org": 50 },
+        });
+
+        let changes = PowerLevelChanges::new()
+            .user(alice, Int::from(50))
+            .reset_user(bob)
+            .event(EventType::RoomTopic, Int::from(100))
+            .kick(Int::from(75));
+
+        assert!(!changes.is_empty());
+        assert_eq!(
+            changes.apply(current),
+            json!({
+                "ban": 50,
+                "kick": 75,
+                "events": { "m.room.name": 50, "m.room.topic": 100 },
+                "users": { "@alice:example.org": 50 },
+            })
+        );
+        assert!(PowerLevelChanges::new().is_empty());
+    }
+}
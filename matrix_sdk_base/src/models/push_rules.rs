@@ -0,0 +1,594 @@
+// Copyright 2020 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::convert::TryFrom;
+
+use serde_json::{json, Value as JsonValue};
+
+use super::Room;
+use crate::events::push_rules::Ruleset;
+use crate::events::room::member::MembershipState;
+use crate::identifiers::UserId;
+use crate::js_int::Int;
+
+/// The power level a user needs to notify the whole room with `@room` if the
+/// room has no `m.room.power_levels` state event.
+const DEFAULT_NOTIFICATION_POWER_LEVEL: i32 = 50;
+
+/// The kinds of push rules, in the order they are evaluated in.
+const RULE_KINDS: [&str; 5] = ["override", "content", "room", "sender", "underride"];
+
+/// The actions the push rules of our user request for an event.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PushActions {
+    /// Should the event cause a notification.
+    pub notify: bool,
+    /// Should the event be highlighted, e.g. because it mentions our user.
+    pub highlight: bool,
+    /// The sound that should be played for the notification.
+    pub sound: Option<String>,
+}
+
+impl PushActions {
+    /// Evaluate the push rules of our user for an event of a room.
+    ///
+    /// The rules are checked in the order the spec defines, the actions of
+    /// the first enabled rule that matches the event are returned. Events
+    /// that don't match any rule and events of our own user don't notify.
+    ///
+    /// # Arguments
+    ///
+    /// * `ruleset` - The push rules of our user.
+    ///
+    /// * `room` - The room the event belongs to, it provides the member count,
+    /// our display name and the power levels for the rule conditions.
+    ///
+    /// * `event` - The JSON of the event.
+    pub fn for_event(ruleset: &Ruleset, room: &Room, event: &JsonValue) -> Self {
+        match serde_json::to_value(ruleset) {
+            Ok(rules) => Self::for_event_with_rules(&rules, room, event),
+            Err(_) => Self::default(),
+        }
+    }
+
+    /// Evaluate the push rules of our user for an event of a room, the rules
+    /// are given as the JSON of the ruleset.
+    ///
+    /// This lets the client serialize the ruleset once when it changes
+    /// instead of once for every event, see `for_event()`.
+    pub(crate) fn for_event_with_rules(rules: &JsonValue, room: &Room, event: &JsonValue) -> Self {
+        let sender = event.get("sender").and_then(JsonValue::as_str);
+        if sender == Some(room.own_user_id.to_string().as_str()) {
+            return Self::default();
+        }
+
+        for kind in RULE_KINDS.iter() {
+            let rules = match rules.get(kind).and_then(JsonValue::as_array) {
+                Some(rules) => rules,
+                None => continue,
+            };
+
+            for rule in rules {
+                let enabled = rule
+                    .get("enabled")
+                    .and_then(JsonValue::as_bool)
+                    .unwrap_or(true);
+
+                if enabled && rule_matches(kind, rule, room, event) {
+                    return Self::from_actions(rule.get("actions"));
+                }
+            }
+        }
+
+        Self::default()
+    }
+
+    /// The actions of keyword rules, a message containing the keyword
+    /// notifies with the default sound and is highlighted.
+    pub fn keyword() -> Self {
+        Self {
+            notify: true,
+            highlight: true,
+            sound: Some("default".to_owned()),
+        }
+    }
+
+    /// Convert the actions into the JSON actions of a push rule.
+    pub fn to_json(&self) -> JsonValue {
+        let mut actions = vec![json!(if self.notify { "notify" } else { "dont_notify" })];
+
+        if let Some(sound) = &self.sound {
+            actions.push(json!({ "set_tweak": "sound", "value": sound }));
+        }
+
+        if self.highlight {
+            actions.push(json!({ "set_tweak": "highlight" }));
+        }
+
+        JsonValue::Array(actions)
+    }
+
+    fn from_actions(actions: Option<&JsonValue>) -> Self {
+        let mut push_actions = Self::default();
+
+        for action in actions.and_then(JsonValue::as_array).into_iter().flatten() {
+            match action.as_str() {
+                Some("notify") | Some("coalesce") => push_actions.notify = true,
+                Some("dont_notify") => push_actions.notify = false,
+                Some(_) => {}
+                None => {
+                    let value = action.get("value");
+
+                    match action.get("set_tweak").and_then(JsonValue::as_str) {
+                        Some("highlight") => {
+                            push_actions.highlight =
+                                value.and_then(JsonValue::as_bool).unwrap_or(true)
+                        }
+                        Some("sound") => {
+                            push_actions.sound =
+                                value.and_then(JsonValue::as_str).map(str::to_owned)
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+
+        push_actions
+    }
+}
+
+/// Get the keywords of the ruleset, the patterns of the content rules that
+/// were added by the user.
+pub(crate) fn keywords(ruleset: &Ruleset) -> Vec<String> {
+    let rules = serde_json::to_value(ruleset).unwrap_or_default();
+
+    rules
+        .get("content")
+        .and_then(JsonValue::as_array)
+        .into_iter()
+        .flatten()
+        .filter(|rule| {
+            !rule
+                .get("default")
+                .and_then(JsonValue::as_bool)
+                .unwrap_or(false)
+        })
+        .filter_map(|rule| rule.get("pattern").and_then(JsonValue::as_str))
+        .map(str::to_owned)
+        .collect()
+}
+
+/// Add or replace the content rule of a keyword, or remove it if no actions
+/// are given.
+///
+/// The rule id of a keyword rule is the keyword itself.
+pub(crate) fn set_keyword_rule(
+    ruleset: &Ruleset,
+    keyword: &str,
+    actions: Option<&PushActions>,
+) -> serde_json::Result<Ruleset> {
+    let mut rules = serde_json::to_value(ruleset)?;
+
+    if !rules["content"].is_array() {
+        rules["content"] = json!([]);
+    }
+
+    if let Some(content) = rules["content"].as_array_mut() {
+        content.retain(|rule| rule.get("rule_id").and_then(JsonValue::as_str) != Some(keyword));
+
+        if let Some(actions) = actions {
+            // User-defined content rules take precedence over the default
+            // ones.
+            content.insert(
+                0,
+                json!({
+                    "rule_id": keyword,
+                    "default": false,
+                    "enabled": true,
+                    "pattern": keyword,
+                    "actions": actions.to_json(),
+                }),
+            );
+        }
+    }
+
+    serde_json::from_value(rules)
+}
+
+fn rule_matches(kind: &str, rule: &JsonValue, room: &Room, event: &JsonValue) -> bool {
+    let rule_id = rule.get("rule_id").and_then(JsonValue::as_str);
+
+    match kind {
+        "override" | "underride" => rule
+            .get("conditions")
+            .and_then(JsonValue::as_array)
+            .map_or(true, |conditions| {
+                conditions.iter().all(|c| condition_matches(c, room, event))
+            }),
+        "content" => match (
+            rule.get("pattern").and_then(JsonValue::as_str),
+            event_value(event, "content.body"),
+        ) {
+            (Some(pattern), Some(body)) => contains_words(body, pattern, true),
+            _ => false,
+        },
+        "room" => rule_id == Some(room.room_id.to_string().as_str()),
+        "sender" => rule_id.is_some() && rule_id == event_value(event, "sender"),
+        _ => false,
+    }
+}
+
+fn condition_matches(condition: &JsonValue, room: &Room, event: &JsonValue) -> bool {
+    let field = |key: &str| condition.get(key).and_then(JsonValue::as_str);
+
+    match field("kind") {
+        Some("event_match") => {
+            let (key, pattern) = match (field("key"), field("pattern")) {
+                (Some(key), Some(pattern)) => (key, pattern),
+                _ => return false,
+            };
+
+            // Events of a sync response don't contain their room id.
+            let room_id = room.room_id.to_string();
+            let value = match event_value(event, key) {
+                Some(value) => value,
+                None if key == "room_id" => room_id.as_str(),
+                None => return false,
+            };
+
+            if key == "content.body" {
+                contains_words(value, pattern, true)
+            } else {
+                glob_match(&lowercase_chars(pattern), &lowercase_chars(value))
+            }
+        }
+        Some("contains_display_name") => {
+            let display_name = room
+                .members
+                .get(&room.own_user_id)
+                .and_then(|m| m.display_name.as_deref());
+
+            match (display_name, event_value(event, "content.body")) {
+                (Some(name), Some(body)) if !name.is_empty() => contains_words(body, name, false),
+                _ => false,
+            }
+        }
+        Some("room_member_count") => match field("is") {
+            Some(is) => member_count_matches(is, member_count(room)),
+            None => false,
+        },
+        Some("sender_notification_permission") => {
+            let sender = match event_value(event, "sender").map(UserId::try_from) {
+                Some(Ok(sender)) => sender,
+                _ => return false,
+            };
+
+            let required = match (field("key"), &room.power_levels) {
+                (Some("room"), Some(levels)) => levels.notifications,
+                (Some("room"), None) => Int::from(DEFAULT_NOTIFICATION_POWER_LEVEL),
+                _ => return false,
+            };
+
+            room.power_level(&sender) >= required
+        }
+        // Unknown conditions never match.
+        _ => false,
+    }
+}
+
+/// Get the string at the given dot-separated path of the event, e.g.
+/// `content.body`.
+fn event_value<'a>(event: &'a JsonValue, key: &str) -> Option<&'a str> {
+    key.split('.')
+        .try_fold(event, |value, key| value.get(key))
+        .and_then(JsonValue::as_str)
+}
+
+fn member_count(room: &Room) -> u64 {
+    match room.room_name.joined_member_count {
+        Some(count) => u64::from(count),
+        None => room
+            .members
+            .values()
+            .filter(|m| m.membership == MembershipState::Join)
+            .count() as u64,
+    }
+}
+
+/// Check a member count against a condition like `2`, `==2`, `<10` or `>=5`.
+fn member_count_matches(is: &str, count: u64) -> bool {
+    let split = is.find(|c: char| c.is_ascii_digit()).unwrap_or(is.len());
+    let (operator, number) = is.split_at(split);
+
+    let number = match number.parse::<u64>() {
+        Ok(number) => number,
+        Err(_) => return false,
+    };
+
+    match operator {
+        "" | "==" => count == number,
+        "<" => count < number,
+        ">" => count > number,
+        "<=" => count <= number,
+        ">=" => count >= number,
+        _ => false,
+    }
+}
+
+fn lowercase_chars(text: &str) -> Vec<char> {
+    text.to_lowercase().chars().collect()
+}
+
+/// Does the text contain the pattern as a whole word, or as whole words if
+/// the pattern contains spaces, ignoring the case.
+///
+/// If `glob` is true the pattern may contain the `*` and `?` wildcards.
+fn contains_words(text: &str, pattern: &str, glob: bool) -> bool {
+    let text = lowercase_chars(text);
+    let pattern = lowercase_chars(pattern);
+
+    let is_word_char = |i: usize| text.get(i).map_or(false, |c| c.is_alphanumeric());
+    let is_boundary = |i: usize| i == 0 || !is_word_char(i - 1) || !is_word_char(i);
+
+    find_pattern(&pattern, &text, glob, is_boundary, is_boundary)
+}
+
+/// Match a text against a glob pattern, `*` matches any number of
+/// characters and `?` a single character.
+fn glob_match(pattern: &[char], text: &[char]) -> bool {
+    find_pattern(pattern, text, true, |i| i == 0, |i| i == text.len())
+}
+
+/// Does the pattern match a part of the text that starts at a position for
+/// which `is_start` returns true and ends at one for which `is_end` does.
+///
+/// The text is scanned once, keeping track of every position in the pattern
+/// a partial match reached, so the time grows linearly with the length of the
+/// text no matter how many wildcards the pattern contains.
+fn find_pattern(
+    pattern: &[char],
+    text: &[char],
+    glob: bool,
+    is_start: impl Fn(usize) -> bool,
+    is_end: impl Fn(usize) -> bool,
+) -> bool {
+    let is_any = |p: usize| glob && pattern[p] == '*';
+
+    // Wildcards that match an empty string let a partial match skip them.
+    let skip_wildcards = |active: &mut Vec<bool>| {
+        for p in 0..pattern.len() {
+            if active[p] && is_any(p) {
+                active[p + 1] = true;
+            }
+        }
+    };
+
+    let mut active = vec![false; pattern.len() + 1];
+
+    for i in 0..=text.len() {
+        if is_start(i) {
+            active[0] = true;
+            skip_wildcards(&mut active);
+        }
+
+        if active[pattern.len()] && is_end(i) {
+            return true;
+        }
+
+        let c = match text.get(i) {
+            Some(c) => c,
+            None => break,
+        };
+
+        let mut next = vec![false; pattern.len() + 1];
+
+        for p in (0..pattern.len()).filter(|p| active[*p]) {
+            if is_any(p) {
+                next[p] = true;
+            } else if (glob && pattern[p] == '?') || pattern[p] == *c {
+                next[p + 1] = true;
+            }
+        }
+
+        skip_wildcards(&mut next);
+        active = next;
+    }
+
+    false
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::events::collections::all::StateEvent;
+    use crate::events::EventJson;
+    use crate::identifiers::RoomId;
+    use crate::js_int::UInt;
+
+    fn ruleset() -> Ruleset {
+        serde_json::from_value(json!({
+            "override": [{
+                "rule_id": ".m.rule.contains_display_name",
+                "default": true,
+                "enabled": true,
+                "conditions": [{ "kind": "contains_display_name" }],
+                "actions": [
+                    "notify",
+                    { "set_tweak": "sound", "value": "default" },
+                    { "set_tweak": "highlight" }
+                ]
+            }],
+            "content": [{
+                "rule_id": "cakes",
+                "default": false,
+                "enabled": true,
+                "pattern": "cake*",
+                "actions": ["notify", { "set_tweak": "highlight", "value": true }]
+            }],
+            "room": [],
+            "sender": [{
+                "rule_id": "@spammer:localhost",
+                "default": false,
+                "enabled": true,
+                "actions": ["dont_notify"]
+            }],
+            "underride": [
+                {
+                    "rule_id": ".m.rule.room_one_to_one",
+                    "default": true,
+                    "enabled": true,
+                    "conditions": [
+                        { "kind": "room_member_count", "is": "2" },
+                        { "kind": "event_match", "key": "type", "pattern": "m.room.message" }
+                    ],
+                    "actions": ["notify", { "set_tweak": "sound", "value": "ring" }]
+                },
+                {
+                    "rule_id": ".m.rule.message",
+                    "default": true,
+                    "enabled": true,
+                    "conditions": [
+                        { "kind": "event_match", "key": "type", "pattern": "m.room.message" }
+                    ],
+                    "actions": ["notify"]
+                }
+            ]
+        }))
+        .unwrap()
+    }
+
+    fn message(sender: &str, body: &str) -> JsonValue {
+        json!({
+            "content": { "body": body, "msgtype": "m.text" },
+            "event_id": "$message:localhost",
+            "origin_server_ts": 1_589_000_000_000u64,
+            "sender": sender,
+            "type": "m.room.message"
+        })
+    }
+
+    fn room() -> Room {
+        let user_id = UserId::try_from("@example:localhost").unwrap();
+        let room_id = RoomId::try_from("!test:localhost").unwrap();
+        let mut room = Room::new(&room_id, &user_id);
+
+        let member = serde_json::from_value::<EventJson<StateEvent>>(json!({
+            "content": { "displayname": "Example", "membership": "join" },
+            "event_id": "$member:localhost",
+            "origin_server_ts": 1_589_000_000_000u64,
+            "sender": "@example:localhost",
+            "state_key": "@example:localhost",
+            "type": "m.room.member"
+        }))
+        .unwrap()
+        .deserialize()
+        .unwrap();
+        room.receive_state_event(&member);
+        room.room_name.joined_member_count = Some(UInt::from(5u32));
+
+        room
+    }
+
+    #[test]
+    fn push_rule_evaluation() {
+        let ruleset = ruleset();
+        let mut room = room();
+        let actions = |room: &Room, sender: &str, body: &str| {
+            PushActions::for_event(&ruleset, room, &message(sender, body))
+        };
+
+        let mention = actions(&room, "@alice:localhost", "Hey Example, how are you?");
+        assert!(mention.notify);
+        assert!(mention.highlight);
+        assert_eq!(mention.sound.as_deref(), Some("default"));
+
+        // Display names only match whole words.
+        let plain = actions(&room, "@alice:localhost", "Examples are great");
+        assert_eq!(
+            plain,
+            PushActions {
+                notify: true,
+                highlight: false,
+                sound: None
+            }
+        );
+
+        assert!(actions(&room, "@alice:localhost", "Who wants CAKES?").highlight);
+        assert!(!actions(&room, "@spammer:localhost", "Buy cheap watches").notify);
+        assert_eq!(
+            actions(&room, "@example:localhost", "Hey Example"),
+            PushActions::default()
+        );
+
+        room.room_name.joined_member_count = Some(UInt::from(2u32));
+        assert_eq!(
+            actions(&room, "@alice:localhost", "Hello").sound.as_deref(),
+            Some("ring")
+        );
+    }
+
+    #[test]
+    fn keyword_rules() {
+        let room = room();
+        let keyword = PushActions::keyword();
+        let ruleset = set_keyword_rule(&ruleset(), "matrix", Some(&keyword)).unwrap();
+
+        assert_eq!(keywords(&ruleset), vec!["matrix", "cake*"]);
+
+        let actions = PushActions::for_event(
+            &ruleset,
+            &room,
+            &message("@alice:localhost", "I love Matrix"),
+        );
+        assert_eq!(actions, keyword);
+        assert_eq!(PushActions::from_actions(Some(&keyword.to_json())), keyword);
+
+        let ruleset = set_keyword_rule(&ruleset, "matrix", None).unwrap();
+        assert_eq!(keywords(&ruleset), vec!["cake*"]);
+        assert!(
+            !PushActions::for_event(
+                &ruleset,
+                &room,
+                &message("@alice:localhost", "I love Matrix")
+            )
+            .highlight
+        );
+    }
+
+    #[test]
+    fn glob_matching() {
+        let glob = |pattern: &str, text: &str| {
+            glob_match(&lowercase_chars(pattern), &lowercase_chars(text))
+        };
+
+        assert!(glob("m.room.*", "m.room.message"));
+        assert!(glob("m.room.messag?", "m.room.message"));
+        assert!(!glob("m.room.message", "m.room.message.feedback"));
+        assert!(glob("*", ""));
+
+        assert!(contains_words("Hello World!", "world", false));
+        assert!(contains_words("Is the cake ready?", "c?ke r*", true));
+        assert!(!contains_words("helloworld", "world", true));
+
+        // Patterns with many wildcards don't take exponential time.
+        let text = "a".repeat(10_000);
+        assert!(!glob("*a*a*a*a*a*a*a*a*b", &text));
+        assert!(!contains_words(&text, "*a*a*a*a*a*a*a*a*b", true));
+
+        assert!(member_count_matches(">=2", 2));
+        assert!(member_count_matches("<10", 5));
+        assert!(!member_count_matches("==2", 3));
+    }
+}
@@ -0,0 +1,138 @@
+// Copyright 2020 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use serde::{Deserialize, Serialize};
+
+/// A pusher, a device the homeserver sends push notifications to through a
+/// push gateway.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Pusher {
+    /// The unique identifier of the device for the push gateway, e.g. the
+    /// APNs or FCM token.
+    pub pushkey: String,
+    /// The kind of the pusher, `http` for pushers that use a push gateway.
+    pub kind: String,
+    /// The reverse-DNS style identifier of the application.
+    pub app_id: String,
+    /// The human readable name of the application.
+    pub app_display_name: String,
+    /// The human readable name of the device.
+    pub device_display_name: String,
+    /// The profile the device belongs to, devices with the same profile tag
+    /// share push rules.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile_tag: Option<String>,
+    /// The preferred language of the notifications, e.g. `en`.
+    pub lang: String,
+    /// The configuration of the push gateway.
+    pub data: PusherData,
+}
+
+/// The configuration of the push gateway of a `Pusher`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct PusherData {
+    /// The URL of the `/_matrix/push/v1/notify` endpoint of the push gateway.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// The format of the notifications, `event_id_only` sends notifications
+    /// without the content of the event.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub format: Option<String>,
+}
+
+impl Pusher {
+    /// Create a new pusher that sends notifications to a push gateway.
+    ///
+    /// The application id is used as the application name and the language
+    /// defaults to `en`, use the setters to change them.
+    ///
+    /// # Arguments
+    ///
+    /// * `app_id` - The reverse-DNS style identifier of the application.
+    ///
+    /// * `pushkey` - The unique identifier of the device for the push
+    /// gateway.
+    ///
+    /// * `gateway_url` - The URL of the `/_matrix/push/v1/notify` endpoint of
+    /// the push gateway.
+    pub fn http(app_id: &str, pushkey: &str, gateway_url: &str) -> Self {
+        Self {
+            pushkey: pushkey.to_owned(),
+            kind: "http".to_owned(),
+            app_id: app_id.to_owned(),
+            app_display_name: app_id.to_owned(),
+            device_display_name: String::new(),
+            profile_tag: None,
+            lang: "en".to_owned(),
+            data: PusherData {
+                url: Some(gateway_url.to_owned()),
+                format: None,
+            },
+        }
+    }
+
+    /// Set the human readable name of the application.
+    pub fn app_display_name(mut self, name: &str) -> Self {
+        self.app_display_name = name.to_owned();
+        self
+    }
+
+    /// Set the human readable name of the device.
+    pub fn device_display_name(mut self, name: &str) -> Self {
+        self.device_display_name = name.to_owned();
+        self
+    }
+
+    /// Set the preferred language of the notifications.
+    pub fn lang(mut self, lang: &str) -> Self {
+        self.lang = lang.to_owned();
+        self
+    }
+
+    /// Only send the event id of the event in notifications, the content of
+    /// the event isn't sent to the push gateway.
+    pub fn event_id_only(mut self) -> Self {
+        self.data.format = Some("event_id_only".to_owned());
+        self
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn pusher_serialization() {
+        let pusher = Pusher::http("org.example.app", "token", "https://push.example.org")
+            .device_display_name("Phone")
+            .event_id_only();
+
+        assert_eq!(
+            serde_json::to_value(&pusher).unwrap(),
+            json!({
+                "pushkey": "token",
+                "kind": "http",
+                "app_id": "org.example.app",
+                "app_display_name": "org.example.app",
+                "device_display_name": "Phone",
+                "lang": "en",
+                "data": {
+                    "url": "https://push.example.org",
+                    "format": "event_id_only"
+                }
+            })
+        );
+    }
+}
@@ -0,0 +1,59 @@
+// Copyright 2020 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use serde::{Deserialize, Serialize};
+
+use crate::events::room::message::MessageEventContent;
+use crate::identifiers::{EventId, RoomId};
+
+/// The state of a message in the send queue.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum SendState {
+    /// The message waits for the messages that were queued before it.
+    Queued,
+    /// The message is being sent to the server.
+    Sending,
+    /// The server accepted the message.
+    Sent(EventId),
+    /// The server refused the message, the error message describes why.
+    ///
+    /// Later messages for the same room are held back until the message is
+    /// retried or removed from the queue.
+    Failed(String),
+}
+
+/// A message that was put into the send queue.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct QueuedMessage {
+    /// The room the message should be sent to.
+    pub room_id: RoomId,
+    /// The transaction id that is used to send the message.
+    pub txn_id: String,
+    /// The content of the message.
+    pub content: MessageEventContent,
+    /// The current state of the message.
+    pub state: SendState,
+}
+
+impl QueuedMessage {
+    /// Create a new message in the `Queued` state.
+    pub fn new(room_id: RoomId, txn_id: String, content: MessageEventContent) -> Self {
+        Self {
+            room_id,
+            txn_id,
+            content,
+            state: SendState::Queued,
+        }
+    }
+}
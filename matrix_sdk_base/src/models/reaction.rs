@@ -0,0 +1,108 @@
+// Copyright 2020 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::convert::TryFrom;
+
+use serde_json::value::RawValue as RawJsonValue;
+use serde_json::Value as JsonValue;
+
+use crate::identifiers::{EventId, UserId};
+
+/// A `m.reaction` event that annotates an event of the room timeline.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Reaction {
+    /// The id of the reaction event itself.
+    pub event_id: EventId,
+    /// The id of the event that is reacted to.
+    pub relates_to: EventId,
+    /// The key of the reaction, usually an emoji.
+    pub key: String,
+    /// The user that reacted.
+    pub sender: UserId,
+}
+
+/// The reactions with the same key to an event.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ReactionGroup {
+    /// The number of users that reacted with the key.
+    pub count: usize,
+    /// The users that reacted with the key, in the order they reacted in.
+    pub senders: Vec<UserId>,
+}
+
+impl Reaction {
+    /// Parse a reaction from the JSON of a `m.reaction` event.
+    ///
+    /// Returns `None` if the event isn't a valid reaction.
+    pub fn from_json(event: &JsonValue) -> Option<Self> {
+        if event.get("type")?.as_str()? != "m.reaction" {
+            return None;
+        }
+
+        let relation = event.get("content")?.get("m.relates_to")?;
+
+        if relation.get("rel_type")?.as_str()? != "m.annotation" {
+            return None;
+        }
+
+        Some(Self {
+            event_id: EventId::try_from(event.get("event_id")?.as_str()?).ok()?,
+            relates_to: EventId::try_from(relation.get("event_id")?.as_str()?).ok()?,
+            key: relation.get("key")?.as_str()?.to_owned(),
+            sender: UserId::try_from(event.get("sender")?.as_str()?).ok()?,
+        })
+    }
+
+    /// Parse a reaction from the raw JSON of an event.
+    ///
+    /// This avoids deserializing the event unless it looks like a reaction.
+    pub fn from_raw(event: &RawJsonValue) -> Option<Self> {
+        if !event.get().contains("m.annotation") {
+            return None;
+        }
+
+        Self::from_json(&serde_json::from_str(event.get()).ok()?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn reaction_parsing() {
+        let mut json = json!({
+            "content": {
+                "m.relates_to": {
+                    "rel_type": "m.annotation",
+                    "event_id": "$original:localhost",
+                    "key": "👍"
+                }
+            },
+            "event_id": "$reaction:localhost",
+            "origin_server_ts": 1_520_372_800_469u64,
+            "sender": "@example:localhost",
+            "type": "m.reaction"
+        });
+
+        let reaction = Reaction::from_json(&json).unwrap();
+
+        assert_eq!(reaction.key, "👍");
+        assert_eq!(reaction.relates_to.to_string(), "$original:localhost");
+
+        json["content"]["m.relates_to"]["rel_type"] = json!("m.reference");
+        assert!(Reaction::from_json(&json).is_none());
+    }
+}
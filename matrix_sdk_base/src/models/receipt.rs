@@ -0,0 +1,111 @@
+// Copyright 2020 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::convert::TryFrom;
+
+use serde_json::Value as JsonValue;
+
+use crate::events::receipt::ReceiptEvent;
+use crate::identifiers::{EventId, UserId};
+use crate::js_int::UInt;
+
+/// A read receipt of a user, the user has read up to the event.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReadReceipt {
+    /// The user that sent the receipt.
+    pub user_id: UserId,
+    /// The id of the event the user has read up to.
+    pub event_id: EventId,
+    /// The timestamp in milliseconds the receipt was sent at.
+    pub ts: Option<UInt>,
+}
+
+impl ReadReceipt {
+    /// Get the read receipts that are part of the given `m.receipt` event.
+    ///
+    /// Receipts of other types than `m.read` and malformed receipts are
+    /// skipped.
+    pub fn from_event(event: &ReceiptEvent) -> Vec<Self> {
+        serde_json::to_value(&event.content)
+            .map(|c| Self::from_content(&c))
+            .unwrap_or_default()
+    }
+
+    /// Get the read receipts that are part of the JSON content of a
+    /// `m.receipt` event.
+    pub fn from_content(content: &JsonValue) -> Vec<Self> {
+        let mut receipts = Vec::new();
+
+        let content = match content.as_object() {
+            Some(c) => c,
+            None => return receipts,
+        };
+
+        for (event_id, receipt) in content {
+            let event_id = match EventId::try_from(event_id.as_str()) {
+                Ok(id) => id,
+                Err(_) => continue,
+            };
+            let users = match receipt.get("m.read").and_then(JsonValue::as_object) {
+                Some(u) => u,
+                None => continue,
+            };
+
+            for (user_id, receipt) in users {
+                if let Ok(user_id) = UserId::try_from(user_id.as_str()) {
+                    receipts.push(Self {
+                        user_id,
+                        event_id: event_id.clone(),
+                        ts: receipt
+                            .get("ts")
+                            .and_then(JsonValue::as_u64)
+                            .and_then(|ts| UInt::try_from(ts).ok()),
+                    });
+                }
+            }
+        }
+
+        receipts
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn receipt_parsing() {
+        let content = json!({
+            "$read:localhost": {
+                "m.read": {
+                    "@alice:localhost": { "ts": 1_520_372_800_469u64 },
+                    "@bob:localhost": {}
+                }
+            },
+            "$other:localhost": {
+                "m.unknown": { "@carol:localhost": { "ts": 1 } }
+            }
+        });
+
+        let mut receipts = ReadReceipt::from_content(&content);
+        receipts.sort_by_key(|r| r.user_id.to_string());
+
+        assert_eq!(receipts.len(), 2);
+        assert_eq!(receipts[0].user_id.to_string(), "@alice:localhost");
+        assert_eq!(receipts[0].event_id.to_string(), "$read:localhost");
+        assert_eq!(receipts[0].ts, UInt::try_from(1_520_372_800_469u64).ok());
+        assert_eq!(receipts[1].ts, None);
+    }
+}
@@ -15,33 +15,59 @@
 
 use std::collections::{BTreeMap, HashMap};
 use std::convert::TryFrom;
+use std::io::Write;
+use std::result::Result as StdResult;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 #[cfg(feature = "messages")]
 use super::message::MessageQueue;
-use super::RoomMember;
+use super::{
+    CustomStateEventContent, MemberExportFormat, PendingEvent, PermissionError, PowerAction,
+    Reaction, ReactionGroup, ReadReceipt, RoomMember, RoomStateSnapshot, SendState, ServerAcl,
+    ServerNotice, Space, SpaceChildContent, SpaceParentContent, StateChange, UrlPreview,
+    SERVER_NOTICE_TAG,
+};
+#[cfg(feature = "messages")]
+use super::{Timeline, TimelineEvent};
 
 use crate::api::r0::sync::sync_events::{RoomSummary, UnreadNotificationsCount};
+use crate::error::Result;
 use crate::events::collections::all::{RoomEvent, StateEvent};
+use crate::events::custom_state::CustomStateEvent;
+use crate::events::fully_read::FullyReadEvent;
 use crate::events::presence::PresenceEvent;
+use crate::events::receipt::ReceiptEvent;
 use crate::events::room::{
     aliases::AliasesEvent,
     canonical_alias::CanonicalAliasEvent,
-    encryption::EncryptionEvent,
-    member::{MemberEvent, MembershipChange},
+    encryption::{EncryptionEvent, EncryptionEventContent},
+    member::{MemberEvent, MembershipChange, MembershipState},
     name::NameEvent,
     power_levels::{NotificationPowerLevels, PowerLevelsEvent, PowerLevelsEventContent},
+    redaction::RedactionEvent,
     tombstone::TombstoneEvent,
 };
-use crate::events::stripped::{AnyStrippedStateEvent, StrippedRoomName};
+use crate::events::stripped::{AnyStrippedStateEvent, StrippedRoomMember, StrippedRoomName};
+use crate::events::tag::TagEvent;
+use crate::events::typing::TypingEvent;
 use crate::events::EventType;
 
-#[cfg(feature = "messages")]
-use crate::events::room::message::MessageEvent;
+use crate::events::room::message::{MessageEvent, MessageEventContent};
 
-use crate::identifiers::{RoomAliasId, RoomId, UserId};
+use crate::identifiers::{EventId, RoomAliasId, RoomId, UserId};
 
 use crate::js_int::{Int, UInt};
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value as JsonValue};
+
+/// The power level that is required for moderation actions if the room has
+/// no `m.room.power_levels` state event.
+const DEFAULT_POWER_LEVEL: i32 = 50;
+
+/// The maximum number of members a room without a name is named after.
+const MAX_HEROES: usize = 5;
+
 #[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
 #[cfg_attr(test, derive(Clone))]
 /// `RoomName` allows the calculation of a text room name.
@@ -86,6 +112,10 @@ pub struct PowerLevels {
     pub state_default: Int,
     /// The default power level for every user in the room.
     pub users_default: Int,
+    /// The power levels of specific users, the other users have the
+    /// `users_default` level.
+    #[serde(default)]
+    pub users: BTreeMap<UserId, Int>,
     /// The power level requirements for specific notification types.
     ///
     /// This is a mapping from `key` to power level for that notifications key.
@@ -101,6 +131,152 @@ pub struct Tombstone {
     replacement: RoomId,
 }
 
+/// The retention settings of a room, taken from the `m.room.retention` state
+/// event.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(test, derive(Clone))]
+pub struct Retention {
+    /// The maximum time, in milliseconds, an event should be kept around.
+    pub max_lifetime: Option<UInt>,
+    /// The minimum time, in milliseconds, an event should be kept around.
+    pub min_lifetime: Option<UInt>,
+}
+
+/// The rule that decides who can join a room, taken from the
+/// `m.room.join_rules` state event.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "join_rule", rename_all = "snake_case")]
+pub enum JoinRule {
+    /// Anyone can join the room without an invite.
+    Public,
+    /// Users can request an invite to the room.
+    Knock,
+    /// Users can only join the room if they are invited.
+    Invite,
+    /// Reserved, no one can join the room.
+    Private,
+    /// Users can join the room if they satisfy one of the allow rules,
+    /// otherwise they need an invite.
+    Restricted {
+        /// The conditions under which a user can join the room.
+        #[serde(default)]
+        allow: Vec<AllowRule>,
+    },
+}
+
+impl JoinRule {
+    /// The rooms whose members can join a restricted room.
+    ///
+    /// Returns an empty list for every other join rule.
+    pub fn allowed_rooms(&self) -> Vec<&RoomId> {
+        match self {
+            JoinRule::Restricted { allow } => allow
+                .iter()
+                .filter(|a| a.kind == "m.room_membership")
+                .filter_map(|a| a.room_id.as_ref())
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Can guests join the room, taken from the `m.room.guest_access` state
+/// event.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "guest_access", rename_all = "snake_case")]
+pub enum GuestAccess {
+    /// Guests can join the room.
+    CanJoin,
+    /// Guests can't join the room.
+    Forbidden,
+}
+
+/// Who can read the history of the room, taken from the
+/// `m.room.history_visibility` state event.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "history_visibility", rename_all = "snake_case")]
+pub enum HistoryVisibility {
+    /// Members can read the history since they were invited.
+    Invited,
+    /// Members can read the history since they joined.
+    Joined,
+    /// Members can read the whole history.
+    Shared,
+    /// Anyone can read the whole history, even without joining the room.
+    WorldReadable,
+}
+
+/// A condition under which a user can join a room with a restricted join
+/// rule.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AllowRule {
+    /// The type of the condition, `m.room_membership` allows the members of
+    /// the given room to join.
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// The room whose members can join, set for `m.room_membership`
+    /// conditions.
+    pub room_id: Option<RoomId>,
+}
+
+/// The part of the room state that was taken from a single state event and
+/// that is removed again if the event gets redacted.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum RedactableState {
+    Name,
+    CanonicalAlias,
+    Avatar,
+    Topic,
+    Tombstone,
+    Member(UserId),
+}
+
+/// A client side policy that limits the number of messages a `Room` keeps
+/// around.
+///
+/// The policy is applied to the message queue of every room after a sync
+/// response has been received. If the room has a `m.room.retention` state event
+/// with a shorter `max_lifetime` than the configured maximum age, the room's
+/// lifetime is used instead.
+#[cfg(feature = "messages")]
+#[cfg_attr(docsrs, doc(cfg(feature = "messages")))]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RetentionPolicy {
+    max_events: Option<usize>,
+    max_age: Option<Duration>,
+}
+
+#[cfg(feature = "messages")]
+impl RetentionPolicy {
+    /// Create a new policy that doesn't limit anything.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the maximum number of messages that should be kept per room.
+    pub fn max_events(mut self, max_events: usize) -> Self {
+        self.max_events = Some(max_events);
+        self
+    }
+
+    /// Set the maximum age a message can reach before it is removed.
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+/// The profile of a member of a room we are invited to, taken from the
+/// stripped `m.room.member` events of the invite.
+pub struct StrippedMember {
+    /// The display name of the member.
+    pub display_name: Option<String>,
+    /// The `mxc://` URI of the avatar of the member.
+    pub avatar_url: Option<String>,
+}
+
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 #[cfg_attr(test, derive(Clone))]
 /// A Matrix room.
@@ -123,7 +299,7 @@ pub struct Room {
     #[cfg_attr(docsrs, doc(cfg(feature = "messages")))]
     #[serde(with = "super::message::ser_deser")]
     pub messages: MessageQueue,
-    /// A list of users that are currently typing.
+    /// A list of users that are currently typing, without our own user.
     pub typing_users: Vec<UserId>,
     /// The power level requirements for specific actions in this room
     pub power_levels: Option<PowerLevels>,
@@ -136,6 +312,108 @@ pub struct Room {
     pub unread_notifications: Option<UInt>,
     /// The tombstone state of this room.
     pub tombstone: Option<Tombstone>,
+    /// The retention settings of this room.
+    #[serde(default)]
+    pub retention: Option<Retention>,
+    /// The spaces this room is part of and the children of it if it is a
+    /// space.
+    #[serde(default)]
+    pub space: Space,
+    /// The content of the state events that aren't known to the SDK, keyed
+    /// by the event type and the state key.
+    #[serde(default)]
+    custom_state: BTreeMap<String, BTreeMap<String, JsonValue>>,
+    /// Data that applications attached to the room, keyed by the namespace
+    /// of the application.
+    #[serde(default)]
+    app_data: BTreeMap<String, JsonValue>,
+    /// The content of the room account data events of our own user, keyed by
+    /// the event type.
+    #[serde(default)]
+    account_data: BTreeMap<String, JsonValue>,
+    /// The join rule of this room.
+    #[serde(default)]
+    join_rule: Option<JoinRule>,
+    /// The `mxc://` URI of the avatar of this room.
+    #[serde(default)]
+    avatar_url: Option<String>,
+    /// The topic of this room.
+    #[serde(default)]
+    topic: Option<String>,
+    /// The guest access setting of this room.
+    #[serde(default)]
+    guest_access: Option<GuestAccess>,
+    /// The history visibility setting of this room.
+    #[serde(default)]
+    history_visibility: Option<HistoryVisibility>,
+    /// The servers that may participate in this room.
+    #[serde(default)]
+    server_acl: Option<ServerAcl>,
+    /// The user that invited our own user to this room.
+    #[serde(default)]
+    inviter: Option<UserId>,
+    /// The time our own user was invited to this room.
+    #[serde(default)]
+    invite_timestamp: Option<SystemTime>,
+    /// The profiles of the members from the stripped state of an invite.
+    #[serde(default)]
+    stripped_members: HashMap<UserId, StrippedMember>,
+    /// The user we exchange direct messages with in this room, taken from
+    /// the `m.direct` account data of our own user.
+    #[serde(default)]
+    direct_target: Option<UserId>,
+    /// The local echoes of the messages our own user sent, the oldest one
+    /// first.
+    #[serde(skip)]
+    pending_events: Vec<PendingEvent>,
+    /// The ordered events of the room, with the gaps where events are
+    /// missing.
+    #[cfg(feature = "messages")]
+    #[serde(skip)]
+    timeline: Timeline,
+    /// The previews of the URLs found in the messages of the room.
+    #[serde(skip)]
+    url_previews: HashMap<EventId, Vec<UrlPreview>>,
+    /// The latest edits of messages in this room, the sender of the edit and
+    /// the `m.new_content` of it keyed by the id of the edited event.
+    #[serde(skip)]
+    edits: HashMap<EventId, (UserId, JsonValue)>,
+    /// The reactions to the events of this room, keyed by the id of the
+    /// event that is reacted to.
+    #[serde(skip)]
+    reactions: HashMap<EventId, Vec<Reaction>>,
+    /// The latest read receipt of every user.
+    #[serde(skip)]
+    user_receipts: HashMap<UserId, ReadReceipt>,
+    /// The users whose latest read receipt points to an event, keyed by the
+    /// id of the event.
+    #[serde(skip)]
+    event_receipts: HashMap<EventId, Vec<UserId>>,
+    /// The ids of the state events the room state was taken from, so the state
+    /// can be removed if one of the events gets redacted.
+    #[serde(default)]
+    state_event_ids: HashMap<EventId, RedactableState>,
+    /// The event our own user last sent a read receipt for.
+    #[serde(default)]
+    read_receipt: Option<EventId>,
+    /// The event our own user has fully read up to.
+    #[serde(default)]
+    fully_read: Option<EventId>,
+    /// The token to fetch the events that came before the ones we know
+    /// about with the `/messages` endpoint.
+    #[serde(default)]
+    prev_batch: Option<String>,
+    /// The tags our own user put on the room, together with the order of the
+    /// room inside of the tag.
+    #[serde(default)]
+    tags: BTreeMap<String, Option<f64>>,
+    /// The latest notice the homeserver sent to this room if it is the server
+    /// notice room.
+    #[serde(default)]
+    server_notice: Option<ServerNotice>,
+    /// Was the full member list fetched from the `/members` endpoint.
+    #[serde(default)]
+    members_synced: bool,
 }
 
 impl RoomName {
@@ -144,6 +422,18 @@ impl RoomName {
         true
     }
 
+    /// The canonical alias of the room, taken from the `m.room.canonical_alias`
+    /// state event.
+    pub fn canonical_alias(&self) -> Option<&RoomAliasId> {
+        self.canonical_alias.as_ref()
+    }
+
+    /// The alternative aliases of the room, taken from the `m.room.aliases`
+    /// state events.
+    pub fn aliases(&self) -> &[RoomAliasId] {
+        &self.aliases
+    }
+
     pub fn set_canonical(&mut self, alias: RoomAliasId) -> bool {
         self.canonical_alias = Some(alias);
         true
@@ -154,59 +444,117 @@ impl RoomName {
         true
     }
 
-    pub fn calculate_name(&self, members: &HashMap<UserId, RoomMember>) -> String {
+    /// Calculate the display name of the room, following the naming algorithm
+    /// of the spec.
+    ///
+    /// The name of the room is used if it has one, then its canonical alias.
+    /// Otherwise the room is named after the heroes of the room summary, or
+    /// after up to five other members if the server didn't send heroes.
+    ///
+    /// # Arguments
+    ///
+    /// * `own_user_id` - The mxid of our own user, we aren't used as a hero.
+    ///
+    /// * `members` - The members of the room.
+    pub fn calculate_name(
+        &self,
+        own_user_id: &UserId,
+        members: &HashMap<UserId, RoomMember>,
+    ) -> String {
         // https://matrix.org/docs/spec/client_server/latest#calculating-the-display-name-for-a-room.
         // the order in which we check for a name ^^
-        if let Some(name) = &self.name {
-            let name = name.trim();
-            name.to_string()
-        } else if let Some(alias) = &self.canonical_alias {
-            let alias = alias.alias().trim();
-            alias.to_string()
-        } else if !self.aliases.is_empty() && !self.aliases[0].alias().is_empty() {
-            self.aliases[0].alias().trim().to_string()
+        if let Some(name) = self
+            .name
+            .as_deref()
+            .map(str::trim)
+            .filter(|n| !n.is_empty())
+        {
+            return name.to_owned();
+        }
+        if let Some(alias) = &self.canonical_alias {
+            return alias.to_string();
+        }
+        if let Some(alias) = self.aliases.first() {
+            return alias.to_string();
+        }
+
+        let heroes: Vec<String> = if self.heroes.is_empty() {
+            let mut others: Vec<&RoomMember> = members
+                .values()
+                .filter(|m| &m.user_id != own_user_id)
+                .collect();
+            others.sort_by(|a, b| a.user_id.to_string().cmp(&b.user_id.to_string()));
+            others
+                .into_iter()
+                .take(MAX_HEROES)
+                .map(RoomName::member_name)
+                .collect()
         } else {
-            let joined = self.joined_member_count.unwrap_or(UInt::MIN);
-            let invited = self.invited_member_count.unwrap_or(UInt::MIN);
-            let heroes = UInt::new(self.heroes.len() as u64).unwrap();
-            let one = UInt::new(1).unwrap();
+            self.heroes
+                .iter()
+                .map(|hero| {
+                    UserId::try_from(hero.as_str())
+                        .ok()
+                        .and_then(|id| members.get(&id))
+                        .map(RoomName::member_name)
+                        .unwrap_or_else(|| RoomName::hero_name(hero))
+                })
+                .collect()
+        };
 
-            let invited_joined = if invited + joined == UInt::MIN {
-                UInt::MIN
-            } else {
-                invited + joined - one
-            };
+        let member_count = match (self.joined_member_count, self.invited_member_count) {
+            (None, None) => members.len() as u64,
+            (joined, invited) => {
+                u64::from(joined.unwrap_or(UInt::MIN)) + u64::from(invited.unwrap_or(UInt::MIN))
+            }
+        };
 
-            // TODO this should use `self.heroes but it is always empty??
-            if heroes >= invited_joined {
-                let mut names = members
-                    .values()
-                    .take(3)
-                    .map(|mem| {
-                        mem.display_name
-                            .clone()
-                            .unwrap_or_else(|| mem.user_id.localpart().to_string())
-                    })
-                    .collect::<Vec<String>>();
-                // stabilize ordering
-                names.sort();
-                names.join(", ")
-            } else if heroes < invited_joined && invited + joined > one {
-                let mut names = members
-                    .values()
-                    .take(3)
-                    .map(|mem| {
-                        mem.display_name
-                            .clone()
-                            .unwrap_or_else(|| mem.user_id.localpart().to_string())
-                    })
-                    .collect::<Vec<String>>();
-                names.sort();
-                // TODO what length does the spec want us to use here and in the `else`
-                format!("{}, and {} others", names.join(", "), (joined + invited))
+        if member_count <= 1 {
+            if heroes.is_empty() {
+                "Empty Room".to_owned()
             } else {
-                format!("Empty Room (was {} others)", members.len())
+                format!("Empty Room (was {})", RoomName::join_names(&heroes, 0))
             }
+        } else {
+            let others = (member_count - 1).saturating_sub(heroes.len() as u64);
+            RoomName::join_names(&heroes, others)
+        }
+    }
+
+    /// Does the room have a name or an alias it is named after.
+    fn is_named(&self) -> bool {
+        self.name.as_deref().map_or(false, |n| !n.trim().is_empty())
+            || self.canonical_alias.is_some()
+            || !self.aliases.is_empty()
+    }
+
+    /// The name of a member that is used in the room name, the display name
+    /// if it has one or the localpart of the user id.
+    fn member_name(member: &RoomMember) -> String {
+        member
+            .display_name
+            .clone()
+            .filter(|n| !n.is_empty())
+            .unwrap_or_else(|| member.user_id.localpart().to_owned())
+    }
+
+    /// The name of a hero we don't know the member event of.
+    fn hero_name(hero: &str) -> String {
+        UserId::try_from(hero)
+            .map(|id| id.localpart().to_owned())
+            .unwrap_or_else(|_| hero.to_owned())
+    }
+
+    /// Join the names like "Alice, Bob and Carol", or "Alice, Bob and 3
+    /// others" if there are further members that aren't named.
+    fn join_names(names: &[String], others: u64) -> String {
+        match (names, others) {
+            ([], 0) => String::new(),
+            ([], others) => format!("{} others", others),
+            ([name], 0) => name.clone(),
+            ([init @ .., last], 0) => format!("{} and {}", init.join(", "), last),
+            (names, 1) => format!("{} and 1 other", names.join(", ")),
+            (names, others) => format!("{} and {} others", names.join(", "), others),
         }
     }
 }
@@ -234,226 +582,1904 @@ impl Room {
             unread_highlight: None,
             unread_notifications: None,
             tombstone: None,
+            retention: None,
+            space: Space::default(),
+            custom_state: BTreeMap::new(),
+            app_data: BTreeMap::new(),
+            account_data: BTreeMap::new(),
+            join_rule: None,
+            avatar_url: None,
+            topic: None,
+            guest_access: None,
+            history_visibility: None,
+            server_acl: None,
+            inviter: None,
+            invite_timestamp: None,
+            stripped_members: HashMap::new(),
+            direct_target: None,
+            pending_events: Vec::new(),
+            #[cfg(feature = "messages")]
+            timeline: Timeline::new(),
+            url_previews: HashMap::new(),
+            edits: HashMap::new(),
+            reactions: HashMap::new(),
+            user_receipts: HashMap::new(),
+            event_receipts: HashMap::new(),
+            state_event_ids: HashMap::new(),
+            read_receipt: None,
+            fully_read: None,
+            prev_batch: None,
+            tags: BTreeMap::new(),
+            server_notice: None,
+            members_synced: false,
         }
     }
 
     /// Return the display name of the room.
+    ///
+    /// A room we are only invited to that has no name or alias is named
+    /// after the user that invited us.
     pub fn display_name(&self) -> String {
-        self.room_name.calculate_name(&self.members)
+        match self.invite_preview_inviter() {
+            Some(inviter) if !self.room_name.is_named() => self
+                .stripped_members
+                .get(inviter)
+                .and_then(|m| m.display_name.clone())
+                .filter(|n| !n.is_empty())
+                .unwrap_or_else(|| inviter.localpart().to_owned()),
+            _ => self
+                .room_name
+                .calculate_name(&self.own_user_id, &self.members),
+        }
     }
 
-    /// Is the room a encrypted room.
-    pub fn is_encrypted(&self) -> bool {
-        self.encrypted
+    /// The user that invited our own user to the room, taken from the
+    /// stripped state of the invite.
+    pub fn inviter(&self) -> Option<&UserId> {
+        self.inviter.as_ref()
     }
 
-    fn add_member(&mut self, event: &MemberEvent) -> bool {
-        if self
-            .members
-            .contains_key(&UserId::try_from(event.state_key.as_str()).unwrap())
-        {
-            return false;
-        }
+    /// The time our own user was invited to the room, `None` if the server
+    /// didn't include it in the stripped state of the invite.
+    pub fn invite_timestamp(&self) -> Option<SystemTime> {
+        self.invite_timestamp
+    }
 
-        let member = RoomMember::new(event);
+    /// The profile of a member that was sent in the stripped state of the
+    /// invite.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - The id of the member.
+    pub fn stripped_member(&self, user_id: &UserId) -> Option<&StrippedMember> {
+        self.stripped_members.get(user_id)
+    }
 
-        self.members
-            .insert(UserId::try_from(event.state_key.as_str()).unwrap(), member);
+    /// Is the room a direct message room according to the `m.direct` account
+    /// data of our own user.
+    pub fn is_direct(&self) -> bool {
+        self.direct_target.is_some()
+    }
 
-        true
+    /// The user we exchange direct messages with in the room, `None` if the
+    /// room isn't a direct message room.
+    pub fn direct_target(&self) -> Option<&UserId> {
+        self.direct_target.as_ref()
     }
 
-    /// Add to the list of `RoomAliasId`s.
-    fn push_room_alias(&mut self, alias: &RoomAliasId) -> bool {
-        self.room_name.push_alias(alias.clone());
-        true
+    /// Set the user we exchange direct messages with in the room.
+    ///
+    /// Returns true if the direct message target changed, false otherwise.
+    ///
+    /// # Arguments
+    ///
+    /// * `target` - The user the direct messages are exchanged with, `None`
+    /// if the room isn't a direct message room.
+    pub fn set_direct_target(&mut self, target: Option<UserId>) -> bool {
+        if self.direct_target == target {
+            false
+        } else {
+            self.direct_target = target;
+            true
+        }
     }
 
-    /// RoomAliasId is `#alias:hostname` and `port`
-    fn canonical_alias(&mut self, alias: &RoomAliasId) -> bool {
-        self.room_name.set_canonical(alias.clone());
-        true
+    /// The inviter of the room if we only know the stripped state of the
+    /// room and none of its members.
+    fn invite_preview_inviter(&self) -> Option<&UserId> {
+        self.inviter.as_ref().filter(|_| self.members.is_empty())
     }
 
-    fn set_room_name(&mut self, name: &str) -> bool {
-        self.room_name.set_name(name);
-        true
+    /// The join rule of the room, `None` if the room has no
+    /// `m.room.join_rules` state event.
+    pub fn join_rule(&self) -> Option<&JoinRule> {
+        self.join_rule.as_ref()
     }
 
-    fn set_room_power_level(&mut self, event: &PowerLevelsEvent) -> bool {
-        let PowerLevelsEventContent {
-            ban,
-            events,
-            events_default,
-            invite,
-            kick,
-            redact,
-            state_default,
-            users_default,
-            notifications: NotificationPowerLevels { room },
-            ..
-        } = &event.content;
+    /// The `mxc://` URI of the avatar of the room, `None` if the room has no
+    /// avatar.
+    ///
+    /// A room we are only invited to that has no avatar uses the avatar of
+    /// the user that invited us.
+    pub fn avatar_url(&self) -> Option<&str> {
+        self.avatar_url.as_deref().or_else(|| {
+            self.invite_preview_inviter()
+                .and_then(|inviter| self.stripped_members.get(inviter))
+                .and_then(|m| m.avatar_url.as_deref())
+        })
+    }
 
-        let power = PowerLevels {
-            ban: *ban,
-            events: events.clone(),
-            events_default: *events_default,
-            invite: *invite,
-            kick: *kick,
-            redact: *redact,
-            state_default: *state_default,
-            users_default: *users_default,
-            notifications: *room,
-        };
-        self.power_levels = Some(power);
-        true
+    /// The topic of the room, `None` if the room has no topic.
+    pub fn topic(&self) -> Option<&str> {
+        self.topic.as_deref()
     }
 
-    pub(crate) fn set_room_summary(&mut self, summary: &RoomSummary) {
-        let RoomSummary {
-            heroes,
-            joined_member_count,
-            invited_member_count,
-        } = summary;
-        self.room_name.heroes = heroes.clone();
-        self.room_name.invited_member_count = *invited_member_count;
-        self.room_name.joined_member_count = *joined_member_count;
+    /// The guest access setting of the room, `None` if the room has no
+    /// `m.room.guest_access` state event.
+    pub fn guest_access(&self) -> Option<GuestAccess> {
+        self.guest_access
     }
 
-    pub(crate) fn set_unread_notice_count(&mut self, notifications: &UnreadNotificationsCount) {
-        self.unread_highlight = notifications.highlight_count;
-        self.unread_notifications = notifications.notification_count;
+    /// The history visibility setting of the room, `None` if the room has no
+    /// `m.room.history_visibility` state event.
+    pub fn history_visibility(&self) -> Option<HistoryVisibility> {
+        self.history_visibility
     }
 
-    /// Handle a room.member updating the room state if necessary.
-    ///
-    /// Returns true if the joined member list changed, false otherwise.
-    pub fn handle_membership(&mut self, event: &MemberEvent) -> bool {
-        match event.membership_change() {
-            MembershipChange::Invited | MembershipChange::Joined => self.add_member(event),
-            _ => {
-                let user = if let Ok(id) = UserId::try_from(event.state_key.as_str()) {
-                    id
-                } else {
-                    return false;
-                };
-                if let Some(member) = self.members.get_mut(&user) {
-                    member.update_member(event)
-                } else {
-                    false
-                }
-            }
-        }
+    /// The servers that may participate in the room, `None` if the room has
+    /// no `m.room.server_acl` state event.
+    pub fn server_acl(&self) -> Option<&ServerAcl> {
+        self.server_acl.as_ref()
     }
 
-    /// Handle a room.message event and update the `MessageQueue` if necessary.
+    /// Is the room a encrypted room.
+    pub fn is_encrypted(&self) -> bool {
+        self.encrypted
+    }
+
+    /// The event our own user last sent a read receipt for, `None` if we
+    /// don't know about a read receipt.
+    pub fn read_receipt(&self) -> Option<&EventId> {
+        self.read_receipt.as_ref()
+    }
+
+    /// The event our own user has fully read up to, taken from the
+    /// `m.fully_read` account data of the room.
+    pub fn fully_read(&self) -> Option<&EventId> {
+        self.fully_read.as_ref()
+    }
+
+    /// The token to page backwards through the history of the room from,
+    /// `None` if the start of the room was reached or no token is known.
     ///
-    /// Returns true if `MessageQueue` was added to.
-    #[cfg(feature = "messages")]
-    #[cfg_attr(docsrs, doc(cfg(feature = "messages")))]
-    pub fn handle_message(&mut self, event: &MessageEvent) -> bool {
-        self.messages.push(event.clone())
+    /// The token is taken from the timeline of a sync response and is moved
+    /// backwards as older events are fetched using `Client::room_messages()`.
+    pub fn prev_batch(&self) -> Option<&str> {
+        self.prev_batch.as_deref()
     }
 
-    /// Handle a room.aliases event, updating the room state if necessary.
+    /// Set the token to page backwards through the history of the room from.
     ///
-    /// Returns true if the room name changed, false otherwise.
-    pub fn handle_room_aliases(&mut self, event: &AliasesEvent) -> bool {
-        match event.content.aliases.as_slice() {
-            [alias] => self.push_room_alias(alias),
-            [alias, ..] => self.push_room_alias(alias),
-            _ => false,
+    /// Returns true if the token changed, false otherwise.
+    pub fn set_prev_batch(&mut self, token: Option<&str>) -> bool {
+        if self.prev_batch.as_deref() == token {
+            return false;
         }
+
+        self.prev_batch = token.map(ToOwned::to_owned);
+        true
     }
 
-    /// Handle a room.canonical_alias event, updating the room state if necessary.
+    /// Set the event our own user last sent a read receipt for.
     ///
-    /// Returns true if the room name changed, false otherwise.
-    pub fn handle_canonical(&mut self, event: &CanonicalAliasEvent) -> bool {
-        match &event.content.alias {
-            Some(name) => self.canonical_alias(&name),
-            _ => false,
+    /// Returns true if the read receipt changed, false otherwise.
+    pub fn set_read_receipt(&mut self, event_id: &EventId) -> bool {
+        if self.read_receipt.as_ref() == Some(event_id) {
+            return false;
         }
+
+        self.read_receipt = Some(event_id.clone());
+        true
     }
 
-    /// Handle a room.name event, updating the room state if necessary.
+    /// Set the event our own user has fully read up to.
     ///
-    /// Returns true if the room name changed, false otherwise.
-    pub fn handle_room_name(&mut self, event: &NameEvent) -> bool {
-        match event.content.name() {
-            Some(name) => self.set_room_name(name),
-            _ => false,
+    /// Returns true if the fully read marker changed, false otherwise.
+    pub fn set_fully_read(&mut self, event_id: &EventId) -> bool {
+        if self.fully_read.as_ref() == Some(event_id) {
+            return false;
         }
+
+        self.fully_read = Some(event_id.clone());
+        true
     }
 
-    /// Handle a room.name event, updating the room state if necessary.
+    /// Handle a `m.fully_read` account data event of this room.
     ///
-    /// Returns true if the room name changed, false otherwise.
-    pub fn handle_stripped_room_name(&mut self, event: &StrippedRoomName) -> bool {
-        match event.content.name() {
-            Some(name) => self.set_room_name(name),
-            _ => false,
-        }
+    /// Returns true if the fully read marker changed, false otherwise.
+    pub fn receive_fully_read(&mut self, event: &FullyReadEvent) -> bool {
+        self.set_fully_read(&event.content.event_id)
     }
 
-    /// Handle a room.power_levels event, updating the room state if necessary.
+    /// Handle a `m.tag` account data event of this room.
     ///
-    /// Returns true if the room name changed, false otherwise.
-    pub fn handle_power_level(&mut self, event: &PowerLevelsEvent) -> bool {
-        // NOTE: this is always true, we assume that if we get an event their is an update.
-        let mut updated = self.set_room_power_level(event);
+    /// Returns true if the tags of the room changed, false otherwise.
+    pub fn receive_tags(&mut self, event: &TagEvent) -> bool {
+        let content = match serde_json::to_value(&event.content) {
+            Ok(c) => c,
+            Err(_) => return false,
+        };
 
-        let mut max_power = event.content.users_default;
-        for power in event.content.users.values() {
-            max_power = *power.max(&max_power);
-        }
+        let tags = content["tags"]
+            .as_object()
+            .map(|tags| {
+                tags.iter()
+                    .map(|(tag, info)| (tag.clone(), info["order"].as_f64()))
+                    .collect()
+            })
+            .unwrap_or_default();
 
-        for user in event.content.users.keys() {
-            if let Some(member) = self.members.get_mut(user) {
-                if member.update_power(event, max_power) {
-                    updated = true;
-                }
-            }
+        if self.tags == tags {
+            false
+        } else {
+            self.tags = tags;
+            true
         }
-        updated
     }
 
-    fn handle_tombstone(&mut self, event: &TombstoneEvent) -> bool {
-        self.tombstone = Some(Tombstone {
-            body: event.content.body.clone(),
-            replacement: event.content.replacement_room.clone(),
-        });
-        true
+    /// Is the member list of the room complete.
+    ///
+    /// Syncs that lazily load members only contain the members that sent the
+    /// events of the sync, the full list is known once it was fetched with
+    /// the `/members` endpoint.
+    pub fn are_members_synced(&self) -> bool {
+        self.members_synced
     }
 
-    fn handle_encryption_event(&mut self, _event: &EncryptionEvent) -> bool {
-        self.encrypted = true;
-        true
+    /// The tags our own user put on the room, together with the order of the
+    /// room inside of the tag.
+    pub fn tags(&self) -> &BTreeMap<String, Option<f64>> {
+        &self.tags
     }
 
-    /// Receive a timeline event for this room and update the room state.
-    ///
-    /// Returns true if the joined member list changed, false otherwise.
+    /// Is this the room the homeserver sends its server notices to.
+    pub fn is_server_notice_room(&self) -> bool {
+        self.tags.contains_key(SERVER_NOTICE_TAG)
+    }
+
+    /// The latest notice the homeserver sent to the room, only server notice
+    /// rooms contain server notices.
+    pub fn server_notice(&self) -> Option<&ServerNotice> {
+        self.server_notice.as_ref()
+    }
+
+    /// The latest read receipt of the given user, `None` if the user didn't
+    /// send a read receipt we know about.
     ///
     /// # Arguments
     ///
-    /// * `event` - The event of the room.
-    pub fn receive_timeline_event(&mut self, event: &RoomEvent) -> bool {
-        match event {
-            // update to the current members of the room
-            RoomEvent::RoomMember(member) => self.handle_membership(member),
-            // finds all events related to the name of the room for later use
-            RoomEvent::RoomName(name) => self.handle_room_name(name),
-            RoomEvent::RoomCanonicalAlias(c_alias) => self.handle_canonical(c_alias),
-            RoomEvent::RoomAliases(alias) => self.handle_room_aliases(alias),
-            // power levels of the room members
-            RoomEvent::RoomPowerLevels(power) => self.handle_power_level(power),
-            RoomEvent::RoomTombstone(tomb) => self.handle_tombstone(tomb),
-            RoomEvent::RoomEncryption(encrypt) => self.handle_encryption_event(encrypt),
-            #[cfg(feature = "messages")]
-            RoomEvent::RoomMessage(msg) => self.handle_message(msg),
-            _ => false,
-        }
+    /// * `user_id` - The id of the user.
+    pub fn user_read_receipt(&self, user_id: &UserId) -> Option<&ReadReceipt> {
+        self.user_receipts.get(user_id)
+    }
+
+    /// The users that have read up to the given event, i.e. whose latest read
+    /// receipt points to the event.
+    ///
+    /// # Arguments
+    ///
+    /// * `event_id` - The id of the event.
+    pub fn read_receipts(&self, event_id: &EventId) -> &[UserId] {
+        self.event_receipts
+            .get(event_id)
+            .map(|r| r.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// Handle a `m.receipt` ephemeral event of this room.
+    ///
+    /// Returns true if a read receipt changed, false otherwise.
+    pub fn receive_receipt(&mut self, event: &ReceiptEvent) -> bool {
+        let mut changed = false;
+
+        for receipt in ReadReceipt::from_event(event) {
+            changed |= self.receive_read_receipt(receipt);
+        }
+
+        changed
+    }
+
+    /// Handle a `m.typing` ephemeral event of this room.
+    ///
+    /// The event contains the full list of users that are typing, our own
+    /// user is left out of `Room::typing_users`.
+    ///
+    /// Returns true if the list of typing users changed, false otherwise.
+    pub fn receive_typing(&mut self, event: &TypingEvent) -> bool {
+        let typing_users: Vec<UserId> = event
+            .content
+            .user_ids
+            .iter()
+            .filter(|u| **u != self.own_user_id)
+            .cloned()
+            .collect();
+
+        if typing_users == self.typing_users {
+            false
+        } else {
+            self.typing_users = typing_users;
+            true
+        }
+    }
+
+    /// Is the given user currently typing in this room.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - The id of the user.
+    pub fn is_typing(&self, user_id: &UserId) -> bool {
+        self.typing_users.contains(user_id)
+    }
+
+    /// Update the latest read receipt of a user.
+    ///
+    /// The read receipt of our own user is also available as
+    /// `Room::read_receipt()`.
+    ///
+    /// Returns true if the read receipt changed, false otherwise.
+    ///
+    /// # Arguments
+    ///
+    /// * `receipt` - The read receipt that was received.
+    pub fn receive_read_receipt(&mut self, receipt: ReadReceipt) -> bool {
+        let own_changed =
+            receipt.user_id == self.own_user_id && self.set_read_receipt(&receipt.event_id);
+
+        if let Some(previous) = self.user_receipts.get(&receipt.user_id) {
+            if previous.event_id == receipt.event_id {
+                return own_changed;
+            }
+
+            if let Some(users) = self.event_receipts.get_mut(&previous.event_id) {
+                users.retain(|u| u != &receipt.user_id);
+                if users.is_empty() {
+                    self.event_receipts.remove(&previous.event_id);
+                }
+            }
+        }
+
+        self.event_receipts
+            .entry(receipt.event_id.clone())
+            .or_insert_with(Vec::new)
+            .push(receipt.user_id.clone());
+        self.user_receipts.insert(receipt.user_id.clone(), receipt);
+        true
+    }
+
+    /// The number of stored messages that other users sent after our own
+    /// read receipt.
+    ///
+    /// Every stored message of another user is counted if the read receipt
+    /// isn't known or points to an event that isn't stored.
+    #[cfg(feature = "messages")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "messages")))]
+    pub fn unread_message_count(&self) -> usize {
+        let unread = self.messages.iter().rev().take_while(|m| {
+            self.read_receipt
+                .as_ref()
+                .map_or(true, |id| &m.event_id != id)
+        });
+
+        unread.filter(|m| m.sender != self.own_user_id).count()
+    }
+
+    /// The local echoes of the messages our own user sent that didn't come
+    /// down the sync stream yet, the oldest one first.
+    ///
+    /// UIs can render these after the timeline to show that the messages are
+    /// still being sent.
+    pub fn pending_events(&self) -> &[PendingEvent] {
+        &self.pending_events
+    }
+
+    /// Add the local echo of a message our own user sent.
+    ///
+    /// Returns false if there already is a local echo with the same
+    /// transaction id.
+    pub(crate) fn add_pending_event(&mut self, event: PendingEvent) -> bool {
+        if self.pending_events.iter().any(|e| e.txn_id == event.txn_id) {
+            return false;
+        }
+
+        self.pending_events.push(event);
+        true
+    }
+
+    /// Update the send state of a local echo.
+    ///
+    /// Returns true if a local echo with the transaction id exists.
+    pub(crate) fn update_pending_event(&mut self, txn_id: &str, state: SendState) -> bool {
+        // The event may have come down the sync stream before the server
+        // answered the send request, the local echo isn't needed anymore.
+        #[cfg(feature = "messages")]
+        {
+            if let SendState::Sent(event_id) = &state {
+                if self.timeline.was_seen(event_id) {
+                    return self.remove_pending_event(txn_id).is_some();
+                }
+            }
+        }
+
+        match self.pending_events.iter_mut().find(|e| e.txn_id == txn_id) {
+            Some(event) => {
+                event.state = state;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Remove a local echo, e.g. because the message won't be sent.
+    pub(crate) fn remove_pending_event(&mut self, txn_id: &str) -> Option<PendingEvent> {
+        let position = self
+            .pending_events
+            .iter()
+            .position(|e| e.txn_id == txn_id)?;
+        Some(self.pending_events.remove(position))
+    }
+
+    /// The ordered events of the room, see `Timeline`.
+    #[cfg(feature = "messages")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "messages")))]
+    pub fn timeline(&self) -> &Timeline {
+        &self.timeline
+    }
+
+    #[cfg(feature = "messages")]
+    pub(crate) fn timeline_mut(&mut self) -> &mut Timeline {
+        &mut self.timeline
+    }
+
+    /// The previews of the URLs that were found in the given message.
+    ///
+    /// Previews are only generated if `ClientConfig::url_previews()` is
+    /// enabled, they are added once the homeserver generated them.
+    pub fn url_previews(&self, event_id: &EventId) -> &[UrlPreview] {
+        self.url_previews
+            .get(event_id)
+            .map(|p| p.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// Attach the preview of a URL to the message it was found in.
+    ///
+    /// Returns false if the message already has a preview for the URL.
+    pub(crate) fn add_url_preview(&mut self, event_id: &EventId, preview: UrlPreview) -> bool {
+        let previews = self
+            .url_previews
+            .entry(event_id.clone())
+            .or_insert_with(Vec::new);
+
+        if previews.iter().any(|p| p.url == preview.url) {
+            return false;
+        }
+
+        previews.push(preview);
+        true
+    }
+
+    /// The latest content of the given message, taking edits into account.
+    ///
+    /// Returns `None` if the message wasn't edited and isn't part of the
+    /// stored messages of the room.
+    ///
+    /// # Arguments
+    ///
+    /// * `event_id` - The id of the message.
+    pub fn latest_content(&self, event_id: &EventId) -> Option<MessageEventContent> {
+        if let Some((_, content)) = self.edits.get(event_id) {
+            if let Ok(content) = serde_json::from_value(content.clone()) {
+                return Some(content);
+            }
+        }
+
+        #[cfg(feature = "messages")]
+        {
+            self.messages.get(event_id).map(|m| m.content.clone())
+        }
+        #[cfg(not(feature = "messages"))]
+        {
+            None
+        }
+    }
+
+    /// Returns true if the given message was edited.
+    pub fn is_edited(&self, event_id: &EventId) -> bool {
+        self.edits.contains_key(event_id)
+    }
+
+    /// Handle the raw JSON of a `m.room.message` event that replaces the
+    /// content of an earlier message.
+    ///
+    /// Edits of stored messages are applied to the `MessageQueue`, edits
+    /// from a different sender than the one of the original message are
+    /// ignored.
+    ///
+    /// Returns true if the event was an edit that was applied, false
+    /// otherwise.
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - The JSON of the event.
+    pub fn receive_edit(&mut self, event: &JsonValue) -> bool {
+        if event.get("type").and_then(JsonValue::as_str) != Some("m.room.message") {
+            return false;
+        }
+
+        let content = &event["content"];
+        let relation = &content["m.relates_to"];
+
+        if relation.get("rel_type").and_then(JsonValue::as_str) != Some("m.replace") {
+            return false;
+        }
+
+        let target = match relation
+            .get("event_id")
+            .and_then(JsonValue::as_str)
+            .and_then(|id| EventId::try_from(id).ok())
+        {
+            Some(id) => id,
+            None => return false,
+        };
+        let sender = match event
+            .get("sender")
+            .and_then(JsonValue::as_str)
+            .and_then(|id| UserId::try_from(id).ok())
+        {
+            Some(id) => id,
+            None => return false,
+        };
+        let new_content = match content.get("m.new_content") {
+            Some(c) => c.clone(),
+            None => return false,
+        };
+
+        if let Some((edit_sender, _)) = self.edits.get(&target) {
+            if edit_sender != &sender {
+                return false;
+            }
+        }
+
+        #[cfg(feature = "messages")]
+        {
+            if let Some(original) = self.messages.get(&target) {
+                if original.sender != sender {
+                    return false;
+                }
+            }
+
+            if let Ok(c) = serde_json::from_value(new_content.clone()) {
+                self.messages.replace_content(&target, c);
+            }
+        }
+
+        self.edits.insert(target, (sender, new_content));
+        true
+    }
+
+    /// The reactions to the given event, grouped by their key.
+    ///
+    /// # Arguments
+    ///
+    /// * `event_id` - The id of the event.
+    pub fn reactions(&self, event_id: &EventId) -> BTreeMap<String, ReactionGroup> {
+        let mut groups: BTreeMap<String, ReactionGroup> = BTreeMap::new();
+
+        for reaction in self.reaction_events(event_id) {
+            let group = groups.entry(reaction.key.clone()).or_default();
+            group.count += 1;
+            group.senders.push(reaction.sender.clone());
+        }
+
+        groups
+    }
+
+    /// The reaction events to the given event, the oldest one first.
+    ///
+    /// # Arguments
+    ///
+    /// * `event_id` - The id of the event.
+    pub fn reaction_events(&self, event_id: &EventId) -> &[Reaction] {
+        self.reactions
+            .get(event_id)
+            .map(|r| r.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// Add a reaction to the event it relates to.
+    ///
+    /// A user can react with the same key only once, further reactions are
+    /// ignored.
+    ///
+    /// Returns true if the reaction was added, false otherwise.
+    ///
+    /// # Arguments
+    ///
+    /// * `reaction` - The reaction that was received.
+    pub fn receive_reaction(&mut self, reaction: Reaction) -> bool {
+        let reactions = self
+            .reactions
+            .entry(reaction.relates_to.clone())
+            .or_insert_with(Vec::new);
+
+        if reactions.iter().any(|r| {
+            r.event_id == reaction.event_id
+                || (r.sender == reaction.sender && r.key == reaction.key)
+        }) {
+            return false;
+        }
+
+        reactions.push(reaction);
+        true
+    }
+
+    /// Handle a room.redaction event, applying the redaction to the stored
+    /// messages and the room state.
+    ///
+    /// The content of a redacted message is blanked, room state that was taken
+    /// from a redacted state event is removed, the same as if the content of
+    /// the event was stripped by the redaction algorithm. Reactions and edits
+    /// that were redacted are removed as well.
+    ///
+    /// Returns true if the room state changed, false otherwise.
+    fn handle_redaction(&mut self, event: &RedactionEvent) -> bool {
+        let redacts = &event.redacts;
+        let mut changed = false;
+
+        for reactions in self.reactions.values_mut() {
+            let len = reactions.len();
+            reactions.retain(|r| &r.event_id != redacts);
+            changed |= len != reactions.len();
+        }
+
+        self.reactions.retain(|_, r| !r.is_empty());
+
+        #[cfg(feature = "messages")]
+        {
+            changed |= self.messages.redact(redacts);
+        }
+
+        changed |= self.edits.remove(redacts).is_some();
+        changed |= self.url_previews.remove(redacts).is_some();
+
+        if let Some(state) = self.state_event_ids.remove(redacts) {
+            changed |= self.redact_state(state);
+        }
+
+        changed
+    }
+
+    /// Remove the room state that was taken from a redacted state event.
+    ///
+    /// Only the keys of the content that survive the redaction algorithm are
+    /// kept, e.g. the membership of a member event but not the display name.
+    fn redact_state(&mut self, state: RedactableState) -> bool {
+        match state {
+            RedactableState::Name => self.room_name.name.take().is_some(),
+            RedactableState::CanonicalAlias => self.room_name.canonical_alias.take().is_some(),
+            RedactableState::Avatar => self.avatar_url.take().is_some(),
+            RedactableState::Topic => self.topic.take().is_some(),
+            RedactableState::Tombstone => self.tombstone.take().is_some(),
+            RedactableState::Member(user_id) => match self.members.get_mut(&user_id) {
+                Some(member) => {
+                    let changed = member.display_name.is_some() || member.avatar_url.is_some();
+                    member.display_name = None;
+                    member.avatar_url = None;
+                    self.update_display_name_ambiguity();
+                    changed
+                }
+                None => false,
+            },
+        }
+    }
+
+    /// Remember the id of the state event the given part of the room state was
+    /// taken from, replacing the event it was taken from before.
+    fn track_state_event(&mut self, event_id: &EventId, state: RedactableState) {
+        self.state_event_ids.retain(|_, s| s != &state);
+        self.state_event_ids.insert(event_id.clone(), state);
+    }
+
+    /// Remove the local echo that belongs to a message that came down the
+    /// sync stream.
+    fn reconcile_pending_event(&mut self, event: &RoomEvent) {
+        if self.pending_events.is_empty() {
+            return;
+        }
+
+        let (event_id, txn_id) = match event {
+            RoomEvent::RoomMessage(m) => (&m.event_id, m.unsigned.transaction_id.as_ref()),
+            RoomEvent::RoomEncrypted(e) => (&e.event_id, e.unsigned.transaction_id.as_ref()),
+            _ => return,
+        };
+
+        self.pending_events.retain(|p| {
+            let same_event = match &p.state {
+                SendState::Sent(id) => id == event_id,
+                _ => false,
+            };
+
+            !same_event && Some(&p.txn_id) != txn_id
+        });
+    }
+
+    /// The power level of the given user in this room.
+    ///
+    /// This is the same as `power_level_for()`.
+    pub fn power_level(&self, user_id: &UserId) -> Int {
+        self.power_level_for(user_id)
+    }
+
+    /// The power level of the given user in this room, the user doesn't need
+    /// to be a member of the room.
+    ///
+    /// If the room has no `m.room.power_levels` state event the creator of
+    /// the room has a power level of 100 and every other user a power level
+    /// of 0.
+    pub fn power_level_for(&self, user_id: &UserId) -> Int {
+        match &self.power_levels {
+            Some(levels) => levels
+                .users
+                .get(user_id)
+                .copied()
+                .or_else(|| self.members.get(user_id).and_then(|m| m.power_level))
+                .unwrap_or(levels.users_default),
+            None if self.creator.as_ref() == Some(user_id) => Int::from(100),
+            None => Int::from(0),
+        }
+    }
+
+    /// The power level that is required to send a message event of the given
+    /// type in this room.
+    ///
+    /// # Arguments
+    ///
+    /// * `event_type` - The type of the message event.
+    pub fn required_level_for_event(&self, event_type: &EventType) -> Int {
+        match &self.power_levels {
+            Some(levels) => levels
+                .events
+                .get(event_type)
+                .copied()
+                .unwrap_or(levels.events_default),
+            None => Int::from(0),
+        }
+    }
+
+    /// The power level that is required to send a state event of the given
+    /// type in this room.
+    ///
+    /// # Arguments
+    ///
+    /// * `event_type` - The type of the state event.
+    pub fn required_level_for_state_event(&self, event_type: &EventType) -> Int {
+        match &self.power_levels {
+            Some(levels) => levels
+                .events
+                .get(event_type)
+                .copied()
+                .unwrap_or(levels.state_default),
+            None => Int::from(0),
+        }
+    }
+
+    /// The power level that is required for the given action in this room.
+    pub fn required_level(&self, action: &PowerAction) -> Int {
+        let level = |f: fn(&PowerLevels) -> Int| {
+            self.power_levels
+                .as_ref()
+                .map_or(Int::from(DEFAULT_POWER_LEVEL), f)
+        };
+
+        match action {
+            PowerAction::Ban => level(|l| l.ban),
+            PowerAction::Kick => level(|l| l.kick),
+            PowerAction::Invite => level(|l| l.invite),
+            PowerAction::Redact => level(|l| l.redact),
+            PowerAction::NotifyRoom => level(|l| l.notifications),
+            PowerAction::SendMessage(event_type) => self.required_level_for_event(event_type),
+            PowerAction::SendState(event_type) => self.required_level_for_state_event(event_type),
+        }
+    }
+
+    /// Does the given user have the power level that is required for the
+    /// action.
+    ///
+    /// Banning or kicking a user additionally requires a higher power level
+    /// than the user that is banned or kicked, see `can_ban()` and
+    /// `can_kick()`.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - The user that wants to perform the action.
+    ///
+    /// * `action` - The action the user wants to perform.
+    pub fn can_user_do(&self, user_id: &UserId, action: &PowerAction) -> bool {
+        self.power_level_for(user_id) >= self.required_level(action)
+    }
+
+    /// Check if our own user has the power level that is required for the
+    /// action.
+    ///
+    /// Unlike `can_user_do()` this returns an error that describes the
+    /// missing permission, e.g. to tell the user of a bot why it can't send a
+    /// message before the server refuses it.
+    ///
+    /// # Arguments
+    ///
+    /// * `action` - The action our own user wants to perform.
+    pub fn check_permission(&self, action: &PowerAction) -> StdResult<(), PermissionError> {
+        let own = self.power_level_for(&self.own_user_id);
+        let required = self.required_level(action);
+
+        if own >= required {
+            Ok(())
+        } else {
+            Err(PermissionError::InsufficientPowerLevel {
+                action: action.clone(),
+                required,
+                own,
+            })
+        }
+    }
+
+    /// The room that replaced this room after it was upgraded, taken from the
+    /// `m.room.tombstone` state event.
+    ///
+    /// Returns `None` if the room wasn't upgraded.
+    pub fn successor(&self) -> Option<&RoomId> {
+        self.tombstone.as_ref().map(|t| &t.replacement)
+    }
+
+    /// Is our own user allowed to ban or unban the given user.
+    ///
+    /// Our own power level needs to be at least the ban level of the room
+    /// and higher than the power level of the user.
+    pub fn can_ban(&self, user_id: &UserId) -> bool {
+        let required = self.required_level(&PowerAction::Ban);
+        self.can_moderate(user_id, required)
+    }
+
+    /// Is our own user allowed to kick the given user out of the room.
+    ///
+    /// Our own power level needs to be at least the kick level of the room
+    /// and higher than the power level of the user.
+    pub fn can_kick(&self, user_id: &UserId) -> bool {
+        let required = self.required_level(&PowerAction::Kick);
+        self.can_moderate(user_id, required)
+    }
+
+    /// Is our own user allowed to invite other users into the room.
+    pub fn can_invite(&self) -> bool {
+        self.can_user_do(&self.own_user_id, &PowerAction::Invite)
+    }
+
+    /// Is our own user allowed to redact events sent by other users.
+    pub fn can_redact(&self) -> bool {
+        self.can_user_do(&self.own_user_id, &PowerAction::Redact)
+    }
+
+    fn can_moderate(&self, user_id: &UserId, required: Int) -> bool {
+        let own_level = self.power_level(&self.own_user_id);
+        own_level >= required && own_level > self.power_level(user_id)
+    }
+
+    /// Take a snapshot of the state of the room that is tracked by the client.
+    ///
+    /// The snapshot contains the known state events as well as the state
+    /// events that aren't known to the SDK. Keep the snapshot around and pass
+    /// it to `state_changes_since()` later on to find out which state entries
+    /// were added, changed or removed in the meantime.
+    ///
+    /// The snapshot doesn't know which sync it belongs to, use
+    /// `BaseClient::room_state_snapshot()` to get a snapshot that carries the
+    /// current sync token.
+    pub fn state_snapshot(&self) -> RoomStateSnapshot {
+        let mut snapshot = RoomStateSnapshot::new();
+
+        let mut insert = |event_type: &str, state_key: &str, content: JsonValue| {
+            snapshot.insert(event_type, state_key, content)
+        };
+
+        insert(
+            "m.room.create",
+            "",
+            self.creator
+                .as_ref()
+                .map(|c| json!({ "creator": c }))
+                .unwrap_or_default(),
+        );
+        insert(
+            "m.room.name",
+            "",
+            self.room_name
+                .name
+                .as_ref()
+                .map(|n| json!({ "name": n }))
+                .unwrap_or_default(),
+        );
+        insert(
+            "m.room.canonical_alias",
+            "",
+            self.room_name
+                .canonical_alias
+                .as_ref()
+                .map(|a| json!({ "alias": a }))
+                .unwrap_or_default(),
+        );
+        if !self.room_name.aliases.is_empty() {
+            insert(
+                "m.room.aliases",
+                "",
+                json!({ "aliases": self.room_name.aliases }),
+            );
+        }
+        if self.encrypted {
+            insert("m.room.encryption", "", json!({}));
+        }
+        insert(
+            "m.room.power_levels",
+            "",
+            serde_json::to_value(&self.power_levels).unwrap_or_default(),
+        );
+        insert(
+            "m.room.tombstone",
+            "",
+            serde_json::to_value(&self.tombstone).unwrap_or_default(),
+        );
+        insert(
+            "m.room.retention",
+            "",
+            serde_json::to_value(&self.retention).unwrap_or_default(),
+        );
+        insert(
+            "m.room.join_rules",
+            "",
+            serde_json::to_value(&self.join_rule).unwrap_or_default(),
+        );
+        insert(
+            "m.room.avatar",
+            "",
+            self.avatar_url
+                .as_ref()
+                .map(|u| json!({ "url": u }))
+                .unwrap_or_default(),
+        );
+        insert(
+            "m.room.guest_access",
+            "",
+            serde_json::to_value(&self.guest_access).unwrap_or_default(),
+        );
+        insert(
+            "m.room.history_visibility",
+            "",
+            serde_json::to_value(&self.history_visibility).unwrap_or_default(),
+        );
+        insert(
+            "m.room.server_acl",
+            "",
+            serde_json::to_value(&self.server_acl).unwrap_or_default(),
+        );
+        insert(
+            "m.room.topic",
+            "",
+            self.topic
+                .as_ref()
+                .map(|t| json!({ "topic": t }))
+                .unwrap_or_default(),
+        );
+
+        for (room_id, child) in &self.space.children {
+            insert(
+                "m.space.child",
+                &room_id.to_string(),
+                serde_json::to_value(child).unwrap_or_default(),
+            );
+        }
+        for (room_id, parent) in &self.space.parents {
+            insert(
+                "m.space.parent",
+                &room_id.to_string(),
+                serde_json::to_value(parent).unwrap_or_default(),
+            );
+        }
+
+        for (user_id, member) in &self.members {
+            insert(
+                "m.room.member",
+                &user_id.to_string(),
+                json!({
+                    "membership": member.membership,
+                    "displayname": member.display_name,
+                    "avatar_url": member.avatar_url,
+                }),
+            );
+        }
+
+        for (event_type, state) in &self.custom_state {
+            for (state_key, content) in state {
+                insert(event_type, state_key, content.clone());
+            }
+        }
+
+        snapshot
+    }
+
+    /// Get the state changes of the room since the given snapshot was taken.
+    ///
+    /// # Arguments
+    ///
+    /// * `snapshot` - A snapshot that was previously returned by
+    /// `state_snapshot()`.
+    pub fn state_changes_since(&self, snapshot: &RoomStateSnapshot) -> Vec<StateChange> {
+        snapshot.diff(&self.state_snapshot())
+    }
+
+    /// Write the members of the room out, e.g. for an audit.
+    ///
+    /// Every member is written with its display name, membership, power
+    /// level and the time it joined at in milliseconds since the unix epoch,
+    /// the members are sorted by their user id. The join time is left empty
+    /// if the member wasn't seen joining.
+    ///
+    /// # Arguments
+    ///
+    /// * `writer` - Where the members are written to.
+    ///
+    /// * `format` - The format the members are written in.
+    pub fn export_members<W: Write>(
+        &self,
+        mut writer: W,
+        format: MemberExportFormat,
+    ) -> Result<()> {
+        const COLUMNS: [&str; 5] = [
+            "user_id",
+            "display_name",
+            "membership",
+            "power_level",
+            "joined_at",
+        ];
+
+        let mut members: Vec<&RoomMember> = self.members.values().collect();
+        members.sort_by_key(|m| m.user_id.to_string());
+
+        let rows: Vec<JsonValue> = members
+            .into_iter()
+            .map(|m| {
+                let joined_at = m
+                    .joined_at
+                    .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                    .map(|d| d.as_millis() as u64);
+
+                json!({
+                    "user_id": m.user_id,
+                    "display_name": m.display_name,
+                    "membership": m.membership,
+                    "power_level": self.power_level(&m.user_id),
+                    "joined_at": joined_at,
+                })
+            })
+            .collect();
+
+        match format {
+            MemberExportFormat::Json => serde_json::to_writer_pretty(&mut writer, &rows)?,
+            MemberExportFormat::Csv => {
+                writeln!(writer, "{}", COLUMNS.join(","))?;
+
+                for row in &rows {
+                    let fields: Vec<String> = COLUMNS.iter().map(|c| csv_field(&row[*c])).collect();
+                    writeln!(writer, "{}", fields.join(","))?;
+                }
+            }
+        }
+
+        writer.flush()?;
+        Ok(())
+    }
+
+    fn add_member(&mut self, user_id: UserId, event: &MemberEvent) -> bool {
+        if self.members.contains_key(&user_id) {
+            return false;
+        }
+
+        let member = RoomMember::new(event);
+        self.members.insert(user_id, member);
+
+        true
+    }
+
+    /// Add to the list of `RoomAliasId`s.
+    fn push_room_alias(&mut self, alias: &RoomAliasId) -> bool {
+        self.room_name.push_alias(alias.clone());
+        true
+    }
+
+    /// RoomAliasId is `#alias:hostname` and `port`
+    fn canonical_alias(&mut self, alias: &RoomAliasId) -> bool {
+        self.room_name.set_canonical(alias.clone());
+        true
+    }
+
+    fn set_room_name(&mut self, name: &str) -> bool {
+        self.room_name.set_name(name);
+        true
+    }
+
+    fn set_room_power_level(&mut self, event: &PowerLevelsEvent) -> bool {
+        let PowerLevelsEventContent {
+            ban,
+            events,
+            events_default,
+            invite,
+            kick,
+            redact,
+            state_default,
+            users_default,
+            users,
+            notifications: NotificationPowerLevels { room },
+            ..
+        } = &event.content;
+
+        let power = PowerLevels {
+            ban: *ban,
+            events: events.clone(),
+            events_default: *events_default,
+            invite: *invite,
+            kick: *kick,
+            redact: *redact,
+            state_default: *state_default,
+            users_default: *users_default,
+            users: users.clone(),
+            notifications: *room,
+        };
+        self.power_levels = Some(power);
+        true
+    }
+
+    pub(crate) fn set_room_summary(&mut self, summary: &RoomSummary) {
+        let RoomSummary {
+            heroes,
+            joined_member_count,
+            invited_member_count,
+        } = summary;
+        // The fields of the summary are only sent if they changed since the
+        // last sync, so missing fields keep their previous value.
+        if !heroes.is_empty() {
+            self.room_name.heroes = heroes.clone();
+        }
+        if invited_member_count.is_some() {
+            self.room_name.invited_member_count = *invited_member_count;
+        }
+        if joined_member_count.is_some() {
+            self.room_name.joined_member_count = *joined_member_count;
+        }
+    }
+
+    pub(crate) fn set_unread_notice_count(&mut self, notifications: &UnreadNotificationsCount) {
+        self.unread_highlight = notifications.highlight_count;
+        self.unread_notifications = notifications.notification_count;
+    }
+
+    /// Handle a room.member updating the room state if necessary.
+    ///
+    /// Returns true if the joined member list changed, false otherwise.
+    pub fn handle_membership(&mut self, event: &MemberEvent) -> bool {
+        // Member events with a state key that isn't a valid user id can only
+        // come from a buggy or malicious server, ignore them.
+        let user_id = match UserId::try_from(event.state_key.as_str()) {
+            Ok(u) => u,
+            Err(_) => return false,
+        };
+
+        self.track_state_event(&event.event_id, RedactableState::Member(user_id.clone()));
+
+        let changed = match event.membership_change() {
+            MembershipChange::Invited | MembershipChange::Joined => self.add_member(user_id, event),
+            _ => {
+                if let Some(member) = self.members.get_mut(&user_id) {
+                    member.update_member(event)
+                } else {
+                    false
+                }
+            }
+        };
+
+        if changed {
+            self.update_display_name_ambiguity();
+        }
+
+        changed
+    }
+
+    /// Merge the full member list of the room into the known members.
+    ///
+    /// The member events are the current `m.room.member` state of the room,
+    /// e.g. the response of the `/members` endpoint. Members that weren't
+    /// known yet because the sync lazily loaded them are added, and the member
+    /// list is marked as complete.
+    ///
+    /// Returns true if the member list changed or wasn't complete before,
+    /// false otherwise.
+    ///
+    /// # Arguments
+    ///
+    /// * `events` - The member events of the room.
+    pub fn receive_members(&mut self, events: &[MemberEvent]) -> bool {
+        let mut changed = false;
+
+        for event in events {
+            let user_id = match UserId::try_from(event.state_key.as_str()) {
+                Ok(u) => u,
+                Err(_) => continue,
+            };
+
+            match self.members.get_mut(&user_id) {
+                Some(member) => {
+                    let content = &event.content;
+
+                    if member.membership != content.membership
+                        || member.display_name != content.displayname
+                        || member.avatar_url != content.avatar_url
+                    {
+                        member.membership = content.membership;
+                        member.display_name = content.displayname.clone();
+                        member.avatar_url = content.avatar_url.clone();
+                        changed = true;
+                    }
+                }
+                None => match event.content.membership {
+                    MembershipState::Join | MembershipState::Invite => {
+                        self.members.insert(user_id, RoomMember::new(event));
+                        changed = true;
+                    }
+                    _ => {}
+                },
+            }
+        }
+
+        if changed {
+            self.update_display_name_ambiguity();
+        }
+
+        if !self.members_synced {
+            self.members_synced = true;
+            changed = true;
+        }
+
+        changed
+    }
+
+    /// Mark the members whose display name is shared with another joined or
+    /// invited member, so their name can be disambiguated.
+    ///
+    /// Returns true if any member was marked or unmarked.
+    fn update_display_name_ambiguity(&mut self) -> bool {
+        let is_present = |m: &RoomMember| {
+            m.membership == MembershipState::Join || m.membership == MembershipState::Invite
+        };
+
+        let mut counts: HashMap<String, usize> = HashMap::new();
+
+        for member in self.members.values().filter(|m| is_present(*m)) {
+            if let Some(name) = member.display_name.as_ref().filter(|n| !n.is_empty()) {
+                *counts.entry(name.clone()).or_default() += 1;
+            }
+        }
+
+        let mut changed = false;
+
+        for member in self.members.values_mut() {
+            let ambiguous = is_present(member)
+                && member
+                    .display_name
+                    .as_ref()
+                    .and_then(|n| counts.get(n))
+                    .map_or(false, |c| *c > 1);
+
+            changed |= member.display_name_ambiguous != ambiguous;
+            member.display_name_ambiguous = ambiguous;
+        }
+
+        changed
+    }
+
+    /// Handle a room.message event and update the `MessageQueue` if necessary.
+    ///
+    /// Returns true if `MessageQueue` was added to.
+    #[cfg(feature = "messages")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "messages")))]
+    pub fn handle_message(&mut self, event: &MessageEvent) -> bool {
+        self.messages.push(event.clone())
+    }
+
+    /// Handle a room.aliases event, updating the room state if necessary.
+    ///
+    /// Returns true if the room name changed, false otherwise.
+    pub fn handle_room_aliases(&mut self, event: &AliasesEvent) -> bool {
+        match event.content.aliases.as_slice() {
+            [alias] => self.push_room_alias(alias),
+            [alias, ..] => self.push_room_alias(alias),
+            _ => false,
+        }
+    }
+
+    /// Handle a room.canonical_alias event, updating the room state if necessary.
+    ///
+    /// Returns true if the room name changed, false otherwise.
+    pub fn handle_canonical(&mut self, event: &CanonicalAliasEvent) -> bool {
+        self.track_state_event(&event.event_id, RedactableState::CanonicalAlias);
+
+        match &event.content.alias {
+            Some(name) => self.canonical_alias(&name),
+            _ => false,
+        }
+    }
+
+    /// Handle a room.name event, updating the room state if necessary.
+    ///
+    /// Returns true if the room name changed, false otherwise.
+    pub fn handle_room_name(&mut self, event: &NameEvent) -> bool {
+        self.track_state_event(&event.event_id, RedactableState::Name);
+
+        match event.content.name() {
+            Some(name) => self.set_room_name(name),
+            _ => false,
+        }
+    }
+
+    /// Handle a room.name event, updating the room state if necessary.
+    ///
+    /// Returns true if the room name changed, false otherwise.
+    pub fn handle_stripped_room_name(&mut self, event: &StrippedRoomName) -> bool {
+        match event.content.name() {
+            Some(name) => self.set_room_name(name),
+            _ => false,
+        }
+    }
+
+    /// Handle a stripped room.member event of an invite, remembering the
+    /// profile of the member and the user that invited us.
+    ///
+    /// Returns true if the room state changed, false otherwise.
+    fn handle_stripped_member(&mut self, event: &StrippedRoomMember) -> bool {
+        let user_id = match UserId::try_from(event.state_key.as_str()) {
+            Ok(id) => id,
+            Err(_) => return false,
+        };
+        let mut changed = false;
+
+        if user_id == self.own_user_id
+            && event.content.membership == MembershipState::Invite
+            && self.inviter.as_ref() != Some(&event.sender)
+        {
+            self.inviter = Some(event.sender.clone());
+            changed = true;
+        }
+
+        let member = StrippedMember {
+            display_name: event.content.displayname.clone(),
+            avatar_url: event.content.avatar_url.clone(),
+        };
+        if self.stripped_members.get(&user_id) != Some(&member) {
+            self.stripped_members.insert(user_id, member);
+            changed = true;
+        }
+
+        changed
+    }
+
+    /// Handle a room.power_levels event, updating the room state if necessary.
+    ///
+    /// Returns true if the room name changed, false otherwise.
+    pub fn handle_power_level(&mut self, event: &PowerLevelsEvent) -> bool {
+        // NOTE: this is always true, we assume that if we get an event their is an update.
+        let mut updated = self.set_room_power_level(event);
+
+        let mut max_power = event.content.users_default;
+        for power in event.content.users.values() {
+            max_power = *power.max(&max_power);
+        }
+
+        for user in event.content.users.keys() {
+            if let Some(member) = self.members.get_mut(user) {
+                if member.update_power(event, max_power) {
+                    updated = true;
+                }
+            }
+        }
+        updated
+    }
+
+    fn handle_tombstone(&mut self, event: &TombstoneEvent) -> bool {
+        self.track_state_event(&event.event_id, RedactableState::Tombstone);
+        self.tombstone = Some(Tombstone {
+            body: event.content.body.clone(),
+            replacement: event.content.replacement_room.clone(),
+        });
+        true
+    }
+
+    fn handle_server_notice(&mut self, event: &MessageEvent) -> bool {
+        let notice = serde_json::to_value(&event.content)
+            .ok()
+            .and_then(|c| ServerNotice::from_content(&c));
+
+        match notice {
+            Some(notice) => {
+                self.server_notice = Some(notice);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn handle_encryption_event(&mut self, _event: &EncryptionEvent) -> bool {
+        self.encrypted = true;
+        true
+    }
+
+    /// Handle the content of a `m.room.encryption` event that isn't part of a
+    /// full state event, e.g. the stripped state of an invite.
+    ///
+    /// Returns true if the room wasn't known to be encrypted before, false
+    /// otherwise.
+    fn handle_encryption_content(&mut self, content: JsonValue) -> bool {
+        if serde_json::from_value::<EncryptionEventContent>(content).is_err() || self.encrypted {
+            return false;
+        }
+
+        self.encrypted = true;
+        true
+    }
+
+    /// Handle the content of a `m.room.join_rules` event, updating the room
+    /// state if necessary.
+    ///
+    /// Returns true if the join rule changed, false otherwise.
+    fn handle_join_rules(&mut self, content: JsonValue) -> bool {
+        match serde_json::from_value(content) {
+            Ok(rule) => {
+                let rule = Some(rule);
+                let changed = self.join_rule != rule;
+                self.join_rule = rule;
+                changed
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// Handle the content of a `m.room.guest_access` event, updating the room
+    /// state if necessary.
+    ///
+    /// Returns true if the guest access changed, false otherwise.
+    fn handle_guest_access(&mut self, content: JsonValue) -> bool {
+        match serde_json::from_value(content) {
+            Ok(access) => {
+                let access = Some(access);
+                let changed = self.guest_access != access;
+                self.guest_access = access;
+                changed
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// Handle the content of a `m.room.history_visibility` event, updating
+    /// the room state if necessary.
+    ///
+    /// Returns true if the history visibility changed, false otherwise.
+    fn handle_history_visibility(&mut self, content: JsonValue) -> bool {
+        match serde_json::from_value(content) {
+            Ok(visibility) => {
+                let visibility = Some(visibility);
+                let changed = self.history_visibility != visibility;
+                self.history_visibility = visibility;
+                changed
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// Handle the content of a `m.room.server_acl` event, updating the room
+    /// state if necessary.
+    ///
+    /// Returns true if the server ACL changed, false otherwise.
+    fn handle_server_acl(&mut self, content: JsonValue) -> bool {
+        match serde_json::from_value(content) {
+            Ok(acl) => {
+                let acl = Some(acl);
+                let changed = self.server_acl != acl;
+                self.server_acl = acl;
+                changed
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// Handle the content of a `m.room.avatar` event, updating the room state
+    /// if necessary.
+    ///
+    /// Content without an URL removes the avatar of the room.
+    ///
+    /// Returns true if the avatar changed, false otherwise.
+    fn handle_room_avatar(&mut self, content: JsonValue) -> bool {
+        let url = content
+            .get("url")
+            .and_then(JsonValue::as_str)
+            .filter(|u| !u.is_empty())
+            .map(str::to_owned);
+
+        let changed = self.avatar_url != url;
+        self.avatar_url = url;
+        changed
+    }
+
+    /// Handle the content of a `m.room.topic` event, updating the room state
+    /// if necessary.
+    ///
+    /// An empty topic removes the topic of the room.
+    ///
+    /// Returns true if the topic changed, false otherwise.
+    fn handle_room_topic(&mut self, content: JsonValue) -> bool {
+        let topic = content
+            .get("topic")
+            .and_then(JsonValue::as_str)
+            .filter(|t| !t.is_empty())
+            .map(str::to_owned);
+
+        let changed = self.topic != topic;
+        self.topic = topic;
+        changed
+    }
+
+    /// Handle a custom state event, updating the room state if necessary.
+    ///
+    /// Returns true if the room state changed, false otherwise.
+    fn handle_custom_state(&mut self, event: &CustomStateEvent) -> bool {
+        let mut changed = self.store_custom_state(event);
+
+        changed |= match event.event_type.as_str() {
+            "m.room.retention" => match serde_json::from_value(event.content.clone()) {
+                Ok(retention) => {
+                    self.retention = Some(retention);
+                    true
+                }
+                Err(_) => false,
+            },
+            "m.space.child" => {
+                let room_id = match RoomId::try_from(event.state_key.as_str()) {
+                    Ok(id) => id,
+                    Err(_) => return false,
+                };
+                let content: SpaceChildContent =
+                    serde_json::from_value(event.content.clone()).unwrap_or_default();
+
+                // A child without any servers to join it through has been
+                // removed from the space.
+                if content.via.is_empty() {
+                    self.space.children.remove(&room_id).is_some()
+                } else {
+                    self.space.children.insert(room_id, content.clone()) != Some(content)
+                }
+            }
+            "m.space.parent" => {
+                let room_id = match RoomId::try_from(event.state_key.as_str()) {
+                    Ok(id) => id,
+                    Err(_) => return false,
+                };
+                let content: SpaceParentContent =
+                    serde_json::from_value(event.content.clone()).unwrap_or_default();
+
+                if content.via.is_empty() {
+                    self.space.parents.remove(&room_id).is_some()
+                } else {
+                    self.space.parents.insert(room_id, content.clone()) != Some(content)
+                }
+            }
+            _ => false,
+        };
+
+        changed
+    }
+
+    /// Store the content of a custom state event so it can be read with
+    /// `get_state_event()`.
+    ///
+    /// A state event with an empty content removes the stored content, the
+    /// same way a state event gets removed in practice.
+    fn store_custom_state(&mut self, event: &CustomStateEvent) -> bool {
+        let is_empty = event
+            .content
+            .as_object()
+            .map_or(event.content.is_null(), |c| c.is_empty());
+
+        if is_empty {
+            let state = match self.custom_state.get_mut(&event.event_type) {
+                Some(s) => s,
+                None => return false,
+            };
+            let removed = state.remove(&event.state_key).is_some();
+
+            if state.is_empty() {
+                self.custom_state.remove(&event.event_type);
+            }
+
+            removed
+        } else {
+            let previous = self
+                .custom_state
+                .entry(event.event_type.clone())
+                .or_default()
+                .insert(event.state_key.clone(), event.content.clone());
+
+            previous.as_ref() != Some(&event.content)
+        }
+    }
+
+    /// Get the content of a state event that isn't known to the SDK.
+    ///
+    /// Returns `None` if the room has no such state event or if the content
+    /// couldn't be deserialized into `T`.
+    ///
+    /// # Arguments
+    ///
+    /// * `state_key` - The state key of the event, usually an empty string.
+    ///
+    /// # Examples
+    /// ```
+    /// # use std::convert::TryFrom;
+    /// # use matrix_sdk_base::identifiers::{RoomId, UserId};
+    /// use matrix_sdk_base::{CustomStateEventContent, Room};
+    /// use serde::Deserialize;
+    ///
+    /// #[derive(Deserialize)]
+    /// struct Widget {
+    ///     url: String,
+    /// }
+    ///
+    /// impl CustomStateEventContent for Widget {
+    ///     const EVENT_TYPE: &'static str = "im.vector.modular.widgets";
+    /// }
+    ///
+    /// # let room_id = RoomId::try_from("!test:localhost").unwrap();
+    /// # let user_id = UserId::try_from("@example:localhost").unwrap();
+    /// let room = Room::new(&room_id, &user_id);
+    /// let widget = room.get_state_event::<Widget>("widget_id");
+    /// assert!(widget.is_none());
+    /// ```
+    pub fn get_state_event<T: CustomStateEventContent>(&self, state_key: &str) -> Option<T> {
+        let content = self.get_raw_state_event(T::EVENT_TYPE, state_key)?;
+        serde_json::from_value(content.clone()).ok()
+    }
+
+    /// Attach data of an application to the room.
+    ///
+    /// The data is stored in the `StateStore` together with the room, e.g. a
+    /// bridge can keep the mapping to the room of the remote network here.
+    /// The data that was previously attached under the namespace is replaced.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace` - The namespace of the application, e.g.
+    /// `org.example.bridge`.
+    ///
+    /// * `data` - The data that should be attached.
+    pub fn set_app_data<T: Serialize>(&mut self, namespace: &str, data: &T) -> Result<()> {
+        self.app_data
+            .insert(namespace.to_owned(), serde_json::to_value(data)?);
+        Ok(())
+    }
+
+    /// Get the data an application attached to the room.
+    ///
+    /// Returns `None` if there is no data attached under the namespace.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace` - The namespace of the application.
+    pub fn app_data<T: DeserializeOwned>(&self, namespace: &str) -> Result<Option<T>> {
+        match self.app_data.get(namespace) {
+            Some(data) => Ok(Some(serde_json::from_value(data.clone())?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Remove the data an application attached to the room.
+    ///
+    /// Returns true if there was data attached under the namespace.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace` - The namespace of the application.
+    pub fn remove_app_data(&mut self, namespace: &str) -> bool {
+        self.app_data.remove(namespace).is_some()
+    }
+
+    /// Get the content of a room account data event of our own user.
+    ///
+    /// Returns `None` if there is no account data of the given type.
+    ///
+    /// # Arguments
+    ///
+    /// * `event_type` - The type of the account data event.
+    pub fn account_data<T: DeserializeOwned>(&self, event_type: &str) -> Result<Option<T>> {
+        match self.account_data.get(event_type) {
+            Some(content) => Ok(Some(serde_json::from_value(content.clone())?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Receive the content of a room account data event of our own user.
+    ///
+    /// Returns true if the account data changed, false otherwise.
+    ///
+    /// # Arguments
+    ///
+    /// * `event_type` - The type of the account data event.
+    ///
+    /// * `content` - The content of the account data event.
+    pub fn receive_account_data(&mut self, event_type: &str, content: JsonValue) -> bool {
+        if self.account_data.get(event_type) == Some(&content) {
+            false
+        } else {
+            self.account_data.insert(event_type.to_owned(), content);
+            true
+        }
+    }
+
+    /// Get the JSON content of a state event that isn't known to the SDK.
+    ///
+    /// # Arguments
+    ///
+    /// * `event_type` - The type of the state event.
+    ///
+    /// * `state_key` - The state key of the event, usually an empty string.
+    pub fn get_raw_state_event(&self, event_type: &str, state_key: &str) -> Option<&JsonValue> {
+        self.custom_state.get(event_type)?.get(state_key)
+    }
+
+    /// Get the JSON content of all the state events of the given type that
+    /// aren't known to the SDK, keyed by their state key.
+    ///
+    /// # Arguments
+    ///
+    /// * `event_type` - The type of the state events.
+    pub fn get_raw_state_events(&self, event_type: &str) -> Option<&BTreeMap<String, JsonValue>> {
+        self.custom_state.get(event_type)
+    }
+
+    /// Apply the given retention policy to the message queue and the timeline
+    /// of this room.
+    ///
+    /// Returns true if any message was removed, false otherwise.
+    ///
+    /// # Arguments
+    ///
+    /// * `policy` - The client side retention policy.
+    #[cfg(feature = "messages")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "messages")))]
+    pub fn apply_retention_policy(&mut self, policy: &RetentionPolicy) -> bool {
+        let lifetime = self
+            .retention
+            .as_ref()
+            .and_then(|r| r.max_lifetime)
+            .map(|l| Duration::from_millis(l.into()));
+
+        let max_age = match (policy.max_age, lifetime) {
+            (Some(age), Some(lifetime)) => Some(age.min(lifetime)),
+            (age, lifetime) => age.or(lifetime),
+        };
+
+        let mut changed = false;
+
+        if let Some(before) = max_age.and_then(|age| SystemTime::now().checked_sub(age)) {
+            changed |= self.messages.prune_before(before);
+            changed |= self.timeline.prune_before(before);
+        }
+
+        if let Some(max) = policy.max_events {
+            changed |= self.messages.truncate(max);
+            changed |= self.timeline.truncate(max);
+        }
+
+        changed
+    }
+
+    /// Receive a timeline event for this room, append it to the `Timeline` and
+    /// update the room state.
+    ///
+    /// Events that were added to the timeline before were handled already and
+    /// are ignored, e.g. if a sync response is received twice, even if they
+    /// were removed from the timeline since.
+    ///
+    /// Returns true if the room state changed, false otherwise.
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - The event of the room as it came down the sync stream.
+    ///
+    /// * `timeline_event` - The event as it should be stored in the timeline,
+    /// the decrypted one for encrypted events.
+    #[cfg(feature = "messages")]
+    pub(crate) fn receive_timeline_item(
+        &mut self,
+        event: &RoomEvent,
+        timeline_event: TimelineEvent,
+    ) -> bool {
+        if !self.timeline.push(timeline_event) {
+            return false;
+        }
+
+        self.receive_timeline_event(event)
+    }
+
+    /// Receive a timeline event for this room and update the room state.
+    ///
+    /// Returns true if the joined member list changed, false otherwise.
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - The event of the room.
+    pub fn receive_timeline_event(&mut self, event: &RoomEvent) -> bool {
+        self.reconcile_pending_event(event);
+
+        match event {
+            // update to the current members of the room
+            RoomEvent::RoomMember(member) => self.handle_membership(member),
+            // finds all events related to the name of the room for later use
+            RoomEvent::RoomName(name) => self.handle_room_name(name),
+            RoomEvent::RoomCanonicalAlias(c_alias) => self.handle_canonical(c_alias),
+            RoomEvent::RoomAliases(alias) => self.handle_room_aliases(alias),
+            // power levels of the room members
+            RoomEvent::RoomPowerLevels(power) => self.handle_power_level(power),
+            RoomEvent::RoomTombstone(tomb) => self.handle_tombstone(tomb),
+            RoomEvent::RoomEncryption(encrypt) => self.handle_encryption_event(encrypt),
+            RoomEvent::CustomState(custom) => self.handle_custom_state(custom),
+            RoomEvent::RoomJoinRules(rules) => serde_json::to_value(&rules.content)
+                .map(|c| self.handle_join_rules(c))
+                .unwrap_or(false),
+            RoomEvent::RoomGuestAccess(access) => serde_json::to_value(&access.content)
+                .map(|c| self.handle_guest_access(c))
+                .unwrap_or(false),
+            RoomEvent::RoomHistoryVisibility(visibility) => {
+                serde_json::to_value(&visibility.content)
+                    .map(|c| self.handle_history_visibility(c))
+                    .unwrap_or(false)
+            }
+            RoomEvent::RoomServerAcl(acl) => serde_json::to_value(&acl.content)
+                .map(|c| self.handle_server_acl(c))
+                .unwrap_or(false),
+            RoomEvent::RoomAvatar(avatar) => {
+                self.track_state_event(&avatar.event_id, RedactableState::Avatar);
+                serde_json::to_value(&avatar.content)
+                    .map(|c| self.handle_room_avatar(c))
+                    .unwrap_or(false)
+            }
+            RoomEvent::RoomTopic(topic) => {
+                self.track_state_event(&topic.event_id, RedactableState::Topic);
+                serde_json::to_value(&topic.content)
+                    .map(|c| self.handle_room_topic(c))
+                    .unwrap_or(false)
+            }
+            RoomEvent::RoomRedaction(redaction) => self.handle_redaction(redaction),
+            RoomEvent::RoomMessage(msg) => {
+                let changed = self.handle_server_notice(msg);
+                #[cfg(feature = "messages")]
+                let changed = self.handle_message(msg) || changed;
+                changed
+            }
+            _ => false,
+        }
     }
 
     /// Receive a state event for this room and update the room state.
@@ -475,6 +2501,105 @@ impl Room {
             StateEvent::RoomPowerLevels(power) => self.handle_power_level(power),
             StateEvent::RoomTombstone(tomb) => self.handle_tombstone(tomb),
             StateEvent::RoomEncryption(encrypt) => self.handle_encryption_event(encrypt),
+            StateEvent::CustomState(custom) => self.handle_custom_state(custom),
+            StateEvent::RoomJoinRules(rules) => serde_json::to_value(&rules.content)
+                .map(|c| self.handle_join_rules(c))
+                .unwrap_or(false),
+            StateEvent::RoomGuestAccess(access) => serde_json::to_value(&access.content)
+                .map(|c| self.handle_guest_access(c))
+                .unwrap_or(false),
+            StateEvent::RoomHistoryVisibility(visibility) => {
+                serde_json::to_value(&visibility.content)
+                    .map(|c| self.handle_history_visibility(c))
+                    .unwrap_or(false)
+            }
+            StateEvent::RoomServerAcl(acl) => serde_json::to_value(&acl.content)
+                .map(|c| self.handle_server_acl(c))
+                .unwrap_or(false),
+            StateEvent::RoomAvatar(avatar) => {
+                self.track_state_event(&avatar.event_id, RedactableState::Avatar);
+                serde_json::to_value(&avatar.content)
+                    .map(|c| self.handle_room_avatar(c))
+                    .unwrap_or(false)
+            }
+            StateEvent::RoomTopic(topic) => {
+                self.track_state_event(&topic.event_id, RedactableState::Topic);
+                serde_json::to_value(&topic.content)
+                    .map(|c| self.handle_room_topic(c))
+                    .unwrap_or(false)
+            }
+            _ => false,
+        }
+    }
+
+    /// Receive a state event that couldn't be deserialized into one of the
+    /// known event types and update the room state.
+    ///
+    /// This handles state events that use a newer version of the spec than
+    /// the event types know about, e.g. restricted join rules, and stripped
+    /// state events that don't have a stripped event type, e.g. the
+    /// `m.room.encryption` event of an invite.
+    ///
+    /// Returns true if the state of the `Room` has changed, false otherwise.
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - The JSON of the event.
+    pub fn receive_raw_state_event(&mut self, event: &JsonValue) -> bool {
+        let content = match event.get("content") {
+            Some(c) => c.clone(),
+            None => return false,
+        };
+
+        match event.get("type").and_then(JsonValue::as_str) {
+            Some("m.room.join_rules") => self.handle_join_rules(content),
+            Some("m.room.avatar") => {
+                if let Some(event_id) = event
+                    .get("event_id")
+                    .and_then(JsonValue::as_str)
+                    .and_then(|id| EventId::try_from(id).ok())
+                {
+                    self.track_state_event(&event_id, RedactableState::Avatar);
+                }
+                self.handle_room_avatar(content)
+            }
+            Some("m.room.encryption") => self.handle_encryption_content(content),
+            Some("m.room.guest_access") => self.handle_guest_access(content),
+            Some("m.room.history_visibility") => self.handle_history_visibility(content),
+            Some("m.room.server_acl") => self.handle_server_acl(content),
+            _ => false,
+        }
+    }
+
+    /// Receive the raw JSON of a stripped state event of an invite and take
+    /// the time of the invite from it.
+    ///
+    /// Stripped state events don't have a timestamp, but servers send the
+    /// `origin_server_ts` of the `m.room.member` event that invited us along.
+    ///
+    /// Returns true if the invite timestamp changed, false otherwise.
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - The raw JSON of the stripped state event.
+    pub fn receive_raw_invite_event(&mut self, event: &JsonValue) -> bool {
+        let own_user_id = self.own_user_id.to_string();
+
+        if event.get("type").and_then(JsonValue::as_str) != Some("m.room.member")
+            || event.get("state_key").and_then(JsonValue::as_str) != Some(own_user_id.as_str())
+        {
+            return false;
+        }
+
+        match event
+            .get("origin_server_ts")
+            .and_then(JsonValue::as_u64)
+            .and_then(|ts| UNIX_EPOCH.checked_add(Duration::from_millis(ts)))
+        {
+            Some(ts) if self.invite_timestamp != Some(ts) => {
+                self.invite_timestamp = Some(ts);
+                true
+            }
             _ => false,
         }
     }
@@ -490,6 +2615,24 @@ impl Room {
     pub fn receive_stripped_state_event(&mut self, event: &AnyStrippedStateEvent) -> bool {
         match event {
             AnyStrippedStateEvent::RoomName(n) => self.handle_stripped_room_name(n),
+            AnyStrippedStateEvent::RoomMember(member) => self.handle_stripped_member(member),
+            AnyStrippedStateEvent::RoomJoinRules(rules) => serde_json::to_value(&rules.content)
+                .map(|c| self.handle_join_rules(c))
+                .unwrap_or(false),
+            AnyStrippedStateEvent::RoomAvatar(avatar) => serde_json::to_value(&avatar.content)
+                .map(|c| self.handle_room_avatar(c))
+                .unwrap_or(false),
+            AnyStrippedStateEvent::RoomTopic(topic) => serde_json::to_value(&topic.content)
+                .map(|c| self.handle_room_topic(c))
+                .unwrap_or(false),
+            AnyStrippedStateEvent::RoomGuestAccess(access) => serde_json::to_value(&access.content)
+                .map(|c| self.handle_guest_access(c))
+                .unwrap_or(false),
+            AnyStrippedStateEvent::RoomHistoryVisibility(visibility) => {
+                serde_json::to_value(&visibility.content)
+                    .map(|c| self.handle_history_visibility(c))
+                    .unwrap_or(false)
+            }
             _ => false,
         }
     }
@@ -509,6 +2652,7 @@ impl Room {
                 false
             } else {
                 member.update_presence(event);
+                self.update_display_name_ambiguity();
                 true
             }
         } else {
@@ -517,12 +2661,47 @@ impl Room {
             false
         }
     }
+
+    /// Receive a presence event that is shared between the rooms of a sync
+    /// response, the members of the rooms store the same event.
+    ///
+    /// Returns true if the specific users presence has changed, false otherwise.
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - The presence event for a specified room member.
+    pub fn receive_shared_presence_event(&mut self, event: &Arc<PresenceEvent>) -> bool {
+        match self.members.get_mut(&event.sender) {
+            Some(member) if !member.did_update_presence(event) => {
+                member.update_shared_presence(event.clone());
+                self.update_display_name_ambiguity();
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Format a value as a CSV field, quoting it if necessary.
+fn csv_field(value: &JsonValue) -> String {
+    let field = match value {
+        JsonValue::Null => return String::new(),
+        JsonValue::String(s) => s.clone(),
+        v => v.to_string(),
+    };
+
+    if field.contains(|c: char| c == ',' || c == '"' || c == '\n' || c == '\r') {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
     use crate::events::room::member::MembershipState;
+    use crate::events::EventJson;
     use crate::identifiers::UserId;
     use crate::{BaseClient, Session};
     use matrix_sdk_test::{async_test, sync_response, EventBuilder, EventsFile, SyncResponseFile};
@@ -567,35 +2746,148 @@ mod test {
             assert_eq!(MembershipState::Join, member.membership);
         }
 
-        assert!(room.deref().power_levels.is_some())
+        assert!(room.deref().power_levels.is_some())
+    }
+
+    #[async_test]
+    async fn room_events() {
+        let client = get_client();
+        let room_id = get_room_id();
+        let user_id = UserId::try_from("@example:localhost").unwrap();
+
+        let mut response = EventBuilder::default()
+            .add_room_event(EventsFile::Member, RoomEvent::RoomMember)
+            .add_room_event(EventsFile::PowerLevels, RoomEvent::RoomPowerLevels)
+            .build_sync_response();
+
+        client.receive_sync_response(&mut response).await.unwrap();
+
+        let room = client.get_joined_room(&room_id).await.unwrap();
+        let room = room.read().await;
+
+        assert_eq!(room.members.len(), 1);
+        assert!(room.power_levels.is_some());
+        assert_eq!(
+            room.power_levels.as_ref().unwrap().kick,
+            crate::js_int::Int::new(50).unwrap()
+        );
+        let admin = room.members.get(&user_id).unwrap();
+        assert_eq!(
+            admin.power_level.unwrap(),
+            crate::js_int::Int::new(100).unwrap()
+        );
+    }
+
+    #[async_test]
+    async fn moderation_permissions() {
+        let client = get_client();
+        let room_id = get_room_id();
+        let user_id = UserId::try_from("@example:localhost").unwrap();
+        let bob = UserId::try_from("@bob:localhost").unwrap();
+
+        let mut room = Room::new(&room_id, &bob);
+        room.creator = Some(user_id.clone());
+
+        // Without power levels only the creator may moderate the room.
+        assert_eq!(room.power_level(&user_id), crate::js_int::Int::from(100));
+        assert!(!room.can_ban(&user_id));
+        assert!(!room.can_kick(&user_id));
+        assert!(!room.can_invite());
+
+        let mut response = EventBuilder::default()
+            .add_room_event(EventsFile::Member, RoomEvent::RoomMember)
+            .add_room_event(EventsFile::PowerLevels, RoomEvent::RoomPowerLevels)
+            .build_sync_response();
+
+        client.receive_sync_response(&mut response).await.unwrap();
+
+        let room = client.get_joined_room(&room_id).await.unwrap();
+        let room = room.read().await;
+
+        assert!(room.can_ban(&bob));
+        assert!(room.can_kick(&bob));
+        assert!(room.can_invite());
+        assert!(room.can_redact());
+
+        // Nobody can moderate users with the same power level.
+        assert!(!room.can_ban(&user_id));
+        assert!(!room.can_kick(&user_id));
+    }
+
+    #[async_test]
+    async fn power_level_helpers() {
+        let client = get_client();
+        let room_id = get_room_id();
+        let user_id = UserId::try_from("@example:localhost").unwrap();
+        let bob = UserId::try_from("@bob:localhost").unwrap();
+        let carol = UserId::try_from("@carol:localhost").unwrap();
+
+        let mut response = EventBuilder::default()
+            .add_room_event(EventsFile::Member, RoomEvent::RoomMember)
+            .add_room_event(EventsFile::PowerLevels, RoomEvent::RoomPowerLevels)
+            .build_sync_response();
+
+        client.receive_sync_response(&mut response).await.unwrap();
+
+        let room = client.get_joined_room(&room_id).await.unwrap();
+        let room = room.read().await;
+
+        // Bob isn't a member of the room but has a power level.
+        assert!(room.members.get(&bob).is_none());
+        assert_eq!(room.power_level_for(&bob), Int::from(0));
+        assert_eq!(room.power_level_for(&user_id), Int::from(100));
+        assert_eq!(room.power_level_for(&carol), Int::from(0));
+
+        assert_eq!(
+            room.required_level_for_event(&EventType::RoomMessage),
+            Int::from(25)
+        );
+        assert_eq!(
+            room.required_level_for_event(&EventType::Sticker),
+            Int::from(0)
+        );
+        assert_eq!(
+            room.required_level_for_state_event(&EventType::RoomPowerLevels),
+            Int::from(100)
+        );
+        assert_eq!(
+            room.required_level_for_state_event(&EventType::RoomTopic),
+            Int::from(50)
+        );
+
+        assert!(room.can_user_do(
+            &user_id,
+            &PowerAction::SendState(EventType::RoomPowerLevels)
+        ));
+        assert!(!room.can_user_do(&bob, &PowerAction::SendMessage(EventType::RoomMessage)));
+        assert!(room.can_user_do(&bob, &PowerAction::Invite));
+        assert!(!room.can_user_do(&bob, &PowerAction::Kick));
+        assert!(room
+            .check_permission(&PowerAction::SendState(EventType::RoomTopic))
+            .is_ok());
     }
 
-    #[async_test]
-    async fn room_events() {
-        let client = get_client();
-        let room_id = get_room_id();
+    #[test]
+    fn permission_errors() {
         let user_id = UserId::try_from("@example:localhost").unwrap();
+        let room = Room::new(&get_room_id(), &user_id);
 
-        let mut response = EventBuilder::default()
-            .add_room_event(EventsFile::Member, RoomEvent::RoomMember)
-            .add_room_event(EventsFile::PowerLevels, RoomEvent::RoomPowerLevels)
-            .build_sync_response();
-
-        client.receive_sync_response(&mut response).await.unwrap();
-
-        let room = client.get_joined_room(&room_id).await.unwrap();
-        let room = room.read().await;
+        assert!(room
+            .check_permission(&PowerAction::SendMessage(EventType::RoomMessage))
+            .is_ok());
 
-        assert_eq!(room.members.len(), 1);
-        assert!(room.power_levels.is_some());
+        let error = room.check_permission(&PowerAction::Ban).unwrap_err();
         assert_eq!(
-            room.power_levels.as_ref().unwrap().kick,
-            crate::js_int::Int::new(50).unwrap()
+            error,
+            PermissionError::InsufficientPowerLevel {
+                action: PowerAction::Ban,
+                required: Int::from(50),
+                own: Int::from(0),
+            }
         );
-        let admin = room.members.get(&user_id).unwrap();
         assert_eq!(
-            admin.power_level.unwrap(),
-            crate::js_int::Int::new(100).unwrap()
+            error.to_string(),
+            "you need power level 50 to ban users in this room, but you have 0"
         );
     }
 
@@ -614,7 +2906,7 @@ mod test {
         let room = client.get_joined_room(&room_id).await.unwrap();
         let room = room.read().await;
 
-        assert_eq!("tutorial", room.display_name());
+        assert_eq!("#tutorial:localhost", room.display_name());
     }
 
     #[async_test]
@@ -632,7 +2924,7 @@ mod test {
         let room = client.get_joined_room(&room_id).await.unwrap();
         let room = room.read().await;
 
-        assert_eq!("tutorial", room.display_name());
+        assert_eq!("#tutorial:localhost", room.display_name());
     }
 
     #[async_test]
@@ -670,6 +2962,992 @@ mod test {
             room_names.push(room.read().await.display_name())
         }
 
-        assert_eq!(vec!["example, example2"], room_names);
+        assert_eq!(vec!["alice and bob"], room_names);
+    }
+
+    #[test]
+    fn display_name_disambiguation() {
+        let own_user_id = UserId::try_from("@example:localhost").unwrap();
+        let mut room = Room::new(&get_room_id(), &own_user_id);
+
+        let member_event = |user_id: &str, name: &str, membership: &str| {
+            let json = json!({
+                "content": { "displayname": name, "membership": membership },
+                "event_id": "$h29iv0s8:example.com",
+                "origin_server_ts": 1_520_372_800_469u64,
+                "sender": user_id,
+                "state_key": user_id,
+                "type": "m.room.member"
+            });
+            serde_json::from_value::<EventJson<MemberEvent>>(json)
+                .unwrap()
+                .deserialize()
+                .unwrap()
+        };
+
+        let alice = UserId::try_from("@alice:localhost").unwrap();
+        let other_alice = UserId::try_from("@alice:example.org").unwrap();
+
+        room.handle_membership(&member_event("@alice:localhost", "Alice", "join"));
+        assert_eq!(room.members[&alice].disambiguated_name(), "Alice");
+
+        room.handle_membership(&member_event("@alice:example.org", "Alice", "join"));
+        assert_eq!(
+            room.members[&alice].disambiguated_name(),
+            "Alice (@alice:localhost)"
+        );
+        assert_eq!(
+            room.members[&other_alice].disambiguated_name(),
+            "Alice (@alice:example.org)"
+        );
+
+        let mut leave = member_event("@alice:example.org", "Alice", "leave");
+        leave.prev_content = Some(member_event("@alice:example.org", "Alice", "join").content);
+        room.handle_membership(&leave);
+
+        assert_eq!(room.members[&alice].disambiguated_name(), "Alice");
+    }
+
+    #[test]
+    fn server_notice_room() {
+        let own_user_id = UserId::try_from("@example:localhost").unwrap();
+        let mut room = Room::new(&get_room_id(), &own_user_id);
+
+        let tags = serde_json::from_value::<EventJson<TagEvent>>(json!({
+            "content": { "tags": { "m.server_notice": { "order": 0.5 } } },
+            "type": "m.tag"
+        }))
+        .unwrap()
+        .deserialize()
+        .unwrap();
+
+        assert!(!room.is_server_notice_room());
+        assert!(room.receive_tags(&tags));
+        assert!(room.is_server_notice_room());
+        assert_eq!(room.tags()[SERVER_NOTICE_TAG], Some(0.5));
+
+        let notice = serde_json::from_value::<EventJson<RoomEvent>>(json!({
+            "content": {
+                "msgtype": "m.server_notice",
+                "body": "The usage limit was reached",
+                "server_notice_type": "m.server_notice.usage_limit_reached",
+                "limit_type": "monthly_active_user"
+            },
+            "event_id": "$notice:localhost",
+            "origin_server_ts": 1_520_372_800_469u64,
+            "sender": "@notices:localhost",
+            "type": "m.room.message"
+        }))
+        .unwrap()
+        .deserialize()
+        .unwrap();
+
+        assert!(room.receive_timeline_event(&notice));
+        assert!(room.server_notice().unwrap().is_usage_limit_reached());
+    }
+
+    #[test]
+    fn room_name_from_heroes() {
+        let own_user_id = UserId::try_from("@example:localhost").unwrap();
+        let member = |user_id: &str, name: Option<&str>| {
+            let user_id = UserId::try_from(user_id).unwrap();
+            let json = json!({
+                "content": { "displayname": name, "membership": "join" },
+                "event_id": "$h29iv0s8:example.com",
+                "origin_server_ts": 1_520_372_800_469u64,
+                "sender": user_id.to_string(),
+                "state_key": user_id.to_string(),
+                "type": "m.room.member"
+            });
+            let event = serde_json::from_value::<EventJson<MemberEvent>>(json)
+                .unwrap()
+                .deserialize()
+                .unwrap();
+            (user_id, RoomMember::new(&event))
+        };
+
+        let mut members: HashMap<UserId, RoomMember> = vec![
+            member("@example:localhost", Some("me")),
+            member("@carol:localhost", Some("Carol")),
+            member("@alice:localhost", None),
+        ]
+        .into_iter()
+        .collect();
+
+        let mut name = RoomName::default();
+        assert_eq!(
+            name.calculate_name(&own_user_id, &members),
+            "alice and Carol"
+        );
+
+        name.joined_member_count = UInt::new(10);
+        assert_eq!(
+            name.calculate_name(&own_user_id, &members),
+            "alice, Carol and 7 others"
+        );
+
+        name.heroes = vec!["@carol:localhost".to_owned(), "@dave:localhost".to_owned()];
+        name.joined_member_count = UInt::new(3);
+        assert_eq!(
+            name.calculate_name(&own_user_id, &members),
+            "Carol and dave"
+        );
+
+        name.joined_member_count = UInt::new(1);
+        assert_eq!(
+            name.calculate_name(&own_user_id, &members),
+            "Empty Room (was Carol and dave)"
+        );
+
+        members.clear();
+        let empty = RoomName::default();
+        assert_eq!(empty.calculate_name(&own_user_id, &members), "Empty Room");
+
+        name.name = Some("Room".to_owned());
+        assert_eq!(name.calculate_name(&own_user_id, &members), "Room");
+    }
+
+    #[test]
+    fn retention_state() {
+        let user_id = UserId::try_from("@example:localhost").unwrap();
+        let mut room = Room::new(&get_room_id(), &user_id);
+
+        let json = serde_json::json!({
+            "content": { "max_lifetime": 86_400_000 },
+            "event_id": "$h29iv0s8:example.com",
+            "origin_server_ts": 1_520_372_800_469u64,
+            "sender": "@example:localhost",
+            "state_key": "",
+            "type": "m.room.retention"
+        });
+        let event = serde_json::from_value::<EventJson<StateEvent>>(json)
+            .unwrap()
+            .deserialize()
+            .unwrap();
+
+        assert!(room.receive_state_event(&event));
+        assert_eq!(
+            room.retention.as_ref().unwrap().max_lifetime,
+            UInt::new(86_400_000)
+        );
+    }
+
+    #[test]
+    fn custom_state() {
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Widget {
+            url: String,
+        }
+
+        impl CustomStateEventContent for Widget {
+            const EVENT_TYPE: &'static str = "im.vector.modular.widgets";
+        }
+
+        let user_id = UserId::try_from("@example:localhost").unwrap();
+        let mut room = Room::new(&get_room_id(), &user_id);
+
+        let event = |content: JsonValue| {
+            let json = serde_json::json!({
+                "content": content,
+                "event_id": "$h29iv0s8:example.com",
+                "origin_server_ts": 1_520_372_800_469u64,
+                "sender": "@example:localhost",
+                "state_key": "widget",
+                "type": "im.vector.modular.widgets"
+            });
+            serde_json::from_value::<EventJson<StateEvent>>(json)
+                .unwrap()
+                .deserialize()
+                .unwrap()
+        };
+
+        assert!(room.receive_state_event(&event(json!({ "url": "https://example.org" }))));
+        assert!(!room.receive_state_event(&event(json!({ "url": "https://example.org" }))));
+        assert_eq!(
+            room.get_state_event::<Widget>("widget"),
+            Some(Widget {
+                url: "https://example.org".to_owned()
+            })
+        );
+        assert!(room.get_state_event::<Widget>("other").is_none());
+        assert_eq!(
+            room.get_raw_state_events("im.vector.modular.widgets")
+                .unwrap()
+                .len(),
+            1
+        );
+
+        assert!(room.receive_state_event(&event(json!({}))));
+        assert!(room.get_state_event::<Widget>("widget").is_none());
+    }
+
+    #[test]
+    fn app_data() {
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Portal {
+            channel: String,
+        }
+
+        let user_id = UserId::try_from("@example:localhost").unwrap();
+        let mut room = Room::new(&get_room_id(), &user_id);
+        let portal = Portal {
+            channel: "#rust".to_owned(),
+        };
+
+        assert!(room
+            .app_data::<Portal>("org.example.bridge")
+            .unwrap()
+            .is_none());
+        room.set_app_data("org.example.bridge", &portal).unwrap();
+
+        let json = serde_json::to_string(&room).unwrap();
+        let mut room: Room = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(
+            room.app_data::<Portal>("org.example.bridge").unwrap(),
+            Some(portal)
+        );
+        assert!(room.app_data::<u64>("org.example.bridge").is_err());
+        assert!(room.remove_app_data("org.example.bridge"));
+        assert!(!room.remove_app_data("org.example.bridge"));
+    }
+
+    #[test]
+    fn space_state() {
+        let user_id = UserId::try_from("@example:localhost").unwrap();
+        let mut room = Room::new(&get_room_id(), &user_id);
+
+        let event = |event_type: &str, state_key: &str, content: JsonValue| {
+            let json = serde_json::json!({
+                "content": content,
+                "event_id": "$h29iv0s8:example.com",
+                "origin_server_ts": 1_520_372_800_469u64,
+                "sender": "@example:localhost",
+                "state_key": state_key,
+                "type": event_type
+            });
+            serde_json::from_value::<EventJson<StateEvent>>(json)
+                .unwrap()
+                .deserialize()
+                .unwrap()
+        };
+
+        let child_a = RoomId::try_from("!a:example.org").unwrap();
+        let child_b = RoomId::try_from("!b:example.org").unwrap();
+        let parent = RoomId::try_from("!parent:example.org").unwrap();
+
+        assert!(room.receive_state_event(&event(
+            "m.space.child",
+            "!a:example.org",
+            json!({ "via": ["example.org"] })
+        )));
+        assert!(room.receive_state_event(&event(
+            "m.space.child",
+            "!b:example.org",
+            json!({ "via": ["example.org"], "order": "a" })
+        )));
+        assert!(room.receive_state_event(&event(
+            "m.space.parent",
+            "!parent:example.org",
+            json!({ "via": ["example.org"], "canonical": true })
+        )));
+
+        assert!(room.space.is_space());
+        assert_eq!(room.space.ordered_children(), vec![&child_b, &child_a]);
+        assert_eq!(room.space.canonical_parent(), Some(&parent));
+
+        assert!(room.receive_state_event(&event("m.space.child", "!a:example.org", json!({}))));
+        assert_eq!(room.space.ordered_children(), vec![&child_b]);
+    }
+
+    #[test]
+    fn restricted_join_rule() {
+        let user_id = UserId::try_from("@example:localhost").unwrap();
+        let mut room = Room::new(&get_room_id(), &user_id);
+
+        let json = serde_json::json!({
+            "content": {
+                "join_rule": "restricted",
+                "allow": [
+                    { "type": "m.room_membership", "room_id": "!space:example.org" },
+                    { "type": "org.example.unknown" }
+                ]
+            },
+            "event_id": "$h29iv0s8:example.com",
+            "origin_server_ts": 1_520_372_800_469u64,
+            "sender": "@example:localhost",
+            "state_key": "",
+            "type": "m.room.join_rules"
+        });
+
+        assert!(room.receive_raw_state_event(&json));
+
+        let rule = room.join_rule().unwrap();
+        let space = RoomId::try_from("!space:example.org").unwrap();
+        assert_eq!(rule.allowed_rooms(), vec![&space]);
+        assert!(!room.receive_raw_state_event(&json));
+    }
+
+    #[test]
+    fn stripped_room_encryption() {
+        let user_id = UserId::try_from("@example:localhost").unwrap();
+        let mut room = Room::new(&get_room_id(), &user_id);
+
+        let mut json = serde_json::json!({
+            "content": { "algorithm": "m.megolm.v1.aes-sha2" },
+            "sender": "@example:localhost",
+            "state_key": "",
+            "type": "m.room.encryption"
+        });
+
+        assert!(!room.is_encrypted());
+        assert!(room.receive_raw_state_event(&json));
+        assert!(room.is_encrypted());
+        assert!(!room.receive_raw_state_event(&json));
+
+        let mut room = Room::new(&get_room_id(), &user_id);
+        json["content"] = serde_json::json!({});
+        assert!(!room.receive_raw_state_event(&json));
+        assert!(!room.is_encrypted());
+    }
+
+    #[test]
+    fn member_with_invalid_state_key() {
+        let user_id = UserId::try_from("@example:localhost").unwrap();
+        let mut room = Room::new(&get_room_id(), &user_id);
+
+        let event = serde_json::from_value::<EventJson<MemberEvent>>(serde_json::json!({
+            "content": { "membership": "join" },
+            "event_id": "$h29iv0s8:example.com",
+            "origin_server_ts": 1_520_372_800_469u64,
+            "sender": "@example:localhost",
+            "state_key": "not a user id",
+            "type": "m.room.member"
+        }))
+        .unwrap()
+        .deserialize()
+        .unwrap();
+
+        assert!(!room.handle_membership(&event));
+        assert!(room.members.is_empty());
+    }
+
+    #[test]
+    fn room_access_settings() {
+        let user_id = UserId::try_from("@example:localhost").unwrap();
+        let mut room = Room::new(&get_room_id(), &user_id);
+
+        let state_event = |event_type: &str, content: serde_json::Value| {
+            serde_json::from_value::<EventJson<StateEvent>>(serde_json::json!({
+                "content": content,
+                "event_id": "$h29iv0s8:example.com",
+                "origin_server_ts": 1_520_372_800_469u64,
+                "sender": "@example:localhost",
+                "state_key": "",
+                "type": event_type
+            }))
+            .unwrap()
+            .deserialize()
+            .unwrap()
+        };
+
+        assert!(room.guest_access().is_none());
+        assert!(room.history_visibility().is_none());
+        assert!(room.server_acl().is_none());
+
+        let event = state_event(
+            "m.room.guest_access",
+            serde_json::json!({ "guest_access": "can_join" }),
+        );
+        assert!(room.receive_state_event(&event));
+        assert!(!room.receive_state_event(&event));
+        assert_eq!(room.guest_access(), Some(GuestAccess::CanJoin));
+
+        let event = state_event(
+            "m.room.history_visibility",
+            serde_json::json!({ "history_visibility": "world_readable" }),
+        );
+        assert!(room.receive_state_event(&event));
+        assert_eq!(
+            room.history_visibility(),
+            Some(HistoryVisibility::WorldReadable)
+        );
+
+        let event = state_event(
+            "m.room.server_acl",
+            serde_json::json!({ "allow": ["*"], "deny": ["evil.com"] }),
+        );
+        assert!(room.receive_state_event(&event));
+        let acl = room.server_acl().unwrap();
+        assert!(acl.is_allowed("matrix.org"));
+        assert!(!acl.is_allowed("evil.com"));
+    }
+
+    #[test]
+    fn room_topic() {
+        let user_id = UserId::try_from("@example:localhost").unwrap();
+        let mut room = Room::new(&get_room_id(), &user_id);
+
+        let mut json = serde_json::json!({
+            "content": { "topic": "Matrix rust SDK" },
+            "event_id": "$h29iv0s8:example.com",
+            "origin_server_ts": 1_520_372_800_469u64,
+            "sender": "@example:localhost",
+            "state_key": "",
+            "type": "m.room.topic"
+        });
+        let event = serde_json::from_value::<EventJson<StateEvent>>(json.clone())
+            .unwrap()
+            .deserialize()
+            .unwrap();
+
+        assert!(room.topic().is_none());
+        assert!(room.receive_state_event(&event));
+        assert_eq!(room.topic(), Some("Matrix rust SDK"));
+        assert!(!room.receive_state_event(&event));
+
+        let serialized = serde_json::to_string(&room).unwrap();
+        let restored: Room = serde_json::from_str(&serialized).unwrap();
+        assert_eq!(restored.topic(), Some("Matrix rust SDK"));
+
+        json["content"]["topic"] = serde_json::json!("");
+        let event = serde_json::from_value::<EventJson<StateEvent>>(json)
+            .unwrap()
+            .deserialize()
+            .unwrap();
+        assert!(room.receive_state_event(&event));
+        assert!(room.topic().is_none());
+    }
+
+    #[test]
+    fn invite_metadata() {
+        let user_id = UserId::try_from("@example:localhost").unwrap();
+        let alice = UserId::try_from("@alice:localhost").unwrap();
+        let mut room = Room::new(&get_room_id(), &user_id);
+
+        let stripped = |json: JsonValue| {
+            serde_json::from_value::<EventJson<AnyStrippedStateEvent>>(json)
+                .unwrap()
+                .deserialize()
+                .unwrap()
+        };
+
+        let inviter = serde_json::json!({
+            "content": {
+                "avatar_url": "mxc://localhost/alice",
+                "displayname": "Alice",
+                "membership": "join"
+            },
+            "sender": "@alice:localhost",
+            "state_key": "@alice:localhost",
+            "type": "m.room.member"
+        });
+        let invite = serde_json::json!({
+            "content": { "membership": "invite" },
+            "origin_server_ts": 1_520_372_800_469u64,
+            "sender": "@alice:localhost",
+            "state_key": "@example:localhost",
+            "type": "m.room.member"
+        });
+
+        assert!(room.receive_stripped_state_event(&stripped(inviter)));
+        assert!(room.inviter().is_none());
+        assert!(room.receive_stripped_state_event(&stripped(invite.clone())));
+        assert!(!room.receive_stripped_state_event(&stripped(invite.clone())));
+        assert_eq!(room.inviter(), Some(&alice));
+
+        assert!(room.receive_raw_invite_event(&invite));
+        assert!(!room.receive_raw_invite_event(&invite));
+        assert_eq!(
+            room.invite_timestamp(),
+            Some(UNIX_EPOCH + Duration::from_millis(1_520_372_800_469))
+        );
+
+        assert_eq!(room.display_name(), "Alice");
+        assert_eq!(room.avatar_url(), Some("mxc://localhost/alice"));
+
+        let name = serde_json::json!({
+            "content": { "name": "Invite room" },
+            "sender": "@alice:localhost",
+            "state_key": "",
+            "type": "m.room.name"
+        });
+        assert!(room.receive_stripped_state_event(&stripped(name)));
+        assert_eq!(room.display_name(), "Invite room");
+
+        let serialized = serde_json::to_string(&room).unwrap();
+        let restored: Room = serde_json::from_str(&serialized).unwrap();
+        assert_eq!(restored.inviter(), Some(&alice));
+        assert_eq!(restored.invite_timestamp(), room.invite_timestamp());
+    }
+
+    #[test]
+    fn room_avatar() {
+        let user_id = UserId::try_from("@example:localhost").unwrap();
+        let mut room = Room::new(&get_room_id(), &user_id);
+
+        let mut json = serde_json::json!({
+            "content": {
+                "url": "mxc://localhost/avatar",
+                "info": { "mimetype": "image/png", "size": 1024 }
+            },
+            "event_id": "$h29iv0s8:example.com",
+            "origin_server_ts": 1_520_372_800_469u64,
+            "sender": "@example:localhost",
+            "state_key": "",
+            "type": "m.room.avatar"
+        });
+
+        assert!(room.avatar_url().is_none());
+        assert!(room.receive_raw_state_event(&json));
+        assert_eq!(room.avatar_url(), Some("mxc://localhost/avatar"));
+        assert!(!room.receive_raw_state_event(&json));
+
+        json["content"] = serde_json::json!({});
+        assert!(room.receive_raw_state_event(&json));
+        assert!(room.avatar_url().is_none());
+    }
+
+    #[test]
+    fn reactions() {
+        let user_id = UserId::try_from("@example:localhost").unwrap();
+        let mut room = Room::new(&get_room_id(), &user_id);
+        let original = EventId::try_from("$original:localhost").unwrap();
+
+        let reaction = |event_id: &str, sender: &str, key: &str| Reaction {
+            event_id: EventId::try_from(event_id).unwrap(),
+            relates_to: original.clone(),
+            key: key.to_owned(),
+            sender: UserId::try_from(sender).unwrap(),
+        };
+
+        assert!(room.receive_reaction(reaction("$1:localhost", "@example:localhost", "👍")));
+        assert!(room.receive_reaction(reaction("$2:localhost", "@alice:localhost", "👍")));
+        assert!(room.receive_reaction(reaction("$3:localhost", "@alice:localhost", "🎉")));
+        // The same user can't react twice with the same key.
+        assert!(!room.receive_reaction(reaction("$4:localhost", "@alice:localhost", "👍")));
+
+        let reactions = room.reactions(&original);
+        assert_eq!(reactions["👍"].count, 2);
+        assert_eq!(reactions["🎉"].count, 1);
+        assert_eq!(
+            reactions["🎉"].senders,
+            vec![UserId::try_from("@alice:localhost").unwrap()]
+        );
+
+        let redaction = serde_json::json!({
+            "content": {},
+            "event_id": "$5:localhost",
+            "origin_server_ts": 1_520_372_800_469u64,
+            "redacts": "$2:localhost",
+            "sender": "@alice:localhost",
+            "type": "m.room.redaction"
+        });
+        let redaction = serde_json::from_value::<EventJson<RoomEvent>>(redaction)
+            .unwrap()
+            .deserialize()
+            .unwrap();
+
+        assert!(room.receive_timeline_event(&redaction));
+        assert_eq!(room.reactions(&original)["👍"].count, 1);
+    }
+
+    #[test]
+    fn redactions() {
+        let user_id = UserId::try_from("@example:localhost").unwrap();
+        let mut room = Room::new(&get_room_id(), &user_id);
+
+        let event = |json: serde_json::Value| {
+            serde_json::from_value::<EventJson<RoomEvent>>(json)
+                .unwrap()
+                .deserialize()
+                .unwrap()
+        };
+        let redaction = |redacts: &str| {
+            event(serde_json::json!({
+                "content": { "reason": "Spam" },
+                "event_id": format!("$redaction-{}", &redacts[1..]),
+                "origin_server_ts": 1_520_372_800_469u64,
+                "redacts": redacts,
+                "sender": "@example:localhost",
+                "type": "m.room.redaction"
+            }))
+        };
+
+        room.receive_timeline_event(&event(serde_json::json!({
+            "content": { "name": "Spam room" },
+            "event_id": "$name:localhost",
+            "origin_server_ts": 1_520_372_800_469u64,
+            "sender": "@example:localhost",
+            "state_key": "",
+            "type": "m.room.name"
+        })));
+        room.receive_timeline_event(&event(serde_json::json!({
+            "content": { "displayname": "Spammer", "membership": "join" },
+            "event_id": "$member:localhost",
+            "origin_server_ts": 1_520_372_800_469u64,
+            "sender": "@example:localhost",
+            "state_key": "@example:localhost",
+            "type": "m.room.member"
+        })));
+
+        assert_eq!(room.display_name(), "Spam room");
+        assert_eq!(
+            room.members[&user_id].display_name.as_deref(),
+            Some("Spammer")
+        );
+
+        assert!(room.receive_timeline_event(&redaction("$name:localhost")));
+        assert!(room.receive_timeline_event(&redaction("$member:localhost")));
+
+        assert_ne!(room.display_name(), "Spam room");
+        assert!(room.members.contains_key(&user_id));
+        assert_eq!(room.members[&user_id].display_name, None);
+
+        // Redacting an event twice or an unknown event does nothing.
+        assert!(!room.receive_timeline_event(&redaction("$name:localhost")));
+        assert!(!room.receive_timeline_event(&redaction("$unknown:localhost")));
+
+        #[cfg(feature = "messages")]
+        {
+            room.receive_timeline_event(&event(serde_json::json!({
+                "content": { "body": "Buy spam", "msgtype": "m.text" },
+                "event_id": "$message:localhost",
+                "origin_server_ts": 1_520_372_800_469u64,
+                "sender": "@example:localhost",
+                "type": "m.room.message"
+            })));
+
+            let message = EventId::try_from("$message:localhost").unwrap();
+            assert!(room.receive_timeline_event(&redaction("$message:localhost")));
+
+            if let Some(MessageEventContent::Text(content)) = room.latest_content(&message) {
+                assert!(content.body.is_empty());
+            } else {
+                panic!("the redacted message should be blanked");
+            }
+        }
+    }
+
+    #[test]
+    fn read_markers() {
+        let user_id = UserId::try_from("@example:localhost").unwrap();
+        let mut room = Room::new(&get_room_id(), &user_id);
+        let read = EventId::try_from("$read:localhost").unwrap();
+
+        let receipt = |user: &str| {
+            serde_json::from_value::<EventJson<ReceiptEvent>>(serde_json::json!({
+                "content": {
+                    "$read:localhost": {
+                        "m.read": { user: { "ts": 1_520_372_800_469u64 } }
+                    }
+                },
+                "room_id": "!test:localhost",
+                "type": "m.receipt"
+            }))
+            .unwrap()
+            .deserialize()
+            .unwrap()
+        };
+
+        // Receipts of other users don't move our own read receipt.
+        assert!(room.receive_receipt(&receipt("@alice:localhost")));
+        assert_eq!(room.read_receipt(), None);
+        assert!(room.receive_receipt(&receipt("@example:localhost")));
+        assert!(!room.receive_receipt(&receipt("@example:localhost")));
+        assert_eq!(room.read_receipt(), Some(&read));
+
+        let fully_read = serde_json::from_value::<EventJson<FullyReadEvent>>(serde_json::json!({
+            "content": { "event_id": "$read:localhost" },
+            "room_id": "!test:localhost",
+            "type": "m.fully_read"
+        }))
+        .unwrap()
+        .deserialize()
+        .unwrap();
+
+        assert!(room.receive_fully_read(&fully_read));
+        assert_eq!(room.fully_read(), Some(&read));
+
+        #[cfg(feature = "messages")]
+        {
+            let message = |event_id: &str, sender: &str, ts: u64| {
+                serde_json::from_value::<EventJson<RoomEvent>>(serde_json::json!({
+                    "content": { "body": "Hello", "msgtype": "m.text" },
+                    "event_id": event_id,
+                    "origin_server_ts": ts,
+                    "sender": sender,
+                    "type": "m.room.message"
+                }))
+                .unwrap()
+                .deserialize()
+                .unwrap()
+            };
+
+            room.receive_timeline_event(&message("$read:localhost", "@alice:localhost", 1));
+            room.receive_timeline_event(&message("$1:localhost", "@alice:localhost", 2));
+            room.receive_timeline_event(&message("$2:localhost", "@example:localhost", 3));
+            room.receive_timeline_event(&message("$3:localhost", "@alice:localhost", 4));
+
+            assert_eq!(room.unread_message_count(), 2);
+        }
+    }
+
+    #[test]
+    fn receipt_aggregation() {
+        let user_id = UserId::try_from("@example:localhost").unwrap();
+        let alice = UserId::try_from("@alice:localhost").unwrap();
+        let mut room = Room::new(&get_room_id(), &user_id);
+        let first = EventId::try_from("$first:localhost").unwrap();
+        let second = EventId::try_from("$second:localhost").unwrap();
+
+        let receipt = |user_id: &UserId, event_id: &EventId| ReadReceipt {
+            user_id: user_id.clone(),
+            event_id: event_id.clone(),
+            ts: None,
+        };
+
+        assert!(room.receive_read_receipt(receipt(&alice, &first)));
+        assert!(room.receive_read_receipt(receipt(&user_id, &first)));
+        assert_eq!(
+            room.read_receipts(&first),
+            &[alice.clone(), user_id.clone()]
+        );
+
+        assert!(room.receive_read_receipt(receipt(&alice, &second)));
+        assert!(!room.receive_read_receipt(receipt(&alice, &second)));
+        assert_eq!(room.read_receipts(&first), &[user_id.clone()]);
+        assert_eq!(room.read_receipts(&second), &[alice.clone()]);
+        assert_eq!(room.user_read_receipt(&alice).unwrap().event_id, second);
+        assert_eq!(room.read_receipt(), Some(&first));
+    }
+
+    #[test]
+    fn typing_users() {
+        let user_id = UserId::try_from("@example:localhost").unwrap();
+        let alice = UserId::try_from("@alice:localhost").unwrap();
+        let mut room = Room::new(&get_room_id(), &user_id);
+
+        let typing = |users: Vec<&str>| {
+            serde_json::from_value::<EventJson<TypingEvent>>(serde_json::json!({
+                "content": { "user_ids": users },
+                "room_id": "!SVkFJHzfwvuaIEawgC:localhost",
+                "type": "m.typing"
+            }))
+            .unwrap()
+            .deserialize()
+            .unwrap()
+        };
+
+        assert!(room.receive_typing(&typing(vec!["@alice:localhost", "@example:localhost"])));
+        assert_eq!(room.typing_users, vec![alice.clone()]);
+        assert!(room.is_typing(&alice));
+        assert!(!room.is_typing(&user_id));
+
+        assert!(!room.receive_typing(&typing(vec!["@alice:localhost"])));
+        assert!(room.receive_typing(&typing(vec![])));
+        assert!(!room.is_typing(&alice));
+    }
+
+    #[test]
+    fn shared_presence_events() {
+        let user_id = UserId::try_from("@example:localhost").unwrap();
+        let other_room_id = RoomId::try_from("!other:localhost").unwrap();
+
+        let member = serde_json::from_str::<EventJson<MemberEvent>>(
+            &std::fs::read_to_string("../test_data/events/member.json").unwrap(),
+        )
+        .unwrap()
+        .deserialize()
+        .unwrap();
+        let presence = serde_json::from_str::<EventJson<PresenceEvent>>(
+            &std::fs::read_to_string("../test_data/events/presence.json").unwrap(),
+        )
+        .unwrap()
+        .deserialize()
+        .unwrap();
+        let presence = Arc::new(presence);
+
+        let mut rooms = vec![
+            Room::new(&get_room_id(), &user_id),
+            Room::new(&other_room_id, &user_id),
+        ];
+
+        for room in &mut rooms {
+            room.handle_membership(&member);
+            assert!(room.receive_shared_presence_event(&presence));
+            assert!(!room.receive_shared_presence_event(&presence));
+        }
+
+        let first = &rooms[0].members[&user_id].presence_events[0];
+        let second = &rooms[1].members[&user_id].presence_events[0];
+        assert!(Arc::ptr_eq(first, second));
+        assert_eq!(Arc::strong_count(&presence), 3);
+    }
+
+    #[test]
+    fn message_edits() {
+        let user_id = UserId::try_from("@example:localhost").unwrap();
+        let mut room = Room::new(&get_room_id(), &user_id);
+        let original = EventId::try_from("$original:localhost").unwrap();
+
+        let mut edit = serde_json::json!({
+            "content": {
+                "body": "* Hello world",
+                "msgtype": "m.text",
+                "m.new_content": {
+                    "body": "Hello world",
+                    "msgtype": "m.text"
+                },
+                "m.relates_to": {
+                    "rel_type": "m.replace",
+                    "event_id": "$original:localhost"
+                }
+            },
+            "event_id": "$edit:localhost",
+            "origin_server_ts": 1_520_372_800_469u64,
+            "sender": "@example:localhost",
+            "type": "m.room.message"
+        });
+
+        assert!(!room.is_edited(&original));
+        assert!(room.receive_edit(&edit));
+        assert!(room.is_edited(&original));
+
+        match room.latest_content(&original) {
+            Some(MessageEventContent::Text(text)) => assert_eq!(text.body, "Hello world"),
+            _ => panic!("Edit wasn't applied"),
+        }
+
+        // Edits of other users are ignored.
+        edit["sender"] = serde_json::json!("@mallory:localhost");
+        edit["content"]["m.new_content"]["body"] = serde_json::json!("Goodbye");
+        assert!(!room.receive_edit(&edit));
+
+        match room.latest_content(&original) {
+            Some(MessageEventContent::Text(text)) => assert_eq!(text.body, "Hello world"),
+            _ => panic!("Edit wasn't applied"),
+        }
+    }
+
+    #[test]
+    fn state_changes() {
+        let user_id = UserId::try_from("@example:localhost").unwrap();
+        let mut room = Room::new(&get_room_id(), &user_id);
+
+        let name_event = |name: &str| {
+            let json = serde_json::json!({
+                "content": { "name": name },
+                "event_id": "$h29iv0s8:example.com",
+                "origin_server_ts": 1_520_372_800_469u64,
+                "sender": "@example:localhost",
+                "state_key": "",
+                "type": "m.room.name"
+            });
+            serde_json::from_value::<EventJson<StateEvent>>(json)
+                .unwrap()
+                .deserialize()
+                .unwrap()
+        };
+
+        let empty = room.state_snapshot();
+        assert!(empty.is_empty());
+
+        room.receive_state_event(&name_event("Old name"));
+        let snapshot = room.state_snapshot();
+
+        assert_eq!(
+            room.state_changes_since(&empty),
+            vec![StateChange::Added {
+                event_type: "m.room.name".to_owned(),
+                state_key: "".to_owned(),
+                content: serde_json::json!({ "name": "Old name" }),
+            }]
+        );
+        assert!(room.state_changes_since(&snapshot).is_empty());
+
+        room.receive_state_event(&name_event("New name"));
+
+        assert_eq!(
+            room.state_changes_since(&snapshot),
+            vec![StateChange::Changed {
+                event_type: "m.room.name".to_owned(),
+                state_key: "".to_owned(),
+                old_content: serde_json::json!({ "name": "Old name" }),
+                new_content: serde_json::json!({ "name": "New name" }),
+            }]
+        );
+        assert_eq!(empty.diff(&snapshot).len(), snapshot.diff(&empty).len(),);
+        assert!(matches!(
+            snapshot.diff(&empty)[0],
+            StateChange::Removed { .. }
+        ));
+
+        let widget = serde_json::json!({
+            "content": { "url": "https://example.org" },
+            "event_id": "$h29iv0s9:example.com",
+            "origin_server_ts": 1_520_372_800_469u64,
+            "sender": "@example:localhost",
+            "state_key": "widget",
+            "type": "im.vector.modular.widgets"
+        });
+        let widget = serde_json::from_value::<EventJson<StateEvent>>(widget)
+            .unwrap()
+            .deserialize()
+            .unwrap();
+        let snapshot = room.state_snapshot();
+        room.receive_state_event(&widget);
+
+        assert_eq!(
+            room.state_changes_since(&snapshot),
+            vec![StateChange::Added {
+                event_type: "im.vector.modular.widgets".to_owned(),
+                state_key: "widget".to_owned(),
+                content: serde_json::json!({ "url": "https://example.org" }),
+            }]
+        );
+    }
+
+    #[test]
+    fn member_export() {
+        let user_id = UserId::try_from("@example:localhost").unwrap();
+        let mut room = Room::new(&get_room_id(), &user_id);
+        room.creator = Some(user_id);
+
+        let member = |user: &str, name: &str, membership: &str, ts: u64| {
+            serde_json::from_value::<EventJson<MemberEvent>>(json!({
+                "content": { "displayname": name, "membership": membership },
+                "event_id": format!("$member{}:localhost", ts),
+                "origin_server_ts": ts,
+                "sender": "@example:localhost",
+                "state_key": user,
+                "type": "m.room.member"
+            }))
+            .unwrap()
+            .deserialize()
+            .unwrap()
+        };
+
+        room.handle_membership(&member("@example:localhost", "Example, Sr.", "join", 1000));
+        room.handle_membership(&member("@bob:localhost", "Bob", "invite", 2000));
+
+        let mut csv = Vec::new();
+        room.export_members(&mut csv, MemberExportFormat::Csv)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "user_id,display_name,membership,power_level,joined_at\n\
+             @bob:localhost,Bob,invite,0,\n\
+             @example:localhost,\"Example, Sr.\",join,100,1000\n"
+        );
+
+        let mut json = Vec::new();
+        room.export_members(&mut json, MemberExportFormat::Json)
+            .unwrap();
+        let members: JsonValue = serde_json::from_slice(&json).unwrap();
+        assert_eq!(members[0]["joined_at"], JsonValue::Null);
+        assert_eq!(members[1]["display_name"], "Example, Sr.");
+        assert_eq!(members[1]["membership"], "join");
+        assert_eq!(members[1]["joined_at"], 1000);
     }
 }
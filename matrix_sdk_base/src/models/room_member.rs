@@ -14,6 +14,8 @@
 // limitations under the License.
 
 use std::convert::TryFrom;
+use std::sync::Arc;
+use std::time::SystemTime;
 
 use crate::events::collections::all::Event;
 use crate::events::presence::{PresenceEvent, PresenceEventContent, PresenceState};
@@ -58,12 +60,21 @@ pub struct RoomMember {
     pub membership: MembershipState,
     /// The human readable name of this room member.
     pub name: String,
+    /// Is the display name of the member shared with another joined or
+    /// invited member of the room.
+    #[serde(default)]
+    pub display_name_ambiguous: bool,
+    /// The time the member joined the room at, if it was seen joining.
+    #[serde(default)]
+    pub joined_at: Option<SystemTime>,
     /// The events that created the state of this room member.
     #[serde(deserialize_with = "super::event_deser::deserialize_events")]
-    pub events: Vec<Event>,
+    pub events: Vec<Arc<Event>>,
     /// The `PresenceEvent`s connected to this user.
+    ///
+    /// The events are shared with the other rooms the user is a member of.
     #[serde(deserialize_with = "super::event_deser::deserialize_presence")]
-    pub presence_events: Vec<PresenceEvent>,
+    pub presence_events: Vec<Arc<PresenceEvent>>,
 }
 
 impl PartialEq for RoomMember {
@@ -95,8 +106,24 @@ impl RoomMember {
             power_level: None,
             power_level_norm: None,
             membership: event.content.membership,
+            display_name_ambiguous: false,
+            joined_at: joined_at(event),
             presence_events: Vec::default(),
-            events: vec![Event::RoomMember(event.clone())],
+            events: vec![Arc::new(Event::RoomMember(event.clone()))],
+        }
+    }
+
+    /// The name clients should show for the member.
+    ///
+    /// This is the display name of the member, followed by the user id if
+    /// another member of the room uses the same display name, e.g. `Alice
+    /// (@alice:example.org)`. Members without a display name are shown with
+    /// their user id.
+    pub fn disambiguated_name(&self) -> String {
+        match self.display_name.as_deref().filter(|n| !n.is_empty()) {
+            Some(name) if self.display_name_ambiguous => format!("{} ({})", name, self.user_id),
+            Some(name) => name.to_owned(),
+            None => self.user_id.to_string(),
         }
     }
 
@@ -109,8 +136,13 @@ impl RoomMember {
                 self.avatar_url = event.content.avatar_url.clone();
                 true
             }
+            Joined => {
+                self.membership = event.content.membership;
+                self.joined_at = joined_at(event);
+                true
+            }
             Banned | Kicked | KickedAndBanned | InvitationRejected | InvitationRevoked | Left
-            | Unbanned | Joined | Invited => {
+            | Unbanned | Invited => {
                 self.membership = event.content.membership;
                 true
             }
@@ -132,7 +164,10 @@ impl RoomMember {
         }
 
         if max_power > Int::from(0) {
-            self.power_level_norm = Some((self.power_level.unwrap() * Int::from(100)) / max_power);
+            // Power levels are user controlled, don't overflow on huge ones.
+            if let Some(level) = self.power_level.and_then(|l| l.checked_mul(Int::from(100))) {
+                self.power_level_norm = Some(level / max_power);
+            }
         }
 
         changed
@@ -174,6 +209,18 @@ impl RoomMember {
     ///
     /// * `presence` - The presence event for a this room member.
     pub fn update_presence(&mut self, presence_ev: &PresenceEvent) {
+        self.update_shared_presence(Arc::new(presence_ev.clone()))
+    }
+
+    /// Updates the `User`s presence with a presence event that is shared
+    /// between rooms, the event isn't copied.
+    ///
+    /// This should only be used if `did_update_presence` was true.
+    ///
+    /// # Arguments
+    ///
+    /// * `presence` - The presence event for a this room member.
+    pub fn update_shared_presence(&mut self, presence_ev: Arc<PresenceEvent>) {
         let PresenceEvent {
             content:
                 PresenceEventContent {
@@ -185,18 +232,36 @@ impl RoomMember {
                     status_msg,
                 },
             ..
-        } = presence_ev;
+        } = &*presence_ev;
 
-        self.presence_events.push(presence_ev.clone());
         self.avatar_url = avatar_url.clone();
         self.currently_active = *currently_active;
         self.display_name = displayname.clone();
         self.last_active_ago = *last_active_ago;
         self.presence = Some(*presence);
         self.status_msg = status_msg.clone();
+        self.presence_events.push(presence_ev);
     }
 }
 
+/// The time a member event was sent at if it made the user join the room.
+fn joined_at(event: &MemberEvent) -> Option<SystemTime> {
+    if event.content.membership == MembershipState::Join {
+        Some(event.origin_server_ts)
+    } else {
+        None
+    }
+}
+
+/// The format `Room::export_members()` writes the members of a room in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MemberExportFormat {
+    /// Comma separated values with a header row.
+    Csv,
+    /// A JSON array with one object per member.
+    Json,
+}
+
 #[cfg(test)]
 mod test {
     use matrix_sdk_test::{async_test, EventBuilder, EventsFile};
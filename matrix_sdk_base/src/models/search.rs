@@ -0,0 +1,147 @@
+// Copyright 2020 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use serde::{Deserialize, Serialize};
+
+use crate::events::collections::all::RoomEvent;
+use crate::events::EventJson;
+use crate::identifiers::RoomId;
+use crate::js_int::UInt;
+
+/// The order in which the results of a search are returned.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SearchOrder {
+    /// The best matching events first.
+    Rank,
+    /// The most recent events first.
+    Recent,
+}
+
+impl Default for SearchOrder {
+    fn default() -> Self {
+        SearchOrder::Rank
+    }
+}
+
+/// A page of the events that matched a search, as returned by the `/search`
+/// endpoint.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct SearchResults {
+    /// An approximation of the total number of matching events.
+    #[serde(default)]
+    pub count: Option<UInt>,
+    /// The matching events.
+    #[serde(default)]
+    pub results: Vec<SearchResult>,
+    /// The words the server searched for, useful to highlight them in the
+    /// results.
+    #[serde(default)]
+    pub highlights: Vec<String>,
+    /// The token to fetch the next page with, `None` if this is the last
+    /// page.
+    #[serde(default)]
+    pub next_batch: Option<String>,
+}
+
+/// An event that matched a search.
+#[derive(Clone, Debug, Deserialize)]
+pub struct SearchResult {
+    /// How well the event matched the search, higher is better.
+    #[serde(default)]
+    pub rank: Option<f64>,
+    /// The matching event.
+    pub result: EventJson<RoomEvent>,
+    /// The events that came right before and after the matching event.
+    #[serde(default)]
+    pub context: Option<SearchContext>,
+}
+
+#[derive(Deserialize)]
+struct RoomIdWrapper {
+    room_id: RoomId,
+}
+
+impl SearchResult {
+    /// The id of the room the matching event belongs to.
+    pub fn room_id(&self) -> Option<RoomId> {
+        serde_json::from_str::<RoomIdWrapper>(self.result.json().get())
+            .map(|w| w.room_id)
+            .ok()
+    }
+}
+
+/// The events around an event that matched a search.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct SearchContext {
+    /// The events that came before the matching event, the newest one first.
+    #[serde(default)]
+    pub events_before: Vec<EventJson<RoomEvent>>,
+    /// The events that came after the matching event, the oldest one first.
+    #[serde(default)]
+    pub events_after: Vec<EventJson<RoomEvent>>,
+    /// The token to fetch older events with the `/messages` endpoint.
+    #[serde(default)]
+    pub start: Option<String>,
+    /// The token to fetch newer events with the `/messages` endpoint.
+    #[serde(default)]
+    pub end: Option<String>,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+    use std::convert::TryFrom;
+
+    #[test]
+    fn search_results_parsing() {
+        let results: SearchResults = serde_json::from_value(json!({
+            "count": 1,
+            "highlights": ["hello"],
+            "next_batch": "next",
+            "results": [{
+                "rank": 0.5,
+                "result": {
+                    "content": { "body": "hello world", "msgtype": "m.text" },
+                    "event_id": "$match:localhost",
+                    "origin_server_ts": 1_520_372_800_469u64,
+                    "room_id": "!room:localhost",
+                    "sender": "@example:localhost",
+                    "type": "m.room.message"
+                },
+                "context": { "events_before": [], "events_after": [], "start": "t1" }
+            }]
+        }))
+        .unwrap();
+
+        assert_eq!(results.next_batch.as_deref(), Some("next"));
+        assert_eq!(results.results.len(), 1);
+
+        let result = &results.results[0];
+        assert_eq!(result.rank, Some(0.5));
+        assert_eq!(
+            result.room_id(),
+            Some(RoomId::try_from("!room:localhost").unwrap())
+        );
+        assert_eq!(
+            result.context.as_ref().unwrap().start.as_deref(),
+            Some("t1")
+        );
+        assert_eq!(
+            serde_json::to_value(SearchOrder::Recent).unwrap(),
+            json!("recent")
+        );
+    }
+}
@@ -0,0 +1,139 @@
+// Copyright 2020 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::net::Ipv4Addr;
+
+use serde::{Deserialize, Serialize};
+
+/// The servers that may participate in a room, taken from the
+/// `m.room.server_acl` state event.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ServerAcl {
+    /// Are server names that are IP literals allowed.
+    #[serde(default = "default_allow_ip_literals")]
+    pub allow_ip_literals: bool,
+    /// The glob patterns of the allowed server names, `*` matches any number
+    /// of characters and `?` a single one.
+    #[serde(default)]
+    pub allow: Vec<String>,
+    /// The glob patterns of the denied server names, they take precedence
+    /// over the allowed ones.
+    #[serde(default)]
+    pub deny: Vec<String>,
+}
+
+fn default_allow_ip_literals() -> bool {
+    true
+}
+
+impl ServerAcl {
+    /// Is the server with the given name allowed to participate in the room.
+    ///
+    /// The port of the server name is ignored.
+    ///
+    /// # Arguments
+    ///
+    /// * `server_name` - The name of the server, e.g. `example.org:8448`.
+    pub fn is_allowed(&self, server_name: &str) -> bool {
+        let host = strip_port(server_name);
+
+        if !self.allow_ip_literals && is_ip_literal(host) {
+            return false;
+        }
+
+        if self.deny.iter().any(|p| glob_matches(p, host)) {
+            return false;
+        }
+
+        self.allow.iter().any(|p| glob_matches(p, host))
+    }
+}
+
+/// Remove the port from a server name, IPv6 literals keep their brackets.
+fn strip_port(server_name: &str) -> &str {
+    if server_name.starts_with('[') {
+        match server_name.find(']') {
+            Some(end) => &server_name[..=end],
+            None => server_name,
+        }
+    } else {
+        match server_name.rfind(':') {
+            Some(colon) => &server_name[..colon],
+            None => server_name,
+        }
+    }
+}
+
+fn is_ip_literal(host: &str) -> bool {
+    host.starts_with('[') || host.parse::<Ipv4Addr>().is_ok()
+}
+
+/// Match a host against a glob pattern, ignoring ASCII case.
+fn glob_matches(pattern: &str, host: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_ascii_lowercase().chars().collect();
+    let host: Vec<char> = host.to_ascii_lowercase().chars().collect();
+
+    let (mut p, mut h) = (0, 0);
+    // The position of the last `*` in the pattern and the position in the
+    // host it currently matches up to.
+    let mut backtrack = None;
+
+    while h < host.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == host[h]) {
+            p += 1;
+            h += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, h));
+            p += 1;
+        } else if let Some((star, matched)) = backtrack {
+            p = star + 1;
+            h = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn server_acl() {
+        let acl: ServerAcl = serde_json::from_value(serde_json::json!({
+            "allow": ["*"],
+            "deny": ["*.evil.com", "evil?.org"],
+            "allow_ip_literals": false
+        }))
+        .unwrap();
+
+        assert!(acl.is_allowed("matrix.org"));
+        assert!(acl.is_allowed("example.org:8448"));
+        assert!(!acl.is_allowed("sub.EVIL.com"));
+        assert!(acl.is_allowed("evil.com"));
+        assert!(!acl.is_allowed("evil1.org"));
+        assert!(!acl.is_allowed("1.2.3.4:8448"));
+        assert!(!acl.is_allowed("[::1]:8448"));
+
+        let acl: ServerAcl =
+            serde_json::from_value(serde_json::json!({ "allow": ["matrix.org"] })).unwrap();
+
+        assert!(acl.allow_ip_literals);
+        assert!(acl.is_allowed("matrix.org"));
+        assert!(!acl.is_allowed("example.org"));
+    }
+}
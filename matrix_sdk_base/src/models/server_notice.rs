@@ -0,0 +1,109 @@
+// Copyright 2020 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+
+/// The tag of the room the homeserver sends its server notices to.
+pub const SERVER_NOTICE_TAG: &str = "m.server_notice";
+
+/// A notice the homeserver sent to the user, e.g. because the user reached
+/// the usage limit of the homeserver.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ServerNotice {
+    /// The text of the notice.
+    pub body: String,
+    /// The kind of the notice.
+    pub kind: ServerNoticeKind,
+}
+
+/// The kind of a `ServerNotice`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ServerNoticeKind {
+    /// The homeserver reached a usage limit and blocks some of the requests
+    /// of the user, clients should show a banner until the next notice.
+    UsageLimitReached {
+        /// The URI the user can contact the administrator of the homeserver
+        /// at.
+        admin_contact: Option<String>,
+        /// The kind of the limit, e.g. `monthly_active_user`.
+        limit_type: Option<String>,
+    },
+    /// A notice of a type that isn't known to the SDK.
+    Other(String),
+}
+
+impl ServerNotice {
+    /// Parse the content of a `m.room.message` event with the
+    /// `m.server_notice` message type.
+    ///
+    /// Returns `None` if the content isn't the one of a server notice.
+    pub fn from_content(content: &JsonValue) -> Option<Self> {
+        if content["msgtype"] != "m.server_notice" {
+            return None;
+        }
+
+        let body = content["body"].as_str()?.to_owned();
+        let notice_type = content["server_notice_type"].as_str()?;
+        let string = |key: &str| content[key].as_str().map(ToOwned::to_owned);
+
+        let kind = match notice_type {
+            "m.server_notice.usage_limit_reached" => ServerNoticeKind::UsageLimitReached {
+                admin_contact: string("admin_contact"),
+                limit_type: string("limit_type"),
+            },
+            other => ServerNoticeKind::Other(other.to_owned()),
+        };
+
+        Some(Self { body, kind })
+    }
+
+    /// Is this a notice that the usage limit of the homeserver was reached.
+    pub fn is_usage_limit_reached(&self) -> bool {
+        matches!(self.kind, ServerNoticeKind::UsageLimitReached { .. })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn usage_limit_notice() {
+        let notice = ServerNotice::from_content(&json!({
+            "msgtype": "m.server_notice",
+            "body": "The monthly active user limit was reached",
+            "server_notice_type": "m.server_notice.usage_limit_reached",
+            "admin_contact": "mailto:admin@example.org",
+            "limit_type": "monthly_active_user"
+        }))
+        .unwrap();
+
+        assert!(notice.is_usage_limit_reached());
+        assert_eq!(
+            notice.kind,
+            ServerNoticeKind::UsageLimitReached {
+                admin_contact: Some("mailto:admin@example.org".to_owned()),
+                limit_type: Some("monthly_active_user".to_owned()),
+            }
+        );
+
+        assert!(ServerNotice::from_content(&json!({
+            "msgtype": "m.text",
+            "body": "hello"
+        }))
+        .is_none());
+    }
+}
@@ -0,0 +1,349 @@
+// Copyright 2020 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::{Deserialize, Serialize};
+
+use crate::identifiers::{RoomId, UserId};
+use crate::js_int::UInt;
+
+/// A page of the room hierarchy of a space, as returned by the
+/// `/hierarchy` endpoint.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct SpaceHierarchy {
+    /// The rooms of the space, the space itself comes first on the first page.
+    #[serde(default)]
+    pub rooms: Vec<SpaceHierarchyRoom>,
+    /// The token to fetch the next page with, `None` if this is the last
+    /// page.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_batch: Option<String>,
+}
+
+/// The summary of a room that is part of a space hierarchy.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SpaceHierarchyRoom {
+    /// The id of the room.
+    pub room_id: RoomId,
+    /// The name of the room.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// The topic of the room.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub topic: Option<String>,
+    /// The canonical alias of the room.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub canonical_alias: Option<String>,
+    /// The `mxc://` URI of the avatar of the room.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub avatar_url: Option<String>,
+    /// The number of members that are joined to the room.
+    #[serde(default)]
+    pub num_joined_members: UInt,
+    /// The type of the room, `m.space` for spaces.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub room_type: Option<String>,
+    /// The `m.space.child` state events of the room.
+    #[serde(default)]
+    pub children_state: Vec<SpaceChildEvent>,
+}
+
+impl SpaceHierarchyRoom {
+    /// Is the room a space itself.
+    pub fn is_space(&self) -> bool {
+        self.room_type.as_deref() == Some("m.space")
+    }
+
+    /// The children of the room that the admins of the space suggest to
+    /// join.
+    pub fn suggested_children(&self) -> impl Iterator<Item = &SpaceChildEvent> {
+        self.children_state.iter().filter(|c| c.content.suggested)
+    }
+}
+
+/// A stripped `m.space.child` state event, it links a child room to a space.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SpaceChildEvent {
+    /// The id of the child room.
+    pub state_key: RoomId,
+    /// The user that added the child to the space.
+    pub sender: UserId,
+    /// The content of the event.
+    pub content: SpaceChildContent,
+}
+
+/// The content of a `m.space.child` event.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct SpaceChildContent {
+    /// The servers the child room can be joined through.
+    #[serde(default)]
+    pub via: Vec<String>,
+    /// The string the children of a space are ordered by.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub order: Option<String>,
+    /// Do the admins of the space suggest to join the child room.
+    #[serde(default)]
+    pub suggested: bool,
+}
+
+/// The content of a `m.space.parent` event.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct SpaceParentContent {
+    /// The servers the parent space can be joined through.
+    #[serde(default)]
+    pub via: Vec<String>,
+    /// Is this the main parent of the room.
+    #[serde(default)]
+    pub canonical: bool,
+}
+
+/// The space relations of a room, taken from its `m.space.child` and
+/// `m.space.parent` state events.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Space {
+    /// The children of the room if it is a space, keyed by their room id.
+    #[serde(default)]
+    pub children: BTreeMap<RoomId, SpaceChildContent>,
+    /// The spaces the room claims to be part of, keyed by their room id.
+    #[serde(default)]
+    pub parents: BTreeMap<RoomId, SpaceParentContent>,
+}
+
+impl Space {
+    /// Does the room have any children, i.e. is it used as a space.
+    pub fn is_space(&self) -> bool {
+        !self.children.is_empty()
+    }
+
+    /// The ids of the children in the order they should be displayed in.
+    ///
+    /// Children with an `order` come first, sorted by it, the rest is sorted
+    /// by the room id.
+    pub fn ordered_children(&self) -> Vec<&RoomId> {
+        let mut children: Vec<_> = self.children.iter().collect();
+        children.sort_by(|(a_id, a), (b_id, b)| match (&a.order, &b.order) {
+            (Some(a), Some(b)) => a.cmp(b).then_with(|| a_id.cmp(b_id)),
+            (Some(_), None) => std::cmp::Ordering::Less,
+            (None, Some(_)) => std::cmp::Ordering::Greater,
+            (None, None) => a_id.cmp(b_id),
+        });
+        children.into_iter().map(|(id, _)| id).collect()
+    }
+
+    /// The canonical parent of the room, or any parent if none is marked as
+    /// canonical.
+    pub fn canonical_parent(&self) -> Option<&RoomId> {
+        self.parents
+            .iter()
+            .find(|(_, p)| p.canonical)
+            .or_else(|| self.parents.iter().next())
+            .map(|(id, _)| id)
+    }
+}
+
+/// A joined room and the joined rooms it contains if it is a space.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpaceTreeNode {
+    /// The id of the room.
+    pub room_id: RoomId,
+    /// The joined children of the room, in display order.
+    pub children: Vec<SpaceTreeNode>,
+}
+
+impl SpaceTreeNode {
+    /// Group rooms under the spaces they are part of.
+    ///
+    /// A room is placed under a space if the space lists it as a child or if
+    /// the room names the space as one of its parents, as long as both rooms
+    /// are part of the given rooms. Rooms that don't belong to any of the
+    /// given spaces are returned as roots, sorted by their room id.
+    ///
+    /// # Arguments
+    ///
+    /// * `rooms` - The space relations of the rooms, keyed by the room id.
+    pub(crate) fn build(rooms: &BTreeMap<RoomId, Space>) -> Vec<SpaceTreeNode> {
+        let mut children: BTreeMap<&RoomId, Vec<&RoomId>> = BTreeMap::new();
+
+        for (room_id, space) in rooms {
+            let listed = children.entry(room_id).or_default();
+            listed.extend(
+                space
+                    .ordered_children()
+                    .into_iter()
+                    .filter(|c| rooms.contains_key(*c) && *c != room_id),
+            );
+        }
+
+        for (room_id, space) in rooms {
+            for parent in space.parents.keys() {
+                if parent == room_id || !rooms.contains_key(parent) {
+                    continue;
+                }
+
+                let listed = children.entry(parent).or_default();
+                if !listed.contains(&room_id) {
+                    listed.push(room_id);
+                }
+            }
+        }
+
+        let has_parent: BTreeSet<&RoomId> = children.values().flatten().copied().collect();
+        let mut visited = BTreeSet::new();
+
+        let mut roots: Vec<_> = rooms
+            .keys()
+            .filter(|r| !has_parent.contains(r))
+            .filter_map(|r| SpaceTreeNode::walk(r, &children, &mut visited))
+            .collect();
+
+        // Rooms that are only part of a cycle of spaces don't have a root,
+        // put them at the top level so they don't get lost.
+        for room_id in rooms.keys() {
+            if let Some(node) = SpaceTreeNode::walk(room_id, &children, &mut visited) {
+                roots.push(node);
+            }
+        }
+
+        roots
+    }
+
+    fn walk<'a>(
+        room_id: &'a RoomId,
+        children: &BTreeMap<&'a RoomId, Vec<&'a RoomId>>,
+        visited: &mut BTreeSet<&'a RoomId>,
+    ) -> Option<SpaceTreeNode> {
+        if !visited.insert(room_id) {
+            return None;
+        }
+
+        let children = children
+            .get(room_id)
+            .into_iter()
+            .flatten()
+            .filter_map(|c| SpaceTreeNode::walk(c, children, visited))
+            .collect();
+
+        Some(SpaceTreeNode {
+            room_id: room_id.clone(),
+            children,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+    use std::convert::TryFrom;
+
+    fn room_id(id: &str) -> RoomId {
+        RoomId::try_from(id).unwrap()
+    }
+
+    fn via() -> Vec<String> {
+        vec!["example.org".to_owned()]
+    }
+
+    #[test]
+    fn space_tree() {
+        let mut rooms = BTreeMap::new();
+
+        let mut space = Space::default();
+        space.children.insert(
+            room_id("!child:example.org"),
+            SpaceChildContent {
+                via: via(),
+                ..Default::default()
+            },
+        );
+        space.children.insert(
+            room_id("!unknown:example.org"),
+            SpaceChildContent {
+                via: via(),
+                ..Default::default()
+            },
+        );
+        rooms.insert(room_id("!space:example.org"), space);
+        rooms.insert(room_id("!child:example.org"), Space::default());
+
+        let mut claiming = Space::default();
+        claiming.parents.insert(
+            room_id("!space:example.org"),
+            SpaceParentContent {
+                via: via(),
+                canonical: true,
+            },
+        );
+        rooms.insert(room_id("!claiming:example.org"), claiming);
+        rooms.insert(room_id("!alone:example.org"), Space::default());
+
+        let tree = SpaceTreeNode::build(&rooms);
+
+        assert_eq!(tree.len(), 2);
+        assert_eq!(tree[0].room_id, room_id("!alone:example.org"));
+        assert!(tree[0].children.is_empty());
+        assert_eq!(tree[1].room_id, room_id("!space:example.org"));
+
+        let children: Vec<_> = tree[1].children.iter().map(|c| &c.room_id).collect();
+        assert_eq!(
+            children,
+            vec![
+                &room_id("!child:example.org"),
+                &room_id("!claiming:example.org")
+            ]
+        );
+    }
+
+    #[test]
+    fn hierarchy_parsing() {
+        let hierarchy: SpaceHierarchy = serde_json::from_value(json!({
+            "rooms": [{
+                "room_id": "!space:example.org",
+                "name": "Space",
+                "num_joined_members": 42,
+                "room_type": "m.space",
+                "world_readable": true,
+                "guest_can_join": false,
+                "children_state": [
+                    {
+                        "type": "m.space.child",
+                        "state_key": "!suggested:example.org",
+                        "sender": "@admin:example.org",
+                        "origin_server_ts": 1_629_413_349_153u64,
+                        "content": { "via": ["example.org"], "suggested": true }
+                    },
+                    {
+                        "type": "m.space.child",
+                        "state_key": "!other:example.org",
+                        "sender": "@admin:example.org",
+                        "origin_server_ts": 1_629_413_349_153u64,
+                        "content": { "via": ["example.org"] }
+                    }
+                ]
+            }],
+            "next_batch": "next"
+        }))
+        .unwrap();
+
+        let space = &hierarchy.rooms[0];
+        assert!(space.is_space());
+        assert_eq!(space.num_joined_members, UInt::from(42u32));
+
+        let suggested: Vec<_> = space.suggested_children().collect();
+        assert_eq!(suggested.len(), 1);
+        assert_eq!(suggested[0].state_key.to_string(), "!suggested:example.org");
+        assert_eq!(hierarchy.next_batch.as_deref(), Some("next"));
+    }
+}
@@ -0,0 +1,192 @@
+// Copyright 2020 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+
+/// The state of a room at some point in time, e.g. after a sync with a
+/// specific sync token.
+///
+/// The state is stored as a map from the event type to a map from the state
+/// key to the content of the state entry. Snapshots can be serialized and
+/// remember the sync token they were taken at, so they can be persisted and
+/// matched up with a sync later on.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct RoomStateSnapshot {
+    #[serde(default)]
+    sync_token: Option<String>,
+    entries: BTreeMap<String, BTreeMap<String, JsonValue>>,
+}
+
+/// A single difference between two `RoomStateSnapshot`s.
+#[derive(Clone, Debug, PartialEq)]
+pub enum StateChange {
+    /// A state entry that didn't exist in the older snapshot.
+    Added {
+        /// The type of the state entry, e.g. `m.room.name`.
+        event_type: String,
+        /// The state key of the entry.
+        state_key: String,
+        /// The content of the new entry.
+        content: JsonValue,
+    },
+    /// A state entry whose content changed.
+    Changed {
+        /// The type of the state entry, e.g. `m.room.name`.
+        event_type: String,
+        /// The state key of the entry.
+        state_key: String,
+        /// The content of the entry in the older snapshot.
+        old_content: JsonValue,
+        /// The content of the entry in the newer snapshot.
+        new_content: JsonValue,
+    },
+    /// A state entry that doesn't exist in the newer snapshot anymore.
+    Removed {
+        /// The type of the state entry, e.g. `m.room.name`.
+        event_type: String,
+        /// The state key of the entry.
+        state_key: String,
+        /// The content the entry had in the older snapshot.
+        content: JsonValue,
+    },
+}
+
+impl StateChange {
+    /// The type of the state entry that changed.
+    pub fn event_type(&self) -> &str {
+        match self {
+            StateChange::Added { event_type, .. }
+            | StateChange::Changed { event_type, .. }
+            | StateChange::Removed { event_type, .. } => event_type,
+        }
+    }
+
+    /// The state key of the state entry that changed.
+    pub fn state_key(&self) -> &str {
+        match self {
+            StateChange::Added { state_key, .. }
+            | StateChange::Changed { state_key, .. }
+            | StateChange::Removed { state_key, .. } => state_key,
+        }
+    }
+}
+
+impl RoomStateSnapshot {
+    /// Create a new empty snapshot.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The sync token of the sync after which the snapshot was taken.
+    ///
+    /// This is `None` if the snapshot was taken before the first sync or
+    /// directly from a `Room`.
+    pub fn sync_token(&self) -> Option<&str> {
+        self.sync_token.as_deref()
+    }
+
+    /// Set the sync token the snapshot belongs to.
+    pub(crate) fn set_sync_token(&mut self, sync_token: Option<String>) {
+        self.sync_token = sync_token;
+    }
+
+    /// Insert a state entry into the snapshot.
+    ///
+    /// Entries with a `null` content are treated as missing.
+    pub(crate) fn insert(&mut self, event_type: &str, state_key: &str, content: JsonValue) {
+        if content.is_null() {
+            return;
+        }
+
+        self.entries
+            .entry(event_type.to_owned())
+            .or_insert_with(BTreeMap::new)
+            .insert(state_key.to_owned(), content);
+    }
+
+    /// Get the content of a state entry.
+    ///
+    /// # Arguments
+    ///
+    /// * `event_type` - The type of the state entry, e.g. `m.room.name`.
+    ///
+    /// * `state_key` - The state key of the entry.
+    pub fn get(&self, event_type: &str, state_key: &str) -> Option<&JsonValue> {
+        self.entries.get(event_type)?.get(state_key)
+    }
+
+    /// Is the snapshot empty.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Compute the changes that turn this snapshot into the given newer one.
+    ///
+    /// The changes are ordered by event type and state key.
+    ///
+    /// # Arguments
+    ///
+    /// * `newer` - The snapshot that was taken after this one.
+    pub fn diff(&self, newer: &RoomStateSnapshot) -> Vec<StateChange> {
+        let mut changes = Vec::new();
+        let empty = BTreeMap::new();
+
+        let mut event_types: Vec<&String> =
+            self.entries.keys().chain(newer.entries.keys()).collect();
+        event_types.sort();
+        event_types.dedup();
+
+        for event_type in event_types {
+            let old = self.entries.get(event_type).unwrap_or(&empty);
+            let new = newer.entries.get(event_type).unwrap_or(&empty);
+
+            for (state_key, content) in old {
+                match new.get(state_key) {
+                    None => changes.push(StateChange::Removed {
+                        event_type: event_type.clone(),
+                        state_key: state_key.clone(),
+                        content: content.clone(),
+                    }),
+                    Some(new_content) if new_content != content => {
+                        changes.push(StateChange::Changed {
+                            event_type: event_type.clone(),
+                            state_key: state_key.clone(),
+                            old_content: content.clone(),
+                            new_content: new_content.clone(),
+                        })
+                    }
+                    Some(_) => (),
+                }
+            }
+
+            for (state_key, content) in new {
+                if !old.contains_key(state_key) {
+                    changes.push(StateChange::Added {
+                        event_type: event_type.clone(),
+                        state_key: state_key.clone(),
+                        content: content.clone(),
+                    });
+                }
+            }
+        }
+
+        changes
+            .sort_by(|a, b| (a.event_type(), a.state_key()).cmp(&(b.event_type(), b.state_key())));
+
+        changes
+    }
+}
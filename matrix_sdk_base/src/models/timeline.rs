@@ -0,0 +1,434 @@
+// Copyright 2020 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::{HashSet, VecDeque};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::Deserialize;
+use serde_json::value::RawValue as RawJsonValue;
+
+use crate::events::collections::all::RoomEvent;
+use crate::identifiers::EventId;
+use crate::js_int::UInt;
+
+/// The maximal number of events a `Timeline` keeps, the oldest events are
+/// removed once there are more.
+const MAX_TIMELINE_EVENTS: usize = 1000;
+
+/// The maximal number of event ids a `Timeline` remembers to deduplicate
+/// events, independently of the events it still keeps.
+const MAX_SEEN_EVENTS: usize = 10_000;
+
+/// An event of a `Timeline`.
+#[derive(Clone, Debug)]
+pub struct TimelineEvent {
+    /// The unique id of the event.
+    pub event_id: EventId,
+    /// The timestamp in milliseconds the event was sent at.
+    pub origin_server_ts: UInt,
+    /// The event itself, shared with the rest of the sync processing.
+    pub event: Arc<RoomEvent>,
+}
+
+#[derive(Deserialize)]
+struct EventMeta {
+    event_id: EventId,
+    origin_server_ts: UInt,
+}
+
+impl TimelineEvent {
+    /// Create a timeline event from a parsed event and the raw JSON it was
+    /// parsed from.
+    ///
+    /// Returns `None` if the raw event has no event id or timestamp.
+    pub fn from_raw(raw: &RawJsonValue, event: Arc<RoomEvent>) -> Option<Self> {
+        let meta = serde_json::from_str::<EventMeta>(raw.get()).ok()?;
+
+        Some(Self {
+            event_id: meta.event_id,
+            origin_server_ts: meta.origin_server_ts,
+            event,
+        })
+    }
+}
+
+/// An entry of a `Timeline`.
+#[derive(Clone, Debug)]
+pub enum TimelineItem {
+    /// An event of the room.
+    Event(TimelineEvent),
+    /// Events are missing at this point of the timeline. The token can be
+    /// used to fetch them backwards with the `/messages` endpoint.
+    Gap(String),
+}
+
+/// The ordered events of a room, the oldest one first.
+///
+/// Events are kept in the order the server sent them in and every event is
+/// only stored once, no matter if it came down the sync stream, was fetched
+/// from the `/messages` endpoint or was both. If a sync response skips
+/// events, e.g. because the timeline of the room was limited, a
+/// `TimelineItem::Gap` marks the place where the events are missing.
+///
+/// Events that were removed by truncation or pruning are still recognized as
+/// duplicates, the ids of the last `MAX_SEEN_EVENTS` events are remembered
+/// separately from the events themselves.
+///
+/// The timeline only lives in memory, it isn't persisted in the state store
+/// yet and starts out empty once a room is restored from the store. Until it
+/// is, it runs alongside the `MessageQueue` of the room, which stays the
+/// source of the stored messages.
+#[derive(Clone, Debug, Default)]
+pub struct Timeline {
+    items: Vec<TimelineItem>,
+    event_ids: HashSet<EventId>,
+    seen_ids: HashSet<EventId>,
+    seen_order: VecDeque<EventId>,
+}
+
+impl PartialEq for Timeline {
+    fn eq(&self, other: &Timeline) -> bool {
+        self.items.len() == other.items.len()
+            && self
+                .items
+                .iter()
+                .zip(other.items.iter())
+                .all(|items| match items {
+                    (TimelineItem::Event(a), TimelineItem::Event(b)) => a.event_id == b.event_id,
+                    (TimelineItem::Gap(a), TimelineItem::Gap(b)) => a == b,
+                    _ => false,
+                })
+    }
+}
+
+impl Timeline {
+    /// Create a new empty `Timeline`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The events and gaps of the timeline, the oldest one first.
+    pub fn items(&self) -> &[TimelineItem] {
+        &self.items
+    }
+
+    /// Iterate over the events of the timeline, the oldest one first.
+    pub fn events(&self) -> impl Iterator<Item = &TimelineEvent> {
+        self.items.iter().filter_map(|i| match i {
+            TimelineItem::Event(e) => Some(e),
+            TimelineItem::Gap(_) => None,
+        })
+    }
+
+    /// The number of events in the timeline.
+    pub fn len(&self) -> usize {
+        self.event_ids.len()
+    }
+
+    /// Does the timeline contain no events.
+    pub fn is_empty(&self) -> bool {
+        self.event_ids.is_empty()
+    }
+
+    /// Does the timeline contain the event with the given id.
+    pub fn contains(&self, event_id: &EventId) -> bool {
+        self.event_ids.contains(event_id)
+    }
+
+    /// Was the event with the given id added to the timeline before, even if
+    /// it was removed from it since.
+    pub fn was_seen(&self, event_id: &EventId) -> bool {
+        self.seen_ids.contains(event_id)
+    }
+
+    /// Remember that the event with the given id was added to the timeline.
+    ///
+    /// Returns false if the event was seen before.
+    fn mark_seen(&mut self, event_id: &EventId) -> bool {
+        if !self.seen_ids.insert(event_id.clone()) {
+            return false;
+        }
+
+        self.seen_order.push_back(event_id.clone());
+
+        if self.seen_order.len() > MAX_SEEN_EVENTS {
+            if let Some(oldest) = self.seen_order.pop_front() {
+                self.seen_ids.remove(&oldest);
+            }
+        }
+
+        true
+    }
+
+    /// The tokens of the gaps in the timeline, the oldest one first.
+    pub fn gaps(&self) -> Vec<&str> {
+        self.items
+            .iter()
+            .filter_map(|i| match i {
+                TimelineItem::Gap(token) => Some(token.as_str()),
+                TimelineItem::Event(_) => None,
+            })
+            .collect()
+    }
+
+    /// The token of the most recent gap, the one that should be filled
+    /// first.
+    pub fn latest_gap(&self) -> Option<&str> {
+        self.gaps().pop()
+    }
+
+    /// Append an event to the timeline.
+    ///
+    /// Returns false if the event was already added to the timeline before.
+    pub fn push(&mut self, event: TimelineEvent) -> bool {
+        if !self.mark_seen(&event.event_id) {
+            return false;
+        }
+
+        self.event_ids.insert(event.event_id.clone());
+        self.items.push(TimelineItem::Event(event));
+        self.truncate(MAX_TIMELINE_EVENTS);
+        true
+    }
+
+    /// Mark that events are missing before the events that are appended
+    /// next.
+    ///
+    /// Two gaps next to each other are merged, only the newer token is kept.
+    ///
+    /// # Arguments
+    ///
+    /// * `token` - The token to fetch the missing events with.
+    pub fn push_gap(&mut self, token: &str) {
+        if let Some(TimelineItem::Gap(_)) = self.items.last() {
+            self.items.pop();
+        }
+
+        self.items.push(TimelineItem::Gap(token.to_owned()));
+    }
+
+    /// Fill a gap with events that were fetched backwards with the
+    /// `/messages` endpoint.
+    ///
+    /// Events that were already added to the timeline are skipped. Once an
+    /// event is reached that is already known the gap is closed, otherwise a
+    /// new gap is left in front of the events.
+    ///
+    /// Returns false if the timeline has no gap with the given token.
+    ///
+    /// # Arguments
+    ///
+    /// * `token` - The token of the gap, the `from` token of the request.
+    ///
+    /// * `events` - The fetched events, the newest one first.
+    ///
+    /// * `end` - The token to continue fetching events with, `None` if the
+    /// start of the room was reached.
+    pub fn fill_gap(&mut self, token: &str, events: Vec<TimelineEvent>, end: Option<&str>) -> bool {
+        let position = match self
+            .items
+            .iter()
+            .position(|i| matches!(i, TimelineItem::Gap(t) if t == token))
+        {
+            Some(p) => p,
+            None => return false,
+        };
+
+        let mut closed = false;
+        let mut new_items = Vec::new();
+
+        for event in events {
+            if !self.mark_seen(&event.event_id) {
+                closed = true;
+                break;
+            }
+
+            self.event_ids.insert(event.event_id.clone());
+            new_items.push(TimelineItem::Event(event));
+        }
+
+        if !closed {
+            if let Some(end) = end {
+                new_items.push(TimelineItem::Gap(end.to_owned()));
+            }
+        }
+
+        new_items.reverse();
+        self.items.splice(position..=position, new_items);
+        self.truncate(MAX_TIMELINE_EVENTS);
+
+        true
+    }
+
+    /// Remove all the events that were sent before the given point in time.
+    ///
+    /// Returns true if any event was removed.
+    pub fn prune_before(&mut self, before: SystemTime) -> bool {
+        let before = before
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or_default();
+
+        let position = self.items.iter().rposition(|i| match i {
+            TimelineItem::Event(e) => u128::from(u64::from(e.origin_server_ts)) < before,
+            TimelineItem::Gap(_) => false,
+        });
+
+        match position {
+            Some(p) => self.remove_front(p + 1),
+            None => false,
+        }
+    }
+
+    /// Remove the oldest events until at most `max` events are left.
+    ///
+    /// Returns true if any event was removed.
+    pub fn truncate(&mut self, max: usize) -> bool {
+        let mut excess = self.len().saturating_sub(max);
+
+        if excess == 0 {
+            return false;
+        }
+
+        let position = self
+            .items
+            .iter()
+            .position(|i| {
+                if let TimelineItem::Event(_) = i {
+                    excess -= 1;
+                }
+                excess == 0
+            })
+            .unwrap_or(self.items.len() - 1);
+
+        self.remove_front(position + 1)
+    }
+
+    /// Remove the first `count` items and the gaps that lead up to the
+    /// remaining events, the history before them isn't part of the timeline
+    /// anymore.
+    fn remove_front(&mut self, count: usize) -> bool {
+        for item in self.items.drain(..count) {
+            if let TimelineItem::Event(e) = item {
+                self.event_ids.remove(&e.event_id);
+            }
+        }
+
+        while let Some(TimelineItem::Gap(_)) = self.items.first() {
+            self.items.remove(0);
+        }
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::events::EventJson;
+
+    use serde_json::json;
+    use std::convert::TryFrom;
+
+    fn event(id: u64) -> TimelineEvent {
+        let raw = serde_json::from_value::<EventJson<RoomEvent>>(json!({
+            "content": { "body": "hello", "msgtype": "m.text" },
+            "event_id": format!("$event{}:localhost", id),
+            "origin_server_ts": id,
+            "sender": "@example:localhost",
+            "type": "m.room.message"
+        }))
+        .unwrap();
+        let parsed = Arc::new(raw.deserialize().unwrap());
+
+        TimelineEvent::from_raw(raw.json(), parsed).unwrap()
+    }
+
+    fn ids(timeline: &Timeline) -> Vec<u64> {
+        timeline
+            .events()
+            .map(|e| u64::from(e.origin_server_ts))
+            .collect()
+    }
+
+    #[test]
+    fn deduplication() {
+        let mut timeline = Timeline::new();
+
+        assert!(timeline.push(event(1)));
+        assert!(timeline.push(event(2)));
+        assert!(!timeline.push(event(1)));
+
+        assert_eq!(ids(&timeline), vec![1, 2]);
+        assert!(timeline.contains(&EventId::try_from("$event2:localhost").unwrap()));
+    }
+
+    #[test]
+    fn gap_filling() {
+        let mut timeline = Timeline::new();
+
+        timeline.push_gap("t0");
+        timeline.push(event(1));
+        timeline.push(event(2));
+        timeline.push_gap("t1");
+        timeline.push_gap("t2");
+        timeline.push(event(6));
+
+        assert_eq!(timeline.gaps(), vec!["t0", "t2"]);
+        assert_eq!(timeline.latest_gap(), Some("t2"));
+        assert!(!timeline.fill_gap("t1", vec![event(5)], Some("t3")));
+
+        // The first page doesn't reach the known events, a gap is left.
+        assert!(timeline.fill_gap("t2", vec![event(5), event(4)], Some("t3")));
+        assert_eq!(ids(&timeline), vec![1, 2, 4, 5, 6]);
+        assert_eq!(timeline.gaps(), vec!["t0", "t3"]);
+
+        // The second page overlaps with the known events, the gap is closed.
+        assert!(timeline.fill_gap("t3", vec![event(3), event(2), event(1)], Some("t4")));
+        assert_eq!(ids(&timeline), vec![1, 2, 3, 4, 5, 6]);
+        assert_eq!(timeline.gaps(), vec!["t0"]);
+
+        // Reaching the start of the room removes the gap as well.
+        assert!(timeline.fill_gap("t0", vec![event(0)], None));
+        assert_eq!(ids(&timeline), vec![0, 1, 2, 3, 4, 5, 6]);
+        assert!(timeline.gaps().is_empty());
+    }
+
+    #[test]
+    fn truncation() {
+        let mut timeline = Timeline::new();
+
+        timeline.push(event(1));
+        timeline.push_gap("t1");
+        timeline.push(event(3));
+        timeline.push(event(4));
+
+        assert!(!timeline.truncate(3));
+        assert!(timeline.truncate(2));
+        assert_eq!(ids(&timeline), vec![3, 4]);
+        assert!(timeline.gaps().is_empty());
+
+        assert!(timeline.prune_before(UNIX_EPOCH + std::time::Duration::from_millis(4)));
+        assert_eq!(ids(&timeline), vec![4]);
+        assert_eq!(timeline.len(), 1);
+
+        // Removed events are still known, they aren't added a second time.
+        let removed = EventId::try_from("$event1:localhost").unwrap();
+        assert!(!timeline.contains(&removed));
+        assert!(timeline.was_seen(&removed));
+        assert!(!timeline.push(event(1)));
+        assert_eq!(ids(&timeline), vec![4]);
+    }
+}
@@ -0,0 +1,219 @@
+// Copyright 2020 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use serde::{Deserialize, Serialize};
+use serde_json::value::RawValue as RawJsonValue;
+
+use crate::events::EventJson;
+use crate::identifiers::{EventId, UserId};
+use crate::js_int::{Int, UInt};
+
+/// The `unsigned` section of an event, the data the homeserver adds to the
+/// event it received.
+///
+/// The parsed events only carry parts of this section, use
+/// `Unsigned::from_event()` to get all of it from the raw event.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Unsigned {
+    /// The time in milliseconds that has elapsed since the event was sent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub age: Option<Int>,
+    /// The transaction id our own client sent the event with.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transaction_id: Option<String>,
+    /// The redaction event that redacted this event.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub redacted_because: Option<RedactedBecause>,
+    /// The aggregations of the events that relate to this event.
+    #[serde(
+        default,
+        rename = "m.relations",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub relations: Option<BundledRelations>,
+}
+
+/// The redaction event that redacted an event.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RedactedBecause {
+    /// The id of the redaction event.
+    pub event_id: EventId,
+    /// The user that redacted the event.
+    pub sender: UserId,
+    /// The timestamp in milliseconds the event was redacted at.
+    pub origin_server_ts: UInt,
+    /// The content of the redaction event.
+    #[serde(default)]
+    pub content: RedactionContent,
+}
+
+/// The content of a redaction event.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct RedactionContent {
+    /// The reason the event was redacted for.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+/// The aggregations of the events that relate to an event, bundled by the
+/// homeserver.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct BundledRelations {
+    /// The reactions to the event.
+    #[serde(
+        default,
+        rename = "m.annotation",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub annotation: Option<RelationChunk<BundledAnnotation>>,
+    /// The events that reference the event.
+    #[serde(
+        default,
+        rename = "m.reference",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub reference: Option<RelationChunk<BundledReference>>,
+    /// The latest edit of the event.
+    #[serde(default, rename = "m.replace", skip_serializing_if = "Option::is_none")]
+    pub replace: Option<BundledReplacement>,
+}
+
+/// A list of aggregated relations.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct RelationChunk<T> {
+    /// The aggregated relations.
+    pub chunk: Vec<T>,
+}
+
+/// The reactions with the same key to an event.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BundledAnnotation {
+    /// The type of the annotating events, usually `m.reaction`.
+    #[serde(rename = "type")]
+    pub event_type: String,
+    /// The key of the reactions.
+    pub key: String,
+    /// The number of reactions with the key.
+    pub count: UInt,
+}
+
+/// An event that references an event.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BundledReference {
+    /// The id of the referencing event.
+    pub event_id: EventId,
+}
+
+/// The latest edit of an event.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BundledReplacement {
+    /// The id of the edit.
+    pub event_id: EventId,
+    /// The user that edited the event.
+    pub sender: UserId,
+    /// The timestamp in milliseconds the event was edited at.
+    pub origin_server_ts: UInt,
+}
+
+#[derive(Deserialize)]
+struct UnsignedWrapper {
+    #[serde(default)]
+    unsigned: Unsigned,
+}
+
+impl Unsigned {
+    /// Get the `unsigned` section of the given event.
+    ///
+    /// Returns an empty section if the event doesn't have one or if it is
+    /// malformed.
+    pub fn from_event<T>(event: &EventJson<T>) -> Self {
+        Self::from_raw(event.json())
+    }
+
+    /// Get the `unsigned` section of the given raw event.
+    ///
+    /// Returns an empty section if the event doesn't have one or if it is
+    /// malformed.
+    pub fn from_raw(event: &RawJsonValue) -> Self {
+        serde_json::from_str::<UnsignedWrapper>(event.get())
+            .map(|w| w.unsigned)
+            .unwrap_or_default()
+    }
+
+    /// Has the event been redacted.
+    pub fn is_redacted(&self) -> bool {
+        self.redacted_because.is_some()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::events::collections::all::RoomEvent;
+
+    use std::convert::TryFrom;
+
+    #[test]
+    fn unsigned_parsing() {
+        let event = serde_json::from_value::<EventJson<RoomEvent>>(serde_json::json!({
+            "content": {},
+            "event_id": "$redacted:localhost",
+            "origin_server_ts": 1_520_372_800_469u64,
+            "sender": "@example:localhost",
+            "type": "m.room.message",
+            "unsigned": {
+                "age": 1234,
+                "redacted_because": {
+                    "content": { "reason": "Spam" },
+                    "event_id": "$redaction:localhost",
+                    "origin_server_ts": 1_520_372_800_470u64,
+                    "redacts": "$redacted:localhost",
+                    "sender": "@moderator:localhost",
+                    "type": "m.room.redaction"
+                },
+                "m.relations": {
+                    "m.annotation": {
+                        "chunk": [{ "type": "m.reaction", "key": "👍", "count": 3 }]
+                    },
+                    "m.replace": {
+                        "event_id": "$edit:localhost",
+                        "origin_server_ts": 1_520_372_800_471u64,
+                        "sender": "@example:localhost"
+                    }
+                }
+            }
+        }))
+        .unwrap();
+
+        let unsigned = Unsigned::from_event(&event);
+
+        assert_eq!(unsigned.age, Some(Int::from(1234)));
+        assert!(unsigned.is_redacted());
+        assert_eq!(
+            unsigned.redacted_because.unwrap().content.reason.as_deref(),
+            Some("Spam")
+        );
+
+        let relations = unsigned.relations.unwrap();
+        assert_eq!(
+            relations.annotation.unwrap().chunk[0].count,
+            UInt::from(3u32)
+        );
+        assert!(relations.reference.is_none());
+        assert_eq!(
+            relations.replace.unwrap().event_id,
+            EventId::try_from("$edit:localhost").unwrap()
+        );
+    }
+}
@@ -0,0 +1,82 @@
+// Copyright 2020 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+
+/// The preview of a URL that was found in a message.
+///
+/// The preview is generated by the homeserver from the OpenGraph metadata of
+/// the linked page.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct UrlPreview {
+    /// The URL the preview belongs to.
+    pub url: String,
+    /// The title of the page.
+    pub title: Option<String>,
+    /// A short description of the page.
+    pub description: Option<String>,
+    /// The name of the site the page belongs to.
+    pub site_name: Option<String>,
+    /// The `mxc://` URI of an image that represents the page.
+    pub image: Option<String>,
+}
+
+impl UrlPreview {
+    /// Create a preview from the OpenGraph data the homeserver returned.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - The URL the preview belongs to.
+    ///
+    /// * `data` - The OpenGraph data, a JSON object with keys like `og:title`.
+    pub fn from_open_graph(url: &str, data: &JsonValue) -> Self {
+        let field = |key: &str| data.get(key).and_then(|v| v.as_str()).map(str::to_owned);
+
+        Self {
+            url: url.to_owned(),
+            title: field("og:title"),
+            description: field("og:description"),
+            site_name: field("og:site_name"),
+            image: field("og:image"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn open_graph_parsing() {
+        let data = json!({
+            "og:title": "Matrix.org",
+            "og:description": "An open network for secure, decentralized communication",
+            "og:image": "mxc://example.com/ascERGshawAWawugaAcauga",
+            "og:image:width": 48,
+            "matrix:image:size": 102400
+        });
+
+        let preview = UrlPreview::from_open_graph("https://matrix.org", &data);
+
+        assert_eq!(preview.url, "https://matrix.org");
+        assert_eq!(preview.title.as_deref(), Some("Matrix.org"));
+        assert_eq!(
+            preview.image.as_deref(),
+            Some("mxc://example.com/ascERGshawAWawugaAcauga")
+        );
+        assert_eq!(preview.site_name, None);
+    }
+}
@@ -0,0 +1,294 @@
+// Copyright 2020 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::time::SystemTime;
+
+use crate::events::room::message::{MessageEvent, MessageEventContent};
+use crate::identifiers::{EventId, RoomId, UserId};
+
+/// The number of messages before and after a match that are returned as its
+/// context.
+const CONTEXT_SIZE: usize = 2;
+
+/// A message that is part of the `SearchIndex`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct IndexedMessage {
+    /// The id of the message event.
+    pub event_id: EventId,
+    /// The sender of the message.
+    pub sender: UserId,
+    /// The time the message was sent at.
+    pub origin_server_ts: SystemTime,
+    /// The body of the message.
+    pub body: String,
+}
+
+/// A message that matched a local search.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LocalSearchResult {
+    /// The room the message belongs to.
+    pub room_id: RoomId,
+    /// How well the message matched the search, higher is better.
+    pub rank: f64,
+    /// The matching message.
+    pub message: IndexedMessage,
+    /// The messages that came before the matching one, the oldest one first.
+    pub context_before: Vec<IndexedMessage>,
+    /// The messages that came after the matching one, the oldest one first.
+    pub context_after: Vec<IndexedMessage>,
+}
+
+/// The indexed messages of a single room.
+#[derive(Debug, Default)]
+struct RoomIndex {
+    /// The messages of the room, the oldest one first.
+    messages: Vec<IndexedMessage>,
+    event_ids: HashSet<EventId>,
+}
+
+impl RoomIndex {
+    fn add(&mut self, message: IndexedMessage) -> bool {
+        if !self.event_ids.insert(message.event_id.clone()) {
+            return false;
+        }
+
+        // Back-filled messages arrive after newer ones, keep the messages
+        // sorted so the context of a match is right.
+        let position = self
+            .messages
+            .iter()
+            .rposition(|m| m.origin_server_ts <= message.origin_server_ts)
+            .map(|p| p + 1)
+            .unwrap_or(0);
+        self.messages.insert(position, message);
+
+        true
+    }
+}
+
+/// An in-memory full-text index of the decrypted messages the client
+/// received.
+///
+/// The server can't search the messages of encrypted rooms, the index makes
+/// them searchable on the client side. Messages are ranked by how often the
+/// search terms appear in them, rare terms weigh more than common ones.
+#[derive(Debug, Default)]
+pub struct SearchIndex {
+    rooms: HashMap<RoomId, RoomIndex>,
+}
+
+impl SearchIndex {
+    /// Create a new empty `SearchIndex`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a message to the index.
+    ///
+    /// Only messages with a textual body, text, notice and emote messages,
+    /// are indexed.
+    ///
+    /// Returns false if the message wasn't indexed, either because it has no
+    /// textual body or because it is already part of the index.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The room the message belongs to.
+    ///
+    /// * `event` - The decrypted message event.
+    pub fn add_message(&mut self, room_id: &RoomId, event: &MessageEvent) -> bool {
+        let body = match &event.content {
+            MessageEventContent::Text(c) => &c.body,
+            MessageEventContent::Notice(c) => &c.body,
+            MessageEventContent::Emote(c) => &c.body,
+            _ => return false,
+        };
+
+        self.rooms
+            .entry(room_id.clone())
+            .or_default()
+            .add(IndexedMessage {
+                event_id: event.event_id.clone(),
+                sender: event.sender.clone(),
+                origin_server_ts: event.origin_server_ts,
+                body: body.clone(),
+            })
+    }
+
+    /// The number of indexed messages.
+    pub fn len(&self) -> usize {
+        self.rooms.values().map(|r| r.messages.len()).sum()
+    }
+
+    /// Is the index empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Search the indexed messages.
+    ///
+    /// A message matches if it contains all the words of the search term,
+    /// case is ignored. The best matching message comes first, messages
+    /// with the same rank are ordered newest first.
+    ///
+    /// # Arguments
+    ///
+    /// * `term` - The words to search for.
+    ///
+    /// * `room_id` - Only search the messages of this room if set.
+    pub fn search(&self, term: &str, room_id: Option<&RoomId>) -> Vec<LocalSearchResult> {
+        let terms = tokenize(term);
+
+        if terms.is_empty() {
+            return Vec::new();
+        }
+
+        let rooms: Vec<(&RoomId, &RoomIndex)> = match room_id {
+            Some(id) => self.rooms.get_key_value(id).into_iter().collect(),
+            None => self.rooms.iter().collect(),
+        };
+
+        let tokenized: Vec<Vec<(usize, Vec<String>)>> = rooms
+            .iter()
+            .map(|(_, room)| {
+                room.messages
+                    .iter()
+                    .enumerate()
+                    .map(|(i, m)| (i, tokenize(&m.body)))
+                    .collect()
+            })
+            .collect();
+
+        let total: usize = tokenized.iter().map(Vec::len).sum();
+
+        // Weigh every term by how rare it is among the searched messages.
+        let weights: Vec<f64> = terms
+            .iter()
+            .map(|term| {
+                let count = tokenized
+                    .iter()
+                    .flatten()
+                    .filter(|(_, words)| words.contains(term))
+                    .count();
+                (total as f64 / (count.max(1) as f64)).ln() + 1.0
+            })
+            .collect();
+
+        let mut results = Vec::new();
+
+        for ((room_id, room), messages) in rooms.iter().zip(tokenized) {
+            for (i, words) in messages {
+                let mut rank = 0.0;
+
+                for (term, weight) in terms.iter().zip(&weights) {
+                    let count = words.iter().filter(|w| *w == term).count();
+
+                    if count == 0 {
+                        rank = 0.0;
+                        break;
+                    }
+
+                    rank += count as f64 * weight;
+                }
+
+                if rank > 0.0 {
+                    let before = i.saturating_sub(CONTEXT_SIZE);
+                    let after = (i + 1 + CONTEXT_SIZE).min(room.messages.len());
+
+                    results.push(LocalSearchResult {
+                        room_id: (*room_id).clone(),
+                        rank: rank / words.len() as f64,
+                        message: room.messages[i].clone(),
+                        context_before: room.messages[before..i].to_vec(),
+                        context_after: room.messages[i + 1..after].to_vec(),
+                    });
+                }
+            }
+        }
+
+        results.sort_by(|a, b| {
+            b.rank
+                .partial_cmp(&a.rank)
+                .unwrap_or(Ordering::Equal)
+                .then_with(|| b.message.origin_server_ts.cmp(&a.message.origin_server_ts))
+        });
+
+        results
+    }
+}
+
+/// Split a text into lowercase words.
+fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(|w| w.to_lowercase())
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::events::EventJson;
+
+    use serde_json::json;
+    use std::convert::TryFrom;
+
+    fn message(id: u64, body: &str) -> MessageEvent {
+        serde_json::from_value::<EventJson<MessageEvent>>(json!({
+            "content": { "body": body, "msgtype": "m.text" },
+            "event_id": format!("$event{}:localhost", id),
+            "origin_server_ts": id,
+            "sender": "@example:localhost",
+            "type": "m.room.message"
+        }))
+        .unwrap()
+        .deserialize()
+        .unwrap()
+    }
+
+    #[test]
+    fn local_search() {
+        let mut index = SearchIndex::new();
+        let room_id = RoomId::try_from("!room:localhost").unwrap();
+        let other_room = RoomId::try_from("!other:localhost").unwrap();
+
+        assert!(index.add_message(&room_id, &message(1, "Good morning")));
+        assert!(index.add_message(&room_id, &message(3, "Is the build green?")));
+        assert!(index.add_message(&room_id, &message(4, "The build is broken, the BUILD!")));
+        assert!(index.add_message(&other_room, &message(5, "build it")));
+        // Back-filled messages are sorted in.
+        assert!(index.add_message(&room_id, &message(2, "Morning")));
+        assert!(!index.add_message(&room_id, &message(2, "Morning")));
+        assert_eq!(index.len(), 5);
+
+        let results = index.search("the build", Some(&room_id));
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].message.body, "The build is broken, the BUILD!");
+        assert_eq!(results[0].room_id, room_id);
+
+        let before: Vec<&str> = results[1]
+            .context_before
+            .iter()
+            .map(|m| m.body.as_str())
+            .collect();
+        assert_eq!(before, vec!["Good morning", "Morning"]);
+        assert_eq!(results[1].context_after.len(), 1);
+
+        assert_eq!(index.search("build", None).len(), 3);
+        assert!(index.search("deploy", None).is_empty());
+        assert!(index.search("  ", None).is_empty());
+    }
+}
@@ -27,3 +27,31 @@ pub struct Session {
     /// The ID of the client device
     pub device_id: String,
 }
+
+impl Session {
+    /// Create a session for an appservice that authenticates with the
+    /// `as_token` of its registration instead of logging in.
+    ///
+    /// Appservice tokens aren't tied to a device, the session has an empty
+    /// device id and the client skips everything that needs a device, like
+    /// end-to-end encryption.
+    ///
+    /// # Arguments
+    ///
+    /// * `as_token` - The `as_token` of the appservice registration.
+    ///
+    /// * `user_id` - The user the appservice acts as, usually its sender
+    /// user.
+    pub fn appservice(as_token: impl Into<String>, user_id: UserId) -> Self {
+        Session {
+            access_token: as_token.into(),
+            user_id,
+            device_id: String::new(),
+        }
+    }
+
+    /// Does the session belong to a device, false for appservice sessions.
+    pub fn has_device(&self) -> bool {
+        !self.device_id.is_empty()
+    }
+}
@@ -0,0 +1,73 @@
+// Copyright 2020 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::events::room::message::MessageEvent;
+use crate::identifiers::RoomId;
+
+/// The classification of an incoming message by a `SpamChecker`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SpamVerdict {
+    /// The message is fine.
+    Pass,
+    /// The message is delivered but looks suspicious, e.g. so it can be
+    /// hidden behind a warning.
+    Flag {
+        /// Why the message was flagged.
+        reason: String,
+    },
+    /// The message is spam, it doesn't update the room state and isn't
+    /// delivered to the `EventEmitter`.
+    Drop,
+}
+
+/// A check that classifies the messages of joined rooms before they update
+/// the room state and are delivered to the `EventEmitter`.
+///
+/// The check is async so it can e.g. ask an external spam detection service.
+/// The verdict of every message that isn't dropped is delivered to
+/// `EventEmitter::on_room_message_verdict()`.
+///
+/// # Examples
+/// ```
+/// # use matrix_sdk_base::{
+/// #     events::room::message::{MessageEvent, MessageEventContent},
+/// #     identifiers::RoomId,
+/// # };
+/// use matrix_sdk_base::{SpamChecker, SpamVerdict};
+///
+/// struct LinkChecker;
+///
+/// #[async_trait::async_trait]
+/// impl SpamChecker for LinkChecker {
+///     async fn check_message(&self, _: &RoomId, event: &MessageEvent) -> SpamVerdict {
+///         match &event.content {
+///             MessageEventContent::Text(c) if c.body.matches("http").count() > 5 => {
+///                 SpamVerdict::Drop
+///             }
+///             _ => SpamVerdict::Pass,
+///         }
+///     }
+/// }
+/// ```
+#[async_trait::async_trait]
+pub trait SpamChecker: Send + Sync {
+    /// Classify a message.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The room the message was sent to.
+    ///
+    /// * `event` - The message, decrypted if it was encrypted.
+    async fn check_message(&self, room_id: &RoomId, event: &MessageEvent) -> SpamVerdict;
+}
@@ -1,4 +1,5 @@
 use std::collections::HashMap;
+use std::convert::TryFrom;
 use std::fmt;
 use std::fs;
 use std::path::{Path, PathBuf};
@@ -12,7 +13,8 @@ use tokio::fs as async_fs;
 use tokio::io::AsyncWriteExt;
 
 use super::{AllRooms, ClientState, StateStore};
-use crate::{Error, Result, Room, RoomState, Session};
+use crate::identifiers::{RoomAliasId, RoomId};
+use crate::{Error, QueuedMessage, Result, Room, RoomState, RoomStateType, Session};
 
 /// A default `StateStore` implementation that serializes state as json
 /// and saves it to disk.
@@ -40,6 +42,103 @@ impl JsonStore {
     }
 }
 
+impl JsonStore {
+    async fn rooms_path(&self, room_state: RoomStateType) -> PathBuf {
+        let mut path = self.path.read().await.clone();
+        path.push("rooms");
+        path.push(match room_state {
+            RoomStateType::Joined => "joined",
+            RoomStateType::Invited => "invited",
+            RoomStateType::Left => "left",
+        });
+        path
+    }
+
+    async fn media_path(&self, key: &str) -> PathBuf {
+        let mut path = self.path.read().await.clone();
+        path.push("media");
+        path.push(escape_media_key(key));
+        path
+    }
+
+    async fn filter_path(&self, filter_name: &str) -> PathBuf {
+        let mut path = self.path.read().await.clone();
+        path.push("filters");
+        path.push(escape_media_key(filter_name));
+        path
+    }
+
+    async fn custom_value_path(&self, key: &str) -> PathBuf {
+        let mut path = self.path.read().await.clone();
+        path.push("custom");
+        path.push(escape_media_key(key));
+        path
+    }
+
+    /// Get the unescaped names of the files in the given directory of the
+    /// store.
+    async fn file_keys(&self, dir: &str) -> Result<Vec<String>> {
+        let mut path = self.path.read().await.clone();
+        path.push(dir);
+
+        if !path.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut keys = Vec::new();
+
+        for file in fs::read_dir(&path)? {
+            let file = file?;
+
+            if file.metadata()?.is_dir() {
+                continue;
+            }
+
+            if let Some(key) = file.file_name().to_str().and_then(unescape_media_key) {
+                keys.push(key);
+            }
+        }
+
+        Ok(keys)
+    }
+}
+
+/// Turn a media key into a file name, every character that isn't safe to use
+/// in a file name is percent encoded.
+fn escape_media_key(key: &str) -> String {
+    let mut escaped = String::with_capacity(key.len());
+
+    for byte in key.bytes() {
+        match byte {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'_' | b'.' => {
+                escaped.push(byte as char)
+            }
+            _ => escaped.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+
+    escaped
+}
+
+/// Turn a file name that was created by `escape_media_key()` back into the
+/// media key.
+fn unescape_media_key(name: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(name.len());
+    let mut iter = name.bytes();
+
+    while let Some(byte) = iter.next() {
+        if byte == b'%' {
+            let hex = [iter.next()?, iter.next()?];
+            let hex = std::str::from_utf8(&hex).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+        } else {
+            bytes.push(byte);
+        }
+    }
+
+    String::from_utf8(bytes).ok()
+}
+
 impl fmt::Debug for JsonStore {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("JsonStore")
@@ -113,6 +212,66 @@ impl StateStore for JsonStore {
         })
     }
 
+    async fn load_room_ids(&self, room_state: RoomStateType) -> Result<Vec<RoomId>> {
+        let path = self.rooms_path(room_state).await;
+
+        // don't load rooms that aren't saved yet
+        if !path.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut room_ids = Vec::new();
+
+        for file in fs::read_dir(&path)? {
+            let file = file?.path();
+
+            if file.is_dir() || file.extension().and_then(|e| e.to_str()) != Some("json") {
+                continue;
+            }
+
+            if let Some(room_id) = file
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .and_then(|s| RoomId::try_from(s).ok())
+            {
+                room_ids.push(room_id);
+            }
+        }
+
+        Ok(room_ids)
+    }
+
+    fn loads_rooms_partially(&self) -> bool {
+        true
+    }
+
+    async fn load_rooms(
+        &self,
+        room_state: RoomStateType,
+        room_ids: &[RoomId],
+    ) -> Result<Vec<Room>> {
+        let path = self.rooms_path(room_state).await;
+        let mut rooms = Vec::with_capacity(room_ids.len());
+
+        for room_id in room_ids {
+            let file = path.join(format!("{}.json", room_id));
+
+            if !file.exists() {
+                continue;
+            }
+
+            let json = async_fs::read_to_string(&file).await?;
+            rooms.push(serde_json::from_str::<Room>(&json).map_err(Error::from)?);
+        }
+
+        Ok(rooms)
+    }
+
+    async fn load_rooms_with_state(&self, room_state: RoomStateType) -> Result<Vec<Room>> {
+        let room_ids = self.load_room_ids(room_state).await?;
+        self.load_rooms(room_state, &room_ids).await
+    }
+
     async fn store_client_state(&self, state: ClientState) -> Result<()> {
         let mut path = self.path.read().await.clone();
         path.push("client.json");
@@ -166,6 +325,267 @@ impl StateStore for JsonStore {
             .await?;
         file.write_all(json.as_bytes()).await.map_err(Error::from)
     }
+
+    async fn remove_room(&self, room_id: &RoomId) -> Result<()> {
+        for room_state in &[
+            RoomStateType::Joined,
+            RoomStateType::Invited,
+            RoomStateType::Left,
+        ] {
+            let path = self
+                .rooms_path(*room_state)
+                .await
+                .join(format!("{}.json", room_id));
+
+            if path.exists() {
+                async_fs::remove_file(path).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn load_media(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        let path = self.media_path(key).await;
+
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        Ok(Some(async_fs::read(path).await?))
+    }
+
+    async fn store_media(&self, key: &str, data: &[u8]) -> Result<()> {
+        let path = self.media_path(key).await;
+
+        if !path.exists() {
+            let mut dir = path.clone();
+            dir.pop();
+            async_fs::create_dir_all(dir).await?;
+        }
+
+        let mut file = async_fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)
+            .await?;
+        file.write_all(data).await.map_err(Error::from)
+    }
+
+    async fn remove_media(&self, key: &str) -> Result<()> {
+        let path = self.media_path(key).await;
+
+        if path.exists() {
+            async_fs::remove_file(path).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn load_media_keys(&self) -> Result<Vec<(String, usize)>> {
+        let mut path = self.path.read().await.clone();
+        path.push("media");
+
+        if !path.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut media = Vec::new();
+
+        for file in fs::read_dir(&path)? {
+            let file = file?;
+            let metadata = file.metadata()?;
+
+            if metadata.is_dir() {
+                continue;
+            }
+
+            let key = match file.file_name().to_str().and_then(unescape_media_key) {
+                Some(k) => k,
+                None => continue,
+            };
+
+            media.push((metadata.modified()?, key, metadata.len() as usize));
+        }
+
+        media.sort();
+
+        Ok(media
+            .into_iter()
+            .map(|(_, key, size)| (key, size))
+            .collect())
+    }
+
+    async fn load_filter(&self, filter_name: &str) -> Result<Option<String>> {
+        let path = self.filter_path(filter_name).await;
+
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        Ok(Some(async_fs::read_to_string(path).await?))
+    }
+
+    async fn store_filter(&self, filter_name: &str, filter_id: &str) -> Result<()> {
+        let path = self.filter_path(filter_name).await;
+
+        if !path.exists() {
+            let mut dir = path.clone();
+            dir.pop();
+            async_fs::create_dir_all(dir).await?;
+        }
+
+        let mut file = async_fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)
+            .await?;
+        file.write_all(filter_id.as_bytes())
+            .await
+            .map_err(Error::from)
+    }
+
+    async fn load_filters(&self) -> Result<Vec<(String, String)>> {
+        let mut filters = Vec::new();
+
+        for filter_name in self.file_keys("filters").await? {
+            if let Some(filter_id) = self.load_filter(&filter_name).await? {
+                filters.push((filter_name, filter_id));
+            }
+        }
+
+        Ok(filters)
+    }
+
+    async fn get_custom_value(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        let path = self.custom_value_path(key).await;
+
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        Ok(Some(async_fs::read(path).await?))
+    }
+
+    async fn set_custom_value(&self, key: &str, value: &[u8]) -> Result<()> {
+        let path = self.custom_value_path(key).await;
+
+        if !path.exists() {
+            let mut dir = path.clone();
+            dir.pop();
+            async_fs::create_dir_all(dir).await?;
+        }
+
+        let mut file = async_fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)
+            .await?;
+        file.write_all(value).await.map_err(Error::from)
+    }
+
+    async fn remove_custom_value(&self, key: &str) -> Result<()> {
+        let path = self.custom_value_path(key).await;
+
+        if path.exists() {
+            async_fs::remove_file(path).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn load_custom_value_keys(&self) -> Result<Vec<String>> {
+        self.file_keys("custom").await
+    }
+
+    async fn load_queued_messages(&self) -> Result<Vec<QueuedMessage>> {
+        let mut path = self.path.read().await.clone();
+        path.push("send_queue.json");
+
+        if !path.exists() {
+            return Ok(Vec::new());
+        }
+
+        let json = async_fs::read_to_string(path).await?;
+        serde_json::from_str(&json).map_err(Error::from)
+    }
+
+    async fn store_queued_messages(&self, messages: &[QueuedMessage]) -> Result<()> {
+        let mut path = self.path.read().await.clone();
+        path.push("send_queue.json");
+
+        if !path.exists() {
+            let mut dir = path.clone();
+            dir.pop();
+            async_fs::create_dir_all(dir).await?;
+        }
+
+        let json = serde_json::to_string(messages).map_err(Error::from)?;
+
+        let mut file = async_fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)
+            .await?;
+        file.write_all(json.as_bytes()).await.map_err(Error::from)
+    }
+
+    async fn load_room_aliases(&self) -> Result<HashMap<RoomAliasId, RoomId>> {
+        let mut path = self.path.read().await.clone();
+        path.push("room_aliases.json");
+
+        if !path.exists() {
+            return Ok(HashMap::new());
+        }
+
+        let json = async_fs::read_to_string(path).await?;
+        serde_json::from_str(&json).map_err(Error::from)
+    }
+
+    async fn store_room_aliases(&self, aliases: &HashMap<RoomAliasId, RoomId>) -> Result<()> {
+        let mut path = self.path.read().await.clone();
+        path.push("room_aliases.json");
+
+        if !path.exists() {
+            let mut dir = path.clone();
+            dir.pop();
+            async_fs::create_dir_all(dir).await?;
+        }
+
+        let json = serde_json::to_string(aliases).map_err(Error::from)?;
+
+        let mut file = async_fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)
+            .await?;
+        file.write_all(json.as_bytes()).await.map_err(Error::from)
+    }
+
+    async fn clear(&self) -> Result<()> {
+        let path = self.path.read().await.clone();
+
+        if !path.exists() {
+            return Ok(());
+        }
+
+        for entry in fs::read_dir(&path)? {
+            let entry = entry?.path();
+
+            if entry.is_dir() {
+                async_fs::remove_dir_all(entry).await?;
+            } else {
+                async_fs::remove_file(entry).await?;
+            }
+        }
+
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -173,6 +593,7 @@ mod test {
     use super::*;
 
     use http::Response;
+    use std::collections::BTreeMap;
     use std::convert::TryFrom;
     use std::fs::File;
     use std::io::Read;
@@ -181,8 +602,8 @@ mod test {
     use tempfile::tempdir;
 
     use crate::api::r0::sync::sync_events::Response as SyncResponse;
-    use crate::identifiers::{RoomId, UserId};
-    use crate::{BaseClient, Session};
+    use crate::identifiers::{RoomAliasId, RoomId, UserId};
+    use crate::{BaseClient, BaseClientConfig, Session};
 
     fn sync_response(file: &str) -> SyncResponse {
         let mut file = File::open(file).unwrap();
@@ -209,6 +630,8 @@ mod test {
             sync_token: Some("hello".into()),
             ignored_users: vec![user],
             push_ruleset: None,
+            direct_rooms: BTreeMap::new(),
+            account_data: BTreeMap::new(),
         };
 
         let mut path_with_user = PathBuf::from(path);
@@ -241,6 +664,131 @@ mod test {
         assert_eq!(joined.get(&id), Some(&Room::new(&id, &user)));
     }
 
+    #[tokio::test]
+    async fn test_store_load_media() {
+        let dir = tempdir().unwrap();
+        let path: &Path = dir.path();
+        let store = JsonStore::open(path).unwrap();
+
+        let key = "mxc://example.com:8448/AQwafuaFswefuhsfAFAgsw";
+
+        assert_eq!(store.load_media(key).await.unwrap(), None);
+
+        store.store_media(key, b"media").await.unwrap();
+        assert_eq!(
+            store.load_media(key).await.unwrap(),
+            Some(b"media".to_vec())
+        );
+        assert_eq!(
+            store.load_media_keys().await.unwrap(),
+            vec![(key.to_owned(), 5)]
+        );
+
+        store.remove_media(key).await.unwrap();
+        assert_eq!(store.load_media(key).await.unwrap(), None);
+        assert!(store.load_media_keys().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_store_load_filter() {
+        let dir = tempdir().unwrap();
+        let path: &Path = dir.path();
+        let store = JsonStore::open(path).unwrap();
+
+        assert_eq!(store.load_filter("sync").await.unwrap(), None);
+
+        store.store_filter("sync", "filter_id").await.unwrap();
+
+        let store = JsonStore::open(path).unwrap();
+        assert_eq!(
+            store.load_filter("sync").await.unwrap(),
+            Some("filter_id".to_owned())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_custom_values() {
+        let dir = tempdir().unwrap();
+        let path: &Path = dir.path();
+        let store = JsonStore::open(path).unwrap();
+
+        let key = "org.example.bot/last_command";
+
+        assert_eq!(store.get_custom_value(key).await.unwrap(), None);
+
+        store.set_custom_value(key, b"!ping").await.unwrap();
+        store.set_custom_value(key, b"!help").await.unwrap();
+        assert_eq!(
+            store.get_custom_value(key).await.unwrap(),
+            Some(b"!help".to_vec())
+        );
+
+        let store = JsonStore::open(path).unwrap();
+        assert_eq!(
+            store.get_custom_value(key).await.unwrap(),
+            Some(b"!help".to_vec())
+        );
+
+        store.remove_custom_value(key).await.unwrap();
+        assert_eq!(store.get_custom_value(key).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_persisted_send_queue() {
+        use crate::events::room::message::{MessageEventContent, TextMessageEventContent};
+        use crate::SendState;
+
+        let dir = tempdir().unwrap();
+        let path: &Path = dir.path();
+
+        let session = Session {
+            access_token: "1234".to_owned(),
+            user_id: UserId::try_from("@cheeky_monkey:matrix.org").unwrap(),
+            device_id: "DEVICEID".to_owned(),
+        };
+        let room_id = RoomId::try_from("!SVkFJHzfwvuaIEawgC:localhost").unwrap();
+        let content = MessageEventContent::Text(TextMessageEventContent {
+            body: "Hello world".to_owned(),
+            format: None,
+            formatted_body: None,
+            relates_to: None,
+        });
+
+        let store = Box::new(JsonStore::open(path).unwrap());
+        let client = BaseClient::new_with_state_store(Some(session.clone()), store).unwrap();
+
+        let mut response = sync_response("../test_data/sync.json");
+        client.receive_sync_response(&mut response).await.unwrap();
+
+        client
+            .queue_message(&room_id, "first", content.clone())
+            .await
+            .unwrap();
+        client
+            .queue_message(&room_id, "second", content)
+            .await
+            .unwrap();
+
+        let message = client.next_queued_message().await.unwrap().unwrap();
+        assert_eq!(message.txn_id, "first");
+        assert_eq!(
+            client.queued_messages(None).await[0].state,
+            SendState::Sending
+        );
+
+        // Messages that were being sent when the client stopped are queued
+        // again after a restart.
+        let store = Box::new(JsonStore::open(path).unwrap());
+        let client = BaseClient::new_with_state_store(Some(session), store).unwrap();
+        client.sync_with_state_store().await.unwrap();
+
+        let queued = client.queued_messages(Some(&room_id)).await;
+        assert_eq!(queued.len(), 2);
+        assert_eq!(queued[0].txn_id, "first");
+        assert_eq!(queued[0].state, SendState::Queued);
+        assert_eq!(queued[1].txn_id, "second");
+    }
+
     #[tokio::test]
     async fn test_store_load_left_room_state() {
         let dir = tempdir().unwrap();
@@ -313,4 +861,267 @@ mod test {
             vec![UserId::try_from("@someone:example.org").unwrap()]
         );
     }
+
+    #[tokio::test]
+    async fn test_load_room_batches() {
+        let dir = tempdir().unwrap();
+        let path: &Path = dir.path();
+        let store = JsonStore::open(path).unwrap();
+
+        let user = UserId::try_from("@example:example.com").unwrap();
+        let first = RoomId::try_from("!first:example.com").unwrap();
+        let second = RoomId::try_from("!second:example.com").unwrap();
+        let missing = RoomId::try_from("!missing:example.com").unwrap();
+
+        for id in &[&first, &second] {
+            store
+                .store_room_state(RoomState::Joined(&Room::new(id, &user)))
+                .await
+                .unwrap();
+        }
+
+        let mut ids = store.load_room_ids(RoomStateType::Joined).await.unwrap();
+        ids.sort_by(|a, b| a.to_string().cmp(&b.to_string()));
+        assert_eq!(ids, vec![first.clone(), second.clone()]);
+        assert!(store
+            .load_room_ids(RoomStateType::Left)
+            .await
+            .unwrap()
+            .is_empty());
+
+        let rooms = store
+            .load_rooms(RoomStateType::Joined, &[second.clone(), missing])
+            .await
+            .unwrap();
+        assert_eq!(rooms, vec![Room::new(&second, &user)]);
+
+        let mut rooms = store
+            .load_rooms_with_state(RoomStateType::Joined)
+            .await
+            .unwrap();
+        rooms.sort_by(|a, b| a.room_id.to_string().cmp(&b.room_id.to_string()));
+        assert_eq!(
+            rooms,
+            vec![Room::new(&first, &user), Room::new(&second, &user)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_remove_room() {
+        let dir = tempdir().unwrap();
+        let path: &Path = dir.path();
+        let store = JsonStore::open(path).unwrap();
+
+        let id = RoomId::try_from("!roomid:example.com").unwrap();
+        let user = UserId::try_from("@example:example.com").unwrap();
+
+        let room = Room::new(&id, &user);
+        store
+            .store_room_state(RoomState::Joined(&room))
+            .await
+            .unwrap();
+        store
+            .store_room_state(RoomState::Left(&room))
+            .await
+            .unwrap();
+
+        store.remove_room(&id).await.unwrap();
+
+        let AllRooms { joined, left, .. } = store.load_all_rooms().await.unwrap();
+        assert!(joined.is_empty());
+        assert!(left.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_export_to() {
+        let dir = tempdir().unwrap();
+        let other_dir = tempdir().unwrap();
+        let store = JsonStore::open(dir.path()).unwrap();
+        let other = JsonStore::open(other_dir.path()).unwrap();
+
+        let id = RoomId::try_from("!roomid:example.com").unwrap();
+        let user = UserId::try_from("@example:example.com").unwrap();
+        let key = "mxc://example.com:8448/AQwafuaFswefuhsfAFAgsw";
+
+        let session = Session {
+            access_token: "32nj9zu034btz90".to_string(),
+            user_id: user.clone(),
+            device_id: "Tester".to_string(),
+        };
+        let state = ClientState {
+            sync_token: Some("hello".into()),
+            ignored_users: vec![],
+            push_ruleset: None,
+            direct_rooms: BTreeMap::new(),
+            account_data: BTreeMap::new(),
+        };
+
+        store.load_client_state(&session).await.unwrap();
+        store.store_client_state(state.clone()).await.unwrap();
+        store
+            .store_room_state(RoomState::Left(&Room::new(&id, &user)))
+            .await
+            .unwrap();
+        store.store_media(key, b"media").await.unwrap();
+        store.store_filter("sync", "filter_id").await.unwrap();
+        store.set_custom_value("bot/last", b"!cmd").await.unwrap();
+
+        store.export_to(&other, &session).await.unwrap();
+
+        let other = JsonStore::open(other_dir.path()).unwrap();
+        assert_eq!(
+            other.load_client_state(&session).await.unwrap(),
+            Some(state)
+        );
+        let AllRooms { left, .. } = other.load_all_rooms().await.unwrap();
+        assert_eq!(left.get(&id), Some(&Room::new(&id, &user)));
+        assert_eq!(
+            other.load_media(key).await.unwrap(),
+            Some(b"media".to_vec())
+        );
+        assert_eq!(
+            other.load_filter("sync").await.unwrap(),
+            Some("filter_id".to_owned())
+        );
+        assert_eq!(
+            other.get_custom_value("bot/last").await.unwrap(),
+            Some(b"!cmd".to_vec())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_room_alias_cache() {
+        let dir = tempdir().unwrap();
+        let path: &Path = dir.path();
+
+        let session = Session {
+            access_token: "1234".to_owned(),
+            user_id: UserId::try_from("@cheeky_monkey:matrix.org").unwrap(),
+            device_id: "DEVICEID".to_owned(),
+        };
+        let alias = RoomAliasId::try_from("#room:example.com").unwrap();
+        let room_id = RoomId::try_from("!roomid:example.com").unwrap();
+
+        let store = Box::new(JsonStore::open(path).unwrap());
+        let client = BaseClient::new_with_state_store(Some(session.clone()), store).unwrap();
+        client.sync_with_state_store().await.unwrap();
+
+        assert_eq!(client.cached_room_id(&alias).await, None);
+        client.cache_room_alias(&alias, &room_id).await.unwrap();
+
+        // the canonical aliases of the joined rooms are cached as well
+        let mut response = sync_response("../test_data/sync.json");
+        client.receive_sync_response(&mut response).await.unwrap();
+
+        let store = Box::new(JsonStore::open(path).unwrap());
+        let client = BaseClient::new_with_state_store(Some(session), store).unwrap();
+        client.sync_with_state_store().await.unwrap();
+
+        assert_eq!(client.cached_room_id(&alias).await, Some(room_id));
+        assert_eq!(
+            client
+                .cached_room_id(&RoomAliasId::try_from("#tutorial:localhost").unwrap())
+                .await,
+            Some(RoomId::try_from("!SVkFJHzfwvuaIEawgC:localhost").unwrap())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_clear() {
+        let dir = tempdir().unwrap();
+        let path: &Path = dir.path();
+        let store = JsonStore::open(path).unwrap();
+
+        let id = RoomId::try_from("!roomid:example.com").unwrap();
+        let user = UserId::try_from("@example:example.com").unwrap();
+        let key = "mxc://example.com:8448/AQwafuaFswefuhsfAFAgsw";
+
+        let room = Room::new(&id, &user);
+        store
+            .store_room_state(RoomState::Joined(&room))
+            .await
+            .unwrap();
+        store.store_media(key, b"media").await.unwrap();
+
+        store.clear().await.unwrap();
+
+        let AllRooms { joined, .. } = store.load_all_rooms().await.unwrap();
+        assert!(joined.is_empty());
+        assert_eq!(store.load_media(key).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_lazy_left_rooms() {
+        let dir = tempdir().unwrap();
+        let path: &Path = dir.path();
+
+        let session = Session {
+            access_token: "1234".to_owned(),
+            user_id: UserId::try_from("@example:localhost").unwrap(),
+            device_id: "DEVICEID".to_owned(),
+        };
+        let room_id = RoomId::try_from("!SVkFJHzfwvuaIEawgC:localhost").unwrap();
+
+        let store = Box::new(JsonStore::open(path).unwrap());
+        let client = BaseClient::new_with_state_store(Some(session.clone()), store).unwrap();
+        let mut response = sync_response("../test_data/leave_sync.json");
+        client.receive_sync_response(&mut response).await.unwrap();
+
+        let store = Box::new(JsonStore::open(path).unwrap());
+        let client = BaseClient::new_with_state_store(Some(session), store).unwrap();
+        assert!(client.sync_with_state_store().await.unwrap());
+
+        // left rooms are only loaded once they are needed
+        assert!(client.left_rooms().read().await.is_empty());
+        assert!(client.get_left_room(&room_id).await.is_some());
+        assert!(client.left_rooms().read().await.contains_key(&room_id));
+    }
+
+    #[tokio::test]
+    async fn test_lazy_room_loading() {
+        let dir = tempdir().unwrap();
+        let path: &Path = dir.path();
+
+        let session = Session {
+            access_token: "1234".to_owned(),
+            user_id: UserId::try_from("@example:localhost").unwrap(),
+            device_id: "DEVICEID".to_owned(),
+        };
+        let room_id = RoomId::try_from("!SVkFJHzfwvuaIEawgC:localhost").unwrap();
+
+        let store = Box::new(JsonStore::open(path).unwrap());
+        let client = BaseClient::new_with_state_store(Some(session.clone()), store).unwrap();
+        let mut response = sync_response("../test_data/sync.json");
+        client.receive_sync_response(&mut response).await.unwrap();
+        let members = client
+            .get_joined_room(&room_id)
+            .await
+            .unwrap()
+            .read()
+            .await
+            .members
+            .len();
+
+        let config = || {
+            BaseClientConfig::new()
+                .state_store(Box::new(JsonStore::open(path).unwrap()))
+                .lazy_room_loading()
+        };
+
+        // rooms are loaded in the background
+        let client = BaseClient::new_with_config(Some(session.clone()), config()).unwrap();
+        assert!(client.sync_with_state_store().await.unwrap());
+        assert!(client.joined_rooms().read().await.is_empty());
+        client.load_stored_rooms().await.unwrap();
+        assert!(client.joined_rooms().read().await.contains_key(&room_id));
+
+        // rooms are loaded before a sync response is applied to them
+        let client = BaseClient::new_with_config(Some(session), config()).unwrap();
+        assert!(client.sync_with_state_store().await.unwrap());
+        let mut response = sync_response("../test_data/sync.json");
+        client.receive_sync_response(&mut response).await.unwrap();
+
+        let room = client.get_joined_room(&room_id).await.unwrap();
+        assert_eq!(room.read().await.members.len(), members);
+    }
 }
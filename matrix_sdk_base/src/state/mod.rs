@@ -13,19 +13,20 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
 
 #[cfg(not(target_arch = "wasm32"))]
 mod json_store;
 #[cfg(not(target_arch = "wasm32"))]
 pub use json_store::JsonStore;
 
-use crate::client::{BaseClient, Token};
+use crate::client::{BaseClient, RoomStateType, Token};
 use crate::events::push_rules::Ruleset;
-use crate::identifiers::{RoomId, UserId};
-use crate::{Result, Room, RoomState, Session};
+use crate::identifiers::{RoomAliasId, RoomId, UserId};
+use crate::{QueuedMessage, Result, Room, RoomState, Session};
 
 /// `ClientState` holds all the information to restore a `BaseClient`
 /// except the `access_token` as the default store is not secure.
@@ -41,11 +42,22 @@ pub struct ClientState {
     pub ignored_users: Vec<UserId>,
     /// The push ruleset for the logged in user.
     pub push_ruleset: Option<Ruleset>,
+    /// The direct message rooms of the logged in user, keyed by the user the
+    /// messages are exchanged with.
+    #[serde(default)]
+    pub direct_rooms: BTreeMap<UserId, Vec<RoomId>>,
+    /// The content of the global account data events of the logged in user,
+    /// keyed by the event type.
+    #[serde(default)]
+    pub account_data: BTreeMap<String, JsonValue>,
 }
 
 impl PartialEq for ClientState {
     fn eq(&self, other: &Self) -> bool {
-        self.sync_token == other.sync_token && self.ignored_users == other.ignored_users
+        self.sync_token == other.sync_token
+            && self.ignored_users == other.ignored_users
+            && self.direct_rooms == other.direct_rooms
+            && self.account_data == other.account_data
     }
 }
 
@@ -55,12 +67,16 @@ impl ClientState {
             sync_token,
             ignored_users,
             push_ruleset,
+            direct_rooms,
+            account_data,
             ..
         } = client;
         Self {
             sync_token: sync_token.read().await.clone(),
             ignored_users: ignored_users.read().await.clone(),
             push_ruleset: push_ruleset.read().await.clone(),
+            direct_rooms: direct_rooms.read().await.clone(),
+            account_data: account_data.read().await.clone(),
         }
     }
 }
@@ -86,14 +102,245 @@ pub trait StateStore: Send + Sync {
     /// An `Option::None` should be returned only if the `StateStore` tries to
     /// load but no state has been stored.
     async fn load_client_state(&self, _: &Session) -> Result<Option<ClientState>>;
+
     /// Load the state of all `Room`s.
     ///
     /// This will be mapped over in the client in order to store `Room`s in an async safe way.
     async fn load_all_rooms(&self) -> Result<AllRooms>;
+
+    /// Load the ids of all the stored rooms with the given state.
+    ///
+    /// The default implementation loads all the rooms, stores should override
+    /// this if they can list the rooms without loading them.
+    async fn load_room_ids(&self, room_state: RoomStateType) -> Result<Vec<RoomId>> {
+        let mut rooms = self.load_all_rooms().await?;
+
+        let rooms = match room_state {
+            RoomStateType::Joined => &mut rooms.joined,
+            RoomStateType::Invited => &mut rooms.invited,
+            RoomStateType::Left => &mut rooms.left,
+        };
+
+        Ok(rooms.drain().map(|(room_id, _)| room_id).collect())
+    }
+
+    /// Can the store load single rooms without loading all of them.
+    ///
+    /// The client only loads the stored rooms in batches with `load_rooms()`
+    /// if this returns true, otherwise it loads all the rooms of a state at
+    /// once with `load_rooms_with_state()`. Stores that override
+    /// `load_rooms()` should return true.
+    fn loads_rooms_partially(&self) -> bool {
+        false
+    }
+
+    /// Load a batch of rooms with the given state.
+    ///
+    /// Rooms that aren't stored are skipped. The default implementation loads
+    /// all the rooms, stores should override this if they can load single
+    /// rooms.
+    async fn load_rooms(
+        &self,
+        room_state: RoomStateType,
+        room_ids: &[RoomId],
+    ) -> Result<Vec<Room>> {
+        let mut rooms = self.load_all_rooms().await?;
+
+        let rooms = match room_state {
+            RoomStateType::Joined => &mut rooms.joined,
+            RoomStateType::Invited => &mut rooms.invited,
+            RoomStateType::Left => &mut rooms.left,
+        };
+
+        Ok(room_ids.iter().filter_map(|id| rooms.remove(id)).collect())
+    }
+
+    /// Load all the rooms with the given state.
+    ///
+    /// The default implementation loads all the rooms once and keeps the ones
+    /// with the given state, stores that override `load_rooms()` should
+    /// override this as well.
+    async fn load_rooms_with_state(&self, room_state: RoomStateType) -> Result<Vec<Room>> {
+        let rooms = self.load_all_rooms().await?;
+
+        let rooms = match room_state {
+            RoomStateType::Joined => rooms.joined,
+            RoomStateType::Invited => rooms.invited,
+            RoomStateType::Left => rooms.left,
+        };
+
+        Ok(rooms.into_iter().map(|(_, room)| room).collect())
+    }
+
     /// Save the current state of the `BaseClient` using the `StateStore::Store` type.
     async fn store_client_state(&self, _: ClientState) -> Result<()>;
+
     /// Save the state a single `Room`.
     async fn store_room_state(&self, _: RoomState<&Room>) -> Result<()>;
+
+    /// Remove a room from the store, whatever state it was stored with.
+    ///
+    /// The default implementation does nothing.
+    async fn remove_room(&self, _room_id: &RoomId) -> Result<()> {
+        Ok(())
+    }
+
+    /// Load a cached media file.
+    ///
+    /// The default implementation doesn't persist media.
+    async fn load_media(&self, _key: &str) -> Result<Option<Vec<u8>>> {
+        Ok(None)
+    }
+
+    /// Persist a media file of the `MediaCache`.
+    async fn store_media(&self, _key: &str, _data: &[u8]) -> Result<()> {
+        Ok(())
+    }
+
+    /// Remove a media file that was evicted from the `MediaCache`.
+    async fn remove_media(&self, _key: &str) -> Result<()> {
+        Ok(())
+    }
+
+    /// Load the keys and sizes of all the persisted media files, the least
+    /// recently stored one first.
+    async fn load_media_keys(&self) -> Result<Vec<(String, usize)>> {
+        Ok(Vec::new())
+    }
+
+    /// Load the messages of the send queue that weren't sent yet.
+    ///
+    /// The default implementation doesn't persist the send queue.
+    async fn load_queued_messages(&self) -> Result<Vec<QueuedMessage>> {
+        Ok(Vec::new())
+    }
+
+    /// Replace the persisted send queue with the given messages.
+    async fn store_queued_messages(&self, _messages: &[QueuedMessage]) -> Result<()> {
+        Ok(())
+    }
+
+    /// Load the room ids that room aliases were resolved to.
+    ///
+    /// The default implementation doesn't persist the room aliases.
+    async fn load_room_aliases(&self) -> Result<HashMap<RoomAliasId, RoomId>> {
+        Ok(HashMap::new())
+    }
+
+    /// Replace the persisted room aliases with the given ones.
+    async fn store_room_aliases(&self, _aliases: &HashMap<RoomAliasId, RoomId>) -> Result<()> {
+        Ok(())
+    }
+
+    /// Load the id of a filter that was uploaded to the homeserver under the
+    /// given name.
+    ///
+    /// The default implementation doesn't persist filter ids.
+    async fn load_filter(&self, _filter_name: &str) -> Result<Option<String>> {
+        Ok(None)
+    }
+
+    /// Persist the id the homeserver assigned to an uploaded filter.
+    async fn store_filter(&self, _filter_name: &str, _filter_id: &str) -> Result<()> {
+        Ok(())
+    }
+
+    /// Load the names of all the persisted filters together with their ids.
+    async fn load_filters(&self) -> Result<Vec<(String, String)>> {
+        Ok(Vec::new())
+    }
+
+    /// Load a value an application stored with `set_custom_value()`.
+    ///
+    /// The default implementation doesn't persist custom values.
+    async fn get_custom_value(&self, _key: &str) -> Result<Option<Vec<u8>>> {
+        Ok(None)
+    }
+
+    /// Persist a small application defined value, e.g. the last command a bot
+    /// processed, next to the sync state.
+    async fn set_custom_value(&self, _key: &str, _value: &[u8]) -> Result<()> {
+        Ok(())
+    }
+
+    /// Remove a value that was stored with `set_custom_value()`.
+    async fn remove_custom_value(&self, _key: &str) -> Result<()> {
+        Ok(())
+    }
+
+    /// Load the keys of all the values that were stored with
+    /// `set_custom_value()`.
+    async fn load_custom_value_keys(&self) -> Result<Vec<String>> {
+        Ok(Vec::new())
+    }
+
+    /// Remove everything that was stored, the client and room state as well
+    /// as the media and the send queue.
+    ///
+    /// The default implementation does nothing.
+    async fn clear(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Copy everything that was stored to another store, e.g. to switch to a
+    /// different store backend without having to do a fresh initial sync.
+    ///
+    /// The client state including the sync token, the rooms, the media, the
+    /// filter ids, the custom values and the send queue are copied, data that
+    /// is already part of the other store is overwritten.
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - The store the data should be copied to.
+    ///
+    /// * `session` - The session of the user the data belongs to.
+    async fn export_to(&self, other: &dyn StateStore, session: &Session) -> Result<()> {
+        // Loading the client state first lets both stores set themselves up
+        // for the user of the session.
+        let client_state = self.load_client_state(session).await?;
+        other.load_client_state(session).await?;
+
+        if let Some(client_state) = client_state {
+            other.store_client_state(client_state).await?;
+        }
+
+        for room_state in &[
+            RoomStateType::Joined,
+            RoomStateType::Invited,
+            RoomStateType::Left,
+        ] {
+            for room in self.load_rooms_with_state(*room_state).await? {
+                let room = match room_state {
+                    RoomStateType::Joined => RoomState::Joined(&room),
+                    RoomStateType::Invited => RoomState::Invited(&room),
+                    RoomStateType::Left => RoomState::Left(&room),
+                };
+                other.store_room_state(room).await?;
+            }
+        }
+
+        for (key, _) in self.load_media_keys().await? {
+            if let Some(data) = self.load_media(&key).await? {
+                other.store_media(&key, &data).await?;
+            }
+        }
+
+        for (filter_name, filter_id) in self.load_filters().await? {
+            other.store_filter(&filter_name, &filter_id).await?;
+        }
+
+        for key in self.load_custom_value_keys().await? {
+            if let Some(value) = self.get_custom_value(&key).await? {
+                other.set_custom_value(&key, &value).await?;
+            }
+        }
+
+        let aliases = self.load_room_aliases().await?;
+        other.store_room_aliases(&aliases).await?;
+
+        let messages = self.load_queued_messages().await?;
+        other.store_queued_messages(&messages).await
+    }
 }
 
 #[cfg(test)]
@@ -116,9 +363,11 @@ mod test {
             sync_token: Some("hello".into()),
             ignored_users: vec![user],
             push_ruleset: None,
+            direct_rooms: BTreeMap::new(),
+            account_data: BTreeMap::new(),
         };
         assert_eq!(
-            r#"{"sync_token":"hello","ignored_users":["@example:example.com"],"push_ruleset":null}"#,
+            r#"{"sync_token":"hello","ignored_users":["@example:example.com"],"push_ruleset":null,"direct_rooms":{},"account_data":{}}"#,
             serde_json::to_string(&state).unwrap()
         );
 
@@ -146,7 +395,32 @@ mod test {
     "encrypted": false,
     "unread_highlight": null,
     "unread_notifications": null,
-    "tombstone": null
+    "tombstone": null,
+    "retention": null,
+    "space": {
+      "children": {},
+      "parents": {}
+    },
+    "custom_state": {},
+    "app_data": {},
+    "account_data": {},
+    "join_rule": null,
+    "avatar_url": null,
+    "topic": null,
+    "guest_access": null,
+    "history_visibility": null,
+    "server_acl": null,
+    "inviter": null,
+    "invite_timestamp": null,
+    "stripped_members": {},
+    "direct_target": null,
+    "state_event_ids": {},
+    "read_receipt": null,
+    "fully_read": null,
+    "prev_batch": null,
+    "tags": {},
+    "server_notice": null,
+    "members_synced": false
   }
 }"#,
             serde_json::to_string_pretty(&joined_rooms).unwrap()
@@ -174,7 +448,32 @@ mod test {
     "encrypted": false,
     "unread_highlight": null,
     "unread_notifications": null,
-    "tombstone": null
+    "tombstone": null,
+    "retention": null,
+    "space": {
+      "children": {},
+      "parents": {}
+    },
+    "custom_state": {},
+    "app_data": {},
+    "account_data": {},
+    "join_rule": null,
+    "avatar_url": null,
+    "topic": null,
+    "guest_access": null,
+    "history_visibility": null,
+    "server_acl": null,
+    "inviter": null,
+    "invite_timestamp": null,
+    "stripped_members": {},
+    "direct_target": null,
+    "state_event_ids": {},
+    "read_receipt": null,
+    "fully_read": null,
+    "prev_batch": null,
+    "tags": {},
+    "server_notice": null,
+    "members_synced": false
   }
 }"#,
             serde_json::to_string_pretty(&joined_rooms).unwrap()
@@ -192,6 +491,8 @@ mod test {
             sync_token: Some("hello".into()),
             ignored_users: vec![user],
             push_ruleset: None,
+            direct_rooms: BTreeMap::new(),
+            account_data: BTreeMap::new(),
         };
         let json = serde_json::to_string(&state).unwrap();
 
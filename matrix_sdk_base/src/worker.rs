@@ -0,0 +1,186 @@
+// Copyright 2020 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::future::Future;
+use std::sync::Arc;
+
+use tokio::sync::Semaphore;
+use tokio::task::JoinHandle;
+
+/// A pool that takes CPU-heavy sync processing off the threads that drive
+/// the async tasks of the runtime.
+///
+/// At most `max_jobs` jobs run at once, further jobs wait until a running job
+/// finished. This bounds the number of threads a large sync response can
+/// occupy, so other tasks of the application don't starve. Jobs that wait for
+/// a free slot aren't queued up in the pool, the caller that submits them
+/// waits instead.
+#[derive(Clone, Debug)]
+pub struct WorkerPool {
+    jobs: Arc<Semaphore>,
+}
+
+impl WorkerPool {
+    /// Create a new pool.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_jobs` - The number of jobs that may run at the same time, at
+    /// least one job is always allowed.
+    pub fn new(max_jobs: usize) -> Self {
+        Self {
+            jobs: Arc::new(Semaphore::new(max_jobs.max(1))),
+        }
+    }
+
+    /// Run blocking, CPU-heavy work, e.g. deserializing a sync response, on
+    /// the blocking thread pool of the runtime.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the job panicked.
+    pub async fn run_blocking<F, T>(&self, job: F) -> T
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let _permit = self.jobs.acquire().await;
+
+        tokio::task::spawn_blocking(job)
+            .await
+            .expect("A sync worker job panicked")
+    }
+
+    /// Run a future as a separate task, so it can make progress on another
+    /// thread of the runtime in parallel to the calling task.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the future panicked.
+    pub async fn spawn<F>(&self, job: F) -> F::Output
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        self.start(job).await.join().await
+    }
+
+    /// Start running a future as a separate task without waiting for it to
+    /// finish.
+    ///
+    /// Waits for a free job slot before the task is spawned, so at most
+    /// `max_jobs` tasks of the pool exist at once. Many jobs can be started
+    /// before the first one is awaited, they run in parallel as long as there
+    /// are free slots.
+    pub(crate) async fn start<F>(&self, job: F) -> WorkerJob<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        self.jobs.acquire().await.forget();
+        let slot = JobSlot(self.jobs.clone());
+
+        WorkerJob(tokio::spawn(async move {
+            let _slot = slot;
+            job.await
+        }))
+    }
+}
+
+/// A job slot of a `WorkerPool` that is taken by a spawned task, the slot is
+/// given back once the task finished or was dropped.
+#[derive(Debug)]
+struct JobSlot(Arc<Semaphore>);
+
+impl Drop for JobSlot {
+    fn drop(&mut self) {
+        self.0.add_permits(1);
+    }
+}
+
+/// A job that was started on a `WorkerPool`.
+#[derive(Debug)]
+pub(crate) struct WorkerJob<T>(JoinHandle<T>);
+
+impl<T: Send + 'static> WorkerJob<T> {
+    /// Start running a future as a separate task that isn't bounded by any
+    /// pool.
+    pub fn spawn<F>(job: F) -> Self
+    where
+        F: Future<Output = T> + Send + 'static,
+    {
+        WorkerJob(tokio::spawn(job))
+    }
+
+    /// Wait for the job to finish.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the job panicked.
+    pub async fn join(self) -> T {
+        self.0.await.expect("A sync worker job panicked")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+
+    #[tokio::test]
+    async fn bounded_jobs() {
+        let pool = WorkerPool::new(2);
+        let running = Arc::new(AtomicUsize::new(0));
+        let seen = Arc::new(Mutex::new(Vec::new()));
+
+        let jobs = (0..8).map(|i| {
+            let pool = pool.clone();
+            let running = running.clone();
+            let seen = seen.clone();
+
+            async move {
+                pool.run_blocking(move || {
+                    let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                    seen.lock().unwrap().push(now);
+                    std::thread::sleep(std::time::Duration::from_millis(10));
+                    running.fetch_sub(1, Ordering::SeqCst);
+                    i * 2
+                })
+                .await
+            }
+        });
+
+        let mut results = Vec::new();
+        for job in jobs.map(tokio::spawn).collect::<Vec<_>>() {
+            results.push(job.await.unwrap());
+        }
+
+        assert_eq!(results, (0..8).map(|i| i * 2).collect::<Vec<_>>());
+        assert!(seen.lock().unwrap().iter().all(|running| *running <= 2));
+        assert_eq!(pool.spawn(async { 42 }).await, 42);
+
+        let mut started = Vec::new();
+        for i in 0..4 {
+            started.push(pool.start(async move { i }).await);
+        }
+        let mut results = Vec::new();
+        for job in started {
+            results.push(job.join().await);
+        }
+        assert_eq!(results, vec![0, 1, 2, 3]);
+        assert_eq!(WorkerJob::spawn(async { 7 }).join().await, 7);
+    }
+}
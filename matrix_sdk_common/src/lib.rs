@@ -1,7 +1,7 @@
 pub use instant;
 pub use js_int;
 pub use ruma_api::{
-    error::{FromHttpResponseError, IntoHttpError, ServerError},
+    error::{EndpointError, FromHttpResponseError, IntoHttpError, ServerError},
     Endpoint,
 };
 pub use ruma_client_api as api;
@@ -0,0 +1,76 @@
+// Copyright 2020 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Benchmarks of the decryption of room messages.
+//!
+//! Run them with `cargo bench -p matrix-sdk-crypto`.
+
+use std::convert::TryFrom;
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use tokio::runtime::Runtime;
+
+use matrix_sdk_common::identifiers::RoomId;
+use matrix_sdk_crypto::{InboundGroupSession, OutboundGroupSession};
+
+/// The number of messages of a room timeline in a large sync response.
+const MESSAGES: usize = 50;
+
+fn megolm_decryption(c: &mut Criterion) {
+    let mut runtime = Runtime::new().unwrap();
+    let room_id = RoomId::try_from("!test:localhost").unwrap();
+
+    let outbound = OutboundGroupSession::new(&room_id);
+    let inbound = runtime
+        .block_on(async {
+            InboundGroupSession::new(
+                "sender_key",
+                "signing_key",
+                &room_id,
+                outbound.session_key().await,
+            )
+        })
+        .unwrap();
+
+    let messages: Vec<String> = runtime.block_on(async {
+        let mut messages = Vec::with_capacity(MESSAGES);
+
+        for i in 0..MESSAGES {
+            let plaintext = serde_json::json!({
+                "content": { "body": format!("Message number {}", i), "msgtype": "m.text" },
+                "room_id": "!test:localhost",
+                "type": "m.room.message"
+            });
+            messages.push(outbound.encrypt(plaintext.to_string()).await);
+        }
+
+        messages
+    });
+
+    let mut group = c.benchmark_group("megolm_decryption");
+    group.throughput(Throughput::Elements(MESSAGES as u64));
+    group.bench_function("timeline", |b| {
+        b.iter(|| {
+            runtime.block_on(async {
+                for message in &messages {
+                    inbound.decrypt(message.clone()).await.unwrap();
+                }
+            })
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, megolm_decryption);
+criterion_main!(benches);
@@ -0,0 +1,343 @@
+// Copyright 2020 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::BTreeMap;
+use std::io::{Error as IoError, ErrorKind, Read, Result as IoResult};
+
+use aes_ctr::stream_cipher::generic_array::GenericArray;
+use aes_ctr::stream_cipher::{NewStreamCipher, SyncStreamCipher};
+use aes_ctr::Aes256Ctr;
+use base64::{decode_config, encode_config, STANDARD_NO_PAD, URL_SAFE_NO_PAD};
+use getrandom::getrandom;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use zeroize::Zeroizing;
+
+use crate::error::DecryptorError;
+
+const VERSION: &str = "v2";
+const KEY_SIZE: usize = 32;
+const IV_SIZE: usize = 16;
+
+/// The key of an encrypted attachment in the JSON Web Key format.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct JsonWebKey {
+    /// The key type, always `oct`.
+    pub kty: String,
+    /// The operations the key can be used for.
+    pub key_ops: Vec<String>,
+    /// The algorithm the key is used with, always `A256CTR`.
+    pub alg: String,
+    /// The key itself, encoded as URL safe unpadded base64.
+    pub k: String,
+    /// Is the key extractable, always true.
+    pub ext: bool,
+}
+
+/// The information that is needed to decrypt an encrypted attachment.
+///
+/// Together with the `mxc://` URI of the uploaded ciphertext this forms the
+/// `EncryptedFile` JSON object that is attached to a message.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct EncryptionInfo {
+    /// The version of the encryption scheme.
+    #[serde(rename = "v")]
+    pub version: String,
+    /// The key that was used to encrypt the attachment.
+    #[serde(rename = "key")]
+    pub web_key: JsonWebKey,
+    /// The initialization vector, encoded as unpadded base64.
+    pub iv: String,
+    /// A map from the hash algorithm to the hash of the ciphertext, encoded
+    /// as unpadded base64.
+    pub hashes: BTreeMap<String, String>,
+}
+
+/// A wrapper around a `Read` that encrypts the data of an attachment while it
+/// is being read.
+///
+/// The data is encrypted using AES-CTR with a freshly generated key, once all
+/// the data has been read `finish()` returns the `EncryptionInfo` that is
+/// needed to decrypt it again.
+///
+/// # Examples
+/// ```
+/// # use std::io::Read;
+/// # use matrix_sdk_crypto::AttachmentEncryptor;
+/// let data = "Hello world".to_owned();
+/// let mut cursor = data.as_bytes();
+///
+/// let mut encryptor = AttachmentEncryptor::new(&mut cursor);
+///
+/// let mut encrypted = Vec::new();
+/// encryptor.read_to_end(&mut encrypted).unwrap();
+///
+/// let info = encryptor.finish();
+/// ```
+pub struct AttachmentEncryptor<'a, R: Read> {
+    inner: &'a mut R,
+    web_key: JsonWebKey,
+    iv: String,
+    hasher: Sha256,
+    aes: Aes256Ctr,
+}
+
+impl<'a, R: Read> std::fmt::Debug for AttachmentEncryptor<'a, R> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AttachmentEncryptor")
+            .field("iv", &self.iv)
+            .finish()
+    }
+}
+
+impl<'a, R: Read> Read for AttachmentEncryptor<'a, R> {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        let read = self.inner.read(buf)?;
+
+        self.aes.apply_keystream(&mut buf[..read]);
+        self.hasher.input(&buf[..read]);
+
+        Ok(read)
+    }
+}
+
+impl<'a, R: Read> AttachmentEncryptor<'a, R> {
+    /// Wrap the given reader, encrypting all the data that is read from it.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - The reader that holds the plaintext of the attachment.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no randomness for the key can be gathered from the operating
+    /// system.
+    pub fn new(reader: &'a mut R) -> Self {
+        let mut key = Zeroizing::new([0u8; KEY_SIZE]);
+        let mut iv = Zeroizing::new([0u8; IV_SIZE]);
+
+        getrandom(&mut *key).expect("Can't generate randomness for the attachment key");
+        // Only the first half of the IV is random, the second half is the
+        // block counter which starts at zero.
+        getrandom(&mut iv[0..8]).expect("Can't generate randomness for the attachment IV");
+
+        let web_key = JsonWebKey {
+            kty: "oct".to_owned(),
+            key_ops: vec!["encrypt".to_owned(), "decrypt".to_owned()],
+            alg: "A256CTR".to_owned(),
+            k: encode_config(&*key, URL_SAFE_NO_PAD),
+            ext: true,
+        };
+
+        let aes = Aes256Ctr::new(
+            GenericArray::from_slice(&*key),
+            GenericArray::from_slice(&*iv),
+        );
+
+        AttachmentEncryptor {
+            inner: reader,
+            iv: encode_config(&*iv, STANDARD_NO_PAD),
+            web_key,
+            hasher: Sha256::default(),
+            aes,
+        }
+    }
+
+    /// Consume the encryptor and get the info that is needed to decrypt the
+    /// attachment.
+    ///
+    /// This should only be called once all the data has been read, otherwise
+    /// the hash won't match the uploaded ciphertext.
+    pub fn finish(self) -> EncryptionInfo {
+        let hash = self.hasher.result();
+
+        let mut hashes = BTreeMap::new();
+        hashes.insert("sha256".to_owned(), encode_config(&hash, STANDARD_NO_PAD));
+
+        EncryptionInfo {
+            version: VERSION.to_owned(),
+            web_key: self.web_key,
+            iv: self.iv,
+            hashes,
+        }
+    }
+}
+
+/// A wrapper around a `Read` that decrypts the data of an encrypted
+/// attachment while it is being read.
+///
+/// The hash of the ciphertext is checked once all the data has been read, if
+/// it doesn't match the last read call returns an error.
+///
+/// # Examples
+/// ```
+/// # use std::io::Read;
+/// # use matrix_sdk_crypto::{AttachmentDecryptor, AttachmentEncryptor};
+/// # let data = "Hello world".to_owned();
+/// # let mut cursor = data.as_bytes();
+/// # let mut encryptor = AttachmentEncryptor::new(&mut cursor);
+/// # let mut encrypted = Vec::new();
+/// # encryptor.read_to_end(&mut encrypted).unwrap();
+/// # let info = encryptor.finish();
+/// let mut cursor = encrypted.as_slice();
+/// let mut decryptor = AttachmentDecryptor::new(&mut cursor, info).unwrap();
+///
+/// let mut decrypted = Vec::new();
+/// decryptor.read_to_end(&mut decrypted).unwrap();
+///
+/// assert_eq!(decrypted, data.as_bytes());
+/// ```
+pub struct AttachmentDecryptor<'a, R: Read> {
+    inner: &'a mut R,
+    expected_hash: Vec<u8>,
+    /// The hasher of the ciphertext, taken once the hash has been checked.
+    hasher: Option<Sha256>,
+    aes: Aes256Ctr,
+}
+
+impl<'a, R: Read> std::fmt::Debug for AttachmentDecryptor<'a, R> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AttachmentDecryptor").finish()
+    }
+}
+
+impl<'a, R: Read> Read for AttachmentDecryptor<'a, R> {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        let read = self.inner.read(buf)?;
+
+        if read == 0 && !buf.is_empty() {
+            if let Some(hasher) = self.hasher.take() {
+                if hasher.result().as_slice() != self.expected_hash.as_slice() {
+                    return Err(IoError::new(
+                        ErrorKind::InvalidData,
+                        "the hash of the attachment doesn't match",
+                    ));
+                }
+            }
+        } else {
+            if let Some(hasher) = self.hasher.as_mut() {
+                hasher.input(&buf[..read]);
+            }
+
+            self.aes.apply_keystream(&mut buf[..read]);
+        }
+
+        Ok(read)
+    }
+}
+
+impl<'a, R: Read> AttachmentDecryptor<'a, R> {
+    /// Wrap the given reader, decrypting all the data that is read from it.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - The reader that holds the ciphertext of the attachment.
+    ///
+    /// * `info` - The info that was created when the attachment was
+    /// encrypted.
+    pub fn new(reader: &'a mut R, info: EncryptionInfo) -> Result<Self, DecryptorError> {
+        if info.version != VERSION {
+            return Err(DecryptorError::UnknownVersion);
+        }
+
+        let hash = info
+            .hashes
+            .get("sha256")
+            .ok_or(DecryptorError::MissingHash)?;
+        let expected_hash = decode_config(hash, STANDARD_NO_PAD)?;
+
+        let key = Zeroizing::new(decode_config(&info.web_key.k, URL_SAFE_NO_PAD)?);
+        let iv = decode_config(&info.iv, STANDARD_NO_PAD)?;
+
+        if key.len() != KEY_SIZE {
+            return Err(DecryptorError::KeyNonceLength);
+        }
+
+        if iv.len() != IV_SIZE {
+            return Err(DecryptorError::KeyNonceLength);
+        }
+
+        let aes = Aes256Ctr::new(
+            GenericArray::from_slice(&*key),
+            GenericArray::from_slice(&iv),
+        );
+
+        Ok(AttachmentDecryptor {
+            inner: reader,
+            expected_hash,
+            hasher: Some(Sha256::default()),
+            aes,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use base64::decode_config;
+
+    #[test]
+    fn encrypt() {
+        let data = "It is a secret to everybody";
+        let mut cursor = data.as_bytes();
+
+        let mut encryptor = AttachmentEncryptor::new(&mut cursor);
+        let mut encrypted = Vec::new();
+        encryptor.read_to_end(&mut encrypted).unwrap();
+        let info = encryptor.finish();
+
+        assert_ne!(encrypted.as_slice(), data.as_bytes());
+        assert_eq!(info.version, "v2");
+        assert_eq!(info.web_key.alg, "A256CTR");
+
+        let hash = Sha256::digest(&encrypted);
+        assert_eq!(info.hashes["sha256"], encode_config(&hash, STANDARD_NO_PAD));
+
+        let key = decode_config(&info.web_key.k, URL_SAFE_NO_PAD).unwrap();
+        let iv = decode_config(&info.iv, STANDARD_NO_PAD).unwrap();
+        assert_eq!(&iv[8..], &[0u8; 8]);
+
+        let mut aes = Aes256Ctr::new(
+            GenericArray::from_slice(&key),
+            GenericArray::from_slice(&iv),
+        );
+        aes.apply_keystream(&mut encrypted);
+
+        assert_eq!(encrypted.as_slice(), data.as_bytes());
+    }
+
+    #[test]
+    fn decrypt() {
+        let data = "It is a secret to everybody";
+        let mut cursor = data.as_bytes();
+
+        let mut encryptor = AttachmentEncryptor::new(&mut cursor);
+        let mut encrypted = Vec::new();
+        encryptor.read_to_end(&mut encrypted).unwrap();
+        let info = encryptor.finish();
+
+        let mut cursor = encrypted.as_slice();
+        let mut decryptor = AttachmentDecryptor::new(&mut cursor, info.clone()).unwrap();
+        let mut decrypted = Vec::new();
+        decryptor.read_to_end(&mut decrypted).unwrap();
+
+        assert_eq!(decrypted.as_slice(), data.as_bytes());
+
+        encrypted[0] ^= 1;
+        let mut cursor = encrypted.as_slice();
+        let mut decryptor = AttachmentDecryptor::new(&mut cursor, info).unwrap();
+
+        assert!(decryptor.read_to_end(&mut Vec::new()).is_err());
+    }
+}
@@ -115,6 +115,11 @@ impl Device {
         self.trust_state.load(Ordering::Relaxed)
     }
 
+    /// Set the trust state of the device.
+    pub(crate) fn set_trust_state(&self, state: TrustState) {
+        self.trust_state.store(state, Ordering::Relaxed)
+    }
+
     /// Get the list of algorithms this device supports.
     pub fn algorithms(&self) -> &[Algorithm] {
         &self.algorithms
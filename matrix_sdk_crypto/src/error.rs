@@ -12,6 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use base64::DecodeError;
 use cjson::Error as CjsonError;
 use olm_rs::errors::{OlmGroupSessionError, OlmSessionError};
 use serde_json::Error as SerdeError;
@@ -103,6 +104,58 @@ pub enum EventError {
     MissmatchedKeys,
 }
 
+/// Error representing a failure while decrypting a key export.
+#[derive(Error, Debug)]
+pub enum KeyExportError {
+    /// The key export isn't valid base64.
+    #[error(transparent)]
+    Decode(#[from] DecodeError),
+
+    /// The decrypted key export couldn't be deserialized.
+    #[error(transparent)]
+    Json(#[from] SerdeError),
+
+    /// The key export is missing its header or footer line.
+    #[error("the key export is missing its header or footer")]
+    InvalidHeader,
+
+    /// The key export is too short to be valid.
+    #[error("the key export is truncated")]
+    Truncated,
+
+    /// The key export was created with a version of the format we don't
+    /// support.
+    #[error("unsupported key export version {0}")]
+    UnsupportedVersion(u8),
+
+    /// The MAC of the key export doesn't match, either the passphrase is
+    /// wrong or the export was tampered with.
+    #[error("the key export MAC doesn't match, the passphrase is likely wrong")]
+    InvalidMac,
+}
+
+/// Error representing a failure while setting up the decryption of an
+/// attachment.
+#[derive(Error, Debug)]
+pub enum DecryptorError {
+    /// A field of the encryption info isn't valid base64.
+    #[error(transparent)]
+    Decode(#[from] DecodeError),
+
+    /// The key or the IV of the encryption info has an invalid length.
+    #[error("the attachment key or IV has an invalid length")]
+    KeyNonceLength,
+
+    /// The encryption info doesn't contain a SHA-256 hash of the ciphertext.
+    #[error("the attachment is missing its SHA-256 hash")]
+    MissingHash,
+
+    /// The attachment was encrypted using an unknown version of the
+    /// encryption scheme.
+    #[error("the attachment was encrypted with an unknown version")]
+    UnknownVersion,
+}
+
 #[derive(Error, Debug)]
 pub(crate) enum SignatureError {
     #[error("the provided JSON value isn't an object")]
@@ -0,0 +1,240 @@
+// Copyright 2020 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::BTreeMap;
+use std::convert::TryInto;
+
+use aes_ctr::stream_cipher::generic_array::GenericArray;
+use aes_ctr::stream_cipher::{NewStreamCipher, SyncStreamCipher};
+use aes_ctr::Aes256Ctr;
+use base64::{decode, encode};
+use getrandom::getrandom;
+use hmac::{Hmac, Mac};
+use pbkdf2::pbkdf2;
+use serde::{Deserialize, Serialize};
+use serde_json::Error as SerdeError;
+use sha2::{Sha256, Sha512};
+use zeroize::Zeroizing;
+
+use matrix_sdk_common::events::Algorithm;
+use matrix_sdk_common::identifiers::RoomId;
+
+use crate::error::KeyExportError;
+
+const HEADER: &str = "-----BEGIN MEGOLM SESSION DATA-----";
+const FOOTER: &str = "-----END MEGOLM SESSION DATA-----";
+const VERSION: u8 = 1;
+const SALT_SIZE: usize = 16;
+const IV_SIZE: usize = 16;
+const KEY_SIZE: usize = 32;
+const MAC_SIZE: usize = 32;
+const LINE_LENGTH: usize = 96;
+
+/// The number of PBKDF2 rounds other Matrix clients use for their key
+/// exports.
+pub const DEFAULT_EXPORT_ROUNDS: u32 = 500_000;
+
+/// A room key in the format of a Megolm key export.
+///
+/// Key exports can be imported by other Matrix clients, e.g. to decrypt
+/// the history of a room on a new device.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ExportedRoomKey {
+    /// The encryption algorithm the session uses.
+    pub algorithm: Algorithm,
+    /// The room the session is used in.
+    pub room_id: RoomId,
+    /// The public curve25519 key of the device that created the session.
+    pub sender_key: String,
+    /// The unique id of the session.
+    pub session_id: String,
+    /// The session key, exported at the first message index we can decrypt.
+    pub session_key: String,
+    /// The keys the device that created the session claims to own.
+    pub sender_claimed_keys: BTreeMap<String, String>,
+    /// The curve25519 keys of the devices the session was forwarded through.
+    pub forwarding_curve25519_key_chain: Vec<String>,
+}
+
+/// Encrypt room keys with a passphrase into the standard key export format.
+///
+/// # Arguments
+///
+/// * `keys` - The room keys that should be exported.
+///
+/// * `passphrase` - The passphrase the export is encrypted with.
+///
+/// * `rounds` - The number of PBKDF2 rounds used to derive the encryption key
+/// from the passphrase, see `DEFAULT_EXPORT_ROUNDS`.
+pub fn encrypt_key_export(
+    keys: &[ExportedRoomKey],
+    passphrase: &str,
+    rounds: u32,
+) -> Result<String, SerdeError> {
+    let mut ciphertext = Zeroizing::new(serde_json::to_vec(keys)?);
+
+    let mut salt = [0u8; SALT_SIZE];
+    let mut iv = [0u8; IV_SIZE];
+
+    getrandom(&mut salt).expect("Can't generate randomness for the key export salt");
+    getrandom(&mut iv).expect("Can't generate randomness for the key export IV");
+    // Clear bit 63 of the IV so the block counter can't overflow into the
+    // nonce, other clients do the same.
+    iv[8] &= 0x7f;
+
+    let (aes_key, mac_key) = derive_keys(passphrase, &salt, rounds);
+
+    Aes256Ctr::new(
+        GenericArray::from_slice(&aes_key),
+        GenericArray::from_slice(&iv),
+    )
+    .apply_keystream(&mut ciphertext);
+
+    let mut payload = Vec::with_capacity(1 + SALT_SIZE + IV_SIZE + 4 + ciphertext.len() + MAC_SIZE);
+    payload.push(VERSION);
+    payload.extend_from_slice(&salt);
+    payload.extend_from_slice(&iv);
+    payload.extend_from_slice(&rounds.to_be_bytes());
+    payload.extend_from_slice(&ciphertext);
+
+    let mut mac = mac(&mac_key);
+    mac.input(&payload);
+    payload.extend_from_slice(&mac.result().code());
+
+    let encoded = encode(&payload);
+    let mut export = format!("{}\n", HEADER);
+
+    for line in encoded.as_bytes().chunks(LINE_LENGTH) {
+        export.push_str(std::str::from_utf8(line).expect("Base64 is always ASCII"));
+        export.push('\n');
+    }
+
+    export.push_str(FOOTER);
+    export.push('\n');
+
+    Ok(export)
+}
+
+/// Decrypt a key export that was encrypted with a passphrase.
+///
+/// # Arguments
+///
+/// * `export` - The key export, including its header and footer lines.
+///
+/// * `passphrase` - The passphrase the export was encrypted with.
+pub fn decrypt_key_export(
+    export: &str,
+    passphrase: &str,
+) -> Result<Vec<ExportedRoomKey>, KeyExportError> {
+    let export = export.trim();
+
+    if !export.starts_with(HEADER) || !export.ends_with(FOOTER) {
+        return Err(KeyExportError::InvalidHeader);
+    }
+
+    let encoded: String = export[HEADER.len()..export.len() - FOOTER.len()]
+        .split_whitespace()
+        .collect();
+    let payload = decode(&encoded)?;
+
+    if payload.len() < 1 + SALT_SIZE + IV_SIZE + 4 + MAC_SIZE {
+        return Err(KeyExportError::Truncated);
+    }
+
+    if payload[0] != VERSION {
+        return Err(KeyExportError::UnsupportedVersion(payload[0]));
+    }
+
+    let (data, expected_mac) = payload.split_at(payload.len() - MAC_SIZE);
+    let (salt, rest) = data[1..].split_at(SALT_SIZE);
+    let (iv, rest) = rest.split_at(IV_SIZE);
+    let (rounds, ciphertext) = rest.split_at(4);
+    let rounds = u32::from_be_bytes(rounds.try_into().expect("The rounds are 4 bytes long"));
+
+    let (aes_key, mac_key) = derive_keys(passphrase, salt, rounds);
+
+    let mut mac = mac(&mac_key);
+    mac.input(data);
+    mac.verify(expected_mac)
+        .map_err(|_| KeyExportError::InvalidMac)?;
+
+    let mut plaintext = Zeroizing::new(ciphertext.to_vec());
+
+    Aes256Ctr::new(
+        GenericArray::from_slice(&aes_key),
+        GenericArray::from_slice(iv),
+    )
+    .apply_keystream(&mut plaintext);
+
+    Ok(serde_json::from_slice(&plaintext)?)
+}
+
+/// Derive the AES and the HMAC key of a key export from the passphrase.
+fn derive_keys(
+    passphrase: &str,
+    salt: &[u8],
+    rounds: u32,
+) -> (Zeroizing<Vec<u8>>, Zeroizing<Vec<u8>>) {
+    let mut key = Zeroizing::new([0u8; KEY_SIZE * 2]);
+    pbkdf2::<Hmac<Sha512>>(passphrase.as_bytes(), salt, rounds as usize, &mut *key);
+
+    (
+        Zeroizing::new(key[..KEY_SIZE].to_vec()),
+        Zeroizing::new(key[KEY_SIZE..].to_vec()),
+    )
+}
+
+fn mac(key: &[u8]) -> Hmac<Sha256> {
+    Hmac::<Sha256>::new_varkey(key).expect("HMAC can take keys of any size")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::convert::TryFrom;
+
+    fn room_key() -> ExportedRoomKey {
+        let mut sender_claimed_keys = BTreeMap::new();
+        sender_claimed_keys.insert("ed25519".to_owned(), "signing_key".to_owned());
+
+        ExportedRoomKey {
+            algorithm: Algorithm::MegolmV1AesSha2,
+            room_id: RoomId::try_from("!test:localhost").unwrap(),
+            sender_key: "sender_key".to_owned(),
+            session_id: "session_id".to_owned(),
+            session_key: "session_key".to_owned(),
+            sender_claimed_keys,
+            forwarding_curve25519_key_chain: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn key_export_roundtrip() {
+        let keys = vec![room_key()];
+        let export = encrypt_key_export(&keys, "passphrase", 10).unwrap();
+
+        assert!(export.starts_with(HEADER));
+        assert!(export.lines().all(|l| l.len() <= LINE_LENGTH));
+        assert_eq!(decrypt_key_export(&export, "passphrase").unwrap(), keys);
+
+        assert!(matches!(
+            decrypt_key_export(&export, "wrong passphrase"),
+            Err(KeyExportError::InvalidMac)
+        ));
+        assert!(matches!(
+            decrypt_key_export("not an export", "passphrase"),
+            Err(KeyExportError::InvalidHeader)
+        ));
+    }
+}
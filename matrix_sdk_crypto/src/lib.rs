@@ -26,18 +26,26 @@
     unused_qualifications
 )]
 
+mod attachments;
 mod device;
 mod error;
+mod key_export;
 mod machine;
 mod memory_stores;
 mod olm;
 mod store;
+mod verification;
 
+pub use attachments::{AttachmentDecryptor, AttachmentEncryptor, EncryptionInfo, JsonWebKey};
 pub use device::{Device, TrustState};
-pub use error::{MegolmError, OlmError};
+pub use error::{DecryptorError, KeyExportError, MegolmError, OlmError};
+pub use key_export::{
+    decrypt_key_export, encrypt_key_export, ExportedRoomKey, DEFAULT_EXPORT_ROUNDS,
+};
 pub use machine::{OlmMachine, OneTimeKeys};
 pub use memory_stores::{DeviceStore, GroupSessionStore, SessionStore, UserDevices};
-pub use olm::{Account, InboundGroupSession, OutboundGroupSession, Session};
+pub use olm::{Account, GroupSessionInfo, InboundGroupSession, OutboundGroupSession, Session};
 #[cfg(feature = "sqlite-cryptostore")]
 pub use store::sqlite::SqliteStore;
-pub use store::{CryptoStore, CryptoStoreError};
+pub use store::{CryptoStore, CryptoStoreError, PendingToDeviceRequest};
+pub use verification::VerificationPolicy;
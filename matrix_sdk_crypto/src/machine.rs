@@ -18,17 +18,21 @@ use std::mem;
 #[cfg(feature = "sqlite-cryptostore")]
 use std::path::Path;
 use std::result::Result as StdResult;
-use std::sync::atomic::{AtomicU64, Ordering};
 
 use super::error::{EventError, MegolmError, MegolmResult, OlmError, OlmResult, SignatureError};
 use super::olm::{
-    Account, GroupSessionKey, IdentityKeys, InboundGroupSession, OlmMessage, OlmUtility,
-    OutboundGroupSession, Session,
+    Account, GroupSessionInfo, GroupSessionKey, IdentityKeys, InboundGroupSession, OlmMessage,
+    OlmUtility, OutboundGroupSession, Session,
 };
 use super::store::memorystore::MemoryStore;
 #[cfg(feature = "sqlite-cryptostore")]
 use super::store::sqlite::SqliteStore;
-use super::{device::Device, store::Result as StoreError, CryptoStore};
+use super::verification::{cancel_content, Sas, VerificationPolicy};
+use super::{
+    device::{Device, TrustState},
+    store::{PendingToDeviceRequest, Result as StoreError},
+    CryptoStore, ExportedRoomKey,
+};
 
 use matrix_sdk_common::api;
 use matrix_sdk_common::events::{
@@ -45,6 +49,7 @@ use matrix_sdk_common::events::{
     Algorithm, EventJson, EventType,
 };
 use matrix_sdk_common::identifiers::{DeviceId, RoomId, UserId};
+use matrix_sdk_common::locks::{Mutex, RwLock};
 use matrix_sdk_common::uuid::Uuid;
 
 use api::r0::keys;
@@ -64,6 +69,12 @@ pub type OneTimeKeys = BTreeMap<AlgorithmAndDeviceId, OneTimeKey>;
 
 /// State machine implementation of the Olm/Megolm encryption protocol used for
 /// Matrix end to end encryption.
+///
+/// Every part of the state that changes while the machine is in use is behind
+/// its own lock, so the machine can be shared and used through a `&self`
+/// reference. Decrypting room events only takes a read lock of the store,
+/// while e.g. sharing a group session only locks the store for the duration
+/// of the individual store calls.
 pub struct OlmMachine {
     /// The unique user id that owns this account.
     user_id: UserId,
@@ -75,16 +86,24 @@ pub struct OlmMachine {
     /// this is None, no action will be taken. After a sync request the client
     /// needs to set this for us, depending on the count we will suggest the
     /// client to upload new keys.
-    uploaded_signed_key_count: Option<AtomicU64>,
+    uploaded_signed_key_count: RwLock<Option<u64>>,
     /// Store for the encryption keys.
     /// Persists all the encryption keys so a client can resume the session
     /// without the need to create new keys.
-    store: Box<dyn CryptoStore>,
+    store: RwLock<Box<dyn CryptoStore>>,
     /// Set of users that we need to query keys for. This is a subset of
     /// the tracked users in the CryptoStore.
-    users_for_key_query: HashSet<UserId>,
+    users_for_key_query: Mutex<HashSet<UserId>>,
     /// The currently active outbound group sessions.
-    outbound_group_sessions: HashMap<RoomId, OutboundGroupSession>,
+    outbound_group_sessions: RwLock<HashMap<RoomId, OutboundGroupSession>>,
+    /// The policy deciding which key verifications get accepted
+    /// automatically.
+    verification_policy: VerificationPolicy,
+    /// The in-progress key verifications, keyed by their transaction id.
+    verifications: Mutex<HashMap<String, Sas>>,
+    /// To-device messages that need to be sent out, e.g. the messages of a
+    /// key verification.
+    outgoing_to_device_requests: Mutex<Vec<ToDeviceRequest>>,
 }
 
 #[cfg_attr(tarpaulin, skip)]
@@ -120,10 +139,13 @@ impl OlmMachine {
             user_id: user_id.clone(),
             device_id: device_id.to_owned(),
             account: Account::new(),
-            uploaded_signed_key_count: None,
-            store: Box::new(MemoryStore::new()),
-            users_for_key_query: HashSet::new(),
-            outbound_group_sessions: HashMap::new(),
+            uploaded_signed_key_count: RwLock::new(None),
+            store: RwLock::new(Box::new(MemoryStore::new())),
+            users_for_key_query: Mutex::new(HashSet::new()),
+            outbound_group_sessions: RwLock::new(HashMap::new()),
+            verification_policy: VerificationPolicy::default(),
+            verifications: Mutex::new(HashMap::new()),
+            outgoing_to_device_requests: Mutex::new(Vec::new()),
         }
     }
 
@@ -164,10 +186,13 @@ impl OlmMachine {
             user_id: user_id.clone(),
             device_id: device_id.to_owned(),
             account,
-            uploaded_signed_key_count: None,
-            store: Box::new(store),
-            users_for_key_query: HashSet::new(),
-            outbound_group_sessions: HashMap::new(),
+            uploaded_signed_key_count: RwLock::new(None),
+            store: RwLock::new(Box::new(store)),
+            users_for_key_query: Mutex::new(HashSet::new()),
+            outbound_group_sessions: RwLock::new(HashMap::new()),
+            verification_policy: VerificationPolicy::default(),
+            verifications: Mutex::new(HashMap::new()),
+            outgoing_to_device_requests: Mutex::new(Vec::new()),
         })
     }
 
@@ -217,10 +242,12 @@ impl OlmMachine {
 
         // If we have a known key count, check that we have more than
         // max_one_time_Keys() / 2, otherwise tell the client to upload more.
-        match &self.uploaded_signed_key_count {
+        let count = *self.uploaded_signed_key_count.read().await;
+
+        match count {
             Some(count) => {
                 let max_keys = self.account.max_one_time_keys().await as u64;
-                let key_count = (max_keys / 2) - count.load(Ordering::Relaxed);
+                let key_count = (max_keys / 2) - count;
                 key_count > 0
             }
             None => false,
@@ -228,11 +255,8 @@ impl OlmMachine {
     }
 
     /// Update the count of one-time keys that are currently on the server.
-    fn update_key_count(&mut self, count: u64) {
-        match &self.uploaded_signed_key_count {
-            Some(c) => c.store(count, Ordering::Relaxed),
-            None => self.uploaded_signed_key_count = Some(AtomicU64::new(count)),
-        }
+    async fn update_key_count(&self, count: u64) {
+        *self.uploaded_signed_key_count.write().await = Some(count);
     }
 
     /// Receive a successful keys upload response.
@@ -243,7 +267,7 @@ impl OlmMachine {
     /// performed.
     #[instrument]
     pub async fn receive_keys_upload_response(
-        &mut self,
+        &self,
         response: &keys::upload_keys::Response,
     ) -> OlmResult<()> {
         if !self.account.shared() {
@@ -258,15 +282,17 @@ impl OlmMachine {
         let count: u64 = one_time_key_count.map_or(0, |c| (*c).into());
         debug!(
             "Updated uploaded one-time key count {} -> {}, marking keys as published",
-            self.uploaded_signed_key_count
-                .as_ref()
-                .map_or(0, |c| c.load(Ordering::Relaxed)),
+            self.uploaded_signed_key_count.read().await.unwrap_or(0),
             count
         );
-        self.update_key_count(count);
+        self.update_key_count(count).await;
 
         self.account.mark_keys_as_published().await;
-        self.store.save_account(self.account.clone()).await?;
+        self.store
+            .write()
+            .await
+            .save_account(self.account.clone())
+            .await?;
 
         Ok(())
     }
@@ -291,13 +317,13 @@ impl OlmMachine {
     /// `users` - The list of users that we should check if we lack a session
     /// with one of their devices.
     pub async fn get_missing_sessions(
-        &mut self,
+        &self,
         users: impl Iterator<Item = &UserId>,
     ) -> OlmResult<BTreeMap<UserId, BTreeMap<DeviceId, KeyAlgorithm>>> {
         let mut missing = BTreeMap::new();
 
         for user_id in users {
-            let user_devices = self.store.get_user_devices(user_id).await?;
+            let user_devices = self.store.read().await.get_user_devices(user_id).await?;
 
             for device in user_devices.devices() {
                 let sender_key = if let Some(k) = device.get_key(KeyAlgorithm::Curve25519) {
@@ -306,7 +332,7 @@ impl OlmMachine {
                     continue;
                 };
 
-                let sessions = self.store.get_sessions(sender_key).await?;
+                let sessions = self.store.write().await.get_sessions(sender_key).await?;
 
                 let is_missing = if let Some(sessions) = sessions {
                     sessions.lock().await.is_empty()
@@ -338,7 +364,7 @@ impl OlmMachine {
     ///
     /// * `response` - The response containing the claimed one-time keys.
     pub async fn receive_keys_claim_response(
-        &mut self,
+        &self,
         response: &keys::claim_keys::Response,
     ) -> OlmResult<()> {
         // TODO log the failures here
@@ -347,6 +373,8 @@ impl OlmMachine {
             for (device_id, key_map) in user_devices {
                 let device = if let Some(d) = self
                     .store
+                    .read()
+                    .await
                     .get_device(&user_id, device_id)
                     .await
                     .expect("Can't get devices")
@@ -431,7 +459,7 @@ impl OlmMachine {
                     }
                 };
 
-                if let Err(e) = self.store.save_sessions(&[session]).await {
+                if let Err(e) = self.store.write().await.save_sessions(&[session]).await {
                     error!("Failed to store newly created Olm session {}", e);
                     continue;
                 }
@@ -455,13 +483,14 @@ impl OlmMachine {
     /// * `response` - The keys query response of the request that the client
     /// performed.
     pub async fn receive_keys_query_response(
-        &mut self,
+        &self,
         response: &keys::get_keys::Response,
     ) -> OlmResult<Vec<Device>> {
         let mut changed_devices = Vec::new();
+        let store = self.store.read().await;
 
         for (user_id, device_map) in &response.device_keys {
-            self.users_for_key_query.remove(&user_id);
+            self.users_for_key_query.lock().await.remove(&user_id);
 
             for (device_id, device_keys) in device_map.iter() {
                 // We don't need our own device in the device store.
@@ -500,7 +529,7 @@ impl OlmMachine {
                     continue;
                 }
 
-                let device = self.store.get_device(&user_id, device_id).await?;
+                let device = store.get_device(&user_id, device_id).await?;
 
                 let device = if let Some(mut d) = device {
                     let stored_signing_key = d.get_key(KeyAlgorithm::Ed25519);
@@ -525,7 +554,7 @@ impl OlmMachine {
             }
 
             let current_devices: HashSet<&DeviceId> = device_map.keys().collect();
-            let stored_devices = self.store.get_user_devices(&user_id).await.unwrap();
+            let stored_devices = store.get_user_devices(&user_id).await.unwrap();
             let stored_devices_set: HashSet<&DeviceId> = stored_devices.keys().collect();
 
             let deleted_devices = stored_devices_set.difference(&current_devices);
@@ -533,12 +562,12 @@ impl OlmMachine {
             for device_id in deleted_devices {
                 if let Some(device) = stored_devices.get(device_id) {
                     device.mark_as_deleted();
-                    self.store.delete_device(device).await?;
+                    store.delete_device(device).await?;
                 }
             }
         }
 
-        self.store.save_devices(&changed_devices).await?;
+        store.save_devices(&changed_devices).await?;
 
         Ok(changed_devices)
     }
@@ -548,9 +577,10 @@ impl OlmMachine {
     /// Returns the number of newly generated one-time keys. If no keys can be
     /// generated returns an empty error.
     async fn generate_one_time_keys(&self) -> StdResult<u64, ()> {
-        match &self.uploaded_signed_key_count {
+        let count = *self.uploaded_signed_key_count.read().await;
+
+        match count {
             Some(count) => {
-                let count = count.load(Ordering::Relaxed);
                 let max_keys = self.account.max_one_time_keys().await as u64;
                 let max_on_server = max_keys / 2;
 
@@ -761,12 +791,12 @@ impl OlmMachine {
     /// This try to decrypt an Olm message using all the sessions we share
     /// have with the given sender.
     async fn try_decrypt_olm_message(
-        &mut self,
+        &self,
         sender: &UserId,
         sender_key: &str,
         message: &OlmMessage,
     ) -> OlmResult<Option<String>> {
-        let s = self.store.get_sessions(sender_key).await?;
+        let s = self.store.write().await.get_sessions(sender_key).await?;
 
         // We don't have any existing sessions, return early.
         let sessions = if let Some(s) = s {
@@ -816,14 +846,14 @@ impl OlmMachine {
             // Decryption was successful, save the new ratchet state of the
             // session that was used to decrypt the message.
             trace!("Saved the new session state for {}", sender);
-            self.store.save_sessions(&[session]).await?;
+            self.store.write().await.save_sessions(&[session]).await?;
         }
 
         Ok(plaintext)
     }
 
     async fn decrypt_olm_message(
-        &mut self,
+        &self,
         sender: &UserId,
         sender_key: &str,
         message: OlmMessage,
@@ -871,7 +901,11 @@ impl OlmMachine {
 
                     // Save the account since we remove the one-time key that
                     // was used to create this session.
-                    self.store.save_account(self.account.clone()).await?;
+                    self.store
+                        .write()
+                        .await
+                        .save_account(self.account.clone())
+                        .await?;
                     session
                 }
             };
@@ -881,7 +915,7 @@ impl OlmMachine {
             let plaintext = session.decrypt(message).await?;
 
             // Save the new ratcheted state of the session.
-            self.store.save_sessions(&[session]).await?;
+            self.store.write().await.save_sessions(&[session]).await?;
             plaintext
         };
 
@@ -955,7 +989,7 @@ impl OlmMachine {
     ///
     /// * `event` - The to-device event that should be decrypted.
     async fn decrypt_to_device_event(
-        &mut self,
+        &self,
         event: &ToDeviceEncrypted,
     ) -> OlmResult<EventJson<ToDeviceEvent>> {
         info!("Decrypting to-device event");
@@ -1017,7 +1051,7 @@ impl OlmMachine {
 
     /// Create a group session from a room key and add it to our crypto store.
     async fn add_room_key(
-        &mut self,
+        &self,
         sender_key: &str,
         signing_key: &str,
         event: &mut ToDeviceRoomKey,
@@ -1032,7 +1066,12 @@ impl OlmMachine {
                     &event.content.room_id,
                     session_key,
                 )?;
-                let _ = self.store.save_inbound_group_session(session).await?;
+                let _ = self
+                    .store
+                    .write()
+                    .await
+                    .save_inbound_group_session(session)
+                    .await?;
                 // TODO ideally we would rewrap the event again just like so
                 // let event = EventJson::from(ToDeviceEvent::RoomKey(event.clone()));
                 // This saidly lacks a type once it's serialized again, fix
@@ -1059,7 +1098,7 @@ impl OlmMachine {
     ///
     /// This also creates a matching inbound group session and saves that one in
     /// the store.
-    async fn create_outbound_group_session(&mut self, room_id: &RoomId) -> OlmResult<()> {
+    async fn create_outbound_group_session(&self, room_id: &RoomId) -> OlmResult<()> {
         let session = OutboundGroupSession::new(room_id);
         let identity_keys = self.account.identity_keys();
 
@@ -1074,11 +1113,15 @@ impl OlmMachine {
         )?;
         let _ = self
             .store
+            .write()
+            .await
             .save_inbound_group_session(inbound_session)
             .await?;
 
         let _ = self
             .outbound_group_sessions
+            .write()
+            .await
             .insert(room_id.to_owned(), session);
         Ok(())
     }
@@ -1109,7 +1152,40 @@ impl OlmMachine {
         room_id: &RoomId,
         content: MessageEventContent,
     ) -> MegolmResult<EncryptedEventContent> {
-        let session = self.outbound_group_sessions.get(room_id);
+        self.encrypt_raw(room_id, EventType::RoomMessage, json!(content))
+            .await
+    }
+
+    /// Encrypt the raw JSON content of a room event for the given room.
+    ///
+    /// This is useful for content that can't be represented by a
+    /// `MessageEventContent`, e.g. the content of an edit or a reaction.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The id of the room for which the message should be
+    /// encrypted.
+    ///
+    /// * `event_type` - The type of the event that should be encrypted.
+    ///
+    /// * `content` - The plaintext JSON content of the event that should be
+    /// encrypted.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a group session for the given room wasn't shared beforehand.
+    pub async fn encrypt_raw(
+        &self,
+        room_id: &RoomId,
+        event_type: EventType,
+        content: Value,
+    ) -> MegolmResult<EncryptedEventContent> {
+        let session = self
+            .outbound_group_sessions
+            .read()
+            .await
+            .get(room_id)
+            .cloned();
 
         let session = if let Some(s) = session {
             s
@@ -1124,7 +1200,7 @@ impl OlmMachine {
         let json_content = json!({
             "content": content,
             "room_id": room_id,
-            "type": EventType::RoomMessage,
+            "type": event_type,
         });
 
         let plaintext = cjson::to_string(&json_content).unwrap_or_else(|_| {
@@ -1148,7 +1224,7 @@ impl OlmMachine {
 
     /// Encrypt some JSON content using the given Olm session.
     async fn olm_encrypt(
-        &mut self,
+        &self,
         mut session: Session,
         recipient_device: &Device,
         event_type: EventType,
@@ -1181,7 +1257,7 @@ impl OlmMachine {
             .unwrap_or_else(|_| panic!(format!("Can't serialize {} to canonical JSON", payload)));
 
         let ciphertext = session.encrypt(&plaintext).await.to_tuple();
-        self.store.save_sessions(&[session]).await?;
+        self.store.write().await.save_sessions(&[session]).await?;
 
         let message_type: usize = ciphertext.0.into();
 
@@ -1210,8 +1286,9 @@ impl OlmMachine {
     ///
     /// This should be called every time a new room message wants to be sent out
     /// since group sessions can expire at any time.
-    pub fn should_share_group_session(&self, room_id: &RoomId) -> bool {
-        let session = self.outbound_group_sessions.get(room_id);
+    pub async fn should_share_group_session(&self, room_id: &RoomId) -> bool {
+        let sessions = self.outbound_group_sessions.read().await;
+        let session = sessions.get(room_id);
 
         match session {
             Some(s) => !s.shared() || s.expired(),
@@ -1219,6 +1296,63 @@ impl OlmMachine {
         }
     }
 
+    /// Get the inbound group sessions we know for the given room.
+    ///
+    /// The sessions are sorted by the key of their sender and by the first
+    /// message index they can decrypt.
+    ///
+    /// # Arguments
+    ///
+    /// `room_id` - The room id of the room the sessions belong to.
+    pub async fn inbound_group_sessions(
+        &self,
+        room_id: &RoomId,
+    ) -> OlmResult<Vec<GroupSessionInfo>> {
+        let sessions = self
+            .store
+            .read()
+            .await
+            .get_inbound_group_sessions(room_id)
+            .await?;
+        let mut infos = Vec::with_capacity(sessions.len());
+
+        for session in sessions {
+            infos.push(session.info().await);
+        }
+
+        infos.sort_by(|a, b| {
+            a.sender_key
+                .cmp(&b.sender_key)
+                .then(a.first_known_index.cmp(&b.first_known_index))
+        });
+
+        Ok(infos)
+    }
+
+    /// Export the inbound group sessions we know for the given room in the
+    /// key export format.
+    ///
+    /// The keys can be encrypted with `encrypt_key_export()`.
+    ///
+    /// # Arguments
+    ///
+    /// `room_id` - The room id of the room the sessions belong to.
+    pub async fn export_room_keys(&self, room_id: &RoomId) -> OlmResult<Vec<ExportedRoomKey>> {
+        let sessions = self
+            .store
+            .read()
+            .await
+            .get_inbound_group_sessions(room_id)
+            .await?;
+        let mut keys = Vec::with_capacity(sessions.len());
+
+        for session in sessions {
+            keys.push(session.export().await?);
+        }
+
+        Ok(keys)
+    }
+
     // TODO accept an algorithm here
     /// Get to-device requests to share a group session with users in a room.
     ///
@@ -1229,7 +1363,7 @@ impl OlmMachine {
     ///
     /// `users` - The list of users that should receive the group session.
     pub async fn share_group_session<'a, I>(
-        &mut self,
+        &self,
         room_id: &RoomId,
         users: I,
     ) -> OlmResult<Vec<ToDeviceRequest>>
@@ -1237,7 +1371,13 @@ impl OlmMachine {
         I: IntoIterator<Item = &'a UserId>,
     {
         self.create_outbound_group_session(room_id).await?;
-        let megolm_session = self.outbound_group_sessions.get(room_id).unwrap();
+        let megolm_session = self
+            .outbound_group_sessions
+            .read()
+            .await
+            .get(room_id)
+            .cloned()
+            .unwrap();
 
         if megolm_session.shared() {
             panic!("Session is already shared");
@@ -1257,7 +1397,9 @@ impl OlmMachine {
         let mut user_map = Vec::new();
 
         for user_id in users {
-            for device in self.store.get_user_devices(user_id).await?.devices() {
+            let user_devices = self.store.read().await.get_user_devices(user_id).await?;
+
+            for device in user_devices.devices() {
                 let sender_key = if let Some(k) = device.get_key(KeyAlgorithm::Curve25519) {
                     k
                 } else {
@@ -1271,7 +1413,7 @@ impl OlmMachine {
                 };
 
                 // TODO abort if the device isn't verified
-                let sessions = self.store.get_sessions(sender_key).await?;
+                let sessions = self.store.write().await.get_sessions(sender_key).await?;
 
                 if let Some(s) = sessions {
                     let session = &s.lock().await[0];
@@ -1323,9 +1465,64 @@ impl OlmMachine {
             });
         }
 
+        // Remember the requests until they are sent, if sending them gets
+        // interrupted the room key is shared with only some of the devices.
+        let pending = message_vec
+            .iter()
+            .map(|r| PendingToDeviceRequest::new(room_id, r))
+            .collect::<StdResult<Vec<_>, _>>()?;
+        self.store
+            .write()
+            .await
+            .save_pending_requests(&pending)
+            .await?;
+
         Ok(message_vec)
     }
 
+    /// Remove our account and all the keys, sessions and devices that were
+    /// stored for it from the crypto store.
+    ///
+    /// The machine shouldn't be used anymore afterwards, this is meant to be
+    /// called after the account was deactivated.
+    pub async fn clear_store(&self) -> StoreError<()> {
+        self.store.write().await.clear().await
+    }
+
+    /// Get the to-device requests that share a room key but weren't marked as
+    /// sent, e.g. because sending them failed or the process was stopped
+    /// while they were sent.
+    ///
+    /// The requests keep their transaction id, resending them won't deliver
+    /// the room key twice.
+    pub async fn pending_room_key_requests(&self) -> StoreError<Vec<ToDeviceRequest>> {
+        let pending = self.store.read().await.get_pending_requests().await?;
+
+        Ok(pending
+            .iter()
+            .filter_map(|p| match p.to_request() {
+                Ok(r) => Some(r),
+                Err(e) => {
+                    warn!("Dropping an invalid pending to-device request {}", e);
+                    None
+                }
+            })
+            .collect())
+    }
+
+    /// Mark a to-device request that shares a room key as sent.
+    ///
+    /// # Arguments
+    ///
+    /// * `txn_id` - The transaction id of the request that was sent.
+    pub async fn mark_room_key_request_as_sent(&self, txn_id: &str) -> StoreError<()> {
+        self.store
+            .write()
+            .await
+            .remove_pending_request(txn_id)
+            .await
+    }
+
     fn add_forwarded_room_key(
         &self,
         _sender_key: &str,
@@ -1346,7 +1543,7 @@ impl OlmMachine {
     ///
     /// * `event` - The decrypted to-device event.
     async fn handle_decrypted_to_device_event(
-        &mut self,
+        &self,
         sender_key: &str,
         signing_key: &str,
         event: &EventJson<ToDeviceEvent>,
@@ -1377,8 +1574,335 @@ impl OlmMachine {
         // TODO handle room key requests here.
     }
 
-    fn handle_verification_event(&self, _: &ToDeviceEvent) {
-        // TODO handle to-device verification events here.
+    /// Set the policy that decides which key verifications get accepted and
+    /// completed without user interaction.
+    ///
+    /// # Arguments
+    ///
+    /// * `policy` - The new verification policy.
+    pub fn set_verification_policy(&mut self, policy: VerificationPolicy) {
+        self.verification_policy = policy;
+    }
+
+    /// Get the to-device requests that need to be sent out.
+    ///
+    /// The requests are removed from the machine, the caller is responsible
+    /// for sending them to the server.
+    pub async fn outgoing_to_device_requests(&self) -> Vec<ToDeviceRequest> {
+        mem::take(&mut *self.outgoing_to_device_requests.lock().await)
+    }
+
+    /// Start a SAS verification with the given device.
+    ///
+    /// The short authentication string is confirmed automatically, once the
+    /// other device confirms it as well the device is marked as verified.
+    /// Only start verifications with devices that are trusted by other
+    /// means.
+    ///
+    /// # Arguments
+    ///
+    /// * `device` - The device that should be verified.
+    pub async fn start_verification(&self, device: &Device) {
+        self.begin_verification(device.clone(), Uuid::new_v4().to_string())
+            .await;
+    }
+
+    async fn begin_verification(&self, device: Device, transaction_id: String) {
+        let (sas, content) = Sas::start(
+            &self.user_id,
+            &self.device_id,
+            self.account.identity_keys().ed25519(),
+            device,
+            transaction_id.clone(),
+        );
+
+        self.queue_to_device(sas.other_device(), EventType::KeyVerificationStart, content)
+            .await;
+        self.verifications.lock().await.insert(transaction_id, sas);
+    }
+
+    async fn queue_to_device(&self, device: &Device, event_type: EventType, content: Value) {
+        let mut user_messages = BTreeMap::new();
+        user_messages.insert(
+            DeviceIdOrAllDevices::DeviceId(device.device_id().clone()),
+            serde_json::value::to_raw_value(&content).expect("Can't serialize a JSON value"),
+        );
+
+        let mut messages = BTreeMap::new();
+        messages.insert(device.user_id().clone(), user_messages);
+
+        self.outgoing_to_device_requests
+            .lock()
+            .await
+            .push(ToDeviceRequest {
+                event_type,
+                txn_id: Uuid::new_v4().to_string(),
+                messages,
+            });
+    }
+
+    /// Find the device that sent a verification event, if the verification
+    /// policy allows us to verify it automatically.
+    async fn allowed_verification_device(
+        &self,
+        sender: &UserId,
+        content: &Value,
+    ) -> Option<Device> {
+        let device_id = content["from_device"].as_str()?.to_owned();
+        let device = self
+            .store
+            .read()
+            .await
+            .get_device(sender, &device_id)
+            .await
+            .ok()??;
+
+        if self.verification_policy.allows(&self.user_id, &device) {
+            Some(device)
+        } else {
+            info!(
+                "Ignoring a verification from {} {}, the verification policy doesn't allow it",
+                sender, device_id
+            );
+            None
+        }
+    }
+
+    async fn handle_verification_event(&self, event: &ToDeviceEvent) {
+        let (sender, content) = match event {
+            ToDeviceEvent::KeyVerificationRequest(e) => {
+                (&e.sender, serde_json::to_value(&e.content))
+            }
+            ToDeviceEvent::KeyVerificationStart(e) => (&e.sender, serde_json::to_value(&e.content)),
+            ToDeviceEvent::KeyVerificationAccept(e) => {
+                (&e.sender, serde_json::to_value(&e.content))
+            }
+            ToDeviceEvent::KeyVerificationKey(e) => (&e.sender, serde_json::to_value(&e.content)),
+            ToDeviceEvent::KeyVerificationMac(e) => (&e.sender, serde_json::to_value(&e.content)),
+            ToDeviceEvent::KeyVerificationCancel(e) => {
+                (&e.sender, serde_json::to_value(&e.content))
+            }
+            _ => return,
+        };
+
+        let content = if let Ok(c) = content {
+            c
+        } else {
+            return;
+        };
+
+        let transaction_id = if let Some(t) = content["transaction_id"].as_str() {
+            t.to_owned()
+        } else {
+            warn!("Received a verification event without a transaction id");
+            return;
+        };
+
+        match event {
+            ToDeviceEvent::KeyVerificationRequest(_) => {
+                let supports_sas = content["methods"]
+                    .as_array()
+                    .map_or(false, |m| m.iter().any(|m| m == "m.sas.v1"));
+
+                if !supports_sas {
+                    return;
+                }
+
+                if let Some(device) = self.allowed_verification_device(sender, &content).await {
+                    self.begin_verification(device, transaction_id).await;
+                }
+            }
+            ToDeviceEvent::KeyVerificationStart(_) => {
+                let device =
+                    if let Some(d) = self.allowed_verification_device(sender, &content).await {
+                        d
+                    } else {
+                        return;
+                    };
+
+                if !self
+                    .resolve_verification_glare(&device, &transaction_id)
+                    .await
+                {
+                    return;
+                }
+
+                match Sas::accept(
+                    &self.user_id,
+                    &self.device_id,
+                    self.account.identity_keys().ed25519(),
+                    device.clone(),
+                    content,
+                ) {
+                    Ok((sas, accept)) => {
+                        self.queue_to_device(&device, EventType::KeyVerificationAccept, accept)
+                            .await;
+                        self.verifications.lock().await.insert(transaction_id, sas);
+                    }
+                    Err(cancel) => {
+                        self.queue_to_device(&device, EventType::KeyVerificationCancel, cancel)
+                            .await
+                    }
+                }
+            }
+            ToDeviceEvent::KeyVerificationCancel(_) => {
+                let removed = self.verifications.lock().await.remove(&transaction_id);
+
+                if removed.is_some() {
+                    info!("The verification {} was cancelled", transaction_id);
+                }
+            }
+            _ => {
+                self.continue_verification(sender, event, transaction_id, &content)
+                    .await
+            }
+        }
+    }
+
+    /// Check a start event we received against the verifications that are
+    /// already in progress with the same device.
+    ///
+    /// If we started a verification with the device as well, the one started
+    /// by the lexicographically smaller user id, or the smaller device id if
+    /// both devices belong to the same user, wins. The other verification is
+    /// cancelled.
+    ///
+    /// Returns true if the received start event should be accepted.
+    async fn resolve_verification_glare(&self, device: &Device, transaction_id: &str) -> bool {
+        let unexpected = |transaction_id: &str, reason: &str| {
+            cancel_content(transaction_id, "m.unexpected_message", reason)
+        };
+
+        let mut verifications = self.verifications.lock().await;
+
+        if let Some(sas) = verifications.get(transaction_id) {
+            if !sas.is_with(device) {
+                let cancel =
+                    unexpected(transaction_id, "The verification belongs to another device");
+                self.queue_to_device(device, EventType::KeyVerificationCancel, cancel)
+                    .await;
+                return false;
+            }
+        }
+
+        let (existing_id, we_started) =
+            match verifications.iter().find(|(_, sas)| sas.is_with(device)) {
+                Some((id, sas)) => (id.clone(), sas.we_started()),
+                None => return true,
+            };
+
+        if !we_started {
+            // The device started a second verification while the first one
+            // is still going on.
+            if existing_id == transaction_id {
+                verifications.remove(&existing_id);
+            }
+
+            let cancel = unexpected(transaction_id, "A verification is already in progress");
+            self.queue_to_device(device, EventType::KeyVerificationCancel, cancel)
+                .await;
+            return false;
+        }
+
+        let we_win = (self.user_id.to_string(), &self.device_id)
+            < (device.user_id().to_string(), device.device_id());
+
+        if we_win {
+            info!(
+                "Ignoring the verification start of {} {}, our own start wins",
+                device.user_id(),
+                device.device_id()
+            );
+
+            // If both starts share the transaction id the other device will
+            // accept ours, there is nothing to cancel.
+            if existing_id != transaction_id {
+                let cancel = unexpected(transaction_id, "Another verification won the glare");
+                self.queue_to_device(device, EventType::KeyVerificationCancel, cancel)
+                    .await;
+            }
+
+            false
+        } else {
+            verifications.remove(&existing_id);
+
+            if existing_id != transaction_id {
+                let cancel = unexpected(&existing_id, "Another verification won the glare");
+                self.queue_to_device(device, EventType::KeyVerificationCancel, cancel)
+                    .await;
+            }
+
+            true
+        }
+    }
+
+    async fn continue_verification(
+        &self,
+        sender: &UserId,
+        event: &ToDeviceEvent,
+        transaction_id: String,
+        content: &Value,
+    ) {
+        let sas = self.verifications.lock().await.remove(&transaction_id);
+
+        let mut sas = if let Some(s) = sas {
+            s
+        } else {
+            return;
+        };
+
+        let device = sas.other_device().clone();
+
+        if device.user_id() != sender {
+            self.queue_to_device(
+                &device,
+                EventType::KeyVerificationCancel,
+                cancel_content(&transaction_id, "m.user_mismatch", "Unexpected sender"),
+            )
+            .await;
+            return;
+        }
+
+        let result = match event {
+            ToDeviceEvent::KeyVerificationAccept(_) => sas
+                .receive_accept(content)
+                .map(|key| vec![(EventType::KeyVerificationKey, key)]),
+            ToDeviceEvent::KeyVerificationKey(_) => sas.receive_key(content),
+            ToDeviceEvent::KeyVerificationMac(_) => match sas.receive_mac(content) {
+                Ok(()) => {
+                    device.set_trust_state(TrustState::Verified);
+
+                    let store = self.store.read().await;
+
+                    if let Err(e) = store.save_devices(&[device.clone()]).await {
+                        error!("Failed to store the verified device {}", e);
+                    }
+
+                    info!(
+                        "Verified the device {} {}",
+                        device.user_id(),
+                        device.device_id()
+                    );
+                    return;
+                }
+                Err(cancel) => Err(cancel),
+            },
+            _ => return,
+        };
+
+        match result {
+            Ok(messages) => {
+                for (event_type, content) in messages {
+                    self.queue_to_device(&device, event_type, content).await;
+                }
+
+                self.verifications.lock().await.insert(transaction_id, sas);
+            }
+            Err(cancel) => {
+                self.queue_to_device(&device, EventType::KeyVerificationCancel, cancel)
+                    .await
+            }
+        }
     }
 
     /// Handle a sync response and update the internal state of the Olm machine.
@@ -1392,13 +1916,13 @@ impl OlmMachine {
     ///
     /// * `response` - The sync latest sync response.
     #[instrument(skip(response))]
-    pub async fn receive_sync_response(&mut self, response: &mut SyncResponse) {
+    pub async fn receive_sync_response(&self, response: &mut SyncResponse) {
         let one_time_key_count = response
             .device_one_time_keys_count
             .get(&keys::KeyAlgorithm::SignedCurve25519);
 
         let count: u64 = one_time_key_count.map_or(0, |c| (*c).into());
-        self.update_key_count(count);
+        self.update_key_count(count).await;
 
         for event_result in &mut response.to_device.events {
             let event = if let Ok(e) = event_result.deserialize() {
@@ -1436,7 +1960,9 @@ impl OlmMachine {
                 | ToDeviceEvent::KeyVerificationKey(..)
                 | ToDeviceEvent::KeyVerificationMac(..)
                 | ToDeviceEvent::KeyVerificationRequest(..)
-                | ToDeviceEvent::KeyVerificationStart(..) => self.handle_verification_event(&event),
+                | ToDeviceEvent::KeyVerificationStart(..) => {
+                    self.handle_verification_event(&event).await
+                }
                 _ => continue,
             }
         }
@@ -1444,11 +1970,14 @@ impl OlmMachine {
 
     /// Decrypt an event from a room timeline.
     ///
+    /// Decryption only reads the group sessions of the store, so multiple
+    /// events can be decrypted concurrently.
+    ///
     /// # Arguments
     ///
     /// * `event` - The event that should be decrypted.
     pub async fn decrypt_room_event(
-        &mut self,
+        &self,
         event: &EncryptedEvent,
     ) -> MegolmResult<EventJson<RoomEvent>> {
         let content = match &event.content {
@@ -1460,6 +1989,8 @@ impl OlmMachine {
 
         let session = self
             .store
+            .read()
+            .await
             .get_inbound_group_session(&room_id, &content.sender_key, &content.session_id)
             .await?;
         // TODO check if the Olm session is wedged and re-request the key.
@@ -1499,6 +2030,32 @@ impl OlmMachine {
         Ok(decrypted_event)
     }
 
+    /// Decrypt the raw JSON of an encrypted room event, e.g. one that was
+    /// delivered inside of a push notification.
+    ///
+    /// Only the group sessions that are in the store are used, no sync or
+    /// other network access is needed. A separate process, like a
+    /// notification service extension, can open the same store, create an
+    /// `OlmMachine` using `new_with_store()` and decrypt the notification
+    /// content with it.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The id of the room the event was sent in, push payloads
+    /// don't necessarily contain it in the event itself.
+    ///
+    /// * `event` - The JSON of the `m.room.encrypted` event.
+    pub async fn decrypt_raw_room_event(
+        &self,
+        room_id: &RoomId,
+        event: &str,
+    ) -> MegolmResult<EventJson<RoomEvent>> {
+        let mut event: EncryptedEvent = serde_json::from_str(event)?;
+        event.room_id = Some(room_id.clone());
+
+        self.decrypt_room_event(&event).await
+    }
+
     /// Update the tracked users.
     ///
     /// # Arguments
@@ -1511,37 +2068,63 @@ impl OlmMachine {
     /// considered for a key query.
     ///
     /// Use the `mark_user_as_changed()` if the user really needs a key query.
-    pub async fn update_tracked_users<'a, I>(&mut self, users: I)
+    pub async fn update_tracked_users<'a, I>(&self, users: I)
     where
         I: IntoIterator<Item = &'a UserId>,
     {
         for user in users {
-            let ret = self.store.add_user_for_tracking(user).await;
+            let ret = self.store.write().await.add_user_for_tracking(user).await;
 
             match ret {
                 Ok(newly_added) => {
                     if newly_added {
-                        self.users_for_key_query.insert(user.clone());
+                        self.users_for_key_query.lock().await.insert(user.clone());
                     }
                 }
                 Err(e) => {
                     warn!("Error storing users for tracking {}", e);
-                    self.users_for_key_query.insert(user.clone());
+                    self.users_for_key_query.lock().await.insert(user.clone());
                 }
             }
         }
     }
 
+    /// Mark a user as changed, so their devices are queried again with the
+    /// next key query.
+    ///
+    /// This also starts tracking the user if they aren't tracked yet.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - The user whose device list changed.
+    pub async fn mark_user_as_changed(&self, user_id: &UserId) {
+        let ret = self
+            .store
+            .write()
+            .await
+            .add_user_for_tracking(user_id)
+            .await;
+
+        if let Err(e) = ret {
+            warn!("Error storing users for tracking {}", e);
+        }
+
+        self.users_for_key_query
+            .lock()
+            .await
+            .insert(user_id.clone());
+    }
+
     /// Should the client perform a key query request.
-    pub fn should_query_keys(&self) -> bool {
-        !self.users_for_key_query.is_empty()
+    pub async fn should_query_keys(&self) -> bool {
+        !self.users_for_key_query.lock().await.is_empty()
     }
 
     /// Get the set of users that we need to query keys for.
     ///
     /// Returns a hash set of users that need to be queried for keys.
-    pub fn users_for_key_query(&self) -> HashSet<UserId> {
-        self.users_for_key_query.clone()
+    pub async fn users_for_key_query(&self) -> HashSet<UserId> {
+        self.users_for_key_query.lock().await.clone()
     }
 }
 
@@ -1551,18 +2134,17 @@ mod test {
     static DEVICE_ID: &str = "DEVICEID";
 
     use matrix_sdk_common::js_int::UInt;
-    use std::collections::BTreeMap;
+    use std::collections::{BTreeMap, HashSet};
     use std::convert::TryFrom;
     use std::fs::File;
     use std::io::prelude::*;
-    use std::sync::atomic::AtomicU64;
     use std::time::SystemTime;
 
     use http::Response;
     use serde_json::json;
 
     use crate::machine::{OlmMachine, OneTimeKeys};
-    use crate::Device;
+    use crate::{Device, TrustState, VerificationPolicy};
 
     use matrix_sdk_common::api::r0::{
         keys, to_device::send_event_to_device::Request as ToDeviceRequest,
@@ -1630,8 +2212,8 @@ mod test {
     }
 
     async fn get_prepared_machine() -> (OlmMachine, OneTimeKeys) {
-        let mut machine = OlmMachine::new(&user_id(), DEVICE_ID);
-        machine.uploaded_signed_key_count = Some(AtomicU64::new(0));
+        let machine = OlmMachine::new(&user_id(), DEVICE_ID);
+        *machine.uploaded_signed_key_count.write().await = Some(0);
         let (_, otk) = machine
             .keys_for_upload()
             .await
@@ -1646,7 +2228,7 @@ mod test {
     }
 
     async fn get_machine_after_query() -> (OlmMachine, OneTimeKeys) {
-        let (mut machine, otk) = get_prepared_machine().await;
+        let (machine, otk) = get_prepared_machine().await;
         let response = keys_query_response();
 
         machine
@@ -1666,14 +2248,25 @@ mod test {
 
         let alice_deivce = Device::from(&alice);
         let bob_device = Device::from(&bob);
-        alice.store.save_devices(&[bob_device]).await.unwrap();
-        bob.store.save_devices(&[alice_deivce]).await.unwrap();
+        alice
+            .store
+            .read()
+            .await
+            .save_devices(&[bob_device])
+            .await
+            .unwrap();
+        bob.store
+            .read()
+            .await
+            .save_devices(&[alice_deivce])
+            .await
+            .unwrap();
 
         (alice, bob, otk)
     }
 
     async fn get_machine_pair_with_session() -> (OlmMachine, OlmMachine) {
-        let (mut alice, bob, one_time_keys) = get_machine_pair().await;
+        let (alice, bob, one_time_keys) = get_machine_pair().await;
 
         let mut bob_keys = BTreeMap::new();
 
@@ -1696,10 +2289,12 @@ mod test {
     }
 
     async fn get_machine_pair_with_setup_sessions() -> (OlmMachine, OlmMachine) {
-        let (mut alice, mut bob) = get_machine_pair_with_session().await;
+        let (alice, bob) = get_machine_pair_with_session().await;
 
         let session = alice
             .store
+            .write()
+            .await
             .get_sessions(bob.account.identity_keys().curve25519())
             .await
             .unwrap()
@@ -1710,6 +2305,8 @@ mod test {
 
         let bob_device = alice
             .store
+            .read()
+            .await
             .get_device(&bob.user_id, &bob.device_id)
             .await
             .unwrap()
@@ -1736,7 +2333,7 @@ mod test {
 
     #[tokio::test]
     async fn receive_keys_upload_response() {
-        let mut machine = OlmMachine::new(&user_id(), DEVICE_ID);
+        let machine = OlmMachine::new(&user_id(), DEVICE_ID);
         let mut response = keys_upload_response();
 
         response
@@ -1774,7 +2371,7 @@ mod test {
 
     #[tokio::test]
     async fn generate_one_time_keys() {
-        let mut machine = OlmMachine::new(&user_id(), DEVICE_ID);
+        let machine = OlmMachine::new(&user_id(), DEVICE_ID);
 
         let mut response = keys_upload_response();
 
@@ -1833,8 +2430,8 @@ mod test {
 
     #[tokio::test]
     async fn test_one_time_key_signing() {
-        let mut machine = OlmMachine::new(&user_id(), DEVICE_ID);
-        machine.uploaded_signed_key_count = Some(AtomicU64::new(49));
+        let machine = OlmMachine::new(&user_id(), DEVICE_ID);
+        *machine.uploaded_signed_key_count.write().await = Some(49);
 
         let mut one_time_keys = machine.signed_one_time_keys().await.unwrap();
         let identity_keys = machine.account.identity_keys();
@@ -1853,8 +2450,8 @@ mod test {
 
     #[tokio::test]
     async fn test_keys_for_upload() {
-        let mut machine = OlmMachine::new(&user_id(), DEVICE_ID);
-        machine.uploaded_signed_key_count = Some(AtomicU64::default());
+        let machine = OlmMachine::new(&user_id(), DEVICE_ID);
+        *machine.uploaded_signed_key_count.write().await = Some(0);
 
         let identity_keys = machine.account.identity_keys();
         let ed25519_key = identity_keys.ed25519();
@@ -1897,12 +2494,18 @@ mod test {
 
     #[tokio::test]
     async fn test_keys_query() {
-        let (mut machine, _) = get_prepared_machine().await;
+        let (machine, _) = get_prepared_machine().await;
         let response = keys_query_response();
         let alice_id = UserId::try_from("@alice:example.org").unwrap();
         let alice_device_id = "JLAFKJWSCS".to_owned();
 
-        let alice_devices = machine.store.get_user_devices(&alice_id).await.unwrap();
+        let alice_devices = machine
+            .store
+            .read()
+            .await
+            .get_user_devices(&alice_id)
+            .await
+            .unwrap();
         assert!(alice_devices.devices().peekable().peek().is_none());
 
         machine
@@ -1912,6 +2515,8 @@ mod test {
 
         let device = machine
             .store
+            .read()
+            .await
             .get_device(&alice_id, &alice_device_id)
             .await
             .unwrap()
@@ -1920,9 +2525,27 @@ mod test {
         assert_eq!(device.device_id(), &alice_device_id);
     }
 
+    #[tokio::test]
+    async fn test_mark_user_as_changed() {
+        let (machine, _) = get_machine_after_query().await;
+        let alice_id = UserId::try_from("@alice:example.org").unwrap();
+
+        assert!(!machine.users_for_key_query().await.contains(&alice_id));
+
+        machine.mark_user_as_changed(&alice_id).await;
+        assert!(machine.should_query_keys().await);
+        assert!(machine.users_for_key_query().await.contains(&alice_id));
+
+        machine
+            .receive_keys_query_response(&keys_query_response())
+            .await
+            .unwrap();
+        assert!(!machine.users_for_key_query().await.contains(&alice_id));
+    }
+
     #[tokio::test]
     async fn test_missing_sessions_calculation() {
-        let (mut machine, _) = get_machine_after_query().await;
+        let (machine, _) = get_machine_after_query().await;
 
         let alice = alice_id();
         let alice_device = alice_device_id();
@@ -1939,7 +2562,7 @@ mod test {
 
     #[tokio::test]
     async fn test_session_creation() {
-        let (mut alice_machine, bob_machine, one_time_keys) = get_machine_pair().await;
+        let (alice_machine, bob_machine, one_time_keys) = get_machine_pair().await;
 
         let mut bob_keys = BTreeMap::new();
 
@@ -1963,6 +2586,8 @@ mod test {
 
         let session = alice_machine
             .store
+            .write()
+            .await
             .get_sessions(bob_machine.account.identity_keys().curve25519())
             .await
             .unwrap()
@@ -1973,10 +2598,12 @@ mod test {
 
     #[tokio::test]
     async fn test_olm_encryption() {
-        let (mut alice, mut bob) = get_machine_pair_with_session().await;
+        let (alice, bob) = get_machine_pair_with_session().await;
 
         let session = alice
             .store
+            .write()
+            .await
             .get_sessions(bob.account.identity_keys().curve25519())
             .await
             .unwrap()
@@ -1987,6 +2614,8 @@ mod test {
 
         let bob_device = alice
             .store
+            .read()
+            .await
             .get_device(&bob.user_id, &bob.device_id)
             .await
             .unwrap()
@@ -2011,7 +2640,7 @@ mod test {
 
     #[tokio::test]
     async fn test_room_key_sharing() {
-        let (mut alice, mut bob) = get_machine_pair_with_session().await;
+        let (alice, bob) = get_machine_pair_with_session().await;
 
         let room_id = RoomId::try_from("!test:example.org").unwrap();
 
@@ -2025,7 +2654,21 @@ mod test {
             content: to_device_requests_to_content(to_device_requests),
         };
 
-        let alice_session = alice.outbound_group_sessions.get(&room_id).unwrap();
+        let pending = alice.pending_room_key_requests().await.unwrap();
+        assert_eq!(pending.len(), 1);
+        alice
+            .mark_room_key_request_as_sent(&pending[0].txn_id)
+            .await
+            .unwrap();
+        assert!(alice.pending_room_key_requests().await.unwrap().is_empty());
+
+        let alice_session = alice
+            .outbound_group_sessions
+            .read()
+            .await
+            .get(&room_id)
+            .cloned()
+            .unwrap();
 
         let event = bob.decrypt_to_device_event(&event).await.unwrap();
 
@@ -2038,6 +2681,8 @@ mod test {
 
         let session = bob
             .store
+            .read()
+            .await
             .get_inbound_group_session(
                 &room_id,
                 alice.account.identity_keys().curve25519(),
@@ -2046,11 +2691,25 @@ mod test {
             .await;
 
         assert!(session.unwrap().is_some());
+
+        let sessions = bob.inbound_group_sessions(&room_id).await.unwrap();
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].session_id, alice_session.session_id());
+        assert_eq!(
+            sessions[0].sender_key,
+            alice.account.identity_keys().curve25519()
+        );
+        assert_eq!(sessions[0].first_known_index, 0);
+
+        let keys = bob.export_room_keys(&room_id).await.unwrap();
+        assert_eq!(keys.len(), 1);
+        assert_eq!(keys[0].session_id, alice_session.session_id());
+        assert_eq!(keys[0].room_id, room_id);
     }
 
     #[tokio::test]
     async fn test_megolm_encryption() {
-        let (mut alice, mut bob) = get_machine_pair_with_setup_sessions().await;
+        let (alice, bob) = get_machine_pair_with_setup_sessions().await;
         let room_id = RoomId::try_from("!test:example.org").unwrap();
 
         let to_device_requests = alice
@@ -2100,4 +2759,287 @@ mod test {
             panic!("Decrypted event has a missmatched content");
         }
     }
+
+    #[tokio::test]
+    async fn test_concurrent_megolm_decryption() {
+        let (alice, bob) = get_machine_pair_with_setup_sessions().await;
+        let room_id = RoomId::try_from("!test:example.org").unwrap();
+
+        let to_device_requests = alice
+            .share_group_session(&room_id, [bob.user_id().clone()].iter())
+            .await
+            .unwrap();
+
+        let event = ToDeviceEncrypted {
+            sender: alice.user_id().clone(),
+            content: to_device_requests_to_content(to_device_requests),
+        };
+
+        bob.decrypt_to_device_event(&event).await.unwrap();
+
+        let mut events = Vec::new();
+
+        for plaintext in &["First secret", "Second secret"] {
+            let content = MessageEventContent::Text(TextMessageEventContent::new_plain(*plaintext));
+            let encrypted_content = alice.encrypt(&room_id, content).await.unwrap();
+
+            events.push(EncryptedEvent {
+                event_id: EventId::new("example.org").unwrap(),
+                origin_server_ts: SystemTime::now(),
+                room_id: Some(room_id.clone()),
+                sender: alice.user_id().clone(),
+                content: encrypted_content,
+                unsigned: UnsignedData::default(),
+            });
+        }
+
+        let (first, second) = tokio::join!(
+            bob.decrypt_room_event(&events[0]),
+            bob.decrypt_room_event(&events[1])
+        );
+
+        for (decrypted, plaintext) in [first, second]
+            .iter()
+            .zip(&["First secret", "Second secret"])
+        {
+            match decrypted.as_ref().unwrap().deserialize().unwrap() {
+                RoomEvent::RoomMessage(e) => match e.content {
+                    MessageEventContent::Text(c) => assert_eq!(c.body, *plaintext),
+                    _ => panic!("Decrypted event has a mismatched content"),
+                },
+                _ => panic!("Decrypted room event has the wrong type"),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_raw_event_decryption() {
+        let (alice, bob) = get_machine_pair_with_setup_sessions().await;
+        let room_id = RoomId::try_from("!test:example.org").unwrap();
+
+        let to_device_requests = alice
+            .share_group_session(&room_id, [bob.user_id().clone()].iter())
+            .await
+            .unwrap();
+
+        let event = ToDeviceEncrypted {
+            sender: alice.user_id().clone(),
+            content: to_device_requests_to_content(to_device_requests),
+        };
+
+        bob.decrypt_to_device_event(&event).await.unwrap();
+
+        let plaintext = "It is a secret to everybody";
+        let content = MessageEventContent::Text(TextMessageEventContent::new_plain(plaintext));
+        let encrypted_content = alice.encrypt(&room_id, content).await.unwrap();
+
+        // Push payloads don't need to contain the room id.
+        let event = EncryptedEvent {
+            event_id: EventId::new("example.org").unwrap(),
+            origin_server_ts: SystemTime::now(),
+            room_id: None,
+            sender: alice.user_id().clone(),
+            content: encrypted_content,
+            unsigned: UnsignedData::default(),
+        };
+        let json = serde_json::to_string(&event).unwrap();
+
+        let decrypted_event = bob
+            .decrypt_raw_room_event(&room_id, &json)
+            .await
+            .unwrap()
+            .deserialize()
+            .unwrap();
+
+        match decrypted_event {
+            RoomEvent::RoomMessage(e) => {
+                assert_eq!(&e.sender, alice.user_id());
+                assert_eq!(e.room_id, Some(room_id));
+            }
+            _ => panic!("Decrypted room event has the wrong type"),
+        }
+    }
+
+    fn verification_event(sender: &UserId, request: &ToDeviceRequest) -> AnyToDeviceEvent {
+        let content: serde_json::Value = serde_json::from_str(
+            request
+                .messages
+                .values()
+                .next()
+                .unwrap()
+                .values()
+                .next()
+                .unwrap()
+                .get(),
+        )
+        .unwrap();
+
+        let event = json!({
+            "sender": sender,
+            "type": request.event_type.to_string(),
+            "content": content,
+        });
+
+        serde_json::from_value::<EventJson<AnyToDeviceEvent>>(event)
+            .unwrap()
+            .deserialize()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn auto_accepted_verification() {
+        let (alice, mut bob, _) = get_machine_pair().await;
+
+        let bob_device = alice
+            .store
+            .read()
+            .await
+            .get_device(bob.user_id(), bob.device_id())
+            .await
+            .unwrap()
+            .unwrap();
+        let alice_device = bob
+            .store
+            .read()
+            .await
+            .get_device(alice.user_id(), alice.device_id())
+            .await
+            .unwrap()
+            .unwrap();
+
+        // Bob doesn't accept verifications from other users by default.
+        alice.start_verification(&bob_device).await;
+        for request in alice.outgoing_to_device_requests().await {
+            let event = verification_event(alice.user_id(), &request);
+            bob.handle_verification_event(&event).await;
+        }
+        assert!(bob.outgoing_to_device_requests().await.is_empty());
+
+        let mut allowed = HashSet::new();
+        allowed.insert(alice.user_id().clone());
+        bob.set_verification_policy(VerificationPolicy::Allowlist(allowed));
+
+        alice.start_verification(&bob_device).await;
+
+        loop {
+            let alice_requests = alice.outgoing_to_device_requests().await;
+            let bob_requests = bob.outgoing_to_device_requests().await;
+
+            if alice_requests.is_empty() && bob_requests.is_empty() {
+                break;
+            }
+
+            for request in alice_requests {
+                let event = verification_event(alice.user_id(), &request);
+                bob.handle_verification_event(&event).await;
+            }
+
+            for request in bob_requests {
+                let event = verification_event(bob.user_id(), &request);
+                alice.handle_verification_event(&event).await;
+            }
+        }
+
+        assert_eq!(bob_device.trust_state(), TrustState::Verified);
+        assert_eq!(alice_device.trust_state(), TrustState::Verified);
+        assert!(bob.verifications.lock().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn unverified_own_device_refused() {
+        let alice = OlmMachine::new(&alice_id(), &alice_device_id());
+        let other = OlmMachine::new(&alice_id(), "OTHERDEVICE");
+        let device = Device::from(&other);
+
+        let mut allowed = HashSet::new();
+        allowed.insert(user_id());
+
+        for policy in &[
+            VerificationPolicy::OwnDevices,
+            VerificationPolicy::Allowlist(allowed),
+        ] {
+            assert!(!policy.allows(alice.user_id(), &device));
+        }
+
+        device.set_trust_state(TrustState::Verified);
+        assert!(VerificationPolicy::OwnDevices.allows(alice.user_id(), &device));
+        assert!(!VerificationPolicy::Manual.allows(alice.user_id(), &device));
+    }
+
+    #[tokio::test]
+    async fn verification_glare() {
+        let (mut alice, mut bob, _) = get_machine_pair().await;
+
+        let bob_device = alice
+            .store
+            .read()
+            .await
+            .get_device(bob.user_id(), bob.device_id())
+            .await
+            .unwrap()
+            .unwrap();
+        let alice_device = bob
+            .store
+            .read()
+            .await
+            .get_device(alice.user_id(), alice.device_id())
+            .await
+            .unwrap()
+            .unwrap();
+
+        let mut allowed = HashSet::new();
+        allowed.insert(alice.user_id().clone());
+        bob.set_verification_policy(VerificationPolicy::Allowlist(allowed));
+
+        let mut allowed = HashSet::new();
+        allowed.insert(bob.user_id().clone());
+        alice.set_verification_policy(VerificationPolicy::Allowlist(allowed));
+
+        // Both sides start a verification at the same time, Alice has the
+        // smaller user id so her verification wins.
+        alice.start_verification(&bob_device).await;
+        bob.start_verification(&alice_device).await;
+
+        let alice_transaction = alice
+            .verifications
+            .lock()
+            .await
+            .keys()
+            .next()
+            .unwrap()
+            .clone();
+        let mut cancelled = Vec::new();
+
+        loop {
+            let alice_requests = alice.outgoing_to_device_requests().await;
+            let bob_requests = bob.outgoing_to_device_requests().await;
+
+            if alice_requests.is_empty() && bob_requests.is_empty() {
+                break;
+            }
+
+            for request in alice_requests {
+                let event = verification_event(alice.user_id(), &request);
+                if let AnyToDeviceEvent::KeyVerificationCancel(e) = &event {
+                    cancelled.push(e.content.transaction_id.clone());
+                }
+                bob.handle_verification_event(&event).await;
+            }
+
+            for request in bob_requests {
+                let event = verification_event(bob.user_id(), &request);
+                if let AnyToDeviceEvent::KeyVerificationCancel(e) = &event {
+                    cancelled.push(e.content.transaction_id.clone());
+                }
+                alice.handle_verification_event(&event).await;
+            }
+        }
+
+        assert!(!cancelled.is_empty());
+        assert!(!cancelled.contains(&alice_transaction));
+        assert_eq!(bob_device.trust_state(), TrustState::Verified);
+        assert_eq!(alice_device.trust_state(), TrustState::Verified);
+        assert!(alice.verifications.lock().await.is_empty());
+        assert!(bob.verifications.lock().await.is_empty());
+    }
 }
@@ -124,6 +124,17 @@ impl GroupSessionStore {
             .get(room_id)
             .and_then(|m| m.get(sender_key).and_then(|m| m.get(session_id).cloned()))
     }
+
+    /// Get all the inbound group sessions of a room.
+    ///
+    /// # Arguments
+    /// * `room_id` - The room id of the room that the sessions belong to.
+    pub fn get_room(&self, room_id: &RoomId) -> Vec<InboundGroupSession> {
+        self.entries
+            .get(room_id)
+            .map(|m| m.values().flat_map(|m| m.values().cloned()).collect())
+            .unwrap_or_default()
+    }
 }
 
 /// In-memory store holding the devices of users.
@@ -275,6 +286,11 @@ mod test {
             .get(&room_id, "test_key", outbound.session_id())
             .unwrap();
         assert_eq!(inbound, loaded_session);
+
+        assert_eq!(store.get_room(&room_id), vec![inbound]);
+        assert!(store
+            .get_room(&RoomId::try_from("!other:localhost").unwrap())
+            .is_empty());
     }
 
     #[tokio::test]
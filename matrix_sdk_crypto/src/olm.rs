@@ -13,6 +13,7 @@
 // limitations under the License.
 
 use matrix_sdk_common::instant::Instant;
+use std::collections::BTreeMap;
 use std::fmt;
 use std::mem;
 use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
@@ -36,8 +37,11 @@ pub use olm_rs::{
 };
 
 use matrix_sdk_common::api::r0::keys::SignedKey;
+use matrix_sdk_common::events::Algorithm;
 use matrix_sdk_common::identifiers::RoomId;
 
+use crate::key_export::ExportedRoomKey;
+
 /// Account holding identity keys for which sessions can be created.
 ///
 /// An account is the central identity for encrypted communication between two
@@ -381,6 +385,21 @@ impl PartialEq for Session {
 #[zeroize(drop)]
 pub struct GroupSessionKey(pub String);
 
+/// Information about an inbound group session, e.g. to debug why messages
+/// of a room can't be decrypted.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GroupSessionInfo {
+    /// The unique id of the session.
+    pub session_id: String,
+    /// The public curve25519 key of the account that sent us the session.
+    pub sender_key: String,
+    /// The public ed25519 key of the account that sent us the session.
+    pub signing_key: String,
+    /// The index of the first message the session can decrypt, messages that
+    /// were sent before it can't be decrypted with this session.
+    pub first_known_index: u32,
+}
+
 /// Inbound group session.
 ///
 /// Inbound group sessions are used to exchange room messages between a group of
@@ -490,6 +509,43 @@ impl InboundGroupSession {
         self.inner.lock().await.first_known_index()
     }
 
+    /// Export the session in the key export format, at the first message
+    /// index we know how to decrypt.
+    pub async fn export(&self) -> Result<ExportedRoomKey, OlmGroupSessionError> {
+        let session_key = {
+            let inner = self.inner.lock().await;
+            inner.export(inner.first_known_index())?
+        };
+
+        let mut sender_claimed_keys = BTreeMap::new();
+        sender_claimed_keys.insert("ed25519".to_owned(), self.signing_key.to_string());
+
+        Ok(ExportedRoomKey {
+            algorithm: Algorithm::MegolmV1AesSha2,
+            room_id: (*self.room_id).clone(),
+            sender_key: self.sender_key.to_string(),
+            session_id: self.session_id().to_owned(),
+            session_key,
+            sender_claimed_keys,
+            forwarding_curve25519_key_chain: self
+                .forwarding_chains
+                .lock()
+                .await
+                .clone()
+                .unwrap_or_default(),
+        })
+    }
+
+    /// Get the information about the session that can be shown to users.
+    pub async fn info(&self) -> GroupSessionInfo {
+        GroupSessionInfo {
+            session_id: self.session_id().to_owned(),
+            sender_key: self.sender_key.to_string(),
+            signing_key: self.signing_key.to_string(),
+            first_known_index: self.first_known_index().await,
+        }
+    }
+
     /// Decrypt the given ciphertext.
     ///
     /// Returns the decrypted plaintext or an `OlmGroupSessionError` if
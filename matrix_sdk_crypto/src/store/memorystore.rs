@@ -18,7 +18,7 @@ use std::sync::Arc;
 use async_trait::async_trait;
 use matrix_sdk_common::locks::Mutex;
 
-use super::{Account, CryptoStore, InboundGroupSession, Result, Session};
+use super::{Account, CryptoStore, InboundGroupSession, PendingToDeviceRequest, Result, Session};
 use crate::device::Device;
 use crate::memory_stores::{DeviceStore, GroupSessionStore, SessionStore, UserDevices};
 use matrix_sdk_common::identifiers::{DeviceId, RoomId, UserId};
@@ -29,6 +29,7 @@ pub struct MemoryStore {
     inbound_group_sessions: GroupSessionStore,
     tracked_users: HashSet<UserId>,
     devices: DeviceStore,
+    pending_requests: Vec<PendingToDeviceRequest>,
 }
 
 impl MemoryStore {
@@ -38,6 +39,7 @@ impl MemoryStore {
             inbound_group_sessions: GroupSessionStore::new(),
             tracked_users: HashSet::new(),
             devices: DeviceStore::new(),
+            pending_requests: Vec::new(),
         }
     }
 }
@@ -69,7 +71,7 @@ impl CryptoStore for MemoryStore {
     }
 
     async fn get_inbound_group_session(
-        &mut self,
+        &self,
         room_id: &RoomId,
         sender_key: &str,
         session_id: &str,
@@ -79,6 +81,13 @@ impl CryptoStore for MemoryStore {
             .get(room_id, sender_key, session_id))
     }
 
+    async fn get_inbound_group_sessions(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Vec<InboundGroupSession>> {
+        Ok(self.inbound_group_sessions.get_room(room_id))
+    }
+
     fn tracked_users(&self) -> &HashSet<UserId> {
         &self.tracked_users
     }
@@ -108,6 +117,25 @@ impl CryptoStore for MemoryStore {
 
         Ok(())
     }
+
+    async fn save_pending_requests(&mut self, requests: &[PendingToDeviceRequest]) -> Result<()> {
+        self.pending_requests.extend_from_slice(requests);
+        Ok(())
+    }
+
+    async fn remove_pending_request(&mut self, txn_id: &str) -> Result<()> {
+        self.pending_requests.retain(|r| r.txn_id != txn_id);
+        Ok(())
+    }
+
+    async fn get_pending_requests(&self) -> Result<Vec<PendingToDeviceRequest>> {
+        Ok(self.pending_requests.clone())
+    }
+
+    async fn clear(&mut self) -> Result<()> {
+        *self = MemoryStore::new();
+        Ok(())
+    }
 }
 
 #[cfg(test)]
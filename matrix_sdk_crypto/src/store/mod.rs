@@ -15,17 +15,21 @@
 use core::fmt::Debug;
 use std::collections::HashSet;
 use std::io::Error as IoError;
+use std::result::Result as StdResult;
 use std::sync::Arc;
 use url::ParseError;
 
 use async_trait::async_trait;
 use matrix_sdk_common::locks::Mutex;
+use serde::{Deserialize, Serialize};
 use serde_json::Error as SerdeError;
 use thiserror::Error;
 
 use super::device::Device;
 use super::memory_stores::UserDevices;
 use super::olm::{Account, InboundGroupSession, Session};
+use matrix_sdk_common::api::r0::to_device::send_event_to_device::Request as ToDeviceRequest;
+use matrix_sdk_common::events::EventType;
 use matrix_sdk_common::identifiers::{DeviceId, RoomId, UserId};
 use olm_rs::errors::{OlmAccountError, OlmGroupSessionError, OlmSessionError};
 
@@ -82,6 +86,47 @@ pub enum CryptoStoreError {
 
 pub type Result<T> = std::result::Result<T, CryptoStoreError>;
 
+/// A to-device request that shares a room key and that wasn't confirmed to be
+/// sent yet.
+///
+/// The requests are persisted so that sharing a room key can be resumed if
+/// the sending got interrupted.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PendingToDeviceRequest {
+    /// The transaction id of the request, resending the request with the same
+    /// transaction id makes sure that it isn't delivered twice.
+    pub txn_id: String,
+    /// The room the shared room key belongs to.
+    pub room_id: RoomId,
+    /// The type of the to-device event.
+    pub event_type: String,
+    /// The JSON of the messages of the request, keyed by user and device.
+    pub messages: String,
+}
+
+impl PendingToDeviceRequest {
+    /// Create a pending request from a to-device request that shares a room
+    /// key of the given room.
+    pub fn new(room_id: &RoomId, request: &ToDeviceRequest) -> StdResult<Self, SerdeError> {
+        Ok(Self {
+            txn_id: request.txn_id.clone(),
+            room_id: room_id.clone(),
+            event_type: request.event_type.to_string(),
+            messages: serde_json::to_string(&request.messages)?,
+        })
+    }
+
+    /// Turn the pending request back into a to-device request that can be
+    /// sent.
+    pub fn to_request(&self) -> StdResult<ToDeviceRequest, SerdeError> {
+        Ok(ToDeviceRequest {
+            event_type: EventType::from(self.event_type.as_str()),
+            txn_id: self.txn_id.clone(),
+            messages: serde_json::from_str(&self.messages)?,
+        })
+    }
+}
+
 #[async_trait]
 /// Trait abstracting a store that the `OlmMachine` uses to store cryptographic
 /// keys.
@@ -129,12 +174,21 @@ pub trait CryptoStore: Debug + Send + Sync {
     ///
     /// * `session_id` - The unique id of the session.
     async fn get_inbound_group_session(
-        &mut self,
+        &self,
         room_id: &RoomId,
         sender_key: &str,
         session_id: &str,
     ) -> Result<Option<InboundGroupSession>>;
 
+    /// Get all the inbound group sessions of a room from our store.
+    ///
+    /// # Arguments
+    /// * `room_id` - The room id of the room that the sessions belong to.
+    async fn get_inbound_group_sessions(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Vec<InboundGroupSession>>;
+
     /// Get the set of tracked users.
     fn tracked_users(&self) -> &HashSet<UserId>;
 
@@ -178,4 +232,26 @@ pub trait CryptoStore: Debug + Send + Sync {
     ///
     /// * `user_id` - The user for which we should get all the devices.
     async fn get_user_devices(&self, user_id: &UserId) -> Result<UserDevices>;
+
+    /// Save to-device requests that share a room key before they are sent.
+    ///
+    /// # Arguments
+    ///
+    /// * `requests` - The requests that should be stored.
+    async fn save_pending_requests(&mut self, requests: &[PendingToDeviceRequest]) -> Result<()>;
+
+    /// Remove a to-device request that was sent from the store.
+    ///
+    /// # Arguments
+    ///
+    /// * `txn_id` - The transaction id of the request that was sent.
+    async fn remove_pending_request(&mut self, txn_id: &str) -> Result<()>;
+
+    /// Get the to-device requests that share a room key but weren't sent, the
+    /// oldest one first.
+    async fn get_pending_requests(&self) -> Result<Vec<PendingToDeviceRequest>>;
+
+    /// Remove the account and all the keys, sessions and devices that belong
+    /// to it from the store, e.g. after the account was deactivated.
+    async fn clear(&mut self) -> Result<()>;
 }
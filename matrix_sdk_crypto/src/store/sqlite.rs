@@ -27,7 +27,10 @@ use olm_rs::PicklingMode;
 use sqlx::{query, query_as, sqlite::SqliteQueryAs, Connect, Executor, SqliteConnection};
 use zeroize::Zeroizing;
 
-use super::{Account, CryptoStore, CryptoStoreError, InboundGroupSession, Result, Session};
+use super::{
+    Account, CryptoStore, CryptoStoreError, InboundGroupSession, PendingToDeviceRequest, Result,
+    Session,
+};
 use crate::device::{Device, TrustState};
 use crate::memory_stores::{DeviceStore, GroupSessionStore, SessionStore, UserDevices};
 use matrix_sdk_common::api::r0::keys::KeyAlgorithm;
@@ -236,6 +239,26 @@ impl SqliteStore {
             )
             .await?;
 
+        connection
+            .execute(
+                r#"
+            CREATE TABLE IF NOT EXISTS pending_requests (
+                "id" INTEGER NOT NULL PRIMARY KEY,
+                "account_id" INTEGER NOT NULL,
+                "txn_id" TEXT NOT NULL,
+                "room_id" TEXT NOT NULL,
+                "event_type" TEXT NOT NULL,
+                "messages" TEXT NOT NULL,
+                FOREIGN KEY ("account_id") REFERENCES "accounts" ("id")
+                    ON DELETE CASCADE
+                UNIQUE(account_id, txn_id)
+            );
+
+            CREATE INDEX IF NOT EXISTS "pending_requests_account_id" ON "pending_requests" ("account_id");
+        "#,
+            )
+            .await?;
+
         Ok(())
     }
 
@@ -622,7 +645,7 @@ impl CryptoStore for SqliteStore {
     }
 
     async fn get_inbound_group_session(
-        &mut self,
+        &self,
         room_id: &RoomId,
         sender_key: &str,
         session_id: &str,
@@ -632,6 +655,13 @@ impl CryptoStore for SqliteStore {
             .get(room_id, sender_key, session_id))
     }
 
+    async fn get_inbound_group_sessions(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Vec<InboundGroupSession>> {
+        Ok(self.inbound_group_sessions.get_room(room_id))
+    }
+
     fn tracked_users(&self) -> &HashSet<UserId> {
         &self.tracked_users
     }
@@ -677,6 +707,99 @@ impl CryptoStore for SqliteStore {
     async fn get_user_devices(&self, user_id: &UserId) -> Result<UserDevices> {
         Ok(self.devices.user_devices(user_id))
     }
+
+    async fn save_pending_requests(&mut self, requests: &[PendingToDeviceRequest]) -> Result<()> {
+        let account_id = self.account_id.ok_or(CryptoStoreError::AccountUnset)?;
+        let mut connection = self.connection.lock().await;
+
+        for request in requests {
+            query(
+                "INSERT OR IGNORE INTO pending_requests (
+                    account_id, txn_id, room_id, event_type, messages
+                 ) VALUES (?1, ?2, ?3, ?4, ?5)
+                 ",
+            )
+            .bind(account_id)
+            .bind(&request.txn_id)
+            .bind(&request.room_id.to_string())
+            .bind(&request.event_type)
+            .bind(&request.messages)
+            .execute(&mut *connection)
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn remove_pending_request(&mut self, txn_id: &str) -> Result<()> {
+        let account_id = self.account_id.ok_or(CryptoStoreError::AccountUnset)?;
+        let mut connection = self.connection.lock().await;
+
+        query("DELETE FROM pending_requests WHERE account_id = ?1 and txn_id = ?2")
+            .bind(account_id)
+            .bind(txn_id)
+            .execute(&mut *connection)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn get_pending_requests(&self) -> Result<Vec<PendingToDeviceRequest>> {
+        let account_id = self.account_id.ok_or(CryptoStoreError::AccountUnset)?;
+        let mut connection = self.connection.lock().await;
+
+        let rows: Vec<(String, String, String, String)> = query_as(
+            "SELECT txn_id, room_id, event_type, messages
+             FROM pending_requests WHERE account_id = ? ORDER BY id",
+        )
+        .bind(account_id)
+        .fetch_all(&mut *connection)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .filter_map(|(txn_id, room_id, event_type, messages)| {
+                Some(PendingToDeviceRequest {
+                    txn_id,
+                    room_id: RoomId::try_from(room_id.as_str()).ok()?,
+                    event_type,
+                    messages,
+                })
+            })
+            .collect())
+    }
+
+    async fn clear(&mut self) -> Result<()> {
+        let account_id = match self.account_id.take() {
+            Some(id) => id,
+            None => return Ok(()),
+        };
+        let mut connection = self.connection.lock().await;
+
+        for statement in &[
+            "DELETE FROM device_keys WHERE device_id IN
+             (SELECT id FROM devices WHERE account_id = ?)",
+            "DELETE FROM algorithms WHERE device_id IN
+             (SELECT id FROM devices WHERE account_id = ?)",
+            "DELETE FROM devices WHERE account_id = ?",
+            "DELETE FROM pending_requests WHERE account_id = ?",
+            "DELETE FROM inbound_group_sessions WHERE account_id = ?",
+            "DELETE FROM sessions WHERE account_id = ?",
+            "DELETE FROM accounts WHERE id = ?",
+        ] {
+            query(statement)
+                .bind(account_id)
+                .execute(&mut *connection)
+                .await?;
+        }
+
+        self.sessions = SessionStore::new();
+        self.inbound_group_sessions = GroupSessionStore::new();
+        self.devices = DeviceStore::new();
+        self.tracked_users.clear();
+
+        Ok(())
+    }
 }
 
 #[cfg_attr(tarpaulin, skip)]
@@ -700,7 +823,8 @@ mod test {
     use tempfile::tempdir;
 
     use super::{
-        Account, CryptoStore, InboundGroupSession, RoomId, Session, SqliteStore, TryFrom, UserId,
+        Account, CryptoStore, InboundGroupSession, PendingToDeviceRequest, RoomId, Session,
+        SqliteStore, TryFrom, UserId,
     };
 
     static USER_ID: &str = "@example:localhost";
@@ -985,6 +1109,41 @@ mod test {
         tracked_users.contains(device.user_id());
     }
 
+    #[tokio::test]
+    async fn pending_requests() {
+        let (_account, mut store, _dir) = get_loaded_store().await;
+        let room_id = RoomId::try_from("!test:localhost").unwrap();
+
+        let request = |txn_id: &str| PendingToDeviceRequest {
+            txn_id: txn_id.to_owned(),
+            room_id: room_id.clone(),
+            event_type: "m.room.encrypted".to_owned(),
+            messages: r#"{"@example:localhost":{"DEVICEID":{"ciphertext":{}}}}"#.to_owned(),
+        };
+
+        assert!(store.get_pending_requests().await.unwrap().is_empty());
+
+        store
+            .save_pending_requests(&[request("first"), request("second")])
+            .await
+            .unwrap();
+        assert_eq!(
+            store.get_pending_requests().await.unwrap(),
+            vec![request("first"), request("second")]
+        );
+
+        store.remove_pending_request("first").await.unwrap();
+        let pending = store.get_pending_requests().await.unwrap();
+        assert_eq!(pending, vec![request("second")]);
+
+        let to_device = pending[0].to_request().unwrap();
+        assert_eq!(to_device.txn_id, "second");
+        assert_eq!(
+            PendingToDeviceRequest::new(&room_id, &to_device).unwrap(),
+            request("second")
+        );
+    }
+
     #[tokio::test]
     async fn device_saving() {
         let (_account, store, dir) = get_loaded_store().await;
@@ -1020,6 +1179,27 @@ mod test {
         assert_eq!(user_devices.devices().nth(0).unwrap(), &device);
     }
 
+    #[tokio::test]
+    async fn clear() {
+        let (_account, mut store, dir) = get_loaded_store().await;
+        let device = get_device();
+
+        store.save_devices(&[device.clone()]).await.unwrap();
+        store.clear().await.unwrap();
+
+        let mut store =
+            SqliteStore::open(&UserId::try_from(USER_ID).unwrap(), DEVICE_ID, dir.path())
+                .await
+                .expect("Can't create store");
+
+        assert!(store.load_account().await.unwrap().is_none());
+        assert!(store
+            .get_device(device.user_id(), device.device_id())
+            .await
+            .unwrap()
+            .is_none());
+    }
+
     #[tokio::test]
     async fn device_deleting() {
         let (_account, store, dir) = get_loaded_store().await;
@@ -0,0 +1,409 @@
+// Copyright 2020 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashSet;
+
+use base64::{encode_config, STANDARD_NO_PAD};
+use olm_rs::sas::OlmSas;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+use matrix_sdk_common::api::r0::keys::KeyAlgorithm;
+use matrix_sdk_common::events::EventType;
+use matrix_sdk_common::identifiers::{DeviceId, UserId};
+
+use crate::device::{Device, TrustState};
+
+const SAS_METHOD: &str = "m.sas.v1";
+const KEY_AGREEMENT_PROTOCOL: &str = "curve25519";
+const HASH: &str = "sha256";
+const MAC_METHOD: &str = "hkdf-hmac-sha256";
+const MAC_INFO: &str = "MATRIX_KEY_VERIFICATION_MAC";
+
+/// A policy deciding which key verifications the `OlmMachine` accepts and
+/// completes on its own.
+///
+/// Automatically accepted verifications skip the comparison of the short
+/// authentication string, the devices are trusted purely because of the
+/// policy. This is meant for headless clients, e.g. bots, that have no user
+/// that could compare emojis.
+///
+/// Our own devices are only accepted if they are already verified, a device
+/// that was just logged in with a stolen password doesn't get trusted this
+/// way.
+#[derive(Clone, Debug, PartialEq)]
+pub enum VerificationPolicy {
+    /// Never accept verifications automatically, this is the default.
+    Manual,
+    /// Accept verifications coming from the other, already verified, devices
+    /// of our own account.
+    OwnDevices,
+    /// Accept verifications coming from our own verified devices and from
+    /// the devices of the given users.
+    ///
+    /// The devices of the given users are trusted on first use, any device
+    /// of those users that isn't blacklisted is accepted, including devices
+    /// that were never seen before.
+    Allowlist(HashSet<UserId>),
+}
+
+impl Default for VerificationPolicy {
+    fn default() -> Self {
+        VerificationPolicy::Manual
+    }
+}
+
+impl VerificationPolicy {
+    /// Should a verification with the given device be accepted
+    /// automatically.
+    ///
+    /// # Arguments
+    ///
+    /// * `own_user_id` - The user id of our own account.
+    ///
+    /// * `device` - The device that wants to verify us.
+    pub fn allows(&self, own_user_id: &UserId, device: &Device) -> bool {
+        if *self == VerificationPolicy::Manual {
+            return false;
+        }
+
+        if device.user_id() == own_user_id {
+            return device.trust_state() == TrustState::Verified;
+        }
+
+        match self {
+            VerificationPolicy::Allowlist(users) => {
+                users.contains(device.user_id()) && device.trust_state() != TrustState::BlackListed
+            }
+            _ => false,
+        }
+    }
+}
+
+/// The content of a `m.key.verification.cancel` event.
+pub(crate) fn cancel_content(transaction_id: &str, code: &str, reason: &str) -> Value {
+    json!({
+        "transaction_id": transaction_id,
+        "code": code,
+        "reason": reason,
+    })
+}
+
+/// An in-progress, automatically confirmed, SAS verification with a single
+/// device.
+pub(crate) struct Sas {
+    inner: OlmSas,
+    user_id: UserId,
+    device_id: DeviceId,
+    ed25519_key: String,
+    other_device: Device,
+    transaction_id: String,
+    /// The content of the start event, used to calculate the commitment.
+    start_content: Value,
+    /// Did we send the start event.
+    we_started: bool,
+    /// The commitment the other side sent us, only set if we started the
+    /// verification.
+    commitment: Option<String>,
+    their_key_received: bool,
+}
+
+#[cfg_attr(tarpaulin, skip)]
+impl std::fmt::Debug for Sas {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Sas")
+            .field("other_user_id", self.other_device.user_id())
+            .field("other_device_id", self.other_device.device_id())
+            .field("transaction_id", &self.transaction_id)
+            .finish()
+    }
+}
+
+impl Sas {
+    /// Start a new verification with the given device.
+    ///
+    /// Returns the verification and the content of the
+    /// `m.key.verification.start` event that should be sent to the device.
+    pub fn start(
+        user_id: &UserId,
+        device_id: &DeviceId,
+        ed25519_key: &str,
+        other_device: Device,
+        transaction_id: String,
+    ) -> (Sas, Value) {
+        let start_content = json!({
+            "from_device": device_id,
+            "transaction_id": transaction_id,
+            "method": SAS_METHOD,
+            "key_agreement_protocols": [KEY_AGREEMENT_PROTOCOL],
+            "hashes": [HASH],
+            "message_authentication_codes": [MAC_METHOD],
+            "short_authentication_string": ["decimal", "emoji"],
+        });
+
+        let mut sas = Sas::new(
+            user_id,
+            device_id,
+            ed25519_key,
+            other_device,
+            transaction_id,
+            start_content.clone(),
+        );
+        sas.we_started = true;
+
+        (sas, start_content)
+    }
+
+    /// Accept a verification the given device started.
+    ///
+    /// Returns the verification and the content of the
+    /// `m.key.verification.accept` event, or the content of a
+    /// `m.key.verification.cancel` event if we don't support the methods the
+    /// other device offered.
+    pub fn accept(
+        user_id: &UserId,
+        device_id: &DeviceId,
+        ed25519_key: &str,
+        other_device: Device,
+        start_content: Value,
+    ) -> Result<(Sas, Value), Value> {
+        let transaction_id = start_content["transaction_id"]
+            .as_str()
+            .unwrap_or_default()
+            .to_owned();
+
+        let offers = |field: &str, value: &str| {
+            start_content[field]
+                .as_array()
+                .map_or(false, |v| v.iter().any(|v| v == value))
+        };
+
+        if start_content["method"] != SAS_METHOD
+            || !offers("key_agreement_protocols", KEY_AGREEMENT_PROTOCOL)
+            || !offers("hashes", HASH)
+            || !offers("message_authentication_codes", MAC_METHOD)
+            || !offers("short_authentication_string", "decimal")
+        {
+            return Err(cancel_content(
+                &transaction_id,
+                "m.unknown_method",
+                "Unsupported verification method",
+            ));
+        }
+
+        let sas = Sas::new(
+            user_id,
+            device_id,
+            ed25519_key,
+            other_device,
+            transaction_id,
+            start_content,
+        );
+        let commitment = sas.commitment(&sas.inner.public_key());
+
+        let accept_content = json!({
+            "transaction_id": sas.transaction_id,
+            "method": SAS_METHOD,
+            "key_agreement_protocol": KEY_AGREEMENT_PROTOCOL,
+            "hash": HASH,
+            "message_authentication_code": MAC_METHOD,
+            "short_authentication_string": ["decimal", "emoji"],
+            "commitment": commitment,
+        });
+
+        Ok((sas, accept_content))
+    }
+
+    fn new(
+        user_id: &UserId,
+        device_id: &DeviceId,
+        ed25519_key: &str,
+        other_device: Device,
+        transaction_id: String,
+        start_content: Value,
+    ) -> Self {
+        Sas {
+            inner: OlmSas::new(),
+            user_id: user_id.clone(),
+            device_id: device_id.clone(),
+            ed25519_key: ed25519_key.to_owned(),
+            other_device,
+            transaction_id,
+            start_content,
+            we_started: false,
+            commitment: None,
+            their_key_received: false,
+        }
+    }
+
+    /// The device we are verifying.
+    pub fn other_device(&self) -> &Device {
+        &self.other_device
+    }
+
+    /// Is this a verification with the given device, both the user and the
+    /// device id need to match.
+    pub fn is_with(&self, device: &Device) -> bool {
+        self.other_device.user_id() == device.user_id()
+            && self.other_device.device_id() == device.device_id()
+    }
+
+    /// Did we send the `m.key.verification.start` event.
+    pub fn we_started(&self) -> bool {
+        self.we_started
+    }
+
+    fn key_content(&self) -> Value {
+        json!({
+            "transaction_id": self.transaction_id,
+            "key": self.inner.public_key(),
+        })
+    }
+
+    /// Receive the `m.key.verification.accept` event of the other device.
+    ///
+    /// Returns the content of our `m.key.verification.key` event.
+    pub fn receive_accept(&mut self, content: &Value) -> Result<Value, Value> {
+        let commitment = content["commitment"]
+            .as_str()
+            .ok_or_else(|| self.cancel("m.unexpected_message", "Missing commitment"))?;
+
+        self.commitment = Some(commitment.to_owned());
+
+        Ok(self.key_content())
+    }
+
+    /// Receive the `m.key.verification.key` event of the other device.
+    ///
+    /// Since the verification is confirmed automatically, this returns the
+    /// content of our `m.key.verification.mac` event right away, preceded by
+    /// our own key if the other device started the verification.
+    pub fn receive_key(&mut self, content: &Value) -> Result<Vec<(EventType, Value)>, Value> {
+        let key = content["key"]
+            .as_str()
+            .ok_or_else(|| self.cancel("m.unexpected_message", "Missing public key"))?;
+
+        if let Some(commitment) = &self.commitment {
+            if *commitment != self.commitment(key) {
+                return Err(self.cancel("m.mismatched_commitment", "Mismatched commitment"));
+            }
+        }
+
+        self.inner
+            .set_their_public_key(key.to_owned())
+            .map_err(|_| self.cancel("m.key_mismatch", "Invalid public key"))?;
+        self.their_key_received = true;
+
+        let info = self.mac_info(
+            &self.user_id,
+            &self.device_id,
+            self.other_device.user_id(),
+            self.other_device.device_id(),
+        );
+        let key_id = format!("ed25519:{}", self.device_id);
+
+        let mac = self
+            .inner
+            .calculate_mac(&self.ed25519_key, &format!("{}{}", info, key_id))
+            .map_err(|_| self.cancel("m.key_mismatch", "Can't calculate the MAC"))?;
+        let keys = self
+            .inner
+            .calculate_mac(&key_id, &format!("{}KEY_IDS", info))
+            .map_err(|_| self.cancel("m.key_mismatch", "Can't calculate the MAC"))?;
+
+        let mac_content = json!({
+            "transaction_id": self.transaction_id,
+            "mac": { key_id: mac },
+            "keys": keys,
+        });
+
+        if self.we_started {
+            Ok(vec![(EventType::KeyVerificationMac, mac_content)])
+        } else {
+            Ok(vec![
+                (EventType::KeyVerificationKey, self.key_content()),
+                (EventType::KeyVerificationMac, mac_content),
+            ])
+        }
+    }
+
+    /// Receive the `m.key.verification.mac` event of the other device.
+    ///
+    /// Returns an error containing the content of a
+    /// `m.key.verification.cancel` event if the MAC doesn't match the keys of
+    /// the device.
+    pub fn receive_mac(&self, content: &Value) -> Result<(), Value> {
+        if !self.their_key_received {
+            return Err(self.cancel("m.unexpected_message", "Received a MAC before a key"));
+        }
+
+        let info = self.mac_info(
+            self.other_device.user_id(),
+            self.other_device.device_id(),
+            &self.user_id,
+            &self.device_id,
+        );
+        let key_id = format!("ed25519:{}", self.other_device.device_id());
+        let mismatch = || self.cancel("m.key_mismatch", "The MAC doesn't match");
+
+        let ed25519_key = self
+            .other_device
+            .get_key(KeyAlgorithm::Ed25519)
+            .ok_or_else(mismatch)?;
+
+        let expected_mac = self
+            .inner
+            .calculate_mac(ed25519_key, &format!("{}{}", info, key_id))
+            .map_err(|_| mismatch())?;
+        let expected_keys = self
+            .inner
+            .calculate_mac(&key_id, &format!("{}KEY_IDS", info))
+            .map_err(|_| mismatch())?;
+
+        if content["mac"][&key_id] != expected_mac.as_str()
+            || content["keys"] != expected_keys.as_str()
+        {
+            return Err(mismatch());
+        }
+
+        Ok(())
+    }
+
+    /// The content of a `m.key.verification.cancel` event for this
+    /// verification.
+    pub fn cancel(&self, code: &str, reason: &str) -> Value {
+        cancel_content(&self.transaction_id, code, reason)
+    }
+
+    fn commitment(&self, public_key: &str) -> String {
+        let start_content =
+            cjson::to_string(&self.start_content).expect("The start content is valid JSON");
+        let hash = Sha256::digest(format!("{}{}", public_key, start_content).as_bytes());
+
+        encode_config(&hash, STANDARD_NO_PAD)
+    }
+
+    fn mac_info(
+        &self,
+        sender: &UserId,
+        sender_device: &DeviceId,
+        receiver: &UserId,
+        receiver_device: &DeviceId,
+    ) -> String {
+        format!(
+            "{}{}{}{}{}{}",
+            MAC_INFO, sender, sender_device, receiver, receiver_device, self.transaction_id
+        )
+    }
+}
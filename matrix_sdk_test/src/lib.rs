@@ -1,7 +1,10 @@
 use std::convert::TryFrom;
+use std::fs;
 use std::panic;
+use std::path::Path;
 
 use http::Response;
+use serde_json::{json, Value as JsonValue};
 
 use matrix_sdk_common::api::r0::sync::sync_events::Response as SyncResponse;
 use matrix_sdk_common::events::{
@@ -225,3 +228,141 @@ pub fn sync_response(kind: SyncResponseFile) -> SyncResponse {
     let response = Response::builder().body(data.to_vec()).unwrap();
     SyncResponse::try_from(response).unwrap()
 }
+
+/// Load a sync response from a JSON file, e.g. a response that was recorded
+/// from a real homeserver.
+///
+/// # Panics
+///
+/// Panics if the file can't be read or doesn't contain a valid sync response.
+pub fn sync_response_from_file<P: AsRef<Path>>(path: P) -> SyncResponse {
+    let data = fs::read(path).unwrap();
+    let response = Response::builder().body(data).unwrap();
+    SyncResponse::try_from(response).unwrap()
+}
+
+/// The size of a generated sync response, see `large_sync_response()`.
+#[derive(Clone, Copy, Debug)]
+pub struct SyncResponseSize {
+    /// The number of joined rooms.
+    pub rooms: usize,
+    /// The number of members of every room.
+    pub members: usize,
+    /// The number of messages in the timeline of every room.
+    pub messages: usize,
+}
+
+/// Generate the JSON of a large sync response, like the initial sync of an
+/// account with many big rooms.
+///
+/// The response is deterministic, generating it twice with the same size
+/// gives the same JSON, so it can be used to compare the performance of
+/// different versions or `StateStore` implementations.
+pub fn large_sync_response_json(size: SyncResponseSize) -> JsonValue {
+    let mut join = serde_json::Map::new();
+    let mut presence = Vec::new();
+
+    for member in 0..size.members {
+        presence.push(json!({
+            "content": {
+                "currently_active": member % 2 == 0,
+                "last_active_ago": member,
+                "presence": "online"
+            },
+            "sender": format!("@user{}:localhost", member),
+            "type": "m.presence"
+        }));
+    }
+
+    for room in 0..size.rooms {
+        let mut state = vec![
+            json!({
+                "content": {
+                    "creator": "@user0:localhost",
+                    "m.federate": true,
+                    "room_version": "5"
+                },
+                "event_id": format!("$room{}_create:localhost", room),
+                "origin_server_ts": 1_519_578_782_185u64,
+                "sender": "@user0:localhost",
+                "state_key": "",
+                "type": "m.room.create"
+            }),
+            json!({
+                "content": { "name": format!("Room {}", room) },
+                "event_id": format!("$room{}_name:localhost", room),
+                "origin_server_ts": 1_519_578_782_185u64,
+                "sender": "@user0:localhost",
+                "state_key": "",
+                "type": "m.room.name"
+            }),
+        ];
+
+        for member in 0..size.members {
+            state.push(json!({
+                "content": {
+                    "displayname": format!("User {}", member),
+                    "membership": "join"
+                },
+                "event_id": format!("$room{}_member{}:localhost", room, member),
+                "origin_server_ts": 1_519_578_782_185u64,
+                "sender": format!("@user{}:localhost", member),
+                "state_key": format!("@user{}:localhost", member),
+                "type": "m.room.member"
+            }));
+        }
+
+        let timeline: Vec<JsonValue> = (0..size.messages)
+            .map(|message| {
+                json!({
+                    "content": {
+                        "body": format!("Message number {} in room {}", message, room),
+                        "msgtype": "m.text"
+                    },
+                    "event_id": format!("$room{}_message{}:localhost", room, message),
+                    "origin_server_ts": 1_520_000_000_000u64 + message as u64,
+                    "sender": format!("@user{}:localhost", message % size.members.max(1)),
+                    "type": "m.room.message"
+                })
+            })
+            .collect();
+
+        join.insert(
+            format!("!room{}:localhost", room),
+            json!({
+                "summary": {
+                    "m.joined_member_count": size.members,
+                    "m.invited_member_count": 0
+                },
+                "account_data": { "events": [] },
+                "ephemeral": { "events": [] },
+                "state": { "events": state },
+                "timeline": {
+                    "events": timeline,
+                    "limited": true,
+                    "prev_batch": format!("t{}-516_47314_0_7_1_1_1_11444_1", room)
+                },
+                "unread_notifications": {
+                    "highlight_count": 0,
+                    "notification_count": size.messages
+                }
+            }),
+        );
+    }
+
+    json!({
+        "device_one_time_keys_count": {},
+        "next_batch": "s526_47314_0_7_1_1_1_11444_1",
+        "device_lists": { "changed": [], "left": [] },
+        "rooms": { "invite": {}, "join": join, "leave": {} },
+        "to_device": { "events": [] },
+        "presence": { "events": presence }
+    })
+}
+
+/// Generate a large sync response, see `large_sync_response_json()`.
+pub fn large_sync_response(size: SyncResponseSize) -> SyncResponse {
+    let body = serde_json::to_vec(&large_sync_response_json(size)).unwrap();
+    let response = Response::builder().body(body).unwrap();
+    SyncResponse::try_from(response).unwrap()
+}